@@ -5,5 +5,11 @@ pub const COMMAND_PAY_BACK: &str = "/payback";
 pub const COMMAND_VIEW_PAYMENTS: &str = "/viewpayments";
 pub const COMMAND_EDIT_PAYMENT: &str = "/editpayment";
 pub const COMMAND_DELETE_PAYMENT: &str = "/deletepayment";
+pub const COMMAND_TRASH: &str = "/trash";
+pub const COMMAND_RECURRING: &str = "/recurring";
+pub const COMMAND_HISTORY: &str = "/history";
 pub const COMMAND_BALANCES: &str = "/balances";
-pub const COMMAND_SPENDINGS: &str = "/spendings";
\ No newline at end of file
+pub const COMMAND_SPENDINGS: &str = "/spendings";
+pub const COMMAND_AUDIT: &str = "/audit";
+pub const COMMAND_EXPORT: &str = "/export";
+pub const COMMAND_RESET_GROUP: &str = "/resetgroup";
\ No newline at end of file
@@ -6,18 +6,28 @@ pub const NO_TEXT_MESSAGE: &str = "❓ No text? Please reply to me in text!\n\n"
 // Instruction messages
 pub const TOTAL_INSTRUCTIONS_MESSAGE: &str =
     "Type the amount and currency (optional). For example: 100.00 USD, 200 MXN, 300.00, etc.\n\n";
+pub const PAYER_INSTRUCTIONS_MESSAGE: &str =
+    "More than one paid? Share their usernames and how much each paid: \n\n@username_1 amount1\n@username_2 amount2\n...\n\n";
 pub const CURRENCY_INSTRUCTIONS_MESSAGE: &str =
     "Type the currency code. For example: USD, EUR, UAH, etc.\n\n";
+pub const DATE_INSTRUCTIONS_MESSAGE: &str =
+    "Type the date, and the time (optional). For example: yesterday, 2026-10-12, 12 Oct 18:30, etc.\n\n";
+pub const RECURRING_INSTRUCTIONS_MESSAGE: &str =
+    "Type daily, weekly, monthly, or a cron expression, such as 0 9 1 * * for 9am on the 1st of every month. Add when it starts and ends if you like, for example: monthly from 2026-11-01 until 2027-06-30\n\nType never to add it just once.\n\n";
+pub const CATEGORY_INSTRUCTIONS_MESSAGE: &str =
+    "Pick a category, or type a new one for this group, such as Groceries. Pick None to leave it uncategorized.\n\n";
 pub const TIME_ZONE_INSTRUCTIONS_MESSAGE: &str =
     "Check out my User Guide with /help for all my supported time zones!"; //TODO
 pub const DEBT_EQUAL_INSTRUCTIONS_MESSAGE: &str =
-    "Share memeber's usernames, for example:\n\n@username_1\n@username_2\n@username_3\n...\n\n Don't forget to add the payer!";
+    "Share memeber's usernames, for example:\n\n@username_1\n@username_2\n@username_3\n...\n\n Don't forget to add the payer!\n\n👤 No username? Mention them, or add a guest who isn't on Telegram as +Name.";
 pub const DEBT_EXACT_INSTRUCTIONS_MESSAGE: &str =
-    "Share memeber's usernames and their amount stakes: \n\n@username_1 amount1\n@username_2 amount2\n@username_3 amount3\n...\n\n⭐️ If balance is positive, it's the payer's!";
+    "Share memeber's usernames and their amount stakes: \n\n@username_1 amount1\n@username_2 amount2\n@username_3 amount3\n...\n\n⭐️ If balance is positive, it's the payer's!\n\n🧾 Tax, tip or service charge on top? Add it as an amount or percentage, like tax 8% or tip 5, and it's shared in proportion to each amount.\n\n👤 No username? Mention them, or add a guest who isn't on Telegram as +Name.";
 pub const DEBT_RATIO_INSTRUCTIONS_MESSAGE: &str =
-    "Share memeber's usernames and their portion stakes: \n\n@username_1 portion1\n@username_2 portion2\n@username_3 portion3\n...\n\n⭐️ It can be 100, 50, 33 etc";
+    "Share memeber's usernames and their portion stakes: \n\n@username_1 portion1\n@username_2 portion2\n@username_3 portion3\n...\n\n⭐️ It can be 100, 50, 33 etc\n\n👤 No username? Mention them, or add a guest who isn't on Telegram as +Name.";
+pub const DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE: &str =
+    "Share each item on its own line, with its price and who shares it: \n\npizza 24 @username_1 @username_2\nwine 30 @username_1 @username_3\n...\n\n🧾 Tax, tip or service charge on top? Add it on its own line as an amount or percentage, like tax 8% or tip 5, and it's shared in proportion to each person's items.\n\n⭐️ Anything else not itemized is the payer's!\n\n👤 No username? Mention them, or add a guest who isn't on Telegram as +Name.";
 pub const PAY_BACK_INSTRUCTIONS_MESSAGE: &str =
-    "Enter the Telegram usernames and exact amounts like this: \n\n@username_1 amount1\n@username_2 amount2\n@username_3 amount3\n...\n\n";
+    "Enter the Telegram usernames and exact amounts like this: \n\n@username_1 amount1\n@username_2 amount2\n@username_3 amount3\n...\n\n👤 No username? Mention them, or add a guest who isn't on Telegram as +Name.\n\n";
 pub const STATEMENT_INSTRUCTIONS_MESSAGE: &str = "I provide other currencies/formats below!";
 
 // Description messages
@@ -25,6 +35,8 @@ pub const DEBT_EQUAL_DESCRIPTION_MESSAGE: &str = "Equal — Even amount for each
 pub const DEBT_EXACT_DESCRIPTION_MESSAGE: &str = "Exact — Precise amount for each user\n";
 pub const DEBT_RATIO_DESCRIPTION_MESSAGE: &str =
     "Share — Total amount is based on shares from 100% for each user\n";
+pub const DEBT_ITEMIZED_DESCRIPTION_MESSAGE: &str =
+    "Itemized — Each item is split evenly among the users who shared it\n";
 pub const TIME_ZONE_DESCRIPTION: &str = "*Time Zone* — Your time zone";
 pub const DEFAULT_CURRENCY_DESCRIPTION: &str = "*Default Currency* — System currency";
 pub const CURRENCY_CONVERSION_DESCRIPTION: &str =
@@ -1 +1,28 @@
 pub const MAX_VALUE: i64 = 1_000_000_000_000_000_000;
+
+// Days that deleted payments are kept in the trash, unless TRASH_RETENTION_DAYS is set
+pub const TRASH_RETENTION_DAYS_DEFAULT: i64 = 30;
+
+// Prefixes of members without a Telegram username.
+// Guests are not on Telegram, while Telegram users are identified by their user ID.
+pub const GUEST_PREFIX: &str = "+";
+pub const TELEGRAM_USER_PREFIX: &str = "#";
+pub const MAX_GUEST_NAME_LENGTH: usize = 32;
+
+// Number of payments shown on each page when viewing payments
+pub const PAYMENTS_PAGE_SIZE: usize = 5;
+
+// Charges on top of the items of a receipt, such as "tax 8%" or "tip 5".
+// They are spread over each member in proportion to what they owe.
+pub const CHARGE_KEYWORDS: [&str; 3] = ["tax", "tip", "service"];
+pub const MAX_CHARGE_PERCENTAGE: f64 = 100.0;
+
+// Occurrences of a recurring payment added at a time, when catching up on those missed.
+// The rest are added the next time the scheduler runs.
+pub const RECURRING_CATCH_UP_LIMIT: usize = 10;
+
+// Categories every chat can give its payments, besides the custom ones it has used.
+// Categories are kept short, as each is sent back as the data of a button.
+pub const PAYMENT_CATEGORIES: [&str; 3] = ["Food", "Transport", "Lodging"];
+pub const MAX_CATEGORY_LENGTH: usize = 16;
+pub const MAX_CUSTOM_CATEGORIES: usize = 6;
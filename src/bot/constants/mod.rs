@@ -3,4 +3,5 @@ pub mod currency;
 pub mod messages;
 pub mod misc;
 pub mod redis;
+pub mod sqlite;
 pub mod urls;
@@ -5,6 +5,7 @@ pub const REQUEST_KEY: &str = "request";
 pub const USER_KEY: &str = "user";
 pub const USER_ID_KEY: &str = "user_id";
 pub const USERNAME_KEY: &str = "username";
+pub const MEMBER_NAME_KEY: &str = "member_name";
 
 // Flow
 #[allow(dead_code)]
@@ -16,12 +17,36 @@ pub const BALANCE_KEY: &str = "balance";
 // Payment
 pub const PAYMENT_KEY: &str = "payment";
 pub const PAYMENT_DEBT_KEY: &str = "payment_debt";
+pub const TRASH_KEY: &str = "trash";
+pub const PAYMENT_EVENT_KEY: &str = "payment_event";
+
+// Recurring payment
+pub const RECURRING_KEY: &str = "recurring";
+pub const RECURRING_DUE_KEY: &str = "recurring_due";
 
 // Chat
 pub const CHAT_KEY: &str = "chat";
 pub const CHAT_PAYMENT_KEY: &str = "chat_payment";
+pub const CHAT_PAYMENT_INDEX_KEY: &str = "chat_payment_index";
+pub const CHAT_TRASH_KEY: &str = "chat_trash";
+pub const CHAT_EVENT_KEY: &str = "chat_event";
 pub const CHAT_CURRENCY_KEY: &str = "chat_currency";
 pub const CHAT_SETTING_KEY: &str = "chat_setting";
+pub const CHAT_RECURRING_KEY: &str = "chat_recurring";
+pub const CHAT_CATEGORY_KEY: &str = "chat_category";
+
+// Event
+pub const EVENT_KEY: &str = "event";
+
+// Dialogue
+pub const DIALOGUE_KEY: &str = "dialogue";
+
+// Migration
+pub const MIGRATION_KEY: &str = "migration";
+pub const MIGRATION_PAYMENT_TIMESTAMPS: &str = "payment_timestamps";
+pub const MIGRATION_CATEGORY_TOTALS: &str = "category_totals";
+// Given to datetimes that cannot be parsed, so that their payments stay readable and can be re-dated
+pub const MIGRATION_DATETIME_FALLBACK: i64 = 0;
 
 // Chat Settings
 pub const SETTING_TIME_ZONE: &str = "time_zone";
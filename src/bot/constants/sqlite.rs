@@ -0,0 +1,2 @@
+// Database
+pub const SQLITE_PATH_DEFAULT: &str = "finamaton.db";
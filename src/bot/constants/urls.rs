@@ -1,2 +1,4 @@
+#[allow(dead_code)]
 pub const USER_GUIDE_URL: &str = "https://finamaton.super.site/user-guide";
+#[allow(dead_code)]
 pub const FEEDBACK_URL: &str = "https://finamaton.super.site/feedback";
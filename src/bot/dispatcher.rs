@@ -1,19 +1,28 @@
-use teloxide::{
-    dispatching::dialogue::{self, InMemStorage},
-    prelude::*,
-    types::MessageId,
-    utils::command::BotCommands,
-};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use teloxide::{prelude::*, types::MessageId, utils::command::BotCommands};
 
 use crate::bot::handlers::*;
 
-use super::{currency::Currency, utils::SelectPaymentType};
+use super::{
+    currency::Currency,
+    redis::ChatRecurringPayment,
+    scheduler::run_scheduler,
+    store::{DialogueKey, DialogueStorage, Store},
+    utils::{SelectPaymentType, UserDialogue},
+};
 
 /* Dispatcher handles conversation branches with the user.
  * Bot states, commands, and control flow are defined here.
  */
 
-#[derive(Clone, Default, Debug)]
+// Version of State as persisted in the store
+// Bump whenever State, or any of the params inside it, changes shape,
+// so that dialogues saved by an older version are discarded instead of misread
+const STATE_VERSION: u32 = 8;
+
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
 pub enum State {
     #[default]
     Start,
@@ -70,12 +79,12 @@ pub enum State {
     },
     ViewPayments {
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
     },
     SelectPayment {
         messages: Vec<MessageId>,
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
         function: SelectPaymentType,
     },
     EditPayment {
@@ -83,14 +92,14 @@ pub enum State {
         payment: Payment,
         edited_payment: EditPaymentParams,
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
     },
     EditPaymentDebtSelection {
         messages: Vec<MessageId>,
         payment: Payment,
         edited_payment: EditPaymentParams,
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
     },
     EditPaymentDetails {
         messages: Vec<MessageId>,
@@ -98,16 +107,43 @@ pub enum State {
         edited_payment: EditPaymentParams,
         edit: AddPaymentEdit,
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
     },
     DeletePayment {
         messages: Vec<MessageId>,
         payment: Payment,
         payments: Vec<Payment>,
-        page: usize,
+        page: PaymentsPage,
     },
     BalancesMenu,
     SpendingsMenu,
+    AuditMenu,
+    ResetGroupMenu,
+    TrashMenu {
+        payments: Vec<Payment>,
+    },
+    RecurringMenu {
+        recurring: Vec<ChatRecurringPayment>,
+    },
+    ImportDocument {
+        messages: Vec<MessageId>,
+    },
+    ImportMembers {
+        messages: Vec<MessageId>,
+        import: ImportParams,
+    },
+    ImportConfirm {
+        messages: Vec<MessageId>,
+        import: ImportParams,
+    },
+    ImportColumns {
+        messages: Vec<MessageId>,
+        import: CsvImportParams,
+    },
+    ImportCsvConfirm {
+        messages: Vec<MessageId>,
+        import: CsvImportParams,
+    },
     SettingsMenu {
         messages: Vec<MessageId>,
     },
@@ -148,17 +184,56 @@ pub enum Command {
     EditPayment,
     #[command(description = "Delete a previous payment")]
     DeletePayment,
+    #[command(description = "View and restore deleted payments")]
+    Trash,
+    #[command(description = "View and stop payments that repeat on a schedule")]
+    Recurring,
+    #[command(description = "View the latest changes, or the history of a payment")]
+    History,
     #[command(description = "View the current balances for everyone")]
     Balances,
     #[command(description = "View the total spendings for everyone")]
     Spendings,
     #[command(description = "View and edit my settings for everyone")]
     Settings,
+    #[command(description = "Check balances and spendings against all payment records")]
+    Audit,
+    #[command(description = "Export all records as CSV and JSON files")]
+    Export,
+    #[command(description = "Import payments from a Splitwise export, or any CSV")]
+    Import,
+    #[command(description = "Delete all records of this group, for admins only")]
+    ResetGroup,
+    #[command(description = "Make me forget you, once your balances are settled")]
+    ForgetMe,
     #[command(description = "Cancel whatever I'm doing")]
     Cancel,
 }
 
-pub async fn run_dispatcher(bot: Bot) {
+/* Gets the dialogue of the user who sent an update, in the chat it was sent in.
+ * Every member of a group has a dialogue of their own,
+ * so that one member in the middle of a flow does not block the others.
+ */
+fn enter_user_dialogue(update: Update, storage: Arc<DialogueStorage>) -> Option<UserDialogue> {
+    let chat_id = update.chat()?.id;
+    let user_id = update.user()?.id;
+    Some(UserDialogue::new(storage, DialogueKey { chat_id, user_id }))
+}
+
+/* Gets the current state of a user dialogue, so that handlers can branch on it.
+ * Updates are dropped if the state cannot be retrieved.
+ */
+async fn get_user_dialogue_state(dialogue: UserDialogue) -> Option<State> {
+    match dialogue.get_or_default().await {
+        Ok(state) => Some(state),
+        Err(err) => {
+            log::error!("Failed to get dialogue state: {err}");
+            None
+        }
+    }
+}
+
+pub async fn run_dispatcher(bot: Bot, store: Store) {
     use dptree::case;
 
     let command_handler = teloxide::filter_command::<Command, _>()
@@ -174,7 +249,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(no_edit_payment))
                 .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
                 .branch(case![Command::Settings].endpoint(action_settings))
-                .branch(case![Command::Spendings].endpoint(action_view_spendings)),
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
         )
         .branch(
             case![State::AddDescription { messages }]
@@ -188,7 +271,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddCreditor { messages, payment }]
@@ -202,7 +293,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddTotal { messages, payment }]
@@ -216,7 +315,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddDebtSelection { messages, payment }]
@@ -230,7 +337,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddDebt {
@@ -248,7 +363,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(block_add_payment))
             .branch(case![Command::DeletePayment].endpoint(block_add_payment))
             .branch(case![Command::Settings].endpoint(block_add_payment))
-            .branch(case![Command::Spendings].endpoint(block_add_payment)),
+            .branch(case![Command::Spendings].endpoint(block_add_payment))
+            .branch(case![Command::Audit].endpoint(block_add_payment))
+            .branch(case![Command::Trash].endpoint(block_add_payment))
+            .branch(case![Command::Recurring].endpoint(block_add_payment))
+            .branch(case![Command::History].endpoint(block_add_payment))
+            .branch(case![Command::Export].endpoint(block_add_payment))
+            .branch(case![Command::Import].endpoint(block_add_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddConfirm { messages, payment }]
@@ -262,7 +385,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddEditMenu { messages, payment }]
@@ -276,7 +407,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_add_payment))
                 .branch(case![Command::DeletePayment].endpoint(block_add_payment))
                 .branch(case![Command::Settings].endpoint(block_add_payment))
-                .branch(case![Command::Spendings].endpoint(block_add_payment)),
+                .branch(case![Command::Spendings].endpoint(block_add_payment))
+                .branch(case![Command::Audit].endpoint(block_add_payment))
+                .branch(case![Command::Trash].endpoint(block_add_payment))
+                .branch(case![Command::Recurring].endpoint(block_add_payment))
+                .branch(case![Command::History].endpoint(block_add_payment))
+                .branch(case![Command::Export].endpoint(block_add_payment))
+                .branch(case![Command::Import].endpoint(block_add_payment))
+                .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+                .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::AddEdit {
@@ -294,7 +433,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(block_add_payment))
             .branch(case![Command::DeletePayment].endpoint(block_add_payment))
             .branch(case![Command::Settings].endpoint(block_add_payment))
-            .branch(case![Command::Spendings].endpoint(block_add_payment)),
+            .branch(case![Command::Spendings].endpoint(block_add_payment))
+            .branch(case![Command::Audit].endpoint(block_add_payment))
+            .branch(case![Command::Trash].endpoint(block_add_payment))
+            .branch(case![Command::Recurring].endpoint(block_add_payment))
+            .branch(case![Command::History].endpoint(block_add_payment))
+            .branch(case![Command::Export].endpoint(block_add_payment))
+            .branch(case![Command::Import].endpoint(block_add_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_add_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_add_payment)),
         )
         .branch(
             case![State::PayBackCurrencyMenu { messages }]
@@ -308,7 +455,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_pay_back))
                 .branch(case![Command::DeletePayment].endpoint(block_pay_back))
                 .branch(case![Command::Settings].endpoint(block_pay_back))
-                .branch(case![Command::Spendings].endpoint(block_pay_back)),
+                .branch(case![Command::Spendings].endpoint(block_pay_back))
+                .branch(case![Command::Audit].endpoint(block_pay_back))
+                .branch(case![Command::Trash].endpoint(block_pay_back))
+                .branch(case![Command::Recurring].endpoint(block_pay_back))
+                .branch(case![Command::History].endpoint(block_pay_back))
+                .branch(case![Command::Export].endpoint(block_pay_back))
+                .branch(case![Command::Import].endpoint(block_pay_back))
+                .branch(case![Command::ResetGroup].endpoint(block_pay_back))
+                .branch(case![Command::ForgetMe].endpoint(block_pay_back)),
         )
         .branch(
             case![State::PayBackCurrency { messages }]
@@ -322,7 +477,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_pay_back))
                 .branch(case![Command::DeletePayment].endpoint(block_pay_back))
                 .branch(case![Command::Settings].endpoint(block_pay_back))
-                .branch(case![Command::Spendings].endpoint(block_pay_back)),
+                .branch(case![Command::Spendings].endpoint(block_pay_back))
+                .branch(case![Command::Audit].endpoint(block_pay_back))
+                .branch(case![Command::Trash].endpoint(block_pay_back))
+                .branch(case![Command::Recurring].endpoint(block_pay_back))
+                .branch(case![Command::History].endpoint(block_pay_back))
+                .branch(case![Command::Export].endpoint(block_pay_back))
+                .branch(case![Command::Import].endpoint(block_pay_back))
+                .branch(case![Command::ResetGroup].endpoint(block_pay_back))
+                .branch(case![Command::ForgetMe].endpoint(block_pay_back)),
         )
         .branch(
             case![State::PayBackDebts { messages, currency }]
@@ -336,7 +499,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_pay_back))
                 .branch(case![Command::DeletePayment].endpoint(block_pay_back))
                 .branch(case![Command::Settings].endpoint(block_pay_back))
-                .branch(case![Command::Spendings].endpoint(block_pay_back)),
+                .branch(case![Command::Spendings].endpoint(block_pay_back))
+                .branch(case![Command::Audit].endpoint(block_pay_back))
+                .branch(case![Command::Trash].endpoint(block_pay_back))
+                .branch(case![Command::Recurring].endpoint(block_pay_back))
+                .branch(case![Command::History].endpoint(block_pay_back))
+                .branch(case![Command::Export].endpoint(block_pay_back))
+                .branch(case![Command::Import].endpoint(block_pay_back))
+                .branch(case![Command::ResetGroup].endpoint(block_pay_back))
+                .branch(case![Command::ForgetMe].endpoint(block_pay_back)),
         )
         .branch(
             case![State::PayBackConfirm { messages, payment }]
@@ -350,7 +521,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_pay_back))
                 .branch(case![Command::DeletePayment].endpoint(block_pay_back))
                 .branch(case![Command::Settings].endpoint(block_pay_back))
-                .branch(case![Command::Spendings].endpoint(block_pay_back)),
+                .branch(case![Command::Spendings].endpoint(block_pay_back))
+                .branch(case![Command::Audit].endpoint(block_pay_back))
+                .branch(case![Command::Trash].endpoint(block_pay_back))
+                .branch(case![Command::Recurring].endpoint(block_pay_back))
+                .branch(case![Command::History].endpoint(block_pay_back))
+                .branch(case![Command::Export].endpoint(block_pay_back))
+                .branch(case![Command::Import].endpoint(block_pay_back))
+                .branch(case![Command::ResetGroup].endpoint(block_pay_back))
+                .branch(case![Command::ForgetMe].endpoint(block_pay_back)),
         )
         .branch(
             case![State::ViewPayments { payments, page }]
@@ -364,7 +543,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(action_select_payment_edit))
                 .branch(case![Command::DeletePayment].endpoint(action_select_payment_delete))
                 .branch(case![Command::Settings].endpoint(action_settings))
-                .branch(case![Command::Spendings].endpoint(action_view_spendings)),
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_select_payment_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
         )
         .branch(
             case![State::SelectPayment {
@@ -383,7 +570,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(handle_repeated_select_payment))
             .branch(case![Command::DeletePayment].endpoint(handle_repeated_select_payment))
             .branch(case![Command::Settings].endpoint(block_select_payment))
-            .branch(case![Command::Spendings].endpoint(block_select_payment)),
+            .branch(case![Command::Spendings].endpoint(block_select_payment))
+            .branch(case![Command::Audit].endpoint(block_select_payment))
+            .branch(case![Command::Trash].endpoint(block_select_payment))
+            .branch(case![Command::Recurring].endpoint(block_select_payment))
+            .branch(case![Command::History].endpoint(handle_repeated_select_payment))
+            .branch(case![Command::Export].endpoint(block_select_payment))
+            .branch(case![Command::Import].endpoint(block_select_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_select_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_select_payment)),
         )
         .branch(
             case![State::EditPayment {
@@ -403,7 +598,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(handle_repeated_edit_payment))
             .branch(case![Command::DeletePayment].endpoint(block_edit_payment))
             .branch(case![Command::Settings].endpoint(block_edit_payment))
-            .branch(case![Command::Spendings].endpoint(block_edit_payment)),
+            .branch(case![Command::Spendings].endpoint(block_edit_payment))
+            .branch(case![Command::Audit].endpoint(block_edit_payment))
+            .branch(case![Command::Trash].endpoint(block_edit_payment))
+            .branch(case![Command::Recurring].endpoint(block_edit_payment))
+            .branch(case![Command::History].endpoint(block_edit_payment))
+            .branch(case![Command::Export].endpoint(block_edit_payment))
+            .branch(case![Command::Import].endpoint(block_edit_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_edit_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_edit_payment)),
         )
         .branch(
             case![State::EditPaymentDebtSelection {
@@ -423,7 +626,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(handle_repeated_edit_payment))
             .branch(case![Command::DeletePayment].endpoint(block_edit_payment))
             .branch(case![Command::Settings].endpoint(block_edit_payment))
-            .branch(case![Command::Spendings].endpoint(block_edit_payment)),
+            .branch(case![Command::Spendings].endpoint(block_edit_payment))
+            .branch(case![Command::Audit].endpoint(block_edit_payment))
+            .branch(case![Command::Trash].endpoint(block_edit_payment))
+            .branch(case![Command::Recurring].endpoint(block_edit_payment))
+            .branch(case![Command::History].endpoint(block_edit_payment))
+            .branch(case![Command::Export].endpoint(block_edit_payment))
+            .branch(case![Command::Import].endpoint(block_edit_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_edit_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_edit_payment)),
         )
         .branch(
             case![State::EditPaymentDetails {
@@ -444,7 +655,15 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(handle_repeated_edit_payment))
             .branch(case![Command::DeletePayment].endpoint(block_edit_payment))
             .branch(case![Command::Settings].endpoint(block_edit_payment))
-            .branch(case![Command::Spendings].endpoint(block_edit_payment)),
+            .branch(case![Command::Spendings].endpoint(block_edit_payment))
+            .branch(case![Command::Audit].endpoint(block_edit_payment))
+            .branch(case![Command::Trash].endpoint(block_edit_payment))
+            .branch(case![Command::Recurring].endpoint(block_edit_payment))
+            .branch(case![Command::History].endpoint(block_edit_payment))
+            .branch(case![Command::Export].endpoint(block_edit_payment))
+            .branch(case![Command::Import].endpoint(block_edit_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_edit_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_edit_payment)),
         )
         .branch(
             case![State::DeletePayment {
@@ -463,7 +682,125 @@ pub async fn run_dispatcher(bot: Bot) {
             .branch(case![Command::EditPayment].endpoint(block_delete_payment))
             .branch(case![Command::DeletePayment].endpoint(handle_repeated_delete_payment))
             .branch(case![Command::Settings].endpoint(block_delete_payment))
-            .branch(case![Command::Spendings].endpoint(block_delete_payment)),
+            .branch(case![Command::Spendings].endpoint(block_delete_payment))
+            .branch(case![Command::Audit].endpoint(block_delete_payment))
+            .branch(case![Command::Trash].endpoint(block_delete_payment))
+            .branch(case![Command::Recurring].endpoint(block_delete_payment))
+            .branch(case![Command::History].endpoint(block_delete_payment))
+            .branch(case![Command::Export].endpoint(block_delete_payment))
+            .branch(case![Command::Import].endpoint(block_delete_payment))
+            .branch(case![Command::ResetGroup].endpoint(block_delete_payment))
+            .branch(case![Command::ForgetMe].endpoint(block_delete_payment)),
+        )
+        .branch(
+            case![State::ImportDocument { messages }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(cancel_import))
+                .branch(case![Command::AddPayment].endpoint(block_import))
+                .branch(case![Command::Balances].endpoint(block_import))
+                .branch(case![Command::PayBack].endpoint(block_import))
+                .branch(case![Command::ViewPayments].endpoint(block_import))
+                .branch(case![Command::EditPayment].endpoint(block_import))
+                .branch(case![Command::DeletePayment].endpoint(block_import))
+                .branch(case![Command::Settings].endpoint(block_import))
+                .branch(case![Command::Spendings].endpoint(block_import))
+                .branch(case![Command::Audit].endpoint(block_import))
+                .branch(case![Command::Trash].endpoint(block_import))
+                .branch(case![Command::Recurring].endpoint(block_import))
+                .branch(case![Command::History].endpoint(block_import))
+                .branch(case![Command::Export].endpoint(block_import))
+                .branch(case![Command::Import].endpoint(handle_repeated_import))
+                .branch(case![Command::ResetGroup].endpoint(block_import))
+                .branch(case![Command::ForgetMe].endpoint(block_import)),
+        )
+        .branch(
+            case![State::ImportMembers { messages, import }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(cancel_import))
+                .branch(case![Command::AddPayment].endpoint(block_import))
+                .branch(case![Command::Balances].endpoint(block_import))
+                .branch(case![Command::PayBack].endpoint(block_import))
+                .branch(case![Command::ViewPayments].endpoint(block_import))
+                .branch(case![Command::EditPayment].endpoint(block_import))
+                .branch(case![Command::DeletePayment].endpoint(block_import))
+                .branch(case![Command::Settings].endpoint(block_import))
+                .branch(case![Command::Spendings].endpoint(block_import))
+                .branch(case![Command::Audit].endpoint(block_import))
+                .branch(case![Command::Trash].endpoint(block_import))
+                .branch(case![Command::Recurring].endpoint(block_import))
+                .branch(case![Command::History].endpoint(block_import))
+                .branch(case![Command::Export].endpoint(block_import))
+                .branch(case![Command::Import].endpoint(handle_repeated_import))
+                .branch(case![Command::ResetGroup].endpoint(block_import))
+                .branch(case![Command::ForgetMe].endpoint(block_import)),
+        )
+        .branch(
+            case![State::ImportConfirm { messages, import }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(cancel_import))
+                .branch(case![Command::AddPayment].endpoint(block_import))
+                .branch(case![Command::Balances].endpoint(block_import))
+                .branch(case![Command::PayBack].endpoint(block_import))
+                .branch(case![Command::ViewPayments].endpoint(block_import))
+                .branch(case![Command::EditPayment].endpoint(block_import))
+                .branch(case![Command::DeletePayment].endpoint(block_import))
+                .branch(case![Command::Settings].endpoint(block_import))
+                .branch(case![Command::Spendings].endpoint(block_import))
+                .branch(case![Command::Audit].endpoint(block_import))
+                .branch(case![Command::Trash].endpoint(block_import))
+                .branch(case![Command::Recurring].endpoint(block_import))
+                .branch(case![Command::History].endpoint(block_import))
+                .branch(case![Command::Export].endpoint(block_import))
+                .branch(case![Command::Import].endpoint(handle_repeated_import))
+                .branch(case![Command::ResetGroup].endpoint(block_import))
+                .branch(case![Command::ForgetMe].endpoint(block_import)),
+        )
+        .branch(
+            case![State::ImportColumns { messages, import }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(cancel_import))
+                .branch(case![Command::AddPayment].endpoint(block_import))
+                .branch(case![Command::Balances].endpoint(block_import))
+                .branch(case![Command::PayBack].endpoint(block_import))
+                .branch(case![Command::ViewPayments].endpoint(block_import))
+                .branch(case![Command::EditPayment].endpoint(block_import))
+                .branch(case![Command::DeletePayment].endpoint(block_import))
+                .branch(case![Command::Settings].endpoint(block_import))
+                .branch(case![Command::Spendings].endpoint(block_import))
+                .branch(case![Command::Audit].endpoint(block_import))
+                .branch(case![Command::Trash].endpoint(block_import))
+                .branch(case![Command::Recurring].endpoint(block_import))
+                .branch(case![Command::History].endpoint(block_import))
+                .branch(case![Command::Export].endpoint(block_import))
+                .branch(case![Command::Import].endpoint(handle_repeated_import))
+                .branch(case![Command::ResetGroup].endpoint(block_import))
+                .branch(case![Command::ForgetMe].endpoint(block_import)),
+        )
+        .branch(
+            case![State::ImportCsvConfirm { messages, import }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(cancel_import))
+                .branch(case![Command::AddPayment].endpoint(block_import))
+                .branch(case![Command::Balances].endpoint(block_import))
+                .branch(case![Command::PayBack].endpoint(block_import))
+                .branch(case![Command::ViewPayments].endpoint(block_import))
+                .branch(case![Command::EditPayment].endpoint(block_import))
+                .branch(case![Command::DeletePayment].endpoint(block_import))
+                .branch(case![Command::Settings].endpoint(block_import))
+                .branch(case![Command::Spendings].endpoint(block_import))
+                .branch(case![Command::Audit].endpoint(block_import))
+                .branch(case![Command::Trash].endpoint(block_import))
+                .branch(case![Command::Recurring].endpoint(block_import))
+                .branch(case![Command::History].endpoint(block_import))
+                .branch(case![Command::Export].endpoint(block_import))
+                .branch(case![Command::Import].endpoint(handle_repeated_import))
+                .branch(case![Command::ResetGroup].endpoint(block_import))
+                .branch(case![Command::ForgetMe].endpoint(block_import)),
         )
         .branch(
             case![State::SettingsMenu { messages }]
@@ -477,7 +814,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsTimeZoneMenu { messages }]
@@ -491,7 +836,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsTimeZone { messages }]
@@ -505,7 +858,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsDefaultCurrencyMenu { messages }]
@@ -519,7 +880,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsDefaultCurrency { messages }]
@@ -533,7 +902,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsCurrencyConversion { messages }]
@@ -547,7 +924,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::SettingsEraseMessages { messages }]
@@ -561,7 +946,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(block_settings))
                 .branch(case![Command::DeletePayment].endpoint(block_settings))
                 .branch(case![Command::Settings].endpoint(handle_repeated_settings))
-                .branch(case![Command::Spendings].endpoint(block_settings)),
+                .branch(case![Command::Spendings].endpoint(block_settings))
+                .branch(case![Command::Audit].endpoint(block_settings))
+                .branch(case![Command::Trash].endpoint(block_settings))
+                .branch(case![Command::Recurring].endpoint(block_settings))
+                .branch(case![Command::History].endpoint(block_settings))
+                .branch(case![Command::Export].endpoint(block_settings))
+                .branch(case![Command::Import].endpoint(block_settings))
+                .branch(case![Command::ResetGroup].endpoint(block_settings))
+                .branch(case![Command::ForgetMe].endpoint(block_settings)),
         )
         .branch(
             case![State::BalancesMenu]
@@ -575,7 +968,15 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(no_edit_payment))
                 .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
                 .branch(case![Command::Settings].endpoint(action_settings))
-                .branch(case![Command::Spendings].endpoint(action_view_spendings)),
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
         )
         .branch(
             case![State::SpendingsMenu]
@@ -589,10 +990,107 @@ pub async fn run_dispatcher(bot: Bot) {
                 .branch(case![Command::EditPayment].endpoint(no_edit_payment))
                 .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
                 .branch(case![Command::Settings].endpoint(action_settings))
-                .branch(case![Command::Spendings].endpoint(action_view_spendings)),
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
+        )
+        .branch(
+            case![State::AuditMenu]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(action_cancel))
+                .branch(case![Command::AddPayment].endpoint(action_add_payment))
+                .branch(case![Command::Balances].endpoint(action_view_balances))
+                .branch(case![Command::PayBack].endpoint(action_pay_back))
+                .branch(case![Command::ViewPayments].endpoint(action_view_payments))
+                .branch(case![Command::EditPayment].endpoint(no_edit_payment))
+                .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
+                .branch(case![Command::Settings].endpoint(action_settings))
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
+        )
+        .branch(
+            case![State::ResetGroupMenu]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(action_cancel))
+                .branch(case![Command::AddPayment].endpoint(action_add_payment))
+                .branch(case![Command::Balances].endpoint(action_view_balances))
+                .branch(case![Command::PayBack].endpoint(action_pay_back))
+                .branch(case![Command::ViewPayments].endpoint(action_view_payments))
+                .branch(case![Command::EditPayment].endpoint(no_edit_payment))
+                .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
+                .branch(case![Command::Settings].endpoint(action_settings))
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
+        )
+        .branch(
+            case![State::TrashMenu { payments }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(action_cancel))
+                .branch(case![Command::AddPayment].endpoint(action_add_payment))
+                .branch(case![Command::Balances].endpoint(action_view_balances))
+                .branch(case![Command::PayBack].endpoint(action_pay_back))
+                .branch(case![Command::ViewPayments].endpoint(action_view_payments))
+                .branch(case![Command::EditPayment].endpoint(no_edit_payment))
+                .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
+                .branch(case![Command::Settings].endpoint(action_settings))
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
+        )
+        .branch(
+            case![State::RecurringMenu { recurring }]
+                .branch(case![Command::Start].endpoint(action_start))
+                .branch(case![Command::Help].endpoint(action_help))
+                .branch(case![Command::Cancel].endpoint(action_cancel))
+                .branch(case![Command::AddPayment].endpoint(action_add_payment))
+                .branch(case![Command::Balances].endpoint(action_view_balances))
+                .branch(case![Command::PayBack].endpoint(action_pay_back))
+                .branch(case![Command::ViewPayments].endpoint(action_view_payments))
+                .branch(case![Command::EditPayment].endpoint(no_edit_payment))
+                .branch(case![Command::DeletePayment].endpoint(no_delete_payment))
+                .branch(case![Command::Settings].endpoint(action_settings))
+                .branch(case![Command::Spendings].endpoint(action_view_spendings))
+                .branch(case![Command::Audit].endpoint(action_audit))
+                .branch(case![Command::Trash].endpoint(action_trash))
+                .branch(case![Command::Recurring].endpoint(action_recurring))
+                .branch(case![Command::History].endpoint(action_history))
+                .branch(case![Command::Export].endpoint(action_export))
+                .branch(case![Command::Import].endpoint(action_import))
+                .branch(case![Command::ResetGroup].endpoint(action_reset_group))
+                .branch(case![Command::ForgetMe].endpoint(action_forget_me)),
         );
 
     let message_handler = Update::filter_message()
+        .inspect_async(track_user_identity)
         .branch(command_handler)
         .branch(case![State::AddDescription { messages }].endpoint(action_add_description))
         .branch(case![State::AddCreditor { messages, payment }].endpoint(action_add_creditor))
@@ -626,6 +1124,8 @@ pub async fn run_dispatcher(bot: Bot) {
             }]
             .endpoint(action_edit_payment_edit),
         )
+        .branch(case![State::ImportDocument { messages }].endpoint(action_import_document))
+        .branch(case![State::ImportMembers { messages, import }].endpoint(action_import_member))
         .branch(case![State::SettingsTimeZone { messages }].endpoint(action_settings_time_zone))
         .branch(
             case![State::SettingsDefaultCurrency { messages }]
@@ -681,6 +1181,11 @@ pub async fn run_dispatcher(bot: Bot) {
             }]
             .endpoint(callback_invalid_message),
         )
+        .branch(case![State::ImportConfirm { messages, import }].endpoint(callback_invalid_message))
+        .branch(case![State::ImportColumns { messages, import }].endpoint(callback_invalid_message))
+        .branch(
+            case![State::ImportCsvConfirm { messages, import }].endpoint(callback_invalid_message),
+        )
         .branch(case![State::SettingsMenu { messages }].endpoint(callback_invalid_message))
         .branch(case![State::SettingsTimeZoneMenu { messages }].endpoint(callback_invalid_message))
         .branch(
@@ -695,6 +1200,10 @@ pub async fn run_dispatcher(bot: Bot) {
         .branch(case![State::ViewPayments { payments, page }].endpoint(invalid_state))
         .branch(case![State::BalancesMenu].endpoint(invalid_state))
         .branch(case![State::SpendingsMenu].endpoint(invalid_state))
+        .branch(case![State::AuditMenu].endpoint(invalid_state))
+        .branch(case![State::ResetGroupMenu].endpoint(invalid_state))
+        .branch(case![State::TrashMenu { payments }].endpoint(invalid_state))
+        .branch(case![State::RecurringMenu { recurring }].endpoint(invalid_state))
         .branch(case![State::Start].endpoint(invalid_state));
 
     let callback_query_handler = Update::filter_callback_query()
@@ -708,6 +1217,14 @@ pub async fn run_dispatcher(bot: Bot) {
                 .endpoint(action_add_debt_selection),
         )
         .branch(case![State::AddEditMenu { messages, payment }].endpoint(action_add_edit_menu))
+        .branch(
+            case![State::AddEdit {
+                messages,
+                payment,
+                edit
+            }]
+            .endpoint(action_add_edit_category),
+        )
         .branch(
             case![State::PayBackCurrencyMenu { messages }].endpoint(action_pay_back_currency_menu),
         )
@@ -744,6 +1261,17 @@ pub async fn run_dispatcher(bot: Bot) {
             }]
             .endpoint(action_edit_payment_debts),
         )
+        .branch(
+            case![State::EditPaymentDetails {
+                messages,
+                payment,
+                edited_payment,
+                edit,
+                payments,
+                page
+            }]
+            .endpoint(action_edit_payment_category),
+        )
         .branch(
             case![State::DeletePayment {
                 messages,
@@ -755,6 +1283,20 @@ pub async fn run_dispatcher(bot: Bot) {
         )
         .branch(case![State::BalancesMenu].endpoint(action_balances_menu))
         .branch(case![State::SpendingsMenu].endpoint(action_spendings_menu))
+        .branch(case![State::AuditMenu].endpoint(action_audit_menu))
+        .branch(case![State::ResetGroupMenu].endpoint(action_reset_group_menu))
+        .branch(case![State::TrashMenu { payments }].endpoint(action_trash_menu))
+        .branch(case![State::RecurringMenu { recurring }].endpoint(action_recurring_menu))
+        .branch(
+            case![State::ImportMembers { messages, import }].endpoint(action_import_member_menu),
+        )
+        .branch(case![State::ImportConfirm { messages, import }].endpoint(action_import_confirm))
+        .branch(
+            case![State::ImportColumns { messages, import }].endpoint(action_import_column_menu),
+        )
+        .branch(
+            case![State::ImportCsvConfirm { messages, import }].endpoint(action_import_csv_confirm),
+        )
         .branch(case![State::SettingsMenu { messages }].endpoint(action_settings_menu))
         .branch(case![State::SettingsTimeZoneMenu { messages }].endpoint(action_time_zone_menu))
         .branch(
@@ -770,12 +1312,27 @@ pub async fn run_dispatcher(bot: Bot) {
                 .endpoint(action_settings_erase_messages),
         );
 
-    let schema = dialogue::enter::<Update, InMemStorage<State>, State, _>()
-        .branch(message_handler)
-        .branch(callback_query_handler);
+    // Migrations are handled before dialogues, as they are service messages about the chat
+    let migration_handler = Update::filter_message()
+        .filter(|msg: Message| {
+            msg.migrate_to_chat_id().is_some() || msg.migrate_from_chat_id().is_some()
+        })
+        .endpoint(action_migrate_chat);
+
+    let schema = dptree::entry().branch(migration_handler).branch(
+        dptree::filter_map(enter_user_dialogue)
+            .filter_map_async(get_user_dialogue_state)
+            .branch(message_handler)
+            .branch(callback_query_handler),
+    );
+
+    tokio::spawn(run_scheduler(bot.clone(), store.clone()));
 
     Dispatcher::builder(bot, schema)
-        .dependencies(dptree::deps![InMemStorage::<State>::new()])
+        .dependencies(dptree::deps![
+            DialogueStorage::new(store.clone(), STATE_VERSION),
+            store
+        ])
         .enable_ctrlc_handler()
         .build()
         .dispatch()
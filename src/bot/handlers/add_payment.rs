@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
@@ -6,27 +7,37 @@ use teloxide::{
 
 use crate::bot::{
     constants::{
-        commands::COMMAND_CANCEL,
+        commands::{COMMAND_CANCEL, COMMAND_RECURRING},
         messages::{
-            CANCEL_ADD_MESSAGE, DEBT_EQUAL_DESCRIPTION_MESSAGE, DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
+            CANCEL_ADD_MESSAGE, CATEGORY_INSTRUCTIONS_MESSAGE, DATE_INSTRUCTIONS_MESSAGE,
+            DEBT_EQUAL_DESCRIPTION_MESSAGE, DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
             DEBT_EXACT_DESCRIPTION_MESSAGE, DEBT_EXACT_INSTRUCTIONS_MESSAGE,
+            DEBT_ITEMIZED_DESCRIPTION_MESSAGE, DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE,
             DEBT_RATIO_DESCRIPTION_MESSAGE, DEBT_RATIO_INSTRUCTIONS_MESSAGE, NO_TEXT_MESSAGE,
-            TOTAL_INSTRUCTIONS_MESSAGE, UNKNOWN_ERROR_MESSAGE,
+            PAYER_INSTRUCTIONS_MESSAGE, RECURRING_INSTRUCTIONS_MESSAGE, TOTAL_INSTRUCTIONS_MESSAGE,
+            UNKNOWN_ERROR_MESSAGE,
         },
     },
     currency::Currency,
     dispatcher::State,
-    processor::add_payment,
+    processor::{add_payment, add_recurring_payment},
+    redis::PaymentItem,
+    store::Store,
     utils::{
-        amounts::{parse_currency_amount, process_debts},
+        amounts::{check_payers_total, parse_currency_amount, process_debts, process_payers},
         bot_actions::{
-            assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
+            assert_handle_request_limit, delete_bot_messages, is_erase_messages,
+            process_chat_categories, send_bot_message,
         },
         format::{
-            display_balance_header, display_balances, display_currency_amount, display_debts,
-            display_username, make_keyboard, make_keyboard_debt_selection, parse_username,
-            use_currency,
+            balance_members, debt_members, display_balance_header, display_balances,
+            display_currency_amount, display_debts, display_items, display_payers,
+            display_username, get_chat_default_currency, get_member_names, make_keyboard,
+            make_keyboard_categories, make_keyboard_debt_selection, member_from_user,
+            parse_category, parse_text_mentions, payment_members, use_currency,
         },
+        schedule::{display_schedule, parse_schedule, Schedule},
+        time::{format_timestamp, parse_payment_date, retrieve_time_zone},
         HandlerResult, UserDialogue,
     },
 };
@@ -36,34 +47,43 @@ use crate::bot::{
 // };
 
 /* Utilities */
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AddPaymentParams {
     chat_id: String,
     sender_id: String,
     sender_username: String,
-    datetime: String,
+    timestamp: i64,
     description: Option<String>,
     creditor: Option<String>,
+    payers: Vec<(String, i64)>,
     currency: Option<Currency>,
     total: Option<i64>,
     debts: Option<Vec<(String, i64)>>,
+    items: Vec<PaymentItem>,
+    category: Option<String>,
+    recurring: Option<Schedule>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AddPaymentEdit {
     Description,
+    Date,
     Creditor,
     Total,
     DebtsEqual,
     DebtsExact,
     DebtsRatio,
+    DebtsItemized,
+    Category,
+    Repeat,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum AddDebtsFormat {
     Equal,
     Exact,
     Ratio,
+    Itemized,
 }
 
 // Controls the state for misc handler actions that return to same state.
@@ -170,9 +190,10 @@ async fn complete_add_payment(
     dialogue: UserDialogue,
     chat_id: &str,
     messages: Vec<MessageId>,
+    store: &Store,
 ) -> HandlerResult {
-    if is_erase_messages(chat_id) {
-        delete_bot_messages(&bot, chat_id, messages).await?;
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
     }
     dialogue.exit().await?;
     Ok(())
@@ -180,20 +201,54 @@ async fn complete_add_payment(
 
 /* Displays a payment entry (being added) in String format.
 */
-fn display_add_payment(payment: &AddPaymentParams) -> String {
+async fn display_add_payment(payment: &AddPaymentParams, store: &Store) -> String {
     let description = match &payment.description {
         Some(desc) => format!("Description: {}\n", desc),
         None => "".to_string(),
     };
+    let category = match &payment.category {
+        Some(category) => format!("Category: {}\n", category),
+        None => "".to_string(),
+    };
+    let time_zone = retrieve_time_zone(store, &payment.chat_id).await;
+    let names = get_member_names(
+        store,
+        payment_members(
+            payment.creditor.as_deref().unwrap_or_default(),
+            &payment.payers,
+            payment.debts.as_deref().unwrap_or_default(),
+            &payment.items,
+        ),
+    )
+    .await;
+    let date = format!("Date: {}\n", format_timestamp(payment.timestamp, time_zone));
+    let recurring = match &payment.recurring {
+        Some(schedule) => format!(
+            "Repeats: {}\n",
+            display_schedule(
+                &schedule.frequency,
+                schedule.start.unwrap_or(payment.timestamp),
+                schedule.end,
+                time_zone
+            )
+        ),
+        None => "".to_string(),
+    };
     let creditor = match &payment.creditor {
-        Some(cred) => format!("Payer: {}\n", display_username(cred)),
+        Some(cred) => match &payment.currency {
+            Some(currency) => display_payers(cred, &payment.payers, currency.1, &names),
+            None => format!("Payer: {}\n", display_username(cred, &names)),
+        },
         None => "".to_string(),
     };
     let total = match &payment.total {
         Some(total) => match &payment.currency {
             Some(currency) => format!(
                 "Total: {}\n",
-                display_currency_amount(*total, use_currency(currency.clone(), &payment.chat_id))
+                display_currency_amount(
+                    *total,
+                    use_currency(store, currency.clone(), &payment.chat_id).await
+                )
             ),
             None => "".to_string(),
         },
@@ -201,13 +256,26 @@ fn display_add_payment(payment: &AddPaymentParams) -> String {
     };
     let debts = match &payment.debts {
         Some(debts) => match &payment.currency {
-            Some(currency) => format!("Split:\n{}", display_debts(&debts, currency.1)),
+            Some(currency) => format!("Split:\n{}", display_debts(debts, currency.1, &names)),
             None => "".to_string(),
         },
         None => "".to_string(),
     };
 
-    format!("{}{}{}{}\n", description, creditor, total, debts)
+    let items = match &payment.currency {
+        Some(currency) if !payment.items.is_empty() => {
+            format!(
+                "Items:\n{}",
+                display_items(&payment.items, currency.1, &names)
+            )
+        }
+        _ => "".to_string(),
+    };
+
+    format!(
+        "{}{}{}{}{}{}{}{}\n",
+        description, category, date, recurring, creditor, total, items, debts
+    )
 }
 
 /* Add a payment entry in a group chat.
@@ -220,16 +288,17 @@ async fn display_add_overview(
     msg: &Message,
     mut messages: Vec<MessageId>,
     payment: AddPaymentParams,
+    store: &Store,
 ) -> HandlerResult {
     let buttons = vec!["Cancel", "Edit", "Confirm"];
     let keyboard = make_keyboard(buttons, Some(2));
 
     let new_message = send_bot_message(
-        &bot,
-        &msg,
+        bot,
+        msg,
         format!(
             "Here you go! 📝\n\n{}Do you submit this entry or do you want to make any changes?",
-            display_add_payment(&payment)
+            display_add_payment(&payment, store).await
         ),
     )
     .reply_markup(keyboard)
@@ -251,8 +320,18 @@ async fn display_add_edit_menu(
     query: CallbackQuery,
     messages: Vec<MessageId>,
     payment: AddPaymentParams,
+    store: &Store,
 ) -> HandlerResult {
-    let buttons = vec!["Description", "Payer", "Total", "Split", "Back"];
+    let buttons = vec![
+        "Description",
+        "Date",
+        "Payer",
+        "Total",
+        "Split",
+        "Category",
+        "Repeat",
+        "Back",
+    ];
     let keyboard = make_keyboard(buttons, Some(2));
 
     if let Some(Message { id, chat, .. }) = query.message {
@@ -261,7 +340,7 @@ async fn display_add_edit_menu(
             id,
             format!(
                 "{}Sure! What expense do you wish to edit?",
-                display_add_payment(&payment)
+                display_add_payment(&payment, store).await
             ),
         )
         .reply_markup(keyboard)
@@ -275,6 +354,7 @@ async fn display_add_edit_menu(
 
 /* Parses a string representing debts, and handles it accordingly
 */
+#[allow(clippy::too_many_arguments)]
 async fn handle_debts(
     bot: Bot,
     dialogue: UserDialogue,
@@ -283,43 +363,52 @@ async fn handle_debts(
     messages: Vec<MessageId>,
     payment: AddPaymentParams,
     debts_format: AddDebtsFormat,
+    store: &Store,
 ) -> HandlerResult {
     let error_msg = match debts_format {
         AddDebtsFormat::Equal => DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
         AddDebtsFormat::Exact => DEBT_EXACT_INSTRUCTIONS_MESSAGE,
         AddDebtsFormat::Ratio => DEBT_RATIO_INSTRUCTIONS_MESSAGE,
+        AddDebtsFormat::Itemized => DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE,
     };
-    match msg.text() {
+    match parse_text_mentions(&msg) {
         Some(text) => {
             let debts = process_debts(
                 debts_format,
-                text,
+                &text,
                 &payment.creditor,
                 payment.currency.clone(),
                 payment.total,
             );
-            if let Err(err) = debts {
-                let new_message =
-                    send_bot_message(&bot, &msg, format!("{}\n\n{error_msg}", err.to_string()))
-                        .await?
-                        .id;
-                repeat_state(dialogue, state, new_message).await?;
-                return Ok(());
-            }
+            let (debts, items) = match debts {
+                Ok(debts) => debts,
+                Err(err) => {
+                    let new_message =
+                        send_bot_message(&bot, &msg, format!("{}\n\n{error_msg}", err))
+                            .await?
+                            .id;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
+                }
+            };
 
             let new_payment = AddPaymentParams {
                 chat_id: payment.chat_id,
                 sender_id: payment.sender_id,
                 sender_username: payment.sender_username,
-                datetime: payment.datetime,
+                timestamp: payment.timestamp,
                 description: payment.description,
                 creditor: payment.creditor,
+                payers: payment.payers,
                 currency: payment.currency,
                 total: payment.total,
-                debts: Some(debts?),
+                debts: Some(debts),
+                items,
+                category: payment.category,
+                recurring: payment.recurring,
             };
 
-            display_add_overview(&bot, &dialogue, &msg, messages, new_payment).await?;
+            display_add_overview(&bot, &dialogue, &msg, messages, new_payment, store).await?;
         }
         None => {
             let new_message = send_bot_message(&bot, &msg, error_msg.to_string())
@@ -339,6 +428,7 @@ async fn call_processor_add_payment(
     messages: Vec<MessageId>,
     payment: AddPaymentParams,
     query: CallbackQuery,
+    store: &Store,
 ) -> HandlerResult {
     if let Some(msg) = query.message {
         let chat_id = msg.chat.id;
@@ -351,7 +441,7 @@ async fn call_processor_add_payment(
                     payment_clone
                 );
                 send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
-                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
                 return Ok(());
             }
         };
@@ -363,7 +453,7 @@ async fn call_processor_add_payment(
                     payment_clone
                 );
                 send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
-                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
                 return Ok(());
             }
         };
@@ -375,7 +465,7 @@ async fn call_processor_add_payment(
                     payment_clone
                 );
                 send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
-                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
                 return Ok(());
             }
         };
@@ -387,7 +477,7 @@ async fn call_processor_add_payment(
                     payment_clone
                 );
                 send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
-                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
                 return Ok(());
             }
         };
@@ -399,21 +489,88 @@ async fn call_processor_add_payment(
                     payment_clone
                 );
                 send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
-                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+                complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
                 return Ok(());
             }
         };
-        let payment_overview = display_add_payment(&payment_clone);
+        let payment_overview = display_add_payment(&payment_clone, store).await;
+        if let Some(schedule) = payment.recurring {
+            let next = add_recurring_payment(
+                store,
+                &payment.chat_id,
+                &payment.sender_username,
+                &payment.sender_id,
+                schedule.start.unwrap_or(payment.timestamp),
+                &description,
+                &creditor,
+                &currency.0,
+                total,
+                debts,
+                payment.items,
+                payment.category,
+                payment.payers,
+                schedule.frequency,
+                schedule.end,
+            )
+            .await;
+            match next {
+                Ok(next) => {
+                    let time_zone = retrieve_time_zone(store, &payment.chat_id).await;
+                    send_bot_message(
+                        &bot,
+                        &msg,
+                        format!(
+                            "Recurring payment successfully added! I'll add it on {}, and every time it's due after that. Stop it anytime with {COMMAND_RECURRING}.\n\n{}",
+                            format_timestamp(next, time_zone).trim(),
+                            payment_overview,
+                        ),
+                    )
+                    .await?;
+
+                    // Logging
+                    log::info!(
+                        "Add Payment Submission - Processor added recurring payment successfully for user {} in chat {}: {:?}",
+                        payment_clone.sender_id,
+                        payment_clone.chat_id,
+                        payment_clone
+                    );
+                }
+                Err(err) => {
+                    send_bot_message(
+                        &bot,
+                        &msg,
+                        "🤷 Oops! Something went wrong! I can't add the recurring payment right now. Please try again later!\n\n".to_string(),
+                    )
+                    .await?;
+
+                    // Logging
+                    log::error!(
+                        "Add Payment Submission - Processor failed to add recurring payment for user {} in chat {} with payment {:?}: {}",
+                        payment_clone.sender_id,
+                        payment_clone.chat_id,
+                        payment_clone,
+                        err.to_string()
+                    );
+                }
+            }
+            complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
+            return Ok(());
+        }
+
         let updated_balances = add_payment(
+            store,
             payment.chat_id.clone(),
             payment.sender_username,
             payment.sender_id,
-            payment.datetime,
+            payment.timestamp,
             &description,
             &creditor,
             &currency.0,
             total,
             debts,
+            payment.items,
+            payment.category,
+            payment.payers,
         )
         .await;
         match updated_balances {
@@ -429,8 +586,11 @@ async fn call_processor_add_payment(
                     &msg,
                     format!(
                         "{}{}",
-                        display_balance_header(&payment.chat_id, &currency.0),
-                        display_balances(&balances)
+                        display_balance_header(store, &payment.chat_id, &currency.0).await,
+                        display_balances(
+                            &balances,
+                            &get_member_names(store, balance_members(&balances)).await
+                        )
                     ),
                 )
                 .await?;
@@ -447,9 +607,7 @@ async fn call_processor_add_payment(
                 send_bot_message(
                     &bot,
                     &msg,
-                    format!(
-                        "🤷 Oops! Something went wrong! I can't add the payment right now. Please try again later!\n\n"
-                    ),
+                    "🤷 Oops! Something went wrong! I can't add the payment right now. Please try again later!\n\n".to_string(),
                 )
                 .await?;
 
@@ -463,7 +621,7 @@ async fn call_processor_add_payment(
                     );
             }
         }
-        complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages).await?;
+        complete_add_payment(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
     }
     Ok(())
 }
@@ -478,8 +636,9 @@ pub async fn handle_repeated_add_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -500,8 +659,9 @@ pub async fn cancel_add_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -517,7 +677,8 @@ pub async fn cancel_add_payment(
         | State::AddEditMenu { messages, .. }
         | State::AddEdit { messages, .. }
         | State::AddEditDebtsMenu { messages, .. } => {
-            complete_add_payment(&bot, dialogue, &msg.chat.id.to_string(), messages).await?;
+            complete_add_payment(&bot, dialogue, &msg.chat.id.to_string(), messages, &store)
+                .await?;
         }
         _ => (),
     }
@@ -532,8 +693,9 @@ pub async fn block_add_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -551,15 +713,21 @@ pub async fn block_add_payment(
  * Bot will ask for user to send messages to fill in required information,
  * before presenting the compiled information for confirmation with a menu.
  */
-pub async fn action_add_payment(bot: Bot, dialogue: UserDialogue, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_add_payment(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     let new_message = send_bot_message(
         &bot,
         &msg,
-        format!("Absolutely, let's get started! \n\nWhat's the description for this new expense?"),
+        "Absolutely, let's get started! \n\nWhat's the description for this new expense?"
+            .to_string(),
     )
     .await?
     .id;
@@ -581,59 +749,45 @@ pub async fn action_add_description(
     state: State,
     msg: Message,
     mut messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     match msg.text() {
         Some(text) => {
             let user = msg.from();
             if let Some(user) = user {
-                if let Some(username) = &user.username {
-                    let username = parse_username(username);
-
-                    if let Err(err) = &username {
-                        let new_message =
-                            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string())
-                                .await?
-                                .id;
-                        repeat_state(dialogue.clone(), state, new_message).await?;
-
-                        // Logging
-                        log::error!(
-                            "Add Payment Description - Failed to parse username for user {}: {}",
-                            user.id,
-                            err.to_string()
-                        );
-                    }
-
-                    let payment = AddPaymentParams {
-                        chat_id: msg.chat.id.to_string(),
-                        sender_id: user.id.to_string(),
-                        sender_username: username?,
-                        datetime: msg.date.to_string(),
-                        description: Some(text.to_string()),
-                        creditor: None,
-                        currency: None,
-                        total: None,
-                        debts: None,
-                    };
-                    let new_message = send_bot_message(
-                        &bot,
-                        &msg,
-                        format!(
-                            "{}Great! What's the Telegram username of the one who paid?",
-                            display_add_payment(&payment)
-                        ),
-                    )
-                    .await?
-                    .id;
-                    messages.push(new_message);
-                    dialogue
-                        .update(State::AddCreditor { messages, payment })
-                        .await?;
-                }
+                let payment = AddPaymentParams {
+                    chat_id: msg.chat.id.to_string(),
+                    sender_id: user.id.to_string(),
+                    sender_username: member_from_user(user),
+                    timestamp: msg.date.timestamp(),
+                    description: Some(text.to_string()),
+                    creditor: None,
+                    payers: Vec::new(),
+                    currency: None,
+                    total: None,
+                    debts: None,
+                    items: Vec::new(),
+                    category: None,
+                    recurring: None,
+                };
+                let new_message = send_bot_message(
+                    &bot,
+                    &msg,
+                    format!(
+                        "{}Great! What's the Telegram username of the one who paid?\n\n{PAYER_INSTRUCTIONS_MESSAGE}",
+                        display_add_payment(&payment, &store).await
+                    ),
+                )
+                .await?
+                .id;
+                messages.push(new_message);
+                dialogue
+                    .update(State::AddCreditor { messages, payment })
+                    .await?;
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -644,6 +798,7 @@ pub async fn action_add_description(
 
 /* Add a payment entry in a group chat.
  * Bot receives a creditor string from user, and proceeds to ask for total.
+ * If the amounts paid are given too, they add up to the total, so it proceeds to ask for debts.
  */
 pub async fn action_add_creditor(
     bot: Bot,
@@ -651,14 +806,66 @@ pub async fn action_add_creditor(
     state: State,
     msg: Message,
     (mut messages, payment): (Vec<MessageId>, AddPaymentParams),
+    store: Store,
 ) -> HandlerResult {
-    match msg.text() {
+    match parse_text_mentions(&msg) {
         Some(text) => {
-            let text = parse_username(text);
+            // Amounts paid are in the currency already given, else in the chat's default one
+            let currency = match payment.currency.clone() {
+                Some(currency) => currency,
+                None => get_chat_default_currency(&store, &payment.chat_id).await,
+            };
+            let paid_amounts = process_payers(&text, currency.clone());
+
+            let (creditor, payers, paid) = match paid_amounts {
+                Ok(paid_amounts) => paid_amounts,
+                Err(err) => {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{PAYER_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
+                }
+            };
 
-            if let Err(err) = text {
-                let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
-                repeat_state(dialogue, state, new_message).await?;
+            if let Some(total) = paid {
+                let new_payment = AddPaymentParams {
+                    chat_id: payment.chat_id,
+                    sender_id: payment.sender_id,
+                    sender_username: payment.sender_username,
+                    timestamp: payment.timestamp,
+                    description: payment.description,
+                    creditor: Some(creditor),
+                    payers,
+                    currency: Some(currency),
+                    total: Some(total),
+                    debts: None,
+                    items: Vec::new(),
+                    category: None,
+                    recurring: None,
+                };
+                let new_message = send_bot_message(
+                    &bot,
+                    &msg,
+                    format!(
+                        "{}Great! How do we split?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",
+                        display_add_payment(&new_payment, &store).await
+                    ),
+                )
+                .reply_markup(make_keyboard_debt_selection())
+                .await?
+                .id;
+                messages.push(new_message);
+                dialogue
+                    .update(State::AddDebtSelection {
+                        messages,
+                        payment: new_payment,
+                    })
+                    .await?;
                 return Ok(());
             }
 
@@ -666,19 +873,23 @@ pub async fn action_add_creditor(
                 chat_id: payment.chat_id,
                 sender_id: payment.sender_id,
                 sender_username: payment.sender_username,
-                datetime: payment.datetime,
+                timestamp: payment.timestamp,
                 description: payment.description,
-                creditor: Some(text?),
+                creditor: Some(creditor),
+                payers: Vec::new(),
                 currency: None,
                 total: None,
                 debts: None,
+                items: Vec::new(),
+                category: None,
+                recurring: None,
             };
             let new_message = send_bot_message(
                 &bot,
                 &msg,
                 format!(
                     "{}Nice! What was the budget?\n\n{TOTAL_INSTRUCTIONS_MESSAGE}",
-                    display_add_payment(&new_payment)
+                    display_add_payment(&new_payment, &store).await
                 ),
             )
             .await?
@@ -692,7 +903,7 @@ pub async fn action_add_creditor(
                 .await?;
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -710,6 +921,7 @@ pub async fn action_add_total(
     state: State,
     msg: Message,
     (mut messages, payment): (Vec<MessageId>, AddPaymentParams),
+    store: Store,
 ) -> HandlerResult {
     match msg.text() {
         Some(text) => {
@@ -720,19 +932,23 @@ pub async fn action_add_total(
                         chat_id: payment.chat_id,
                         sender_id: payment.sender_id,
                         sender_username: payment.sender_username,
-                        datetime: payment.datetime,
+                        timestamp: payment.timestamp,
                         description: payment.description,
                         creditor: payment.creditor,
+                        payers: payment.payers,
                         currency: Some(currency),
                         total: Some(total),
                         debts: None,
+                        items: Vec::new(),
+                        category: None,
+                        recurring: None,
                     };
                     let new_message = send_bot_message(
                         &bot,
                         &msg,
                         format!(
-                            "{}Great! How do we split?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}",
-                            display_add_payment(&new_payment)
+                            "{}Great! How do we split?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",
+                            display_add_payment(&new_payment, &store).await
                             ),
                             )
                         .reply_markup(make_keyboard_debt_selection())
@@ -749,7 +965,7 @@ pub async fn action_add_total(
                     let new_message = send_bot_message(
                         &bot,
                         &msg,
-                        format!("{}\n\n{TOTAL_INSTRUCTIONS_MESSAGE}", err.to_string()),
+                        format!("{}\n\n{TOTAL_INSTRUCTIONS_MESSAGE}", err),
                     )
                     .await?
                     .id;
@@ -759,7 +975,7 @@ pub async fn action_add_total(
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -777,6 +993,7 @@ pub async fn action_add_debt_selection(
     dialogue: UserDialogue,
     query: CallbackQuery,
     (messages, payment): (Vec<MessageId>, AddPaymentParams),
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -789,7 +1006,7 @@ pub async fn action_add_debt_selection(
                         id,
                         format!(
                             "{}Okay! Who is involved in the payment?\n\n{DEBT_EQUAL_INSTRUCTIONS_MESSAGE}",
-                            display_add_payment(&payment)
+                            display_add_payment(&payment, &store).await
                             ),
                             )
                         .await?;
@@ -809,7 +1026,7 @@ pub async fn action_add_debt_selection(
                         id,
                         format!(
                             "{}Okay! Provide usernames of debtors and their debt amounts.\n\n{DEBT_EXACT_INSTRUCTIONS_MESSAGE}",
-                            display_add_payment(&payment)
+                            display_add_payment(&payment, &store).await
                         ),
                     )
                     .await?;
@@ -829,7 +1046,7 @@ pub async fn action_add_debt_selection(
                         id,
                         format!(
                             "{}Okay! Provide usernames of debtors and their debt amounts.\n\n{DEBT_RATIO_INSTRUCTIONS_MESSAGE}",
-                            display_add_payment(&payment))
+                            display_add_payment(&payment, &store).await)
                         ).await?;
                     dialogue
                         .update(State::AddDebt {
@@ -840,6 +1057,24 @@ pub async fn action_add_debt_selection(
                         .await?;
                 }
             }
+            "Itemized" => {
+                if let Some(Message { id, chat, .. }) = query.message {
+                    bot.edit_message_text(
+                        chat.id,
+                        id,
+                        format!(
+                            "{}Okay! What items were paid for, and who shared each of them?\n\n{DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE}",
+                            display_add_payment(&payment, &store).await)
+                        ).await?;
+                    dialogue
+                        .update(State::AddDebt {
+                            messages,
+                            payment,
+                            debts_format: AddDebtsFormat::Itemized,
+                        })
+                        .await?;
+                }
+            }
             _ => {
                 log::error!("Add Payment Debt Selection - Invalid button for user {} in chat {} with payment {:?}: {}",
                             payment.sender_id, payment.chat_id, payment, button);
@@ -859,8 +1094,19 @@ pub async fn action_add_debt(
     state: State,
     msg: Message,
     (messages, payment, debts_format): (Vec<MessageId>, AddPaymentParams, AddDebtsFormat),
+    store: Store,
 ) -> HandlerResult {
-    handle_debts(bot, dialogue, state, msg, messages, payment, debts_format).await
+    handle_debts(
+        bot,
+        dialogue,
+        state,
+        msg,
+        messages,
+        payment,
+        debts_format,
+        &store,
+    )
+    .await
 }
 
 /* Add a payment entry in a group chat.
@@ -874,6 +1120,7 @@ pub async fn action_add_confirm(
     state: State,
     query: CallbackQuery,
     (messages, payment): (Vec<MessageId>, AddPaymentParams),
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -881,14 +1128,14 @@ pub async fn action_add_confirm(
         match button.as_str() {
             "Cancel" => {
                 if let Some(msg) = query.message {
-                    cancel_add_payment(bot, dialogue, state, msg).await?;
+                    cancel_add_payment(bot, dialogue, state, msg, store).await?;
                 }
             }
             "Edit" => {
-                display_add_edit_menu(bot, dialogue, query, messages, payment).await?;
+                display_add_edit_menu(bot, dialogue, query, messages, payment, &store).await?;
             }
             "Confirm" => {
-                call_processor_add_payment(bot, dialogue, messages, payment, query).await?;
+                call_processor_add_payment(bot, dialogue, messages, payment, query, &store).await?;
             }
             _ => {
                 log::error!("Add Payment Confirm - Invalid button for user {} in chat {} with payment {:?}: {}",
@@ -908,6 +1155,7 @@ pub async fn action_add_edit_menu(
     dialogue: UserDialogue,
     query: CallbackQuery,
     (messages, payment): (Vec<MessageId>, AddPaymentParams),
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -935,13 +1183,53 @@ pub async fn action_add_edit_menu(
                         })
                         .await?;
                 }
+                "Date" => {
+                    let time_zone = retrieve_time_zone(&store, &payment_clone.chat_id).await;
+                    bot.edit_message_text(
+                        chat_id,
+                        id,
+                        format!(
+                            "Current date: {}\n\nWhen was this payment made?\n\n{DATE_INSTRUCTIONS_MESSAGE}",
+                            format_timestamp(payment_clone.timestamp, time_zone)
+                        ),
+                    )
+                    .await?;
+                    dialogue
+                        .update(State::AddEdit {
+                            messages,
+                            payment,
+                            edit: AddPaymentEdit::Date,
+                        })
+                        .await?;
+                }
                 "Payer" => {
+                    let names = get_member_names(
+                        &store,
+                        payment_members(
+                            payment_clone.creditor.as_deref().unwrap_or_default(),
+                            &payment_clone.payers,
+                            &[],
+                            &[],
+                        ),
+                    )
+                    .await;
+                    let current = match &payment_clone.currency {
+                        Some(currency) if !payment_clone.payers.is_empty() => {
+                            format!(
+                                "s:\n{}",
+                                display_debts(&payment_clone.payers, currency.1, &names)
+                            )
+                        }
+                        _ => format!(
+                            ": {}\n",
+                            display_username(&payment_clone.creditor.unwrap(), &names)
+                        ),
+                    };
                     bot.edit_message_text(
                         chat_id,
                         id,
                         format!(
-                            "Current payer: {}\n\nWho should the payer be?",
-                            display_username(&payment_clone.creditor.unwrap())
+                            "Current payer{current}\nWho should the payer be?\n\n{PAYER_INSTRUCTIONS_MESSAGE}"
                         ),
                     )
                     .await?;
@@ -959,7 +1247,7 @@ pub async fn action_add_edit_menu(
                         id,
                         format!(
                             "Current total: {}\n\nWhat should the total be?\n\n{TOTAL_INSTRUCTIONS_MESSAGE}",
-                            display_currency_amount(payment_clone.total.unwrap(), use_currency(payment_clone.currency.unwrap(), &payment_clone.chat_id))
+                            display_currency_amount(payment_clone.total.unwrap(), use_currency(&store, payment_clone.currency.unwrap(), &payment_clone.chat_id).await)
                             ),
                             )
                         .await?;
@@ -976,8 +1264,8 @@ pub async fn action_add_edit_menu(
                         chat_id,
                         id,
                         format!(
-                            "Current split:\n{}\nHow should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}",
-                            display_debts(&payment_clone.debts.unwrap(), payment_clone.currency.unwrap().1)
+                            "Current split:\n{}\nHow should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",
+                            display_debts(&payment_clone.debts.clone().unwrap(), payment_clone.currency.unwrap().1, &get_member_names(&store, debt_members(&payment_clone.debts.unwrap())).await)
                             ),
                             ).reply_markup(make_keyboard_debt_selection())
                         .await?;
@@ -985,8 +1273,55 @@ pub async fn action_add_edit_menu(
                         .update(State::AddDebtSelection { messages, payment })
                         .await?;
                 }
+                "Category" => {
+                    let categories = process_chat_categories(&store, &payment_clone.chat_id).await;
+                    bot.edit_message_text(
+                        chat_id,
+                        id,
+                        format!(
+                            "Current category: {}\n\nWhich category is this payment in?\n\n{CATEGORY_INSTRUCTIONS_MESSAGE}",
+                            payment_clone.category.as_deref().unwrap_or("None")
+                        ),
+                    )
+                    .reply_markup(make_keyboard_categories(&categories))
+                    .await?;
+                    dialogue
+                        .update(State::AddEdit {
+                            messages,
+                            payment,
+                            edit: AddPaymentEdit::Category,
+                        })
+                        .await?;
+                }
+                "Repeat" => {
+                    let time_zone = retrieve_time_zone(&store, &payment_clone.chat_id).await;
+                    let current = match &payment_clone.recurring {
+                        Some(schedule) => display_schedule(
+                            &schedule.frequency,
+                            schedule.start.unwrap_or(payment_clone.timestamp),
+                            schedule.end,
+                            time_zone,
+                        ),
+                        None => "Never".to_string(),
+                    };
+                    bot.edit_message_text(
+                        chat_id,
+                        id,
+                        format!(
+                            "Currently repeats: {current}\n\nHow often should this payment repeat?\n\n{RECURRING_INSTRUCTIONS_MESSAGE}"
+                        ),
+                    )
+                    .await?;
+                    dialogue
+                        .update(State::AddEdit {
+                            messages,
+                            payment,
+                            edit: AddPaymentEdit::Repeat,
+                        })
+                        .await?;
+                }
                 "Back" => {
-                    display_add_overview(&bot, &dialogue, &msg, messages, payment).await?;
+                    display_add_overview(&bot, &dialogue, &msg, messages, payment, &store).await?;
                 }
                 _ => {
                     log::error!("Add Payment Edit Menu - Invalid button for user {} in chat {} with payment {:?}: {}",
@@ -1009,6 +1344,7 @@ pub async fn action_add_edit(
     state: State,
     msg: Message,
     (mut messages, payment, edit): (Vec<MessageId>, AddPaymentParams, AddPaymentEdit),
+    store: Store,
 ) -> HandlerResult {
     match msg.text() {
         Some(text) => match edit {
@@ -1017,20 +1353,32 @@ pub async fn action_add_edit(
                     chat_id: payment.chat_id,
                     sender_id: payment.sender_id,
                     sender_username: payment.sender_username,
-                    datetime: payment.datetime,
+                    timestamp: payment.timestamp,
                     description: Some(text.to_string()),
                     creditor: payment.creditor,
+                    payers: payment.payers,
                     currency: payment.currency,
                     total: payment.total,
                     debts: payment.debts,
+                    items: payment.items,
+                    category: payment.category,
+                    recurring: payment.recurring,
                 };
-                display_add_overview(&bot, &dialogue, &msg, messages, new_payment).await?;
+                display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
             }
-            AddPaymentEdit::Creditor => {
-                let username = parse_username(text);
+            AddPaymentEdit::Date => {
+                let time_zone = retrieve_time_zone(&store, &payment.chat_id).await;
+                let timestamp =
+                    parse_payment_date(text, time_zone, payment.timestamp, msg.date.timestamp());
 
-                if let Err(err) = username {
-                    let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
+                if let Err(err) = timestamp {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{DATE_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
                     repeat_state(dialogue, state, new_message).await?;
                     return Ok(());
                 }
@@ -1039,33 +1387,100 @@ pub async fn action_add_edit(
                     chat_id: payment.chat_id,
                     sender_id: payment.sender_id,
                     sender_username: payment.sender_username,
-                    datetime: payment.datetime,
+                    timestamp: timestamp?,
                     description: payment.description,
-                    creditor: Some(username?),
+                    creditor: payment.creditor,
+                    payers: payment.payers,
                     currency: payment.currency,
                     total: payment.total,
                     debts: payment.debts,
+                    items: payment.items,
+                    category: payment.category,
+                    recurring: payment.recurring,
+                };
+                display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
+            }
+            AddPaymentEdit::Creditor => {
+                let text = parse_text_mentions(&msg).unwrap_or_default();
+                let currency = match payment.currency.clone() {
+                    Some(currency) => currency,
+                    None => get_chat_default_currency(&store, &payment.chat_id).await,
+                };
+                let paid_amounts = process_payers(&text, currency.clone());
+
+                let (creditor, payers, paid) = match paid_amounts {
+                    Ok(paid_amounts) => paid_amounts,
+                    Err(err) => {
+                        let new_message = send_bot_message(
+                            &bot,
+                            &msg,
+                            format!("{}\n\n{PAYER_INSTRUCTIONS_MESSAGE}", err),
+                        )
+                        .await?
+                        .id;
+                        repeat_state(dialogue, state, new_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                // Amounts paid which add up to a new total need the split to be given again
+                let total = paid.or(payment.total);
+                let new_payment = AddPaymentParams {
+                    chat_id: payment.chat_id,
+                    sender_id: payment.sender_id,
+                    sender_username: payment.sender_username,
+                    timestamp: payment.timestamp,
+                    description: payment.description,
+                    creditor: Some(creditor),
+                    payers,
+                    currency: payment.currency.or(paid.map(|_| currency)),
+                    total,
+                    debts: payment.debts,
+                    items: payment.items,
+                    category: payment.category,
+                    recurring: payment.recurring,
                 };
-                display_add_overview(&bot, &dialogue, &msg, messages, new_payment).await?;
+                if total != payment.total {
+                    let new_message = send_bot_message(&bot,
+                        &msg,
+                        format!("Great! The total is now what they paid. How are we splitting this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",),
+                        ).reply_markup(make_keyboard_debt_selection())
+                        .await?.id;
+                    messages.push(new_message);
+                    dialogue
+                        .update(State::AddDebtSelection {
+                            messages,
+                            payment: new_payment,
+                        })
+                        .await?;
+                    return Ok(());
+                }
+                display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
             }
             AddPaymentEdit::Total => {
-                let currency_amount = parse_currency_amount(text);
+                let currency_amount = parse_currency_amount(text).and_then(|(total, currency)| {
+                    check_payers_total(&payment.payers, total).map(|_| (total, currency))
+                });
                 match currency_amount {
                     Ok((total, currency)) => {
                         let new_payment = AddPaymentParams {
                             chat_id: payment.chat_id,
                             sender_id: payment.sender_id,
                             sender_username: payment.sender_username,
-                            datetime: payment.datetime,
+                            timestamp: payment.timestamp,
                             description: payment.description,
                             creditor: payment.creditor,
+                            payers: payment.payers,
                             currency: Some(currency),
                             total: Some(total),
                             debts: payment.debts,
+                            items: payment.items,
+                            category: payment.category,
+                            recurring: payment.recurring,
                         };
                         let new_message = send_bot_message(&bot,
                             &msg,
-                            format!("Great! How are we splitting this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}",),
+                            format!("Great! How are we splitting this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",),
                             ).reply_markup(make_keyboard_debt_selection())
                             .await?.id;
                         messages.push(new_message);
@@ -1080,7 +1495,7 @@ pub async fn action_add_edit(
                         let new_message = send_bot_message(
                             &bot,
                             &msg,
-                            format!("{}\n\n{TOTAL_INSTRUCTIONS_MESSAGE}", err.to_string()),
+                            format!("{}\n\n{TOTAL_INSTRUCTIONS_MESSAGE}", err),
                         )
                         .await?
                         .id;
@@ -1099,6 +1514,7 @@ pub async fn action_add_edit(
                     messages,
                     payment,
                     AddDebtsFormat::Equal,
+                    &store,
                 )
                 .await?;
             }
@@ -1111,6 +1527,7 @@ pub async fn action_add_edit(
                     messages,
                     payment,
                     AddDebtsFormat::Exact,
+                    &store,
                 )
                 .await?;
             }
@@ -1123,12 +1540,101 @@ pub async fn action_add_edit(
                     messages,
                     payment,
                     AddDebtsFormat::Ratio,
+                    &store,
                 )
                 .await?;
             }
+            AddPaymentEdit::DebtsItemized => {
+                handle_debts(
+                    bot,
+                    dialogue,
+                    state,
+                    msg,
+                    messages,
+                    payment,
+                    AddDebtsFormat::Itemized,
+                    &store,
+                )
+                .await?;
+            }
+            AddPaymentEdit::Category => {
+                let category = if text.trim().eq_ignore_ascii_case("none") {
+                    Ok(None)
+                } else {
+                    let categories = process_chat_categories(&store, &payment.chat_id).await;
+                    parse_category(text, &categories).map(Some)
+                };
+
+                if let Err(err) = category {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{CATEGORY_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
+                }
+
+                let new_payment = AddPaymentParams {
+                    chat_id: payment.chat_id,
+                    sender_id: payment.sender_id,
+                    sender_username: payment.sender_username,
+                    timestamp: payment.timestamp,
+                    description: payment.description,
+                    creditor: payment.creditor,
+                    payers: payment.payers,
+                    currency: payment.currency,
+                    total: payment.total,
+                    debts: payment.debts,
+                    items: payment.items,
+                    category: category?,
+                    recurring: payment.recurring,
+                };
+                display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
+            }
+            AddPaymentEdit::Repeat => {
+                let recurring = if text.trim().eq_ignore_ascii_case("never") {
+                    Ok(None)
+                } else {
+                    let time_zone = retrieve_time_zone(&store, &payment.chat_id).await;
+                    parse_schedule(text, time_zone, payment.timestamp, msg.date.timestamp())
+                        .map(Some)
+                };
+
+                if let Err(err) = recurring {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{RECURRING_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
+                }
+
+                let new_payment = AddPaymentParams {
+                    chat_id: payment.chat_id,
+                    sender_id: payment.sender_id,
+                    sender_username: payment.sender_username,
+                    timestamp: payment.timestamp,
+                    description: payment.description,
+                    creditor: payment.creditor,
+                    payers: payment.payers,
+                    currency: payment.currency,
+                    total: payment.total,
+                    debts: payment.debts,
+                    items: payment.items,
+                    category: payment.category,
+                    recurring: recurring?,
+                };
+                display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
+            }
         },
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -1137,3 +1643,50 @@ pub async fn action_add_edit(
 
     Ok(())
 }
+
+/* Add a payment entry in a group chat.
+ * Bot receives a callback query with the category picked for the payment.
+ * Categories typed instead are handled by action_add_edit.
+ */
+pub async fn action_add_edit_category(
+    bot: Bot,
+    dialogue: UserDialogue,
+    query: CallbackQuery,
+    (messages, payment, edit): (Vec<MessageId>, AddPaymentParams, AddPaymentEdit),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            if !matches!(edit, AddPaymentEdit::Category) {
+                log::error!("Add Payment Edit Category - Invalid button for user {} in chat {} with payment {:?}: {}",
+                            payment.sender_id, payment.chat_id, payment, button);
+                return Ok(());
+            }
+
+            let category = match button.as_str() {
+                "None" => None,
+                _ => Some(button.to_string()),
+            };
+            let new_payment = AddPaymentParams {
+                chat_id: payment.chat_id,
+                sender_id: payment.sender_id,
+                sender_username: payment.sender_username,
+                timestamp: payment.timestamp,
+                description: payment.description,
+                creditor: payment.creditor,
+                payers: payment.payers,
+                currency: payment.currency,
+                total: payment.total,
+                debts: payment.debts,
+                items: payment.items,
+                category,
+                recurring: payment.recurring,
+            };
+            display_add_overview(&bot, &dialogue, &msg, messages, new_payment, &store).await?;
+        }
+    }
+
+    Ok(())
+}
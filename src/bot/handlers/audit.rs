@@ -0,0 +1,226 @@
+use teloxide::{prelude::*, types::Message};
+
+use crate::bot::{
+    constants::{commands::COMMAND_AUDIT, messages::UNKNOWN_ERROR_MESSAGE},
+    processor::{audit_ledger, LedgerAudit, LedgerCounter, LedgerDiscrepancy},
+    store::Store,
+    utils::{
+        bot_actions::{assert_handle_request_limit, is_chat_admin, send_bot_message},
+        format::{
+            display_currency_amount, display_username, get_currency, get_member_names,
+            make_keyboard, MemberNames,
+        },
+        HandlerResult, UserDialogue,
+    },
+    State,
+};
+
+/* Utilities */
+
+// Gets the members displayed with an audit, those with a discrepancy
+fn audit_members(audit: &LedgerAudit) -> impl Iterator<Item = &str> {
+    audit
+        .discrepancies
+        .iter()
+        .map(|discrepancy| discrepancy.username.as_str())
+}
+
+fn display_discrepancy(discrepancy: &LedgerDiscrepancy, names: &MemberNames) -> String {
+    let counter = match discrepancy.counter {
+        LedgerCounter::Balance => "Balance",
+        LedgerCounter::Spending => "Spending",
+    };
+    let currency = match get_currency(&discrepancy.currency) {
+        Ok(currency) => currency,
+        // Should not occur. Currency string is from database, so should exist.
+        Err(_) => (discrepancy.currency.clone(), 2),
+    };
+
+    format!(
+        "{} — {}\n    Expected: {}\n    Recorded: {}\n",
+        display_username(&discrepancy.username, names),
+        counter,
+        display_currency_amount(discrepancy.expected, currency.clone()),
+        display_currency_amount(discrepancy.actual, currency)
+    )
+}
+
+fn display_audit(audit: &LedgerAudit, names: &MemberNames) -> String {
+    if audit.discrepancies.is_empty() {
+        return format!(
+            "🔎 I checked all {} payment records, and every balance and spending adds up! 🥳",
+            audit.payments
+        );
+    }
+
+    let discrepancies = audit
+        .discrepancies
+        .iter()
+        .map(|discrepancy| display_discrepancy(discrepancy, names))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    if audit.repaired {
+        format!(
+            "🛠 I've repaired these records, based on all {} payment records!\n\n{}",
+            audit.payments, discrepancies
+        )
+    } else {
+        format!(
+            "🔎 I checked all {} payment records, and these records don't add up!\n\n{}\nShould I repair them?",
+            audit.payments, discrepancies
+        )
+    }
+}
+
+/* Audits the balances and spendings of the group against all payment records.
+ * Bot reports any discrepancies, and offers to repair them.
+ */
+pub async fn action_audit(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let chat_id = msg.chat.id.to_string();
+
+    if !is_chat_admin(&bot, &msg, user).await? {
+        send_bot_message(
+            &bot,
+            &msg,
+            format!("🚫 Sorry, only group admins can {COMMAND_AUDIT} the records!"),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    match audit_ledger(&store, &chat_id, false).await {
+        Ok(audit) => {
+            let names = get_member_names(&store, audit_members(&audit)).await;
+            if audit.discrepancies.is_empty() {
+                send_bot_message(&bot, &msg, display_audit(&audit, &names)).await?;
+                dialogue.exit().await?;
+            } else {
+                let keyboard = make_keyboard(vec!["Repair", "Cancel"], Some(2));
+                send_bot_message(&bot, &msg, display_audit(&audit, &names))
+                    .reply_markup(keyboard)
+                    .await?;
+                dialogue.update(State::AuditMenu).await?;
+            }
+
+            // Logging
+            log::info!(
+                "Audit - User {} audited chat {}, found {} discrepancies in {} payments",
+                user.id,
+                chat_id,
+                audit.discrepancies.len(),
+                audit.payments
+            );
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "Audit - User {} failed to audit chat {}: {}",
+                user.id,
+                chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/* Repairs the balances and spendings of the group, if confirmed.
+ * Bot receives a callback query from the user.
+ */
+pub async fn action_audit_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    query: CallbackQuery,
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            let chat_id = msg.chat.id.to_string();
+            match button.as_str() {
+                "Repair" => {
+                    if !is_chat_admin(&bot, &msg, &query.from).await? {
+                        send_bot_message(
+                            &bot,
+                            &msg,
+                            "🚫 Sorry, only group admins can repair the records!".to_string(),
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+
+                    // Audit again, so that only current discrepancies are repaired
+                    match audit_ledger(&store, &chat_id, true).await {
+                        Ok(audit) => {
+                            let names = get_member_names(&store, audit_members(&audit)).await;
+                            bot.edit_message_text(
+                                msg.chat.id,
+                                msg.id,
+                                display_audit(&audit, &names),
+                            )
+                            .await?;
+
+                            // Logging
+                            log::info!(
+                                "Audit Menu - User {} repaired {} discrepancies in chat {}",
+                                query.from.id,
+                                audit.discrepancies.len(),
+                                chat_id
+                            );
+                        }
+                        Err(err) => {
+                            bot.edit_message_text(msg.chat.id, msg.id, UNKNOWN_ERROR_MESSAGE)
+                                .await?;
+
+                            // Logging
+                            log::error!(
+                                "Audit Menu - User {} failed to repair chat {}: {}",
+                                query.from.id,
+                                chat_id,
+                                err.to_string()
+                            );
+                        }
+                    }
+                    dialogue.exit().await?;
+                }
+                "Cancel" => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        "Okay! I left the records as they are.",
+                    )
+                    .await?;
+                    dialogue.exit().await?;
+                }
+                _ => {
+                    log::error!(
+                        "Audit Menu - Invalid button in chat {} by user {}: {}",
+                        chat_id,
+                        query.from.id,
+                        button
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
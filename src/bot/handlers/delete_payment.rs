@@ -5,17 +5,21 @@ use teloxide::{
 
 use crate::bot::{
     constants::{
-        commands::{COMMAND_CANCEL, COMMAND_VIEW_PAYMENTS},
+        commands::{COMMAND_CANCEL, COMMAND_TRASH, COMMAND_VIEW_PAYMENTS},
         messages::CANCEL_DELETE_MESSAGE,
     },
     dispatcher::State,
-    handlers::Payment,
-    processor::delete_payment,
+    handlers::{Payment, PaymentsPage},
+    processor::{delete_payment, get_trash_retention_days},
+    store::Store,
     utils::{
         bot_actions::{
             assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
         },
-        format::{display_balance_header, display_balances, display_payment, make_keyboard},
+        format::{
+            balance_members, display_balance_header, display_balances, display_payment,
+            display_user, get_member_names, make_keyboard, member_from_user, payment_members,
+        },
         time::retrieve_time_zone,
         HandlerResult, UserDialogue,
     },
@@ -29,24 +33,22 @@ async fn repeat_state(
     state: State,
     new_message: MessageId,
 ) -> HandlerResult {
-    match state {
-        State::DeletePayment {
-            mut messages,
-            payment,
-            payments,
-            page,
-        } => {
-            messages.push(new_message);
-            dialogue
-                .update(State::DeletePayment {
-                    messages,
-                    payment,
-                    payments,
-                    page,
-                })
-                .await?;
-        }
-        _ => (),
+    if let State::DeletePayment {
+        mut messages,
+        payment,
+        payments,
+        page,
+    } = state
+    {
+        messages.push(new_message);
+        dialogue
+            .update(State::DeletePayment {
+                messages,
+                payment,
+                payments,
+                page,
+            })
+            .await?;
     }
 
     Ok(())
@@ -59,10 +61,11 @@ async fn complete_delete_payment(
     chat_id: &str,
     messages: Vec<MessageId>,
     payments: Vec<Payment>,
-    page: usize,
+    page: PaymentsPage,
+    store: &Store,
 ) -> HandlerResult {
-    if is_erase_messages(chat_id) {
-        delete_bot_messages(&bot, chat_id, messages).await?;
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
     }
     dialogue
         .update(State::ViewPayments { payments, page })
@@ -97,6 +100,7 @@ pub async fn cancel_delete_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
     send_bot_message(&bot, &msg, CANCEL_DELETE_MESSAGE.to_string()).await?;
 
@@ -120,6 +124,7 @@ pub async fn cancel_delete_payment(
                 messages,
                 payments,
                 page,
+                &store,
             )
             .await?;
         }
@@ -151,8 +156,8 @@ pub async fn block_delete_payment(
 /* Blocks user command.
  * Called when user attempts to delete payment without first viewing anything.
  */
-pub async fn no_delete_payment(bot: Bot, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn no_delete_payment(bot: Bot, msg: Message, store: Store) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -174,20 +179,38 @@ pub async fn action_delete_payment(
     dialogue: UserDialogue,
     msg: &Message,
     msg_id: MessageId,
-    (messages, payments, page): (Vec<MessageId>, Vec<Payment>, usize),
+    (messages, payments, page): (Vec<MessageId>, Vec<Payment>, PaymentsPage),
     index: usize,
+    store: Store,
 ) -> HandlerResult {
     let payment = payments[index].clone();
     let keyboard = make_keyboard(vec!["Cancel", "Confirm"], Some(2));
     let chat_id = msg.chat.id.to_string();
-    let time_zone = retrieve_time_zone(&chat_id);
+    let time_zone = retrieve_time_zone(&store, &chat_id).await;
 
     bot.edit_message_text(
         chat_id,
         msg_id,
         format!(
-            "Are you sure you want to delete this expense permanently?\n\n{}",
-            display_payment(&payment, index + 1, time_zone)
+            "Are you sure you want to delete this expense?\nI'll keep it in the {COMMAND_TRASH} for {} days, in case you change your mind.\n\n{}",
+            get_trash_retention_days(),
+            display_payment(
+                &store,
+                &payment,
+                page.serial_num(index),
+                time_zone,
+                &get_member_names(
+                    &store,
+                    payment_members(
+                        &payment.creditor,
+                        &payment.payers,
+                        &payment.debts,
+                        &payment.items
+                    )
+                )
+                .await
+            )
+            .await
         ),
     )
     .reply_markup(keyboard)
@@ -210,22 +233,46 @@ pub async fn action_delete_payment_confirm(
     bot: Bot,
     dialogue: UserDialogue,
     state: State,
-    (messages, payment, payments, page): (Vec<MessageId>, Payment, Vec<Payment>, usize),
+    (messages, payment, payments, page): (Vec<MessageId>, Payment, Vec<Payment>, PaymentsPage),
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
 
         if let Some(msg) = query.message {
             let chat_id = msg.chat.id.to_string();
-            let time_zone = retrieve_time_zone(&chat_id);
+            let time_zone = retrieve_time_zone(&store, &chat_id).await;
             match button.as_str() {
                 "Cancel" => {
-                    cancel_delete_payment(bot, dialogue, state, msg).await?;
+                    cancel_delete_payment(bot, dialogue, state, msg, store).await?;
                 }
                 "Confirm" => {
                     let payment_id = &payment.payment_id;
-                    let deletion = delete_payment(&chat_id, payment_id).await;
+                    let sender_id = query.from.id.to_string();
+                    let sender_username = member_from_user(&query.from);
+                    let deleted_by = display_user(&query.from);
+                    let deleted_at = chrono::Utc::now().timestamp();
+                    let names = get_member_names(
+                        &store,
+                        payment_members(
+                            &payment.creditor,
+                            &payment.payers,
+                            &payment.debts,
+                            &payment.items,
+                        ),
+                    )
+                    .await;
+                    let deletion = delete_payment(
+                        &store,
+                        &chat_id,
+                        payment_id,
+                        &sender_id,
+                        &sender_username,
+                        &deleted_by,
+                        deleted_at,
+                    )
+                    .await;
 
                     match deletion {
                         Ok(balances) => {
@@ -233,8 +280,8 @@ pub async fn action_delete_payment_confirm(
                                 &bot,
                                 &msg,
                                 format!(
-                                    "Expense successfully deleted!\n\n{}",
-                                    display_payment(&payment, 1, time_zone)
+                                    "Expense successfully deleted! Changed your mind? Restore it from the {COMMAND_TRASH}.\n\n{}",
+                                    display_payment(&store, &payment, 1, time_zone, &names).await
                                 ),
                             )
                             .await?;
@@ -243,8 +290,12 @@ pub async fn action_delete_payment_confirm(
                                 &msg,
                                 format!(
                                     "{}{}",
-                                    display_balance_header(&chat_id, &payment.currency.0),
-                                    display_balances(&balances),
+                                    display_balance_header(&store, &chat_id, &payment.currency.0)
+                                        .await,
+                                    display_balances(
+                                        &balances,
+                                        &get_member_names(&store, balance_members(&balances)).await
+                                    ),
                                 ),
                             )
                             .await?;
@@ -253,11 +304,11 @@ pub async fn action_delete_payment_confirm(
                             log::info!(
                                 "Delete Payment Submission - payment deleted for chat {} with payment {}",
                                 chat_id,
-                                display_payment(&payment, 1, time_zone)
+                                display_payment(&store, &payment, 1, time_zone, &names).await
                                 );
 
                             complete_delete_payment(
-                                &bot, dialogue, &chat_id, messages, payments, page,
+                                &bot, dialogue, &chat_id, messages, payments, page, &store,
                             )
                             .await?;
                         }
@@ -265,12 +316,12 @@ pub async fn action_delete_payment_confirm(
                             send_bot_message(
                                 &bot,
                                 &msg,
-                                format!("🤷 Oops! Something went wrong! I can't delete the payment right now. Please try again later!\n\n" ),
+                                "🤷 Oops! Something went wrong! I can't delete the payment right now. Please try again later!\n\n".to_string(),
                                 )
                                 .await?;
 
                             complete_delete_payment(
-                                &bot, dialogue, &chat_id, messages, payments, page,
+                                &bot, dialogue, &chat_id, messages, payments, page, &store,
                             )
                             .await?;
 
@@ -278,7 +329,7 @@ pub async fn action_delete_payment_confirm(
                             log::error!(
                                 "Delete Payment Submission - Processor failed to delete payment for chat {} with payment {}: {}",
                                 chat_id,
-                                display_payment(&payment, 1, time_zone),
+                                display_payment(&store, &payment, 1, time_zone, &names).await,
                                 err.to_string()
                                 );
                         }
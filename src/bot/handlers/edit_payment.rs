@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
@@ -8,39 +9,50 @@ use crate::bot::{
     constants::{
         commands::{COMMAND_CANCEL, COMMAND_VIEW_PAYMENTS},
         messages::{
-            CANCEL_EDIT_MESSAGE, DEBT_EQUAL_DESCRIPTION_MESSAGE, DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
+            CANCEL_EDIT_MESSAGE, CATEGORY_INSTRUCTIONS_MESSAGE, DATE_INSTRUCTIONS_MESSAGE,
+            DEBT_EQUAL_DESCRIPTION_MESSAGE, DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
             DEBT_EXACT_DESCRIPTION_MESSAGE, DEBT_EXACT_INSTRUCTIONS_MESSAGE,
+            DEBT_ITEMIZED_DESCRIPTION_MESSAGE, DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE,
             DEBT_RATIO_DESCRIPTION_MESSAGE, DEBT_RATIO_INSTRUCTIONS_MESSAGE, NO_TEXT_MESSAGE,
-            TOTAL_INSTRUCTIONS_MESSAGE,
+            PAYER_INSTRUCTIONS_MESSAGE, TOTAL_INSTRUCTIONS_MESSAGE,
         },
     },
     currency::Currency,
     dispatcher::State,
-    handlers::{AddDebtsFormat, AddPaymentEdit, Payment},
-    processor::edit_payment,
+    handlers::{AddDebtsFormat, AddPaymentEdit, Payment, PaymentsPage},
+    processor::{edit_payment, ProcessError},
+    redis::PaymentItem,
+    store::Store,
     utils::{
-        amounts::{parse_currency_amount, process_debts},
+        amounts::{check_payers_total, parse_currency_amount, process_debts, process_payers},
         bot_actions::{
-            assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
+            assert_handle_request_limit, delete_bot_messages, is_erase_messages,
+            process_chat_categories, send_bot_message,
         },
         format::{
-            display_balance_header, display_balances, display_currency_amount, display_debts,
-            display_payment, display_username, make_keyboard, make_keyboard_debt_selection,
-            parse_username, use_currency,
+            balance_members, debt_members, display_balance_header, display_balances,
+            display_currency_amount, display_debts, display_items, display_payers, display_payment,
+            display_username, get_member_names, make_keyboard, make_keyboard_categories,
+            make_keyboard_debt_selection, member_from_user, parse_category, parse_text_mentions,
+            payment_members, use_currency,
         },
-        time::retrieve_time_zone,
+        time::{format_timestamp, parse_payment_date, retrieve_time_zone},
         HandlerResult, UserDialogue,
     },
 };
 
 /* Utilities */
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EditPaymentParams {
     description: Option<String>,
+    timestamp: Option<i64>,
     creditor: Option<String>,
+    payers: Option<Vec<(String, i64)>>,
     currency: Option<Currency>,
     total: Option<i64>,
     debts: Option<Vec<(String, i64)>>,
+    items: Option<Vec<PaymentItem>>,
+    category: Option<Option<String>>,
 }
 
 // Controls the state for misc handler actions that return to same state.
@@ -119,10 +131,11 @@ async fn complete_edit_payment(
     chat_id: &str,
     messages: Vec<MessageId>,
     payments: Vec<Payment>,
-    page: usize,
+    page: PaymentsPage,
+    store: &Store,
 ) -> HandlerResult {
-    if is_erase_messages(chat_id) {
-        delete_bot_messages(&bot, chat_id, messages).await?;
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
     }
     dialogue
         .update(State::ViewPayments { payments, page })
@@ -132,26 +145,49 @@ async fn complete_edit_payment(
 
 /* Displays a payment entry by combining original entry and edited fields.
 */
-fn display_edit_payment(payment: Payment, edited_payment: EditPaymentParams) -> String {
+async fn display_edit_payment(
+    payment: Payment,
+    edited_payment: EditPaymentParams,
+    store: &Store,
+) -> String {
     let currency = edited_payment.currency.unwrap_or(payment.currency);
+    let time_zone = retrieve_time_zone(store, &payment.chat_id).await;
+    let creditor = edited_payment.creditor.unwrap_or(payment.creditor);
+    let payers = edited_payment.payers.unwrap_or(payment.payers);
+    let debts = edited_payment.debts.unwrap_or(payment.debts);
+    let items = edited_payment.items.unwrap_or(payment.items);
+    let names = get_member_names(store, payment_members(&creditor, &payers, &debts, &items)).await;
+    let items = if items.is_empty() {
+        "".to_string()
+    } else {
+        format!("Items:\n{}", display_items(&items, currency.1, &names))
+    };
+    let category = match edited_payment.category.unwrap_or(payment.category) {
+        Some(category) => format!("Category: {}\n", category),
+        None => "".to_string(),
+    };
     format!(
-        "Description: {}\nPayer: {}\nTotal: {}\nSplit:\n{}",
+        "Description: {}\n{}Date: {}\n{}Total: {}\n{}Split:\n{}",
         edited_payment.description.unwrap_or(payment.description),
-        display_username(&edited_payment.creditor.unwrap_or(payment.creditor)),
+        category,
+        format_timestamp(
+            edited_payment.timestamp.unwrap_or(payment.timestamp),
+            time_zone
+        ),
+        display_payers(&creditor, &payers, currency.1, &names),
         display_currency_amount(
             edited_payment.total.unwrap_or(payment.total),
-            use_currency(currency.clone(), &payment.chat_id),
+            use_currency(store, currency.clone(), &payment.chat_id).await,
         ),
-        display_debts(
-            &edited_payment.debts.unwrap_or(payment.debts.clone()),
-            currency.1
-        )
+        items,
+        display_debts(&debts, currency.1, &names)
     )
 }
 
 /* Edit a payment entry in a group chat.
  * Displays an overview of the current details provided.
  */
+#[allow(clippy::too_many_arguments)]
 async fn display_edit_overview(
     bot: Bot,
     dialogue: UserDialogue,
@@ -161,13 +197,16 @@ async fn display_edit_overview(
     payment: Payment,
     edited_payment: EditPaymentParams,
     payments: Vec<Payment>,
-    page: usize,
+    page: PaymentsPage,
+    store: &Store,
 ) -> HandlerResult {
     let options = vec![
         "Description",
+        "Date",
         "Payer",
         "Total",
         "Split",
+        "Category",
         "Cancel",
         "Confirm",
     ];
@@ -179,7 +218,7 @@ async fn display_edit_overview(
                 id,
                 format!(
                     "Sure! What expense do you wish to ✏️ edit?\n\n{}",
-                    display_edit_payment(payment.clone(), edited_payment.clone())
+                    display_edit_payment(payment.clone(), edited_payment.clone(), store).await
                 ),
             )
             .reply_markup(keyboard)
@@ -191,7 +230,7 @@ async fn display_edit_overview(
                 msg,
                 format!(
                     "Sure! What expense do you wish to ✏️ edit?\n\n{}",
-                    display_edit_payment(payment.clone(), edited_payment.clone())
+                    display_edit_payment(payment.clone(), edited_payment.clone(), store).await
                 ),
             )
             .reply_markup(keyboard)
@@ -215,6 +254,7 @@ async fn display_edit_overview(
 
 /* Calls processor to execute the edit of the payment entry.
 */
+#[allow(clippy::too_many_arguments)]
 async fn call_processor_edit_payment(
     bot: Bot,
     dialogue: UserDialogue,
@@ -222,8 +262,9 @@ async fn call_processor_edit_payment(
     payment: Payment,
     edited_payment: EditPaymentParams,
     payments: Vec<Payment>,
-    page: usize,
+    page: PaymentsPage,
     query: CallbackQuery,
+    store: &Store,
 ) -> HandlerResult {
     if let Some(msg) = query.message {
         let chat_id = msg.chat.id.to_string();
@@ -233,38 +274,52 @@ async fn call_processor_edit_payment(
             // Check first if there are any changes at all
             if let EditPaymentParams {
                 description: None,
+                timestamp: None,
                 creditor: None,
+                payers: None,
                 currency: None,
                 total: None,
                 debts: None,
+                items: None,
+                category: None,
             } = edited_payment
             {
                 send_bot_message(
                     &bot,
                     &msg,
-                    format!("You haven't added change to this expense. I've automatically cancelled editing the payment for you!"),
+                    "You haven't added change to this expense. I've automatically cancelled editing the payment for you!".to_string(),
                 )
                 .await?;
-                complete_edit_payment(&bot, dialogue, &chat_id, messages, payments, page).await?;
+                complete_edit_payment(&bot, dialogue, &chat_id, messages, payments, page, store)
+                    .await?;
                 return Ok(());
             }
 
             let edited = edit_payment(
+                store,
                 &chat_id,
-                user.clone().username.unwrap_or("".to_string()),
+                member_from_user(user),
                 user.id.to_string(),
                 &payment.payment_id,
                 edited_payment.description.as_deref(),
+                edited_payment.timestamp,
                 edited_payment.creditor.as_deref(),
                 edited_payment.currency.clone().unzip().0.as_deref(),
                 edited_payment.total.as_ref(),
                 edited_payment.debts,
+                edited_payment.items,
+                edited_payment
+                    .category
+                    .as_ref()
+                    .map(|category| category.as_deref()),
+                edited_payment.payers,
             )
             .await;
 
             match edited {
                 Ok(balances) => {
-                    let edit_overview = display_edit_payment(payment.clone(), edited_clone);
+                    let edit_overview =
+                        display_edit_payment(payment.clone(), edited_clone, store).await;
                     match balances {
                         Some(balances) => {
                             send_bot_message(
@@ -279,6 +334,7 @@ async fn call_processor_edit_payment(
                                 format!(
                                     "{}{}",
                                     display_balance_header(
+                                        store,
                                         &chat_id,
                                         edited_payment
                                             .currency
@@ -286,8 +342,12 @@ async fn call_processor_edit_payment(
                                             .0
                                             .as_deref()
                                             .unwrap_or(&payment.currency.0)
-                                    ),
-                                    display_balances(&balances)
+                                    )
+                                    .await,
+                                    display_balances(
+                                        &balances,
+                                        &get_member_names(store, balance_members(&balances)).await
+                                    )
                                 ),
                             )
                             .await?;
@@ -304,8 +364,10 @@ async fn call_processor_edit_payment(
                             .await?;
                         }
                     }
-                    complete_edit_payment(&bot, dialogue, &chat_id, messages, payments, page)
-                        .await?;
+                    complete_edit_payment(
+                        &bot, dialogue, &chat_id, messages, payments, page, store,
+                    )
+                    .await?;
 
                     // Logging
                     log::info!(
@@ -314,25 +376,64 @@ async fn call_processor_edit_payment(
                         edit_overview
                     );
                 }
+                Err(ProcessError::PayersTotalError()) => {
+                    send_bot_message(
+                        &bot,
+                        &msg,
+                        "Uh-oh! ❌ The amounts paid by each payer don't add up to the new total! Please edit the payers together with the total.".to_string(),
+                    )
+                    .await?;
+
+                    // Pending edits are kept, so that the payers can be edited too
+                    display_edit_overview(
+                        bot,
+                        dialogue,
+                        &msg,
+                        None,
+                        messages,
+                        payment,
+                        edited_clone,
+                        payments,
+                        page,
+                        store,
+                    )
+                    .await?;
+                }
                 Err(err) => {
-                    let time_zone = retrieve_time_zone(&chat_id);
+                    let time_zone = retrieve_time_zone(store, &chat_id).await;
                     send_bot_message(
                         &bot,
                         &msg,
-                        format!(
-                            "🤷 Oops! Something went wrong! I can't edit the payment right now. Please try again later!\n\n"
-                        ),
+                        "🤷 Oops! Something went wrong! I can't edit the payment right now. Please try again later!\n\n".to_string(),
                     )
                     .await?;
 
-                    complete_edit_payment(&bot, dialogue, &chat_id, messages, payments, page)
-                        .await?;
+                    complete_edit_payment(
+                        &bot, dialogue, &chat_id, messages, payments, page, store,
+                    )
+                    .await?;
 
                     // Logging
                     log::error!(
                         "Edit Payment Submission - Processor failed to edit payment for chat {} with payment {}: {}",
                         chat_id,
-                        display_payment(&payment, 1, time_zone),
+                        display_payment(
+                            store,
+                            &payment,
+                            1,
+                            time_zone,
+                            &get_member_names(
+                                store,
+                                payment_members(
+                                    &payment.creditor,
+                                    &payment.payers,
+                                    &payment.debts,
+                                    &payment.items
+                                )
+                            )
+                            .await
+                        )
+                        .await,
                         err.to_string()
                     );
                 }
@@ -372,6 +473,7 @@ pub async fn cancel_edit_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
     send_bot_message(&bot, &msg, CANCEL_EDIT_MESSAGE.to_string()).await?;
 
@@ -411,6 +513,7 @@ pub async fn cancel_edit_payment(
                 messages,
                 payments,
                 page,
+                &store,
             )
             .await?;
         }
@@ -441,8 +544,8 @@ pub async fn block_edit_payment(
 /* Blocks user command.
  * Called when user attempts to edit payment without first viewing anything.
  */
-pub async fn no_edit_payment(bot: Bot, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn no_edit_payment(bot: Bot, msg: Message, store: Store) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -465,28 +568,34 @@ pub async fn action_edit_payment(
     dialogue: UserDialogue,
     msg: &Message,
     msg_id: MessageId,
-    (messages, payments, page): (Vec<MessageId>, Vec<Payment>, usize),
+    (messages, payments, page): (Vec<MessageId>, Vec<Payment>, PaymentsPage),
     index: usize,
+    store: Store,
 ) -> HandlerResult {
     let payment = payments[index].clone();
     let edited_payment = EditPaymentParams {
         description: None,
+        timestamp: None,
         creditor: None,
+        payers: None,
         currency: None,
         total: None,
         debts: None,
+        items: None,
+        category: None,
     };
 
     display_edit_overview(
         bot,
         dialogue,
-        &msg,
+        msg,
         Some(msg_id),
         messages,
         payment,
         edited_payment,
         payments,
         page,
+        &store,
     )
     .await?;
     Ok(())
@@ -504,9 +613,10 @@ pub async fn action_edit_payment_confirm(
         Payment,
         EditPaymentParams,
         Vec<Payment>,
-        usize,
+        PaymentsPage,
     ),
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -515,7 +625,7 @@ pub async fn action_edit_payment_confirm(
             let chat_id = msg.chat.id.to_string();
             match button.as_str() {
                 "Cancel" => {
-                    cancel_edit_payment(bot, dialogue, state, msg.clone()).await?;
+                    cancel_edit_payment(bot, dialogue, state, msg.clone(), store).await?;
                 }
                 "Confirm" => {
                     call_processor_edit_payment(
@@ -527,13 +637,14 @@ pub async fn action_edit_payment_confirm(
                         payments,
                         page,
                         query,
+                        &store,
                     )
                     .await?;
                 }
                 "Description" => {
                     let new_message = send_bot_message(
                         &bot,
-                        &msg,
+                        msg,
                         format!(
                             "Current description: {}\n\nWhat should the description be?",
                             edited_payment
@@ -556,23 +667,64 @@ pub async fn action_edit_payment_confirm(
                         })
                         .await?;
                 }
-                "Payer" => {
+                "Date" => {
+                    let time_zone = retrieve_time_zone(&store, &chat_id).await;
                     let new_message = send_bot_message(
                         &bot,
-                        &msg,
+                        msg,
                         format!(
-                            "Current payer: {}\n\nWho should the payer be?",
-                            display_username(
-                                &edited_payment
-                                    .creditor
-                                    .clone()
-                                    .unwrap_or(payment.creditor.clone())
+                            "Current date: {}\n\nWhen was this payment made?\n\n{DATE_INSTRUCTIONS_MESSAGE}",
+                            format_timestamp(
+                                edited_payment.timestamp.unwrap_or(payment.timestamp),
+                                time_zone
                             )
                         ),
                     )
                     .await?
                     .id;
                     messages.push(new_message);
+                    dialogue
+                        .update(State::EditPaymentDetails {
+                            messages,
+                            payment,
+                            edited_payment,
+                            edit: AddPaymentEdit::Date,
+                            payments,
+                            page,
+                        })
+                        .await?;
+                }
+                "Payer" => {
+                    let creditor = edited_payment
+                        .creditor
+                        .clone()
+                        .unwrap_or(payment.creditor.clone());
+                    let payers = edited_payment
+                        .payers
+                        .clone()
+                        .unwrap_or(payment.payers.clone());
+                    let names =
+                        get_member_names(&store, payment_members(&creditor, &payers, &[], &[]))
+                            .await;
+                    let current = if payers.is_empty() {
+                        format!(": {}\n", display_username(&creditor, &names))
+                    } else {
+                        let currency = edited_payment
+                            .currency
+                            .clone()
+                            .unwrap_or(payment.currency.clone());
+                        format!("s:\n{}", display_debts(&payers, currency.1, &names))
+                    };
+                    let new_message = send_bot_message(
+                        &bot,
+                        msg,
+                        format!(
+                            "Current payer{current}\nWho should the payer be?\n\n{PAYER_INSTRUCTIONS_MESSAGE}"
+                        ),
+                    )
+                    .await?
+                    .id;
+                    messages.push(new_message);
                     dialogue
                         .update(State::EditPaymentDetails {
                             messages,
@@ -589,10 +741,10 @@ pub async fn action_edit_payment_confirm(
                         .currency
                         .clone()
                         .unwrap_or(payment.currency.clone());
-                    let actual_currency = use_currency(currency, &payment.chat_id);
+                    let actual_currency = use_currency(&store, currency, &payment.chat_id).await;
                     let new_message = send_bot_message(
                         &bot,
-                        &msg,
+                        msg,
                         format!(
                             "Current total: {}\n\nWhat should the total be?\n\n{TOTAL_INSTRUCTIONS_MESSAGE}",
                             display_currency_amount(edited_payment.total.unwrap_or(payment.total), actual_currency)
@@ -612,12 +764,16 @@ pub async fn action_edit_payment_confirm(
                         .await?;
                 }
                 "Split" => {
+                    let debts = edited_payment
+                        .debts
+                        .clone()
+                        .unwrap_or(payment.debts.clone());
                     let new_message = send_bot_message(
                         &bot,
-                        &msg,
+                        msg,
                         format!(
-                            "Current split:\n{}\nHow should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}",
-                            display_debts(&edited_payment.debts.clone().unwrap_or(payment.debts.clone()), edited_payment.currency.clone().unwrap_or(payment.currency.clone()).1)
+                            "Current split:\n{}\nHow should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}",
+                            display_debts(&debts, edited_payment.currency.clone().unwrap_or(payment.currency.clone()).1, &get_member_names(&store, debt_members(&debts)).await)
                             )
                             ).reply_markup(make_keyboard_debt_selection())
                         .await?.id;
@@ -632,6 +788,36 @@ pub async fn action_edit_payment_confirm(
                         })
                         .await?;
                 }
+                "Category" => {
+                    let categories = process_chat_categories(&store, &chat_id).await;
+                    let new_message = send_bot_message(
+                        &bot,
+                        msg,
+                        format!(
+                            "Current category: {}\n\nWhich category is this payment in?\n\n{CATEGORY_INSTRUCTIONS_MESSAGE}",
+                            edited_payment
+                                .category
+                                .clone()
+                                .unwrap_or(payment.category.clone())
+                                .as_deref()
+                                .unwrap_or("None")
+                        ),
+                    )
+                    .reply_markup(make_keyboard_categories(&categories))
+                    .await?
+                    .id;
+                    messages.push(new_message);
+                    dialogue
+                        .update(State::EditPaymentDetails {
+                            messages,
+                            payment,
+                            edited_payment,
+                            edit: AddPaymentEdit::Category,
+                            payments,
+                            page,
+                        })
+                        .await?;
+                }
                 _ => {
                     log::error!(
                         "Edit Payment Menu - Invalid button in chat {}: {}",
@@ -658,7 +844,7 @@ pub async fn action_edit_payment_debts(
         Payment,
         EditPaymentParams,
         Vec<Payment>,
-        usize,
+        PaymentsPage,
     ),
 ) -> HandlerResult {
     if let Some(button) = &query.data {
@@ -727,6 +913,26 @@ pub async fn action_edit_payment_debts(
                         .await?;
                 }
             }
+            "Itemized" => {
+                if let Some(Message { id, chat, .. }) = query.message {
+                    bot.edit_message_text(
+                        chat.id,
+                        id,
+                        format!(
+                            "Okay! What items were paid for, and who shared each of them?\n\n{DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE}",
+                            )).await?;
+                    dialogue
+                        .update(State::EditPaymentDetails {
+                            messages,
+                            payment,
+                            edited_payment,
+                            edit: AddPaymentEdit::DebtsItemized,
+                            payments,
+                            page,
+                        })
+                        .await?;
+                }
+            }
             _ => {
                 log::error!("Edit Payment Debt Selection - Invalid button for in chat {} with payment {:?}: {}",
                             payment.chat_id, payment, button);
@@ -750,18 +956,23 @@ pub async fn action_edit_payment_edit(
         EditPaymentParams,
         AddPaymentEdit,
         Vec<Payment>,
-        usize,
+        PaymentsPage,
     ),
+    store: Store,
 ) -> HandlerResult {
     match msg.text() {
         Some(text) => match edit {
             AddPaymentEdit::Description => {
                 let new_edited_payment = EditPaymentParams {
                     description: Some(text.to_string()),
+                    timestamp: edited_payment.timestamp,
                     creditor: edited_payment.creditor,
+                    payers: edited_payment.payers,
                     currency: edited_payment.currency,
                     total: edited_payment.total,
                     debts: edited_payment.debts,
+                    items: edited_payment.items,
+                    category: edited_payment.category,
                 };
                 display_edit_overview(
                     bot,
@@ -773,22 +984,121 @@ pub async fn action_edit_payment_edit(
                     new_edited_payment,
                     payments,
                     page,
+                    &store,
                 )
                 .await?;
             }
-            AddPaymentEdit::Creditor => {
-                let username = parse_username(text);
-                if let Err(err) = username {
-                    let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
+            AddPaymentEdit::Date => {
+                let time_zone = retrieve_time_zone(&store, &payment.chat_id).await;
+                let timestamp = parse_payment_date(
+                    text,
+                    time_zone,
+                    edited_payment.timestamp.unwrap_or(payment.timestamp),
+                    msg.date.timestamp(),
+                );
+                if let Err(err) = timestamp {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{DATE_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
                     repeat_state(dialogue, state, new_message).await?;
                     return Ok(());
                 }
                 let new_edited_payment = EditPaymentParams {
                     description: edited_payment.description,
-                    creditor: Some(username?),
+                    timestamp: Some(timestamp?),
+                    creditor: edited_payment.creditor,
+                    payers: edited_payment.payers,
+                    currency: edited_payment.currency,
+                    total: edited_payment.total,
+                    debts: edited_payment.debts,
+                    items: edited_payment.items,
+                    category: edited_payment.category,
+                };
+                display_edit_overview(
+                    bot,
+                    dialogue,
+                    &msg,
+                    None,
+                    messages,
+                    payment,
+                    new_edited_payment,
+                    payments,
+                    page,
+                    &store,
+                )
+                .await?;
+            }
+            AddPaymentEdit::Creditor => {
+                let text = parse_text_mentions(&msg).unwrap_or_default();
+                let currency = edited_payment
+                    .currency
+                    .clone()
+                    .unwrap_or(payment.currency.clone());
+                let paid_amounts = process_payers(&text, currency);
+                let (creditor, payers, paid) = match paid_amounts {
+                    Ok(paid_amounts) => paid_amounts,
+                    Err(err) => {
+                        let new_message = send_bot_message(
+                            &bot,
+                            &msg,
+                            format!("{}\n\n{PAYER_INSTRUCTIONS_MESSAGE}", err),
+                        )
+                        .await?
+                        .id;
+                        repeat_state(dialogue, state, new_message).await?;
+                        return Ok(());
+                    }
+                };
+
+                // Amounts paid which add up to a new total need the split to be given again
+                let total = edited_payment.total.unwrap_or(payment.total);
+                if let Some(paid) = paid.filter(|paid| *paid != total) {
+                    let new_edited_payment = EditPaymentParams {
+                        description: edited_payment.description,
+                        timestamp: edited_payment.timestamp,
+                        creditor: Some(creditor),
+                        payers: Some(payers),
+                        currency: edited_payment.currency,
+                        total: Some(paid),
+                        debts: None,
+                        items: None,
+                        category: edited_payment.category,
+                    };
+
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("Fantastic! The total is now what they paid. How should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}")
+                        )
+                        .reply_markup(make_keyboard_debt_selection())
+                        .await?.id;
+                    messages.push(new_message);
+                    dialogue
+                        .update(State::EditPaymentDebtSelection {
+                            messages,
+                            payment,
+                            edited_payment: new_edited_payment,
+                            payments,
+                            page,
+                        })
+                        .await?;
+                    return Ok(());
+                }
+
+                let new_edited_payment = EditPaymentParams {
+                    description: edited_payment.description,
+                    timestamp: edited_payment.timestamp,
+                    creditor: Some(creditor),
+                    payers: Some(payers),
                     currency: edited_payment.currency,
                     total: edited_payment.total,
                     debts: edited_payment.debts,
+                    items: edited_payment.items,
+                    category: edited_payment.category,
                 };
                 display_edit_overview(
                     bot,
@@ -800,25 +1110,36 @@ pub async fn action_edit_payment_edit(
                     new_edited_payment,
                     payments,
                     page,
+                    &store,
                 )
                 .await?;
             }
             AddPaymentEdit::Total => {
-                let currency_amount = parse_currency_amount(text);
+                let payers = edited_payment
+                    .payers
+                    .clone()
+                    .unwrap_or(payment.payers.clone());
+                let currency_amount = parse_currency_amount(text).and_then(|(total, currency)| {
+                    check_payers_total(&payers, total).map(|_| (total, currency))
+                });
                 match currency_amount {
                     Ok((total, currency)) => {
                         let new_edited_payment = EditPaymentParams {
                             description: edited_payment.description,
+                            timestamp: edited_payment.timestamp,
                             creditor: edited_payment.creditor,
+                            payers: edited_payment.payers,
                             currency: Some(currency),
                             total: Some(total),
                             debts: None,
+                            items: None,
+                            category: edited_payment.category,
                         };
 
                         let new_message = send_bot_message(
                             &bot,
                             &msg,
-                            format!("Fantastic! How should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}")
+                            format!("Fantastic! How should we split this?\n\n{DEBT_EQUAL_DESCRIPTION_MESSAGE}{DEBT_EXACT_DESCRIPTION_MESSAGE}{DEBT_RATIO_DESCRIPTION_MESSAGE}{DEBT_ITEMIZED_DESCRIPTION_MESSAGE}")
                             )
                             .reply_markup(make_keyboard_debt_selection())
                             .await?.id;
@@ -839,7 +1160,7 @@ pub async fn action_edit_payment_edit(
                             &msg,
                             format!(
                                 "{}\n\nWhat should the total be?\n\n{TOTAL_INSTRUCTIONS_MESSAGE}",
-                                err.to_string()
+                                err
                             ),
                         )
                         .await?
@@ -851,23 +1172,26 @@ pub async fn action_edit_payment_edit(
             }
             AddPaymentEdit::DebtsEqual
             | AddPaymentEdit::DebtsExact
-            | AddPaymentEdit::DebtsRatio => {
+            | AddPaymentEdit::DebtsRatio
+            | AddPaymentEdit::DebtsItemized => {
                 let debts_format = match edit {
                     AddPaymentEdit::DebtsEqual => AddDebtsFormat::Equal,
                     AddPaymentEdit::DebtsExact => AddDebtsFormat::Exact,
                     AddPaymentEdit::DebtsRatio => AddDebtsFormat::Ratio,
+                    AddPaymentEdit::DebtsItemized => AddDebtsFormat::Itemized,
                     _ => AddDebtsFormat::Equal,
                 };
                 let error_msg = match debts_format {
                     AddDebtsFormat::Equal => DEBT_EQUAL_INSTRUCTIONS_MESSAGE,
                     AddDebtsFormat::Exact => DEBT_EXACT_INSTRUCTIONS_MESSAGE,
                     AddDebtsFormat::Ratio => DEBT_RATIO_INSTRUCTIONS_MESSAGE,
+                    AddDebtsFormat::Itemized => DEBT_ITEMIZED_INSTRUCTIONS_MESSAGE,
                 };
-                match msg.text() {
+                match parse_text_mentions(&msg) {
                     Some(text) => {
                         let debts = process_debts(
                             debts_format,
-                            text,
+                            &text,
                             &edited_payment
                                 .creditor
                                 .clone()
@@ -878,24 +1202,28 @@ pub async fn action_edit_payment_edit(
                                 .or(Some(payment.currency.clone())),
                             edited_payment.total.or(Some(payment.total)),
                         );
-                        if let Err(err) = debts {
-                            let new_message = send_bot_message(
-                                &bot,
-                                &msg,
-                                format!("{}\n\n{error_msg}", err.to_string()),
-                            )
-                            .await?
-                            .id;
-                            repeat_state(dialogue, state, new_message).await?;
-                            return Ok(());
-                        }
+                        let (debts, items) = match debts {
+                            Ok(debts) => debts,
+                            Err(err) => {
+                                let new_message =
+                                    send_bot_message(&bot, &msg, format!("{}\n\n{error_msg}", err))
+                                        .await?
+                                        .id;
+                                repeat_state(dialogue, state, new_message).await?;
+                                return Ok(());
+                            }
+                        };
 
                         let new_edited_payment = EditPaymentParams {
                             description: edited_payment.description,
+                            timestamp: edited_payment.timestamp,
                             creditor: edited_payment.creditor,
+                            payers: edited_payment.payers,
                             currency: edited_payment.currency,
                             total: edited_payment.total,
-                            debts: Some(debts.unwrap()),
+                            debts: Some(debts),
+                            items: Some(items),
+                            category: edited_payment.category,
                         };
 
                         display_edit_overview(
@@ -908,20 +1236,72 @@ pub async fn action_edit_payment_edit(
                             new_edited_payment,
                             payments,
                             page,
+                            &store,
                         )
                         .await?;
                     }
                     None => {
-                        let new_message = send_bot_message(&bot, &msg, format!("{error_msg}"))
+                        let new_message = send_bot_message(&bot, &msg, error_msg.to_string())
                             .await?
                             .id;
                         repeat_state(dialogue, state, new_message).await?;
                     }
                 }
             }
+            AddPaymentEdit::Category => {
+                let category = if text.trim().eq_ignore_ascii_case("none") {
+                    Ok(None)
+                } else {
+                    let categories = process_chat_categories(&store, &payment.chat_id).await;
+                    parse_category(text, &categories).map(Some)
+                };
+                if let Err(err) = category {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{CATEGORY_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
+                }
+                let new_edited_payment = EditPaymentParams {
+                    description: edited_payment.description,
+                    timestamp: edited_payment.timestamp,
+                    creditor: edited_payment.creditor,
+                    payers: edited_payment.payers,
+                    currency: edited_payment.currency,
+                    total: edited_payment.total,
+                    debts: edited_payment.debts,
+                    items: edited_payment.items,
+                    category: Some(category?),
+                };
+                display_edit_overview(
+                    bot,
+                    dialogue,
+                    &msg,
+                    None,
+                    messages,
+                    payment,
+                    new_edited_payment,
+                    payments,
+                    page,
+                    &store,
+                )
+                .await?;
+            }
+            // Only payments being added can repeat, so this is never asked for here
+            AddPaymentEdit::Repeat => {
+                log::error!(
+                    "Edit Payment Edit - Invalid edit in chat {}: {:?}",
+                    msg.chat.id,
+                    edit
+                );
+            }
         },
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -930,3 +1310,68 @@ pub async fn action_edit_payment_edit(
 
     Ok(())
 }
+
+/* Edits a specified payment.
+ * Bot receives a callback query with the category picked for the payment.
+ * Categories typed instead are handled by action_edit_payment_edit.
+ */
+pub async fn action_edit_payment_category(
+    bot: Bot,
+    dialogue: UserDialogue,
+    query: CallbackQuery,
+    (messages, payment, edited_payment, edit, payments, page): (
+        Vec<MessageId>,
+        Payment,
+        EditPaymentParams,
+        AddPaymentEdit,
+        Vec<Payment>,
+        PaymentsPage,
+    ),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            if !matches!(edit, AddPaymentEdit::Category) {
+                log::error!(
+                    "Edit Payment Category - Invalid button in chat {}: {}",
+                    msg.chat.id,
+                    button
+                );
+                return Ok(());
+            }
+
+            let category = match button.as_str() {
+                "None" => None,
+                _ => Some(button.to_string()),
+            };
+            let new_edited_payment = EditPaymentParams {
+                description: edited_payment.description,
+                timestamp: edited_payment.timestamp,
+                creditor: edited_payment.creditor,
+                payers: edited_payment.payers,
+                currency: edited_payment.currency,
+                total: edited_payment.total,
+                debts: edited_payment.debts,
+                items: edited_payment.items,
+                category: Some(category),
+            };
+            display_edit_overview(
+                bot,
+                dialogue,
+                &msg,
+                Some(msg.id),
+                messages,
+                payment,
+                new_edited_payment,
+                payments,
+                page,
+                &store,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
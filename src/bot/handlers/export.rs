@@ -0,0 +1,314 @@
+use chrono_tz::Tz;
+use serde::Serialize;
+use teloxide::{
+    payloads::SendDocumentSetters,
+    prelude::*,
+    types::{InputFile, Message},
+};
+
+use crate::bot::{
+    constants::messages::UNKNOWN_ERROR_MESSAGE,
+    processor::{export_ledger, LedgerExport},
+    redis::UserBalance,
+    store::Store,
+    utils::{
+        bot_actions::{assert_handle_request_limit, send_bot_document, send_bot_message},
+        format::{display_amount, get_currency, member_from_user},
+        time::{format_timestamp_iso, retrieve_time_zone},
+        HandlerResult, UserDialogue,
+    },
+};
+
+// Columns of the CSV export. Payments take one row per debt,
+// while balances and spendings take one row per user and currency.
+const EXPORT_CSV_HEADER: [&str; 9] = [
+    "Type",
+    "Payment ID",
+    "Date",
+    "Description",
+    "Payer",
+    "Currency",
+    "Total",
+    "User",
+    "Amount",
+];
+
+/* Types */
+
+// Amounts are exported as decimal strings, so that they stay exact
+#[derive(Serialize)]
+struct ExportDebt {
+    user: String,
+    amount: String,
+}
+
+#[derive(Serialize)]
+struct ExportItem {
+    description: String,
+    amount: String,
+    users: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ExportPayment {
+    payment_id: String,
+    date: String,
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    payer: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    payers: Vec<ExportDebt>,
+    currency: String,
+    total: String,
+    debts: Vec<ExportDebt>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    items: Vec<ExportItem>,
+}
+
+#[derive(Serialize)]
+struct ExportBalance {
+    user: String,
+    currency: String,
+    amount: String,
+}
+
+#[derive(Serialize)]
+struct ExportRecords {
+    time_zone: String,
+    exported_at: String,
+    payments: Vec<ExportPayment>,
+    balances: Vec<ExportBalance>,
+    spendings: Vec<ExportBalance>,
+}
+
+/* Utilities */
+
+fn export_amount(amount: i64, code: &str) -> String {
+    let decimal_places = match get_currency(code) {
+        Ok(currency) => currency.1,
+        // Should not occur. Currency string is from database, so should exist.
+        Err(_) => 2,
+    };
+    display_amount(amount, decimal_places)
+}
+
+fn export_balances(balances: &[UserBalance]) -> Vec<ExportBalance> {
+    balances
+        .iter()
+        .map(|balance| ExportBalance {
+            user: balance.username.clone(),
+            currency: balance.currency.clone(),
+            amount: export_amount(balance.balance, &balance.currency),
+        })
+        .collect()
+}
+
+fn export_records(export: &LedgerExport, time_zone: Tz, timestamp: i64) -> ExportRecords {
+    let payments = export
+        .payments
+        .iter()
+        .map(|user_payment| {
+            let payment = &user_payment.payment;
+            ExportPayment {
+                payment_id: user_payment.payment_id.clone(),
+                date: format_timestamp_iso(payment.timestamp, time_zone),
+                description: payment.description.clone(),
+                category: payment.category.clone(),
+                payer: payment.creditor.clone(),
+                payers: payment
+                    .payers
+                    .iter()
+                    .map(|(user, amount)| ExportDebt {
+                        user: user.clone(),
+                        amount: export_amount(*amount, &payment.currency),
+                    })
+                    .collect(),
+                currency: payment.currency.clone(),
+                total: export_amount(payment.total, &payment.currency),
+                debts: payment
+                    .debts
+                    .iter()
+                    .map(|(user, amount)| ExportDebt {
+                        user: user.clone(),
+                        amount: export_amount(*amount, &payment.currency),
+                    })
+                    .collect(),
+                items: payment
+                    .items
+                    .iter()
+                    .map(|item| ExportItem {
+                        description: item.description.clone(),
+                        amount: export_amount(item.amount, &payment.currency),
+                        users: item.participants.clone(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    ExportRecords {
+        time_zone: time_zone.name().to_string(),
+        exported_at: format_timestamp_iso(timestamp, time_zone),
+        payments,
+        balances: export_balances(&export.balances),
+        spendings: export_balances(&export.spendings),
+    }
+}
+
+// Quotes a CSV field if it contains a separator, quote or line break
+fn escape_csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn display_csv_row(fields: &[&str]) -> String {
+    let row = fields
+        .iter()
+        .map(|field| escape_csv_field(field))
+        .collect::<Vec<String>>()
+        .join(",");
+    format!("{row}\r\n")
+}
+
+fn display_csv(records: &ExportRecords) -> String {
+    let mut csv = display_csv_row(&EXPORT_CSV_HEADER);
+    for payment in records.payments.iter() {
+        // Several payers share the payer column, each with what they paid
+        let payer = if payment.payers.is_empty() {
+            payment.payer.clone()
+        } else {
+            payment
+                .payers
+                .iter()
+                .map(|payer| format!("{} {}", payer.user, payer.amount))
+                .collect::<Vec<String>>()
+                .join("; ")
+        };
+        for debt in payment.debts.iter() {
+            csv.push_str(&display_csv_row(&[
+                "Payment",
+                &payment.payment_id,
+                &payment.date,
+                &payment.description,
+                &payer,
+                &payment.currency,
+                &payment.total,
+                &debt.user,
+                &debt.amount,
+            ]));
+        }
+    }
+    for (record_type, balances) in [
+        ("Balance", &records.balances),
+        ("Spending", &records.spendings),
+    ] {
+        for balance in balances.iter() {
+            csv.push_str(&display_csv_row(&[
+                record_type,
+                "",
+                "",
+                "",
+                "",
+                &balance.currency,
+                "",
+                &balance.user,
+                &balance.amount,
+            ]));
+        }
+    }
+    csv
+}
+
+/* Exports all records of the group as CSV and JSON files.
+ * Bot sends both files as documents, with payments dated in the chat time zone.
+ */
+pub async fn action_export(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let chat_id = msg.chat.id.to_string();
+    let sender_id = user.id.to_string();
+    let sender_username = member_from_user(user);
+
+    match export_ledger(&store, &chat_id, &sender_id, Some(&sender_username)).await {
+        Ok(export) => {
+            if export.payments.is_empty() {
+                send_bot_message(
+                    &bot,
+                    &msg,
+                    "📦 There are no payment records to export yet!".to_string(),
+                )
+                .await?;
+                dialogue.exit().await?;
+                return Ok(());
+            }
+
+            let time_zone = retrieve_time_zone(&store, &chat_id).await;
+            let records = export_records(&export, time_zone, msg.date.timestamp());
+            let date = records.exported_at.chars().take(10).collect::<String>();
+            let json = match serde_json::to_string_pretty(&records) {
+                Ok(json) => json,
+                Err(err) => {
+                    send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+                    // Logging
+                    log::error!(
+                        "Export - User {} failed to serialize records for chat {}: {}",
+                        sender_id,
+                        chat_id,
+                        err.to_string()
+                    );
+                    return Ok(());
+                }
+            };
+
+            let csv_file = InputFile::memory(display_csv(&records).into_bytes())
+                .file_name(format!("records_{date}.csv"));
+            let json_file =
+                InputFile::memory(json.into_bytes()).file_name(format!("records_{date}.json"));
+            send_bot_document(&bot, &msg, csv_file)
+                .caption(format!(
+                    "📦 Here are all {} payment records, with the current balances and spendings!",
+                    records.payments.len()
+                ))
+                .await?;
+            send_bot_document(&bot, &msg, json_file).await?;
+            dialogue.exit().await?;
+
+            // Logging
+            log::info!(
+                "Export - User {} exported {} payments for chat {}",
+                sender_id,
+                records.payments.len(),
+                chat_id
+            );
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "Export - User {} failed to export chat {}: {}",
+                sender_id,
+                chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
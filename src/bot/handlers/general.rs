@@ -1,4 +1,8 @@
-use teloxide::{prelude::*, types::ParseMode, utils::command::BotCommands};
+use teloxide::{
+    prelude::*,
+    types::{ParseMode, User},
+    utils::command::BotCommands,
+};
 
 use crate::bot::{
     constants::{
@@ -10,19 +14,101 @@ use crate::bot::{
         // urls::{FEEDBACK_URL, USER_GUIDE_URL},
     },
     dispatcher::Command,
-    processor::init_chat_config,
+    processor::{init_chat_config, migrate_chat, update_member_name, update_user_identity},
+    store::Store,
     utils::{
         bot_actions::{assert_handle_request_limit, send_bot_message},
+        format::{get_text_mentioned_users, member_from_user},
         HandlerResult,
     },
 };
 
+/* Tracks the identity of the sender of every message, and of users mentioned by text mentions.
+ * If a user has changed their username, all their records are moved to the new one.
+ * Users without a username also have their latest name kept, to display them by.
+ */
+pub async fn track_user_identity(msg: Message, store: Store) {
+    let mut users = get_text_mentioned_users(&msg);
+    if let Some(user) = msg.from() {
+        users.push(user.clone());
+    }
+
+    for user in users {
+        track_user(&store, &user).await;
+    }
+}
+
+// Tracks the identity of a single user
+async fn track_user(store: &Store, user: &User) {
+    let username = member_from_user(user);
+    match update_user_identity(store, &user.id.to_string(), &username).await {
+        Ok(Some(previous)) => {
+            log::info!(
+                "User Identity - User {} changed username from {} to {}",
+                user.id,
+                previous,
+                username
+            );
+        }
+        Ok(None) => {}
+        Err(err) => {
+            log::error!(
+                "User Identity - Failed to update identity of user {} as {}: {}",
+                user.id,
+                username,
+                err.to_string()
+            );
+        }
+    }
+
+    if user.username.is_none() {
+        if let Err(err) = update_member_name(store, &username, &user.full_name()).await {
+            log::error!(
+                "User Identity - Failed to update name of user {}: {}",
+                user.id,
+                err.to_string()
+            );
+        }
+    }
+}
+
+/* Migrates the records of a group to its new chat ID, when it is upgraded to a supergroup.
+ * Telegram sends a service message to both the old and the new chat, so either one may arrive first.
+ */
+pub async fn action_migrate_chat(msg: Message, store: Store) -> HandlerResult {
+    let (chat_id, new_chat_id) = match (msg.migrate_to_chat_id(), msg.migrate_from_chat_id()) {
+        (Some(new_chat_id), _) => (msg.chat.id, new_chat_id),
+        (_, Some(chat_id)) => (chat_id, msg.chat.id),
+        _ => return Ok(()),
+    };
+
+    match migrate_chat(&store, &chat_id.to_string(), &new_chat_id.to_string()).await {
+        Ok(()) => {
+            log::info!(
+                "Migrate Chat - Chat {} migrated to chat {}",
+                chat_id,
+                new_chat_id
+            );
+        }
+        Err(err) => {
+            log::error!(
+                "Migrate Chat - Failed to migrate chat {} to chat {}: {}",
+                chat_id,
+                new_chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
 /* Invalid state.
  * This action is invoked when the bot is in start state, and there is a non-command message
  * addressed to it.
  * Currently, simply does not respond to anything. Reduces spam.
  */
-pub async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
+pub async fn invalid_state(bot: Bot, msg: Message, store: Store) -> HandlerResult {
     // Checks if msg is a service message, ignores it if so
     let is_service_msg = msg.from().is_none();
 
@@ -32,7 +118,7 @@ pub async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
         if let Some(new_members) = new_members {
             let bot_id = bot.get_me().send().await?.id;
             if new_members.iter().any(|member| member.id == bot_id) {
-                action_start(bot, msg).await?;
+                action_start(bot, msg, store).await?;
             }
         }
 
@@ -61,16 +147,16 @@ pub async fn callback_invalid_message(_bot: Bot, _msg: Message) -> HandlerResult
 /* Start command.
  * Displays a welcome message to the user.
  */
-pub async fn action_start(bot: Bot, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_start(bot: Bot, msg: Message, store: Store) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     // Inits chat configs
-    init_chat_config(&msg.chat.id.to_string())?;
+    init_chat_config(&store, &msg.chat.id.to_string()).await?;
 
     // TODO: Add to messages constant
-    let intro = format!("Hello! I'm Finamaton!\n\nI'm tracking both individual and group expenses to simplify finance management");
+    let intro = "Hello! I'm Finamaton!\n\nI'm tracking both individual and group expenses to simplify finance management".to_string();
 
     let add_info = &format!("Start with {COMMAND_ADD_PAYMENT}. You can {COMMAND_VIEW_PAYMENTS} anytime, and I'll help to {COMMAND_EDIT_PAYMENT} or {COMMAND_DELETE_PAYMENT}.");
     let view_info = &format!("Check out {COMMAND_SPENDINGS} to see overall spendings. Track {COMMAND_BALANCES} of those who owes what. To repay, use {COMMAND_PAY_BACK}");
@@ -86,8 +172,8 @@ pub async fn action_start(bot: Bot, msg: Message) -> HandlerResult {
 /* Help command.
  * Displays a list of commands available to the user.
  */
-pub async fn action_help(bot: Bot, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_help(bot: Bot, msg: Message, store: Store) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -108,15 +194,15 @@ pub async fn action_help(bot: Bot, msg: Message) -> HandlerResult {
 /* Cancel command.
  * Called when state is at start, thus nothing to cancel.
  */
-pub async fn action_cancel(bot: Bot, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_cancel(bot: Bot, msg: Message, store: Store) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     send_bot_message(
         &bot,
         &msg,
-        format!("{BLANK_CANCEL}"), // TODO: Add to messages constant
+        BLANK_CANCEL.to_string(), // TODO: Add to messages constant
     )
     .await?;
     Ok(())
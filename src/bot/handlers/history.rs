@@ -0,0 +1,497 @@
+use chrono_tz::Tz;
+use teloxide::{
+    payloads::SendMessageSetters,
+    prelude::*,
+    types::{Message, MessageId},
+};
+
+use crate::bot::{
+    constants::{
+        commands::{COMMAND_CANCEL, COMMAND_HISTORY, COMMAND_VIEW_PAYMENTS},
+        messages::UNKNOWN_ERROR_MESSAGE,
+    },
+    currency::Currency,
+    dispatcher::State,
+    handlers::{Payment, PaymentsPage},
+    processor::{view_chat_activity, view_payment_history},
+    redis::{PaymentAction, PaymentEvent},
+    store::Store,
+    utils::{
+        bot_actions::{
+            assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
+        },
+        format::{
+            display_currency_amount, display_debts, display_items, display_username, get_currency,
+            get_member_names, make_keyboard, payment_members, MemberNames,
+        },
+        time::{format_timestamp, retrieve_time_zone},
+        HandlerResult, SelectPaymentType, UserDialogue,
+    },
+};
+
+// Number of latest changes shown in the activity of a chat
+const ACTIVITY_DISPLAY_COUNT: usize = 10;
+
+/* Utilities */
+
+// Controls the state for misc handler actions that return to same state.
+async fn repeat_state(
+    dialogue: UserDialogue,
+    state: State,
+    new_message: MessageId,
+) -> HandlerResult {
+    if let State::SelectPayment {
+        mut messages,
+        payments,
+        page,
+        function,
+    } = state
+    {
+        messages.push(new_message);
+        dialogue
+            .update(State::SelectPayment {
+                messages,
+                payments,
+                page,
+                function,
+            })
+            .await?;
+    }
+
+    Ok(())
+}
+
+// Controls the dialogue for ending a payment history operation.
+async fn complete_payment_history(
+    bot: &Bot,
+    dialogue: UserDialogue,
+    chat_id: &str,
+    messages: Vec<MessageId>,
+    payments: Vec<Payment>,
+    page: PaymentsPage,
+    store: &Store,
+) -> HandlerResult {
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
+    }
+    dialogue
+        .update(State::ViewPayments { payments, page })
+        .await?;
+    Ok(())
+}
+
+// Gets the members displayed with events: who made each change, and those in the payments changed
+fn event_members(events: &[PaymentEvent]) -> Vec<&str> {
+    let mut members = Vec::new();
+    for event in events {
+        members.push(event.username.as_str());
+        for payment in event.before.iter().chain(&event.after) {
+            members.extend(payment_members(
+                &payment.creditor,
+                &payment.payers,
+                &payment.debts,
+                &payment.items,
+            ));
+        }
+    }
+    members
+}
+
+fn display_actor(event: &PaymentEvent, names: &MemberNames) -> String {
+    if event.username.is_empty() {
+        format!("user {}", event.user_id)
+    } else {
+        display_username(&event.username, names)
+    }
+}
+
+fn display_action(action: &PaymentAction) -> &'static str {
+    match action {
+        PaymentAction::Add => "➕ Added",
+        PaymentAction::Edit => "✏️ Edited",
+        PaymentAction::Delete => "🗑 Deleted",
+        PaymentAction::Restore => "♻️ Restored",
+        PaymentAction::Purge => "🔥 Removed permanently",
+    }
+}
+
+fn display_event_currency(code: &str) -> Currency {
+    match get_currency(code) {
+        Ok(currency) => currency,
+        // Should not occur. Currency string is from database, so should exist.
+        Err(_) => (code.to_string(), 2),
+    }
+}
+
+// Displays what an edit changed, one line per changed detail
+fn display_event_changes(event: &PaymentEvent, time_zone: Tz, names: &MemberNames) -> String {
+    let (before, after) = match (&event.before, &event.after) {
+        (Some(before), Some(after)) => (before, after),
+        _ => return String::new(),
+    };
+    let before_currency = display_event_currency(&before.currency);
+    let after_currency = display_event_currency(&after.currency);
+
+    let mut changes = String::new();
+    if before.description != after.description {
+        changes.push_str(&format!(
+            "    Description: {} → {}\n",
+            before.description, after.description
+        ));
+    }
+    if before.category != after.category {
+        changes.push_str(&format!(
+            "    Category: {} → {}\n",
+            before.category.as_deref().unwrap_or("None"),
+            after.category.as_deref().unwrap_or("None")
+        ));
+    }
+    if before.timestamp != after.timestamp {
+        changes.push_str(&format!(
+            "    Date: {} → {}\n",
+            format_timestamp(before.timestamp, time_zone),
+            format_timestamp(after.timestamp, time_zone)
+        ));
+    }
+    if before.payers.is_empty() && after.payers.is_empty() {
+        if before.creditor != after.creditor {
+            changes.push_str(&format!(
+                "    Payer: {} → {}\n",
+                display_username(&before.creditor, names),
+                display_username(&after.creditor, names)
+            ));
+        }
+    } else if before.paid_amounts() != after.paid_amounts() || before.currency != after.currency {
+        changes.push_str(&format!(
+            "    Payers before:\n{}    Payers after:\n{}",
+            display_debts(&before.paid_amounts(), before_currency.1, names),
+            display_debts(&after.paid_amounts(), after_currency.1, names)
+        ));
+    }
+    if before.currency != after.currency || before.total != after.total {
+        changes.push_str(&format!(
+            "    Total: {} → {}\n",
+            display_currency_amount(before.total, before_currency.clone()),
+            display_currency_amount(after.total, after_currency.clone())
+        ));
+    }
+    if before.debts != after.debts || before.currency != after.currency {
+        changes.push_str(&format!(
+            "    Split before:\n{}    Split after:\n{}",
+            display_debts(&before.debts, before_currency.1, names),
+            display_debts(&after.debts, after_currency.1, names)
+        ));
+    }
+    if before.items != after.items || (before.currency != after.currency && !after.items.is_empty())
+    {
+        changes.push_str(&format!(
+            "    Items before:\n{}    Items after:\n{}",
+            display_items(&before.items, before_currency.1, names),
+            display_items(&after.items, after_currency.1, names)
+        ));
+    }
+    changes
+}
+
+fn display_payment_history(events: &[PaymentEvent], time_zone: Tz, names: &MemberNames) -> String {
+    events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            format!(
+                "{}. {} by {} on {}\n{}",
+                index + 1,
+                display_action(&event.action),
+                display_actor(event, names),
+                format_timestamp(event.timestamp, time_zone),
+                display_event_changes(event, time_zone, names)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("")
+}
+
+fn display_chat_activity(events: &[PaymentEvent], time_zone: Tz, names: &MemberNames) -> String {
+    events
+        .iter()
+        .map(|event| {
+            // Deleted payments only have a snapshot from before the change
+            let payment = event.after.as_ref().or(event.before.as_ref());
+            let details = match payment {
+                Some(payment) => format!(
+                    "{} ({})",
+                    payment.description,
+                    display_currency_amount(
+                        payment.total,
+                        display_event_currency(&payment.currency)
+                    )
+                ),
+                None => "a payment".to_string(),
+            };
+            format!(
+                "{}\n{} {} by {}\n",
+                format_timestamp(event.timestamp, time_zone),
+                display_action(&event.action),
+                details,
+                display_actor(event, names)
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/* Handles a repeated call to view payment history.
+ * Does nothing, simply notifies the user.
+ */
+pub async fn handle_repeated_payment_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+) -> HandlerResult {
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        format!("Oops! Probably you forgot to pick a payment! Please choose one above, or {COMMAND_CANCEL} this before starting another one with me."),
+    )
+    .await?
+    .id;
+
+    repeat_state(dialogue, state, new_message).await?;
+    Ok(())
+}
+
+/* Cancels the payment history operation.
+ * Can be called at any step of the process.
+ */
+pub async fn cancel_payment_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    send_bot_message(
+        &bot,
+        &msg,
+        "Okay! I cancelled viewing the payment history.".to_string(),
+    )
+    .await?;
+
+    if let State::SelectPayment {
+        messages,
+        payments,
+        page,
+        function: _,
+    } = state
+    {
+        complete_payment_history(
+            &bot,
+            dialogue,
+            &msg.chat.id.to_string(),
+            messages,
+            payments,
+            page,
+            &store,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/* Blocks user command.
+ * Called when user attempts to start another operation in the middle of choosing a payment.
+ */
+pub async fn block_payment_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+) -> HandlerResult {
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        format!("Oops! Probably you forgot to pick a payment! Please choose one above, or {COMMAND_CANCEL} this before starting something new with me."),
+    )
+    .await?
+    .id;
+
+    repeat_state(dialogue, state, new_message).await?;
+    Ok(())
+}
+
+/* Shows the latest changes to payments in the group.
+ * Called outside of viewing payments. The history of a single payment is shown after
+ * choosing it from the payments.
+ */
+pub async fn action_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.to_string();
+    let time_zone = retrieve_time_zone(&store, &chat_id).await;
+
+    match view_chat_activity(&store, &chat_id, ACTIVITY_DISPLAY_COUNT).await {
+        Ok(events) => {
+            if events.is_empty() {
+                send_bot_message(
+                    &bot,
+                    &msg,
+                    "📜 Nothing has happened to the payments here yet!".to_string(),
+                )
+                .await?;
+            } else {
+                send_bot_message(
+                    &bot,
+                    &msg,
+                    format!(
+                        "📜 Here are the latest changes to payments!\n\n{}\nFor the full history of a payment, {COMMAND_VIEW_PAYMENTS} and then use {COMMAND_HISTORY} again.",
+                        display_chat_activity(&events, time_zone, &get_member_names(&store, event_members(&events)).await)
+                    ),
+                )
+                .await?;
+            }
+            dialogue.exit().await?;
+
+            // Logging
+            log::info!(
+                "History - User {} viewed activity for chat {}, found {} changes",
+                msg.from()
+                    .map(|user| user.id.to_string())
+                    .unwrap_or_default(),
+                chat_id,
+                events.len()
+            );
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "History - Failed to view activity for chat {}: {}",
+                chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/* Entry point for payment history function.
+ * Bot responds by providing button menu of payments to choose from.
+ * Points to SelectPayment state.
+ */
+pub async fn action_select_payment_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    (payments, page): (Vec<Payment>, PaymentsPage),
+) -> HandlerResult {
+    let mut buttons: Vec<String> = (0..payments.len())
+        .map(|index| format!("{}", page.serial_num(index)))
+        .collect();
+    buttons.push("Cancel".to_string());
+    let keyboard = make_keyboard(
+        buttons.iter().map(|option| option.as_str()).collect(),
+        Some(3),
+    );
+
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        "📜 Which payment no. would you like to see the history of?".to_string(),
+    )
+    .reply_markup(keyboard)
+    .await?
+    .id;
+
+    dialogue
+        .update(State::SelectPayment {
+            messages: vec![new_message],
+            payments,
+            page,
+            function: SelectPaymentType::ViewHistory,
+        })
+        .await?;
+
+    Ok(())
+}
+
+/* Shows the history of a specified payment.
+ * Bot shows every change made to the payment, by whom and when,
+ * then returns to viewing payments.
+ */
+pub async fn action_payment_history(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: &Message,
+    msg_id: MessageId,
+    (messages, payments, page): (Vec<MessageId>, Vec<Payment>, PaymentsPage),
+    index: usize,
+    store: Store,
+) -> HandlerResult {
+    let payment = payments[index].clone();
+    let chat_id = msg.chat.id.to_string();
+    let time_zone = retrieve_time_zone(&store, &chat_id).await;
+
+    match view_payment_history(&store, &payment.payment_id).await {
+        Ok(events) => {
+            let history = if events.is_empty() {
+                "No changes have been recorded for this payment yet.\n".to_string()
+            } else {
+                display_payment_history(
+                    &events,
+                    time_zone,
+                    &get_member_names(&store, event_members(&events)).await,
+                )
+            };
+            bot.edit_message_text(
+                chat_id.clone(),
+                msg_id,
+                format!(
+                    "📜 Here's the history of payment no. {}, {}!\n\n{}",
+                    page.serial_num(index),
+                    payment.description,
+                    history
+                ),
+            )
+            .await?;
+
+            // Logging
+            log::info!(
+                "Payment History - Viewed history for chat {} of payment {}, found {} changes",
+                chat_id,
+                payment.payment_id,
+                events.len()
+            );
+        }
+        Err(err) => {
+            bot.edit_message_text(chat_id.clone(), msg_id, UNKNOWN_ERROR_MESSAGE)
+                .await?;
+
+            // Logging
+            log::error!(
+                "Payment History - Failed to view history for chat {} of payment {}: {}",
+                chat_id,
+                payment.payment_id,
+                err.to_string()
+            );
+        }
+    }
+
+    // The message showing the history itself is never erased
+    let messages = messages
+        .into_iter()
+        .filter(|message| *message != msg_id)
+        .collect();
+    complete_payment_history(&bot, dialogue, &chat_id, messages, payments, page, &store).await?;
+    Ok(())
+}
@@ -0,0 +1,1060 @@
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    net::Download,
+    payloads::{EditMessageTextSetters, SendMessageSetters},
+    prelude::*,
+    types::{InlineKeyboardMarkup, Message, MessageId},
+};
+
+use crate::bot::{
+    constants::{
+        commands::{COMMAND_BALANCES, COMMAND_CANCEL},
+        messages::{NO_TEXT_MESSAGE, UNKNOWN_ERROR_MESSAGE},
+    },
+    currency::Currency,
+    dispatcher::State,
+    processor::add_payment_entry,
+    redis::Payment,
+    store::Store,
+    utils::{
+        bot_actions::{
+            assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
+        },
+        format::{
+            display_currency_amount, display_debts, display_username, get_chat_default_currency,
+            get_currency, get_member_names, make_keyboard, member_from_user, parse_text_mentions,
+            parse_username, payment_members, MemberNames,
+        },
+        import::{
+            convert_csv_record, convert_splitwise_row, display_skipped_row, is_splitwise_csv,
+            parse_csv_table, parse_splitwise_csv, CsvField, CsvRecord, SkippedRow, SplitwiseRow,
+            CSV_FIELDS,
+        },
+        time::{format_timestamp, retrieve_time_zone},
+        HandlerResult, UserDialogue,
+    },
+};
+
+// Largest file that can be imported, in bytes
+const IMPORT_FILE_SIZE_LIMIT: u32 = 1_000_000;
+
+// Number of rows that cannot be imported shown, the rest are counted
+const IMPORT_SKIPPED_DISPLAY_COUNT: usize = 10;
+
+// Number of payments shown in the preview of a CSV import
+const IMPORT_PREVIEW_COUNT: usize = 5;
+
+// Longest column name shown on a button
+const IMPORT_COLUMN_NAME_LENGTH: usize = 24;
+
+/* Utilities */
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImportParams {
+    chat_id: String,
+    sender_id: String,
+    sender_username: String,
+    members: Vec<String>,
+    usernames: Vec<Option<String>>,
+    rows: Vec<SplitwiseRow>,
+    skipped: Vec<SkippedRow>,
+}
+
+// Columns are matched in the order of CSV_FIELDS, with None for details left out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvImportParams {
+    chat_id: String,
+    sender_id: String,
+    sender_username: String,
+    timestamp: i64,
+    currency: Currency,
+    header: Vec<String>,
+    records: Vec<CsvRecord>,
+    columns: Vec<Option<usize>>,
+}
+
+// Controls the state for misc handler actions that return to same state.
+async fn repeat_state(
+    dialogue: UserDialogue,
+    state: State,
+    new_message: MessageId,
+) -> HandlerResult {
+    match state {
+        State::ImportDocument { mut messages } => {
+            messages.push(new_message);
+            dialogue.update(State::ImportDocument { messages }).await?;
+        }
+        State::ImportMembers {
+            mut messages,
+            import,
+        } => {
+            messages.push(new_message);
+            dialogue
+                .update(State::ImportMembers { messages, import })
+                .await?;
+        }
+        State::ImportConfirm {
+            mut messages,
+            import,
+        } => {
+            messages.push(new_message);
+            dialogue
+                .update(State::ImportConfirm { messages, import })
+                .await?;
+        }
+        State::ImportColumns {
+            mut messages,
+            import,
+        } => {
+            messages.push(new_message);
+            dialogue
+                .update(State::ImportColumns { messages, import })
+                .await?;
+        }
+        State::ImportCsvConfirm {
+            mut messages,
+            import,
+        } => {
+            messages.push(new_message);
+            dialogue
+                .update(State::ImportCsvConfirm { messages, import })
+                .await?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+// Controls the dialogue for ending an import operation.
+async fn complete_import(
+    bot: &Bot,
+    dialogue: UserDialogue,
+    chat_id: &str,
+    messages: Vec<MessageId>,
+    store: &Store,
+) -> HandlerResult {
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
+    }
+    dialogue.exit().await?;
+    Ok(())
+}
+
+fn display_skipped_rows(skipped: &[SkippedRow]) -> String {
+    let mut rows = skipped
+        .iter()
+        .take(IMPORT_SKIPPED_DISPLAY_COUNT)
+        .map(display_skipped_row)
+        .collect::<Vec<String>>()
+        .join("\n");
+    if skipped.len() > IMPORT_SKIPPED_DISPLAY_COUNT {
+        rows.push_str(&format!(
+            "\n...and {} more",
+            skipped.len() - IMPORT_SKIPPED_DISPLAY_COUNT
+        ));
+    }
+    rows
+}
+
+fn display_members(import: &ImportParams, names: &MemberNames) -> String {
+    import
+        .members
+        .iter()
+        .zip(import.usernames.iter())
+        .map(|(member, username)| match username {
+            Some(username) => format!("{} → {}", member, display_username(username, names)),
+            None => format!("{} → left out", member),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/* Converts the rows of a Splitwise export that can be imported into payments.
+ * Rows that cannot be mapped are returned together with those that could not be read.
+ */
+fn convert_import(import: &ImportParams) -> (Vec<(usize, Payment)>, Vec<SkippedRow>) {
+    let mut payments = Vec::new();
+    let mut skipped = import.skipped.clone();
+    for row in import.rows.iter() {
+        match convert_splitwise_row(row, &import.members, &import.usernames) {
+            Ok(payment) => payments.push((row.row, payment)),
+            Err(skip) => skipped.push(skip),
+        }
+    }
+    skipped.sort_by_key(|skip| skip.row);
+    (payments, skipped)
+}
+
+/* Converts the records of any CSV that can be imported into payments.
+ * Records that cannot be read are returned with the reason why.
+ */
+fn convert_csv_import(
+    import: &CsvImportParams,
+    time_zone: Tz,
+) -> (Vec<(usize, Payment)>, Vec<SkippedRow>) {
+    let mut payments = Vec::new();
+    let mut skipped = Vec::new();
+    for record in import.records.iter() {
+        match convert_csv_record(
+            record,
+            &import.columns,
+            import.timestamp,
+            &import.currency,
+            time_zone,
+        ) {
+            Ok(payment) => payments.push((record.row, payment)),
+            Err(skip) => skipped.push(skip),
+        }
+    }
+    (payments, skipped)
+}
+
+// Sends a new message in the import dialogue, or edits the given one
+async fn send_import_message(
+    bot: &Bot,
+    msg: &Message,
+    messages: &mut Vec<MessageId>,
+    edit_message: Option<MessageId>,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> HandlerResult {
+    match (edit_message, keyboard) {
+        (Some(id), Some(keyboard)) => {
+            bot.edit_message_text(msg.chat.id, id, text)
+                .reply_markup(keyboard)
+                .await?;
+        }
+        (Some(id), None) => {
+            bot.edit_message_text(msg.chat.id, id, text).await?;
+        }
+        (None, Some(keyboard)) => {
+            let new_message = send_bot_message(bot, msg, text)
+                .reply_markup(keyboard)
+                .await?
+                .id;
+            messages.push(new_message);
+        }
+        (None, None) => {
+            let new_message = send_bot_message(bot, msg, text).await?.id;
+            messages.push(new_message);
+        }
+    }
+    Ok(())
+}
+
+fn display_column_button(index: usize, column: &str) -> String {
+    let name = if column.is_empty() {
+        format!("Column {}", index + 1)
+    } else {
+        column.chars().take(IMPORT_COLUMN_NAME_LENGTH).collect()
+    };
+    format!("{}. {}", index + 1, name)
+}
+
+// Reads the index of a column from its button
+fn parse_column_button(button: &str) -> Option<usize> {
+    let (number, _) = button.split_once(". ")?;
+    number.parse::<usize>().ok()?.checked_sub(1)
+}
+
+fn display_column_question(field: &CsvField) -> String {
+    let fallback = match field {
+        CsvField::Date => " Skip if there's none, and I'll use today's date.",
+        CsvField::Currency => " Skip if there's none, and I'll use the default currency.",
+        CsvField::SplitType => " Skip if there's none, and I'll split equally.",
+        CsvField::Participants => {
+            " Participants are usernames, with their amounts or shares for exact or ratio splits."
+        }
+        _ => "",
+    };
+    format!(
+        "Which column has the {} of each payment?{}",
+        field.name(),
+        fallback
+    )
+}
+
+fn make_column_keyboard(header: &[String], field: &CsvField) -> InlineKeyboardMarkup {
+    let mut buttons: Vec<String> = header
+        .iter()
+        .enumerate()
+        .map(|(index, column)| display_column_button(index, column))
+        .collect();
+    if field.is_optional() {
+        buttons.push("Skip".to_string());
+    }
+    buttons.push("Cancel".to_string());
+    make_keyboard(
+        buttons.iter().map(|button| button.as_str()).collect(),
+        Some(2),
+    )
+}
+
+fn display_columns(import: &CsvImportParams) -> String {
+    CSV_FIELDS
+        .iter()
+        .zip(import.columns.iter())
+        .map(|(field, column)| match column {
+            Some(index) => format!(
+                "{} → {}",
+                field.name(),
+                display_column_button(*index, &import.header[*index])
+            ),
+            None => format!("{} → left out", field.name()),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn display_preview_payment(
+    row: usize,
+    payment: &Payment,
+    time_zone: Tz,
+    names: &MemberNames,
+) -> String {
+    let currency = match get_currency(&payment.currency) {
+        Ok(currency) => currency,
+        // Should not occur. Currency string is from the import, so should exist.
+        Err(_) => (payment.currency.clone(), 2),
+    };
+    format!(
+        "Row {}: {}\n{} paid {} on {}\n{}",
+        row,
+        payment.description,
+        display_username(&payment.creditor, names),
+        display_currency_amount(payment.total, currency.clone()),
+        format_timestamp(payment.timestamp, time_zone),
+        display_debts(&payment.debts, currency.1, names)
+    )
+}
+
+/* Asks for the column of the next detail of the payments in a CSV.
+ * Once every column is matched, displays a preview of the import instead,
+ * without adding any payments yet.
+ */
+async fn ask_import_column(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &UserDialogue,
+    mut messages: Vec<MessageId>,
+    import: CsvImportParams,
+    edit_message: Option<MessageId>,
+    store: &Store,
+) -> HandlerResult {
+    if let Some(field) = CSV_FIELDS.get(import.columns.len()) {
+        let keyboard = make_column_keyboard(&import.header, field);
+        send_import_message(
+            bot,
+            msg,
+            &mut messages,
+            edit_message,
+            display_column_question(field),
+            Some(keyboard),
+        )
+        .await?;
+        dialogue
+            .update(State::ImportColumns { messages, import })
+            .await?;
+        return Ok(());
+    }
+
+    let time_zone = retrieve_time_zone(store, &import.chat_id).await;
+    let (payments, skipped) = convert_csv_import(&import, time_zone);
+    let skipped_info = if skipped.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "\n\nThese rows can't be imported:\n{}",
+            display_skipped_rows(&skipped)
+        )
+    };
+
+    if payments.is_empty() {
+        send_import_message(
+            bot,
+            msg,
+            &mut messages,
+            edit_message,
+            format!(
+                "🥺 Sorry, none of the payments can be imported!\n\n{}{}",
+                display_columns(&import),
+                skipped_info
+            ),
+            None,
+        )
+        .await?;
+
+        // The message with the import results is never erased
+        let messages = messages
+            .into_iter()
+            .filter(|message| Some(*message) != edit_message)
+            .collect();
+        complete_import(bot, dialogue.clone(), &import.chat_id, messages, store).await?;
+        return Ok(());
+    }
+
+    let names = get_member_names(
+        store,
+        payments
+            .iter()
+            .take(IMPORT_PREVIEW_COUNT)
+            .flat_map(|(_, payment)| payment_members(&payment.creditor, &[], &payment.debts, &[])),
+    )
+    .await;
+    let preview = payments
+        .iter()
+        .take(IMPORT_PREVIEW_COUNT)
+        .map(|(row, payment)| display_preview_payment(*row, payment, time_zone, &names))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let keyboard = make_keyboard(vec!["Cancel", "Confirm"], Some(2));
+    send_import_message(
+        bot,
+        msg,
+        &mut messages,
+        edit_message,
+        format!(
+            "Amazing! Check the import? Nothing is added until you confirm.\n\n{}\n\n{}\nI can import {} payments.{}",
+            display_columns(&import),
+            preview,
+            payments.len(),
+            skipped_info
+        ),
+        Some(keyboard),
+    )
+    .await?;
+    dialogue
+        .update(State::ImportCsvConfirm { messages, import })
+        .await?;
+    Ok(())
+}
+
+/* Asks for the Telegram username of the next member of the Splitwise group.
+ * Once every member is matched, displays an overview of the import instead.
+ */
+async fn ask_import_member(
+    bot: &Bot,
+    msg: &Message,
+    dialogue: &UserDialogue,
+    mut messages: Vec<MessageId>,
+    import: ImportParams,
+    store: &Store,
+) -> HandlerResult {
+    if let Some(member) = import.members.get(import.usernames.len()) {
+        let keyboard = make_keyboard(vec!["Cancel", "Skip"], Some(2));
+        let new_message = send_bot_message(
+            bot,
+            msg,
+            format!("Who is {member} from Splitwise? Send me their Telegram username, or skip to leave out their payments."),
+        )
+        .reply_markup(keyboard)
+        .await?
+        .id;
+        messages.push(new_message);
+        dialogue
+            .update(State::ImportMembers { messages, import })
+            .await?;
+        return Ok(());
+    }
+
+    let (payments, skipped) = convert_import(&import);
+    let names =
+        get_member_names(store, import.usernames.iter().flatten().map(String::as_str)).await;
+    let skipped_info = if skipped.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "\n\nThese rows can't be imported:\n{}",
+            display_skipped_rows(&skipped)
+        )
+    };
+
+    if payments.is_empty() {
+        send_bot_message(
+            bot,
+            msg,
+            format!(
+                "🥺 Sorry, none of the payments can be imported!\n\n{}{}",
+                display_members(&import, &names),
+                skipped_info
+            ),
+        )
+        .await?;
+        complete_import(bot, dialogue.clone(), &import.chat_id, messages, store).await?;
+        return Ok(());
+    }
+
+    let keyboard = make_keyboard(vec!["Cancel", "Confirm"], Some(2));
+    let new_message = send_bot_message(
+        bot,
+        msg,
+        format!(
+            "Amazing! Check the import?\n\n{}\n\nI can import {} payments.{}",
+            display_members(&import, &names),
+            payments.len(),
+            skipped_info
+        ),
+    )
+    .reply_markup(keyboard)
+    .await?
+    .id;
+    messages.push(new_message);
+    dialogue
+        .update(State::ImportConfirm { messages, import })
+        .await?;
+    Ok(())
+}
+
+async fn call_processor_import(
+    bot: &Bot,
+    msg: &Message,
+    chat_id: &str,
+    sender_id: &str,
+    sender_username: &str,
+    (payments, mut skipped): (Vec<(usize, Payment)>, Vec<SkippedRow>),
+    store: &Store,
+) -> HandlerResult {
+    let mut imported = 0;
+    for (row, payment) in payments {
+        let description = payment.description.clone();
+        match add_payment_entry(store, chat_id, sender_username, sender_id, payment).await {
+            Ok(()) => imported += 1,
+            Err(err) => {
+                skipped.push(SkippedRow {
+                    row,
+                    description,
+                    reason: "I couldn't save this payment".to_string(),
+                });
+
+                // Logging
+                log::error!(
+                    "Import Submission - Processor failed to add payment from row {} for user {} in chat {}: {}",
+                    row,
+                    sender_id,
+                    chat_id,
+                    err.to_string()
+                );
+            }
+        }
+    }
+    skipped.sort_by_key(|skip| skip.row);
+
+    let skipped_info = if skipped.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "\n\nThese rows were not imported:\n{}",
+            display_skipped_rows(&skipped)
+        )
+    };
+    bot.edit_message_text(
+        msg.chat.id,
+        msg.id,
+        format!(
+            "🥳 Imported {} payments! Check out the {COMMAND_BALANCES} now.{}",
+            imported, skipped_info
+        ),
+    )
+    .await?;
+
+    // Logging
+    log::info!(
+        "Import Submission - User {} imported {} payments in chat {}, {} rows not imported",
+        sender_id,
+        imported,
+        chat_id,
+        skipped.len()
+    );
+    Ok(())
+}
+
+/* Action handler functions */
+
+/* Handles a repeated call to import.
+ * Does nothing, simply notifies the user.
+ */
+pub async fn handle_repeated_import(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        format!("🚫 Oops! Probably you forgot to finish importing! Please finish or {COMMAND_CANCEL} this before starting another one with me."),
+    )
+    .await?
+    .id;
+
+    repeat_state(dialogue, state, new_message).await?;
+    Ok(())
+}
+
+/* Cancels the import operation.
+ * Can be called at any step of the process.
+ */
+pub async fn cancel_import(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    send_bot_message(
+        &bot,
+        &msg,
+        "Okay! I cancelled the import. Nothing was added.".to_string(),
+    )
+    .await?;
+
+    match state {
+        State::ImportDocument { messages }
+        | State::ImportMembers { messages, .. }
+        | State::ImportConfirm { messages, .. }
+        | State::ImportColumns { messages, .. }
+        | State::ImportCsvConfirm { messages, .. } => {
+            complete_import(&bot, dialogue, &msg.chat.id.to_string(), messages, &store).await?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/* Blocks user command.
+ * Called when user attempts to start another operation in the middle of importing.
+ */
+pub async fn block_import(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        format!("🚫 Oops! Probably you forgot to finish importing! Please finish or {COMMAND_CANCEL} this before starting something new with me."),
+    )
+    .await?
+    .id;
+
+    repeat_state(dialogue, state, new_message).await?;
+    Ok(())
+}
+
+/* Imports payments from a Splitwise export, or any CSV.
+ * Entrypoint to the dialogue sequence.
+ */
+pub async fn action_import(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let new_message = send_bot_message(
+        &bot,
+        &msg,
+        format!("Absolutely! Send me the CSV file exported from your Splitwise group, or any CSV with your payments. You can also {COMMAND_CANCEL} this."),
+    )
+    .await?
+    .id;
+
+    dialogue
+        .update(State::ImportDocument {
+            messages: vec![new_message],
+        })
+        .await?;
+    Ok(())
+}
+
+/* Imports payments from a Splitwise export, or any CSV.
+ * Bot receives a CSV document. For a Splitwise export, proceeds to ask for the username
+ * of each member. Otherwise, proceeds to ask for the column of each detail of the payments.
+ */
+pub async fn action_import_document(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    messages: Vec<MessageId>,
+    store: Store,
+) -> HandlerResult {
+    let document = match msg.document() {
+        Some(document) => document,
+        None => {
+            let new_message = send_bot_message(
+                &bot,
+                &msg,
+                "❓ No file? Please send me a CSV file with your payments!".to_string(),
+            )
+            .await?
+            .id;
+            repeat_state(dialogue, state, new_message).await?;
+            return Ok(());
+        }
+    };
+
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let sender_username = member_from_user(user);
+
+    if document.file.size > IMPORT_FILE_SIZE_LIMIT {
+        let new_message = send_bot_message(
+            &bot,
+            &msg,
+            "🥺 Sorry, this file is too large for me to handle!".to_string(),
+        )
+        .await?
+        .id;
+        repeat_state(dialogue, state, new_message).await?;
+        return Ok(());
+    }
+
+    let file = bot.get_file(document.file.id.clone()).await?;
+    let mut data: Vec<u8> = Vec::new();
+    if let Err(err) = bot.download_file(&file.path, &mut data).await {
+        let new_message = send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string())
+            .await?
+            .id;
+
+        // Logging
+        log::error!(
+            "Import Document - Failed to download file for user {} in chat {}: {}",
+            user.id,
+            msg.chat.id,
+            err.to_string()
+        );
+
+        repeat_state(dialogue, state, new_message).await?;
+        return Ok(());
+    }
+
+    let text = String::from_utf8_lossy(&data);
+    let table = match parse_csv_table(&text) {
+        Ok(table) => table,
+        Err(err) => {
+            let new_message =
+                send_bot_message(&bot, &msg, format!("{}\n\nPlease send me a CSV file!", err))
+                    .await?
+                    .id;
+            repeat_state(dialogue, state, new_message).await?;
+            return Ok(());
+        }
+    };
+    let chat_id = msg.chat.id.to_string();
+
+    if !is_splitwise_csv(&table) {
+        let import = CsvImportParams {
+            chat_id: chat_id.clone(),
+            sender_id: user.id.to_string(),
+            sender_username,
+            timestamp: msg.date.timestamp(),
+            currency: get_chat_default_currency(&store, &chat_id).await,
+            header: table.header,
+            records: table.records,
+            columns: Vec::new(),
+        };
+
+        // Logging
+        log::info!(
+            "Import Document - User {} uploaded {} rows of a CSV in chat {}",
+            import.sender_id,
+            import.records.len(),
+            import.chat_id
+        );
+
+        ask_import_column(&bot, &msg, &dialogue, messages, import, None, &store).await?;
+        return Ok(());
+    }
+
+    let time_zone = retrieve_time_zone(&store, &chat_id).await;
+    match parse_splitwise_csv(&table, time_zone) {
+        Ok(splitwise) => {
+            let import = ImportParams {
+                chat_id,
+                sender_id: user.id.to_string(),
+                sender_username,
+                members: splitwise.members,
+                usernames: Vec::new(),
+                rows: splitwise.rows,
+                skipped: splitwise.skipped,
+            };
+
+            // Logging
+            log::info!(
+                "Import Document - User {} uploaded {} rows for {} members in chat {}",
+                import.sender_id,
+                import.rows.len(),
+                import.members.len(),
+                import.chat_id
+            );
+
+            ask_import_member(&bot, &msg, &dialogue, messages, import, &store).await?;
+        }
+        Err(err) => {
+            let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
+            repeat_state(dialogue, state, new_message).await?;
+        }
+    }
+    Ok(())
+}
+
+/* Imports payments from a Splitwise export.
+ * Bot receives a string representing the username of a member.
+ */
+pub async fn action_import_member(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    msg: Message,
+    (messages, mut import): (Vec<MessageId>, ImportParams),
+    store: Store,
+) -> HandlerResult {
+    match parse_text_mentions(&msg) {
+        Some(text) => match parse_username(&text) {
+            Ok(username) => {
+                import.usernames.push(Some(username));
+                ask_import_member(&bot, &msg, &dialogue, messages, import, &store).await?;
+            }
+            Err(err) => {
+                let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
+                repeat_state(dialogue, state, new_message).await?;
+            }
+        },
+        None => {
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
+                .await?
+                .id;
+            repeat_state(dialogue, state, new_message).await?;
+        }
+    }
+    Ok(())
+}
+
+/* Imports payments from a Splitwise export.
+ * Bot receives a callback query indicating to skip a member or cancel.
+ */
+pub async fn action_import_member_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    query: CallbackQuery,
+    (messages, mut import): (Vec<MessageId>, ImportParams),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            match button.as_str() {
+                "Cancel" => {
+                    cancel_import(bot, dialogue, state, msg, store).await?;
+                }
+                "Skip" => {
+                    if let Some(member) = import.members.get(import.usernames.len()) {
+                        bot.edit_message_text(
+                            msg.chat.id,
+                            msg.id,
+                            format!("Okay! I'll leave out the payments of {member}."),
+                        )
+                        .await?;
+                    }
+                    import.usernames.push(None);
+                    ask_import_member(&bot, &msg, &dialogue, messages, import, &store).await?;
+                }
+                _ => {
+                    log::error!(
+                        "Import Member Menu - Invalid button for user {} in chat {}: {}",
+                        query.from.id,
+                        msg.chat.id,
+                        button
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/* Imports payments from a Splitwise export.
+ * Bot receives a callback query indicating to confirm or cancel the import.
+ */
+pub async fn action_import_confirm(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    query: CallbackQuery,
+    (messages, import): (Vec<MessageId>, ImportParams),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            match button.as_str() {
+                "Cancel" => {
+                    cancel_import(bot, dialogue, state, msg, store).await?;
+                }
+                "Confirm" => {
+                    call_processor_import(
+                        &bot,
+                        &msg,
+                        &import.chat_id,
+                        &import.sender_id,
+                        &import.sender_username,
+                        convert_import(&import),
+                        &store,
+                    )
+                    .await?;
+
+                    // The message with the import results is never erased
+                    let messages = messages
+                        .into_iter()
+                        .filter(|message| *message != msg.id)
+                        .collect();
+                    complete_import(&bot, dialogue, &import.chat_id, messages, &store).await?;
+                }
+                _ => {
+                    log::error!(
+                        "Import Confirm - Invalid button for user {} in chat {}: {}",
+                        query.from.id,
+                        msg.chat.id,
+                        button
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/* Imports payments from any CSV.
+ * Bot receives a callback query indicating the column of a detail, to skip it, or cancel.
+ */
+pub async fn action_import_column_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    query: CallbackQuery,
+    (messages, mut import): (Vec<MessageId>, CsvImportParams),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            let field = CSV_FIELDS.get(import.columns.len());
+            match button.as_str() {
+                "Cancel" => {
+                    cancel_import(bot, dialogue, state, msg, store).await?;
+                }
+                "Skip" if field.is_some_and(|field| field.is_optional()) => {
+                    import.columns.push(None);
+                    ask_import_column(
+                        &bot,
+                        &msg,
+                        &dialogue,
+                        messages,
+                        import,
+                        Some(msg.id),
+                        &store,
+                    )
+                    .await?;
+                }
+                _ => match parse_column_button(button) {
+                    Some(index) if index < import.header.len() => {
+                        import.columns.push(Some(index));
+                        ask_import_column(
+                            &bot,
+                            &msg,
+                            &dialogue,
+                            messages,
+                            import,
+                            Some(msg.id),
+                            &store,
+                        )
+                        .await?;
+                    }
+                    _ => {
+                        log::error!(
+                            "Import Column Menu - Invalid button for user {} in chat {}: {}",
+                            query.from.id,
+                            msg.chat.id,
+                            button
+                        );
+                    }
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+/* Imports payments from any CSV.
+ * Bot receives a callback query indicating to confirm or cancel the import.
+ */
+pub async fn action_import_csv_confirm(
+    bot: Bot,
+    dialogue: UserDialogue,
+    state: State,
+    query: CallbackQuery,
+    (messages, import): (Vec<MessageId>, CsvImportParams),
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            match button.as_str() {
+                "Cancel" => {
+                    cancel_import(bot, dialogue, state, msg, store).await?;
+                }
+                "Confirm" => {
+                    let time_zone = retrieve_time_zone(&store, &import.chat_id).await;
+                    call_processor_import(
+                        &bot,
+                        &msg,
+                        &import.chat_id,
+                        &import.sender_id,
+                        &import.sender_username,
+                        convert_csv_import(&import, time_zone),
+                        &store,
+                    )
+                    .await?;
+
+                    // The message with the import results is never erased
+                    let messages = messages
+                        .into_iter()
+                        .filter(|message| *message != msg.id)
+                        .collect();
+                    complete_import(&bot, dialogue, &import.chat_id, messages, &store).await?;
+                }
+                _ => {
+                    log::error!(
+                        "Import CSV Confirm - Invalid button for user {} in chat {}: {}",
+                        query.from.id,
+                        msg.chat.id,
+                        button
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,27 +1,41 @@
 // Exported functions
 pub use self::add_payment::{
     action_add_confirm, action_add_creditor, action_add_debt, action_add_debt_selection,
-    action_add_description, action_add_edit, action_add_edit_menu, action_add_payment,
-    action_add_total, block_add_payment, cancel_add_payment, handle_repeated_add_payment,
-    AddDebtsFormat, AddPaymentEdit, AddPaymentParams,
+    action_add_description, action_add_edit, action_add_edit_category, action_add_edit_menu,
+    action_add_payment, action_add_total, block_add_payment, cancel_add_payment,
+    handle_repeated_add_payment, AddDebtsFormat, AddPaymentEdit, AddPaymentParams,
 };
+pub use self::audit::{action_audit, action_audit_menu};
 pub use self::delete_payment::{
     action_delete_payment, action_delete_payment_confirm, block_delete_payment,
     cancel_delete_payment, handle_repeated_delete_payment, no_delete_payment,
 };
 pub use self::edit_payment::{
-    action_edit_payment, action_edit_payment_confirm, action_edit_payment_debts,
-    action_edit_payment_edit, block_edit_payment, cancel_edit_payment,
+    action_edit_payment, action_edit_payment_category, action_edit_payment_confirm,
+    action_edit_payment_debts, action_edit_payment_edit, block_edit_payment, cancel_edit_payment,
     handle_repeated_edit_payment, no_edit_payment, EditPaymentParams,
 };
+pub use self::export::action_export;
 pub use self::general::{
-    action_cancel, action_help, action_start, callback_invalid_message, invalid_state,
+    action_cancel, action_help, action_migrate_chat, action_start, callback_invalid_message,
+    invalid_state, track_user_identity,
+};
+pub use self::history::{
+    action_history, action_payment_history, action_select_payment_history, block_payment_history,
+    cancel_payment_history, handle_repeated_payment_history,
+};
+pub use self::import::{
+    action_import, action_import_column_menu, action_import_confirm, action_import_csv_confirm,
+    action_import_document, action_import_member, action_import_member_menu, block_import,
+    cancel_import, handle_repeated_import, CsvImportParams, ImportParams,
 };
 pub use self::pay_back::{
     action_pay_back, action_pay_back_confirm, action_pay_back_currency,
     action_pay_back_currency_menu, action_pay_back_debts, block_pay_back, cancel_pay_back,
     handle_repeated_pay_back, PayBackParams,
 };
+pub use self::recurring::{action_recurring, action_recurring_menu};
+pub use self::reset::{action_forget_me, action_reset_group, action_reset_group_menu};
 pub use self::settings::{
     action_default_currency_menu, action_settings, action_settings_currency_conversion,
     action_settings_default_currency, action_settings_erase_messages, action_settings_menu,
@@ -29,20 +43,28 @@ pub use self::settings::{
     handle_repeated_settings,
 };
 pub use self::spendings::{action_spendings_menu, action_view_spendings};
+pub use self::trash::{action_trash, action_trash_menu};
 pub use self::view_balances::{action_balances_menu, action_view_balances};
 pub use self::view_payments::{
     action_select_payment_delete, action_select_payment_edit, action_select_payment_number,
     action_view_more, action_view_payments, block_select_payment, cancel_select_payment,
-    handle_repeated_select_payment, Payment,
+    handle_repeated_select_payment, unfold_payment, Payment, PaymentsPage,
 };
 
 // Submodules
 mod add_payment;
+mod audit;
 mod delete_payment;
 mod edit_payment;
+mod export;
 mod general;
+mod history;
+mod import;
 mod pay_back;
+mod recurring;
+mod reset;
 mod settings;
 mod spendings;
+mod trash;
 mod view_balances;
 mod view_payments;
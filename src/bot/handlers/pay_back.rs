@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
@@ -10,32 +11,34 @@ use crate::bot::{
         currency::CURRENCY_DEFAULT,
         messages::{
             CANCEL_ADD_MESSAGE, CURRENCY_INSTRUCTIONS_MESSAGE, NO_TEXT_MESSAGE,
-            PAY_BACK_INSTRUCTIONS_MESSAGE, UNKNOWN_ERROR_MESSAGE,
+            PAY_BACK_INSTRUCTIONS_MESSAGE,
         },
     },
     currency::{get_default_currency, Currency},
     dispatcher::State,
     processor::add_payment,
+    store::Store,
     utils::{
         amounts::parse_debts_payback,
         bot_actions::{
             assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
         },
         format::{
-            display_balance_header, display_balances, display_debts, display_username,
-            get_chat_default_currency, get_currency, make_keyboard, parse_username, use_currency,
+            balance_members, debt_members, display_balance_header, display_balances, display_debts,
+            display_user, get_chat_default_currency, get_currency, get_member_names, make_keyboard,
+            member_from_user, parse_text_mentions, use_currency,
         },
         HandlerResult, UserDialogue,
     },
 };
 
 /* Utilities */
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PayBackParams {
     chat_id: String,
     sender_id: String,
     sender_username: String,
-    datetime: String,
+    timestamp: i64,
     currency: Currency,
     total: i64,
     debts: Vec<(String, i64)>,
@@ -87,27 +90,31 @@ async fn complete_pay_back(
     dialogue: UserDialogue,
     chat_id: &str,
     messages: Vec<MessageId>,
+    store: &Store,
 ) -> HandlerResult {
-    if is_erase_messages(chat_id) {
-        delete_bot_messages(&bot, chat_id, messages).await?;
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
     }
     dialogue.exit().await?;
     Ok(())
 }
 
-fn display_pay_back_entry(payment: &PayBackParams) -> String {
-    let currency_info: String;
-    let actual_currency = use_currency(payment.currency.clone(), &payment.chat_id);
-    if actual_currency.0 == CURRENCY_DEFAULT.0 {
-        currency_info = "".to_string();
+async fn display_pay_back_entry(payment: &PayBackParams, store: &Store) -> String {
+    let actual_currency = use_currency(store, payment.currency.clone(), &payment.chat_id).await;
+    let currency_info = if actual_currency.0 == CURRENCY_DEFAULT.0 {
+        "".to_string()
     } else {
-        currency_info = format!(" in {} ", actual_currency.0);
-    }
+        format!(" in {} ", actual_currency.0)
+    };
 
     format!(
         "You've paid{}:\n{}",
         currency_info,
-        display_debts(&payment.debts, actual_currency.1)
+        display_debts(
+            &payment.debts,
+            actual_currency.1,
+            &get_member_names(store, debt_members(&payment.debts)).await
+        )
     )
 }
 
@@ -119,16 +126,17 @@ async fn display_pay_back_overview(
     dialogue: &UserDialogue,
     mut messages: Vec<MessageId>,
     payment: PayBackParams,
+    store: &Store,
 ) -> HandlerResult {
     let buttons = vec!["Cancel", "Edit", "Confirm"];
     let keyboard = make_keyboard(buttons, Some(2));
 
     let new_message = send_bot_message(
-        &bot,
-        &msg,
+        bot,
+        msg,
         format!(
             "Amazing! Check the repay info?\n\n{}",
-            display_pay_back_entry(&payment)
+            display_pay_back_entry(&payment, store).await
         ),
     )
     .reply_markup(keyboard)
@@ -147,23 +155,28 @@ async fn call_processor_pay_back(
     messages: Vec<MessageId>,
     payment: PayBackParams,
     query: CallbackQuery,
+    store: &Store,
 ) -> HandlerResult {
     if let Some(msg) = query.message {
         let chat_id = msg.chat.id;
         let payment_clone = payment.clone();
-        let payment_overview = display_pay_back_entry(&payment);
-        let description = format!("{} repaid!", display_username(&payment.sender_username));
+        let payment_overview = display_pay_back_entry(&payment, store).await;
+        let description = format!("{} repaid!", display_user(&query.from));
 
         let updated_balances = add_payment(
+            store,
             payment.chat_id,
             payment.sender_username.clone(),
             payment.sender_id,
-            payment.datetime,
+            payment.timestamp,
             &description,
             &payment.sender_username,
             &payment.currency.0,
             payment.total,
             payment.debts,
+            Vec::new(),
+            None,
+            Vec::new(),
         )
         .await;
 
@@ -172,9 +185,7 @@ async fn call_processor_pay_back(
                 send_bot_message(
                     &bot,
                     &msg,
-                    format!(
-                        "🤷 Oops! Something went wrong! I can't add the payment right now. Please try again later!\n\n"
-                    ),
+                    "🤷 Oops! Something went wrong! I can't add the payment right now. Please try again later!\n\n".to_string(),
                 )
                 .await?;
 
@@ -199,8 +210,12 @@ async fn call_processor_pay_back(
                     &msg,
                     format!(
                         "{}{}",
-                        display_balance_header(&chat_id.to_string(), &payment.currency.0),
-                        display_balances(&balances)
+                        display_balance_header(store, &chat_id.to_string(), &payment.currency.0)
+                            .await,
+                        display_balances(
+                            &balances,
+                            &get_member_names(store, balance_members(&balances)).await
+                        )
                     ),
                 )
                 .await?;
@@ -214,7 +229,7 @@ async fn call_processor_pay_back(
                     );
             }
         }
-        complete_pay_back(&bot, dialogue, &chat_id.to_string(), messages).await?;
+        complete_pay_back(&bot, dialogue, &chat_id.to_string(), messages, store).await?;
     }
     Ok(())
 }
@@ -229,8 +244,9 @@ pub async fn handle_repeated_pay_back(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -252,8 +268,9 @@ pub async fn cancel_pay_back(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -264,7 +281,7 @@ pub async fn cancel_pay_back(
         | State::PayBackCurrency { messages }
         | State::PayBackDebts { messages, .. }
         | State::PayBackConfirm { messages, .. } => {
-            complete_pay_back(&bot, dialogue, &msg.chat.id.to_string(), messages).await?;
+            complete_pay_back(&bot, dialogue, &msg.chat.id.to_string(), messages, &store).await?;
         }
         _ => (),
     }
@@ -279,8 +296,9 @@ pub async fn block_pay_back(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -297,8 +315,13 @@ pub async fn block_pay_back(
 /* Adds a pay back entry.
  * Entrypoint to the dialogue sequence.
  */
-pub async fn action_pay_back(bot: Bot, dialogue: UserDialogue, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_pay_back(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -307,7 +330,7 @@ pub async fn action_pay_back(bot: Bot, dialogue: UserDialogue, msg: Message) ->
     let new_message = send_bot_message(
         &bot,
         &msg,
-        format!("Absolutely! What currency do you want to set for this payment? You can also skip this step."),
+        "Absolutely! What currency do you want to set for this payment? You can also skip this step.".to_string(),
         )
         .reply_markup(keyboard)
         .await?.id;
@@ -329,6 +352,7 @@ pub async fn action_pay_back_currency_menu(
     state: State,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -336,7 +360,7 @@ pub async fn action_pay_back_currency_menu(
         match button.as_str() {
             "Cancel" => {
                 if let Some(msg) = query.message {
-                    cancel_pay_back(bot, dialogue, state, msg).await?;
+                    cancel_pay_back(bot, dialogue, state, msg, store).await?;
                 }
             }
             "Skip" => {
@@ -422,7 +446,7 @@ pub async fn action_pay_back_currency(
                         &msg,
                         format!(
                             "{}\n\nIf you're unsure of the currency code, you can always check out my User Guide with {COMMAND_HELP}.",
-                            err.to_string()
+                            err
                         ),
                     )
                     .await?.id;
@@ -431,7 +455,7 @@ pub async fn action_pay_back_currency(
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -449,69 +473,50 @@ pub async fn action_pay_back_debts(
     state: State,
     msg: Message,
     (messages, currency): (Vec<MessageId>, Currency),
+    store: Store,
 ) -> HandlerResult {
     let chat_id = msg.chat.id.to_string();
-    match msg.text() {
+    match parse_text_mentions(&msg) {
         Some(text) => {
             if let Some(user) = msg.from() {
-                if let Some(username) = &user.username {
-                    let username = parse_username(username);
-                    if let Err(err) = &username {
-                        let new_message =
-                            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string())
-                                .await?
-                                .id;
-
-                        // Logging
-                        log::error!(
-                            "Pay Back Debts - Failed to parse username for sender {}: {}",
-                            user.id,
-                            err.to_string()
-                        );
+                let username = member_from_user(user);
 
-                        repeat_state(dialogue, state, new_message).await?;
-                        return Ok(());
-                    }
-                    let username = username?;
-
-                    let actual_currency: Currency;
-                    if currency.0 == CURRENCY_DEFAULT.0 {
-                        actual_currency = get_chat_default_currency(&chat_id);
-                    } else {
-                        actual_currency = currency.clone();
-                    }
+                let actual_currency: Currency = if currency.0 == CURRENCY_DEFAULT.0 {
+                    get_chat_default_currency(&store, &chat_id).await
+                } else {
+                    currency.clone()
+                };
 
-                    let debts = parse_debts_payback(text, actual_currency.clone(), &username);
-                    if let Err(err) = debts {
-                        let new_message = send_bot_message(
-                            &bot,
-                            &msg,
-                            format!("{}\n\n{PAY_BACK_INSTRUCTIONS_MESSAGE}", err.to_string()),
-                        )
-                        .await?
-                        .id;
-
-                        repeat_state(dialogue, state, new_message).await?;
-                        return Ok(());
-                    }
+                let debts = parse_debts_payback(&text, actual_currency.clone(), &username);
+                if let Err(err) = debts {
+                    let new_message = send_bot_message(
+                        &bot,
+                        &msg,
+                        format!("{}\n\n{PAY_BACK_INSTRUCTIONS_MESSAGE}", err),
+                    )
+                    .await?
+                    .id;
 
-                    let debts = debts?;
-                    let total = debts.iter().fold(0, |curr, next| curr + next.1);
-                    let payment = PayBackParams {
-                        chat_id,
-                        sender_id: msg.from().as_ref().unwrap().id.to_string(),
-                        sender_username: username,
-                        datetime: msg.date.to_string(),
-                        currency,
-                        total,
-                        debts,
-                    };
-                    display_pay_back_overview(&bot, &msg, &dialogue, messages, payment).await?;
+                    repeat_state(dialogue, state, new_message).await?;
+                    return Ok(());
                 }
+
+                let debts = debts?;
+                let total = debts.iter().fold(0, |curr, next| curr + next.1);
+                let payment = PayBackParams {
+                    chat_id,
+                    sender_id: user.id.to_string(),
+                    sender_username: username,
+                    timestamp: msg.date.timestamp(),
+                    currency,
+                    total,
+                    debts,
+                };
+                display_pay_back_overview(&bot, &msg, &dialogue, messages, payment, &store).await?;
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -531,6 +536,7 @@ pub async fn action_pay_back_confirm(
     state: State,
     (messages, payment): (Vec<MessageId>, PayBackParams),
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -538,7 +544,7 @@ pub async fn action_pay_back_confirm(
         match button.as_str() {
             "Cancel" => {
                 if let Some(msg) = query.message {
-                    cancel_pay_back(bot, dialogue, state, msg).await?;
+                    cancel_pay_back(bot, dialogue, state, msg, store).await?;
                 }
             }
             "Edit" => {
@@ -548,9 +554,7 @@ pub async fn action_pay_back_confirm(
                     bot.edit_message_text(
                         chat.id,
                         id,
-                        format!(
-                            "Absolutely! What currency do you want to set for this payment? You can also skip this step."
-                            ),
+                        "Absolutely! What currency do you want to set for this payment? You can also skip this step.".to_string(),
                             )
                         .reply_markup(keyboard)
                         .await?;
@@ -560,7 +564,7 @@ pub async fn action_pay_back_confirm(
                 }
             }
             "Confirm" => {
-                call_processor_pay_back(bot, dialogue, messages, payment, query).await?;
+                call_processor_pay_back(bot, dialogue, messages, payment, query, &store).await?;
             }
             _ => {
                 log::error!("Pay Back Confirm - Invalid button for user {} in chat {} with payment {:?}: {}",
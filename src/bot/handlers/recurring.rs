@@ -0,0 +1,223 @@
+use chrono_tz::Tz;
+use teloxide::{prelude::*, types::Message};
+
+use crate::bot::{
+    constants::{commands::COMMAND_ADD_PAYMENT, messages::UNKNOWN_ERROR_MESSAGE},
+    processor::{delete_recurring_payment, view_recurring_payments},
+    redis::{ChatRecurringPayment, UserPayment},
+    store::Store,
+    utils::{
+        bot_actions::{assert_handle_request_limit, send_bot_message},
+        format::{display_payment, get_member_names, make_keyboard, payment_members, MemberNames},
+        schedule::display_schedule,
+        time::{format_timestamp, retrieve_time_zone},
+        HandlerResult, UserDialogue,
+    },
+    State,
+};
+
+use super::unfold_payment;
+
+// Number of recurring payments shown, same as a page of payments
+const RECURRING_DISPLAY_COUNT: usize = 5;
+
+/* Utilities */
+
+// Gets the members displayed with a recurring payment
+fn recurring_members(recurring: &ChatRecurringPayment) -> Vec<&str> {
+    let payment = &recurring.recurring.payment;
+    payment_members(
+        &payment.creditor,
+        &payment.payers,
+        &payment.debts,
+        &payment.items,
+    )
+}
+
+async fn display_recurring_payment(
+    store: &Store,
+    recurring: &ChatRecurringPayment,
+    serial_num: usize,
+    time_zone: Tz,
+    names: &MemberNames,
+) -> String {
+    let payment = unfold_payment(UserPayment {
+        chat_id: recurring.chat_id.clone(),
+        payment_id: recurring.recurring_id.clone(),
+        payment: recurring.recurring.payment.clone(),
+    });
+    format!(
+        "{}Repeats: {}\nNext due: {}\n",
+        display_payment(store, &payment, serial_num, time_zone, names).await,
+        display_schedule(
+            &recurring.recurring.frequency,
+            recurring.recurring.payment.timestamp,
+            recurring.recurring.end,
+            time_zone
+        ),
+        format_timestamp(recurring.recurring.next, time_zone)
+    )
+}
+
+/* Shows the recurring payments of the group, next due first.
+ * Bot presents a button menu to stop any of them.
+ */
+pub async fn action_recurring(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.to_string();
+
+    match view_recurring_payments(&store, &chat_id).await {
+        Ok(recurring) => {
+            if recurring.is_empty() {
+                send_bot_message(
+                    &bot,
+                    &msg,
+                    format!("🔁 There are no recurring payments! Make a payment repeat from the Edit menu when adding it with {COMMAND_ADD_PAYMENT}."),
+                )
+                .await?;
+                dialogue.exit().await?;
+                return Ok(());
+            }
+
+            let recurring: Vec<ChatRecurringPayment> = recurring
+                .into_iter()
+                .take(RECURRING_DISPLAY_COUNT)
+                .collect();
+            let mut buttons: Vec<String> =
+                (1..=recurring.len()).map(|num| num.to_string()).collect();
+            buttons.push("Cancel".to_string());
+            let keyboard = make_keyboard(
+                buttons.iter().map(|option| option.as_str()).collect(),
+                Some(3),
+            );
+
+            let time_zone = retrieve_time_zone(&store, &chat_id).await;
+            let names =
+                get_member_names(&store, recurring.iter().flat_map(recurring_members)).await;
+            let mut formatted_payments = Vec::new();
+            for (index, recurring) in recurring.iter().enumerate() {
+                formatted_payments.push(
+                    display_recurring_payment(&store, recurring, index + 1, time_zone, &names)
+                        .await,
+                );
+            }
+
+            send_bot_message(
+                &bot,
+                &msg,
+                format!(
+                    "🔁 Here are the recurring payments, next due first!\n\n{}\nWhich one should I stop?",
+                    formatted_payments.join("")
+                ),
+            )
+            .reply_markup(keyboard)
+            .await?;
+            dialogue.update(State::RecurringMenu { recurring }).await?;
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "Recurring - Failed to view recurring payments for chat {}: {}",
+                chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/* Stops a recurring payment, keeping the payments it has already added.
+ * Bot receives a callback query from the user, with the recurring payment to stop.
+ */
+pub async fn action_recurring_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    recurring: Vec<ChatRecurringPayment>,
+    query: CallbackQuery,
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            let chat_id = msg.chat.id.to_string();
+            let time_zone = retrieve_time_zone(&store, &chat_id).await;
+
+            if button == "Cancel" {
+                bot.edit_message_text(
+                    msg.chat.id,
+                    msg.id,
+                    "Okay! I left the recurring payments as they are.",
+                )
+                .await?;
+                dialogue.exit().await?;
+                return Ok(());
+            }
+
+            let recurring = match button.parse::<usize>() {
+                Ok(num) if num >= 1 && num <= recurring.len() => recurring[num - 1].clone(),
+                _ => {
+                    log::error!(
+                        "Recurring Menu - Invalid button in chat {}: {}",
+                        chat_id,
+                        button
+                    );
+                    return Ok(());
+                }
+            };
+
+            match delete_recurring_payment(&store, &chat_id, &recurring.recurring_id).await {
+                Ok(()) => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        format!(
+                            "Recurring payment successfully stopped! The payments it already added are kept.\n\n{}",
+                            display_recurring_payment(&store, &recurring, 1, time_zone, &get_member_names(&store, recurring_members(&recurring)).await).await
+                        ),
+                    )
+                    .await?;
+
+                    // Logging
+                    log::info!(
+                        "Recurring Menu - User {} stopped recurring payment {} for chat {}",
+                        query.from.id,
+                        recurring.recurring_id,
+                        chat_id
+                    );
+                }
+                Err(err) => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        "🤷 Oops! Something went wrong! I can't stop the recurring payment right now. It may have been stopped already.",
+                    )
+                    .await?;
+
+                    // Logging
+                    log::error!(
+                        "Recurring Menu - User {} failed to stop recurring payment {} for chat {}: {}",
+                        query.from.id,
+                        recurring.recurring_id,
+                        chat_id,
+                        err.to_string()
+                    );
+                }
+            }
+            dialogue.exit().await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use teloxide::{payloads::SendMessageSetters, prelude::*, types::Message};
+
+use crate::bot::{
+    constants::{
+        commands::{COMMAND_BALANCES, COMMAND_EXPORT, COMMAND_PAY_BACK, COMMAND_RESET_GROUP},
+        messages::UNKNOWN_ERROR_MESSAGE,
+    },
+    processor::{forget_user, reset_chat, ProcessError},
+    store::Store,
+    utils::{
+        bot_actions::{assert_handle_request_limit, is_chat_admin, send_bot_message},
+        format::{make_keyboard, member_from_user},
+        HandlerResult, UserDialogue,
+    },
+    State,
+};
+
+/* Asks to reset all records of the group.
+ * Only group admins can reset, and bot asks for confirmation as it cannot be undone.
+ */
+pub async fn action_reset_group(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+
+    if !is_chat_admin(&bot, &msg, user).await? {
+        send_bot_message(
+            &bot,
+            &msg,
+            format!("🚫 Sorry, only group admins can {COMMAND_RESET_GROUP}!"),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let keyboard = make_keyboard(vec!["Reset", "Cancel"], Some(2));
+    send_bot_message(
+        &bot,
+        &msg,
+        format!("⚠️ This deletes all payments, balances, spendings and settings of this group for everyone, and cannot be undone!\n\nYou may want to {COMMAND_EXPORT} the records first. Should I reset the group?"),
+    )
+    .reply_markup(keyboard)
+    .await?;
+    dialogue.update(State::ResetGroupMenu).await?;
+
+    // Logging
+    log::info!(
+        "Reset Group - User {} asked to reset chat {}",
+        user.id,
+        msg.chat.id
+    );
+
+    Ok(())
+}
+
+/* Resets all records of the group, if confirmed.
+ * Bot receives a callback query from the user.
+ */
+pub async fn action_reset_group_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    query: CallbackQuery,
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            let chat_id = msg.chat.id.to_string();
+            match button.as_str() {
+                "Reset" => {
+                    if !is_chat_admin(&bot, &msg, &query.from).await? {
+                        send_bot_message(
+                            &bot,
+                            &msg,
+                            "🚫 Sorry, only group admins can reset the group!".to_string(),
+                        )
+                        .await?;
+                        return Ok(());
+                    }
+
+                    match reset_chat(&store, &chat_id).await {
+                        Ok(()) => {
+                            bot.edit_message_text(
+                                msg.chat.id,
+                                msg.id,
+                                "🧹 I've reset the group! All records are gone, and my settings are back to default.",
+                            )
+                            .await?;
+
+                            // Logging
+                            log::info!(
+                                "Reset Group Menu - User {} reset chat {}",
+                                query.from.id,
+                                chat_id
+                            );
+                        }
+                        Err(err) => {
+                            bot.edit_message_text(msg.chat.id, msg.id, UNKNOWN_ERROR_MESSAGE)
+                                .await?;
+
+                            // Logging
+                            log::error!(
+                                "Reset Group Menu - User {} failed to reset chat {}: {}",
+                                query.from.id,
+                                chat_id,
+                                err.to_string()
+                            );
+                        }
+                    }
+                    dialogue.exit().await?;
+                }
+                "Cancel" => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        "Okay! I left the records as they are.",
+                    )
+                    .await?;
+                    dialogue.exit().await?;
+                }
+                _ => {
+                    log::error!(
+                        "Reset Group Menu - Invalid button in chat {} by user {}: {}",
+                        chat_id,
+                        query.from.id,
+                        button
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/* Forgets the username of the user in every group, once all their balances are settled.
+ * Payments which include the user are kept, as they belong to the whole group.
+ */
+pub async fn action_forget_me(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let user = match msg.from() {
+        Some(user) => user,
+        None => return Ok(()),
+    };
+    let username = member_from_user(user);
+
+    match forget_user(&store, &username).await {
+        Ok(0) => {
+            send_bot_message(
+                &bot,
+                &msg,
+                "🙈 I don't remember you from any group, so there's nothing to forget!".to_string(),
+            )
+            .await?;
+        }
+        Ok(count) => {
+            let groups = if count == 1 { "group" } else { "groups" };
+            send_bot_message(
+                &bot,
+                &msg,
+                format!("👋 Done! I've forgotten you in {count} {groups}. Payments which include you are kept for everyone else, and I'll remember you again if you use me or are added to a payment."),
+            )
+            .await?;
+
+            // Logging
+            log::info!(
+                "Forget Me - User {} was forgotten in {} chats",
+                user.id,
+                count
+            );
+        }
+        Err(ProcessError::UnsettledBalancesError()) => {
+            send_bot_message(
+                &bot,
+                &msg,
+                format!("💸 You still have unsettled balances! Check your {COMMAND_BALANCES} and {COMMAND_PAY_BACK} in every group first, then I can forget you."),
+            )
+            .await?;
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "Forget Me - User {} failed to be forgotten: {}",
+                user.id,
+                err.to_string()
+            );
+        }
+    }
+    dialogue.exit().await?;
+
+    Ok(())
+}
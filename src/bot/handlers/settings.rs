@@ -17,6 +17,7 @@ use crate::bot::{
     },
     dispatcher::State,
     processor::{get_chat_setting, set_chat_setting, update_chat_default_currency, ChatSetting},
+    store::Store,
     utils::{
         bot_actions::{
             assert_handle_request_limit, delete_bot_messages, is_erase_messages, send_bot_message,
@@ -85,9 +86,10 @@ async fn complete_settings(
     dialogue: UserDialogue,
     chat_id: &str,
     messages: Vec<MessageId>,
+    store: &Store,
 ) -> HandlerResult {
-    if is_erase_messages(chat_id) {
-        delete_bot_messages(&bot, chat_id, messages).await?;
+    if is_erase_messages(store, chat_id).await {
+        delete_bot_messages(bot, chat_id, messages).await?;
     }
     dialogue.exit().await?;
     Ok(())
@@ -117,7 +119,7 @@ async fn display_settings_menu(
             dialogue.update(State::SettingsMenu { messages }).await?;
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, message)
+            let new_message = send_bot_message(bot, msg, message)
                 .parse_mode(ParseMode::MarkdownV2)
                 .reply_markup(keyboard)
                 .await?
@@ -137,8 +139,9 @@ pub async fn handle_repeated_settings(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -161,14 +164,13 @@ pub async fn cancel_settings(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
-    send_bot_message(&bot, &msg, CANCEL_SETTINGS_MESSAGE.to_string())
-        .await?
-        .id;
+    send_bot_message(&bot, &msg, CANCEL_SETTINGS_MESSAGE.to_string()).await?;
 
     match state {
         State::SettingsMenu { messages }
@@ -177,7 +179,7 @@ pub async fn cancel_settings(
         | State::SettingsDefaultCurrencyMenu { messages }
         | State::SettingsDefaultCurrency { messages }
         | State::SettingsCurrencyConversion { messages } => {
-            complete_settings(&bot, dialogue, &msg.chat.id.to_string(), messages).await?;
+            complete_settings(&bot, dialogue, &msg.chat.id.to_string(), messages, &store).await?;
         }
         _ => (),
     }
@@ -193,8 +195,9 @@ pub async fn block_settings(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -212,8 +215,13 @@ pub async fn block_settings(
 /* Allows user to view and edit chat settings.
  * Bot presents a button menu of setting options.
  */
-pub async fn action_settings(bot: Bot, dialogue: UserDialogue, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_settings(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -230,6 +238,7 @@ pub async fn action_settings_menu(
     state: State,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -237,7 +246,7 @@ pub async fn action_settings_menu(
             let chat_id = msg.chat.id.to_string();
             match button.as_str() {
                 "🕔" => {
-                    let time_zone = retrieve_time_zone(&chat_id);
+                    let time_zone = retrieve_time_zone(&store, &chat_id).await;
                     let buttons = vec!["Back", "Edit"];
                     let keyboard = make_keyboard(buttons, Some(2));
                     bot.edit_message_text(
@@ -255,12 +264,14 @@ pub async fn action_settings_menu(
                         .await?;
                 }
                 "💵" => {
-                    let setting = get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None))?;
+                    let setting =
+                        get_chat_setting(&store, &chat_id, ChatSetting::DefaultCurrency(None))
+                            .await?;
                     if let ChatSetting::DefaultCurrency(Some(currency)) = setting {
                         let currency_info: String;
                         let buttons: Vec<&str>;
                         if currency == CURRENCY_DEFAULT.0 {
-                            currency_info = format!("💵 Default Currency is NOT set.");
+                            currency_info = "💵 Default Currency is NOT set.".to_string();
                             buttons = vec!["Back", "Edit"];
                         } else {
                             currency_info = format!("💵 Default Currency: {}", currency);
@@ -283,7 +294,8 @@ pub async fn action_settings_menu(
                 }
                 "↔️" => {
                     let setting =
-                        get_chat_setting(&chat_id, ChatSetting::CurrencyConversion(None))?;
+                        get_chat_setting(&store, &chat_id, ChatSetting::CurrencyConversion(None))
+                            .await?;
                     if let ChatSetting::CurrencyConversion(Some(convert)) = setting {
                         let status: &str;
                         let prompt: &str;
@@ -293,8 +305,12 @@ pub async fn action_settings_menu(
                             buttons = vec!["Back", "Turn Off"];
                             prompt = "Do you wish to turn off currency conversion for this chat?";
                         } else {
-                            let currency =
-                                get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None))?;
+                            let currency = get_chat_setting(
+                                &store,
+                                &chat_id,
+                                ChatSetting::DefaultCurrency(None),
+                            )
+                            .await?;
                             if let ChatSetting::DefaultCurrency(Some(currency)) = currency {
                                 if currency == CURRENCY_DEFAULT.0 {
                                     buttons = vec!["Back"];
@@ -327,7 +343,9 @@ pub async fn action_settings_menu(
                     }
                 }
                 "🚮" => {
-                    let setting = get_chat_setting(&chat_id, ChatSetting::EraseMessages(None))?;
+                    let setting =
+                        get_chat_setting(&store, &chat_id, ChatSetting::EraseMessages(None))
+                            .await?;
                     if let ChatSetting::EraseMessages(Some(erase)) = setting {
                         let status: &str;
                         let prompt: &str;
@@ -357,7 +375,7 @@ pub async fn action_settings_menu(
                     }
                 }
                 "Cancel" => {
-                    cancel_settings(bot, dialogue, state, msg).await?;
+                    cancel_settings(bot, dialogue, state, msg, store).await?;
                 }
                 _ => {
                     if let Some(user) = msg.from() {
@@ -383,6 +401,7 @@ pub async fn action_time_zone_menu(
     dialogue: UserDialogue,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -393,7 +412,7 @@ pub async fn action_time_zone_menu(
                     display_settings_menu(&bot, &dialogue, &msg, Some(msg.id), messages).await?;
                 }
                 "Edit" => {
-                    let time_zone = retrieve_time_zone(&chat_id.to_string());
+                    let time_zone = retrieve_time_zone(&store, &chat_id.to_string()).await;
                     bot.edit_message_text(
                         msg.chat.id,
                         msg.id,
@@ -432,6 +451,7 @@ pub async fn action_settings_time_zone(
     state: State,
     msg: Message,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     let chat_id = msg.chat.id.to_string();
     match msg.text() {
@@ -440,7 +460,7 @@ pub async fn action_settings_time_zone(
             match time_zone {
                 Ok(time_zone) => {
                     let setting = ChatSetting::TimeZone(Some(text.to_string()));
-                    let process = set_chat_setting(&chat_id, setting).await;
+                    let process = set_chat_setting(&store, &chat_id, setting).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -468,7 +488,7 @@ pub async fn action_settings_time_zone(
                             );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 Err(err) => {
                     let new_message = send_bot_message(&bot, &msg, err.to_string()).await?.id;
@@ -477,7 +497,7 @@ pub async fn action_settings_time_zone(
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -494,6 +514,7 @@ pub async fn action_default_currency_menu(
     dialogue: UserDialogue,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -501,13 +522,14 @@ pub async fn action_default_currency_menu(
             let chat_id = msg.chat.id.to_string();
             match button.as_str() {
                 "Disable" => {
-                    let process = update_chat_default_currency(&chat_id, CURRENCY_DEFAULT.0).await;
+                    let process =
+                        update_chat_default_currency(&store, &chat_id, CURRENCY_DEFAULT.0).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
                                 &bot,
                                 &msg,
-                                format!("💵 Default Currency is disabled"),
+                                "💵 Default Currency is disabled".to_string(),
                             )
                             .await?;
 
@@ -528,7 +550,7 @@ pub async fn action_default_currency_menu(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
 
                     // Logging
                     log::info!(
@@ -537,14 +559,15 @@ pub async fn action_default_currency_menu(
                     );
                 }
                 "Edit" => {
-                    let setting = get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None))?;
+                    let setting =
+                        get_chat_setting(&store, &chat_id, ChatSetting::DefaultCurrency(None))
+                            .await?;
                     if let ChatSetting::DefaultCurrency(Some(currency)) = setting {
-                        let currency_info: String;
-                        if currency == CURRENCY_DEFAULT.0 {
-                            currency_info = format!("💵 Default Currency is NOT set.");
+                        let currency_info = if currency == CURRENCY_DEFAULT.0 {
+                            "💵 Default Currency is NOT set.".to_string()
                         } else {
-                            currency_info = format!("💵 Default Currency: {}", currency);
-                        }
+                            format!("💵 Default Currency: {}", currency)
+                        };
 
                         bot.edit_message_text(
                             chat_id,
@@ -586,6 +609,7 @@ pub async fn action_settings_default_currency(
     state: State,
     msg: Message,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     let chat_id = msg.chat.id.to_string();
     match msg.text() {
@@ -593,7 +617,7 @@ pub async fn action_settings_default_currency(
             let currency = get_currency(text);
             match currency {
                 Ok(currency) => {
-                    let process = update_chat_default_currency(&chat_id, &currency.0).await;
+                    let process = update_chat_default_currency(&store, &chat_id, &currency.0).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -621,7 +645,7 @@ pub async fn action_settings_default_currency(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 Err(err) => {
                     let new_message = send_bot_message(
@@ -636,7 +660,7 @@ pub async fn action_settings_default_currency(
             }
         }
         None => {
-            let new_message = send_bot_message(&bot, &msg, format!("{NO_TEXT_MESSAGE}"))
+            let new_message = send_bot_message(&bot, &msg, NO_TEXT_MESSAGE.to_string())
                 .await?
                 .id;
             repeat_state(dialogue, state, new_message).await?;
@@ -653,6 +677,7 @@ pub async fn action_settings_currency_conversion(
     dialogue: UserDialogue,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -664,7 +689,7 @@ pub async fn action_settings_currency_conversion(
                 }
                 "Turn On" => {
                     let setting = ChatSetting::CurrencyConversion(Some(true));
-                    let process = set_chat_setting(&chat_id, setting).await;
+                    let process = set_chat_setting(&store, &chat_id, setting).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -691,11 +716,11 @@ pub async fn action_settings_currency_conversion(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 "Turn Off" => {
                     let setting = ChatSetting::CurrencyConversion(Some(false));
-                    let process = set_chat_setting(&chat_id, setting).await;
+                    let process = set_chat_setting(&store, &chat_id, setting).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -722,7 +747,7 @@ pub async fn action_settings_currency_conversion(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 _ => {
                     if let Some(user) = msg.from() {
@@ -748,6 +773,7 @@ pub async fn action_settings_erase_messages(
     dialogue: UserDialogue,
     query: CallbackQuery,
     messages: Vec<MessageId>,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -759,7 +785,7 @@ pub async fn action_settings_erase_messages(
                 }
                 "Turn On" => {
                     let setting = ChatSetting::EraseMessages(Some(true));
-                    let process = set_chat_setting(&chat_id, setting).await;
+                    let process = set_chat_setting(&store, &chat_id, setting).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -786,11 +812,11 @@ pub async fn action_settings_erase_messages(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 "Turn Off" => {
                     let setting = ChatSetting::EraseMessages(Some(false));
-                    let process = set_chat_setting(&chat_id, setting).await;
+                    let process = set_chat_setting(&store, &chat_id, setting).await;
                     match process {
                         Ok(_) => {
                             send_bot_message(
@@ -817,7 +843,7 @@ pub async fn action_settings_erase_messages(
                                 );
                         }
                     }
-                    complete_settings(&bot, dialogue, &chat_id, messages).await?;
+                    complete_settings(&bot, dialogue, &chat_id, messages, &store).await?;
                 }
                 _ => {
                     if let Some(user) = msg.from() {
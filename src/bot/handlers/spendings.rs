@@ -10,11 +10,16 @@ use crate::bot::{
     },
     currency::Currency,
     processor::{
-        get_chat_setting, retrieve_spending_data, ChatSetting, SpendingData, UserSpending,
+        get_chat_setting, retrieve_spending_data, CategorySpending, ChatSetting, SpendingData,
+        UserSpending,
     },
+    store::Store,
     utils::{
         bot_actions::{assert_handle_request_limit, process_valid_currencies, send_bot_message},
-        format::{display_amount, display_username, get_currency, make_keyboard},
+        format::{
+            display_amount, display_username, get_currency, get_member_names, make_keyboard,
+            MemberNames,
+        },
         HandlerResult, StatementOption, UserDialogue,
     },
     State,
@@ -22,18 +27,32 @@ use crate::bot::{
 
 /* Utilities */
 
-fn display_individual_spending(spending: UserSpending, currency: Currency) -> String {
+fn display_individual_spending(
+    spending: UserSpending,
+    currency: Currency,
+    names: &MemberNames,
+) -> String {
     format!(
         "{}\n    Total Spent: {}\n    Total Paid For: {}\n",
-        display_username(&spending.username),
+        display_username(&spending.username, names),
         display_amount(spending.spending, currency.1),
         display_amount(spending.paid, currency.1)
     )
 }
 
-fn display_spendings(spending_data: &SpendingData) -> String {
+fn display_category_spending(category_spending: CategorySpending, currency: Currency) -> String {
+    format!(
+        "    {}: {}\n",
+        category_spending
+            .category
+            .unwrap_or("Uncategorized".to_string()),
+        display_amount(category_spending.spending, currency.1)
+    )
+}
+
+fn display_spendings(spending_data: &SpendingData, names: &MemberNames) -> String {
     if spending_data.group_spending == 0 {
-        return format!("Total Group Spending: 0\n");
+        return "Total Group Spending: 0\n".to_string();
     }
 
     let currency = match get_currency(&spending_data.currency) {
@@ -47,16 +66,35 @@ fn display_spendings(spending_data: &SpendingData) -> String {
         individual_spendings.push_str(&display_individual_spending(
             spending.clone(),
             currency.clone(),
+            names,
         ));
     }
 
+    // Breakdown is only shown once the group has categorized some payment
+    let mut category_spendings = String::new();
+    if spending_data
+        .category_spendings
+        .iter()
+        .any(|category_spending| category_spending.category.is_some())
+    {
+        category_spendings.push_str("\nBy Category:\n");
+        for category_spending in &spending_data.category_spendings {
+            category_spendings.push_str(&display_category_spending(
+                category_spending.clone(),
+                currency.clone(),
+            ));
+        }
+    }
+
     format!(
-        "Total Group Spending: {}\n\n{}",
+        "Total Group Spending: {}\n\n{}{}",
         display_amount(spending_data.group_spending, currency.1),
-        individual_spendings
+        individual_spendings,
+        category_spendings
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_spendings_with_option(
     bot: Bot,
     dialogue: UserDialogue,
@@ -65,29 +103,33 @@ async fn handle_spendings_with_option(
     sender_id: String,
     mut option: StatementOption,
     id: Option<MessageId>,
+    store: &Store,
 ) -> HandlerResult {
-    let spending_data = retrieve_spending_data(&chat_id, option.clone()).await;
+    let spending_data = retrieve_spending_data(store, &chat_id, option.clone()).await;
 
     match spending_data {
         Ok(mut spending_data) => {
             let default_currency =
-                match get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None)) {
+                match get_chat_setting(store, &chat_id, ChatSetting::DefaultCurrency(None)).await {
                     Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
                     _ => CURRENCY_DEFAULT.0.to_string(),
                 };
 
             let mut valid_currencies = process_valid_currencies(
+                store,
                 &chat_id,
                 &sender_id,
                 option.clone(),
                 default_currency.clone(),
-            );
+            )
+            .await;
 
             // If no default currency, NIL has no balances, but other currencies do
-            if spending_data.group_spending == 0 && valid_currencies.len() > 0 {
+            if spending_data.group_spending == 0 && !valid_currencies.is_empty() {
                 let currency = valid_currencies.first().unwrap().clone();
                 option = StatementOption::Currency(currency.clone());
-                spending_data = match retrieve_spending_data(&chat_id, option.clone()).await {
+                spending_data = match retrieve_spending_data(store, &chat_id, option.clone()).await
+                {
                     Ok(new_data) => {
                         valid_currencies.retain(|curr| curr != &currency);
                         new_data
@@ -101,19 +143,27 @@ async fn handle_spendings_with_option(
                 .map(|x| x.as_str())
                 .collect::<Vec<&str>>();
 
-            let has_buttons = valid_currencies.len() > 0;
+            let has_buttons = !valid_currencies.is_empty();
             let keyboard = make_keyboard(ref_valid_currencies, Some(2));
+            let names = get_member_names(
+                store,
+                spending_data
+                    .user_spendings
+                    .iter()
+                    .map(|spending| spending.username.as_str()),
+            )
+            .await;
 
             let header = if let StatementOption::Currency(curr) = option {
                 if curr == CURRENCY_DEFAULT.0 {
-                    format!("🔥 Total spendings!")
+                    "🔥 Total spendings!".to_string()
                 } else {
                     format!("🔥 Total spendings for {curr}!")
                 }
             } else if has_buttons {
                 format!("🔥 Total spendings, converted to {default_currency}!")
             } else {
-                format!("🔥 Total spendings!")
+                "🔥 Total spendings!".to_string()
             };
 
             match id {
@@ -124,7 +174,7 @@ async fn handle_spendings_with_option(
                         format!(
                             "{}\n\n{}\n{}",
                             header,
-                            display_spendings(&spending_data),
+                            display_spendings(&spending_data, &names),
                             if has_buttons {
                                 STATEMENT_INSTRUCTIONS_MESSAGE
                             } else {
@@ -142,7 +192,7 @@ async fn handle_spendings_with_option(
                         format!(
                             "{}\n\n{}\n{}",
                             header,
-                            display_spendings(&spending_data),
+                            display_spendings(&spending_data, &names),
                             if has_buttons {
                                 STATEMENT_INSTRUCTIONS_MESSAGE
                             } else {
@@ -160,7 +210,7 @@ async fn handle_spendings_with_option(
                 "View Spendings - User {} viewed spendings for group {}: {}",
                 sender_id,
                 chat_id,
-                display_spendings(&spending_data)
+                display_spendings(&spending_data, &names)
             );
         }
         Err(err) => {
@@ -191,21 +241,24 @@ pub async fn action_view_spendings(
     bot: Bot,
     dialogue: UserDialogue,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     let chat_id = msg.chat.id.to_string();
     let sender_id = msg.from().as_ref().unwrap().id.to_string();
-    let is_convert = match get_chat_setting(&chat_id, ChatSetting::CurrencyConversion(None)) {
-        Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
-        _ => false,
-    };
-    let default_currency = match get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None)) {
-        Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
-        _ => "NIL".to_string(),
-    };
+    let is_convert =
+        match get_chat_setting(&store, &chat_id, ChatSetting::CurrencyConversion(None)).await {
+            Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
+            _ => false,
+        };
+    let default_currency =
+        match get_chat_setting(&store, &chat_id, ChatSetting::DefaultCurrency(None)).await {
+            Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
+            _ => "NIL".to_string(),
+        };
 
     let option = if is_convert {
         StatementOption::ConvertCurrency
@@ -213,7 +266,8 @@ pub async fn action_view_spendings(
         StatementOption::Currency(default_currency.clone())
     };
 
-    handle_spendings_with_option(bot, dialogue, msg, chat_id, sender_id, option, None).await?;
+    handle_spendings_with_option(bot, dialogue, msg, chat_id, sender_id, option, None, &store)
+        .await?;
 
     Ok(())
 }
@@ -225,6 +279,7 @@ pub async fn action_spendings_menu(
     bot: Bot,
     dialogue: UserDialogue,
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -244,6 +299,7 @@ pub async fn action_spendings_menu(
                         sender_id,
                         option,
                         Some(id),
+                        &store,
                     )
                     .await?;
                 }
@@ -257,6 +313,7 @@ pub async fn action_spendings_menu(
                         sender_id,
                         option,
                         Some(id),
+                        &store,
                     )
                     .await?;
                 }
@@ -270,6 +327,7 @@ pub async fn action_spendings_menu(
                         sender_id,
                         option,
                         Some(id),
+                        &store,
                     )
                     .await?;
                 }
@@ -0,0 +1,248 @@
+use teloxide::{prelude::*, types::Message};
+
+use crate::bot::{
+    constants::{commands::COMMAND_DELETE_PAYMENT, messages::UNKNOWN_ERROR_MESSAGE},
+    processor::{get_trash_retention_days, restore_payment, view_trash},
+    redis::{TrashedPayment, UserPayment},
+    store::Store,
+    utils::{
+        bot_actions::{assert_handle_request_limit, send_bot_message},
+        format::{
+            balance_members, display_balance_header, display_balances, display_payment,
+            get_member_names, make_keyboard, member_from_user, payment_members,
+        },
+        time::{format_timestamp, retrieve_time_zone},
+        HandlerResult, UserDialogue,
+    },
+    State,
+};
+
+use super::{unfold_payment, Payment};
+
+// Number of latest deleted payments shown, same as a page of payments
+const TRASH_DISPLAY_COUNT: usize = 5;
+
+/* Utilities */
+
+async fn display_trash(store: &Store, chat_id: &str, trash: &[TrashedPayment]) -> String {
+    let time_zone = retrieve_time_zone(store, chat_id).await;
+    let names = get_member_names(
+        store,
+        trash.iter().flat_map(|trashed| {
+            let payment = &trashed.payment;
+            payment_members(
+                &payment.creditor,
+                &payment.payers,
+                &payment.debts,
+                &payment.items,
+            )
+        }),
+    )
+    .await;
+
+    let mut formatted_payments = Vec::new();
+    for (index, trashed) in trash.iter().enumerate() {
+        let payment = unfold_payment(UserPayment {
+            chat_id: chat_id.to_string(),
+            payment_id: trashed.payment_id.clone(),
+            payment: trashed.payment.clone(),
+        });
+        formatted_payments.push(format!(
+            "{}Deleted by {} on {}\n",
+            display_payment(store, &payment, index + 1, time_zone, &names).await,
+            trashed.deleted_by,
+            format_timestamp(trashed.deleted_at, time_zone)
+        ));
+    }
+
+    formatted_payments.join("")
+}
+
+/* Shows the latest deleted payments of the group.
+ * Bot presents a button menu to restore any of them.
+ */
+pub async fn action_trash(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
+        return Ok(());
+    }
+
+    let chat_id = msg.chat.id.to_string();
+    let retention_days = get_trash_retention_days();
+
+    match view_trash(&store, &chat_id, msg.date.timestamp()).await {
+        Ok(trash) => {
+            if trash.is_empty() {
+                send_bot_message(
+                    &bot,
+                    &msg,
+                    format!("🗑 The trash is empty! Payments from {COMMAND_DELETE_PAYMENT} stay here for {retention_days} days."),
+                )
+                .await?;
+                dialogue.exit().await?;
+                return Ok(());
+            }
+
+            let trash: Vec<TrashedPayment> = trash.into_iter().take(TRASH_DISPLAY_COUNT).collect();
+            let mut buttons: Vec<String> = (1..=trash.len()).map(|num| num.to_string()).collect();
+            buttons.push("Cancel".to_string());
+            let keyboard = make_keyboard(
+                buttons.iter().map(|option| option.as_str()).collect(),
+                Some(3),
+            );
+
+            send_bot_message(
+                &bot,
+                &msg,
+                format!(
+                    "🗑 Here are the latest deleted payments, kept for {retention_days} days!\n\n{}\nWhich one should I restore?",
+                    display_trash(&store, &chat_id, &trash).await
+                ),
+            )
+            .reply_markup(keyboard)
+            .await?;
+
+            let payments: Vec<Payment> = trash
+                .into_iter()
+                .map(|trashed| {
+                    unfold_payment(UserPayment {
+                        chat_id: chat_id.clone(),
+                        payment_id: trashed.payment_id,
+                        payment: trashed.payment,
+                    })
+                })
+                .collect();
+            dialogue.update(State::TrashMenu { payments }).await?;
+        }
+        Err(err) => {
+            send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
+
+            // Logging
+            log::error!(
+                "Trash - Failed to view trash for chat {}: {}",
+                chat_id,
+                err.to_string()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/* Restores a deleted payment.
+ * Bot receives a callback query from the user, with the payment to restore.
+ */
+pub async fn action_trash_menu(
+    bot: Bot,
+    dialogue: UserDialogue,
+    payments: Vec<Payment>,
+    query: CallbackQuery,
+    store: Store,
+) -> HandlerResult {
+    if let Some(button) = &query.data {
+        bot.answer_callback_query(query.id.to_string()).await?;
+
+        if let Some(msg) = query.message {
+            let chat_id = msg.chat.id.to_string();
+            let time_zone = retrieve_time_zone(&store, &chat_id).await;
+
+            if button == "Cancel" {
+                bot.edit_message_text(msg.chat.id, msg.id, "Okay! I left the trash as it is.")
+                    .await?;
+                dialogue.exit().await?;
+                return Ok(());
+            }
+
+            let payment = match button.parse::<usize>() {
+                Ok(num) if num >= 1 && num <= payments.len() => payments[num - 1].clone(),
+                _ => {
+                    log::error!(
+                        "Trash Menu - Invalid button in chat {}: {}",
+                        chat_id,
+                        button
+                    );
+                    return Ok(());
+                }
+            };
+
+            let sender_id = query.from.id.to_string();
+            let sender_username = member_from_user(&query.from);
+            let names = get_member_names(
+                &store,
+                payment_members(
+                    &payment.creditor,
+                    &payment.payers,
+                    &payment.debts,
+                    &payment.items,
+                ),
+            )
+            .await;
+            match restore_payment(
+                &store,
+                &chat_id,
+                &payment.payment_id,
+                &sender_id,
+                &sender_username,
+            )
+            .await
+            {
+                Ok(balances) => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        format!(
+                            "Expense successfully restored!\n\n{}",
+                            display_payment(&store, &payment, 1, time_zone, &names).await
+                        ),
+                    )
+                    .await?;
+                    send_bot_message(
+                        &bot,
+                        &msg,
+                        format!(
+                            "{}{}",
+                            display_balance_header(&store, &chat_id, &payment.currency.0).await,
+                            display_balances(
+                                &balances,
+                                &get_member_names(&store, balance_members(&balances)).await
+                            ),
+                        ),
+                    )
+                    .await?;
+
+                    // Logging
+                    log::info!(
+                        "Trash Menu - User {} restored payment for chat {} with payment {}",
+                        query.from.id,
+                        chat_id,
+                        display_payment(&store, &payment, 1, time_zone, &names).await
+                    );
+                }
+                Err(err) => {
+                    bot.edit_message_text(
+                        msg.chat.id,
+                        msg.id,
+                        "🤷 Oops! Something went wrong! I can't restore the payment right now. It may have been restored already.",
+                    )
+                    .await?;
+
+                    // Logging
+                    log::error!(
+                        "Trash Menu - User {} failed to restore payment for chat {} with payment {}: {}",
+                        query.from.id,
+                        chat_id,
+                        display_payment(&store, &payment, 1, time_zone, &names).await,
+                        err.to_string()
+                    );
+                }
+            }
+            dialogue.exit().await?;
+        }
+    }
+
+    Ok(())
+}
@@ -9,9 +9,10 @@ use crate::bot::{
         messages::{STATEMENT_INSTRUCTIONS_MESSAGE, UNKNOWN_ERROR_MESSAGE},
     },
     processor::{get_chat_setting, retrieve_debts, ChatSetting},
+    store::Store,
     utils::{
         bot_actions::{assert_handle_request_limit, process_valid_currencies, send_bot_message},
-        format::{display_balances, make_keyboard},
+        format::{balance_members, display_balances, get_member_names, make_keyboard},
         HandlerResult, StatementOption, UserDialogue,
     },
     State,
@@ -26,30 +27,33 @@ async fn handle_balances_with_option(
     sender_id: String,
     mut option: StatementOption,
     id: Option<MessageId>,
+    store: &Store,
 ) -> HandlerResult {
     let chat_id = msg.chat.id.to_string();
-    let balances_data = retrieve_debts(&chat_id, option.clone()).await;
+    let balances_data = retrieve_debts(store, &chat_id, option.clone()).await;
 
     match balances_data {
         Ok(mut balances_data) => {
             let default_currency =
-                match get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None)) {
+                match get_chat_setting(store, &chat_id, ChatSetting::DefaultCurrency(None)).await {
                     Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
                     _ => CURRENCY_DEFAULT.0.to_string(),
                 };
 
             let mut valid_currencies = process_valid_currencies(
+                store,
                 &chat_id,
                 &sender_id,
                 option.clone(),
                 default_currency.clone(),
-            );
+            )
+            .await;
 
             // If no default currency, NIL has no balances, but other currencies do
-            if balances_data.len() == 0 && valid_currencies.len() > 0 {
+            if balances_data.is_empty() && !valid_currencies.is_empty() {
                 let currency = valid_currencies.first().unwrap().clone();
                 option = StatementOption::Currency(currency.clone());
-                balances_data = match retrieve_debts(&chat_id, option.clone()).await {
+                balances_data = match retrieve_debts(store, &chat_id, option.clone()).await {
                     Ok(new_data) => {
                         valid_currencies.retain(|curr| curr != &currency);
                         new_data
@@ -63,19 +67,20 @@ async fn handle_balances_with_option(
                 .map(|x| x.as_str())
                 .collect::<Vec<&str>>();
 
-            let has_buttons = valid_currencies.len() > 0;
+            let has_buttons = !valid_currencies.is_empty();
             let keyboard = make_keyboard(ref_valid_currencies, Some(2));
+            let names = get_member_names(store, balance_members(&balances_data)).await;
 
             let header = if let StatementOption::Currency(curr) = option {
                 if curr == CURRENCY_DEFAULT.0 {
-                    format!("📊 Current balances!")
+                    "📊 Current balances!".to_string()
                 } else {
                     format!("📊 Current {curr} balances!")
                 }
             } else if has_buttons {
                 format!("📊 Current balances, converted to {default_currency}!")
             } else {
-                format!("📊 Current balances!")
+                "📊 Current balances!".to_string()
             };
 
             match id {
@@ -86,7 +91,7 @@ async fn handle_balances_with_option(
                         format!(
                             "{}\n\n{}\n{}",
                             header,
-                            display_balances(&balances_data),
+                            display_balances(&balances_data, &names),
                             if has_buttons {
                                 STATEMENT_INSTRUCTIONS_MESSAGE
                             } else {
@@ -104,7 +109,7 @@ async fn handle_balances_with_option(
                         format!(
                             "{}\n\n{}\n{}",
                             header,
-                            display_balances(&balances_data),
+                            display_balances(&balances_data, &names),
                             if has_buttons {
                                 STATEMENT_INSTRUCTIONS_MESSAGE
                             } else {
@@ -122,7 +127,7 @@ async fn handle_balances_with_option(
                 "View Balances - User {} viewed balances for group {}: {}",
                 sender_id,
                 chat_id,
-                display_balances(&balances_data)
+                display_balances(&balances_data, &names)
             );
         }
         Err(err) => {
@@ -149,21 +154,28 @@ async fn handle_balances_with_option(
 
 /* View the balances for the group.
 */
-pub async fn action_view_balances(bot: Bot, dialogue: UserDialogue, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_view_balances(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     let chat_id = msg.chat.id.to_string();
     let sender_id = msg.from().as_ref().unwrap().id.to_string();
-    let is_convert = match get_chat_setting(&chat_id, ChatSetting::CurrencyConversion(None)) {
-        Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
-        _ => false,
-    };
-    let default_currency = match get_chat_setting(&chat_id, ChatSetting::DefaultCurrency(None)) {
-        Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
-        _ => "NIL".to_string(),
-    };
+    let is_convert =
+        match get_chat_setting(&store, &chat_id, ChatSetting::CurrencyConversion(None)).await {
+            Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
+            _ => false,
+        };
+    let default_currency =
+        match get_chat_setting(&store, &chat_id, ChatSetting::DefaultCurrency(None)).await {
+            Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
+            _ => "NIL".to_string(),
+        };
 
     let option = if is_convert {
         StatementOption::ConvertCurrency
@@ -171,7 +183,7 @@ pub async fn action_view_balances(bot: Bot, dialogue: UserDialogue, msg: Message
         StatementOption::Currency(default_currency.clone())
     };
 
-    handle_balances_with_option(bot, dialogue, msg, sender_id, option, None).await?;
+    handle_balances_with_option(bot, dialogue, msg, sender_id, option, None, &store).await?;
 
     Ok(())
 }
@@ -183,6 +195,7 @@ pub async fn action_balances_menu(
     bot: Bot,
     dialogue: UserDialogue,
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -194,18 +207,42 @@ pub async fn action_balances_menu(
             match button.as_str() {
                 _ if button.as_str().starts_with("Convert To ") => {
                     let option = StatementOption::ConvertCurrency;
-                    handle_balances_with_option(bot, dialogue, msg, sender_id, option, Some(id))
-                        .await?;
+                    handle_balances_with_option(
+                        bot,
+                        dialogue,
+                        msg,
+                        sender_id,
+                        option,
+                        Some(id),
+                        &store,
+                    )
+                    .await?;
                 }
                 _ if button.as_str() == "No Currency" => {
                     let option = StatementOption::Currency(CURRENCY_DEFAULT.0.to_string());
-                    handle_balances_with_option(bot, dialogue, msg, sender_id, option, Some(id))
-                        .await?;
+                    handle_balances_with_option(
+                        bot,
+                        dialogue,
+                        msg,
+                        sender_id,
+                        option,
+                        Some(id),
+                        &store,
+                    )
+                    .await?;
                 }
                 _ if button.as_str().len() == 3 => {
                     let option = StatementOption::Currency(button.as_str().to_string());
-                    handle_balances_with_option(bot, dialogue, msg, sender_id, option, Some(id))
-                        .await?;
+                    handle_balances_with_option(
+                        bot,
+                        dialogue,
+                        msg,
+                        sender_id,
+                        option,
+                        Some(id),
+                        &store,
+                    )
+                    .await?;
                 }
                 _ => {
                     log::error!(
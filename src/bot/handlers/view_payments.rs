@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use teloxide::{
     payloads::SendMessageSetters,
     prelude::*,
@@ -8,80 +9,124 @@ use crate::bot::{
     constants::{
         commands::COMMAND_ADD_PAYMENT,
         messages::{HEADER_MSG_HEAD, HEADER_MSG_TAIL, UNKNOWN_ERROR_MESSAGE},
+        misc::PAYMENTS_PAGE_SIZE,
     },
     currency::{get_default_currency, Currency},
     dispatcher::State,
     processor::{view_payments, ProcessError},
-    redis::{CrudError, UserPayment},
+    redis::{CrudError, PaymentCursor, PaymentItem, PaymentPage, UserPayment},
+    store::Store,
     utils::{
         bot_actions::{assert_handle_request_limit, send_bot_message},
-        format::{display_payment, get_currency, make_keyboard},
+        format::{
+            display_payment, get_currency, get_member_names, make_keyboard, member_from_user,
+            payment_members,
+        },
         time::retrieve_time_zone,
         HandlerResult, SelectPaymentType, UserDialogue,
     },
 };
 
 use super::{
-    action_delete_payment, action_edit_payment, block_delete_payment, block_edit_payment,
-    cancel_delete_payment, cancel_edit_payment, handle_repeated_delete_payment,
-    handle_repeated_edit_payment,
+    action_delete_payment, action_edit_payment, action_payment_history, block_delete_payment,
+    block_edit_payment, block_payment_history, cancel_delete_payment, cancel_edit_payment,
+    cancel_payment_history, handle_repeated_delete_payment, handle_repeated_edit_payment,
+    handle_repeated_payment_history,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Payment {
     pub payment_id: String,
     pub chat_id: String,
-    pub datetime: String,
+    pub timestamp: i64,
     pub description: String,
     pub creditor: String,
     pub currency: Currency,
     pub total: i64,
     pub debts: Vec<(String, i64)>,
+    pub items: Vec<PaymentItem>,
+    pub category: Option<String>,
+    pub payers: Vec<(String, i64)>,
 }
 
-fn unfold_payment(payment: UserPayment) -> Payment {
+pub fn unfold_payment(payment: UserPayment) -> Payment {
     let currency = get_currency(&payment.payment.currency);
     match currency {
         Ok(currency) => Payment {
             payment_id: payment.payment_id,
             chat_id: payment.chat_id,
-            datetime: payment.payment.datetime,
+            timestamp: payment.payment.timestamp,
             description: payment.payment.description,
             creditor: payment.payment.creditor,
             currency,
             total: payment.payment.total,
             debts: payment.payment.debts,
+            items: payment.payment.items,
+            category: payment.payment.category,
+            payers: payment.payment.payers,
         },
         Err(_) => Payment {
             payment_id: payment.payment_id,
             chat_id: payment.chat_id,
-            datetime: payment.payment.datetime,
+            timestamp: payment.payment.timestamp,
             description: payment.payment.description,
             creditor: payment.payment.creditor,
             currency: get_default_currency(),
             total: payment.payment.total,
             debts: payment.payment.debts,
+            items: payment.payment.items,
+            category: payment.payment.category,
+            payers: payment.payment.payers,
         },
     }
 }
 
-fn display_payments_paged(payments: &Vec<Payment>, page: usize, chat_id: &str) -> String {
-    let time_zone = retrieve_time_zone(chat_id);
-    let start_index = page * 5;
-    let displayed_payments: &[Payment];
-    if start_index + 5 >= payments.len() {
-        displayed_payments = &payments[start_index..];
-    } else {
-        displayed_payments = &payments[start_index..start_index + 5];
+// The page of payments being viewed, as only one page is loaded at a time
+// Cursors are where each page after the first starts, up to the current page
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PaymentsPage {
+    pub cursors: Vec<PaymentCursor>,
+    pub next: Option<PaymentCursor>,
+    pub total: usize,
+}
+
+impl PaymentsPage {
+    // Gets the serial number of the payment at an index of the page
+    pub fn serial_num(&self, index: usize) -> usize {
+        self.cursors.len() * PAYMENTS_PAGE_SIZE + index + 1
     }
+}
 
-    let serial_num = start_index + 1;
-    let formatted_payments = displayed_payments
-        .iter()
-        .enumerate()
-        .map(|(index, payment)| display_payment(payment, serial_num + index, time_zone));
+async fn display_payments_paged(
+    payments: &[Payment],
+    page: &PaymentsPage,
+    chat_id: &str,
+    store: &Store,
+) -> String {
+    let time_zone = retrieve_time_zone(store, chat_id).await;
+    let names = get_member_names(
+        store,
+        payments.iter().flat_map(|payment| {
+            payment_members(
+                &payment.creditor,
+                &payment.payers,
+                &payment.debts,
+                &payment.items,
+            )
+        }),
+    )
+    .await;
+    let mut formatted_payments = Vec::new();
+    for (index, payment) in payments.iter().enumerate() {
+        formatted_payments
+            .push(display_payment(store, payment, page.serial_num(index), time_zone, &names).await);
+    }
 
-    format!("{}", formatted_payments.collect::<Vec<String>>().join(""))
+    format!(
+        "{HEADER_MSG_HEAD}{}{HEADER_MSG_TAIL}{}",
+        page.total,
+        formatted_payments.join("")
+    )
 }
 
 fn get_navigation_menu() -> InlineKeyboardMarkup {
@@ -89,16 +134,9 @@ fn get_navigation_menu() -> InlineKeyboardMarkup {
     make_keyboard(buttons, Some(2))
 }
 
-fn get_select_menu(page: usize, payments: &Vec<Payment>) -> InlineKeyboardMarkup {
-    let start_index = page * 5;
-    let end_index = if start_index + 5 >= payments.len() {
-        payments.len()
-    } else {
-        start_index + 5
-    };
-
-    let mut buttons: Vec<String> = (start_index..end_index)
-        .map(|index| format!("{}", index + 1))
+fn get_select_menu(page: &PaymentsPage, payments: &[Payment]) -> InlineKeyboardMarkup {
+    let mut buttons: Vec<String> = (0..payments.len())
+        .map(|index| format!("{}", page.serial_num(index)))
         .collect();
     buttons.push("Cancel".to_string());
 
@@ -119,11 +157,12 @@ pub async fn handle_repeated_select_payment(
     (_messages, _payments, _page, function): (
         Vec<MessageId>,
         Vec<Payment>,
-        usize,
+        PaymentsPage,
         SelectPaymentType,
     ),
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -134,11 +173,14 @@ pub async fn handle_repeated_select_payment(
         SelectPaymentType::DeletePayment => {
             handle_repeated_delete_payment(bot, dialogue, state, msg).await?;
         }
+        SelectPaymentType::ViewHistory => {
+            handle_repeated_payment_history(bot, dialogue, state, msg).await?;
+        }
     }
     Ok(())
 }
 
-/* Cancels the edit/delete payment operation.
+/* Cancels the edit/delete payment or payment history operation.
  * Can be called at any step of the process.
  */
 pub async fn cancel_select_payment(
@@ -146,18 +188,22 @@ pub async fn cancel_select_payment(
     dialogue: UserDialogue,
     state: State,
     msg: Message,
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
     if let State::SelectPayment { ref function, .. } = state {
         match function {
             SelectPaymentType::EditPayment => {
-                cancel_edit_payment(bot, dialogue, state, msg).await?;
+                cancel_edit_payment(bot, dialogue, state, msg, store).await?;
             }
             SelectPaymentType::DeletePayment => {
-                cancel_delete_payment(bot, dialogue, state, msg).await?;
+                cancel_delete_payment(bot, dialogue, state, msg, store).await?;
+            }
+            SelectPaymentType::ViewHistory => {
+                cancel_payment_history(bot, dialogue, state, msg, store).await?;
             }
         }
     }
@@ -166,7 +212,7 @@ pub async fn cancel_select_payment(
 }
 
 /* Blocks user command.
- * Called when user attempts to start another operation in the middle of editing/deleting a payment.
+ * Called when user attempts to start another operation in the middle of choosing a payment.
  */
 pub async fn block_select_payment(
     bot: Bot,
@@ -176,11 +222,12 @@ pub async fn block_select_payment(
     (_messages, _payments, _page, function): (
         Vec<MessageId>,
         Vec<Payment>,
-        usize,
+        PaymentsPage,
         SelectPaymentType,
     ),
+    store: Store,
 ) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -191,16 +238,24 @@ pub async fn block_select_payment(
         SelectPaymentType::DeletePayment => {
             block_delete_payment(bot, dialogue, state, msg).await?;
         }
+        SelectPaymentType::ViewHistory => {
+            block_payment_history(bot, dialogue, state, msg).await?;
+        }
     }
     Ok(())
 }
 
 /* View all payments.
- * Bot retrieves all payments, and displays the most recent 5.
+ * Bot retrieves the most recent 5 payments, and displays them.
  * Then, presents a previous and next page button for the user to navigate the pagination.
  */
-pub async fn action_view_payments(bot: Bot, dialogue: UserDialogue, msg: Message) -> HandlerResult {
-    if !assert_handle_request_limit(msg.clone()) {
+pub async fn action_view_payments(
+    bot: Bot,
+    dialogue: UserDialogue,
+    msg: Message,
+    store: Store,
+) -> HandlerResult {
+    if !assert_handle_request_limit(&store, msg.clone()).await {
         return Ok(());
     }
 
@@ -208,22 +263,25 @@ pub async fn action_view_payments(bot: Bot, dialogue: UserDialogue, msg: Message
     let user = msg.from();
     if let Some(user) = user {
         let sender_id = user.id.to_string();
-        let sender_username = user.username.clone();
-        let payments = view_payments(&chat_id, &sender_id, sender_username.as_deref());
+        let sender_username = member_from_user(user);
+        let payments =
+            view_payments(&store, &chat_id, &sender_id, Some(&sender_username), None).await;
         match payments {
-            Ok(payments) => {
-                let payments: Vec<Payment> = payments
-                    .into_iter()
-                    .map(|payment| unfold_payment(payment))
-                    .collect();
+            Ok(PaymentPage {
+                payments,
+                total,
+                next,
+            }) => {
+                let payments: Vec<Payment> = payments.into_iter().map(unfold_payment).collect();
+                let page = PaymentsPage {
+                    cursors: Vec::new(),
+                    next,
+                    total,
+                };
                 send_bot_message(
                     &bot,
                     &msg,
-                    format!(
-                        "{HEADER_MSG_HEAD}{}{HEADER_MSG_TAIL}{}",
-                        &payments.len(),
-                        display_payments_paged(&payments, 0, &chat_id)
-                    ),
+                    display_payments_paged(&payments, &page, &chat_id, &store).await,
                 )
                 .reply_markup(get_navigation_menu())
                 .await?;
@@ -233,11 +291,11 @@ pub async fn action_view_payments(bot: Bot, dialogue: UserDialogue, msg: Message
                     "View Payments - User {} viewed payments for group {}, found {} payments",
                     sender_id,
                     chat_id,
-                    &payments.len()
+                    total
                 );
 
                 dialogue
-                    .update(State::ViewPayments { payments, page: 0 })
+                    .update(State::ViewPayments { payments, page })
                     .await?;
             }
             Err(ProcessError::CrudError(CrudError::NoPaymentsError())) => {
@@ -254,7 +312,7 @@ pub async fn action_view_payments(bot: Bot, dialogue: UserDialogue, msg: Message
                 dialogue.exit().await?;
             }
             Err(err) => {
-                send_bot_message(&bot, &msg, format!("{UNKNOWN_ERROR_MESSAGE}")).await?;
+                send_bot_message(&bot, &msg, UNKNOWN_ERROR_MESSAGE.to_string()).await?;
 
                 // Logging
                 log::error!(
@@ -272,67 +330,96 @@ pub async fn action_view_payments(bot: Bot, dialogue: UserDialogue, msg: Message
 }
 
 /* Navigation function for user to interact with payment pagination menu.
-*/
+ * Bot retrieves the newer or older page of payments, and displays it in place.
+ */
 pub async fn action_view_more(
     bot: Bot,
     dialogue: UserDialogue,
-    (payments, page): (Vec<Payment>, usize),
+    (_payments, page): (Vec<Payment>, PaymentsPage),
     query: CallbackQuery,
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
 
         if let Some(Message { id, chat, .. }) = query.message {
             let chat_id = chat.id.to_string();
+            let mut cursors = page.cursors.clone();
             match button.as_str() {
                 "Newer" => {
-                    if page > 0 {
-                        bot.edit_message_text(
-                            chat_id.clone(),
-                            id,
-                            format!(
-                                "{HEADER_MSG_HEAD}{}{HEADER_MSG_TAIL}{}",
-                                &payments.len(),
-                                display_payments_paged(&payments, page - 1, &chat_id)
-                            ),
-                        )
-                        .reply_markup(get_navigation_menu())
-                        .await?;
-                        dialogue
-                            .update(State::ViewPayments {
-                                payments,
-                                page: page - 1,
-                            })
-                            .await?;
-                    }
-                }
-                "Older" => {
-                    if (page + 1) * 5 < payments.len() {
-                        bot.edit_message_text(
-                            chat_id.clone(),
-                            id,
-                            format!(
-                                "{HEADER_MSG_HEAD}{}{HEADER_MSG_TAIL}{}",
-                                &payments.len(),
-                                display_payments_paged(&payments, page + 1, &chat_id)
-                            ),
-                        )
-                        .reply_markup(get_navigation_menu())
-                        .await?;
-                        dialogue
-                            .update(State::ViewPayments {
-                                payments,
-                                page: page + 1,
-                            })
-                            .await?;
+                    if cursors.pop().is_none() {
+                        return Ok(());
                     }
                 }
+                "Older" => match &page.next {
+                    Some(next) => cursors.push(next.clone()),
+                    None => return Ok(()),
+                },
                 _ => {
                     log::error!(
                         "View Payments Menu - Invalid button in chat {}: {}",
                         chat.id,
                         button
                     );
+                    return Ok(());
+                }
+            }
+
+            let sender_id = query.from.id.to_string();
+            let sender_username = member_from_user(&query.from);
+            let payments = view_payments(
+                &store,
+                &chat_id,
+                &sender_id,
+                Some(&sender_username),
+                cursors.last().cloned(),
+            )
+            .await;
+            match payments {
+                Ok(PaymentPage {
+                    payments,
+                    total,
+                    next,
+                }) => {
+                    let payments: Vec<Payment> = payments.into_iter().map(unfold_payment).collect();
+                    let page = PaymentsPage {
+                        cursors,
+                        next,
+                        total,
+                    };
+                    bot.edit_message_text(
+                        chat_id.clone(),
+                        id,
+                        display_payments_paged(&payments, &page, &chat_id, &store).await,
+                    )
+                    .reply_markup(get_navigation_menu())
+                    .await?;
+                    dialogue
+                        .update(State::ViewPayments { payments, page })
+                        .await?;
+                }
+                Err(ProcessError::CrudError(CrudError::NoPaymentsError())) => {
+                    bot.edit_message_text(
+                        chat_id.clone(),
+                        id,
+                        format!("No payments records found! But let's start adding one {COMMAND_ADD_PAYMENT}!"),
+                    )
+                    .await?;
+                    dialogue.exit().await?;
+                }
+                Err(err) => {
+                    bot.edit_message_text(chat_id.clone(), id, UNKNOWN_ERROR_MESSAGE)
+                        .await?;
+
+                    // Logging
+                    log::error!(
+                        "View Payments Menu - User {} failed to view more payments for group {}: {}",
+                        sender_id,
+                        chat_id,
+                        err.to_string()
+                    );
+
+                    dialogue.exit().await?;
                 }
             }
         }
@@ -349,9 +436,9 @@ pub async fn action_select_payment_edit(
     bot: Bot,
     dialogue: UserDialogue,
     msg: Message,
-    (payments, page): (Vec<Payment>, usize),
+    (payments, page): (Vec<Payment>, PaymentsPage),
 ) -> HandlerResult {
-    let keyboard = get_select_menu(page, &payments);
+    let keyboard = get_select_menu(&page, &payments);
 
     let new_message = send_bot_message(
         &bot,
@@ -382,9 +469,9 @@ pub async fn action_select_payment_delete(
     bot: Bot,
     dialogue: UserDialogue,
     msg: Message,
-    (payments, page): (Vec<Payment>, usize),
+    (payments, page): (Vec<Payment>, PaymentsPage),
 ) -> HandlerResult {
-    let keyboard = get_select_menu(page, &payments);
+    let keyboard = get_select_menu(&page, &payments);
 
     let new_message = send_bot_message(
         &bot,
@@ -415,7 +502,13 @@ pub async fn action_select_payment_number(
     dialogue: UserDialogue,
     query: CallbackQuery,
     state: State,
-    (messages, payments, page, function): (Vec<MessageId>, Vec<Payment>, usize, SelectPaymentType),
+    (messages, payments, page, function): (
+        Vec<MessageId>,
+        Vec<Payment>,
+        PaymentsPage,
+        SelectPaymentType,
+    ),
+    store: Store,
 ) -> HandlerResult {
     if let Some(button) = &query.data {
         bot.answer_callback_query(query.id.to_string()).await?;
@@ -425,13 +518,15 @@ pub async fn action_select_payment_number(
             let id = msg.id;
             match button.as_str() {
                 "Cancel" => {
-                    cancel_select_payment(bot, dialogue, state, query.message.unwrap()).await?;
+                    cancel_select_payment(bot, dialogue, state, query.message.unwrap(), store)
+                        .await?;
                 }
                 num => {
                     let parsing = num.parse::<usize>();
                     if let Ok(serial_num) = parsing {
-                        if serial_num <= payments.len() && serial_num > 0 {
-                            let index = serial_num - 1;
+                        let first_num = page.serial_num(0);
+                        if serial_num >= first_num && serial_num < first_num + payments.len() {
+                            let index = serial_num - first_num;
 
                             match function {
                                 SelectPaymentType::EditPayment => {
@@ -442,6 +537,7 @@ pub async fn action_select_payment_number(
                                         id,
                                         (messages, payments, page),
                                         index,
+                                        store,
                                     )
                                     .await?;
                                 }
@@ -453,6 +549,19 @@ pub async fn action_select_payment_number(
                                         id,
                                         (messages, payments, page),
                                         index,
+                                        store,
+                                    )
+                                    .await?;
+                                }
+                                SelectPaymentType::ViewHistory => {
+                                    action_payment_history(
+                                        bot,
+                                        dialogue,
+                                        msg,
+                                        id,
+                                        (messages, payments, page),
+                                        index,
+                                        store,
                                     )
                                     .await?;
                                 }
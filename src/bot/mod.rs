@@ -2,6 +2,8 @@ pub use self::dispatcher::run_dispatcher;
 
 pub use self::dispatcher::{Command, State};
 
+pub use self::store::{init_store, Store};
+
 mod constants;
 mod currency;
 mod dispatcher;
@@ -9,4 +11,7 @@ mod handlers;
 mod optimizer;
 mod processor;
 mod redis;
+mod scheduler;
+mod sqlite;
+mod store;
 mod utils;
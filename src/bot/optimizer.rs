@@ -11,12 +11,12 @@ fn compare(a: &UserBalance, b: &UserBalance) -> Ordering {
     }
 }
 
-fn sort_balances(balances: &mut Vec<UserBalance>) -> () {
+fn sort_balances(balances: &mut [UserBalance]) {
     balances.sort_by(compare);
 }
 
 pub fn optimize_debts(balances: Vec<UserBalance>) -> Vec<Debt> {
-    if balances.len() == 0 {
+    if balances.is_empty() {
         return Vec::new();
     }
 
@@ -52,7 +52,7 @@ pub fn optimize_debts(balances: Vec<UserBalance>) -> Vec<Debt> {
         if amount == sorted_balances[left].balance.abs() {
             left += 1;
         } else {
-            sorted_balances[left].balance = amount + sorted_balances[left].balance;
+            sorted_balances[left].balance += amount;
         }
         if amount == sorted_balances[right].balance {
             right -= 1;
@@ -102,7 +102,7 @@ mod tests {
 
     #[test]
     fn test_compare() {
-        let balances = vec![
+        let balances = [
             UserBalance {
                 username: "user1".to_string(),
                 currency: "USD".to_string(),
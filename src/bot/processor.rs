@@ -1,23 +1,33 @@
-use std::ops::Neg;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    ops::Neg,
+};
 
 use super::{
+    constants::misc::{
+        MAX_CUSTOM_CATEGORIES, PAYMENTS_PAGE_SIZE, PAYMENT_CATEGORIES, RECURRING_CATCH_UP_LIMIT,
+        TRASH_RETENTION_DAYS_DEFAULT,
+    },
     currency::{convert_currency, fetch_currency_conversion},
     optimizer::optimize_debts,
     redis::{
-        add_payment_entry, delete_payment_entry, get_chat_balances, get_chat_balances_currency,
-        get_chat_payments_details, get_currency_conversion, get_default_currency,
-        get_erase_messages, get_payment_entry, get_time_zone, get_valid_chat_currencies,
-        is_request_limit_exceeded, retrieve_chat_spendings, retrieve_chat_spendings_currency,
-        set_currency_conversion, set_default_currency, set_erase_messages, set_time_zone,
-        update_chat, update_chat_balances, update_chat_spendings, update_payment_entry,
-        update_user, CrudError, Debt, Payment, UserBalance, UserPayment, CURRENCY_CODE_DEFAULT,
+        CategoryTotal, ChatRecurringPayment, CrudError, Debt, LedgerActor, LedgerUpdate, Payment,
+        PaymentChange, PaymentCursor, PaymentEvent, PaymentItem, PaymentPage, PaymentQuery,
+        RecurringChange, RecurringFrequency, RecurringPayment, TrashedPayment, UserBalance,
+        UserPayment, CURRENCY_CODE_DEFAULT,
+    },
+    store::Store,
+    utils::{
+        schedule::{first_occurrence, next_occurrence},
+        time::retrieve_time_zone,
+        StatementOption,
     },
-    utils::StatementOption,
 };
 
 /* Processor is the overall logic center of the bot.
  * It handles the main logic, communicating with the front-facing handler
- * and the back-facing redis manager.
+ * and the back-facing storage backend, given as a LedgerStore.
  * It defines and executes the main functions required of the bot,
  * and handles exceptions and errors in the back.
  */
@@ -37,17 +47,73 @@ pub struct UserSpending {
     pub paid: i64,
 }
 
+// Spending on payments of a category, None for payments without one
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategorySpending {
+    pub category: Option<String>,
+    pub spending: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct SpendingData {
     pub currency: String,
     pub group_spending: i64,
     pub user_spendings: Vec<UserSpending>,
+    pub category_spendings: Vec<CategorySpending>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LedgerCounter {
+    Balance,
+    Spending,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerDiscrepancy {
+    pub username: String,
+    pub currency: String,
+    pub counter: LedgerCounter,
+    pub expected: i64,
+    pub actual: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerAudit {
+    pub payments: usize,
+    pub discrepancies: Vec<LedgerDiscrepancy>,
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerExport {
+    pub payments: Vec<UserPayment>,
+    pub balances: Vec<UserBalance>,
+    pub spendings: Vec<UserBalance>,
+}
+
+// Payments added for a recurring payment which was due
+#[derive(Debug, Clone)]
+pub struct RecurringUpdate {
+    pub recurring_id: String,
+    pub chat_id: String,
+    pub payments: Vec<Payment>,
+    pub debts: Vec<Debt>,
+    pub next: Option<i64>,
 }
 
 #[derive(thiserror::Error, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum ProcessError {
     #[error("{0}")]
     CrudError(CrudError),
+    #[error("Payments kept changing during the ledger audit")]
+    LedgerChangedError(),
+    #[error("User still has unsettled balances")]
+    UnsettledBalancesError(),
+    #[error("Recurring payment is never due")]
+    RecurringNeverDueError(),
+    #[error("Amounts paid by the payers do not add up to the total")]
+    PayersTotalError(),
 }
 
 // Implement the From trait to convert from CrudError to ProcessError
@@ -62,57 +128,64 @@ pub fn is_username_equal(first: &str, second: &str) -> bool {
     first.to_lowercase() == second.to_lowercase()
 }
 
-fn auto_update_user(
+// Checks that the amounts paid by each payer add up to the total, if more than one paid
+fn check_payers_total(payers: &[(String, i64)], total: i64) -> Result<(), ProcessError> {
+    let paid = payers
+        .iter()
+        .try_fold(0i64, |sum, (_, amount)| sum.checked_add(*amount));
+    if !payers.is_empty() && paid != Some(total) {
+        return Err(ProcessError::PayersTotalError());
+    }
+    Ok(())
+}
+
+async fn auto_update_user(
+    store: &Store,
     chat_id: &str,
     sender_id: &str,
     sender_username: Option<&str>,
 ) -> Result<(), ProcessError> {
     if let Some(username) = sender_username {
-        update_user(&username, chat_id, Some(sender_id))?;
+        store
+            .update_user(username, chat_id, Some(sender_id))
+            .await?;
     }
     Ok(())
 }
 
-fn update_balances(chat_id: &str, changes: Vec<UserBalance>) -> Result<(), ProcessError> {
-    // Update balances
-    update_chat_balances(chat_id, changes)?;
-    Ok(())
-}
-
-// Utility function required by many commands.
-// Updates the balances based on changes that are given.
-// Returns the debts for the relevant currency.
-async fn update_balances_debts(
-    chat_id: &str,
-    changes: Vec<UserBalance>,
-    currency: StatementOption,
-) -> Result<Vec<Debt>, ProcessError> {
-    update_balances(chat_id, changes)?;
-
-    let debts = retrieve_debts(chat_id, currency).await?;
-
-    Ok(debts)
+// Identifies the user making a change, so that it is recorded in the payment history
+fn ledger_actor(sender_id: &str, sender_username: &str, timestamp: i64) -> LedgerActor {
+    LedgerActor {
+        user_id: sender_id.to_string(),
+        username: sender_username.to_string(),
+        timestamp,
+    }
 }
 
-pub fn init_chat_config(chat_id: &str) -> Result<(), ProcessError> {
-    update_chat(chat_id, Vec::new())?;
+pub async fn init_chat_config(store: &Store, chat_id: &str) -> Result<(), ProcessError> {
+    store.update_chat(chat_id, Vec::new()).await?;
     Ok(())
 }
 
 /* Retrieves all valid currencies for a chat.
  * Valid currencies are currencies with some payments.
  */
-pub fn retrieve_valid_currencies(chat_id: &str) -> Result<Vec<String>, ProcessError> {
-    let currencies = get_valid_chat_currencies(chat_id)?;
+pub async fn retrieve_valid_currencies(
+    store: &Store,
+    chat_id: &str,
+) -> Result<Vec<String>, ProcessError> {
+    let currencies = store.get_valid_chat_currencies(chat_id).await?;
     Ok(currencies)
 }
 
 // Updates users and chat given payment details
-fn update_users_chat(
+async fn update_users_chat(
+    store: &Store,
     chat_id: &str,
     sender_username: &str,
     sender_id: &str,
     creditor: Option<&str>,
+    payers: Option<&[(String, i64)]>,
     debts: Option<Vec<(String, i64)>>,
 ) -> Result<(), ProcessError> {
     let mut all_users = vec![];
@@ -121,6 +194,15 @@ fn update_users_chat(
         all_users.push(creditor.to_string());
     }
 
+    if let Some(payers) = payers {
+        for (user, _) in payers.iter() {
+            if is_username_equal(user, creditor.unwrap_or("")) {
+                continue;
+            }
+            all_users.push(user.to_string());
+        }
+    }
+
     if let Some(debts) = debts {
         for (user, _) in debts.iter() {
             if is_username_equal(user, creditor.unwrap_or("")) {
@@ -137,234 +219,374 @@ fn update_users_chat(
             is_sender_included = true;
             continue;
         }
-        update_user(user, chat_id, None)?;
+        store.update_user(user, chat_id, None).await?;
     }
 
     // Add message sender to the list of users
-    update_user(sender_username, chat_id, Some(sender_id))?;
+    store
+        .update_user(sender_username, chat_id, Some(sender_id))
+        .await?;
     if !is_sender_included {
         all_users.push(sender_username.to_string());
     }
 
     // Update chat
-    update_chat(&chat_id, all_users)?;
+    store.update_chat(chat_id, all_users).await?;
 
     Ok(())
 }
 
 /* Add a new payment entry in a group chat.
  * Execution flow: Updates relevant users, updates chat.
- * Adds payment entry, updates balances, updates group debts.
+ * Adds payment entry, updates spendings and balances atomically, updates group debts.
  * Important: assumes that debts sum up to total. Creditor's share included.
+ * Payers are empty if the creditor paid the whole total, else they sum up to total too.
  */
+#[allow(clippy::too_many_arguments)]
 pub async fn add_payment(
+    store: &Store,
     chat_id: String,
     sender_username: String,
     sender_id: String,
-    datetime: String,
+    timestamp: i64,
     description: &str,
     creditor: &str,
     currency: &str,
     total: i64,
     debts: Vec<(String, i64)>,
+    items: Vec<PaymentItem>,
+    category: Option<String>,
+    payers: Vec<(String, i64)>,
 ) -> Result<Vec<Debt>, ProcessError> {
-    // Update users and chat
-    update_users_chat(
-        &chat_id,
-        &sender_username,
-        &sender_id,
-        Some(creditor),
-        Some(debts.clone()),
-    )?;
-
-    // Add payment entry
     let payment = Payment {
         description: description.to_string(),
-        datetime,
+        timestamp,
         creditor: creditor.to_string(),
         currency: currency.to_string(),
         total,
-        debts: debts.clone(),
+        debts,
+        items,
+        category,
+        payers,
     };
-    add_payment_entry(&chat_id, &payment)?;
+    add_payment_entry(store, &chat_id, &sender_username, &sender_id, payment).await?;
 
+    let conversion = store.get_currency_conversion(&chat_id).await?;
+    let option = if conversion {
+        StatementOption::ConvertCurrency
+    } else {
+        StatementOption::Currency(currency.to_string())
+    };
+
+    let debts = retrieve_debts(store, &chat_id, option).await?;
+    Ok(debts)
+}
+
+/* Add a new payment entry in a group chat, without retrieving group debts.
+ * Execution flow: Checks payers, updates relevant users, updates chat.
+ * Adds payment entry, updates spendings and balances atomically.
+ * Used on its own when importing many payments at once.
+ * Important: assumes that debts sum up to total. Creditor's share included.
+ */
+pub async fn add_payment_entry(
+    store: &Store,
+    chat_id: &str,
+    sender_username: &str,
+    sender_id: &str,
+    payment: Payment,
+) -> Result<(), ProcessError> {
+    check_payers_total(&payment.payers, payment.total)?;
+
+    // Update users and chat
+    update_users_chat(
+        store,
+        chat_id,
+        sender_username,
+        sender_id,
+        Some(&payment.creditor),
+        Some(&payment.payers),
+        Some(payment.debts.clone()),
+    )
+    .await?;
+
+    let update = add_payment_update(sender_username, sender_id, payment);
+    store.apply_ledger_update(chat_id, update).await?;
+    Ok(())
+}
+
+// Builds the ledger update adding a payment entry, with its spendings and balances
+fn add_payment_update(sender_username: &str, sender_id: &str, payment: Payment) -> LedgerUpdate {
     // Update spendings
-    let spendings: Vec<UserBalance> = debts
+    let spendings: Vec<UserBalance> = payment
+        .debts
         .iter()
         .map(|(user, amount)| UserBalance {
             username: user.to_string(),
-            currency: currency.to_string(),
+            currency: payment.currency.clone(),
             balance: *amount,
         })
         .collect();
-    update_chat_spendings(&chat_id, spendings)?;
 
     // Update balances
-    let mut changes: Vec<UserBalance> = debts
+    let mut changes: Vec<UserBalance> = payment
+        .debts
         .iter()
         .map(|(user, amount)| UserBalance {
             username: user.to_string(),
-            currency: currency.to_string(),
+            currency: payment.currency.clone(),
             balance: amount.neg(),
         })
         .collect();
 
-    changes.push(UserBalance {
-        username: creditor.to_string(),
-        currency: currency.to_string(),
-        balance: total,
-    });
-
-    let conversion = get_currency_conversion(&chat_id)?;
-    let option = if conversion {
-        StatementOption::ConvertCurrency
-    } else {
-        StatementOption::Currency(currency.to_string())
-    };
+    changes.extend(
+        payment
+            .paid_amounts()
+            .into_iter()
+            .map(|(user, amount)| UserBalance {
+                username: user,
+                currency: payment.currency.clone(),
+                balance: amount,
+            }),
+    );
+
+    LedgerUpdate {
+        categories: vec![payment.category_total()],
+        payments: vec![PaymentChange::Add(payment)],
+        spendings,
+        balances: changes,
+        actor: Some(ledger_actor(
+            sender_id,
+            sender_username,
+            chrono::Utc::now().timestamp(),
+        )),
+        ..Default::default()
+    }
+}
 
-    update_balances_debts(&chat_id, changes, option).await
+// Gets the change taking a payment out of the total of its category
+fn removed_category(payment: &Payment) -> CategoryTotal {
+    let total = payment.category_total();
+    CategoryTotal {
+        spending: total.spending.neg(),
+        ..total
+    }
 }
 
-/* View all payment entries of a group chat.
- * Execution flow: Retrieve chat payment details.
- * Called only once per command. Pagination handled by Handler.
+/* View a page of payment entries of a group chat, latest first.
+ * Execution flow: Retrieve the chat payment details of the page starting after the cursor.
+ * Called once per command, and again whenever the user moves to another page.
  */
-pub fn view_payments(
+pub async fn view_payments(
+    store: &Store,
     chat_id: &str,
     sender_id: &str,
     sender_username: Option<&str>,
-) -> Result<Vec<UserPayment>, ProcessError> {
-    auto_update_user(chat_id, sender_id, sender_username)?;
-
-    let payments = get_chat_payments_details(&chat_id)?;
-    Ok(payments)
+    cursor: Option<PaymentCursor>,
+) -> Result<PaymentPage, ProcessError> {
+    auto_update_user(store, chat_id, sender_id, sender_username).await?;
+
+    let query = PaymentQuery {
+        cursor,
+        page_size: PAYMENTS_PAGE_SIZE,
+        ..Default::default()
+    };
+    let page = store.get_chat_payments_page(chat_id, &query).await?;
+    if page.total == 0 {
+        return Err(ProcessError::CrudError(CrudError::NoPaymentsError()));
+    }
+    Ok(page)
 }
 
 /* Edit a payment entry in a group chat.
  * Execution flow: Edit payment entry.
- * Update balances, update group debts.
+ * Update balances, update group debts. All changes are applied atomically.
  * Has to be called after self::view_payments.
+ * Payers of Some empty list leave the creditor as the only payer, as in self::add_payment.
  */
+#[allow(clippy::too_many_arguments)]
 pub async fn edit_payment(
+    store: &Store,
     chat_id: &str,
     sender_username: String,
     sender_id: String,
     payment_id: &str,
     description: Option<&str>,
+    timestamp: Option<i64>,
     creditor: Option<&str>,
     currency: Option<&str>,
     total: Option<&i64>,
     debts: Option<Vec<(String, i64)>>,
+    items: Option<Vec<PaymentItem>>,
+    category: Option<Option<&str>>,
+    payers: Option<Vec<(String, i64)>>,
 ) -> Result<Option<Vec<Debt>>, ProcessError> {
     // Get current payment entry
-    let current_payment = get_payment_entry(payment_id)?;
+    let current_payment = store.get_payment_entry(payment_id).await?;
+
+    // Payers are kept when only the total or currency is edited, so must still add up to it
+    check_payers_total(
+        payers.as_ref().unwrap_or(&current_payment.payers),
+        *total.unwrap_or(&current_payment.total),
+    )?;
 
     // Update users and chat
     update_users_chat(
-        &chat_id,
+        store,
+        chat_id,
         &sender_username,
         &sender_id,
         creditor,
+        payers.as_deref(),
         debts.clone(),
-    )?;
+    )
+    .await?;
+
+    // Move the payment's spending from its previous category total to the new one
+    let new_category = CategoryTotal {
+        category: category
+            .map(|category| category.map(|category| category.to_string()))
+            .unwrap_or(current_payment.category.clone()),
+        currency: currency.unwrap_or(&current_payment.currency).to_string(),
+        spending: debts
+            .as_ref()
+            .unwrap_or(&current_payment.debts)
+            .iter()
+            .map(|(_, amount)| amount)
+            .sum(),
+        latest: timestamp.unwrap_or(current_payment.timestamp),
+    };
 
     // Edit payment entry
-    update_payment_entry(
-        payment_id,
-        description,
-        creditor,
-        currency,
-        total,
-        debts.clone(),
-    )?;
+    let mut update = LedgerUpdate {
+        payments: vec![PaymentChange::Update {
+            payment_id: payment_id.to_string(),
+            description: description.map(|desc| desc.to_string()),
+            timestamp,
+            creditor: creditor.map(|cred| cred.to_string()),
+            currency: currency.map(|curr| curr.to_string()),
+            total: total.copied(),
+            debts: debts.clone(),
+            items,
+            category: category.map(|category| category.map(|category| category.to_string())),
+            payers: payers.clone(),
+        }],
+        categories: vec![removed_category(&current_payment), new_category],
+        actor: Some(ledger_actor(
+            &sender_id,
+            &sender_username,
+            chrono::Utc::now().timestamp(),
+        )),
+        ..Default::default()
+    };
+
+    if creditor.is_none() && total.is_none() && debts.is_none() && payers.is_none() {
+        store.apply_ledger_update(chat_id, update).await?;
+        return Ok(None);
+    }
 
     // Update balances in two stages: first undo the previous payment, then set the new one
-    if creditor.is_some() || total.is_some() || debts.is_some() {
-        // First round of update
-        let prev_creditor = &current_payment.creditor;
-        let prev_currency = &current_payment.currency;
-        let mut prev_changes: Vec<UserBalance> = current_payment
-            .debts
-            .iter()
-            .map(|debt| UserBalance {
-                username: debt.0.to_string(),
-                currency: prev_currency.to_string(),
-                balance: debt.1,
-            })
-            .collect();
-        prev_changes.push(UserBalance {
-            username: prev_creditor.to_string(),
+    // First round of update
+    let prev_currency = &current_payment.currency;
+    let mut changes: Vec<UserBalance> = current_payment
+        .debts
+        .iter()
+        .map(|debt| UserBalance {
+            username: debt.0.to_string(),
             currency: prev_currency.to_string(),
-            balance: current_payment.total.neg(),
-        });
-        update_chat_balances(&chat_id, prev_changes)?;
-
-        // Update spendings as well
-        let prev_spendings: Vec<UserBalance> = current_payment
-            .debts
-            .iter()
-            .map(|debt| UserBalance {
-                username: debt.0.to_string(),
+            balance: debt.1,
+        })
+        .collect();
+    changes.extend(
+        current_payment
+            .paid_amounts()
+            .into_iter()
+            .map(|(user, amount)| UserBalance {
+                username: user,
                 currency: prev_currency.to_string(),
-                balance: debt.1.neg(),
-            })
-            .collect();
-        update_chat_spendings(&chat_id, prev_spendings)?;
+                balance: amount.neg(),
+            }),
+    );
 
-        // Second round of update
-        let mut changes: Vec<UserBalance> = debts
-            .clone()
-            .unwrap_or(current_payment.debts.clone())
-            .iter()
-            .map(|debt| UserBalance {
-                username: debt.0.to_string(),
-                currency: currency.unwrap_or(prev_currency).to_string(),
-                balance: debt.1.neg(),
-            })
-            .collect();
-        changes.push(UserBalance {
-            username: creditor.unwrap_or(&current_payment.creditor).to_string(),
-            currency: currency.unwrap_or(prev_currency).to_string(),
-            balance: *total.unwrap_or(&current_payment.total),
-        });
-
-        // Update spendings as well
-        let new_spendings: Vec<UserBalance> = debts
-            .unwrap_or(current_payment.debts)
-            .iter()
-            .map(|debt| UserBalance {
-                username: debt.0.to_string(),
-                currency: currency.unwrap_or(prev_currency).to_string(),
-                balance: debt.1,
-            })
-            .collect();
-        update_chat_spendings(&chat_id, new_spendings)?;
-
-        let conversion = get_currency_conversion(&chat_id)?;
-        let option = if conversion {
-            StatementOption::ConvertCurrency
-        } else {
-            StatementOption::Currency(currency.unwrap_or(prev_currency).to_string())
-        };
+    // Update spendings as well
+    let mut spendings: Vec<UserBalance> = current_payment
+        .debts
+        .iter()
+        .map(|debt| UserBalance {
+            username: debt.0.to_string(),
+            currency: prev_currency.to_string(),
+            balance: debt.1.neg(),
+        })
+        .collect();
 
-        let res = update_balances_debts(&chat_id, changes, option).await?;
-        return Ok(Some(res));
-    }
+    // Second round of update
+    let new_debts = debts.unwrap_or(current_payment.debts.clone());
+    let new_currency = currency.unwrap_or(prev_currency);
+    changes.extend(new_debts.iter().map(|debt| UserBalance {
+        username: debt.0.to_string(),
+        currency: new_currency.to_string(),
+        balance: debt.1.neg(),
+    }));
+    let new_payers = payers.unwrap_or(current_payment.payers.clone());
+    let new_paid_amounts = if new_payers.is_empty() {
+        vec![(
+            creditor.unwrap_or(&current_payment.creditor).to_string(),
+            *total.unwrap_or(&current_payment.total),
+        )]
+    } else {
+        new_payers
+    };
+    changes.extend(
+        new_paid_amounts
+            .into_iter()
+            .map(|(user, amount)| UserBalance {
+                username: user,
+                currency: new_currency.to_string(),
+                balance: amount,
+            }),
+    );
+
+    // Update spendings as well
+    spendings.extend(new_debts.iter().map(|debt| UserBalance {
+        username: debt.0.to_string(),
+        currency: new_currency.to_string(),
+        balance: debt.1,
+    }));
+
+    update.spendings = spendings;
+    update.balances = changes;
+    store.apply_ledger_update(chat_id, update).await?;
+
+    let conversion = store.get_currency_conversion(chat_id).await?;
+    let option = if conversion {
+        StatementOption::ConvertCurrency
+    } else {
+        StatementOption::Currency(new_currency.to_string())
+    };
 
-    Ok(None)
+    let res = retrieve_debts(store, chat_id, option).await?;
+    Ok(Some(res))
 }
 
 /* Delete a payment entry in a group chat.
- * Execution flow: Delete payment entry.
- * Update balances, update group debts.
+ * Execution flow: Move payment entry to the trash.
+ * Update balances, update group debts. All changes are applied atomically.
+ * The payment can be restored with self::restore_payment, until it is purged.
  * Has to be called after self::view_payments.
  */
-pub async fn delete_payment(chat_id: &str, payment_id: &str) -> Result<Vec<Debt>, ProcessError> {
-    // Get payment entry
-    let payment = get_payment_entry(payment_id)?;
+pub async fn delete_payment(
+    store: &Store,
+    chat_id: &str,
+    payment_id: &str,
+    sender_id: &str,
+    sender_username: &str,
+    deleted_by: &str,
+    deleted_at: i64,
+) -> Result<Vec<Debt>, ProcessError> {
+    // Clear out expired payments first, so that the trash does not grow forever
+    purge_trash(store, chat_id, deleted_at).await?;
 
-    // Delete payment entry
-    delete_payment_entry(&chat_id, payment_id)?;
+    // Get payment entry
+    let payment = store.get_payment_entry(payment_id).await?;
 
     // Update spendings
     let spendings: Vec<UserBalance> = payment
@@ -376,7 +598,6 @@ pub async fn delete_payment(chat_id: &str, payment_id: &str) -> Result<Vec<Debt>
             balance: debt.1.neg(),
         })
         .collect();
-    update_chat_spendings(&chat_id, spendings)?;
 
     // Update balances
     let mut changes: Vec<UserBalance> = payment
@@ -388,20 +609,360 @@ pub async fn delete_payment(chat_id: &str, payment_id: &str) -> Result<Vec<Debt>
             balance: debt.1,
         })
         .collect();
-    changes.push(UserBalance {
-        username: payment.creditor,
-        currency: payment.currency.clone(),
-        balance: payment.total.neg(),
-    });
+    changes.extend(
+        payment
+            .paid_amounts()
+            .into_iter()
+            .map(|(user, amount)| UserBalance {
+                username: user,
+                currency: payment.currency.clone(),
+                balance: amount.neg(),
+            }),
+    );
+
+    // Trash payment entry together with its effects
+    let update = LedgerUpdate {
+        payments: vec![PaymentChange::Trash {
+            payment_id: payment_id.to_string(),
+            deleted_at,
+            deleted_by: deleted_by.to_string(),
+        }],
+        spendings,
+        categories: vec![removed_category(&payment)],
+        balances: changes,
+        actor: Some(ledger_actor(sender_id, sender_username, deleted_at)),
+        ..Default::default()
+    };
+    store.apply_ledger_update(chat_id, update).await?;
+
+    let conversion = store.get_currency_conversion(chat_id).await?;
+    let option = if conversion {
+        StatementOption::ConvertCurrency
+    } else {
+        StatementOption::Currency(payment.currency.clone())
+    };
+
+    let debts = retrieve_debts(store, chat_id, option).await?;
+    Ok(debts)
+}
+
+/* Gets the number of days that deleted payments are kept in the trash.
+ * Configured with the TRASH_RETENTION_DAYS environment variable.
+ */
+pub fn get_trash_retention_days() -> i64 {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(TRASH_RETENTION_DAYS_DEFAULT)
+}
+
+/* Permanently deletes all payments in the trash of a group chat past the retention period.
+ * Trashed payments no longer count towards balances or spendings, so only entries are removed.
+ * Returns the number of payments purged.
+ */
+pub async fn purge_trash(store: &Store, chat_id: &str, now: i64) -> Result<usize, ProcessError> {
+    let retention = get_trash_retention_days() * 24 * 60 * 60;
+    let expired: Vec<PaymentChange> = store
+        .get_chat_trash_details(chat_id)
+        .await?
+        .into_iter()
+        .filter(|payment| now - payment.deleted_at >= retention)
+        .map(|payment| PaymentChange::Purge(payment.payment_id))
+        .collect();
+
+    let count = expired.len();
+    if count > 0 {
+        let update = LedgerUpdate {
+            payments: expired,
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await?;
+    }
+    Ok(count)
+}
 
-    let conversion = get_currency_conversion(&chat_id)?;
+/* View all deleted payment entries of a group chat, latest deletion first.
+ * Execution flow: Purge expired payments, retrieve trashed payment details.
+ */
+pub async fn view_trash(
+    store: &Store,
+    chat_id: &str,
+    now: i64,
+) -> Result<Vec<TrashedPayment>, ProcessError> {
+    purge_trash(store, chat_id, now).await?;
+
+    let payments = store.get_chat_trash_details(chat_id).await?;
+    Ok(payments)
+}
+
+/* Restore a deleted payment entry in a group chat.
+ * Execution flow: Move payment entry out of the trash.
+ * Reapply its balances and spendings, update group debts. All changes are applied atomically.
+ * Has to be called after self::view_trash.
+ */
+pub async fn restore_payment(
+    store: &Store,
+    chat_id: &str,
+    payment_id: &str,
+    sender_id: &str,
+    sender_username: &str,
+) -> Result<Vec<Debt>, ProcessError> {
+    // Get payment entry
+    let payment = store.get_payment_entry(payment_id).await?;
+
+    // Update spendings
+    let spendings: Vec<UserBalance> = payment
+        .debts
+        .iter()
+        .map(|debt| UserBalance {
+            username: debt.0.to_string(),
+            currency: payment.currency.clone(),
+            balance: debt.1,
+        })
+        .collect();
+
+    // Update balances
+    let mut changes: Vec<UserBalance> = payment
+        .debts
+        .iter()
+        .map(|debt| UserBalance {
+            username: debt.0.to_string(),
+            currency: payment.currency.clone(),
+            balance: debt.1.neg(),
+        })
+        .collect();
+    changes.extend(
+        payment
+            .paid_amounts()
+            .into_iter()
+            .map(|(user, amount)| UserBalance {
+                username: user,
+                currency: payment.currency.clone(),
+                balance: amount,
+            }),
+    );
+
+    // Restore payment entry together with its effects
+    let update = LedgerUpdate {
+        payments: vec![PaymentChange::Restore(payment_id.to_string())],
+        spendings,
+        categories: vec![payment.category_total()],
+        balances: changes,
+        actor: Some(ledger_actor(
+            sender_id,
+            sender_username,
+            chrono::Utc::now().timestamp(),
+        )),
+        ..Default::default()
+    };
+    store.apply_ledger_update(chat_id, update).await?;
+
+    let conversion = store.get_currency_conversion(chat_id).await?;
     let option = if conversion {
         StatementOption::ConvertCurrency
     } else {
         StatementOption::Currency(payment.currency.clone())
     };
 
-    update_balances_debts(&chat_id, changes, option).await
+    let debts = retrieve_debts(store, chat_id, option).await?;
+    Ok(debts)
+}
+
+/* Add a recurring payment to a group chat, to be added by the scheduler whenever it is due.
+ * Execution flow: Updates the sender, finds when it is first due, adds the recurring payment.
+ * The payment is dated at its start, and is first due at or after it, in the time zone of the chat.
+ * Returns when the payment is first due.
+ */
+#[allow(clippy::too_many_arguments)]
+pub async fn add_recurring_payment(
+    store: &Store,
+    chat_id: &str,
+    sender_username: &str,
+    sender_id: &str,
+    start: i64,
+    description: &str,
+    creditor: &str,
+    currency: &str,
+    total: i64,
+    debts: Vec<(String, i64)>,
+    items: Vec<PaymentItem>,
+    category: Option<String>,
+    payers: Vec<(String, i64)>,
+    frequency: RecurringFrequency,
+    end: Option<i64>,
+) -> Result<i64, ProcessError> {
+    auto_update_user(store, chat_id, sender_id, Some(sender_username)).await?;
+    let payment = Payment {
+        description: description.to_string(),
+        timestamp: start,
+        creditor: creditor.to_string(),
+        currency: currency.to_string(),
+        total,
+        debts,
+        items,
+        category,
+        payers,
+    };
+
+    let time_zone = retrieve_time_zone(store, chat_id).await;
+    let next = first_occurrence(&frequency, payment.timestamp, time_zone)
+        .filter(|next| end.is_none_or(|end| *next <= end))
+        .ok_or(ProcessError::RecurringNeverDueError())?;
+
+    let recurring = RecurringPayment {
+        payment,
+        frequency,
+        end,
+        next,
+        creator_id: sender_id.to_string(),
+        creator: sender_username.to_string(),
+    };
+    store.add_recurring_payment(chat_id, &recurring).await?;
+    Ok(next)
+}
+
+/* View all recurring payments of a group chat, earliest due first.
+ */
+pub async fn view_recurring_payments(
+    store: &Store,
+    chat_id: &str,
+) -> Result<Vec<ChatRecurringPayment>, ProcessError> {
+    let recurring_payments = store.get_chat_recurring_payments(chat_id).await?;
+    Ok(recurring_payments)
+}
+
+/* Stop a recurring payment of a group chat.
+ * Payments it has already added are kept.
+ */
+pub async fn delete_recurring_payment(
+    store: &Store,
+    chat_id: &str,
+    recurring_id: &str,
+) -> Result<(), ProcessError> {
+    store
+        .delete_recurring_payment(chat_id, recurring_id)
+        .await?;
+    Ok(())
+}
+
+/* Retrieves all recurring payments due by now, across all group chats, earliest due first.
+ */
+pub async fn get_due_recurring_payments(
+    store: &Store,
+    now: i64,
+) -> Result<Vec<ChatRecurringPayment>, ProcessError> {
+    let recurring_payments = store.get_due_recurring_payments(now).await?;
+    Ok(recurring_payments)
+}
+
+/* Add a due recurring payment to its group chat, once for every occurrence missed up to now.
+ * Execution flow: For each occurrence, adds the payment dated at it, on behalf of its creator.
+ * In the same ledger update, schedules the next one, or deletes the recurring payment past its end.
+ * So an occurrence is added exactly once, and not at all if the recurring payment has since
+ * been changed, deleted or moved to another chat.
+ * Adds at most RECURRING_CATCH_UP_LIMIT occurrences at a time, leaving the rest for the next run.
+ * Returns the payments added, the group debts after them, and when the payment is next due.
+ */
+pub async fn add_due_recurring_payment(
+    store: &Store,
+    recurring: ChatRecurringPayment,
+    now: i64,
+) -> Result<RecurringUpdate, ProcessError> {
+    let ChatRecurringPayment {
+        recurring_id,
+        chat_id,
+        mut recurring,
+    } = recurring;
+    let time_zone = retrieve_time_zone(store, &chat_id).await;
+
+    // The creator may have changed their username since
+    let creator = store
+        .get_tracked_username(&recurring.creator_id)
+        .await?
+        .unwrap_or(recurring.creator.clone());
+
+    let conversion = store.get_currency_conversion(&chat_id).await?;
+    let option = if conversion {
+        StatementOption::ConvertCurrency
+    } else {
+        StatementOption::Currency(recurring.payment.currency.clone())
+    };
+
+    let mut payments: Vec<Payment> = Vec::new();
+    let mut next = Some(recurring.next);
+    while payments.len() < RECURRING_CATCH_UP_LIMIT {
+        let timestamp = match next {
+            Some(timestamp) if timestamp <= now => timestamp,
+            _ => break,
+        };
+
+        let start = recurring.payment.timestamp;
+        next = next_occurrence(&recurring.frequency, start, timestamp, time_zone)
+            .filter(|next| recurring.end.is_none_or(|end| *next <= end));
+
+        let payment = Payment {
+            timestamp,
+            ..recurring.payment.clone()
+        };
+        update_users_chat(
+            store,
+            &chat_id,
+            &creator,
+            &recurring.creator_id,
+            Some(&payment.creditor),
+            Some(&payment.payers),
+            Some(payment.debts.clone()),
+        )
+        .await?;
+        let update = LedgerUpdate {
+            recurring: Some(RecurringChange {
+                recurring_id: recurring_id.clone(),
+                current: recurring.clone(),
+                next,
+            }),
+            ..add_payment_update(&creator, &recurring.creator_id, payment.clone())
+        };
+        store.apply_ledger_update(&chat_id, update).await?;
+        payments.push(payment);
+
+        if let Some(next) = next {
+            recurring.next = next;
+        }
+    }
+
+    let debts = retrieve_debts(store, &chat_id, option).await?;
+    Ok(RecurringUpdate {
+        recurring_id,
+        chat_id,
+        payments,
+        debts,
+        next,
+    })
+}
+
+/* View the history of a payment entry, earliest change first.
+ * Execution flow: Retrieve payment events.
+ * History is kept even after the payment is deleted.
+ */
+pub async fn view_payment_history(
+    store: &Store,
+    payment_id: &str,
+) -> Result<Vec<PaymentEvent>, ProcessError> {
+    let events = store.get_payment_history(payment_id).await?;
+    Ok(events)
+}
+
+/* View the latest changes to payment entries of a group chat, latest change first.
+ * Execution flow: Retrieve chat payment events, up to the given count.
+ */
+pub async fn view_chat_activity(
+    store: &Store,
+    chat_id: &str,
+    count: usize,
+) -> Result<Vec<PaymentEvent>, ProcessError> {
+    let events = store.get_chat_activity(chat_id, count).await?;
+    Ok(events)
 }
 
 /* View balances of a group chat.
@@ -409,34 +970,42 @@ pub async fn delete_payment(chat_id: &str, payment_id: &str) -> Result<Vec<Debt>
  * Which is whether the currency is to be converted, and which currency.
  */
 pub async fn retrieve_debts(
+    store: &Store,
     chat_id: &str,
     option: StatementOption,
 ) -> Result<Vec<Debt>, ProcessError> {
     match option {
-        StatementOption::Currency(currency) => retrieve_debts_by_currency(chat_id, &currency),
-        StatementOption::ConvertCurrency => retrieve_debts_converted(chat_id).await,
+        StatementOption::Currency(currency) => {
+            retrieve_debts_by_currency(store, chat_id, &currency).await
+        }
+        StatementOption::ConvertCurrency => retrieve_debts_converted(store, chat_id).await,
     }
 }
 
 /* View debts of a group chat for a specific currency.
  * Retrieves all balances, optimizes debts, and returns.
  */
-fn retrieve_debts_by_currency(chat_id: &str, currency: &str) -> Result<Vec<Debt>, ProcessError> {
-    let default_currency = match get_chat_setting(chat_id, ChatSetting::DefaultCurrency(None))? {
-        ChatSetting::DefaultCurrency(Some(curr)) => curr,
-        _ => CURRENCY_CODE_DEFAULT.to_string(),
-    };
+async fn retrieve_debts_by_currency(
+    store: &Store,
+    chat_id: &str,
+    currency: &str,
+) -> Result<Vec<Debt>, ProcessError> {
+    let default_currency =
+        match get_chat_setting(store, chat_id, ChatSetting::DefaultCurrency(None)).await? {
+            ChatSetting::DefaultCurrency(Some(curr)) => curr,
+            _ => CURRENCY_CODE_DEFAULT.to_string(),
+        };
 
     // // If currency is NIL, which is not default currency.
     // if currency == CURRENCY_CODE_DEFAULT && default_currency != CURRENCY_CODE_DEFAULT {
-    //     return retrieve_debts_by_default_currency(chat_id);
+    //     return retrieve_debts_by_default_currency(store, chat_id).await;
     // }
 
     if default_currency == currency && currency != CURRENCY_CODE_DEFAULT {
-        return retrieve_debts_by_default_currency(chat_id);
+        return retrieve_debts_by_default_currency(store, chat_id).await;
     }
 
-    let balances = get_chat_balances_currency(chat_id, currency)?;
+    let balances = store.get_chat_balances_currency(chat_id, currency).await?;
     let debts = optimize_debts(balances);
 
     Ok(debts)
@@ -445,13 +1014,19 @@ fn retrieve_debts_by_currency(chat_id: &str, currency: &str) -> Result<Vec<Debt>
 /* View debts of a group chat for the default currency.
  * Retrieves all balances, optimizes debts, and returns.
  */
-fn retrieve_debts_by_default_currency(chat_id: &str) -> Result<Vec<Debt>, ProcessError> {
-    let currency = match get_chat_setting(chat_id, ChatSetting::DefaultCurrency(None))? {
-        ChatSetting::DefaultCurrency(Some(curr)) => curr,
-        _ => CURRENCY_CODE_DEFAULT.to_string(),
-    };
-    let mut balances_curr = get_chat_balances_currency(chat_id, &currency)?;
-    let balances_nil = get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)?;
+async fn retrieve_debts_by_default_currency(
+    store: &Store,
+    chat_id: &str,
+) -> Result<Vec<Debt>, ProcessError> {
+    let currency =
+        match get_chat_setting(store, chat_id, ChatSetting::DefaultCurrency(None)).await? {
+            ChatSetting::DefaultCurrency(Some(curr)) => curr,
+            _ => CURRENCY_CODE_DEFAULT.to_string(),
+        };
+    let mut balances_curr = store.get_chat_balances_currency(chat_id, &currency).await?;
+    let balances_nil = store
+        .get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)
+        .await?;
 
     for balance in balances_nil {
         let curr_index = balances_curr
@@ -475,13 +1050,13 @@ fn retrieve_debts_by_default_currency(chat_id: &str) -> Result<Vec<Debt>, Proces
 /* View debts of a group chat for all currencies, converted to default currency.
  * Retrieves all balances, optimizes debts, and returns.
  */
-async fn retrieve_debts_converted(chat_id: &str) -> Result<Vec<Debt>, ProcessError> {
-    let mut balances = get_chat_balances(chat_id)?;
-    let default_currency = get_default_currency(chat_id)?;
+async fn retrieve_debts_converted(store: &Store, chat_id: &str) -> Result<Vec<Debt>, ProcessError> {
+    let mut balances = store.get_chat_balances(chat_id).await?;
+    let default_currency = store.get_default_currency(chat_id).await?;
 
     let mut converted_balances: Vec<UserBalance> = Vec::new();
     for balances_currency in &mut balances {
-        if balances_currency.len() == 0 {
+        if balances_currency.is_empty() {
             continue;
         }
 
@@ -529,40 +1104,159 @@ async fn retrieve_debts_converted(chat_id: &str) -> Result<Vec<Debt>, ProcessErr
     Ok(debts)
 }
 
+/* Retrieves the categories a payment of a group chat can be given.
+ * These are the default categories, followed by the custom ones its payments have, latest first.
+ * Only the latest few custom categories are given, so that they all fit in a menu.
+ */
+pub async fn retrieve_chat_categories(
+    store: &Store,
+    chat_id: &str,
+) -> Result<Vec<String>, ProcessError> {
+    let mut categories: Vec<String> = PAYMENT_CATEGORIES
+        .iter()
+        .map(|category| category.to_string())
+        .collect();
+
+    let mut totals = store.retrieve_chat_categories(chat_id).await?;
+    totals.sort_by_key(|total| (Reverse(total.latest), total.category.clone()));
+    for category in totals.into_iter().filter_map(|total| total.category) {
+        if categories.len() >= PAYMENT_CATEGORIES.len() + MAX_CUSTOM_CATEGORIES {
+            break;
+        }
+        if !categories
+            .iter()
+            .any(|known| known.to_lowercase() == category.to_lowercase())
+        {
+            categories.push(category);
+        }
+    }
+    Ok(categories)
+}
+
 /* View spendings of a group chat.
  * Takes in a specification of the options for viewing.
  * Which is whether the currency is to be converted, and which currency.
  */
 pub async fn retrieve_spending_data(
+    store: &Store,
     chat_id: &str,
     option: StatementOption,
 ) -> Result<SpendingData, ProcessError> {
-    match option {
+    let mut spending_data = match &option {
         StatementOption::Currency(currency) => {
-            retrieve_spending_data_by_currency(chat_id, &currency)
+            retrieve_spending_data_by_currency(store, chat_id, currency).await?
+        }
+        StatementOption::ConvertCurrency => {
+            retrieve_spending_data_converted(store, chat_id).await?
+        }
+    };
+    spending_data.category_spendings = retrieve_category_spendings(store, chat_id, &option).await?;
+    Ok(spending_data)
+}
+
+/* View spendings of a group chat by category, for the same payments as self::retrieve_spending_data.
+ * Each payment counts what everyone owes for it, the same as the spendings of its members.
+ * Totals are kept by every ledger update, so payments are not read.
+ * Categories are sorted by most spent, with payments without a category last.
+ */
+async fn retrieve_category_spendings(
+    store: &Store,
+    chat_id: &str,
+    option: &StatementOption,
+) -> Result<Vec<CategorySpending>, ProcessError> {
+    let mut totals = store.retrieve_chat_categories(chat_id).await?;
+    let default_currency = store.get_default_currency(chat_id).await?;
+
+    // Ordered so that categories spelt differently across currencies are always merged alike
+    totals.sort_by(|first, second| {
+        (&first.category, &first.currency).cmp(&(&second.category, &second.currency))
+    });
+
+    let mut rates: BTreeMap<String, f64> = BTreeMap::new();
+    let mut category_spendings: Vec<CategorySpending> = Vec::new();
+    for CategoryTotal {
+        category,
+        currency,
+        mut spending,
+        ..
+    } in totals
+    {
+        let is_counted = match option {
+            StatementOption::Currency(option_currency) => {
+                currency == *option_currency
+                    || (currency == CURRENCY_CODE_DEFAULT && *option_currency == default_currency)
+            }
+            StatementOption::ConvertCurrency => true,
+        };
+        if !is_counted {
+            continue;
+        }
+
+        let should_convert = *option == StatementOption::ConvertCurrency
+            && currency != default_currency
+            && currency != CURRENCY_CODE_DEFAULT;
+        if should_convert {
+            let rate = match rates.get(&currency) {
+                Some(rate) => *rate,
+                None => {
+                    let rate = match fetch_currency_conversion(&currency, &default_currency).await {
+                        Ok(rate) => rate,
+                        Err(err) => {
+                            log::error!("Error fetching currency conversion from {currency} to {default_currency}: {}", err);
+                            1.0
+                        }
+                    };
+                    rates.insert(currency.clone(), rate);
+                    rate
+                }
+            };
+            spending = convert_currency(spending, &currency, &default_currency, rate);
+        }
+
+        // Categories are matched regardless of case, the same as when they are picked
+        match category_spendings.iter_mut().find(|category_spending| {
+            category_spending.category.as_deref().map(str::to_lowercase)
+                == category.as_deref().map(str::to_lowercase)
+        }) {
+            Some(category_spending) => category_spending.spending += spending,
+            None => category_spendings.push(CategorySpending { category, spending }),
         }
-        StatementOption::ConvertCurrency => retrieve_spending_data_converted(chat_id).await,
     }
+
+    category_spendings.retain(|category_spending| category_spending.spending != 0);
+    category_spendings.sort_by(|first, second| {
+        first
+            .category
+            .is_none()
+            .cmp(&second.category.is_none())
+            .then(second.spending.cmp(&first.spending))
+            .then(first.category.cmp(&second.category))
+    });
+    Ok(category_spendings)
 }
 
 /* View spendings of a group chat for a specific currency.
  * Retrieves all spendings, gets current balances, and returns:
  * Total group spending, total individual spendings, and total individual payments
  */
-fn retrieve_spending_data_by_currency(
+async fn retrieve_spending_data_by_currency(
+    store: &Store,
     chat_id: &str,
     currency: &str,
 ) -> Result<SpendingData, ProcessError> {
-    let default_currency = match get_chat_setting(chat_id, ChatSetting::DefaultCurrency(None))? {
-        ChatSetting::DefaultCurrency(Some(curr)) => curr,
-        _ => CURRENCY_CODE_DEFAULT.to_string(),
-    };
+    let default_currency =
+        match get_chat_setting(store, chat_id, ChatSetting::DefaultCurrency(None)).await? {
+            ChatSetting::DefaultCurrency(Some(curr)) => curr,
+            _ => CURRENCY_CODE_DEFAULT.to_string(),
+        };
     if default_currency == currency && currency != CURRENCY_CODE_DEFAULT {
-        return retrieve_spending_data_by_default_currency(chat_id, currency);
+        return retrieve_spending_data_by_default_currency(store, chat_id, currency).await;
     }
 
-    let spendings = retrieve_chat_spendings_currency(chat_id, currency)?;
-    let balances = get_chat_balances_currency(chat_id, currency)?;
+    let spendings = store
+        .retrieve_chat_spendings_currency(chat_id, currency)
+        .await?;
+    let balances = store.get_chat_balances_currency(chat_id, currency).await?;
 
     let mut group_spending = 0;
     let mut user_spendings: Vec<UserSpending> = Vec::new();
@@ -608,6 +1302,7 @@ fn retrieve_spending_data_by_currency(
         currency: currency.to_string(),
         group_spending,
         user_spendings,
+        category_spendings: Vec::new(),
     })
 }
 
@@ -615,14 +1310,21 @@ fn retrieve_spending_data_by_currency(
  * Retrieves all spendings, gets current balances, and returns:
  * Total group spending, total individual spendings, and total individual payments
  */
-fn retrieve_spending_data_by_default_currency(
+async fn retrieve_spending_data_by_default_currency(
+    store: &Store,
     chat_id: &str,
     currency: &str,
 ) -> Result<SpendingData, ProcessError> {
-    let spendings_curr = retrieve_chat_spendings_currency(chat_id, currency)?;
-    let spendings_nil = retrieve_chat_spendings_currency(chat_id, CURRENCY_CODE_DEFAULT)?;
-    let mut balances_curr = get_chat_balances_currency(chat_id, currency)?;
-    let mut balances_nil = get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)?;
+    let spendings_curr = store
+        .retrieve_chat_spendings_currency(chat_id, currency)
+        .await?;
+    let spendings_nil = store
+        .retrieve_chat_spendings_currency(chat_id, CURRENCY_CODE_DEFAULT)
+        .await?;
+    let mut balances_curr = store.get_chat_balances_currency(chat_id, currency).await?;
+    let mut balances_nil = store
+        .get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)
+        .await?;
 
     let mut group_spending = 0;
     let mut user_spendings: Vec<UserSpending> = Vec::new();
@@ -731,6 +1433,7 @@ fn retrieve_spending_data_by_default_currency(
         currency: currency.to_string(),
         group_spending,
         user_spendings,
+        category_spendings: Vec::new(),
     })
 }
 
@@ -738,15 +1441,18 @@ fn retrieve_spending_data_by_default_currency(
  * Only called if currency conversion is enabled.
  * Retrieves all spendings, gets current balances, converts them.
  */
-async fn retrieve_spending_data_converted(chat_id: &str) -> Result<SpendingData, ProcessError> {
-    let mut spendings = retrieve_chat_spendings(chat_id)?;
-    let mut balances = get_chat_balances(chat_id)?;
+async fn retrieve_spending_data_converted(
+    store: &Store,
+    chat_id: &str,
+) -> Result<SpendingData, ProcessError> {
+    let mut spendings = store.retrieve_chat_spendings(chat_id).await?;
+    let mut balances = store.get_chat_balances(chat_id).await?;
 
-    let default_currency = get_default_currency(chat_id)?;
+    let default_currency = store.get_default_currency(chat_id).await?;
     let mut group_spending = 0;
     let mut user_spendings: Vec<UserSpending> = Vec::new();
     for spending_currency in &mut spendings {
-        if spending_currency.len() == 0 {
+        if spending_currency.is_empty() {
             continue;
         }
 
@@ -768,7 +1474,7 @@ async fn retrieve_spending_data_converted(chat_id: &str) -> Result<SpendingData,
         let mut empty_balances: Vec<UserBalance> = Vec::new();
         let balances_currency: &mut Vec<UserBalance> = match balances
             .iter_mut()
-            .find(|bal| bal.len() > 0 && bal[0].currency == *currency)
+            .find(|bal| !bal.is_empty() && bal[0].currency == *currency)
         {
             Some(bal) => bal,
             None => &mut empty_balances,
@@ -780,15 +1486,12 @@ async fn retrieve_spending_data_converted(chat_id: &str) -> Result<SpendingData,
                 .position(|bal| bal.username == spending.username);
 
             let mut paid_amount = spending.balance;
-            match balance_index {
-                Some(index) => {
-                    paid_amount += balances_currency[index].balance;
-                    balances_currency[index].balance = 0;
-                }
-                None => {}
+            if let Some(index) = balance_index {
+                paid_amount += balances_currency[index].balance;
+                balances_currency[index].balance = 0;
             }
 
-            let mut spending_amount = spending.balance.clone();
+            let mut spending_amount = spending.balance;
 
             if should_convert {
                 spending_amount = convert_currency(
@@ -854,27 +1557,32 @@ async fn retrieve_spending_data_converted(chat_id: &str) -> Result<SpendingData,
         currency: default_currency.to_string(),
         group_spending,
         user_spendings,
+        category_spendings: Vec::new(),
     })
 }
 
 /* Retrieves a group chat setting.
 */
-pub fn get_chat_setting(chat_id: &str, setting: ChatSetting) -> Result<ChatSetting, ProcessError> {
+pub async fn get_chat_setting(
+    store: &Store,
+    chat_id: &str,
+    setting: ChatSetting,
+) -> Result<ChatSetting, ProcessError> {
     match setting {
         ChatSetting::TimeZone(_) => {
-            let time_zone = get_time_zone(chat_id)?;
+            let time_zone = store.get_time_zone(chat_id).await?;
             Ok(ChatSetting::TimeZone(Some(time_zone)))
         }
         ChatSetting::DefaultCurrency(_) => {
-            let currency = get_default_currency(chat_id)?;
+            let currency = store.get_default_currency(chat_id).await?;
             Ok(ChatSetting::DefaultCurrency(Some(currency)))
         }
         ChatSetting::CurrencyConversion(_) => {
-            let convert = get_currency_conversion(chat_id)?;
+            let convert = store.get_currency_conversion(chat_id).await?;
             Ok(ChatSetting::CurrencyConversion(Some(convert)))
         }
         ChatSetting::EraseMessages(_) => {
-            let erase = get_erase_messages(chat_id)?;
+            let erase = store.get_erase_messages(chat_id).await?;
             Ok(ChatSetting::EraseMessages(Some(erase)))
         }
     }
@@ -882,29 +1590,30 @@ pub fn get_chat_setting(chat_id: &str, setting: ChatSetting) -> Result<ChatSetti
 
 /* Sets a group chat setting.
 */
-pub async fn set_chat_setting(chat_id: &str, setting: ChatSetting) -> Result<(), ProcessError> {
+pub async fn set_chat_setting(
+    store: &Store,
+    chat_id: &str,
+    setting: ChatSetting,
+) -> Result<(), ProcessError> {
     match setting {
         ChatSetting::TimeZone(time_zone) => {
             if let Some(time_zone) = time_zone {
-                set_time_zone(chat_id, &time_zone)?;
+                store.set_time_zone(chat_id, &time_zone).await?;
             }
         }
         ChatSetting::DefaultCurrency(currency) => {
             if let Some(currency) = currency {
-                set_default_currency(chat_id, &currency)?;
+                store.set_default_currency(chat_id, &currency).await?;
             }
         }
         ChatSetting::CurrencyConversion(convert) => {
             if let Some(convert) = convert {
-                set_currency_conversion(chat_id, convert)?;
-
-                // If currency conversion is updated, need to update balances
-                update_balances(chat_id, Vec::new())?;
+                store.set_currency_conversion(chat_id, convert).await?;
             }
         }
         ChatSetting::EraseMessages(erase) => {
             if let Some(erase) = erase {
-                set_erase_messages(chat_id, erase)?;
+                store.set_erase_messages(chat_id, erase).await?;
             }
         }
     }
@@ -913,34 +1622,42 @@ pub async fn set_chat_setting(chat_id: &str, setting: ChatSetting) -> Result<(),
 
 /* Changes the default currency of a group chat.
  * Also handles all the conversion logic for past payments.
+ * Payments, balances, spendings and settings are all updated atomically.
  */
 pub async fn update_chat_default_currency(
+    store: &Store,
     chat_id: &str,
     currency: &str,
 ) -> Result<(), ProcessError> {
-    let old_currency = get_default_currency(chat_id)?;
+    let old_currency = store.get_default_currency(chat_id).await?;
 
     // Update all payments to old currency
-    let payments = get_chat_payments_details(chat_id);
-    let mut changes: Vec<UserBalance> = Vec::new();
+    let payments = store.get_chat_payments_details(chat_id).await;
+    let mut update = LedgerUpdate::default();
 
     match payments {
         Ok(payments) => {
             for payment in payments {
                 if payment.payment.currency == CURRENCY_CODE_DEFAULT {
-                    update_payment_entry(
-                        &payment.payment_id,
-                        None,
-                        None,
-                        Some(&old_currency),
-                        None,
-                        None,
-                    )?;
+                    update.payments.push(PaymentChange::Update {
+                        payment_id: payment.payment_id,
+                        description: None,
+                        timestamp: None,
+                        creditor: None,
+                        currency: Some(old_currency.clone()),
+                        total: None,
+                        debts: None,
+                        items: None,
+                        category: None,
+                        payers: None,
+                    });
                 }
             }
 
             // Update all balances to old currency
-            let balances = get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)?;
+            let balances = store
+                .get_chat_balances_currency(chat_id, CURRENCY_CODE_DEFAULT)
+                .await?;
             for balance in balances {
                 let change_sub = UserBalance {
                     username: balance.username.clone(),
@@ -952,12 +1669,12 @@ pub async fn update_chat_default_currency(
                     currency: old_currency.clone(),
                     balance: balance.balance,
                 };
-                changes.extend(vec![change_sub, change_add]);
+                update.balances.extend(vec![change_sub, change_add]);
             }
 
             // Update all spendings to old currency
-            let mut spendings_changes: Vec<UserBalance> = Vec::new();
-            let spendings = retrieve_spending_data_by_currency(chat_id, CURRENCY_CODE_DEFAULT)?;
+            let spendings =
+                retrieve_spending_data_by_currency(store, chat_id, CURRENCY_CODE_DEFAULT).await?;
             for spending in spendings.user_spendings {
                 let change_sub = UserBalance {
                     username: spending.username.clone(),
@@ -969,10 +1686,8 @@ pub async fn update_chat_default_currency(
                     currency: old_currency.clone(),
                     balance: spending.spending,
                 };
-                spendings_changes.extend(vec![change_sub, change_add]);
+                update.spendings.extend(vec![change_sub, change_add]);
             }
-
-            update_chat_spendings(chat_id, spendings_changes)?;
         }
         Err(_) => {
             // This means that there were no payments found
@@ -980,21 +1695,289 @@ pub async fn update_chat_default_currency(
     }
 
     // Update default currency in settings. If now NIL, disable currency conversion.
-    set_default_currency(chat_id, &currency)?;
+    update.default_currency = Some(currency.to_string());
     if currency == CURRENCY_CODE_DEFAULT {
-        set_currency_conversion(chat_id, false)?;
+        update.currency_conversion = Some(false);
     }
 
-    // Finally, update balances and debts
-    update_balances(chat_id, changes)?;
+    // Finally, apply everything at once
+    store.apply_ledger_update(chat_id, update).await?;
 
     Ok(())
 }
 
+// Number of times the ledger is read again if payments change in the middle of an audit
+const LEDGER_AUDIT_ATTEMPTS: usize = 3;
+
+// Ledger amounts by lowercase username and currency, together with the username to display
+type LedgerTally = BTreeMap<(String, String), (String, i64)>;
+
+fn tally_ledger(tally: &mut LedgerTally, username: &str, currency: &str, amount: i64) {
+    tally
+        .entry((username.to_lowercase(), currency.to_string()))
+        .or_insert_with(|| (username.to_string(), 0))
+        .1 += amount;
+}
+
+fn tally_user_balances(balances: Vec<Vec<UserBalance>>) -> LedgerTally {
+    let mut tally = LedgerTally::new();
+    for balance in balances.into_iter().flatten() {
+        tally_ledger(
+            &mut tally,
+            &balance.username,
+            &balance.currency,
+            balance.balance,
+        );
+    }
+    tally
+}
+
+// Lists every user and currency where the stored amount differs from the expected one
+fn compare_ledger(
+    expected: &LedgerTally,
+    actual: &LedgerTally,
+    counter: LedgerCounter,
+) -> Vec<LedgerDiscrepancy> {
+    let keys: BTreeSet<&(String, String)> = expected.keys().chain(actual.keys()).collect();
+    keys.into_iter()
+        .filter_map(|key| {
+            let expected_entry = expected.get(key);
+            let actual_entry = actual.get(key);
+            let expected_amount = expected_entry.map_or(0, |entry| entry.1);
+            let actual_amount = actual_entry.map_or(0, |entry| entry.1);
+            if expected_amount == actual_amount {
+                return None;
+            }
+
+            // Stored usernames are the preferred ones, so use them if possible
+            let (username, _) = actual_entry.or(expected_entry)?;
+            Some(LedgerDiscrepancy {
+                username: username.to_string(),
+                currency: key.1.to_string(),
+                counter: counter.clone(),
+                expected: expected_amount,
+                actual: actual_amount,
+            })
+        })
+        .collect()
+}
+
+async fn retrieve_all_payments(
+    store: &Store,
+    chat_id: &str,
+) -> Result<Vec<UserPayment>, ProcessError> {
+    match store.get_chat_payments_details(chat_id).await {
+        Ok(payments) => Ok(payments),
+        Err(CrudError::NoPaymentsError()) => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/* Audits the balances and spendings of a group chat against its payments.
+ * Execution flow: Replays every payment to recompute balances and spendings,
+ * and compares them with the stored ones, per user and currency.
+ * Only users still in the chat are compared, as only theirs are stored totals that can be read.
+ * Forgotten users keep their spendings, which come back if they rejoin.
+ * If repair is set, the differences are applied atomically as a single ledger update.
+ * Only differences are applied, so payments added in the meantime are not lost.
+ */
+pub async fn audit_ledger(
+    store: &Store,
+    chat_id: &str,
+    repair: bool,
+) -> Result<LedgerAudit, ProcessError> {
+    for _ in 0..LEDGER_AUDIT_ATTEMPTS {
+        let payments = retrieve_all_payments(store, chat_id).await?;
+        let users: BTreeSet<String> = store.get_chat_users(chat_id).await?.into_iter().collect();
+        let actual_balances = tally_user_balances(store.get_chat_balances(chat_id).await?);
+        let actual_spendings = tally_user_balances(store.retrieve_chat_spendings(chat_id).await?);
+
+        // A payment made while reading would show up as a discrepancy, so read again
+        if retrieve_all_payments(store, chat_id).await? != payments {
+            continue;
+        }
+
+        // Replay payments, in the same way as self::add_payment
+        let mut expected_balances = LedgerTally::new();
+        let mut expected_spendings = LedgerTally::new();
+        for UserPayment { payment, .. } in payments.iter() {
+            for (user, amount) in payment.debts.iter() {
+                tally_ledger(&mut expected_spendings, user, &payment.currency, *amount);
+                tally_ledger(
+                    &mut expected_balances,
+                    user,
+                    &payment.currency,
+                    amount.neg(),
+                );
+            }
+            for (user, amount) in payment.paid_amounts().iter() {
+                tally_ledger(&mut expected_balances, user, &payment.currency, *amount);
+            }
+        }
+        expected_balances.retain(|(user, _), _| users.contains(user));
+        expected_spendings.retain(|(user, _), _| users.contains(user));
+
+        let mut discrepancies =
+            compare_ledger(&expected_balances, &actual_balances, LedgerCounter::Balance);
+        discrepancies.extend(compare_ledger(
+            &expected_spendings,
+            &actual_spendings,
+            LedgerCounter::Spending,
+        ));
+
+        let repaired = repair && !discrepancies.is_empty();
+        if repaired {
+            let mut update = LedgerUpdate::default();
+            for discrepancy in discrepancies.iter() {
+                let change = UserBalance {
+                    username: discrepancy.username.clone(),
+                    currency: discrepancy.currency.clone(),
+                    balance: discrepancy.expected - discrepancy.actual,
+                };
+                match discrepancy.counter {
+                    LedgerCounter::Balance => update.balances.push(change),
+                    LedgerCounter::Spending => update.spendings.push(change),
+                }
+            }
+            store.apply_ledger_update(chat_id, update).await?;
+        }
+
+        return Ok(LedgerAudit {
+            payments: payments.len(),
+            discrepancies,
+            repaired,
+        });
+    }
+
+    Err(ProcessError::LedgerChangedError())
+}
+
+/* Exports all records of a group chat.
+ * Execution flow: Retrieve all payments, earliest first, with all non-zero balances and spendings.
+ */
+pub async fn export_ledger(
+    store: &Store,
+    chat_id: &str,
+    sender_id: &str,
+    sender_username: Option<&str>,
+) -> Result<LedgerExport, ProcessError> {
+    auto_update_user(store, chat_id, sender_id, sender_username).await?;
+
+    let mut payments = retrieve_all_payments(store, chat_id).await?;
+    payments.reverse();
+    let balances = store
+        .get_chat_balances(chat_id)
+        .await?
+        .into_iter()
+        .flatten()
+        .filter(|balance| balance.balance != 0)
+        .collect();
+    let spendings = store
+        .retrieve_chat_spendings(chat_id)
+        .await?
+        .into_iter()
+        .flatten()
+        .filter(|spending| spending.balance != 0)
+        .collect();
+
+    Ok(LedgerExport {
+        payments,
+        balances,
+        spendings,
+    })
+}
+
+/* Resets a group chat, deleting all of its records.
+ * Execution flow: Delete all payments, history, recurring payments, balances, spendings,
+ * currencies and settings.
+ * Settings return to their defaults the next time the chat is used.
+ */
+pub async fn reset_chat(store: &Store, chat_id: &str) -> Result<(), ProcessError> {
+    store.reset_chat(chat_id).await?;
+    Ok(())
+}
+
+/* Migrates a group chat to a new chat ID, moving all of its records.
+ * Execution flow: Move all payments, history, recurring payments, balances, spendings,
+ * currencies and settings.
+ * Called when a group is upgraded to a supergroup, and may be called again for the same migration.
+ */
+pub async fn migrate_chat(
+    store: &Store,
+    chat_id: &str,
+    new_chat_id: &str,
+) -> Result<(), ProcessError> {
+    store.migrate_chat(chat_id, new_chat_id).await?;
+    Ok(())
+}
+
+/* Forgets a user, so that their username is no longer kept in any group chat.
+ * Execution flow: Check that the user has no unsettled balances in any chat, then forget them.
+ * Returns the number of chats the user was removed from.
+ */
+pub async fn forget_user(store: &Store, sender_username: &str) -> Result<usize, ProcessError> {
+    let chats = store.get_user_chats(sender_username).await?;
+    for chat_id in &chats {
+        let balances = store.get_chat_balances(chat_id).await?;
+        if balances.iter().flatten().any(|balance| {
+            balance.balance != 0 && is_username_equal(&balance.username, sender_username)
+        }) {
+            return Err(ProcessError::UnsettledBalancesError());
+        }
+    }
+
+    if !chats.is_empty() {
+        store.forget_user(sender_username).await?;
+    }
+    Ok(chats.len())
+}
+
+/* Tracks the username of a user by their user ID, the canonical identity of the user.
+ * Execution flow: Compare with the username last seen for the user ID,
+ * and rename the user in all their chats if it has changed.
+ * Returns the previous username if the user was renamed.
+ */
+pub async fn update_user_identity(
+    store: &Store,
+    sender_id: &str,
+    sender_username: &str,
+) -> Result<Option<String>, ProcessError> {
+    let previous = store.get_tracked_username(sender_id).await?;
+    if previous.as_deref() == Some(sender_username) {
+        return Ok(None);
+    }
+
+    if let Some(previous) = &previous {
+        store.rename_user(previous, sender_username).await?;
+    }
+    store.track_username(sender_id, sender_username).await?;
+    Ok(previous)
+}
+
+/* Keeps the latest name of a member without a username, to display them by.
+ * Such members are identified by their user ID only, so a change in name moves no records.
+ * Called on every message from such a member, so the name is only written when it has changed.
+ */
+pub async fn update_member_name(
+    store: &Store,
+    member: &str,
+    name: &str,
+) -> Result<(), ProcessError> {
+    let names = store.get_member_names(&[member.to_string()]).await?;
+    if names.get(&member.to_lowercase()).map(String::as_str) != Some(name) {
+        store.set_member_name(member, name).await?;
+    }
+    Ok(())
+}
+
 /* Asserts that a user has not exceeded the rate limit.
  */
-pub fn assert_rate_limit(user_id: &str, timestamp: i64) -> Result<(), ProcessError> {
-    let status = is_request_limit_exceeded(user_id, timestamp)?;
+pub async fn assert_rate_limit(
+    store: &Store,
+    user_id: &str,
+    timestamp: i64,
+) -> Result<(), ProcessError> {
+    let status = store.is_request_limit_exceeded(user_id, timestamp).await?;
     if status {
         Err(ProcessError::CrudError(
             CrudError::RequestLimitExceededError(),
@@ -1003,3 +1986,1429 @@ pub fn assert_rate_limit(user_id: &str, timestamp: i64) -> Result<(), ProcessErr
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::bot::{redis::PaymentAction, sqlite::SqliteStore, store::MemoryStore};
+
+    use super::*;
+
+    fn debt(debtor: &str, creditor: &str, amount: i64) -> Debt {
+        Debt {
+            debtor: debtor.to_string(),
+            creditor: creditor.to_string(),
+            currency: "USD".to_string(),
+            amount,
+        }
+    }
+
+    async fn add_test_payment(store: &Store, chat_id: &str) -> Vec<Debt> {
+        add_payment(
+            store,
+            chat_id.to_string(),
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            1704067200,
+            "test_payment",
+            "Test_User_1",
+            "USD",
+            900,
+            vec![
+                ("Test_User_1".to_string(), 300),
+                ("Test_User_2".to_string(), 300),
+                ("Test_User_3".to_string(), 300),
+            ],
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_payment() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_add_payment";
+
+        let mut debts = add_test_payment(&store, chat_id).await;
+        debts.sort_by(|a, b| a.debtor.cmp(&b.debtor));
+        assert_eq!(
+            debts,
+            vec![
+                debt("Test_User_2", "Test_User_1", 300),
+                debt("Test_User_3", "Test_User_1", 300),
+            ]
+        );
+
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment.total, 900);
+
+        let spendings = store
+            .retrieve_chat_spendings_currency(chat_id, "USD")
+            .await
+            .unwrap();
+        assert_eq!(spendings.len(), 3);
+        assert!(spendings.iter().all(|spending| spending.balance == 300));
+    }
+
+    #[tokio::test]
+    async fn test_payment_categories() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_payment_categories";
+
+        add_test_payment(&store, chat_id).await;
+        for (timestamp, total, category) in [
+            (1704067201, 300, "Food"),
+            (1704067202, 600, "Groceries"),
+            (1704067203, 200, "groceries"),
+        ] {
+            add_payment(
+                &store,
+                chat_id.to_string(),
+                "Test_User_1".to_string(),
+                "processor_user_1".to_string(),
+                timestamp,
+                "test_payment",
+                "Test_User_1",
+                "USD",
+                total,
+                vec![("Test_User_2".to_string(), total)],
+                Vec::new(),
+                Some(category.to_string()),
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Custom categories are only listed and summed once, regardless of case
+        let categories = retrieve_chat_categories(&store, chat_id).await.unwrap();
+        assert_eq!(categories.len(), PAYMENT_CATEGORIES.len() + 1);
+        assert_eq!(
+            categories.last().unwrap().to_lowercase(),
+            "groceries".to_string()
+        );
+
+        let spending_data = retrieve_spending_data(
+            &store,
+            chat_id,
+            StatementOption::Currency("USD".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            spending_data.category_spendings,
+            vec![
+                CategorySpending {
+                    category: Some("Groceries".to_string()),
+                    spending: 800,
+                },
+                CategorySpending {
+                    category: Some("Food".to_string()),
+                    spending: 300,
+                },
+                CategorySpending {
+                    category: None,
+                    spending: 900,
+                },
+            ]
+        );
+
+        // Totals follow payments as they are recategorised, deleted and restored
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        edit_payment(
+            &store,
+            chat_id,
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            &payments[2].payment_id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(Some("Travel")),
+            None,
+        )
+        .await
+        .unwrap();
+        delete_payment(
+            &store,
+            chat_id,
+            &payments[0].payment_id,
+            "processor_user_1",
+            "Test_User_1",
+            "Test_User_1",
+            1704067300,
+        )
+        .await
+        .unwrap();
+
+        let categories = retrieve_chat_categories(&store, chat_id).await.unwrap();
+        assert_eq!(
+            categories[PAYMENT_CATEGORIES.len()..],
+            ["Groceries".to_string(), "Travel".to_string()]
+        );
+        let spending_data = retrieve_spending_data(
+            &store,
+            chat_id,
+            StatementOption::Currency("USD".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            spending_data.category_spendings,
+            vec![
+                CategorySpending {
+                    category: Some("Groceries".to_string()),
+                    spending: 600,
+                },
+                CategorySpending {
+                    category: Some("Travel".to_string()),
+                    spending: 300,
+                },
+                CategorySpending {
+                    category: None,
+                    spending: 900,
+                },
+            ]
+        );
+
+        restore_payment(
+            &store,
+            chat_id,
+            &payments[0].payment_id,
+            "processor_user_1",
+            "Test_User_1",
+        )
+        .await
+        .unwrap();
+        let spending_data = retrieve_spending_data(
+            &store,
+            chat_id,
+            StatementOption::Currency("USD".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(spending_data.category_spendings[0].spending, 800);
+    }
+
+    #[tokio::test]
+    async fn test_payment_payers() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_payment_payers";
+
+        let debts = add_payment(
+            &store,
+            chat_id.to_string(),
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            1704067200,
+            "test_payment",
+            "Test_User_1",
+            "USD",
+            900,
+            vec![
+                ("Test_User_1".to_string(), 300),
+                ("Test_User_2".to_string(), 300),
+                ("Test_User_3".to_string(), 300),
+            ],
+            Vec::new(),
+            None,
+            vec![
+                ("Test_User_1".to_string(), 600),
+                ("Test_User_2".to_string(), 300),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(debts, vec![debt("Test_User_3", "Test_User_1", 300)]);
+
+        // Payers that do not add up to the total are rejected on add too
+        assert_eq!(
+            add_payment(
+                &store,
+                chat_id.to_string(),
+                "Test_User_1".to_string(),
+                "processor_user_1".to_string(),
+                1704067300,
+                "test_payment_2",
+                "Test_User_1",
+                "USD",
+                900,
+                vec![("Test_User_2".to_string(), 900)],
+                Vec::new(),
+                None,
+                vec![
+                    ("Test_User_1".to_string(), 600),
+                    ("Test_User_2".to_string(), i64::MAX),
+                ],
+            )
+            .await,
+            Err(ProcessError::PayersTotalError())
+        );
+
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        let payment_id = &payments[0].payment_id;
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment.payers.len(), 2);
+
+        // Changing who paid how much moves the balances between the payers
+        let debts = edit_payment(
+            &store,
+            chat_id,
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            payment_id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(vec![
+                ("Test_User_1".to_string(), 300),
+                ("Test_User_2".to_string(), 600),
+            ]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(debts, Some(vec![debt("Test_User_3", "Test_User_2", 300)]));
+        assert!(audit_ledger(&store, chat_id, false)
+            .await
+            .unwrap()
+            .discrepancies
+            .is_empty());
+
+        // A new total that the kept payers do not add up to is rejected
+        assert_eq!(
+            edit_payment(
+                &store,
+                chat_id,
+                "Test_User_1".to_string(),
+                "processor_user_1".to_string(),
+                payment_id,
+                None,
+                None,
+                None,
+                Some("EUR"),
+                Some(&1200),
+                Some(vec![
+                    ("Test_User_1".to_string(), 400),
+                    ("Test_User_2".to_string(), 400),
+                    ("Test_User_3".to_string(), 400),
+                ]),
+                None,
+                None,
+                None,
+            )
+            .await,
+            Err(ProcessError::PayersTotalError())
+        );
+        assert_eq!(
+            store.get_payment_entry(payment_id).await.unwrap().total,
+            900
+        );
+
+        let debts = delete_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_1",
+            "Test_User_1",
+            "@Test_User_1",
+            1704067300,
+        )
+        .await
+        .unwrap();
+        assert!(debts.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_view_payments_page() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_view_payments_page";
+
+        for minute in 0..7 {
+            add_payment(
+                &store,
+                chat_id.to_string(),
+                "Test_User_1".to_string(),
+                "processor_user_1".to_string(),
+                1704067200 + minute * 60,
+                &format!("test_payment_{minute}"),
+                "Test_User_1",
+                "USD",
+                200,
+                vec![
+                    ("Test_User_1".to_string(), 100),
+                    ("Test_User_2".to_string(), 100),
+                ],
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Latest payment first, one page at a time
+        let page = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 7);
+        assert_eq!(page.payments.len(), PAYMENTS_PAGE_SIZE);
+        assert_eq!(page.payments[0].payment.description, "test_payment_6");
+        assert!(page.next.is_some());
+
+        let page = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            page.next,
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 7);
+        assert_eq!(
+            page.payments
+                .iter()
+                .map(|payment| payment.payment.description.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["test_payment_1", "test_payment_0"]
+        );
+        assert_eq!(page.next, None);
+    }
+
+    #[tokio::test]
+    async fn test_edit_payment_date() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_edit_payment_date";
+
+        for day in 0..3 {
+            add_payment(
+                &store,
+                chat_id.to_string(),
+                "Test_User_1".to_string(),
+                "processor_user_1".to_string(),
+                1704067200 + day * 86400,
+                &format!("test_payment_{day}"),
+                "Test_User_1",
+                "USD",
+                200,
+                vec![
+                    ("Test_User_1".to_string(), 100),
+                    ("Test_User_2".to_string(), 100),
+                ],
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+        }
+        let page = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap();
+        let payment_id = &page.payments[1].payment_id;
+
+        // Backdating a payment does not touch balances, but moves it in the list
+        let res = edit_payment(
+            &store,
+            chat_id,
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            payment_id,
+            None,
+            Some(1703980800),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(res.is_none());
+
+        let page = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            page.payments
+                .iter()
+                .map(|payment| payment.payment.description.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["test_payment_2", "test_payment_0", "test_payment_1"]
+        );
+        assert_eq!(page.payments[2].payment.timestamp, 1703980800);
+    }
+
+    #[tokio::test]
+    async fn test_edit_payment() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_edit_payment";
+
+        add_test_payment(&store, chat_id).await;
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        let payment_id = &payments[0].payment_id;
+
+        // Editing only the description does not touch balances
+        let res = edit_payment(
+            &store,
+            chat_id,
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            payment_id,
+            Some("edited_payment"),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(res.is_none());
+
+        // Editing the debts undoes the previous split
+        let debts = edit_payment(
+            &store,
+            chat_id,
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            payment_id,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(vec![
+                ("Test_User_1".to_string(), 450),
+                ("Test_User_2".to_string(), 450),
+            ]),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(debts, Some(vec![debt("Test_User_2", "Test_User_1", 450)]));
+
+        let payment = store.get_payment_entry(payment_id).await.unwrap();
+        assert_eq!(payment.description, "edited_payment");
+    }
+
+    #[tokio::test]
+    async fn test_delete_payment() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_delete_payment";
+
+        add_test_payment(&store, chat_id).await;
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+
+        let debts = delete_payment(
+            &store,
+            chat_id,
+            &payments[0].payment_id,
+            "processor_user_1",
+            "Test_User_1",
+            "@Test_User_1",
+            1700000000,
+        )
+        .await
+        .unwrap();
+        assert!(debts.is_empty());
+
+        assert_eq!(
+            view_payments(
+                &store,
+                chat_id,
+                "processor_user_1",
+                Some("Test_User_1"),
+                None,
+            )
+            .await,
+            Err(ProcessError::CrudError(CrudError::NoPaymentsError()))
+        );
+        assert!(store
+            .retrieve_chat_spendings_currency(chat_id, "USD")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_restore_purge_payment() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_restore_payment";
+        let deleted_at = 1700000000;
+
+        add_test_payment(&store, chat_id).await;
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        let payment_id = &payments[0].payment_id;
+        delete_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_2",
+            "Test_User_2",
+            "@Test_User_2",
+            deleted_at,
+        )
+        .await
+        .unwrap();
+
+        let trash = view_trash(&store, chat_id, deleted_at).await.unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].payment_id, *payment_id);
+        assert_eq!(trash[0].deleted_by, "@Test_User_2");
+
+        // Restoring brings back the payment with its balances and spendings
+        let mut debts = restore_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_2",
+            "Test_User_2",
+        )
+        .await
+        .unwrap();
+        debts.sort_by(|a, b| a.debtor.cmp(&b.debtor));
+        assert_eq!(
+            debts,
+            vec![
+                debt("Test_User_2", "Test_User_1", 300),
+                debt("Test_User_3", "Test_User_1", 300),
+            ]
+        );
+        assert_eq!(
+            view_payments(
+                &store,
+                chat_id,
+                "processor_user_1",
+                Some("Test_User_1"),
+                None,
+            )
+            .await
+            .unwrap()
+            .payments,
+            payments
+        );
+        assert!(view_trash(&store, chat_id, deleted_at)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(audit_ledger(&store, chat_id, false)
+            .await
+            .unwrap()
+            .discrepancies
+            .is_empty());
+
+        // Payments are purged once the retention period is over
+        delete_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_2",
+            "Test_User_2",
+            "@Test_User_2",
+            deleted_at,
+        )
+        .await
+        .unwrap();
+        let retention = get_trash_retention_days() * 24 * 60 * 60;
+        assert_eq!(
+            purge_trash(&store, chat_id, deleted_at + retention - 1)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            purge_trash(&store, chat_id, deleted_at + retention)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(view_trash(&store, chat_id, deleted_at + retention)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            restore_payment(
+                &store,
+                chat_id,
+                payment_id,
+                "processor_user_2",
+                "Test_User_2",
+            )
+            .await,
+            Err(ProcessError::CrudError(CrudError::NoSuchPaymentError()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_history() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_payment_history";
+
+        add_test_payment(&store, chat_id).await;
+        let payments = view_payments(
+            &store,
+            chat_id,
+            "processor_user_1",
+            Some("Test_User_1"),
+            None,
+        )
+        .await
+        .unwrap()
+        .payments;
+        let payment_id = &payments[0].payment_id;
+
+        edit_payment(
+            &store,
+            chat_id,
+            "Test_User_2".to_string(),
+            "processor_user_2".to_string(),
+            payment_id,
+            Some("edited_payment"),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        delete_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_1",
+            "Test_User_1",
+            "@Test_User_1",
+            1700000000,
+        )
+        .await
+        .unwrap();
+        restore_payment(
+            &store,
+            chat_id,
+            payment_id,
+            "processor_user_2",
+            "Test_User_2",
+        )
+        .await
+        .unwrap();
+
+        // Every change is recorded with who made it, and the payment before and after
+        let history = view_payment_history(&store, payment_id).await.unwrap();
+        let actions: Vec<(PaymentAction, &str)> = history
+            .iter()
+            .map(|event| (event.action.clone(), event.user_id.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (PaymentAction::Add, "processor_user_1"),
+                (PaymentAction::Edit, "processor_user_2"),
+                (PaymentAction::Delete, "processor_user_1"),
+                (PaymentAction::Restore, "processor_user_2"),
+            ]
+        );
+        assert_eq!(
+            history[1].before.as_ref().unwrap().description,
+            "test_payment"
+        );
+        assert_eq!(
+            history[1].after.as_ref().unwrap().description,
+            "edited_payment"
+        );
+        assert_eq!(history[2].timestamp, 1700000000);
+        assert_eq!(history[2].after, None);
+
+        let activity = view_chat_activity(&store, chat_id, 2).await.unwrap();
+        assert_eq!(activity.len(), 2);
+        assert_eq!(activity[0].action, PaymentAction::Restore);
+        assert_eq!(activity[1].action, PaymentAction::Delete);
+    }
+
+    #[tokio::test]
+    async fn test_audit_ledger() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_audit_ledger";
+
+        // A chat without payments is consistent
+        let audit = audit_ledger(&store, chat_id, false).await.unwrap();
+        assert_eq!(audit.payments, 0);
+        assert!(audit.discrepancies.is_empty());
+
+        add_test_payment(&store, chat_id).await;
+        let audit = audit_ledger(&store, chat_id, false).await.unwrap();
+        assert_eq!(audit.payments, 1);
+        assert!(audit.discrepancies.is_empty());
+        assert!(!audit.repaired);
+
+        // Counters drift away from the payments
+        let update = LedgerUpdate {
+            balances: vec![UserBalance {
+                username: "test_user_2".to_string(),
+                currency: "USD".to_string(),
+                balance: 100,
+            }],
+            spendings: vec![UserBalance {
+                username: "Test_User_3".to_string(),
+                currency: "USD".to_string(),
+                balance: -300,
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        let audit = audit_ledger(&store, chat_id, false).await.unwrap();
+        assert_eq!(
+            audit.discrepancies,
+            vec![
+                LedgerDiscrepancy {
+                    username: "Test_User_2".to_string(),
+                    currency: "USD".to_string(),
+                    counter: LedgerCounter::Balance,
+                    expected: -300,
+                    actual: -200,
+                },
+                LedgerDiscrepancy {
+                    username: "Test_User_3".to_string(),
+                    currency: "USD".to_string(),
+                    counter: LedgerCounter::Spending,
+                    expected: 300,
+                    actual: 0,
+                },
+            ]
+        );
+        assert!(!audit.repaired);
+
+        // Repairing brings the counters back in line with the payments
+        let audit = audit_ledger(&store, chat_id, true).await.unwrap();
+        assert_eq!(audit.discrepancies.len(), 2);
+        assert!(audit.repaired);
+
+        let audit = audit_ledger(&store, chat_id, false).await.unwrap();
+        assert!(audit.discrepancies.is_empty());
+        let spendings = store
+            .retrieve_chat_spendings_currency(chat_id, "USD")
+            .await
+            .unwrap();
+        assert_eq!(spendings.len(), 3);
+        assert!(spendings.iter().all(|spending| spending.balance == 300));
+    }
+
+    #[tokio::test]
+    async fn test_export_ledger() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_export_ledger";
+
+        // A chat without payments has nothing to export
+        let export = export_ledger(&store, chat_id, "processor_user_1", Some("Test_User_1"))
+            .await
+            .unwrap();
+        assert!(export.payments.is_empty());
+        assert!(export.balances.is_empty());
+        assert!(export.spendings.is_empty());
+
+        add_test_payment(&store, chat_id).await;
+        add_payment(
+            &store,
+            chat_id.to_string(),
+            "Test_User_2".to_string(),
+            "processor_user_2".to_string(),
+            1704153600,
+            "test_payment_2",
+            "Test_User_2",
+            "USD",
+            300,
+            vec![("Test_User_1".to_string(), 300)],
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+
+        // Payments are exported earliest first, and settled balances are left out
+        let export = export_ledger(&store, chat_id, "processor_user_1", Some("Test_User_1"))
+            .await
+            .unwrap();
+        let descriptions: Vec<&str> = export
+            .payments
+            .iter()
+            .map(|payment| payment.payment.description.as_str())
+            .collect();
+        assert_eq!(descriptions, vec!["test_payment", "test_payment_2"]);
+        assert_eq!(export.balances.len(), 2);
+        assert!(export
+            .balances
+            .iter()
+            .all(|balance| balance.username != "Test_User_2"));
+        assert_eq!(export.spendings.len(), 3);
+    }
+    #[tokio::test]
+    async fn test_recurring_payment() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_recurring_payment";
+        let day = 86400;
+
+        let payment = Payment {
+            description: "test_rent".to_string(),
+            timestamp: 1704067200,
+            creditor: "Test_User_1".to_string(),
+            currency: "USD".to_string(),
+            total: 900,
+            debts: vec![
+                ("Test_User_1".to_string(), 450),
+                ("Test_User_2".to_string(), 450),
+            ],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        };
+        assert_eq!(
+            add_recurring_payment(
+                &store,
+                chat_id,
+                "Test_User_1",
+                "processor_user_1",
+                payment.timestamp,
+                &payment.description,
+                &payment.creditor,
+                &payment.currency,
+                payment.total,
+                payment.debts.clone(),
+                Vec::new(),
+                None,
+                Vec::new(),
+                RecurringFrequency::Daily,
+                Some(1704067200 - 1),
+            )
+            .await,
+            Err(ProcessError::RecurringNeverDueError())
+        );
+        let next = add_recurring_payment(
+            &store,
+            chat_id,
+            "Test_User_1",
+            "processor_user_1",
+            payment.timestamp,
+            &payment.description,
+            &payment.creditor,
+            &payment.currency,
+            payment.total,
+            payment.debts.clone(),
+            Vec::new(),
+            None,
+            Vec::new(),
+            RecurringFrequency::Daily,
+            Some(1704067200 + 20 * day),
+        )
+        .await
+        .unwrap();
+        assert_eq!(next, 1704067200);
+
+        // Not due yet
+        assert!(get_due_recurring_payments(&store, next - 1)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Missed occurrences are caught up on, a limited number at a time
+        let now = 1704067200 + 12 * day;
+        let due = get_due_recurring_payments(&store, now).await.unwrap();
+        assert_eq!(due.len(), 1);
+        let update = add_due_recurring_payment(&store, due[0].clone(), now)
+            .await
+            .unwrap();
+        assert_eq!(update.payments.len(), RECURRING_CATCH_UP_LIMIT);
+        assert_eq!(update.payments[1].timestamp, 1704067200 + day);
+        assert_eq!(update.next, Some(1704067200 + 10 * day));
+        assert_eq!(update.debts, vec![debt("Test_User_2", "Test_User_1", 4500)]);
+
+        let due = get_due_recurring_payments(&store, now).await.unwrap();
+        let update = add_due_recurring_payment(&store, due[0].clone(), now)
+            .await
+            .unwrap();
+        assert_eq!(update.payments.len(), 3);
+        assert_eq!(update.next, Some(1704067200 + 13 * day));
+        assert_eq!(
+            store
+                .get_chat_payments_details(chat_id)
+                .await
+                .unwrap()
+                .len(),
+            13
+        );
+
+        // Deleted once past its end, keeping the payments already added
+        let now = 1704067200 + 30 * day;
+        let due = get_due_recurring_payments(&store, now).await.unwrap();
+        let update = add_due_recurring_payment(&store, due[0].clone(), now)
+            .await
+            .unwrap();
+        assert_eq!(update.payments.len(), 8);
+        assert_eq!(update.next, None);
+        assert!(view_recurring_payments(&store, chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .get_chat_payments_details(chat_id)
+                .await
+                .unwrap()
+                .len(),
+            21
+        );
+
+        // An occurrence read before it was added is never added again
+        assert_eq!(
+            add_due_recurring_payment(&store, due[0].clone(), now)
+                .await
+                .err(),
+            Some(ProcessError::CrudError(
+                CrudError::NoSuchRecurringPaymentError()
+            ))
+        );
+        assert_eq!(
+            store
+                .get_chat_payments_details(chat_id)
+                .await
+                .unwrap()
+                .len(),
+            21
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reset_chat() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_reset_chat";
+        let other_chat_id = "processor_reset_chat_other";
+
+        add_test_payment(&store, chat_id).await;
+        add_test_payment(&store, other_chat_id).await;
+        set_chat_setting(
+            &store,
+            chat_id,
+            ChatSetting::TimeZone(Some("Asia/Singapore".to_string())),
+        )
+        .await
+        .unwrap();
+
+        reset_chat(&store, chat_id).await.unwrap();
+        assert!(store.get_chat_payments_details(chat_id).await.is_err());
+        assert!(view_chat_activity(&store, chat_id, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store.get_chat_balances(chat_id).await.unwrap().is_empty());
+        assert!(store
+            .retrieve_chat_spendings(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_user_chats("Test_User_1").await.unwrap(),
+            vec![other_chat_id.to_string()]
+        );
+
+        // Settings are back to default once the chat is used again
+        add_test_payment(&store, chat_id).await;
+        assert_eq!(store.get_time_zone(chat_id).await.unwrap(), "UTC");
+
+        // Other chats are left untouched
+        assert_eq!(
+            store
+                .get_chat_payments_details(other_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_chat() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_migrate_chat";
+        let new_chat_id = "processor_migrate_chat_new";
+
+        add_test_payment(&store, chat_id).await;
+        set_chat_setting(
+            &store,
+            chat_id,
+            ChatSetting::TimeZone(Some("Asia/Singapore".to_string())),
+        )
+        .await
+        .unwrap();
+        store
+            .set_dialogue(chat_id, "processor_user_1", "dialogue")
+            .await
+            .unwrap();
+        store
+            .set_dialogue(new_chat_id, "processor_user_2", "other")
+            .await
+            .unwrap();
+        let balances = store.get_chat_balances(chat_id).await.unwrap();
+
+        migrate_chat(&store, chat_id, new_chat_id).await.unwrap();
+        assert!(store.get_chat_payments_details(chat_id).await.is_err());
+        assert!(store.get_chat_balances(chat_id).await.unwrap().is_empty());
+        assert_eq!(
+            store
+                .get_chat_payments_details(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            store.get_chat_balances(new_chat_id).await.unwrap(),
+            balances
+        );
+        assert_eq!(
+            view_chat_activity(&store, new_chat_id, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            store.get_time_zone(new_chat_id).await.unwrap(),
+            "Asia/Singapore"
+        );
+        assert_eq!(
+            store
+                .get_dialogue(new_chat_id, "processor_user_1")
+                .await
+                .unwrap(),
+            Some("dialogue".to_string())
+        );
+        assert_eq!(
+            store
+                .get_dialogue(new_chat_id, "processor_user_2")
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            store.get_user_chats("Test_User_1").await.unwrap(),
+            vec![new_chat_id.to_string()]
+        );
+
+        // Migrating again, from the service message in the new chat, changes nothing
+        migrate_chat(&store, chat_id, new_chat_id).await.unwrap();
+        assert_eq!(
+            store
+                .get_chat_payments_details(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(audit_ledger(&store, new_chat_id, false)
+            .await
+            .unwrap()
+            .discrepancies
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_forget_user() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_forget_user";
+
+        // Users who were never added have nothing to forget
+        assert_eq!(forget_user(&store, "Test_User_4").await.unwrap(), 0);
+
+        add_test_payment(&store, chat_id).await;
+        assert_eq!(
+            forget_user(&store, "test_user_2").await,
+            Err(ProcessError::UnsettledBalancesError())
+        );
+
+        // Settle the debt, so that the balance of the user is zero
+        add_payment(
+            &store,
+            chat_id.to_string(),
+            "Test_User_2".to_string(),
+            "processor_user_2".to_string(),
+            1704153600,
+            "test_payback",
+            "Test_User_2",
+            "USD",
+            300,
+            vec![("Test_User_1".to_string(), 300)],
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(forget_user(&store, "test_user_2").await.unwrap(), 1);
+        assert!(store
+            .get_user_chats("Test_User_2")
+            .await
+            .unwrap()
+            .is_empty());
+        let spendings = store.retrieve_chat_spendings(chat_id).await.unwrap();
+        assert!(spendings
+            .iter()
+            .flatten()
+            .all(|spending| spending.username != "Test_User_2"));
+
+        // Payments still refer to the user
+        assert_eq!(
+            store
+                .get_chat_payments_details(chat_id)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_member_name() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let member = "#123456789";
+        let members = vec![member.to_string()];
+
+        update_member_name(&store, member, "Test User")
+            .await
+            .unwrap();
+        update_member_name(&store, member, "Test User")
+            .await
+            .unwrap();
+        update_member_name(&store, member, "Renamed User")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_member_names(&members).await.unwrap()[member],
+            "Renamed User"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forget_user_audit() {
+        let stores: [Store; 2] = [
+            Arc::new(MemoryStore::new()),
+            Arc::new(SqliteStore::open(":memory:").unwrap()),
+        ];
+        for store in stores {
+            let chat_id = "processor_forget_user_audit";
+            add_test_payment(&store, chat_id).await;
+            add_payment(
+                &store,
+                chat_id.to_string(),
+                "Test_User_2".to_string(),
+                "processor_user_2".to_string(),
+                1704153600,
+                "test_payback",
+                "Test_User_2",
+                "USD",
+                300,
+                vec![("Test_User_1".to_string(), 300)],
+                Vec::new(),
+                None,
+                Vec::new(),
+            )
+            .await
+            .unwrap();
+            forget_user(&store, "test_user_2").await.unwrap();
+
+            // The spendings of a forgotten user are neither a discrepancy nor repaired again
+            for _ in 0..2 {
+                let audit = audit_ledger(&store, chat_id, true).await.unwrap();
+                assert!(audit.discrepancies.is_empty());
+                assert!(!audit.repaired);
+            }
+
+            // Kept for when the user rejoins
+            store
+                .update_chat(chat_id, vec!["Test_User_2".to_string()])
+                .await
+                .unwrap();
+            assert!(audit_ledger(&store, chat_id, false)
+                .await
+                .unwrap()
+                .discrepancies
+                .is_empty());
+        }
+    }
+    #[tokio::test]
+    async fn test_update_user_identity() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let chat_id = "processor_user_identity";
+
+        // The first username seen is only tracked
+        assert_eq!(
+            update_user_identity(&store, "processor_user_2", "Test_User_2")
+                .await
+                .unwrap(),
+            None
+        );
+        add_test_payment(&store, chat_id).await;
+
+        // Another member mentions the new username before the user is seen with it
+        add_payment(
+            &store,
+            chat_id.to_string(),
+            "Test_User_1".to_string(),
+            "processor_user_1".to_string(),
+            1704153600,
+            "test_payment_2",
+            "New_User_2",
+            "USD",
+            300,
+            vec![("Test_User_1".to_string(), 300)],
+            Vec::new(),
+            None,
+            Vec::new(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            update_user_identity(&store, "processor_user_2", "New_User_2")
+                .await
+                .unwrap(),
+            Some("Test_User_2".to_string())
+        );
+        assert_eq!(
+            update_user_identity(&store, "processor_user_2", "New_User_2")
+                .await
+                .unwrap(),
+            None
+        );
+
+        // Both payments now refer to the new username, and the balances are merged
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments[0].payment.creditor, "New_User_2");
+        assert!(payments[1]
+            .payment
+            .debts
+            .contains(&("New_User_2".to_string(), 300)));
+        let balances: Vec<UserBalance> = store
+            .get_chat_balances(chat_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(balances.len(), 2);
+        assert!(balances
+            .iter()
+            .all(|balance| !is_username_equal(&balance.username, "Test_User_2")));
+        assert!(store
+            .get_user_chats("Test_User_2")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_user_chats("New_User_2").await.unwrap(),
+            vec![chat_id.to_string()]
+        );
+
+        // Audit agrees with the merged balances and spendings
+        let audit = audit_ledger(&store, chat_id, false).await.unwrap();
+        assert!(audit.discrepancies.is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use redis::{Commands, Connection, RedisResult};
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
 
 use super::BALANCE_KEY;
 
@@ -9,9 +9,11 @@ use super::BALANCE_KEY;
  * Has add, exists, get, update, and delete operations.
  */
 
-// Adds or updates a balance to Redis, only called when payment is created
-pub fn set_balance(
-    con: &mut Connection,
+// Sets a balance to Redis directly
+// Mainly for testing purposes, balances are otherwise only incremented
+#[allow(dead_code)]
+pub async fn set_balance(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
@@ -21,39 +23,101 @@ pub fn set_balance(
         format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"),
         balance,
     )
+    .await
+}
+
+// Adds a delta onto a balance atomically, creating it if not exists
+// Returns the new balance
+// Mainly for testing purposes
+// In application, balances are only incremented through atomic ledger updates
+#[allow(dead_code)]
+pub async fn incr_balance(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+    currency: &str,
+    delta: i64,
+) -> RedisResult<i64> {
+    con.incr(
+        format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"),
+        delta,
+    )
+    .await
+}
+
+// Queues adding a delta onto a balance into a pipeline, used for atomic ledger updates
+pub fn queue_incr_balance(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    user_id: &str,
+    currency: &str,
+    delta: i64,
+) {
+    pipe.incr(
+        format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"),
+        delta,
+    )
+    .ignore();
 }
 
 // Checks if balance exists
-pub fn get_balance_exists(
-    con: &mut Connection,
+pub async fn get_balance_exists(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<bool> {
     con.exists(format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
 }
 
 // Gets a balance
-pub fn get_balance(
-    con: &mut Connection,
+pub async fn get_balance(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<i64> {
     con.get(format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
 }
 
 // Deletes a balance in Redis
 // Mainly for testing purposes
-// In application, no real need to delete keys
+// In application, balances are only deleted through atomic pipelines
 #[allow(dead_code)]
-pub fn delete_balance(
-    con: &mut Connection,
+pub async fn delete_balance(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<()> {
     con.del(format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
+}
+
+// Queues deleting a balance into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_balance(pipe: &mut Pipeline, chat_id: &str, user_id: &str, currency: &str) {
+    pipe.del(format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .ignore();
+}
+
+// Queues moving a balance to a new chat into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the balance exists
+pub fn queue_move_balance(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    new_chat_id: &str,
+    user_id: &str,
+    currency: &str,
+) {
+    pipe.rename(
+        format!("{BALANCE_KEY}:{chat_id}:{user_id}:{currency}"),
+        format!("{BALANCE_KEY}:{new_chat_id}:{user_id}:{currency}"),
+    )
+    .ignore();
 }
 
 // Tests
@@ -62,49 +126,102 @@ mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_add_get_balance() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_get_balance() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "123456789";
         let user_id = "987654321";
         let currency = "USD";
-        assert!(set_balance(&mut con, chat_id, user_id, currency, 1300).is_ok());
-        assert!(get_balance_exists(&mut con, chat_id, user_id, currency).unwrap());
+        assert!(set_balance(&mut con, chat_id, user_id, currency, 1300)
+            .await
+            .is_ok());
+        assert!(get_balance_exists(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap());
         assert_eq!(
-            get_balance(&mut con, chat_id, user_id, currency).unwrap(),
+            get_balance(&mut con, chat_id, user_id, currency)
+                .await
+                .unwrap(),
             (1300)
         );
 
-        delete_balance(&mut con, chat_id, user_id, currency).unwrap();
+        delete_balance(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap();
     }
 
-    #[test]
-    fn test_update_balance() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_balance() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567891";
         let user_id = "9876543211";
         let currency = "USD";
-        set_balance(&mut con, chat_id, user_id, currency, 500).unwrap();
-        assert!(set_balance(&mut con, chat_id, user_id, currency, -4213).is_ok());
+        set_balance(&mut con, chat_id, user_id, currency, 500)
+            .await
+            .unwrap();
+        assert!(set_balance(&mut con, chat_id, user_id, currency, -4213)
+            .await
+            .is_ok());
         assert_eq!(
-            get_balance(&mut con, chat_id, user_id, currency).unwrap(),
+            get_balance(&mut con, chat_id, user_id, currency)
+                .await
+                .unwrap(),
             (-4213)
         );
 
-        delete_balance(&mut con, chat_id, user_id, currency).unwrap();
+        delete_balance(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap();
     }
 
-    #[test]
-    fn test_delete_balance() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_balance() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567892";
         let user_id = "9876543212";
         let currency = "SGD";
-        set_balance(&mut con, chat_id, user_id, currency, 4213).unwrap();
-        assert!(delete_balance(&mut con, chat_id, user_id, currency).is_ok());
-        assert!(!get_balance_exists(&mut con, chat_id, user_id, currency).unwrap());
+        set_balance(&mut con, chat_id, user_id, currency, 4213)
+            .await
+            .unwrap();
+        assert!(delete_balance(&mut con, chat_id, user_id, currency)
+            .await
+            .is_ok());
+        assert!(!get_balance_exists(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_incr_balance() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "1234567893";
+        let user_id = "9876543213";
+        let currency = "USD";
+        assert_eq!(
+            incr_balance(&mut con, chat_id, user_id, currency, 500)
+                .await
+                .unwrap(),
+            500
+        );
+        assert_eq!(
+            incr_balance(&mut con, chat_id, user_id, currency, -800)
+                .await
+                .unwrap(),
+            -300
+        );
+        assert_eq!(
+            get_balance(&mut con, chat_id, user_id, currency)
+                .await
+                .unwrap(),
+            (-300)
+        );
+
+        delete_balance(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap();
     }
 }
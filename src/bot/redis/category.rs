@@ -0,0 +1,157 @@
+use super::CHAT_CATEGORY_KEY;
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
+use serde::{Deserialize, Serialize};
+
+/* Chat Category CRUD Operations
+ * Chat category represents how much a chat has spent on each category of payments,
+ * in each currency, kept as a hash of serialized totals per chat.
+ * Totals are changed together with the spendings of a ledger update, so they are read
+ * without going through every payment of the chat.
+ * Has get, set, exists, move, and delete operations.
+ */
+
+// Spending on payments of a category in a currency, None for payments without one
+// Latest is the time of the latest payment counted, used to list recent categories first
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CategoryTotal {
+    pub category: Option<String>,
+    pub currency: String,
+    pub spending: i64,
+    pub latest: i64,
+}
+
+impl CategoryTotal {
+    // Gets the key of the category, which is matched regardless of case
+    // Payments without a category have an empty key, as categories are never empty
+    pub fn key(&self) -> String {
+        self.category
+            .as_deref()
+            .map(str::to_lowercase)
+            .unwrap_or_default()
+    }
+
+    // Adds a change onto the total, keeping the name the category was first given
+    pub fn add(&mut self, change: &CategoryTotal) {
+        self.spending += change.spending;
+        self.latest = self.latest.max(change.latest);
+    }
+}
+
+// Gets the field of a category total in the hash of its chat
+fn category_field(total: &CategoryTotal) -> String {
+    format!("{}:{}", total.currency, total.key())
+}
+
+// Gets all category totals of a chat
+pub async fn get_chat_categories(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<CategoryTotal>> {
+    let totals: Vec<String> = con.hvals(format!("{CHAT_CATEGORY_KEY}:{chat_id}")).await?;
+    Ok(totals
+        .iter()
+        .filter_map(|total| serde_json::from_str(total).ok())
+        .collect())
+}
+
+// Gets the category total of a chat with the same category and currency, if any
+pub async fn get_chat_category(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    total: &CategoryTotal,
+) -> RedisResult<Option<CategoryTotal>> {
+    let current: Option<String> = con
+        .hget(
+            format!("{CHAT_CATEGORY_KEY}:{chat_id}"),
+            category_field(total),
+        )
+        .await?;
+    Ok(current.and_then(|current| serde_json::from_str(&current).ok()))
+}
+
+// Queues setting a category total of a chat into a pipeline, deleting it once nothing is spent
+pub fn queue_set_chat_category(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    total: &CategoryTotal,
+) -> Result<(), serde_json::Error> {
+    let key = format!("{CHAT_CATEGORY_KEY}:{chat_id}");
+    if total.spending == 0 {
+        pipe.hdel(key, category_field(total)).ignore();
+    } else {
+        pipe.hset(key, category_field(total), serde_json::to_string(total)?)
+            .ignore();
+    }
+    Ok(())
+}
+
+// Checks if a chat has any category totals
+pub async fn get_chat_categories_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_CATEGORY_KEY}:{chat_id}")).await
+}
+
+// Deletes all category totals of a chat
+// Mainly for testing purposes
+#[allow(dead_code)]
+pub async fn delete_chat_categories(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<()> {
+    con.del(format!("{CHAT_CATEGORY_KEY}:{chat_id}")).await
+}
+
+// Queues deleting all category totals of a chat into a pipeline
+// Used when a chat is reset, or its totals are migrated
+pub fn queue_delete_chat_categories(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_CATEGORY_KEY}:{chat_id}")).ignore();
+}
+
+// Queues moving all category totals of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the totals exist
+pub fn queue_move_chat_categories(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_CATEGORY_KEY}:{chat_id}"),
+        format!("{CHAT_CATEGORY_KEY}:{new_chat_id}"),
+    )
+    .ignore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_set_get_delete_chat_categories() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "123456789_chat_category";
+        let total = CategoryTotal {
+            category: Some("Food".to_string()),
+            currency: "USD".to_string(),
+            spending: 100,
+            latest: 1704067200,
+        };
+        let mut pipe = redis::pipe();
+        queue_set_chat_category(&mut pipe, chat_id, &total).unwrap();
+        pipe.query_async::<_, ()>(&mut con).await.unwrap();
+
+        assert!(get_chat_categories_exists(&mut con, chat_id).await.unwrap());
+        let lookup = CategoryTotal {
+            category: Some("food".to_string()),
+            ..total.clone()
+        };
+        assert_eq!(
+            get_chat_category(&mut con, chat_id, &lookup).await.unwrap(),
+            Some(total.clone())
+        );
+        assert_eq!(
+            get_chat_categories(&mut con, chat_id).await.unwrap(),
+            vec![total]
+        );
+
+        delete_chat_categories(&mut con, chat_id).await.unwrap();
+        assert!(!get_chat_categories_exists(&mut con, chat_id).await.unwrap());
+    }
+}
@@ -1,8 +1,9 @@
 use super::{
-    CHAT_CURRENCY_KEY, CHAT_KEY, CHAT_PAYMENT_KEY, CHAT_SETTING_KEY, SETTING_CURRENCY_CONVERSION,
-    SETTING_DEFAULT_CURRENCY, SETTING_ERASE_MESSAGES, SETTING_TIME_ZONE,
+    CHAT_CURRENCY_KEY, CHAT_KEY, CHAT_PAYMENT_INDEX_KEY, CHAT_PAYMENT_KEY, CHAT_SETTING_KEY,
+    SETTING_CURRENCY_CONVERSION, SETTING_DEFAULT_CURRENCY, SETTING_ERASE_MESSAGES,
+    SETTING_TIME_ZONE,
 };
-use redis::{Commands, Connection, RedisResult};
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
 use serde::{Deserialize, Serialize};
 
 /* Chat CRUD Operations
@@ -23,122 +24,419 @@ pub struct Debt {
 }
 
 // Adds a new chat to Redis
-pub fn add_chat(con: &mut Connection, chat_id: &str, username: &str) -> RedisResult<()> {
-    con.rpush(format!("{CHAT_KEY}:{chat_id}"), username)
+pub async fn add_chat(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    username: &str,
+) -> RedisResult<()> {
+    con.rpush(format!("{CHAT_KEY}:{chat_id}"), username).await
 }
 
 // Gets all users from a chat
 // Returns a vector of usernames
-pub fn get_chat_users(con: &mut Connection, chat_id: &str) -> RedisResult<Vec<String>> {
-    con.lrange(format!("{CHAT_KEY}:{chat_id}"), 0, -1)
+pub async fn get_chat_users(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
+    con.lrange(format!("{CHAT_KEY}:{chat_id}"), 0, -1).await
 }
 
 // Checks if chat exists
-pub fn get_chat_exists(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
-    con.exists(format!("{CHAT_KEY}:{chat_id}"))
+pub async fn get_chat_exists(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_KEY}:{chat_id}")).await
 }
 
 // Adds a single new user to the chat. Automatically checks if already added.
 // Not in use in production, prefers add_chat_user_multiple
 #[allow(dead_code)]
-pub fn add_chat_user(con: &mut Connection, chat_id: &str, username: &str) -> RedisResult<()> {
-    let current_users: Vec<String> = get_chat_users(con, chat_id)?;
+pub async fn add_chat_user(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    username: &str,
+) -> RedisResult<()> {
+    let current_users: Vec<String> = get_chat_users(con, chat_id).await?;
     if current_users.contains(&username.to_string()) {
         return Ok(());
     }
-    con.rpush(format!("{CHAT_KEY}:{chat_id}"), username)
+    con.rpush(format!("{CHAT_KEY}:{chat_id}"), username).await
 }
 
 // Adds more users to the chat. Automatically checks if already added.
-pub fn add_chat_user_multiple(
-    con: &mut Connection,
+pub async fn add_chat_user_multiple(
+    con: &mut ConnectionManager,
     chat_id: &str,
     users: Vec<String>,
 ) -> RedisResult<()> {
-    let current_users: Vec<String> = get_chat_users(con, chat_id)?;
+    let current_users: Vec<String> = get_chat_users(con, chat_id).await?;
     for user in users {
         if !current_users.contains(&user) {
-            con.rpush(format!("{CHAT_KEY}:{chat_id}"), user)?;
+            con.rpush::<_, _, ()>(format!("{CHAT_KEY}:{chat_id}"), user)
+                .await?;
         }
     }
 
     Ok(())
 }
 
+// Removes a single user from the chat
+// Used when a user asks to be forgotten
+pub async fn delete_chat_user(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    username: &str,
+) -> RedisResult<()> {
+    con.lrem(format!("{CHAT_KEY}:{chat_id}"), 0, username).await
+}
+
+// Queues adding a single new user to the chat into a pipeline
+// The caller is responsible for checking that the user is not already added
+pub fn queue_add_chat_user(pipe: &mut Pipeline, chat_id: &str, username: &str) {
+    pipe.rpush(format!("{CHAT_KEY}:{chat_id}"), username)
+        .ignore();
+}
+
+// Queues removing a single user from the chat into a pipeline
+// Used when a user is renamed
+pub fn queue_delete_chat_user(pipe: &mut Pipeline, chat_id: &str, username: &str) {
+    pipe.lrem(format!("{CHAT_KEY}:{chat_id}"), 0, username)
+        .ignore();
+}
+
 // Deletes a chat from Redis
 // Mainly for testing purposes
-// In application, no real need to delete keys
+// In application, chats are only deleted through atomic pipelines
 #[allow(dead_code)]
-pub fn delete_chat(con: &mut Connection, chat_id: &str) -> RedisResult<()> {
-    con.del(format!("{CHAT_KEY}:{chat_id}"))
+pub async fn delete_chat(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<()> {
+    con.del(format!("{CHAT_KEY}:{chat_id}")).await
+}
+
+// Queues deleting a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_KEY}:{chat_id}")).ignore();
+}
+
+// Queues moving the users of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the users exist
+pub fn queue_move_chat(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_KEY}:{chat_id}"),
+        format!("{CHAT_KEY}:{new_chat_id}"),
+    )
+    .ignore();
 }
 
 /* Chat Payment CRUD Operations */
 
 // Adds a new payment to a chat
-pub fn add_chat_payment(con: &mut Connection, chat_id: &str, payment_id: &str) -> RedisResult<()> {
+// Mainly for testing purposes
+// In application, payments are only added through atomic ledger updates
+#[allow(dead_code)]
+pub async fn add_chat_payment(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    payment_id: &str,
+) -> RedisResult<()> {
     con.lpush(format!("{CHAT_PAYMENT_KEY}:{chat_id}"), payment_id)
+        .await
+}
+
+// Queues adding a new payment to a chat into a pipeline
+pub fn queue_add_chat_payment(pipe: &mut Pipeline, chat_id: &str, payment_id: &str) {
+    pipe.lpush(format!("{CHAT_PAYMENT_KEY}:{chat_id}"), payment_id)
+        .ignore();
 }
 
 // Checks if payments exist in a chat
-pub fn get_chat_payment_exists(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
-    con.exists(format!("{CHAT_PAYMENT_KEY}:{chat_id}"))
+pub async fn get_chat_payment_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_PAYMENT_KEY}:{chat_id}")).await
 }
 
 // Gets all payments from a chat
-pub fn get_chat_payments(con: &mut Connection, chat_id: &str) -> RedisResult<Vec<String>> {
+pub async fn get_chat_payments(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
     con.lrange(format!("{CHAT_PAYMENT_KEY}:{chat_id}"), 0, -1)
+        .await
+}
+
+// Gets all chats with any payments
+// Used when category totals are migrated
+pub async fn get_all_payment_chats(con: &mut ConnectionManager) -> RedisResult<Vec<String>> {
+    let prefix = format!("{CHAT_PAYMENT_KEY}:");
+    let mut keys = con.scan_match::<_, String>(format!("{prefix}*")).await?;
+    let mut chat_ids = Vec::new();
+    while let Some(key) = keys.next_item().await {
+        if let Some(chat_id) = key.strip_prefix(&prefix) {
+            chat_ids.push(chat_id.to_string());
+        }
+    }
+    Ok(chat_ids)
+}
+
+// Counts all payments in a chat, including those in the trash
+pub async fn get_chat_payment_count(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<usize> {
+    con.llen(format!("{CHAT_PAYMENT_KEY}:{chat_id}")).await
 }
 
 // Deletes a payment from a chat
-pub fn delete_chat_payment(
-    con: &mut Connection,
+// Mainly for testing purposes
+// In application, payments are only deleted through atomic ledger updates
+#[allow(dead_code)]
+pub async fn delete_chat_payment(
+    con: &mut ConnectionManager,
     chat_id: &str,
     payment_id: &str,
 ) -> RedisResult<()> {
     con.lrem(format!("{CHAT_PAYMENT_KEY}:{chat_id}"), 0, payment_id)
+        .await
+}
+
+// Queues deleting a payment from a chat into a pipeline
+pub fn queue_delete_chat_payment(pipe: &mut Pipeline, chat_id: &str, payment_id: &str) {
+    pipe.lrem(format!("{CHAT_PAYMENT_KEY}:{chat_id}"), 0, payment_id)
+        .ignore();
 }
 
 // Deletes all payments from a chat
 // Mainly for testing purposes
-// In application, no real need to delete keys
 #[allow(dead_code)]
-pub fn delete_all_chat_payment(con: &mut Connection, chat_id: &str) -> RedisResult<()> {
-    con.del(format!("{CHAT_PAYMENT_KEY}:{chat_id}"))
+pub async fn delete_all_chat_payment(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<()> {
+    con.del(format!("{CHAT_PAYMENT_KEY}:{chat_id}")).await
+}
+
+// Queues deleting all payments from a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_all_chat_payment(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_PAYMENT_KEY}:{chat_id}")).ignore();
+}
+
+// Queues moving all payments of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the payments exist
+pub fn queue_move_all_chat_payment(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_PAYMENT_KEY}:{chat_id}"),
+        format!("{CHAT_PAYMENT_KEY}:{new_chat_id}"),
+    )
+    .ignore();
+}
+
+/* Chat Payment Index CRUD Operations
+ * The index is a sorted set of the payments shown in a chat, scored by their time.
+ * Trashed payments are left out, so that pages of payments can be read directly.
+ */
+
+// Adds a payment to the index of a chat
+pub async fn add_chat_payment_index(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    payment_id: &str,
+    timestamp: i64,
+) -> RedisResult<()> {
+    con.zadd(
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+        payment_id,
+        timestamp,
+    )
+    .await
+}
+
+// Queues adding a payment to the index of a chat into a pipeline
+pub fn queue_add_chat_payment_index(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    payment_id: &str,
+    timestamp: i64,
+) {
+    pipe.zadd(
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+        payment_id,
+        timestamp,
+    )
+    .ignore();
+}
+
+// Counts the payments in the index of a chat, within an inclusive time range if given
+pub async fn count_chat_payment_index(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    from: Option<i64>,
+    to: Option<i64>,
+) -> RedisResult<usize> {
+    let min = from.map_or("-inf".to_string(), |from| from.to_string());
+    let max = to.map_or("+inf".to_string(), |to| to.to_string());
+    con.zcount(format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"), min, max)
+        .await
+}
+
+// Gets the payments in the index of a chat between two ranks, latest first
+// Returns a vector of payment IDs with their timestamps
+pub async fn get_chat_payment_index_range(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    start: usize,
+    stop: usize,
+) -> RedisResult<Vec<(String, i64)>> {
+    let payments: Vec<(String, f64)> = con
+        .zrevrange_withscores(
+            format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+            start as isize,
+            stop as isize,
+        )
+        .await?;
+    Ok(payments
+        .into_iter()
+        .map(|(payment_id, timestamp)| (payment_id, timestamp as i64))
+        .collect())
+}
+
+// Gets the payments in the index of a chat at exactly the given time, by payment ID descending
+pub async fn get_chat_payment_index_at(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    timestamp: i64,
+) -> RedisResult<Vec<String>> {
+    con.zrevrangebyscore(
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+        timestamp,
+        timestamp,
+    )
+    .await
+}
+
+// Queues removing a payment from the index of a chat into a pipeline
+pub fn queue_delete_chat_payment_index_entry(pipe: &mut Pipeline, chat_id: &str, payment_id: &str) {
+    pipe.zrem(format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"), payment_id)
+        .ignore();
+}
+
+// Deletes the index of a chat
+// Used when the index is rebuilt
+pub async fn delete_chat_payment_index(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<()> {
+    con.del(format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}")).await
+}
+
+// Queues deleting the index of a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat_payment_index(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"))
+        .ignore();
+}
+
+// Checks if a chat has an index of its payments
+pub async fn get_chat_payment_index_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"))
+        .await
+}
+
+// Queues moving the index of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the index exists
+pub fn queue_move_chat_payment_index(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{new_chat_id}"),
+    )
+    .ignore();
 }
 
 /* Chat Currency CRUD Operations */
 // Adds a currency to a chat
-pub fn add_chat_currency(con: &mut Connection, chat_id: &str, currency: &str) -> RedisResult<()> {
+// Mainly for testing purposes
+// In application, currencies are only added through atomic ledger updates
+#[allow(dead_code)]
+pub async fn add_chat_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    currency: &str,
+) -> RedisResult<()> {
     con.rpush(format!("{CHAT_CURRENCY_KEY}:{chat_id}"), currency)
+        .await
+}
+
+// Queues adding a currency to a chat into a pipeline
+pub fn queue_add_chat_currency(pipe: &mut Pipeline, chat_id: &str, currency: &str) {
+    pipe.rpush(format!("{CHAT_CURRENCY_KEY}:{chat_id}"), currency)
+        .ignore();
 }
 
 // Gets all currencies from a chat
-pub fn get_chat_currencies(con: &mut Connection, chat_id: &str) -> RedisResult<Vec<String>> {
+pub async fn get_chat_currencies(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
     con.lrange(format!("{CHAT_CURRENCY_KEY}:{chat_id}"), 0, -1)
+        .await
 }
 
 // Deletes all currencies from a chat
 // Mainly for testing purposes
-// In application, no real need to delete keys
 #[allow(dead_code)]
-pub fn delete_chat_currencies(con: &mut Connection, chat_id: &str) -> RedisResult<()> {
-    con.del(format!("{CHAT_CURRENCY_KEY}:{chat_id}"))
+pub async fn delete_chat_currencies(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<()> {
+    con.del(format!("{CHAT_CURRENCY_KEY}:{chat_id}")).await
+}
+
+// Queues deleting all currencies from a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat_currencies(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_CURRENCY_KEY}:{chat_id}")).ignore();
+}
+
+// Checks if a chat has any currencies
+pub async fn get_chat_currencies_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_CURRENCY_KEY}:{chat_id}")).await
+}
+
+// Queues moving all currencies of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the currencies exist
+pub fn queue_move_chat_currencies(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_CURRENCY_KEY}:{chat_id}"),
+        format!("{CHAT_CURRENCY_KEY}:{new_chat_id}"),
+    )
+    .ignore();
 }
 
 /* Chat Setting CRUD Operations */
 // Sets time zone for a chat
-pub fn set_chat_time_zone(con: &mut Connection, chat_id: &str, time_zone: &str) -> RedisResult<()> {
+pub async fn set_chat_time_zone(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    time_zone: &str,
+) -> RedisResult<()> {
     con.hset(
         format!("{CHAT_SETTING_KEY}:{chat_id}"),
         SETTING_TIME_ZONE,
         time_zone,
     )
+    .await
 }
 
 // Sets default currency for a chat
-pub fn set_chat_default_currency(
-    con: &mut Connection,
+pub async fn set_chat_default_currency(
+    con: &mut ConnectionManager,
     chat_id: &str,
     currency: &str,
 ) -> RedisResult<()> {
@@ -147,11 +445,12 @@ pub fn set_chat_default_currency(
         SETTING_DEFAULT_CURRENCY,
         currency,
     )
+    .await
 }
 
 // Sets currency conversion for a chat
-pub fn set_chat_currency_conversion(
-    con: &mut Connection,
+pub async fn set_chat_currency_conversion(
+    con: &mut ConnectionManager,
     chat_id: &str,
     currency_conversion: bool,
 ) -> RedisResult<()> {
@@ -160,11 +459,36 @@ pub fn set_chat_currency_conversion(
         SETTING_CURRENCY_CONVERSION,
         currency_conversion,
     )
+    .await
+}
+
+// Queues setting default currency for a chat into a pipeline
+pub fn queue_set_chat_default_currency(pipe: &mut Pipeline, chat_id: &str, currency: &str) {
+    pipe.hset(
+        format!("{CHAT_SETTING_KEY}:{chat_id}"),
+        SETTING_DEFAULT_CURRENCY,
+        currency,
+    )
+    .ignore();
+}
+
+// Queues setting currency conversion for a chat into a pipeline
+pub fn queue_set_chat_currency_conversion(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    currency_conversion: bool,
+) {
+    pipe.hset(
+        format!("{CHAT_SETTING_KEY}:{chat_id}"),
+        SETTING_CURRENCY_CONVERSION,
+        currency_conversion,
+    )
+    .ignore();
 }
 
 // Sets erase messages for a chat
-pub fn set_chat_erase_messages(
-    con: &mut Connection,
+pub async fn set_chat_erase_messages(
+    con: &mut ConnectionManager,
     chat_id: &str,
     erase_messages: bool,
 ) -> RedisResult<()> {
@@ -173,11 +497,15 @@ pub fn set_chat_erase_messages(
         SETTING_ERASE_MESSAGES,
         erase_messages,
     )
+    .await
 }
 
 // Checks if time zone exists for a chat
-pub fn is_exists_chat_time_zone(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
-    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}"))?;
+pub async fn is_exists_chat_time_zone(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}")).await?;
     if keys.contains(&SETTING_TIME_ZONE.to_string()) {
         Ok(true)
     } else {
@@ -186,8 +514,11 @@ pub fn is_exists_chat_time_zone(con: &mut Connection, chat_id: &str) -> RedisRes
 }
 
 // Checks if default currency exists for a chat
-pub fn is_exists_chat_default_currency(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
-    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}"))?;
+pub async fn is_exists_chat_default_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}")).await?;
     if keys.contains(&SETTING_DEFAULT_CURRENCY.to_string()) {
         Ok(true)
     } else {
@@ -196,11 +527,11 @@ pub fn is_exists_chat_default_currency(con: &mut Connection, chat_id: &str) -> R
 }
 
 // Checks if currency conversion exists for a chat
-pub fn is_exists_chat_currency_conversion(
-    con: &mut Connection,
+pub async fn is_exists_chat_currency_conversion(
+    con: &mut ConnectionManager,
     chat_id: &str,
 ) -> RedisResult<bool> {
-    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}"))?;
+    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}")).await?;
     if keys.contains(&SETTING_CURRENCY_CONVERSION.to_string()) {
         Ok(true)
     } else {
@@ -209,8 +540,11 @@ pub fn is_exists_chat_currency_conversion(
 }
 
 // Checks if erase messages exists for a chat
-pub fn is_exists_chat_erase_messages(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
-    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}"))?;
+pub async fn is_exists_chat_erase_messages(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    let keys: Vec<String> = con.hkeys(format!("{CHAT_SETTING_KEY}:{chat_id}")).await?;
     if keys.contains(&SETTING_ERASE_MESSAGES.to_string()) {
         Ok(true)
     } else {
@@ -219,40 +553,77 @@ pub fn is_exists_chat_erase_messages(con: &mut Connection, chat_id: &str) -> Red
 }
 
 // Gets time zone for a chat
-pub fn get_chat_time_zone(con: &mut Connection, chat_id: &str) -> RedisResult<String> {
+pub async fn get_chat_time_zone(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<String> {
     con.hget(format!("{CHAT_SETTING_KEY}:{chat_id}"), SETTING_TIME_ZONE)
+        .await
 }
 
 // Gets default currency for a chat
-pub fn get_chat_default_currency(con: &mut Connection, chat_id: &str) -> RedisResult<String> {
+pub async fn get_chat_default_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<String> {
     con.hget(
         format!("{CHAT_SETTING_KEY}:{chat_id}"),
         SETTING_DEFAULT_CURRENCY,
     )
+    .await
 }
 
 // Gets currency conversion for a chat
-pub fn get_chat_currency_conversion(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
+pub async fn get_chat_currency_conversion(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
     con.hget(
         format!("{CHAT_SETTING_KEY}:{chat_id}"),
         SETTING_CURRENCY_CONVERSION,
     )
+    .await
 }
 
 // Gets erase messages for a chat
-pub fn get_chat_erase_messages(con: &mut Connection, chat_id: &str) -> RedisResult<bool> {
+pub async fn get_chat_erase_messages(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
     con.hget(
         format!("{CHAT_SETTING_KEY}:{chat_id}"),
         SETTING_ERASE_MESSAGES,
     )
+    .await
+}
+
+// Checks if a chat has any settings
+pub async fn get_chat_settings_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_SETTING_KEY}:{chat_id}")).await
 }
 
 // Deletes chat settings
 // Mainly for testing purposes
-// In application, no real need to delete keys
 #[allow(dead_code)]
-pub fn delete_chat_settings(con: &mut Connection, chat_id: &str) -> RedisResult<()> {
-    con.del(format!("{CHAT_SETTING_KEY}:{chat_id}"))
+pub async fn delete_chat_settings(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<()> {
+    con.del(format!("{CHAT_SETTING_KEY}:{chat_id}")).await
+}
+
+// Queues deleting chat settings into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat_settings(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_SETTING_KEY}:{chat_id}")).ignore();
+}
+
+// Queues moving the settings of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the settings exist
+pub fn queue_move_chat_settings(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_SETTING_KEY}:{chat_id}"),
+        format!("{CHAT_SETTING_KEY}:{new_chat_id}"),
+    )
+    .ignore();
 }
 
 #[cfg(test)]
@@ -260,59 +631,59 @@ mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_add_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_chat() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "123456789";
         let username = "987654321";
-        assert!(add_chat(&mut con, chat_id, username).is_ok());
+        assert!(add_chat(&mut con, chat_id, username).await.is_ok());
 
-        delete_chat(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_get_chat_exists() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_chat_exists() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567891";
         let username = "9876543211";
-        add_chat(&mut con, chat_id, username).unwrap();
-        assert!(get_chat_exists(&mut con, chat_id).unwrap());
+        add_chat(&mut con, chat_id, username).await.unwrap();
+        assert!(get_chat_exists(&mut con, chat_id).await.unwrap());
 
-        delete_chat(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_get_chat_users() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_chat_users() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567890";
         let username = "9876543210";
-        add_chat(&mut con, chat_id, username).unwrap();
-        let users = get_chat_users(&mut con, chat_id);
+        add_chat(&mut con, chat_id, username).await.unwrap();
+        let users = get_chat_users(&mut con, chat_id).await;
         assert!(users.is_ok());
         assert_eq!(users.unwrap(), vec![username.to_string()]);
 
-        delete_chat(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_add_user_to_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_user_to_chat() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567892";
         let username = "9876543212";
         let new_username = "9876543213";
-        add_chat(&mut con, chat_id, username).unwrap();
-        assert!(add_chat_user(&mut con, chat_id, new_username).is_ok());
+        add_chat(&mut con, chat_id, username).await.unwrap();
+        assert!(add_chat_user(&mut con, chat_id, new_username).await.is_ok());
 
-        delete_chat(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_add_users_to_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_users_to_chat() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567893";
         let first_user = "987654321";
@@ -321,10 +692,12 @@ mod tests {
             "987654323".to_string(),
             "987654324".to_string(),
         ];
-        add_chat(&mut con, chat_id, first_user).unwrap();
-        assert!(add_chat_user_multiple(&mut con, chat_id, users).is_ok());
+        add_chat(&mut con, chat_id, first_user).await.unwrap();
+        assert!(add_chat_user_multiple(&mut con, chat_id, users)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_users(&mut con, chat_id).unwrap(),
+            get_chat_users(&mut con, chat_id).await.unwrap(),
             vec![
                 "987654321".to_string(),
                 "987654322".to_string(),
@@ -333,191 +706,246 @@ mod tests {
             ]
         );
 
-        delete_chat(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_delete_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_chat() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567894";
         let username = "9876543216";
-        add_chat(&mut con, chat_id, username).unwrap();
-        assert!(get_chat_exists(&mut con, chat_id).unwrap());
-        delete_chat(&mut con, chat_id).unwrap();
-        assert!(!get_chat_exists(&mut con, chat_id).unwrap());
+        add_chat(&mut con, chat_id, username).await.unwrap();
+        assert!(get_chat_exists(&mut con, chat_id).await.unwrap());
+        delete_chat(&mut con, chat_id).await.unwrap();
+        assert!(!get_chat_exists(&mut con, chat_id).await.unwrap());
     }
 
-    #[test]
-    fn test_add_get_chat_payment() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_get_chat_payment() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567895";
         let payment_id = "payment_id_1";
-        assert!(add_chat_payment(&mut con, chat_id, payment_id).is_ok());
-        assert!(get_chat_payment_exists(&mut con, chat_id).is_ok());
-        assert!(get_chat_payments(&mut con, chat_id).unwrap() == vec![payment_id]);
+        assert!(add_chat_payment(&mut con, chat_id, payment_id)
+            .await
+            .is_ok());
+        assert!(get_chat_payment_exists(&mut con, chat_id).await.is_ok());
+        assert!(get_chat_payments(&mut con, chat_id).await.unwrap() == vec![payment_id]);
 
         let second_payment_id = "payment_id_2";
-        assert!(add_chat_payment(&mut con, chat_id, second_payment_id).is_ok());
+        assert!(add_chat_payment(&mut con, chat_id, second_payment_id)
+            .await
+            .is_ok());
         assert!(
-            get_chat_payments(&mut con, chat_id).unwrap() == vec![second_payment_id, payment_id]
+            get_chat_payments(&mut con, chat_id).await.unwrap()
+                == vec![second_payment_id, payment_id]
         );
 
-        delete_all_chat_payment(&mut con, chat_id).unwrap();
+        delete_all_chat_payment(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_delete_chat_payment() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_chat_payment() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567896";
         let payment_id = "payment_id_2";
-        add_chat_payment(&mut con, chat_id, payment_id).unwrap();
+        add_chat_payment(&mut con, chat_id, payment_id)
+            .await
+            .unwrap();
         let payment_id_second = "payment_id_3";
-        add_chat_payment(&mut con, chat_id, payment_id_second).unwrap();
+        add_chat_payment(&mut con, chat_id, payment_id_second)
+            .await
+            .unwrap();
         let payment_id_third = "payment_id_4";
-        add_chat_payment(&mut con, chat_id, payment_id_third).unwrap();
-        delete_chat_payment(&mut con, chat_id, payment_id_second).unwrap();
+        add_chat_payment(&mut con, chat_id, payment_id_third)
+            .await
+            .unwrap();
+        delete_chat_payment(&mut con, chat_id, payment_id_second)
+            .await
+            .unwrap();
 
         assert_eq!(
-            get_chat_payments(&mut con, chat_id).unwrap(),
+            get_chat_payments(&mut con, chat_id).await.unwrap(),
             vec![payment_id_third, payment_id]
         );
-        delete_all_chat_payment(&mut con, chat_id).unwrap();
+        delete_all_chat_payment(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_delete_all_chat_payment() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_all_chat_payment() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567897";
         let payment_id = "payment_id_5";
-        add_chat_payment(&mut con, chat_id, payment_id).unwrap();
-        delete_all_chat_payment(&mut con, chat_id).unwrap();
-        assert!(!get_chat_payment_exists(&mut con, chat_id).unwrap());
+        add_chat_payment(&mut con, chat_id, payment_id)
+            .await
+            .unwrap();
+        delete_all_chat_payment(&mut con, chat_id).await.unwrap();
+        assert!(!get_chat_payment_exists(&mut con, chat_id).await.unwrap());
     }
 
-    #[test]
-    fn test_add_get_chat_currency() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_get_chat_currency() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "1234567899";
         let currency = "USD";
-        assert!(add_chat_currency(&mut con, chat_id, currency).is_ok());
+        assert!(add_chat_currency(&mut con, chat_id, currency).await.is_ok());
         assert_eq!(
-            get_chat_currencies(&mut con, chat_id).unwrap(),
+            get_chat_currencies(&mut con, chat_id).await.unwrap(),
             vec![currency]
         );
 
         let second_currency = "EUR";
-        assert!(add_chat_currency(&mut con, chat_id, second_currency).is_ok());
+        assert!(add_chat_currency(&mut con, chat_id, second_currency)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_currencies(&mut con, chat_id).unwrap(),
+            get_chat_currencies(&mut con, chat_id).await.unwrap(),
             vec![currency, second_currency]
         );
-        assert!(delete_chat_currencies(&mut con, chat_id).is_ok());
+        assert!(delete_chat_currencies(&mut con, chat_id).await.is_ok());
     }
 
-    #[test]
-    fn test_set_get_chat_time_zone() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_set_get_chat_time_zone() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "12345678900";
         let time_zone = "SST";
 
-        assert!(!is_exists_chat_time_zone(&mut con, chat_id).unwrap());
-        assert!(set_chat_time_zone(&mut con, chat_id, time_zone).is_ok());
+        assert!(!is_exists_chat_time_zone(&mut con, chat_id).await.unwrap());
+        assert!(set_chat_time_zone(&mut con, chat_id, time_zone)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_time_zone(&mut con, chat_id).unwrap(),
+            get_chat_time_zone(&mut con, chat_id).await.unwrap(),
             time_zone.to_string()
         );
-        assert!(is_exists_chat_time_zone(&mut con, chat_id).unwrap());
+        assert!(is_exists_chat_time_zone(&mut con, chat_id).await.unwrap());
 
         let second_time_zone = "PST";
-        assert!(set_chat_time_zone(&mut con, chat_id, second_time_zone).is_ok());
+        assert!(set_chat_time_zone(&mut con, chat_id, second_time_zone)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_time_zone(&mut con, chat_id).unwrap(),
+            get_chat_time_zone(&mut con, chat_id).await.unwrap(),
             second_time_zone.to_string()
         );
 
-        assert!(delete_chat_settings(&mut con, chat_id).is_ok());
+        assert!(delete_chat_settings(&mut con, chat_id).await.is_ok());
     }
 
-    #[test]
-    fn test_set_get_chat_default_currency() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_set_get_chat_default_currency() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "12345678901";
         let currency = "USD";
 
-        assert!(!is_exists_chat_default_currency(&mut con, chat_id).unwrap());
-        assert!(set_chat_default_currency(&mut con, chat_id, currency).is_ok());
+        assert!(!is_exists_chat_default_currency(&mut con, chat_id)
+            .await
+            .unwrap());
+        assert!(set_chat_default_currency(&mut con, chat_id, currency)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_default_currency(&mut con, chat_id).unwrap(),
+            get_chat_default_currency(&mut con, chat_id).await.unwrap(),
             currency.to_string()
         );
-        assert!(is_exists_chat_default_currency(&mut con, chat_id).unwrap());
+        assert!(is_exists_chat_default_currency(&mut con, chat_id)
+            .await
+            .unwrap());
 
         let second_currency = "EUR";
-        assert!(set_chat_default_currency(&mut con, chat_id, second_currency).is_ok());
+        assert!(
+            set_chat_default_currency(&mut con, chat_id, second_currency)
+                .await
+                .is_ok()
+        );
         assert_eq!(
-            get_chat_default_currency(&mut con, chat_id).unwrap(),
+            get_chat_default_currency(&mut con, chat_id).await.unwrap(),
             second_currency.to_string()
         );
 
-        assert!(delete_chat_settings(&mut con, chat_id).is_ok());
+        assert!(delete_chat_settings(&mut con, chat_id).await.is_ok());
     }
 
-    #[test]
-    fn test_set_get_chat_currency_conversion() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_set_get_chat_currency_conversion() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "12345678902";
         let currency_conversion = true;
 
-        assert!(!is_exists_chat_currency_conversion(&mut con, chat_id).unwrap());
-        assert!(set_chat_currency_conversion(&mut con, chat_id, currency_conversion).is_ok());
+        assert!(!is_exists_chat_currency_conversion(&mut con, chat_id)
+            .await
+            .unwrap());
+        assert!(
+            set_chat_currency_conversion(&mut con, chat_id, currency_conversion)
+                .await
+                .is_ok()
+        );
         assert_eq!(
-            get_chat_currency_conversion(&mut con, chat_id).unwrap(),
+            get_chat_currency_conversion(&mut con, chat_id)
+                .await
+                .unwrap(),
             currency_conversion
         );
-        assert!(is_exists_chat_currency_conversion(&mut con, chat_id).unwrap());
+        assert!(is_exists_chat_currency_conversion(&mut con, chat_id)
+            .await
+            .unwrap());
 
         let second_currency_conversion = false;
         assert!(
-            set_chat_currency_conversion(&mut con, chat_id, second_currency_conversion).is_ok()
+            set_chat_currency_conversion(&mut con, chat_id, second_currency_conversion)
+                .await
+                .is_ok()
         );
         assert_eq!(
-            get_chat_currency_conversion(&mut con, chat_id).unwrap(),
+            get_chat_currency_conversion(&mut con, chat_id)
+                .await
+                .unwrap(),
             second_currency_conversion
         );
 
-        assert!(delete_chat_settings(&mut con, chat_id).is_ok());
+        assert!(delete_chat_settings(&mut con, chat_id).await.is_ok());
     }
 
-    #[test]
-    fn test_set_get_chat_erase_messages() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_set_get_chat_erase_messages() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "12345678903";
         let erase_messages = true;
 
-        assert!(!is_exists_chat_erase_messages(&mut con, chat_id).unwrap());
-        assert!(set_chat_erase_messages(&mut con, chat_id, erase_messages).is_ok());
+        assert!(!is_exists_chat_erase_messages(&mut con, chat_id)
+            .await
+            .unwrap());
+        assert!(set_chat_erase_messages(&mut con, chat_id, erase_messages)
+            .await
+            .is_ok());
         assert_eq!(
-            get_chat_erase_messages(&mut con, chat_id).unwrap(),
+            get_chat_erase_messages(&mut con, chat_id).await.unwrap(),
             erase_messages
         );
-        assert!(is_exists_chat_erase_messages(&mut con, chat_id).unwrap());
+        assert!(is_exists_chat_erase_messages(&mut con, chat_id)
+            .await
+            .unwrap());
 
         let second_erase_messages = false;
-        assert!(set_chat_erase_messages(&mut con, chat_id, second_erase_messages).is_ok());
+        assert!(
+            set_chat_erase_messages(&mut con, chat_id, second_erase_messages)
+                .await
+                .is_ok()
+        );
         assert_eq!(
-            get_chat_erase_messages(&mut con, chat_id).unwrap(),
+            get_chat_erase_messages(&mut con, chat_id).await.unwrap(),
             second_erase_messages
         );
 
-        assert!(delete_chat_settings(&mut con, chat_id).is_ok());
+        assert!(delete_chat_settings(&mut con, chat_id).await.is_ok());
     }
 }
@@ -1,4 +1,4 @@
-use redis::{Client, Commands, Connection, RedisError, RedisResult};
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError, RedisResult};
 
 #[derive(thiserror::Error, Debug, PartialEq)]
 pub enum DBError {
@@ -22,11 +22,14 @@ impl From<DBError> for RedisError {
     }
 }
 
-pub fn connect() -> Result<Connection, DBError> {
+// Creates an async connection manager to Redis, configured by REDIS_URL
+// The manager multiplexes commands over a single connection, and reconnects when it drops
+// It is cheap to clone, so it should be created once and shared
+pub async fn connect() -> Result<ConnectionManager, DBError> {
     dotenv::dotenv().ok();
     let url = std::env::var("REDIS_URL").expect("REDIS_URL token not set");
     match Client::open(url) {
-        Ok(client) => match client.get_connection() {
+        Ok(client) => match ConnectionManager::new(client).await {
             Ok(con) => Ok(con),
             Err(e) => Err(DBError::RedisConnectionError(e)),
         },
@@ -38,11 +41,11 @@ pub fn connect() -> Result<Connection, DBError> {
 // Tests connection to Redis
 // Only used for testing purposes
 #[allow(dead_code)]
-pub fn test_redis_connection() -> RedisResult<bool> {
-    let mut con = connect()?;
-    con.set("my_key", 42)?;
-    let res: i32 = con.get("my_key")?;
-    con.del("my_key")?;
+pub async fn test_redis_connection() -> RedisResult<bool> {
+    let mut con = connect().await?;
+    con.set::<_, _, ()>("my_key", 42).await?;
+    let res: i32 = con.get("my_key").await?;
+    con.del::<_, ()>("my_key").await?;
 
     Ok(res == 42)
 }
@@ -52,8 +55,8 @@ mod tests {
     use super::test_redis_connection;
 
     // Tests working connection
-    #[test]
-    fn test_connection() {
-        assert!(test_redis_connection().unwrap());
+    #[tokio::test]
+    async fn test_connection() {
+        assert!(test_redis_connection().await.unwrap());
     }
 }
@@ -0,0 +1,122 @@
+use super::DIALOGUE_KEY;
+
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
+
+/* Dialogue CRUD Operations
+ * Dialogue represents the serialized conversation state of a user with the bot in a chat.
+ * It is kept so that unfinished flows can resume after the bot restarts.
+ * Has set, get, and delete operations, and moves can be queued into a pipeline.
+ */
+
+// Sets the dialogue of a user in a chat
+pub async fn set_chat_dialogue(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+    dialogue: &str,
+) -> RedisResult<()> {
+    con.set(format!("{DIALOGUE_KEY}:{chat_id}:{user_id}"), dialogue)
+        .await
+}
+
+// Gets the dialogue of a user in a chat, if any
+pub async fn get_chat_dialogue(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+) -> RedisResult<Option<String>> {
+    con.get(format!("{DIALOGUE_KEY}:{chat_id}:{user_id}")).await
+}
+
+// Deletes the dialogue of a user in a chat
+pub async fn delete_chat_dialogue(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+) -> RedisResult<()> {
+    con.del(format!("{DIALOGUE_KEY}:{chat_id}:{user_id}")).await
+}
+
+// Queues deleting the dialogue of a user in a chat into a pipeline
+// Used when a chat is migrated, replacing the dialogues already under the new chat ID
+pub fn queue_delete_chat_dialogue(pipe: &mut Pipeline, chat_id: &str, user_id: &str) {
+    pipe.del(format!("{DIALOGUE_KEY}:{chat_id}:{user_id}"))
+        .ignore();
+}
+
+// Gets the user IDs of all users with a dialogue in a chat
+// Used when a chat is migrated, as dialogues are not listed per chat
+pub async fn get_chat_dialogue_users(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
+    let prefix = format!("{DIALOGUE_KEY}:{chat_id}:");
+    let mut keys = con.scan_match::<_, String>(format!("{prefix}*")).await?;
+    let mut user_ids = Vec::new();
+    while let Some(key) = keys.next_item().await {
+        if let Some(user_id) = key.strip_prefix(&prefix) {
+            user_ids.push(user_id.to_string());
+        }
+    }
+    Ok(user_ids)
+}
+
+// Queues moving the dialogue of a user to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the dialogue exists
+pub fn queue_move_chat_dialogue(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    new_chat_id: &str,
+    user_id: &str,
+) {
+    pipe.rename(
+        format!("{DIALOGUE_KEY}:{chat_id}:{user_id}"),
+        format!("{DIALOGUE_KEY}:{new_chat_id}:{user_id}"),
+    )
+    .ignore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_set_get_delete_dialogue() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "dialogue_123456789";
+        let user_id = "987654321";
+        let other_user_id = "987654322";
+        let dialogue = "{\"version\":1,\"state\":\"Start\"}";
+
+        assert_eq!(
+            get_chat_dialogue(&mut con, chat_id, user_id).await.unwrap(),
+            None
+        );
+        assert!(set_chat_dialogue(&mut con, chat_id, user_id, dialogue)
+            .await
+            .is_ok());
+        assert_eq!(
+            get_chat_dialogue(&mut con, chat_id, user_id).await.unwrap(),
+            Some(dialogue.to_string())
+        );
+
+        // Other users in the same chat have their own dialogues
+        assert_eq!(
+            get_chat_dialogue(&mut con, chat_id, other_user_id)
+                .await
+                .unwrap(),
+            None
+        );
+
+        assert!(delete_chat_dialogue(&mut con, chat_id, user_id)
+            .await
+            .is_ok());
+        assert_eq!(
+            get_chat_dialogue(&mut con, chat_id, user_id).await.unwrap(),
+            None
+        );
+    }
+}
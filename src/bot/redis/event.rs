@@ -0,0 +1,235 @@
+use super::{CHAT_EVENT_KEY, EVENT_KEY, PAYMENT_EVENT_KEY};
+
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
+
+/* Event CRUD Operations
+ * Event represents an immutable record of a change to a payment, serialized by the manager.
+ * Each event is kept under its own key, and is never updated once added, unless migrated.
+ * Each payment has a list of its events, earliest first,
+ * and each chat has a list of all its payment events, latest first.
+ * Has add, get, set, and delete operations, add and delete can also be queued into a pipeline.
+ * Events are only deleted when the whole chat is reset, and the chat list is moved when a chat is migrated.
+ */
+
+// Adds a new event of a payment in a chat
+// Mainly for testing purposes
+// In application, events are only added through ledger updates
+#[allow(dead_code)]
+pub async fn add_event(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    payment_id: &str,
+    event_id: &str,
+    event: &str,
+) -> RedisResult<()> {
+    con.set::<_, _, ()>(format!("{EVENT_KEY}:{event_id}"), event)
+        .await?;
+    con.rpush::<_, _, ()>(format!("{PAYMENT_EVENT_KEY}:{payment_id}"), event_id)
+        .await?;
+    con.lpush(format!("{CHAT_EVENT_KEY}:{chat_id}"), event_id)
+        .await
+}
+
+// Queues a new event of a payment in a chat into a pipeline
+pub fn queue_add_event(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    payment_id: &str,
+    event_id: &str,
+    event: &str,
+) {
+    pipe.set(format!("{EVENT_KEY}:{event_id}"), event).ignore();
+    pipe.rpush(format!("{PAYMENT_EVENT_KEY}:{payment_id}"), event_id)
+        .ignore();
+    pipe.lpush(format!("{CHAT_EVENT_KEY}:{chat_id}"), event_id)
+        .ignore();
+}
+
+// Gets an event
+pub async fn get_event(con: &mut ConnectionManager, event_id: &str) -> RedisResult<String> {
+    con.get(format!("{EVENT_KEY}:{event_id}")).await
+}
+
+// Gets the IDs of all events, of every chat
+// Used when events are migrated
+pub async fn get_all_events(con: &mut ConnectionManager) -> RedisResult<Vec<String>> {
+    let prefix = format!("{EVENT_KEY}:");
+    let mut keys = con.scan_match::<_, String>(format!("{prefix}*")).await?;
+    let mut event_ids = Vec::new();
+    while let Some(key) = keys.next_item().await {
+        if let Some(event_id) = key.strip_prefix(&prefix) {
+            event_ids.push(event_id.to_string());
+        }
+    }
+    Ok(event_ids)
+}
+
+// Replaces an event with its migrated version
+// Used when events are migrated
+pub async fn set_event(
+    con: &mut ConnectionManager,
+    event_id: &str,
+    event: &str,
+) -> RedisResult<()> {
+    con.set(format!("{EVENT_KEY}:{event_id}"), event).await
+}
+
+// Gets all events of a payment, earliest first
+pub async fn get_payment_events(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> RedisResult<Vec<String>> {
+    con.lrange(format!("{PAYMENT_EVENT_KEY}:{payment_id}"), 0, -1)
+        .await
+}
+
+// Gets the latest events of a chat, latest first, up to the given count
+pub async fn get_chat_events(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    count: usize,
+) -> RedisResult<Vec<String>> {
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    con.lrange(format!("{CHAT_EVENT_KEY}:{chat_id}"), 0, count as isize - 1)
+        .await
+}
+
+// Gets all events of a chat, latest first
+pub async fn get_all_chat_events(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
+    con.lrange(format!("{CHAT_EVENT_KEY}:{chat_id}"), 0, -1)
+        .await
+}
+
+// Deletes an event
+// Mainly for testing purposes
+// In application, events are only deleted through atomic pipelines
+#[allow(dead_code)]
+pub async fn delete_event(con: &mut ConnectionManager, event_id: &str) -> RedisResult<()> {
+    con.del(format!("{EVENT_KEY}:{event_id}")).await
+}
+
+// Deletes the list of events of a payment
+// Mainly for testing purposes
+#[allow(dead_code)]
+pub async fn delete_payment_events(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> RedisResult<()> {
+    con.del(format!("{PAYMENT_EVENT_KEY}:{payment_id}")).await
+}
+
+// Deletes the list of events of a chat
+// Mainly for testing purposes
+#[allow(dead_code)]
+pub async fn delete_chat_events(con: &mut ConnectionManager, chat_id: &str) -> RedisResult<()> {
+    con.del(format!("{CHAT_EVENT_KEY}:{chat_id}")).await
+}
+
+// Queues deleting an event into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_event(pipe: &mut Pipeline, event_id: &str) {
+    pipe.del(format!("{EVENT_KEY}:{event_id}")).ignore();
+}
+
+// Queues deleting the list of events of a payment into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_payment_events(pipe: &mut Pipeline, payment_id: &str) {
+    pipe.del(format!("{PAYMENT_EVENT_KEY}:{payment_id}"))
+        .ignore();
+}
+
+// Queues deleting the list of events of a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat_events(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_EVENT_KEY}:{chat_id}")).ignore();
+}
+
+// Checks if a chat has any events
+pub async fn get_chat_events_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_EVENT_KEY}:{chat_id}")).await
+}
+
+// Queues moving the list of events of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the list exists
+pub fn queue_move_chat_events(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_EVENT_KEY}:{chat_id}"),
+        format!("{CHAT_EVENT_KEY}:{new_chat_id}"),
+    )
+    .ignore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_add_get_event() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "event_123456789";
+        let payment_id = "event_payment_123456789";
+        let first_id = "event_first_123456789";
+        let second_id = "event_second_123456789";
+
+        // Clear out any events from earlier runs
+        con.del::<_, ()>(vec![
+            format!("{PAYMENT_EVENT_KEY}:{payment_id}"),
+            format!("{CHAT_EVENT_KEY}:{chat_id}"),
+        ])
+        .await
+        .unwrap();
+
+        assert!(add_event(&mut con, chat_id, payment_id, first_id, "first")
+            .await
+            .is_ok());
+        assert!(
+            add_event(&mut con, chat_id, payment_id, second_id, "second")
+                .await
+                .is_ok()
+        );
+
+        assert_eq!(get_event(&mut con, first_id).await.unwrap(), "first");
+        assert_eq!(
+            get_payment_events(&mut con, payment_id).await.unwrap(),
+            vec![first_id.to_string(), second_id.to_string()]
+        );
+        assert_eq!(
+            get_chat_events(&mut con, chat_id, 1).await.unwrap(),
+            vec![second_id.to_string()]
+        );
+        assert_eq!(
+            get_chat_events(&mut con, chat_id, 10).await.unwrap(),
+            vec![second_id.to_string(), first_id.to_string()]
+        );
+        assert_eq!(
+            get_all_chat_events(&mut con, chat_id).await.unwrap(),
+            vec![second_id.to_string(), first_id.to_string()]
+        );
+
+        for event_id in [first_id, second_id] {
+            assert!(delete_event(&mut con, event_id).await.is_ok());
+        }
+        assert!(delete_payment_events(&mut con, payment_id).await.is_ok());
+        assert!(delete_chat_events(&mut con, chat_id).await.is_ok());
+        assert!(get_event(&mut con, first_id).await.is_err());
+        assert!(get_payment_events(&mut con, payment_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(get_all_chat_events(&mut con, chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
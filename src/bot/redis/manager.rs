@@ -1,24 +1,81 @@
-use redis::RedisError;
+use std::collections::HashMap;
+
+use redis::{aio::ConnectionManager, Pipeline, RedisError};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use super::{
-    balance::{get_balance, get_balance_exists, set_balance},
+    balance::{
+        get_balance, get_balance_exists, queue_delete_balance, queue_incr_balance,
+        queue_move_balance,
+    },
+    category::{
+        get_chat_categories, get_chat_categories_exists, get_chat_category,
+        queue_delete_chat_categories, queue_move_chat_categories, queue_set_chat_category,
+        CategoryTotal,
+    },
     chat::{
-        add_chat, add_chat_currency, add_chat_payment, add_chat_user_multiple, delete_chat_payment,
-        get_chat_currencies, get_chat_currency_conversion, get_chat_default_currency,
-        get_chat_erase_messages, get_chat_exists, get_chat_payment_exists, get_chat_payments,
-        get_chat_time_zone, get_chat_users, is_exists_chat_currency_conversion,
-        is_exists_chat_default_currency, is_exists_chat_erase_messages, is_exists_chat_time_zone,
+        add_chat, add_chat_payment_index, add_chat_user_multiple, count_chat_payment_index,
+        delete_chat_payment_index, delete_chat_user, get_all_payment_chats, get_chat_currencies,
+        get_chat_currencies_exists, get_chat_currency_conversion, get_chat_default_currency,
+        get_chat_erase_messages, get_chat_exists, get_chat_payment_count, get_chat_payment_exists,
+        get_chat_payment_index_at, get_chat_payment_index_exists, get_chat_payment_index_range,
+        get_chat_payments, get_chat_settings_exists, get_chat_time_zone, get_chat_users,
+        is_exists_chat_currency_conversion, is_exists_chat_default_currency,
+        is_exists_chat_erase_messages, is_exists_chat_time_zone, queue_add_chat_currency,
+        queue_add_chat_payment, queue_add_chat_payment_index, queue_add_chat_user,
+        queue_delete_all_chat_payment, queue_delete_chat, queue_delete_chat_currencies,
+        queue_delete_chat_payment, queue_delete_chat_payment_index,
+        queue_delete_chat_payment_index_entry, queue_delete_chat_settings, queue_delete_chat_user,
+        queue_move_all_chat_payment, queue_move_chat, queue_move_chat_currencies,
+        queue_move_chat_payment_index, queue_move_chat_settings,
+        queue_set_chat_currency_conversion, queue_set_chat_default_currency,
         set_chat_currency_conversion, set_chat_default_currency, set_chat_erase_messages,
         set_chat_time_zone,
     },
-    connect::{connect, DBError},
-    payment::{add_payment, delete_payment, get_payment, update_payment, Payment},
+    connect::DBError,
+    dialogue::{
+        delete_chat_dialogue, get_chat_dialogue, get_chat_dialogue_users,
+        queue_delete_chat_dialogue, queue_move_chat_dialogue, set_chat_dialogue,
+    },
+    event::{
+        get_all_chat_events, get_all_events, get_chat_events, get_chat_events_exists, get_event,
+        get_payment_events, queue_add_event, queue_delete_chat_events, queue_delete_event,
+        queue_delete_payment_events, queue_move_chat_events, set_event,
+    },
+    migration::{get_migration_done, set_migration_done},
+    payment::{
+        get_legacy_datetime, get_legacy_payments, get_payment, get_payment_exists,
+        parse_legacy_datetime, queue_add_payment, queue_delete_payment, queue_update_payment,
+        set_payment_timestamp, Payment, PaymentItem,
+    },
+    recurring::{
+        delete_recurring, get_chat_recurring, get_due_recurring, get_recurring,
+        queue_delete_recurring, queue_set_recurring, set_recurring, ChatRecurringPayment,
+        RecurringPayment,
+    },
     request::{get_request, set_request},
-    spending::{get_spending, get_spending_exists, set_spending},
+    spending::{
+        get_spending, get_spending_exists, queue_delete_spending, queue_incr_spending,
+        queue_move_spending,
+    },
+    trash::{
+        get_chat_trash, get_chat_trash_exists, get_trash, get_trash_exists, queue_add_trash,
+        queue_delete_chat_trash, queue_delete_trash, queue_move_chat_trash,
+    },
     user::{
-        add_user, get_preferred_username, get_user_chats, get_user_exists, set_preferred_username,
-        update_user_chats,
-    }, CURRENCY_CODE_DEFAULT,
+        add_user, delete_member_name, delete_preferred_username, delete_user, get_member_names,
+        get_preferred_username, get_user_chats, get_user_exists, get_user_is_init, get_username,
+        initialize_user, queue_delete_member_name, queue_delete_preferred_username,
+        queue_delete_user, queue_delete_user_chat, queue_set_preferred_username,
+        queue_update_user_chats, set_member_name, set_preferred_username, update_user_chats,
+        update_username,
+    },
+    BALANCE_KEY, CHAT_CATEGORY_KEY, CHAT_CURRENCY_KEY, CHAT_EVENT_KEY, CHAT_KEY,
+    CHAT_PAYMENT_INDEX_KEY, CHAT_PAYMENT_KEY, CHAT_RECURRING_KEY, CHAT_SETTING_KEY, CHAT_TRASH_KEY,
+    CURRENCY_CODE_DEFAULT, DIALOGUE_KEY, EXPENSE_KEY, MIGRATION_CATEGORY_TOTALS,
+    MIGRATION_DATETIME_FALLBACK, MIGRATION_PAYMENT_TIMESTAMPS, PAYMENT_DEBT_KEY, PAYMENT_KEY,
+    RECURRING_KEY, TRASH_KEY, USER_KEY,
 };
 
 #[derive(Debug, PartialEq, Clone)]
@@ -28,27 +85,281 @@ pub struct UserBalance {
     pub balance: i64,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct UserPayment {
     pub chat_id: String,
     pub payment_id: String,
     pub payment: Payment,
 }
 
+// The position of a payment when paging through the payments of a chat, latest first
+// Payments made at the same time are ordered by payment ID, descending
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PaymentCursor {
+    pub timestamp: i64,
+    pub payment_id: String,
+}
+
+impl PaymentCursor {
+    // Gets the cursor of a payment
+    pub fn of(payment_id: &str, payment: &Payment) -> PaymentCursor {
+        PaymentCursor {
+            timestamp: payment.timestamp,
+            payment_id: payment_id.to_string(),
+        }
+    }
+
+    // Checks if a payment comes after this cursor, that is, if it is older
+    pub fn is_before(&self, timestamp: i64, payment_id: &str) -> bool {
+        timestamp < self.timestamp
+            || (timestamp == self.timestamp && payment_id < self.payment_id.as_str())
+    }
+}
+
+// A query for a page of payments in a chat, latest first
+// Starts after the cursor if given, and only includes payments within the inclusive time range
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PaymentQuery {
+    pub cursor: Option<PaymentCursor>,
+    pub page_size: usize,
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+// A page of payments in a chat, with the total number of payments within the time range
+// Next is the cursor to query the following page with, None if this is the last page
+#[derive(Debug, PartialEq, Clone)]
+pub struct PaymentPage {
+    pub payments: Vec<UserPayment>,
+    pub total: usize,
+    pub next: Option<PaymentCursor>,
+}
+
+// A payment deleted from a chat, which can still be restored
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrashedPayment {
+    pub payment_id: String,
+    pub payment: Payment,
+    pub deleted_at: i64,
+    pub deleted_by: String,
+}
+
+// An action taken on a payment, as recorded in its history
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum PaymentAction {
+    Add,
+    Edit,
+    Delete,
+    Restore,
+    Purge,
+}
+
+// An immutable record of a change to a payment, with the payment before and after the change
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PaymentEvent {
+    pub payment_id: String,
+    pub action: PaymentAction,
+    pub user_id: String,
+    pub username: String,
+    pub timestamp: i64,
+    pub before: Option<Payment>,
+    pub after: Option<Payment>,
+}
+
+// Outcome of migrating payments and their history to timestamps, to be logged
+#[derive(Debug, Default, PartialEq)]
+pub struct DatetimeMigration {
+    pub migrated: usize,
+    // Datetimes which could not be parsed, and were given MIGRATION_DATETIME_FALLBACK instead
+    pub fallbacks: Vec<String>,
+    // Records which could not be migrated at all, and are left to be retried
+    pub skipped: Vec<String>,
+}
+
+impl DatetimeMigration {
+    // Logs the outcome, as migrations run when connecting, before anything else can report it
+    pub fn log(&self, store: &str) {
+        if self.migrated > 0 {
+            log::info!(
+                "{store} - Migrated {} payments to timestamps",
+                self.migrated
+            );
+        }
+        for datetime in &self.fallbacks {
+            log::warn!(
+                "{store} - Could not parse datetime {datetime}, gave it timestamp {MIGRATION_DATETIME_FALLBACK} instead"
+            );
+        }
+        for record in &self.skipped {
+            log::error!(
+                "{store} - Skipped migrating {record} to timestamps, retrying on next connect"
+            );
+        }
+    }
+}
+
+// Migrates a serialized event from before payments kept timestamps
+// The payment before and after the change has its formatted datetime replaced by a timestamp
+// Returns None if there is nothing to migrate, else the event with any datetimes given a fallback
+pub fn migrate_legacy_event(event: &str) -> Result<Option<(String, Vec<String>)>, CrudError> {
+    let mut event: serde_json::Value = serde_json::from_str(event)?;
+    let mut is_migrated = false;
+    let mut fallbacks = Vec::new();
+    for field in ["before", "after"] {
+        let payment = match event
+            .get_mut(field)
+            .and_then(|payment| payment.as_object_mut())
+        {
+            Some(payment) => payment,
+            None => continue,
+        };
+        let datetime = match payment.remove("datetime") {
+            Some(datetime) => datetime,
+            None => continue,
+        };
+        let timestamp = datetime
+            .as_str()
+            .and_then(parse_legacy_datetime)
+            .unwrap_or_else(|| {
+                fallbacks.push(datetime.to_string());
+                MIGRATION_DATETIME_FALLBACK
+            });
+        payment.insert("timestamp".to_string(), timestamp.into());
+        is_migrated = true;
+    }
+
+    match is_migrated {
+        true => Ok(Some((serde_json::to_string(&event)?, fallbacks))),
+        false => Ok(None),
+    }
+}
+
+// The user who made a ledger update, and when
+#[derive(Debug, PartialEq, Clone)]
+pub struct LedgerActor {
+    pub user_id: String,
+    pub username: String,
+    pub timestamp: i64,
+}
+
+impl LedgerActor {
+    // Records a change to a payment made by this actor
+    pub fn event(
+        &self,
+        payment_id: &str,
+        action: PaymentAction,
+        before: Option<Payment>,
+        after: Option<Payment>,
+    ) -> PaymentEvent {
+        PaymentEvent {
+            payment_id: payment_id.to_string(),
+            action,
+            user_id: self.user_id.clone(),
+            username: self.username.clone(),
+            timestamp: self.timestamp,
+            before,
+            after,
+        }
+    }
+}
+
+// A single change to a payment entry, as part of a LedgerUpdate
+#[derive(Debug, PartialEq, Clone)]
+pub enum PaymentChange {
+    Add(Payment),
+    Update {
+        payment_id: String,
+        description: Option<String>,
+        timestamp: Option<i64>,
+        creditor: Option<String>,
+        currency: Option<String>,
+        total: Option<i64>,
+        debts: Option<Vec<(String, i64)>>,
+        items: Option<Vec<PaymentItem>>,
+        // Some(None) clears the category of the payment
+        category: Option<Option<String>>,
+        // Some of an empty list leaves the creditor as the only payer
+        payers: Option<Vec<(String, i64)>>,
+    },
+    Delete(String),
+    // Moves a payment into the trash, keeping its entry
+    Trash {
+        payment_id: String,
+        deleted_at: i64,
+        deleted_by: String,
+    },
+    // Moves a trashed payment back into the chat
+    Restore(String),
+    // Deletes a trashed payment permanently
+    Purge(String),
+}
+
+impl PaymentChange {
+    // Gets the action recorded in the payment history for this change
+    pub fn action(&self) -> PaymentAction {
+        match self {
+            PaymentChange::Add(_) => PaymentAction::Add,
+            PaymentChange::Update { .. } => PaymentAction::Edit,
+            PaymentChange::Delete(_) | PaymentChange::Trash { .. } => PaymentAction::Delete,
+            PaymentChange::Restore(_) => PaymentAction::Restore,
+            PaymentChange::Purge(_) => PaymentAction::Purge,
+        }
+    }
+}
+
+// Schedules a recurring payment past the occurrence added in the same LedgerUpdate
+// Fails if the recurring payment is no longer in the chat, or has changed since it was read
+#[derive(Debug, PartialEq, Clone)]
+pub struct RecurringChange {
+    pub recurring_id: String,
+    // The recurring payment as it was read when the occurrence became due
+    pub current: RecurringPayment,
+    // When it is next due, or None to delete it past its end
+    pub next: Option<i64>,
+}
+
+// A set of ledger mutations for a chat, which must be applied all at once or not at all
+// If there is an actor, every payment change is recorded as a payment event as well
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct LedgerUpdate {
+    pub payments: Vec<PaymentChange>,
+    pub spendings: Vec<UserBalance>,
+    // Changes to the spending of each category, added onto its total in the same currency
+    pub categories: Vec<CategoryTotal>,
+    pub balances: Vec<UserBalance>,
+    pub default_currency: Option<String>,
+    pub currency_conversion: Option<bool>,
+    pub recurring: Option<RecurringChange>,
+    pub actor: Option<LedgerActor>,
+}
+
 #[derive(thiserror::Error, Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum CrudError {
     #[error("Redis operation error: {0}")]
     RedisError(RedisError),
     #[error("Redis database error: {0}")]
     DBError(DBError),
+    #[error("SQLite operation error: {0}")]
+    SqliteError(rusqlite::Error),
     #[error("No payments found")]
     NoPaymentsError(),
     #[error("No such payment entry found")]
     NoSuchPaymentError(),
+    #[error("No such recurring payment found")]
+    NoSuchRecurringPaymentError(),
+    #[error("Recurring payment changed")]
+    RecurringChangedError(),
     #[error("Spending computed to be negative")]
     NegativeSpendingError(),
     #[error("Request limit exceeded")]
     RequestLimitExceededError(),
+    #[error("Serialization error: {0}")]
+    SerializationError(String),
+    #[error("Invalid datetime: {0}")]
+    InvalidDatetimeError(String),
+    #[error("Blocking task error: {0}")]
+    TaskError(String),
 }
 
 // Implement the From trait to convert from RedisError to CrudError
@@ -65,6 +376,20 @@ impl From<DBError> for CrudError {
     }
 }
 
+// Implement the From trait to convert from SQLite errors to CrudError
+impl From<rusqlite::Error> for CrudError {
+    fn from(sqlite_error: rusqlite::Error) -> CrudError {
+        CrudError::SqliteError(sqlite_error)
+    }
+}
+
+// Implement the From trait to convert from serde errors to CrudError
+impl From<serde_json::Error> for CrudError {
+    fn from(serde_error: serde_json::Error) -> CrudError {
+        CrudError::SerializationError(serde_error.to_string())
+    }
+}
+
 /* Redis Manager
  * Manager represents a module that manages all database operations.
  * No external package should call any of the database operations directly,
@@ -76,21 +401,24 @@ impl From<DBError> for CrudError {
  * If the user exists, ensures that chats are updated. Inits user if not init.
  * Called whenever a new payment is added, and all relevant users are updated with this.
  */
-pub fn update_user(username: &str, chat_id: &str, user_id: Option<&str>) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
+pub async fn update_user(
+    con: &mut ConnectionManager,
+    username: &str,
+    chat_id: &str,
+    user_id: Option<&str>,
+) -> Result<(), CrudError> {
     let user_key = username.to_lowercase();
 
     // Adds user if not exists
-    if !get_user_exists(&mut con, &user_key)? {
-        add_user(&mut con, &user_key, chat_id, user_id)?;
-        set_preferred_username(&mut con, username, &user_key)?;
+    if !get_user_exists(con, &user_key).await? {
+        add_user(con, &user_key, chat_id, user_id).await?;
+        set_preferred_username(con, username, &user_key).await?;
     }
 
     // Adds chat to user list if not already added
-    let current_chats = get_user_chats(&mut con, &user_key)?;
+    let current_chats = get_user_chats(con, &user_key).await?;
     if !current_chats.contains(&chat_id.to_string()) {
-        update_user_chats(&mut con, &user_key, chat_id)?;
+        update_user_chats(con, &user_key, chat_id).await?;
     }
 
     Ok(())
@@ -100,68 +428,72 @@ pub fn update_user(username: &str, chat_id: &str, user_id: Option<&str>) -> Resu
  * If the chat exists, ensures that it is updated with the usernames.
  * Called whenever a new payment is added.
  */
-pub fn update_chat(chat_id: &str, usernames: Vec<String>) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
+pub async fn update_chat(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    usernames: Vec<String>,
+) -> Result<(), CrudError> {
     // Adds chat if not exists
-    if !get_chat_exists(&mut con, chat_id)? {
+    if !get_chat_exists(con, chat_id).await? {
         if !usernames.is_empty() {
-            add_chat(&mut con, chat_id, &usernames[0].to_lowercase())?;
+            add_chat(con, chat_id, &usernames[0].to_lowercase()).await?;
         }
-        init_chat_settings(chat_id)?;
+        init_chat_settings(con, chat_id).await?;
     }
 
     // Adds all users, automatically checked if added
     add_chat_user_multiple(
-        &mut con,
+        con,
         chat_id,
         usernames.iter().map(|user| user.to_lowercase()).collect(),
-    )?;
+    )
+    .await?;
 
     Ok(())
 }
 
 /* Initialises chat settings to default.
  */
-fn init_chat_settings(chat_id: &str) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
+async fn init_chat_settings(con: &mut ConnectionManager, chat_id: &str) -> Result<(), CrudError> {
     // Set default time zone
     let default_time_zone = "UTC";
-    set_chat_time_zone(&mut con, chat_id, default_time_zone)?;
+    set_chat_time_zone(con, chat_id, default_time_zone).await?;
 
     // Set default currency
-    set_chat_default_currency(&mut con, chat_id, CURRENCY_CODE_DEFAULT)?;
+    set_chat_default_currency(con, chat_id, CURRENCY_CODE_DEFAULT).await?;
 
     // Set default currency conversion
-    set_chat_currency_conversion(&mut con, chat_id, false)?;
+    set_chat_currency_conversion(con, chat_id, false).await?;
 
     // Set default erase messages
-    set_chat_erase_messages(&mut con, chat_id, true)?;
+    set_chat_erase_messages(con, chat_id, true).await?;
 
     Ok(())
 }
 
 /* Sets time zone for a chat.
  */
-pub fn set_time_zone(chat_id: &str, time_zone: &str) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
-    set_chat_time_zone(&mut con, chat_id, time_zone)?;
+pub async fn set_time_zone(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    time_zone: &str,
+) -> Result<(), CrudError> {
+    set_chat_time_zone(con, chat_id, time_zone).await?;
     Ok(())
 }
 
 /* Gets time zone for a chat.
  */
-pub fn get_time_zone(chat_id: &str) -> Result<String, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_time_zone(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<String, CrudError> {
     // By default, return UTC
-    if !is_exists_chat_time_zone(&mut con, chat_id)? {
+    if !is_exists_chat_time_zone(con, chat_id).await? {
         return Ok("UTC".to_string());
     }
 
-    let time_zone = get_chat_time_zone(&mut con, chat_id);
+    let time_zone = get_chat_time_zone(con, chat_id).await;
     match time_zone {
         Ok(time_zone) => Ok(time_zone),
         Err(_) => Ok("UTC".to_string()),
@@ -170,24 +502,27 @@ pub fn get_time_zone(chat_id: &str) -> Result<String, CrudError> {
 
 /* Sets default currency for a chat.
  */
-pub fn set_default_currency(chat_id: &str, currency: &str) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
-    set_chat_default_currency(&mut con, chat_id, currency)?;
+pub async fn set_default_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    currency: &str,
+) -> Result<(), CrudError> {
+    set_chat_default_currency(con, chat_id, currency).await?;
     Ok(())
 }
 
 /* Gets default currency for a chat.
  */
-pub fn get_default_currency(chat_id: &str) -> Result<String, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_default_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<String, CrudError> {
     // By default, return NIL
-    if !is_exists_chat_default_currency(&mut con, chat_id)? {
+    if !is_exists_chat_default_currency(con, chat_id).await? {
         return Ok(CURRENCY_CODE_DEFAULT.to_string());
     }
 
-    let currency = get_chat_default_currency(&mut con, chat_id);
+    let currency = get_chat_default_currency(con, chat_id).await;
     match currency {
         Ok(currency) => Ok(currency),
         Err(_) => Ok(CURRENCY_CODE_DEFAULT.to_string()),
@@ -196,24 +531,27 @@ pub fn get_default_currency(chat_id: &str) -> Result<String, CrudError> {
 
 /* Sets currency conversion for a chat.
  */
-pub fn set_currency_conversion(chat_id: &str, conversion: bool) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
-    set_chat_currency_conversion(&mut con, chat_id, conversion)?;
+pub async fn set_currency_conversion(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    conversion: bool,
+) -> Result<(), CrudError> {
+    set_chat_currency_conversion(con, chat_id, conversion).await?;
     Ok(())
 }
 
 /* Gets currency conversion for a chat.
  */
-pub fn get_currency_conversion(chat_id: &str) -> Result<bool, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_currency_conversion(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<bool, CrudError> {
     // By default, return false
-    if !is_exists_chat_currency_conversion(&mut con, chat_id)? {
+    if !is_exists_chat_currency_conversion(con, chat_id).await? {
         return Ok(false);
     }
 
-    let conversion = get_chat_currency_conversion(&mut con, chat_id);
+    let conversion = get_chat_currency_conversion(con, chat_id).await;
     match conversion {
         Ok(conversion) => Ok(conversion),
         Err(_) => Ok(false),
@@ -222,24 +560,27 @@ pub fn get_currency_conversion(chat_id: &str) -> Result<bool, CrudError> {
 
 /* Sets erase messages for a chat.
  */
-pub fn set_erase_messages(chat_id: &str, erase: bool) -> Result<(), CrudError> {
-    let mut con = connect()?;
-
-    set_chat_erase_messages(&mut con, chat_id, erase)?;
+pub async fn set_erase_messages(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    erase: bool,
+) -> Result<(), CrudError> {
+    set_chat_erase_messages(con, chat_id, erase).await?;
     Ok(())
 }
 
 /* Gets erase messages for a chat.
  */
-pub fn get_erase_messages(chat_id: &str) -> Result<bool, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_erase_messages(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<bool, CrudError> {
     // By default, return true
-    if !is_exists_chat_erase_messages(&mut con, chat_id)? {
+    if !is_exists_chat_erase_messages(con, chat_id).await? {
         return Ok(true);
     }
 
-    let erase = get_chat_erase_messages(&mut con, chat_id);
+    let erase = get_chat_erase_messages(con, chat_id).await;
     match erase {
         Ok(erase) => Ok(erase),
         Err(_) => Ok(true),
@@ -249,21 +590,22 @@ pub fn get_erase_messages(chat_id: &str) -> Result<bool, CrudError> {
 /* Gets all valid currencies for a chat.
  * Valid currencies are currencies with some payments.
  */
-pub fn get_valid_chat_currencies(chat_id: &str) -> Result<Vec<String>, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_valid_chat_currencies(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<String>, CrudError> {
     // Retrieve all currencies
-    let currencies = get_chat_currencies(&mut con, chat_id)?;
+    let currencies = get_chat_currencies(con, chat_id).await?;
 
     // Retrieve all users
-    let users = get_chat_users(&mut con, chat_id)?;
+    let users = get_chat_users(con, chat_id).await?;
 
     // For each currency, check that there is at least one spending amongst all users
     let mut valid_currencies: Vec<String> = Vec::new();
     for currency in &currencies {
         for user in &users {
-            if get_spending_exists(&mut con, chat_id, user, &currency)?
-                && get_spending(&mut con, chat_id, user, &currency)? > 0
+            if get_spending_exists(con, chat_id, user, currency).await?
+                && get_spending(con, chat_id, user, currency).await? > 0
             {
                 valid_currencies.push(currency.to_string());
                 break;
@@ -277,23 +619,23 @@ pub fn get_valid_chat_currencies(chat_id: &str) -> Result<Vec<String>, CrudError
 /* Gets all balances for a chat.
  * Used when updating default currencies for a chat.
  */
-pub fn get_chat_balances(chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError> {
-    let mut con = connect()?;
-
+pub async fn get_chat_balances(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<Vec<UserBalance>>, CrudError> {
     // Retrieve all balances
     let mut balances: Vec<Vec<UserBalance>> = Vec::new();
-    let users = get_chat_users(&mut con, chat_id)?;
-    let currencies = get_chat_currencies(&mut con, chat_id)?;
+    let users = get_chat_users(con, chat_id).await?;
+    let currencies = get_chat_currencies(con, chat_id).await?;
 
-    let mut curr_index = 0;
-    for currency in &currencies {
+    for (curr_index, currency) in currencies.iter().enumerate() {
         balances.push(Vec::new());
 
         for user in &users {
-            if get_balance_exists(&mut con, chat_id, user, &currency)? {
-                let balance = get_balance(&mut con, chat_id, user, &currency)?;
+            if get_balance_exists(con, chat_id, user, currency).await? {
+                let balance = get_balance(con, chat_id, user, currency).await?;
                 if balance != 0 {
-                    let username = get_preferred_username(&mut con, user)?;
+                    let username = get_preferred_username(con, user).await?;
                     balances[curr_index].push(UserBalance {
                         username,
                         currency: currency.to_string(),
@@ -302,8 +644,6 @@ pub fn get_chat_balances(chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudErr
                 }
             }
         }
-
-        curr_index += 1;
     }
 
     Ok(balances)
@@ -311,21 +651,20 @@ pub fn get_chat_balances(chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudErr
 
 /* Gets all balances for a chat for a specific currency.
  */
-pub fn get_chat_balances_currency(
+pub async fn get_chat_balances_currency(
+    con: &mut ConnectionManager,
     chat_id: &str,
     currency: &str,
 ) -> Result<Vec<UserBalance>, CrudError> {
-    let mut con = connect()?;
-
     // Retrieve all balances
     let mut balances: Vec<UserBalance> = Vec::new();
-    let users = get_chat_users(&mut con, chat_id)?;
+    let users = get_chat_users(con, chat_id).await?;
 
     for user in &users {
-        if get_balance_exists(&mut con, chat_id, user, currency)? {
-            let balance = get_balance(&mut con, chat_id, user, currency)?;
+        if get_balance_exists(con, chat_id, user, currency).await? {
+            let balance = get_balance(con, chat_id, user, currency).await?;
             if balance != 0 {
-                let username = get_preferred_username(&mut con, user)?;
+                let username = get_preferred_username(con, user).await?;
                 balances.push(UserBalance {
                     username,
                     currency: currency.to_string(),
@@ -338,91 +677,191 @@ pub fn get_chat_balances_currency(
     Ok(balances)
 }
 
-/* Updates balances for a chat based on given change amounts.
- * If balance does not exist, creates it.
- * Returns the updated balances.
+/* Retrieves all payments for a chat and their details.
+ * Called whenever a user views past payments.
  */
-pub fn update_chat_balances(chat_id: &str, changes: Vec<UserBalance>) -> Result<(), CrudError> {
-    let mut con = connect()?;
+pub async fn get_chat_payments_details(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<UserPayment>, CrudError> {
+    if get_chat_payment_exists(con, chat_id).await.is_err() {
+        return Err(CrudError::NoPaymentsError());
+    }
 
-    // Update balances through changes
-    for change in changes {
-        let username = change.username.to_lowercase();
-        let balance = change.balance;
-        let currency = change.currency;
+    // Trashed payments are kept in the chat, but not shown
+    let trash = get_chat_trash(con, chat_id).await?;
+    let payment_ids: Vec<String> = get_chat_payments(con, chat_id)
+        .await?
+        .into_iter()
+        .filter(|payment_id| !trash.contains(payment_id))
+        .collect();
+    let mut payments: Vec<UserPayment> = Vec::new();
 
-        // Update currency into chat first
-        let currencies = get_chat_currencies(&mut con, chat_id)?;
-        if !currencies.contains(&currency) {
-            add_chat_currency(&mut con, chat_id, &currency)?;
-        }
+    if payment_ids.is_empty() {
+        return Err(CrudError::NoPaymentsError());
+    }
 
-        // Update balance
-        if let Ok(false) = get_balance_exists(&mut con, chat_id, &username, &currency) {
-            set_balance(&mut con, chat_id, &username, &currency, balance)?;
-        } else {
-            let current_balance = get_balance(&mut con, chat_id, &username, &currency)?;
-            let new_balance = current_balance + balance;
-            set_balance(&mut con, chat_id, &username, &currency, new_balance)?;
-        }
+    for payment_id in payment_ids {
+        let payment = get_payment(con, &payment_id).await?;
+        let user_payment = UserPayment {
+            chat_id: chat_id.to_string(),
+            payment_id,
+            payment,
+        };
+        payments.push(user_payment);
     }
 
-    Ok(())
+    Ok(payments)
 }
 
-/* Adds a payment.
- * Sets a new key-value pair for the payment, and updates the payments list in chat.
- * Called whenever a new payment is added.
+/* Retrieves a page of payments for a chat and their details, latest first.
+ * Payments are read from the chat payment index, so only the page is loaded.
+ * Called whenever a user views past payments, or moves between pages of them.
  */
-pub fn add_payment_entry(chat_id: &str, payment: &Payment) -> Result<(), CrudError> {
-    let mut con = connect()?;
+pub async fn get_chat_payments_page(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    query: &PaymentQuery,
+) -> Result<PaymentPage, CrudError> {
+    index_chat_payments(con, chat_id).await?;
+
+    // Ranks of the payments within the time range, latest first
+    let total = count_chat_payment_index(con, chat_id, query.from, query.to).await?;
+    let mut start = match query.to {
+        Some(to) => count_chat_payment_index(con, chat_id, Some(to + 1), None).await?,
+        None => 0,
+    };
+    let end = start + total;
+
+    // Skips the payments up to the cursor, even if the payment at the cursor is gone
+    if let Some(cursor) = &query.cursor {
+        let later =
+            count_chat_payment_index(con, chat_id, Some(cursor.timestamp + 1), None).await?;
+        let tied = get_chat_payment_index_at(con, chat_id, cursor.timestamp)
+            .await?
+            .into_iter()
+            .filter(|payment_id| !cursor.is_before(cursor.timestamp, payment_id))
+            .count();
+        start = start.max(later + tied);
+    }
 
-    // Adds payment
-    let payment_id = add_payment(&mut con, &payment)?;
+    let stop = end.min(start + query.page_size);
+    let mut payments: Vec<UserPayment> = Vec::new();
+    if start < stop {
+        for (payment_id, _) in get_chat_payment_index_range(con, chat_id, start, stop - 1).await? {
+            let payment = get_payment(con, &payment_id).await?;
+            payments.push(UserPayment {
+                chat_id: chat_id.to_string(),
+                payment_id,
+                payment,
+            });
+        }
+    }
 
-    // Adds payment to chat
-    add_chat_payment(&mut con, chat_id, &payment_id)?;
+    let next = match payments.last() {
+        Some(last) if start + payments.len() < end => {
+            Some(PaymentCursor::of(&last.payment_id, &last.payment))
+        }
+        _ => None,
+    };
 
-    Ok(())
+    Ok(PaymentPage {
+        payments,
+        total,
+        next,
+    })
 }
 
-/* Retrieves all payments for a chat and their details.
- * Called whenever a user views past payments.
- */
-pub fn get_chat_payments_details(chat_id: &str) -> Result<Vec<UserPayment>, CrudError> {
-    let mut con = connect()?;
+// Rebuilds the payment index of a chat if it is out of sync with the payments in the chat
+// Chats with payments from before the index was added are indexed when first viewed
+async fn index_chat_payments(con: &mut ConnectionManager, chat_id: &str) -> Result<(), CrudError> {
+    let trash = get_chat_trash(con, chat_id).await?;
+    let count = get_chat_payment_count(con, chat_id).await?;
+    if count_chat_payment_index(con, chat_id, None, None).await? + trash.len() == count {
+        return Ok(());
+    }
 
-    if let Err(_) = get_chat_payment_exists(&mut con, chat_id) {
-        return Err(CrudError::NoPaymentsError());
+    delete_chat_payment_index(con, chat_id).await?;
+    for payment_id in get_chat_payments(con, chat_id).await? {
+        if trash.contains(&payment_id) {
+            continue;
+        }
+        let payment = get_payment(con, &payment_id).await?;
+        add_chat_payment_index(con, chat_id, &payment_id, payment.timestamp).await?;
     }
 
-    let payment_ids = get_chat_payments(&mut con, chat_id)?;
-    let mut payments: Vec<UserPayment> = Vec::new();
+    Ok(())
+}
 
-    if payment_ids.is_empty() {
-        return Err(CrudError::NoPaymentsError());
-    }
+/* Retrieves all trashed payments for a chat and their details, latest deletion first.
+ * Called whenever a user views the trash, or expired payments are purged.
+ */
+pub async fn get_chat_trash_details(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<TrashedPayment>, CrudError> {
+    let payment_ids = get_chat_trash(con, chat_id).await?;
+    let mut payments: Vec<TrashedPayment> = Vec::new();
 
     for payment_id in payment_ids {
-        let payment = get_payment(&mut con, &payment_id)?;
-        let user_payment = UserPayment {
-            chat_id: chat_id.to_string(),
+        let payment = get_payment(con, &payment_id).await?;
+        let (deleted_at, deleted_by) = get_trash(con, &payment_id).await?;
+        payments.push(TrashedPayment {
             payment_id,
             payment,
-        };
-        payments.push(user_payment);
+            deleted_at,
+            deleted_by,
+        });
     }
 
     Ok(payments)
 }
 
+/* Retrieves the history of a payment, earliest event first.
+ * Events are kept even after the payment is deleted.
+ * Called whenever a user views the history of a payment.
+ */
+pub async fn get_payment_history(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> Result<Vec<PaymentEvent>, CrudError> {
+    let event_ids = get_payment_events(con, payment_id).await?;
+    get_events(con, event_ids).await
+}
+
+/* Retrieves the latest payment events of a chat, latest first, up to the given count.
+ * Called whenever a user views the activity of a chat.
+ */
+pub async fn get_chat_activity(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    count: usize,
+) -> Result<Vec<PaymentEvent>, CrudError> {
+    let event_ids = get_chat_events(con, chat_id, count).await?;
+    get_events(con, event_ids).await
+}
+
+// Gets and deserializes the given events, in the same order
+async fn get_events(
+    con: &mut ConnectionManager,
+    event_ids: Vec<String>,
+) -> Result<Vec<PaymentEvent>, CrudError> {
+    let mut events: Vec<PaymentEvent> = Vec::new();
+    for event_id in event_ids {
+        let event = get_event(con, &event_id).await?;
+        events.push(serde_json::from_str(&event)?);
+    }
+    Ok(events)
+}
+
 /* Retrieves a specific payment entry by ID.
  * Called when a user wants to edit or delete a payment.
  */
-pub fn get_payment_entry(payment_id: &str) -> Result<Payment, CrudError> {
-    let mut con = connect()?;
-
-    let payment = get_payment(&mut con, payment_id);
+pub async fn get_payment_entry(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> Result<Payment, CrudError> {
+    let payment = get_payment(con, payment_id).await;
 
     match payment {
         Err(_) => Err(CrudError::NoSuchPaymentError()),
@@ -430,159 +869,1139 @@ pub fn get_payment_entry(payment_id: &str) -> Result<Payment, CrudError> {
     }
 }
 
-/* Updates a payment entry.
- * Called when a user edits payment details.
+/* Retrieves all spendings for a chat for all currencies.
+ * Returns a vector of UserBalance by user.
  */
-pub fn update_payment_entry(
-    payment_id: &str,
-    description: Option<&str>,
-    creditor: Option<&str>,
-    currency: Option<&str>,
-    total: Option<&i64>,
-    debts: Option<Vec<(String, i64)>>,
+pub async fn retrieve_chat_spendings(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+    let mut spendings: Vec<Vec<UserBalance>> = Vec::new();
+    let users = get_chat_users(con, chat_id).await?;
+    let currencies = get_chat_currencies(con, chat_id).await?;
+
+    for (curr_index, currency) in currencies.iter().enumerate() {
+        spendings.push(Vec::new());
+
+        for user in &users {
+            if get_spending_exists(con, chat_id, user, currency).await? {
+                let spending = get_spending(con, chat_id, user, currency).await?;
+                if spending != 0 {
+                    let username = get_preferred_username(con, user).await?;
+                    spendings[curr_index].push(UserBalance {
+                        username,
+                        currency: currency.to_string(),
+                        balance: spending as i64,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(spendings)
+}
+
+/* Retrieves all spendings for a chat for specific currency.
+ * Returns a vector of UserBalance by user.
+ */
+pub async fn retrieve_chat_spendings_currency(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    currency: &str,
+) -> Result<Vec<UserBalance>, CrudError> {
+    let mut spendings: Vec<UserBalance> = Vec::new();
+    let users = get_chat_users(con, chat_id).await?;
+
+    for user in &users {
+        if get_spending_exists(con, chat_id, user, currency).await? {
+            let spending = get_spending(con, chat_id, user, currency).await?;
+            if spending != 0 {
+                let username = get_preferred_username(con, user).await?;
+                spendings.push(UserBalance {
+                    username,
+                    currency: currency.to_string(),
+                    balance: spending as i64,
+                });
+            }
+        }
+    }
+
+    Ok(spendings)
+}
+
+/* Retrieves the spending of a chat on each category of payments, in each currency.
+ * Totals are kept by every ledger update, so no payment has to be read.
+ */
+pub async fn retrieve_chat_categories(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<CategoryTotal>, CrudError> {
+    let totals = get_chat_categories(con, chat_id).await?;
+    Ok(totals)
+}
+
+/* Applies a ledger update atomically.
+ * All keys that are read are watched, and all writes are queued into a single MULTI/EXEC.
+ * If any watched key is changed by another client before EXEC, the update is retried.
+ * If any check fails (missing payment, negative spending), nothing is written.
+ * Called whenever a payment is added, edited, or deleted, or the default currency changes.
+ */
+pub async fn apply_ledger_update(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    update: LedgerUpdate,
 ) -> Result<(), CrudError> {
-    let mut con = connect()?;
+    let keys = ledger_update_keys(chat_id, &update);
+
+    loop {
+        watch_keys(con, &keys).await?;
+        let pipe = queue_ledger_update(con, chat_id, &update).await;
+        if exec_watched(con, pipe).await? {
+            return Ok(());
+        }
+    }
+}
+
+// Watches keys read by an atomic update, so that it is aborted if any of them changes
+async fn watch_keys(con: &mut ConnectionManager, keys: &[String]) -> Result<(), CrudError> {
+    if keys.is_empty() {
+        return Ok(());
+    }
+    redis::cmd("WATCH")
+        .arg(keys)
+        .query_async::<_, ()>(con)
+        .await?;
+    Ok(())
+}
+
+// Executes an atomic update queued under WATCH, unwatching all keys if it failed to be queued
+// Returns false if any watched key was changed, in which case the update has to be retried
+async fn exec_watched(
+    con: &mut ConnectionManager,
+    pipe: Result<Pipeline, CrudError>,
+) -> Result<bool, CrudError> {
+    let pipe = match pipe {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            redis::cmd("UNWATCH").query_async::<_, ()>(con).await?;
+            return Err(err);
+        }
+    };
+
+    // EXEC returns nil if any watched key was changed
+    let result: Option<()> = pipe.query_async(con).await?;
+    Ok(result.is_some())
+}
+
+// Gets all keys read by a ledger update, which have to be watched
+fn ledger_update_keys(chat_id: &str, update: &LedgerUpdate) -> Vec<String> {
+    let mut keys = vec![format!("{CHAT_CURRENCY_KEY}:{chat_id}")];
+
+    for change in &update.payments {
+        match change {
+            PaymentChange::Add(_) => {}
+            PaymentChange::Update { payment_id, .. }
+            | PaymentChange::Delete(payment_id)
+            | PaymentChange::Trash { payment_id, .. }
+            | PaymentChange::Restore(payment_id)
+            | PaymentChange::Purge(payment_id) => {
+                keys.push(format!("{PAYMENT_KEY}:{payment_id}"));
+                keys.push(format!("{PAYMENT_DEBT_KEY}:{payment_id}"));
+                keys.push(format!("{TRASH_KEY}:{payment_id}"));
+            }
+        }
+    }
+    for spending in &update.spendings {
+        let user = spending.username.to_lowercase();
+        let currency = &spending.currency;
+        keys.push(format!("{EXPENSE_KEY}:{chat_id}:{user}:{currency}"));
+    }
+    if !update.categories.is_empty() {
+        keys.push(format!("{CHAT_CATEGORY_KEY}:{chat_id}"));
+    }
+    if let Some(recurring) = &update.recurring {
+        keys.push(format!("{RECURRING_KEY}:{}", recurring.recurring_id));
+    }
+
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// Reads current values, checks the update, and queues all writes into an atomic pipeline
+async fn queue_ledger_update(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    update: &LedgerUpdate,
+) -> Result<Pipeline, CrudError> {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    // Payments, with the events recorded for each of them
+    let mut events: Vec<PaymentEvent> = Vec::new();
+    for change in &update.payments {
+        if let Some(actor) = &update.actor {
+            if let Some(event) = read_payment_event(con, actor, change).await? {
+                events.push(event);
+            }
+        }
+
+        match change {
+            PaymentChange::Add(payment) => {
+                let payment_id = Uuid::new_v4().to_string();
+                queue_add_payment(&mut pipe, &payment_id, payment)?;
+                queue_add_chat_payment(&mut pipe, chat_id, &payment_id);
+                queue_add_chat_payment_index(&mut pipe, chat_id, &payment_id, payment.timestamp);
+                if let Some(actor) = &update.actor {
+                    events.push(actor.event(
+                        &payment_id,
+                        PaymentAction::Add,
+                        None,
+                        Some(payment.clone()),
+                    ));
+                }
+            }
+            PaymentChange::Update {
+                payment_id,
+                description,
+                timestamp,
+                creditor,
+                currency,
+                total,
+                debts,
+                items,
+                category,
+                payers,
+            } => {
+                if !get_payment_exists(con, payment_id).await?
+                    || get_trash_exists(con, payment_id).await?
+                {
+                    return Err(CrudError::NoSuchPaymentError());
+                }
+                queue_update_payment(
+                    &mut pipe,
+                    payment_id,
+                    description.as_deref(),
+                    *timestamp,
+                    creditor.as_deref(),
+                    currency.as_deref(),
+                    total.as_ref(),
+                    debts.as_deref(),
+                    items.as_deref(),
+                    category.as_ref().map(|category| category.as_deref()),
+                    payers.as_deref(),
+                )?;
+                // Re-dated payments are moved within the chat's payment index
+                if let Some(timestamp) = timestamp {
+                    queue_add_chat_payment_index(&mut pipe, chat_id, payment_id, *timestamp);
+                }
+            }
+            PaymentChange::Delete(payment_id) => {
+                if !get_payment_exists(con, payment_id).await? {
+                    return Err(CrudError::NoSuchPaymentError());
+                }
+                queue_delete_payment(&mut pipe, payment_id);
+                queue_delete_chat_payment(&mut pipe, chat_id, payment_id);
+                queue_delete_chat_payment_index_entry(&mut pipe, chat_id, payment_id);
+                queue_delete_trash(&mut pipe, chat_id, payment_id);
+            }
+            PaymentChange::Trash {
+                payment_id,
+                deleted_at,
+                deleted_by,
+            } => {
+                if !get_payment_exists(con, payment_id).await?
+                    || get_trash_exists(con, payment_id).await?
+                {
+                    return Err(CrudError::NoSuchPaymentError());
+                }
+                queue_add_trash(&mut pipe, chat_id, payment_id, *deleted_at, deleted_by);
+                queue_delete_chat_payment_index_entry(&mut pipe, chat_id, payment_id);
+            }
+            PaymentChange::Restore(payment_id) => {
+                if !get_trash_exists(con, payment_id).await? {
+                    return Err(CrudError::NoSuchPaymentError());
+                }
+                let payment = get_payment(con, payment_id).await?;
+                queue_delete_trash(&mut pipe, chat_id, payment_id);
+                queue_add_chat_payment_index(&mut pipe, chat_id, payment_id, payment.timestamp);
+            }
+            PaymentChange::Purge(payment_id) => {
+                if !get_trash_exists(con, payment_id).await? {
+                    return Err(CrudError::NoSuchPaymentError());
+                }
+                queue_delete_payment(&mut pipe, payment_id);
+                queue_delete_chat_payment(&mut pipe, chat_id, payment_id);
+                queue_delete_chat_payment_index_entry(&mut pipe, chat_id, payment_id);
+                queue_delete_trash(&mut pipe, chat_id, payment_id);
+            }
+        }
+    }
+
+    for event in &events {
+        let event_id = Uuid::new_v4().to_string();
+        let serialized = serde_json::to_string(event)?;
+        queue_add_event(
+            &mut pipe,
+            chat_id,
+            &event.payment_id,
+            &event_id,
+            &serialized,
+        );
+    }
+
+    // Spendings, checked against a running total so that the same key can change more than once
+    let mut spendings: Vec<((String, String), i64)> = Vec::new();
+    for spending in &update.spendings {
+        let key = (spending.username.to_lowercase(), spending.currency.clone());
+        let index = match spendings.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                let current = if get_spending_exists(con, chat_id, &key.0, &key.1).await? {
+                    get_spending(con, chat_id, &key.0, &key.1).await? as i64
+                } else {
+                    0
+                };
+                spendings.push((key, current));
+                spendings.len() - 1
+            }
+        };
+
+        spendings[index].1 += spending.balance;
+        if spendings[index].1 < 0 {
+            return Err(CrudError::NegativeSpendingError());
+        }
+    }
+    for spending in &update.spendings {
+        let user = spending.username.to_lowercase();
+        queue_incr_spending(
+            &mut pipe,
+            chat_id,
+            &user,
+            &spending.currency,
+            spending.balance,
+        );
+    }
+
+    // Category totals, checked against a running total the same as spendings
+    let mut categories: Vec<CategoryTotal> = Vec::new();
+    for change in &update.categories {
+        let index = match categories
+            .iter()
+            .position(|total| total.key() == change.key() && total.currency == change.currency)
+        {
+            Some(index) => index,
+            None => {
+                let current =
+                    get_chat_category(con, chat_id, change)
+                        .await?
+                        .unwrap_or(CategoryTotal {
+                            spending: 0,
+                            ..change.clone()
+                        });
+                categories.push(current);
+                categories.len() - 1
+            }
+        };
+
+        categories[index].add(change);
+        if categories[index].spending < 0 {
+            return Err(CrudError::NegativeSpendingError());
+        }
+    }
+    for total in &categories {
+        queue_set_chat_category(&mut pipe, chat_id, total)?;
+    }
+
+    // Balances, adding any new currencies into the chat
+    // Balances are only incremented, so they do not need to be read or watched
+    let mut currencies = get_chat_currencies(con, chat_id).await?;
+    for balance in &update.balances {
+        if !currencies.contains(&balance.currency) {
+            queue_add_chat_currency(&mut pipe, chat_id, &balance.currency);
+            currencies.push(balance.currency.clone());
+        }
+
+        let user = balance.username.to_lowercase();
+        queue_incr_balance(
+            &mut pipe,
+            chat_id,
+            &user,
+            &balance.currency,
+            balance.balance,
+        );
+    }
+
+    // Settings
+    if let Some(currency) = &update.default_currency {
+        queue_set_chat_default_currency(&mut pipe, chat_id, currency);
+    }
+    if let Some(conversion) = update.currency_conversion {
+        queue_set_chat_currency_conversion(&mut pipe, chat_id, conversion);
+    }
+
+    // Recurring payment, only if it is still as it was read
+    if let Some(change) = &update.recurring {
+        let mut recurring = match get_recurring(con, &change.recurring_id).await? {
+            Some(recurring) if recurring.chat_id == chat_id => recurring,
+            _ => return Err(CrudError::NoSuchRecurringPaymentError()),
+        };
+        if recurring.recurring != change.current {
+            return Err(CrudError::RecurringChangedError());
+        }
+        match change.next {
+            Some(next) => {
+                recurring.recurring.next = next;
+                queue_set_recurring(&mut pipe, &recurring)?;
+            }
+            None => queue_delete_recurring(&mut pipe, chat_id, &change.recurring_id),
+        }
+    }
+
+    Ok(pipe)
+}
 
-    if let Err(_) = get_payment(&mut con, payment_id) {
-        return Err(CrudError::NoSuchPaymentError());
+// Reads the payment before and after an existing payment is changed, as a payment event
+// Returns None for new payments, or payments which do not exist, to be checked by the caller
+async fn read_payment_event(
+    con: &mut ConnectionManager,
+    actor: &LedgerActor,
+    change: &PaymentChange,
+) -> Result<Option<PaymentEvent>, CrudError> {
+    let payment_id = match change {
+        PaymentChange::Add(_) => return Ok(None),
+        PaymentChange::Update { payment_id, .. }
+        | PaymentChange::Delete(payment_id)
+        | PaymentChange::Trash { payment_id, .. }
+        | PaymentChange::Restore(payment_id)
+        | PaymentChange::Purge(payment_id) => payment_id,
+    };
+    if !get_payment_exists(con, payment_id).await? {
+        return Ok(None);
+    }
+
+    let payment = get_payment(con, payment_id).await?;
+    let (before, after) = match change {
+        PaymentChange::Update {
+            description,
+            timestamp,
+            creditor,
+            currency,
+            total,
+            debts,
+            items,
+            category,
+            payers,
+            ..
+        } => {
+            let mut after = payment.clone();
+            if let Some(description) = description {
+                after.description = description.clone();
+            }
+            if let Some(timestamp) = timestamp {
+                after.timestamp = *timestamp;
+            }
+            if let Some(creditor) = creditor {
+                after.creditor = creditor.clone();
+            }
+            if let Some(currency) = currency {
+                after.currency = currency.clone();
+            }
+            if let Some(total) = total {
+                after.total = *total;
+            }
+            if let Some(debts) = debts {
+                after.debts = debts.clone();
+            }
+            if let Some(items) = items {
+                after.items = items.clone();
+            }
+            if let Some(category) = category {
+                after.category = category.clone();
+            }
+            if let Some(payers) = payers {
+                after.payers = payers.clone();
+            }
+            (Some(payment), Some(after))
+        }
+        PaymentChange::Restore(_) => (None, Some(payment)),
+        _ => (Some(payment), None),
+    };
+
+    Ok(Some(actor.event(
+        payment_id,
+        change.action(),
+        before,
+        after,
+    )))
+}
+
+/* Adds a recurring payment to a chat.
+ * Returns the ID of the recurring payment.
+ */
+pub async fn add_recurring_payment(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    recurring: &RecurringPayment,
+) -> Result<String, CrudError> {
+    let recurring_id = Uuid::new_v4().to_string();
+    let recurring = ChatRecurringPayment {
+        recurring_id: recurring_id.clone(),
+        chat_id: chat_id.to_string(),
+        recurring: recurring.clone(),
+    };
+    set_recurring(con, &recurring).await?;
+    Ok(recurring_id)
+}
+
+/* Retrieves all recurring payments of a chat, earliest due first.
+ */
+pub async fn get_chat_recurring_payments(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+    let mut recurring_payments = Vec::new();
+    for recurring_id in get_chat_recurring(con, chat_id).await? {
+        if let Some(recurring) = get_recurring(con, &recurring_id).await? {
+            recurring_payments.push(recurring);
+        }
     }
+    recurring_payments.sort_by_key(|recurring| recurring.recurring.next);
+    Ok(recurring_payments)
+}
+
+/* Retrieves all recurring payments due by the given time, across all chats, earliest due first.
+ * Called by the scheduler, which adds the payments that are due.
+ */
+pub async fn get_due_recurring_payments(
+    con: &mut ConnectionManager,
+    now: i64,
+) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+    let mut recurring_payments = Vec::new();
+    for recurring_id in get_due_recurring(con, now).await? {
+        if let Some(recurring) = get_recurring(con, &recurring_id).await? {
+            recurring_payments.push(recurring);
+        }
+    }
+    Ok(recurring_payments)
+}
+
+/* Deletes a recurring payment of a chat.
+ * Payments it has already added are kept.
+ */
+pub async fn delete_recurring_payment(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    recurring_id: &str,
+) -> Result<(), CrudError> {
+    match get_recurring(con, recurring_id).await? {
+        Some(recurring) if recurring.chat_id == chat_id => {
+            delete_recurring(con, chat_id, recurring_id).await?;
+            Ok(())
+        }
+        _ => Err(CrudError::NoSuchRecurringPaymentError()),
+    }
+}
+
+/* Deletes all records of a chat atomically.
+ * Removes every payment with its trash entry and history, all recurring payments,
+ * all balances and spendings, category totals, currencies and settings,
+ * and takes the chat out of the chats of its users.
+ * The chat lists used to find everything else are watched, and all deletes are queued
+ * into a single MULTI/EXEC, which is retried if any of the lists changes before EXEC.
+ * Called when a group admin resets the group.
+ */
+pub async fn reset_chat(con: &mut ConnectionManager, chat_id: &str) -> Result<(), CrudError> {
+    let keys = chat_list_keys(chat_id);
+
+    loop {
+        watch_keys(con, &keys).await?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let pipe = queue_reset_chat(con, &mut pipe, chat_id)
+            .await
+            .map(|_| pipe);
+        if exec_watched(con, pipe).await? {
+            return Ok(());
+        }
+    }
+}
+
+// Gets the lists of a chat which are read to find all of its records, which have to be watched
+fn chat_list_keys(chat_id: &str) -> Vec<String> {
+    vec![
+        format!("{CHAT_KEY}:{chat_id}"),
+        format!("{CHAT_CURRENCY_KEY}:{chat_id}"),
+        format!("{CHAT_PAYMENT_KEY}:{chat_id}"),
+        format!("{CHAT_EVENT_KEY}:{chat_id}"),
+        format!("{CHAT_RECURRING_KEY}:{chat_id}"),
+    ]
+}
+
+// Reads all records of a chat, and queues deleting them into an atomic pipeline
+// The chat lists themselves are deleted last, as they are needed to find everything else
+async fn queue_reset_chat(
+    con: &mut ConnectionManager,
+    pipe: &mut Pipeline,
+    chat_id: &str,
+) -> Result<(), CrudError> {
+    // Payments, including those in the trash
+    for payment_id in get_chat_payments(con, chat_id).await? {
+        queue_delete_payment(pipe, &payment_id);
+        queue_delete_trash(pipe, chat_id, &payment_id);
+    }
+
+    // History, including that of payments which were already purged
+    let event_ids = get_all_chat_events(con, chat_id).await?;
+    for event in get_events(con, event_ids.clone()).await? {
+        queue_delete_payment_events(pipe, &event.payment_id);
+    }
+    for event_id in &event_ids {
+        queue_delete_event(pipe, event_id);
+    }
+
+    // Balances and spendings of every user in every currency
+    let users = get_chat_users(con, chat_id).await?;
+    let currencies = get_chat_currencies(con, chat_id).await?;
+    for user in &users {
+        for currency in &currencies {
+            queue_delete_balance(pipe, chat_id, user, currency);
+            queue_delete_spending(pipe, chat_id, user, currency);
+        }
+        queue_delete_user_chat(pipe, user, chat_id);
+    }
+
+    for recurring_id in get_chat_recurring(con, chat_id).await? {
+        queue_delete_recurring(pipe, chat_id, &recurring_id);
+    }
+
+    queue_delete_chat_events(pipe, chat_id);
+    queue_delete_chat_trash(pipe, chat_id);
+    queue_delete_all_chat_payment(pipe, chat_id);
+    queue_delete_chat_payment_index(pipe, chat_id);
+    queue_delete_chat_currencies(pipe, chat_id);
+    queue_delete_chat_settings(pipe, chat_id);
+    queue_delete_chat_categories(pipe, chat_id);
+    queue_delete_chat(pipe, chat_id);
+
+    Ok(())
+}
+
+/* Moves all records of a chat to a new chat ID atomically, replacing any under the new chat ID.
+ * Records under the new chat ID are deleted as in a reset, and those of the old chat are renamed.
+ * Everything read is watched, and all writes are queued into a single MULTI/EXEC,
+ * which is retried if anything read is changed by another client before EXEC.
+ * Called when a group is upgraded to a supergroup, which has a new chat ID.
+ * Does nothing if there are no records under the old chat ID, such as when already migrated.
+ */
+pub async fn migrate_chat(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    new_chat_id: &str,
+) -> Result<(), CrudError> {
+    if chat_id == new_chat_id {
+        return Ok(());
+    }
+    let mut keys = chat_list_keys(chat_id);
+    keys.extend(chat_list_keys(new_chat_id));
+    keys.extend([
+        format!("{CHAT_PAYMENT_INDEX_KEY}:{chat_id}"),
+        format!("{CHAT_TRASH_KEY}:{chat_id}"),
+        format!("{CHAT_SETTING_KEY}:{chat_id}"),
+        format!("{CHAT_CATEGORY_KEY}:{chat_id}"),
+    ]);
+
+    loop {
+        watch_keys(con, &keys).await?;
+        let pipe = queue_migrate_chat(con, chat_id, new_chat_id).await;
+        if exec_watched(con, pipe).await? {
+            return Ok(());
+        }
+    }
+}
+
+// Reads all records of a chat, and queues moving them to a new chat ID into an atomic pipeline
+// Keys are only renamed if they exist, so every key checked is watched before it is checked
+// Nothing is queued if the chat has no records, and the empty transaction only ends the watch
+async fn queue_migrate_chat(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    new_chat_id: &str,
+) -> Result<Pipeline, CrudError> {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    let is_exists = get_chat_exists(con, chat_id).await?
+        || get_chat_payment_exists(con, chat_id).await?
+        || get_chat_settings_exists(con, chat_id).await?;
+    if !is_exists {
+        return Ok(pipe);
+    }
+    queue_reset_chat(con, &mut pipe, new_chat_id).await?;
+
+    // Balances and spendings of every user in every currency
+    let users = get_chat_users(con, chat_id).await?;
+    let currencies = get_chat_currencies(con, chat_id).await?;
+    let keys: Vec<String> = users
+        .iter()
+        .flat_map(|user| {
+            let mut keys = vec![format!("{USER_KEY}:{user}")];
+            for currency in &currencies {
+                keys.push(format!("{BALANCE_KEY}:{chat_id}:{user}:{currency}"));
+                keys.push(format!("{EXPENSE_KEY}:{chat_id}:{user}:{currency}"));
+            }
+            keys
+        })
+        .collect();
+    watch_keys(con, &keys).await?;
+    for user in &users {
+        for currency in &currencies {
+            if get_balance_exists(con, chat_id, user, currency).await? {
+                queue_move_balance(&mut pipe, chat_id, new_chat_id, user, currency);
+            }
+            if get_spending_exists(con, chat_id, user, currency).await? {
+                queue_move_spending(&mut pipe, chat_id, new_chat_id, user, currency);
+            }
+        }
+        // The new chat is removed first, so that it is not listed twice
+        queue_delete_user_chat(&mut pipe, user, chat_id);
+        queue_delete_user_chat(&mut pipe, user, new_chat_id);
+        queue_update_user_chats(&mut pipe, user, new_chat_id);
+    }
+
+    // Dialogues already under the new chat ID are replaced as well
+    // Deleting a key that is already gone does nothing, so these are not watched
+    for user_id in get_chat_dialogue_users(con, new_chat_id).await? {
+        queue_delete_chat_dialogue(&mut pipe, new_chat_id, &user_id);
+    }
+
+    // Dialogues are found by a scan, so they are checked again once watched
+    let user_ids = get_chat_dialogue_users(con, chat_id).await?;
+    let keys: Vec<String> = user_ids
+        .iter()
+        .map(|user_id| format!("{DIALOGUE_KEY}:{chat_id}:{user_id}"))
+        .collect();
+    watch_keys(con, &keys).await?;
+    for user_id in &user_ids {
+        if get_chat_dialogue(con, chat_id, user_id).await?.is_some() {
+            queue_move_chat_dialogue(&mut pipe, chat_id, new_chat_id, user_id);
+        }
+    }
+
+    let recurring_ids = get_chat_recurring(con, chat_id).await?;
+    let keys: Vec<String> = recurring_ids
+        .iter()
+        .map(|recurring_id| format!("{RECURRING_KEY}:{recurring_id}"))
+        .collect();
+    watch_keys(con, &keys).await?;
+    for recurring_id in &recurring_ids {
+        if let Some(mut recurring) = get_recurring(con, recurring_id).await? {
+            queue_delete_recurring(&mut pipe, chat_id, recurring_id);
+            recurring.chat_id = new_chat_id.to_string();
+            queue_set_recurring(&mut pipe, &recurring)?;
+        }
+    }
+
+    if get_chat_events_exists(con, chat_id).await? {
+        queue_move_chat_events(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_trash_exists(con, chat_id).await? {
+        queue_move_chat_trash(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_payment_exists(con, chat_id).await? {
+        queue_move_all_chat_payment(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_payment_index_exists(con, chat_id).await? {
+        queue_move_chat_payment_index(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_currencies_exists(con, chat_id).await? {
+        queue_move_chat_currencies(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_settings_exists(con, chat_id).await? {
+        queue_move_chat_settings(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_categories_exists(con, chat_id).await? {
+        queue_move_chat_categories(&mut pipe, chat_id, new_chat_id);
+    }
+    if get_chat_exists(con, chat_id).await? {
+        queue_move_chat(&mut pipe, chat_id, new_chat_id);
+    }
+
+    Ok(pipe)
+}
+
+/* Retrieves all chats of a user.
+ * Called before forgetting a user, to check their balances in each chat.
+ */
+pub async fn retrieve_user_chats(
+    con: &mut ConnectionManager,
+    username: &str,
+) -> Result<Vec<String>, CrudError> {
+    Ok(get_user_chats(con, &username.to_lowercase()).await?)
+}
+
+/* Retrieves all users of a chat, by their lowercase username.
+ * Called when auditing a chat, as balances and spendings are only read for these users.
+ */
+pub async fn retrieve_chat_users(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> Result<Vec<String>, CrudError> {
+    Ok(get_chat_users(con, chat_id).await?)
+}
+
+/* Forgets a user, removing them from all their chats with their preferred username.
+ * Balances and spendings are kept under the lowercase username, as payments still refer to it.
+ * Called when a user asks to be forgotten, once all their balances are settled.
+ */
+pub async fn forget_user(con: &mut ConnectionManager, username: &str) -> Result<(), CrudError> {
+    let user_key = username.to_lowercase();
+    for chat_id in get_user_chats(con, &user_key).await? {
+        delete_chat_user(con, &chat_id, &user_key).await?;
+    }
+    delete_user(con, &user_key).await?;
+    delete_preferred_username(con, &user_key).await?;
+    delete_member_name(con, &user_key).await?;
+
+    Ok(())
+}
+
+/* Gets the username last seen for a user ID, None if the user ID is not tracked yet.
+ * Called on every message, to detect changes in username.
+ */
+pub async fn get_tracked_username(
+    con: &mut ConnectionManager,
+    user_id: &str,
+) -> Result<Option<String>, CrudError> {
+    if !get_user_is_init(con, user_id).await? {
+        return Ok(None);
+    }
+    Ok(Some(get_username(con, user_id).await?))
+}
+
+/* Tracks the username of a user ID, replacing any previous one.
+ */
+pub async fn track_username(
+    con: &mut ConnectionManager,
+    user_id: &str,
+    username: &str,
+) -> Result<(), CrudError> {
+    if get_user_is_init(con, user_id).await? {
+        update_username(con, user_id, username).await?;
+    } else {
+        initialize_user(con, user_id, username).await?;
+    }
+    Ok(())
+}
+
+/* Sets the name of a member without a username, replacing any previous one.
+ * Called on every message from such a user, so that their latest name is displayed.
+ */
+pub async fn update_member_name(
+    con: &mut ConnectionManager,
+    member: &str,
+    name: &str,
+) -> Result<(), CrudError> {
+    Ok(set_member_name(con, &member.to_lowercase(), name).await?)
+}
+
+/* Gets the names of the given members without a username, by member.
+ * Called before displaying members, as only their user ID is kept in payments and balances.
+ */
+pub async fn retrieve_member_names(
+    con: &mut ConnectionManager,
+    members: &[String],
+) -> Result<HashMap<String, String>, CrudError> {
+    let members: Vec<String> = members.iter().map(|member| member.to_lowercase()).collect();
+    Ok(get_member_names(con, &members).await?)
+}
+
+/* Renames a user atomically, moving everything under their old username to the new one.
+ * Payments in every chat of the user are rewritten, including trashed and recurring ones.
+ * Balances and spendings are added onto any that the new username already has.
+ * Payment history is left as it was, as it records the payments at that time.
+ * Everything read is watched, and all writes are queued into a single MULTI/EXEC,
+ * which is retried if anything read is changed by another client before EXEC.
+ * Called when a user is seen with a different username than the one tracked for their ID.
+ */
+pub async fn rename_user(
+    con: &mut ConnectionManager,
+    old_username: &str,
+    new_username: &str,
+) -> Result<(), CrudError> {
+    let old_key = old_username.to_lowercase();
+    let new_key = new_username.to_lowercase();
+    let keys = vec![
+        format!("{USER_KEY}:{old_key}"),
+        format!("{USER_KEY}:{new_key}"),
+    ];
+
+    loop {
+        watch_keys(con, &keys).await?;
+        let pipe = queue_rename_user(con, old_username, new_username).await;
+        if exec_watched(con, pipe).await? {
+            return Ok(());
+        }
+    }
+}
+
+// Reads everything under the old username, and queues moving it into an atomic pipeline
+// Keys are watched as they are found, starting from the chats of the user
+async fn queue_rename_user(
+    con: &mut ConnectionManager,
+    old_username: &str,
+    new_username: &str,
+) -> Result<Pipeline, CrudError> {
+    let old_key = old_username.to_lowercase();
+    let new_key = new_username.to_lowercase();
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+
+    let chats = get_user_chats(con, &old_key).await?;
+    let new_chats = get_user_chats(con, &new_key).await?;
+    let keys: Vec<String> = chats
+        .iter()
+        .flat_map(|chat_id| chat_list_keys(chat_id))
+        .collect();
+    watch_keys(con, &keys).await?;
+
+    for chat_id in &chats {
+        // Payments, including those in the trash
+        let payment_ids = get_chat_payments(con, chat_id).await?;
+        let keys: Vec<String> = payment_ids
+            .iter()
+            .flat_map(|payment_id| {
+                [
+                    format!("{PAYMENT_KEY}:{payment_id}"),
+                    format!("{PAYMENT_DEBT_KEY}:{payment_id}"),
+                ]
+            })
+            .collect();
+        watch_keys(con, &keys).await?;
+        for payment_id in &payment_ids {
+            let payment = get_payment(con, payment_id).await?;
+            if let Some(renamed) = payment.rename_user(old_username, new_username) {
+                queue_update_payment(
+                    &mut pipe,
+                    payment_id,
+                    None,
+                    None,
+                    Some(&renamed.creditor),
+                    None,
+                    None,
+                    Some(&renamed.debts),
+                    Some(&renamed.items),
+                    None,
+                    Some(&renamed.payers),
+                )?;
+            }
+        }
 
-    // Updates payment
-    update_payment(
-        &mut con,
-        payment_id,
-        description,
-        creditor,
-        currency,
-        total,
-        debts,
-    )?;
+        let recurring_ids = get_chat_recurring(con, chat_id).await?;
+        let keys: Vec<String> = recurring_ids
+            .iter()
+            .map(|recurring_id| format!("{RECURRING_KEY}:{recurring_id}"))
+            .collect();
+        watch_keys(con, &keys).await?;
+        for recurring_id in &recurring_ids {
+            if let Some(mut recurring) = get_recurring(con, recurring_id).await? {
+                let payment = &recurring.recurring.payment;
+                if let Some(renamed) = payment.rename_user(old_username, new_username) {
+                    recurring.recurring.payment = renamed;
+                    queue_set_recurring(&mut pipe, &recurring)?;
+                }
+            }
+        }
 
-    Ok(())
-}
+        // Only casing has changed, so everything is already under the right key
+        if old_key == new_key {
+            continue;
+        }
 
-/* Deletes a payment entry.
- * Removes the main payment entry, and also from the list in chat.
- * Called when a user wants to remove a payment.
- */
-pub fn delete_payment_entry(chat_id: &str, payment_id: &str) -> Result<(), CrudError> {
-    let mut con = connect()?;
+        let currencies = get_chat_currencies(con, chat_id).await?;
+        let keys: Vec<String> = currencies
+            .iter()
+            .flat_map(|currency| {
+                [
+                    format!("{BALANCE_KEY}:{chat_id}:{old_key}:{currency}"),
+                    format!("{EXPENSE_KEY}:{chat_id}:{old_key}:{currency}"),
+                ]
+            })
+            .collect();
+        watch_keys(con, &keys).await?;
+        for currency in &currencies {
+            if get_balance_exists(con, chat_id, &old_key, currency).await? {
+                let balance = get_balance(con, chat_id, &old_key, currency).await?;
+                queue_incr_balance(&mut pipe, chat_id, &new_key, currency, balance);
+                queue_delete_balance(&mut pipe, chat_id, &old_key, currency);
+            }
+            if get_spending_exists(con, chat_id, &old_key, currency).await? {
+                let spending = get_spending(con, chat_id, &old_key, currency).await? as i64;
+                queue_incr_spending(&mut pipe, chat_id, &new_key, currency, spending);
+                queue_delete_spending(&mut pipe, chat_id, &old_key, currency);
+            }
+        }
 
-    if let Err(_) = get_payment(&mut con, payment_id) {
-        return Err(CrudError::NoSuchPaymentError());
+        queue_delete_chat_user(&mut pipe, chat_id, &old_key);
+        if !get_chat_users(con, chat_id).await?.contains(&new_key) {
+            queue_add_chat_user(&mut pipe, chat_id, &new_key);
+        }
+        if !new_chats.contains(chat_id) {
+            queue_update_user_chats(&mut pipe, &new_key, chat_id);
+        }
     }
 
-    delete_payment(&mut con, payment_id)?;
-    delete_chat_payment(&mut con, chat_id, payment_id)?;
+    if old_key != new_key {
+        queue_delete_user(&mut pipe, &old_key);
+        queue_delete_preferred_username(&mut pipe, &old_key);
+        queue_delete_member_name(&mut pipe, &old_key);
+    }
+    queue_set_preferred_username(&mut pipe, new_username, &new_key);
 
-    Ok(())
+    Ok(pipe)
 }
 
-/* Updates the spendings of a chat with new changes.
- * If the spending already exists, simply adds the value to the current spending.
- * Else, it creates a new key and sets the value.
- * Does not add currency. As balances and spendings are always updated together,
- * currency will be added by balances instead.
+/* Migrates all payments and their history from formatted datetimes to UTC timestamps.
+ * Datetimes that cannot be parsed are given MIGRATION_DATETIME_FALLBACK, so every payment
+ * stays readable. Events that cannot be read at all are skipped, and left as they were.
+ * Called when connecting. As it scans every payment and event, it is marked as done once
+ * it has gone through all of them without skipping any, and does nothing on any later connect.
  */
-pub fn update_chat_spendings(chat_id: &str, spendings: Vec<UserBalance>) -> Result<(), CrudError> {
-    let mut con = connect()?;
+pub async fn migrate_payment_datetimes(
+    con: &mut ConnectionManager,
+) -> Result<DatetimeMigration, CrudError> {
+    let mut migration = DatetimeMigration::default();
+    if get_migration_done(con, MIGRATION_PAYMENT_TIMESTAMPS).await? {
+        return Ok(migration);
+    }
 
-    for spending in spendings {
-        let mut amount = spending.balance;
-        let username = &spending.username.to_lowercase();
-        let is_exists = get_spending_exists(&mut con, chat_id, username, &spending.currency)?;
-        if is_exists {
-            let current_spending = get_spending(&mut con, chat_id, username, &spending.currency)?;
-            amount += current_spending as i64;
-        }
+    for payment_id in get_legacy_payments(con).await? {
+        let datetime = get_legacy_datetime(con, &payment_id).await?;
+        let timestamp = parse_legacy_datetime(&datetime).unwrap_or_else(|| {
+            migration
+                .fallbacks
+                .push(format!("{datetime} of payment {payment_id}"));
+            MIGRATION_DATETIME_FALLBACK
+        });
+        set_payment_timestamp(con, &payment_id, timestamp).await?;
+        migration.migrated += 1;
+    }
 
-        if amount < 0 {
-            return Err(CrudError::NegativeSpendingError());
-        } else {
-            set_spending(
-                &mut con,
-                chat_id,
-                username,
-                &spending.currency,
-                amount as u64,
-            )?;
+    for event_id in get_all_events(con).await? {
+        let event = get_event(con, &event_id).await?;
+        match migrate_legacy_event(&event) {
+            Ok(Some((event, fallbacks))) => {
+                set_event(con, &event_id, &event).await?;
+                migration.fallbacks.extend(
+                    fallbacks
+                        .into_iter()
+                        .map(|datetime| format!("{datetime} of event {event_id}")),
+                );
+            }
+            Ok(None) => {}
+            Err(err) => migration.skipped.push(format!("event {event_id}: {err}")),
         }
     }
 
-    Ok(())
+    // Skipped events are retried on the next connect
+    if migration.skipped.is_empty() {
+        set_migration_done(con, MIGRATION_PAYMENT_TIMESTAMPS).await?;
+    }
+    Ok(migration)
 }
 
-/* Retrieves all spendings for a chat for all currencies.
- * Returns a vector of UserBalance by user.
+/* Migrates every chat with payments to keep the spending on each category of its payments.
+ * Totals are summed from the payments not in the trash, and replace any totals of the chat.
+ * Called when connecting, after datetimes are migrated. Marked as done once every chat
+ * has its totals, and does nothing on any later connect, as totals are kept from then on.
+ * Returns the number of chats migrated.
  */
-pub fn retrieve_chat_spendings(chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError> {
-    let mut con = connect()?;
-
-    let mut spendings: Vec<Vec<UserBalance>> = Vec::new();
-    let users = get_chat_users(&mut con, chat_id)?;
-    let currencies = get_chat_currencies(&mut con, chat_id)?;
+pub async fn migrate_category_totals(con: &mut ConnectionManager) -> Result<usize, CrudError> {
+    if get_migration_done(con, MIGRATION_CATEGORY_TOTALS).await? {
+        return Ok(0);
+    }
 
-    let mut curr_index = 0;
-    for currency in &currencies {
-        spendings.push(Vec::new());
+    let chat_ids = get_all_payment_chats(con).await?;
+    for chat_id in &chat_ids {
+        let payments = match get_chat_payments_details(con, chat_id).await {
+            Ok(payments) => payments,
+            Err(CrudError::NoPaymentsError()) => Vec::new(),
+            Err(err) => return Err(err),
+        };
 
-        for user in &users {
-            if get_spending_exists(&mut con, chat_id, user, &currency)? {
-                let spending = get_spending(&mut con, chat_id, user, &currency)?;
-                if spending != 0 {
-                    let username = get_preferred_username(&mut con, user)?;
-                    spendings[curr_index].push(UserBalance {
-                        username,
-                        currency: currency.to_string(),
-                        balance: spending as i64,
-                    });
-                }
+        // Payments are listed latest first, so each category keeps the name it was first given
+        let mut totals: Vec<CategoryTotal> = Vec::new();
+        for UserPayment { payment, .. } in payments.into_iter().rev() {
+            let change = payment.category_total();
+            match totals
+                .iter_mut()
+                .find(|total| total.key() == change.key() && total.currency == change.currency)
+            {
+                Some(total) => total.add(&change),
+                None => totals.push(change),
             }
         }
 
-        curr_index += 1;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        queue_delete_chat_categories(&mut pipe, chat_id);
+        for total in &totals {
+            queue_set_chat_category(&mut pipe, chat_id, total)?;
+        }
+        pipe.query_async::<_, ()>(con).await?;
     }
 
-    Ok(spendings)
+    set_migration_done(con, MIGRATION_CATEGORY_TOTALS).await?;
+    Ok(chat_ids.len())
 }
 
-/* Retrieves all spendings for a chat for specific currency.
- * Returns a vector of UserBalance by user.
+/* Saves the serialized dialogue state of a user in a chat.
+ * Called whenever the dialogue of a user moves to a new state.
  */
-pub fn retrieve_chat_spendings_currency(
+pub async fn set_dialogue(
+    con: &mut ConnectionManager,
     chat_id: &str,
-    currency: &str,
-) -> Result<Vec<UserBalance>, CrudError> {
-    let mut con = connect()?;
-
-    let mut spendings: Vec<UserBalance> = Vec::new();
-    let users = get_chat_users(&mut con, chat_id)?;
+    user_id: &str,
+    dialogue: &str,
+) -> Result<(), CrudError> {
+    set_chat_dialogue(con, chat_id, user_id, dialogue).await?;
+    Ok(())
+}
 
-    for user in &users {
-        if get_spending_exists(&mut con, chat_id, user, &currency)? {
-            let spending = get_spending(&mut con, chat_id, user, &currency)?;
-            if spending != 0 {
-                let username = get_preferred_username(&mut con, user)?;
-                spendings.push(UserBalance {
-                    username,
-                    currency: currency.to_string(),
-                    balance: spending as i64,
-                });
-            }
-        }
-    }
+/* Gets the serialized dialogue state of a user in a chat.
+ * Returns None if the user has no dialogue in progress in the chat.
+ */
+pub async fn get_dialogue(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+) -> Result<Option<String>, CrudError> {
+    let dialogue = get_chat_dialogue(con, chat_id, user_id).await?;
+    Ok(dialogue)
+}
 
-    Ok(spendings)
+/* Deletes the dialogue state of a user in a chat.
+ * Called whenever the dialogue of a user exits.
+ */
+pub async fn delete_dialogue(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    user_id: &str,
+) -> Result<(), CrudError> {
+    delete_chat_dialogue(con, chat_id, user_id).await?;
+    Ok(())
 }
 
 /* Checks if a user has exceeded the request limit.
  * Returns a boolean representing this status.
  * Automatically updates the request timestamp if not exceeded.
  */
-pub fn is_request_limit_exceeded(user_id: &str, time_now: i64) -> Result<bool, CrudError> {
-    let mut con = connect()?;
-
-    let timestamp = get_request(&mut con, user_id);
+pub async fn is_request_limit_exceeded(
+    con: &mut ConnectionManager,
+    user_id: &str,
+    time_now: i64,
+) -> Result<bool, CrudError> {
+    let timestamp = get_request(con, user_id).await;
     let mut status = false;
 
     match timestamp {
@@ -598,7 +2017,7 @@ pub fn is_request_limit_exceeded(user_id: &str, time_now: i64) -> Result<bool, C
 
     if !status {
         // Updates request timestamp if not exceeded
-        set_request(&mut con, user_id, time_now)?;
+        set_request(con, user_id, time_now).await?;
     }
 
     Ok(status)
@@ -608,7 +2027,13 @@ pub fn is_request_limit_exceeded(user_id: &str, time_now: i64) -> Result<bool, C
 mod tests {
     use crate::bot::redis::{
         balance::delete_balance,
-        chat::{delete_chat, delete_chat_currencies, delete_chat_settings, get_chat_users},
+        chat::{
+            add_chat_currency, delete_all_chat_payment, delete_chat, delete_chat_currencies,
+            delete_chat_settings, get_chat_users,
+        },
+        connect::connect,
+        migration::delete_migration_done,
+        payment::{add_payment, delete_payment},
         request::delete_request,
         spending::delete_spending,
         user::{delete_preferred_username, delete_user, get_preferred_username, get_user_chats},
@@ -616,41 +2041,73 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn test_update_user_add_user() {
-        let mut con = connect().unwrap();
+    // Builds a ledger update with only the given payment change
+    fn payment_change(change: PaymentChange) -> LedgerUpdate {
+        LedgerUpdate {
+            payments: vec![change],
+            ..Default::default()
+        }
+    }
+
+    // Builds a ledger update with only the given balance changes
+    fn balance_changes(balances: Vec<UserBalance>) -> LedgerUpdate {
+        LedgerUpdate {
+            balances,
+            ..Default::default()
+        }
+    }
+
+    // Builds a ledger update with only the given spending changes
+    fn spending_changes(spendings: Vec<UserBalance>) -> LedgerUpdate {
+        LedgerUpdate {
+            spendings,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_user_add_user() {
+        let mut con = connect().await.unwrap();
 
         let username = "Manager_Test_User";
         let user_key = username.to_lowercase();
         let chat_id = "manager_123456789";
 
         // Checks that user does not exist
-        assert!(!get_user_exists(&mut con, &user_key).unwrap());
+        assert!(!get_user_exists(&mut con, &user_key).await.unwrap());
 
         // Adds user
-        assert!(update_user(username, chat_id, None).is_ok());
+        assert!(update_user(&mut con, username, chat_id, None).await.is_ok());
         assert_eq!(
-            get_preferred_username(&mut con, &user_key).unwrap(),
+            get_preferred_username(&mut con, &user_key).await.unwrap(),
             username
         );
-        assert_eq!(get_user_chats(&mut con, &user_key).unwrap(), vec![chat_id]);
+        assert_eq!(
+            get_user_chats(&mut con, &user_key).await.unwrap(),
+            vec![chat_id]
+        );
 
         // Performs again, nothing should happen
-        assert!(update_user(username, chat_id, None).is_ok());
+        assert!(update_user(&mut con, username, chat_id, None).await.is_ok());
         assert_eq!(
-            get_preferred_username(&mut con, &user_key).unwrap(),
+            get_preferred_username(&mut con, &user_key).await.unwrap(),
             username
         );
-        assert_eq!(get_user_chats(&mut con, &user_key).unwrap(), vec![chat_id]);
+        assert_eq!(
+            get_user_chats(&mut con, &user_key).await.unwrap(),
+            vec![chat_id]
+        );
 
         // Deletes user
-        delete_user(&mut con, &user_key).unwrap();
-        delete_preferred_username(&mut con, &user_key).unwrap();
+        delete_user(&mut con, &user_key).await.unwrap();
+        delete_preferred_username(&mut con, &user_key)
+            .await
+            .unwrap();
     }
 
-    #[test]
-    fn test_update_user_add_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_user_add_chat() {
+        let mut con = connect().await.unwrap();
 
         let username = "Manager_Test_User_0";
         let user_key = username.to_lowercase();
@@ -658,63 +2115,72 @@ mod tests {
         let second_chat_id = "manager_1234567891";
 
         // Adds user and chat
-        assert!(update_user(username, chat_id, None).is_ok());
-        assert_eq!(get_user_chats(&mut con, &user_key).unwrap(), vec![chat_id]);
+        assert!(update_user(&mut con, username, chat_id, None).await.is_ok());
+        assert_eq!(
+            get_user_chats(&mut con, &user_key).await.unwrap(),
+            vec![chat_id]
+        );
 
         // Calls again, adds a second chat
-        assert!(update_user(username, second_chat_id, None).is_ok());
+        assert!(update_user(&mut con, username, second_chat_id, None)
+            .await
+            .is_ok());
         assert_eq!(
-            get_user_chats(&mut con, &user_key).unwrap(),
+            get_user_chats(&mut con, &user_key).await.unwrap(),
             vec![chat_id, second_chat_id]
         );
 
         // Calls again, nothing should happen
-        assert!(update_user(username, second_chat_id, None).is_ok());
+        assert!(update_user(&mut con, username, second_chat_id, None)
+            .await
+            .is_ok());
         assert_eq!(
-            get_user_chats(&mut con, &user_key).unwrap(),
+            get_user_chats(&mut con, &user_key).await.unwrap(),
             vec![chat_id, second_chat_id]
         );
 
         // Deletes user
-        delete_user(&mut con, &user_key).unwrap();
-        delete_preferred_username(&mut con, &user_key).unwrap();
+        delete_user(&mut con, &user_key).await.unwrap();
+        delete_preferred_username(&mut con, &user_key)
+            .await
+            .unwrap();
     }
 
     /*
-    #[test]
-    fn test_update_user_init_user() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_user_init_user() {
+        let mut con = connect().await.unwrap();
 
         let username = "manager_test_user_1";
         let chat_id = "manager_1234567892";
         let user_id = "manager_987654321";
 
         // Adds user and chat, check that init works properly
-        assert!(update_user(username, chat_id, Some(user_id)).is_ok());
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
+        assert!(update_user(&mut con, username, chat_id, Some(user_id)).await.is_ok());
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
 
         // Deletes user temporarily
-        delete_user(&mut con, username).unwrap();
-        delete_user_id(&mut con, user_id).unwrap();
-        delete_preferred_username(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
+        delete_preferred_username(&mut con, username).await.unwrap();
 
         // Calls again, adds user again but without ID
-        assert!(update_user(username, chat_id, None).is_ok());
-        assert!(!get_user_is_init(&mut con, user_id).unwrap());
+        assert!(update_user(&mut con, username, chat_id, None).await.is_ok());
+        assert!(!get_user_is_init(&mut con, user_id).await.unwrap());
 
         // Calls again, should init user
-        assert!(update_user(username, chat_id, Some(user_id)).is_ok());
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
+        assert!(update_user(&mut con, username, chat_id, Some(user_id)).await.is_ok());
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
 
         // Deletes user
-        delete_user(&mut con, username).unwrap();
-        delete_user_id(&mut con, user_id).unwrap();
-        delete_preferred_username(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
+        delete_preferred_username(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_update_user_update_username() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_user_update_username() {
+        let mut con = connect().await.unwrap();
 
         let username = "manager_test_user_2";
         let chat_id = "manager_1234567893";
@@ -722,26 +2188,26 @@ mod tests {
         let second_username = "manager_test_user_3";
 
         // Adds user and chat, check that user is init
-        assert!(update_user(username, chat_id, Some(user_id)).is_ok());
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
+        assert!(update_user(&mut con, username, chat_id, Some(user_id)).await.is_ok());
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
 
         // Calls again, updates username
-        assert!(update_user(second_username, chat_id, Some(user_id)).is_ok());
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
-        assert!(get_username(&mut con, user_id).unwrap() == second_username);
+        assert!(update_user(&mut con, second_username, chat_id, Some(user_id)).await.is_ok());
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
+        assert!(get_username(&mut con, user_id).await.unwrap() == second_username);
 
         // Deletes user
-        delete_user(&mut con, username).unwrap();
-        delete_user(&mut con, second_username).unwrap();
-        delete_user_id(&mut con, user_id).unwrap();
-        delete_preferred_username(&mut con, username).unwrap();
-        delete_preferred_username(&mut con, second_username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
+        delete_user(&mut con, second_username).await.unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
+        delete_preferred_username(&mut con, username).await.unwrap();
+        delete_preferred_username(&mut con, second_username).await.unwrap();
     }
     */
 
-    #[test]
-    fn test_update_chat_add_chat_users() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_chat_add_chat_users() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "manager_1234567894";
         let mut usernames = vec![
@@ -756,32 +2222,39 @@ mod tests {
         ];
 
         // Check that chat does not exist
-        assert!(!get_chat_exists(&mut con, chat_id).unwrap());
+        assert!(!get_chat_exists(&mut con, chat_id).await.unwrap());
 
         // Add chat with first group of usernames
-        assert!(update_chat(chat_id, usernames.clone()).is_ok());
-        assert!(get_chat_exists(&mut con, chat_id).unwrap());
+        assert!(update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .is_ok());
+        assert!(get_chat_exists(&mut con, chat_id).await.unwrap());
         assert_eq!(
-            get_chat_users(&mut con, chat_id).unwrap(),
+            get_chat_users(&mut con, chat_id).await.unwrap(),
             vec![
                 "manager_test_user_4".to_string(),
                 "manager_test_user_5".to_string(),
                 "manager_test_user_6".to_string(),
             ]
         );
-        assert_eq!(get_time_zone(chat_id).unwrap(), "UTC".to_string());
         assert_eq!(
-            get_default_currency(chat_id).unwrap(),
+            get_time_zone(&mut con, chat_id).await.unwrap(),
+            "UTC".to_string()
+        );
+        assert_eq!(
+            get_default_currency(&mut con, chat_id).await.unwrap(),
             CURRENCY_CODE_DEFAULT.to_string()
         );
-        assert_eq!(get_currency_conversion(chat_id).unwrap(), false);
+        assert!(!get_currency_conversion(&mut con, chat_id).await.unwrap());
 
         // Call again, add both groups of usernames
         usernames.extend(more_usernames.clone());
-        assert!(update_chat(chat_id, usernames.clone()).is_ok());
-        assert!(get_chat_exists(&mut con, chat_id).unwrap());
+        assert!(update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .is_ok());
+        assert!(get_chat_exists(&mut con, chat_id).await.unwrap());
         assert_eq!(
-            get_chat_users(&mut con, chat_id).unwrap(),
+            get_chat_users(&mut con, chat_id).await.unwrap(),
             vec![
                 "manager_test_user_4".to_string(),
                 "manager_test_user_5".to_string(),
@@ -793,10 +2266,10 @@ mod tests {
         );
 
         // Call again, nothing should happen
-        assert!(update_chat(chat_id, usernames).is_ok());
-        assert!(get_chat_exists(&mut con, chat_id).unwrap());
+        assert!(update_chat(&mut con, chat_id, usernames).await.is_ok());
+        assert!(get_chat_exists(&mut con, chat_id).await.unwrap());
         assert_eq!(
-            get_chat_users(&mut con, chat_id).unwrap(),
+            get_chat_users(&mut con, chat_id).await.unwrap(),
             vec![
                 "manager_test_user_4".to_string(),
                 "manager_test_user_5".to_string(),
@@ -808,16 +2281,18 @@ mod tests {
         );
 
         // Deletes chat
-        delete_chat(&mut con, chat_id).unwrap();
-        delete_chat_settings(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_add_get_update_delete_payment_details() {
+    #[tokio::test]
+    async fn test_add_get_update_delete_payment_details() {
+        let mut con = connect().await.unwrap();
+
         let chat_id = "manager_1234567895";
         let payment = Payment {
             description: "manager_test_payment".to_string(),
-            datetime: "2021-01-01T00:00:00".to_string(),
+            timestamp: 1609459200,
             creditor: "manager_test_user_10".to_string(),
             currency: "USD".to_string(),
             total: 10000,
@@ -825,14 +2300,23 @@ mod tests {
                 ("manager_test_user_11".to_string(), 5000),
                 ("manager_test_user_12".to_string(), 5000),
             ],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
         };
 
         // Adds payment
-        assert!(add_payment_entry(chat_id, &payment).is_ok());
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Add(payment.clone()))
+        )
+        .await
+        .is_ok());
 
         let second_payment = Payment {
             description: "manager_test_payment_2".to_string(),
-            datetime: "2021-01-01T00:00:01".to_string(),
+            timestamp: 1609459201,
             creditor: "manager_test_user_13".to_string(),
             currency: "USD".to_string(),
             total: 20000,
@@ -840,17 +2324,26 @@ mod tests {
                 ("manager_test_user_14".to_string(), 10000),
                 ("manager_test_user_15".to_string(), 10000),
             ],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
         };
 
         // Adds second payment
-        assert!(add_payment_entry(chat_id, &second_payment).is_ok());
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Add(second_payment.clone()))
+        )
+        .await
+        .is_ok());
 
         // Gets both payments
-        let payments = get_chat_payments_details(chat_id).unwrap();
+        let payments = get_chat_payments_details(&mut con, chat_id).await.unwrap();
         let second_id = payments[0].payment_id.clone();
 
         // Gets second payment by ID
-        let second_payment_values = get_payment_entry(&second_id);
+        let second_payment_values = get_payment_entry(&mut con, &second_id).await;
         assert_eq!(second_payment_values.unwrap(), second_payment);
 
         // Updates second payment
@@ -863,18 +2356,25 @@ mod tests {
             ("manager_test_user_18".to_string(), 15000),
         ];
 
-        assert!(update_payment_entry(
-            &second_id,
-            Some(updated_description),
-            Some(updated_creditor),
-            Some(updated_currency),
-            Some(&updated_total),
-            Some(updated_debts.clone()),
-        )
-        .is_ok());
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: second_id.clone(),
+                description: Some(updated_description.to_string()),
+                timestamp: None,
+                creditor: Some(updated_creditor.to_string()),
+                currency: Some(updated_currency.to_string()),
+                total: Some(updated_total),
+                debts: Some(updated_debts.clone()),
+                items: None,
+                category: None,
+                payers: None,
+            }],
+            ..Default::default()
+        };
+        assert!(apply_ledger_update(&mut con, chat_id, update).await.is_ok());
 
         // Gets both payments again
-        let payments = get_chat_payments_details(chat_id).unwrap();
+        let payments = get_chat_payments_details(&mut con, chat_id).await.unwrap();
         assert_eq!(
             payments,
             vec![
@@ -883,34 +2383,126 @@ mod tests {
                     payment_id: second_id.clone(),
                     payment: Payment {
                         description: updated_description.to_string(),
-                        datetime: "2021-01-01T00:00:01".to_string(),
+                        timestamp: 1609459201,
                         creditor: updated_creditor.to_string(),
                         currency: updated_currency.to_string(),
                         total: updated_total,
                         debts: updated_debts.clone(),
+                        items: Vec::new(),
+                        category: None,
+                        payers: Vec::new(),
                     },
                 },
                 UserPayment {
                     chat_id: chat_id.to_string(),
                     payment_id: payments[1].payment_id.clone(),
-                    payment: payment,
+                    payment,
                 },
             ]
         );
 
         // Deletes both payments
-        assert!(delete_payment_entry(chat_id, &payments[0].payment_id).is_ok());
-        assert!(delete_payment_entry(chat_id, &payments[1].payment_id).is_ok());
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Delete(payments[0].payment_id.to_string()))
+        )
+        .await
+        .is_ok());
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Delete(payments[1].payment_id.to_string()))
+        )
+        .await
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_get_chat_payments_page() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "manager_1234567896";
+        for timestamp in [1609459200, 1609459201, 1609459201] {
+            let payment = Payment {
+                description: "manager_test_payment".to_string(),
+                timestamp,
+                creditor: "manager_test_user_10".to_string(),
+                currency: "USD".to_string(),
+                total: 10000,
+                debts: vec![("manager_test_user_11".to_string(), 10000)],
+                items: Vec::new(),
+                category: None,
+                payers: Vec::new(),
+            };
+            apply_ledger_update(
+                &mut con,
+                chat_id,
+                payment_change(PaymentChange::Add(payment.clone())),
+            )
+            .await
+            .unwrap();
+        }
+
+        // Latest payment first, payments at the same time by payment ID descending
+        let mut expected = get_chat_payments_details(&mut con, chat_id).await.unwrap();
+        expected.sort_by(|first, second| {
+            (second.payment.timestamp, &second.payment_id)
+                .cmp(&(first.payment.timestamp, &first.payment_id))
+        });
+
+        let mut query = PaymentQuery {
+            page_size: 2,
+            ..Default::default()
+        };
+        let page = get_chat_payments_page(&mut con, chat_id, &query)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.payments, expected[0..2].to_vec());
+
+        query.cursor = page.next;
+        let page = get_chat_payments_page(&mut con, chat_id, &query)
+            .await
+            .unwrap();
+        assert_eq!(page.payments, expected[2..3].to_vec());
+        assert_eq!(page.next, None);
+
+        // Missing index is rebuilt from the payments in the chat
+        delete_chat_payment_index(&mut con, chat_id).await.unwrap();
+        let query = PaymentQuery {
+            page_size: 5,
+            from: Some(1609459201),
+            ..Default::default()
+        };
+        let page = get_chat_payments_page(&mut con, chat_id, &query)
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.payments, expected[0..2].to_vec());
+
+        for payment in expected {
+            apply_ledger_update(
+                &mut con,
+                chat_id,
+                payment_change(PaymentChange::Delete(payment.payment_id.to_string())),
+            )
+            .await
+            .unwrap();
+        }
+        delete_chat_payment_index(&mut con, chat_id).await.unwrap();
     }
 
     // Test for empty payments
-    #[test]
-    fn test_no_payments_found() {
+    #[tokio::test]
+    async fn test_no_payments_found() {
+        let mut con = connect().await.unwrap();
+
         let chat_id = "manager_1234567898";
 
         let payment = Payment {
             description: "manager_test_user_20".to_string(),
-            datetime: "2021-01-01T00:00:00".to_string(),
+            timestamp: 1609459200,
             creditor: "manager_test_user_21".to_string(),
             currency: "USD".to_string(),
             total: 10000,
@@ -918,54 +2510,62 @@ mod tests {
                 ("manager_test_user_22".to_string(), 5000),
                 ("manager_test_user_23".to_string(), 5000),
             ],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
         };
 
         // Checks that payments don't exist
         assert_eq!(
-            get_chat_payments_details(chat_id).unwrap_err(),
+            get_chat_payments_details(&mut con, chat_id)
+                .await
+                .unwrap_err(),
             CrudError::NoPaymentsError()
         );
 
         // Adds chat payment
-        assert!(add_payment_entry(chat_id, &payment).is_ok());
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Add(payment.clone()))
+        )
+        .await
+        .is_ok());
 
-        // Updates fake payment, should fail
+        // Deletes fake payment, should fail
         assert_eq!(
-            update_payment_entry(
-                "nonexistent_payment",
-                Some("manager_test_payment_3"),
-                Some("manager_test_user_16"),
-                Some("JPY"),
-                Some(&30000),
-                Some(vec![
-                    ("manager_test_user_17".to_string(), 15000),
-                    ("manager_test_user_18".to_string(), 15000),
-                ]),
+            apply_ledger_update(
+                &mut con,
+                chat_id,
+                payment_change(PaymentChange::Delete("nonexistent_payment".to_string()))
             )
+            .await
             .unwrap_err(),
             CrudError::NoSuchPaymentError()
         );
 
-        // Deletes fake payment, should fail
-        assert_eq!(
-            delete_payment_entry(chat_id, "nonexistent_payment").unwrap_err(),
-            CrudError::NoSuchPaymentError()
-        );
-
         // Deletes actual payment
-        let payments = get_chat_payments_details(chat_id).unwrap();
-        assert!(delete_payment_entry(chat_id, &payments[0].payment_id).is_ok());
+        let payments = get_chat_payments_details(&mut con, chat_id).await.unwrap();
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Delete(payments[0].payment_id.to_string()))
+        )
+        .await
+        .is_ok());
 
         // Checks that payments don't exist
         assert_eq!(
-            get_chat_payments_details(chat_id).unwrap_err(),
+            get_chat_payments_details(&mut con, chat_id)
+                .await
+                .unwrap_err(),
             CrudError::NoPaymentsError()
         );
     }
 
-    #[test]
-    fn test_update_retrieve_balances() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_retrieve_balances() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "manager_1234567898";
 
@@ -975,10 +2575,14 @@ mod tests {
             "manager_test_user_21".to_string(),
             "manager_test_user_22".to_string(),
         ];
-        update_chat(chat_id, usernames.clone()).unwrap();
+        update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .unwrap();
 
         for username in &usernames {
-            update_user(&username, chat_id, None).unwrap();
+            update_user(&mut con, username, chat_id, None)
+                .await
+                .unwrap();
         }
 
         let changes = vec![
@@ -1000,8 +2604,12 @@ mod tests {
         ];
 
         // Adds initial balances
-        assert!(update_chat_balances(chat_id, changes.clone()).is_ok());
-        let initial_balances = get_chat_balances(chat_id).unwrap();
+        assert!(
+            apply_ledger_update(&mut con, chat_id, balance_changes(changes.clone()))
+                .await
+                .is_ok()
+        );
+        let initial_balances = get_chat_balances(&mut con, chat_id).await.unwrap();
 
         // Checks that balances are correct
         assert_eq!(
@@ -1044,8 +2652,12 @@ mod tests {
             },
         ];
 
-        assert!(update_chat_balances(chat_id, new_changes.clone()).is_ok());
-        let new_balances = get_chat_balances(chat_id).unwrap();
+        assert!(
+            apply_ledger_update(&mut con, chat_id, balance_changes(new_changes.clone()))
+                .await
+                .is_ok()
+        );
+        let new_balances = get_chat_balances(&mut con, chat_id).await.unwrap();
 
         // Checks that balances are correct
         assert_eq!(
@@ -1066,24 +2678,26 @@ mod tests {
 
         // Deletes balances
         for balance in &initial_balances[0] {
-            delete_balance(&mut con, chat_id, &balance.username, "USD").unwrap();
+            delete_balance(&mut con, chat_id, &balance.username, "USD")
+                .await
+                .unwrap();
         }
 
         // Deletes usernames
         for username in &usernames {
-            delete_user(&mut con, &username).unwrap();
-            delete_preferred_username(&mut con, username).unwrap();
+            delete_user(&mut con, username).await.unwrap();
+            delete_preferred_username(&mut con, username).await.unwrap();
         }
 
         // Deletes chat
-        delete_chat(&mut con, chat_id).unwrap();
-        delete_chat_currencies(&mut con, chat_id).unwrap();
-        delete_chat_settings(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_currencies(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_multiple_currencies_balances() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_multiple_currencies_balances() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "manager_1234567899";
 
@@ -1093,10 +2707,14 @@ mod tests {
             "manager_test_user_27".to_string(),
             "manager_test_user_28".to_string(),
         ];
-        update_chat(chat_id, usernames.clone()).unwrap();
+        update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .unwrap();
 
         for username in &usernames {
-            update_user(&username, chat_id, None).unwrap();
+            update_user(&mut con, username, chat_id, None)
+                .await
+                .unwrap();
         }
 
         // Add first changes
@@ -1118,7 +2736,9 @@ mod tests {
             },
         ];
 
-        update_chat_balances(chat_id, changes.clone()).unwrap();
+        apply_ledger_update(&mut con, chat_id, balance_changes(changes.clone()))
+            .await
+            .unwrap();
 
         // Add second changes
         let new_changes = vec![
@@ -1139,8 +2759,12 @@ mod tests {
             },
         ];
 
-        assert!(update_chat_balances(chat_id, new_changes.clone()).is_ok());
-        let new_balances = get_chat_balances(chat_id).unwrap();
+        assert!(
+            apply_ledger_update(&mut con, chat_id, balance_changes(new_changes.clone()))
+                .await
+                .is_ok()
+        );
+        let new_balances = get_chat_balances(&mut con, chat_id).await.unwrap();
 
         // Check balances
         let balances = vec![
@@ -1183,7 +2807,9 @@ mod tests {
         assert_eq!(new_balances, balances);
 
         // Checks balances for individual currency
-        let balances_usd = get_chat_balances_currency(chat_id, "USD").unwrap();
+        let balances_usd = get_chat_balances_currency(&mut con, chat_id, "USD")
+            .await
+            .unwrap();
         assert_eq!(
             balances_usd,
             vec![
@@ -1204,7 +2830,9 @@ mod tests {
                 },
             ]
         );
-        let balances_jpy = get_chat_balances_currency(chat_id, "JPY").unwrap();
+        let balances_jpy = get_chat_balances_currency(&mut con, chat_id, "JPY")
+            .await
+            .unwrap();
         assert_eq!(
             balances_jpy,
             vec![
@@ -1229,25 +2857,27 @@ mod tests {
         // Deletes balances
         for current in balances {
             for balance in current {
-                delete_balance(&mut con, chat_id, &balance.username, &balance.currency).unwrap();
+                delete_balance(&mut con, chat_id, &balance.username, &balance.currency)
+                    .await
+                    .unwrap();
             }
         }
 
         // Deletes usernames
         for username in &usernames {
-            delete_user(&mut con, &username).unwrap();
-            delete_preferred_username(&mut con, username).unwrap();
+            delete_user(&mut con, username).await.unwrap();
+            delete_preferred_username(&mut con, username).await.unwrap();
         }
 
         // Deletes chat
-        delete_chat(&mut con, chat_id).unwrap();
-        delete_chat_currencies(&mut con, chat_id).unwrap();
-        delete_chat_settings(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_currencies(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_get_set_chat_settings() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_set_chat_settings() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "manager_12345678991";
         let usernames = vec![
@@ -1257,42 +2887,60 @@ mod tests {
         ];
 
         // Checks "default" chat settings
-        assert_eq!(get_time_zone(chat_id).unwrap(), "UTC".to_string());
         assert_eq!(
-            get_default_currency(chat_id).unwrap(),
+            get_time_zone(&mut con, chat_id).await.unwrap(),
+            "UTC".to_string()
+        );
+        assert_eq!(
+            get_default_currency(&mut con, chat_id).await.unwrap(),
             CURRENCY_CODE_DEFAULT.to_string()
         );
-        assert_eq!(get_currency_conversion(chat_id).unwrap(), false);
+        assert!(!get_currency_conversion(&mut con, chat_id).await.unwrap());
 
         // Adds chat
-        assert!(update_chat(chat_id, usernames.clone()).is_ok());
-        assert_eq!(get_time_zone(chat_id).unwrap(), "UTC".to_string());
+        assert!(update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .is_ok());
+        assert_eq!(
+            get_time_zone(&mut con, chat_id).await.unwrap(),
+            "UTC".to_string()
+        );
         assert_eq!(
-            get_default_currency(chat_id).unwrap(),
+            get_default_currency(&mut con, chat_id).await.unwrap(),
             CURRENCY_CODE_DEFAULT.to_string()
         );
-        assert_eq!(get_currency_conversion(chat_id).unwrap(), false);
+        assert!(!get_currency_conversion(&mut con, chat_id).await.unwrap());
 
         // Sets various chat settings
         let time_zone = "GMT";
         let currency = "USD";
         let conversion = true;
 
-        assert!(set_time_zone(chat_id, time_zone).is_ok());
-        assert_eq!(get_time_zone(chat_id).unwrap(), time_zone);
-        assert!(set_default_currency(chat_id, currency).is_ok());
-        assert_eq!(get_default_currency(chat_id).unwrap(), currency);
-        assert!(set_currency_conversion(chat_id, conversion).is_ok());
-        assert_eq!(get_currency_conversion(chat_id).unwrap(), conversion);
+        assert!(set_time_zone(&mut con, chat_id, time_zone).await.is_ok());
+        assert_eq!(get_time_zone(&mut con, chat_id).await.unwrap(), time_zone);
+        assert!(set_default_currency(&mut con, chat_id, currency)
+            .await
+            .is_ok());
+        assert_eq!(
+            get_default_currency(&mut con, chat_id).await.unwrap(),
+            currency
+        );
+        assert!(set_currency_conversion(&mut con, chat_id, conversion)
+            .await
+            .is_ok());
+        assert_eq!(
+            get_currency_conversion(&mut con, chat_id).await.unwrap(),
+            conversion
+        );
 
         // Deletes chat
-        delete_chat(&mut con, chat_id).unwrap();
-        delete_chat_settings(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
     }
 
-    #[test]
-    fn test_update_retrieve_chat_spendings() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_retrieve_chat_spendings() {
+        let mut con = connect().await.unwrap();
 
         let chat_id = "manager_12345678992";
         let usernames = vec![
@@ -1302,10 +2950,14 @@ mod tests {
         ];
 
         // Adds chat
-        assert!(update_chat(chat_id, usernames.clone()).is_ok());
+        assert!(update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .is_ok());
 
         for username in &usernames {
-            update_user(&username, chat_id, None).unwrap();
+            update_user(&mut con, username, chat_id, None)
+                .await
+                .unwrap();
         }
 
         // Adds spendings
@@ -1327,13 +2979,17 @@ mod tests {
             },
         ];
 
-        assert!(update_chat_spendings(chat_id, spendings.clone()).is_ok());
+        assert!(
+            apply_ledger_update(&mut con, chat_id, spending_changes(spendings.clone()))
+                .await
+                .is_ok()
+        );
 
         // Manually add currency
-        assert!(add_chat_currency(&mut con, chat_id, "USD").is_ok());
+        assert!(add_chat_currency(&mut con, chat_id, "USD").await.is_ok());
 
         // Retrieves spendings
-        let retrieved_spendings = retrieve_chat_spendings_currency(chat_id, "USD");
+        let retrieved_spendings = retrieve_chat_spendings_currency(&mut con, chat_id, "USD").await;
         assert!(retrieved_spendings.is_ok());
         assert_eq!(retrieved_spendings.unwrap(), spendings.clone());
 
@@ -1356,25 +3012,29 @@ mod tests {
             },
         ];
 
-        assert!(update_chat_spendings(chat_id, new_spendings.clone()).is_ok());
+        assert!(
+            apply_ledger_update(&mut con, chat_id, spending_changes(new_spendings.clone()))
+                .await
+                .is_ok()
+        );
 
         // Manually add currency
-        assert!(add_chat_currency(&mut con, chat_id, "JPY").is_ok());
+        assert!(add_chat_currency(&mut con, chat_id, "JPY").await.is_ok());
 
         // Retrieves and checks spendings again
-        let retrieved_spendings = retrieve_chat_spendings_currency(chat_id, "JPY");
+        let retrieved_spendings = retrieve_chat_spendings_currency(&mut con, chat_id, "JPY").await;
         assert!(retrieved_spendings.is_ok());
         assert_eq!(retrieved_spendings.unwrap(), new_spendings.clone());
-        let retrieved_spendings = retrieve_chat_spendings_currency(chat_id, "USD");
+        let retrieved_spendings = retrieve_chat_spendings_currency(&mut con, chat_id, "USD").await;
         assert_eq!(retrieved_spendings.unwrap(), spendings.clone());
-        let retrieved_spendings = retrieve_chat_spendings(chat_id);
+        let retrieved_spendings = retrieve_chat_spendings(&mut con, chat_id).await;
         assert_eq!(
             retrieved_spendings.unwrap(),
             vec![spendings.clone(), new_spendings.clone()]
         );
 
         // Checks validity of currencies
-        let valid_currencies = get_valid_chat_currencies(chat_id);
+        let valid_currencies = get_valid_chat_currencies(&mut con, chat_id).await;
         assert!(valid_currencies.is_ok());
         assert_eq!(
             valid_currencies.unwrap(),
@@ -1384,43 +3044,386 @@ mod tests {
         // Deletes spendings
         for spending in spendings {
             assert!(
-                delete_spending(&mut con, chat_id, &spending.username, &spending.currency).is_ok()
+                delete_spending(&mut con, chat_id, &spending.username, &spending.currency)
+                    .await
+                    .is_ok()
             );
         }
 
         for spending in new_spendings {
             assert!(
-                delete_spending(&mut con, chat_id, &spending.username, &spending.currency).is_ok()
+                delete_spending(&mut con, chat_id, &spending.username, &spending.currency)
+                    .await
+                    .is_ok()
             );
         }
 
         // Deletes usernames
         for username in &usernames {
-            delete_user(&mut con, &username).unwrap();
-            delete_preferred_username(&mut con, username).unwrap();
+            delete_user(&mut con, username).await.unwrap();
+            delete_preferred_username(&mut con, username).await.unwrap();
+        }
+
+        // Deletes chat
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_currencies(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_apply_ledger_update() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "manager_12345678993";
+        let usernames = vec![
+            "manager_test_user_36".to_string(),
+            "manager_test_user_37".to_string(),
+        ];
+
+        // Adds chat
+        assert!(update_chat(&mut con, chat_id, usernames.clone())
+            .await
+            .is_ok());
+
+        for username in &usernames {
+            update_user(&mut con, username, chat_id, None)
+                .await
+                .unwrap();
+        }
+
+        let payment = Payment {
+            description: "manager_test_payment".to_string(),
+            timestamp: 1609459200,
+            creditor: "manager_test_user_36".to_string(),
+            currency: "USD".to_string(),
+            total: 10000,
+            debts: vec![("manager_test_user_37".to_string(), 10000)],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        };
+        let balances = vec![
+            UserBalance {
+                username: "manager_test_user_36".to_string(),
+                balance: 10000,
+                currency: "USD".to_string(),
+            },
+            UserBalance {
+                username: "manager_test_user_37".to_string(),
+                balance: -10000,
+                currency: "USD".to_string(),
+            },
+        ];
+        let spendings = vec![UserBalance {
+            username: "manager_test_user_37".to_string(),
+            balance: 10000,
+            currency: "USD".to_string(),
+        }];
+
+        // Applies payment, spendings, balances and settings together
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment.clone())],
+            spendings: spendings.clone(),
+            categories: vec![payment.category_total()],
+            balances: balances.clone(),
+            default_currency: Some("USD".to_string()),
+            currency_conversion: None,
+            recurring: None,
+            actor: None,
+        };
+        assert!(apply_ledger_update(&mut con, chat_id, update).await.is_ok());
+
+        let payments = get_chat_payments_details(&mut con, chat_id).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment, payment);
+        assert_eq!(
+            get_chat_balances_currency(&mut con, chat_id, "USD")
+                .await
+                .unwrap(),
+            balances
+        );
+        assert_eq!(
+            retrieve_chat_spendings_currency(&mut con, chat_id, "USD")
+                .await
+                .unwrap(),
+            spendings
+        );
+        assert_eq!(
+            retrieve_chat_categories(&mut con, chat_id).await.unwrap(),
+            vec![payment.category_total()]
+        );
+        assert_eq!(
+            get_default_currency(&mut con, chat_id).await.unwrap(),
+            "USD"
+        );
+
+        // Fails on negative spending, nothing should be written
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Delete(payments[0].payment_id.clone())],
+            spendings: vec![UserBalance {
+                username: "manager_test_user_37".to_string(),
+                balance: -20000,
+                currency: "USD".to_string(),
+            }],
+            balances: vec![UserBalance {
+                username: "manager_test_user_36".to_string(),
+                balance: -10000,
+                currency: "USD".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_ledger_update(&mut con, chat_id, update)
+                .await
+                .unwrap_err(),
+            CrudError::NegativeSpendingError()
+        );
+        assert_eq!(
+            get_chat_payments_details(&mut con, chat_id).await.unwrap(),
+            payments
+        );
+        assert_eq!(
+            get_chat_balances_currency(&mut con, chat_id, "USD")
+                .await
+                .unwrap(),
+            balances
+        );
+
+        // Deletes payment, balances and spendings
+        assert!(apply_ledger_update(
+            &mut con,
+            chat_id,
+            payment_change(PaymentChange::Delete(payments[0].payment_id.to_string()))
+        )
+        .await
+        .is_ok());
+        delete_all_chat_payment(&mut con, chat_id).await.unwrap();
+        for balance in &balances {
+            delete_balance(&mut con, chat_id, &balance.username, "USD")
+                .await
+                .unwrap();
+        }
+        for spending in &spendings {
+            delete_spending(&mut con, chat_id, &spending.username, "USD")
+                .await
+                .unwrap();
+        }
+
+        // Deletes usernames
+        for username in &usernames {
+            delete_user(&mut con, username).await.unwrap();
+            delete_preferred_username(&mut con, username).await.unwrap();
         }
 
         // Deletes chat
-        delete_chat(&mut con, chat_id).unwrap();
-        delete_chat_currencies(&mut con, chat_id).unwrap();
-        delete_chat_settings(&mut con, chat_id).unwrap();
+        delete_chat(&mut con, chat_id).await.unwrap();
+        delete_chat_currencies(&mut con, chat_id).await.unwrap();
+        delete_chat_settings(&mut con, chat_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_trash_restore_purge_payment() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "manager_12345678994";
+        let payment = Payment {
+            description: "manager_test_payment".to_string(),
+            timestamp: 1609459200,
+            creditor: "manager_test_user_38".to_string(),
+            currency: "USD".to_string(),
+            total: 10000,
+            debts: vec![("manager_test_user_38".to_string(), 10000)],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        };
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment.clone())],
+            ..Default::default()
+        };
+        apply_ledger_update(&mut con, chat_id, update)
+            .await
+            .unwrap();
+        let payments = get_chat_payments_details(&mut con, chat_id).await.unwrap();
+        let payment_id = payments[0].payment_id.clone();
+
+        // Trashed payments are hidden, but kept in the trash
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: payment_id.clone(),
+                deleted_at: 1700000000,
+                deleted_by: "manager_test_user_38".to_string(),
+            }],
+            ..Default::default()
+        };
+        apply_ledger_update(&mut con, chat_id, update.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            get_chat_payments_details(&mut con, chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+        assert_eq!(
+            get_chat_trash_details(&mut con, chat_id).await.unwrap(),
+            vec![TrashedPayment {
+                payment_id: payment_id.clone(),
+                payment: payment.clone(),
+                deleted_at: 1700000000,
+                deleted_by: "manager_test_user_38".to_string(),
+            }]
+        );
+
+        // A payment cannot be trashed twice
+        assert_eq!(
+            apply_ledger_update(&mut con, chat_id, update.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Restored payments are shown again
+        let restore = LedgerUpdate {
+            payments: vec![PaymentChange::Restore(payment_id.clone())],
+            ..Default::default()
+        };
+        apply_ledger_update(&mut con, chat_id, restore.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            get_chat_payments_details(&mut con, chat_id).await.unwrap(),
+            payments
+        );
+        assert!(get_chat_trash_details(&mut con, chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            apply_ledger_update(&mut con, chat_id, restore).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Only trashed payments can be purged
+        let purge = LedgerUpdate {
+            payments: vec![PaymentChange::Purge(payment_id.clone())],
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_ledger_update(&mut con, chat_id, purge.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        apply_ledger_update(&mut con, chat_id, update)
+            .await
+            .unwrap();
+        apply_ledger_update(&mut con, chat_id, purge).await.unwrap();
+        assert!(get_chat_trash_details(&mut con, chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            get_payment_entry(&mut con, &payment_id).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        delete_all_chat_payment(&mut con, chat_id).await.unwrap();
+        delete_chat_currencies(&mut con, chat_id).await.unwrap();
     }
 
     #[test]
-    fn test_request_limit() {
+    fn test_migrate_legacy_event() {
+        let event = r#"{"before":null,"after":{"datetime":"2024-01-01 00:00:00 UTC"}}"#;
+        let (migrated, fallbacks) = migrate_legacy_event(event).unwrap().unwrap();
+        assert!(fallbacks.is_empty());
+        let migrated: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+        assert_eq!(migrated["after"]["timestamp"], 1704067200);
+        assert!(migrated["after"].get("datetime").is_none());
+
+        // Events already migrated are left as they are
+        let event = r#"{"before":null,"after":{"timestamp":1704067200}}"#;
+        assert_eq!(migrate_legacy_event(event).unwrap(), None);
+
+        // Unparseable datetimes are given the fallback timestamp
+        let event = r#"{"before":null,"after":{"datetime":"yesterday"}}"#;
+        let (migrated, fallbacks) = migrate_legacy_event(event).unwrap().unwrap();
+        let migrated: serde_json::Value = serde_json::from_str(&migrated).unwrap();
+        assert_eq!(migrated["after"]["timestamp"], MIGRATION_DATETIME_FALLBACK);
+        assert_eq!(fallbacks, vec!["\"yesterday\"".to_string()]);
+
+        // Events that are not JSON are an error
+        assert!(migrate_legacy_event("not an event").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_payment_datetimes() {
+        let mut con = connect().await.unwrap();
+
+        let payment = Payment {
+            description: "manager_test_migrate".to_string(),
+            timestamp: 0,
+            creditor: "Test_User".to_string(),
+            currency: "USD".to_string(),
+            total: 100,
+            debts: vec![("Test_User".to_string(), 100)],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        };
+        let payment_id = add_payment(&mut con, &payment).await.unwrap();
+
+        // Stores the payment as an older version would
+        let main_key = format!("{PAYMENT_KEY}:{payment_id}");
+        redis::cmd("HDEL")
+            .arg(&main_key)
+            .arg("timestamp")
+            .query_async::<_, ()>(&mut con)
+            .await
+            .unwrap();
+        redis::cmd("HSET")
+            .arg(&main_key)
+            .arg("datetime")
+            .arg("2024-01-01 00:00:00 UTC")
+            .query_async::<_, ()>(&mut con)
+            .await
+            .unwrap();
+
+        delete_migration_done(&mut con, MIGRATION_PAYMENT_TIMESTAMPS)
+            .await
+            .unwrap();
+        let migration = migrate_payment_datetimes(&mut con).await.unwrap();
+        assert!(migration.migrated >= 1);
+        assert_eq!(
+            get_payment(&mut con, &payment_id).await.unwrap().timestamp,
+            1704067200
+        );
+
+        // Does nothing once the migration is done
+        assert_eq!(
+            migrate_payment_datetimes(&mut con).await.unwrap(),
+            DatetimeMigration::default()
+        );
+
+        delete_payment(&mut con, &payment_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_limit() {
+        let mut con = connect().await.unwrap();
+
         let user_id = "manager_test_user_35";
 
         // Checks that request limit is not exceeded
-        assert!(!is_request_limit_exceeded(user_id, 1).unwrap());
+        assert!(!is_request_limit_exceeded(&mut con, user_id, 1)
+            .await
+            .unwrap());
 
         // Checks that request limit is exceeded
-        assert!(is_request_limit_exceeded(user_id, 1).unwrap());
+        assert!(is_request_limit_exceeded(&mut con, user_id, 1)
+            .await
+            .unwrap());
 
         // Checks that request limit is not exceeded
-        assert!(!is_request_limit_exceeded(user_id, 2).unwrap());
+        assert!(!is_request_limit_exceeded(&mut con, user_id, 2)
+            .await
+            .unwrap());
 
         // Deletes request
-        let mut con = connect().unwrap();
-        delete_request(&mut con, user_id).unwrap();
+        delete_request(&mut con, user_id).await.unwrap();
     }
 }
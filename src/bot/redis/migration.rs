@@ -0,0 +1,49 @@
+use super::MIGRATION_KEY;
+
+use redis::{aio::ConnectionManager, AsyncCommands, RedisResult};
+
+/* Migration CRUD Operations
+ * Migration records the names of all migrations which are done, in a single set.
+ * It is checked on every connect, so that a migration scanning every record only runs once.
+ * Has set, get, and delete operations.
+ */
+
+// Marks a migration as done
+pub async fn set_migration_done(con: &mut ConnectionManager, migration: &str) -> RedisResult<()> {
+    con.sadd(MIGRATION_KEY, migration).await
+}
+
+// Checks if a migration is done
+pub async fn get_migration_done(con: &mut ConnectionManager, migration: &str) -> RedisResult<bool> {
+    con.sismember(MIGRATION_KEY, migration).await
+}
+
+// Unmarks a migration as done
+// Mainly for testing purposes
+// In application, migrations are never undone
+#[allow(dead_code)]
+pub async fn delete_migration_done(
+    con: &mut ConnectionManager,
+    migration: &str,
+) -> RedisResult<()> {
+    con.srem(MIGRATION_KEY, migration).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_set_get_delete_migration_done() {
+        let mut con = connect().await.unwrap();
+
+        let migration = "test_migration";
+        assert!(!get_migration_done(&mut con, migration).await.unwrap());
+        set_migration_done(&mut con, migration).await.unwrap();
+        assert!(get_migration_done(&mut con, migration).await.unwrap());
+
+        delete_migration_done(&mut con, migration).await.unwrap();
+        assert!(!get_migration_done(&mut con, migration).await.unwrap());
+    }
+}
@@ -1,26 +1,30 @@
 pub use crate::bot::constants::{currency::CURRENCY_CODE_DEFAULT, redis::*};
 
-// Exported functions
-pub use self::manager::{
-    add_payment_entry, delete_payment_entry, get_chat_balances, get_chat_balances_currency,
-    get_chat_payments_details, get_currency_conversion, get_default_currency, get_erase_messages,
-    get_payment_entry, get_time_zone, get_valid_chat_currencies, is_request_limit_exceeded,
-    retrieve_chat_spendings, retrieve_chat_spendings_currency, set_currency_conversion,
-    set_default_currency, set_erase_messages, set_time_zone, update_chat, update_chat_balances,
-    update_chat_spendings, update_payment_entry, update_user,
-};
-
 // Exported structs and types
+pub use self::category::CategoryTotal;
 pub use self::chat::Debt;
-pub use self::manager::{CrudError, UserBalance, UserPayment};
-pub use self::payment::Payment;
+pub use self::manager::{
+    migrate_legacy_event, CrudError, DatetimeMigration, LedgerActor, LedgerUpdate, PaymentAction,
+    PaymentChange, PaymentCursor, PaymentEvent, PaymentPage, PaymentQuery, RecurringChange,
+    TrashedPayment, UserBalance, UserPayment,
+};
+pub use self::payment::{parse_legacy_datetime, Payment, PaymentItem};
+pub use self::recurring::{ChatRecurringPayment, RecurringFrequency, RecurringPayment};
+pub use self::store::RedisStore;
 
 // Submodules
 mod balance;
+mod category;
 mod chat;
 mod connect;
+mod dialogue;
+mod event;
 mod manager;
+mod migration;
 mod payment;
+mod recurring;
 mod request;
 mod spending;
+mod store;
+mod trash;
 mod user;
@@ -1,169 +1,565 @@
-use super::{PAYMENT_DEBT_KEY, PAYMENT_KEY};
+use super::{category::CategoryTotal, PAYMENT_DEBT_KEY, PAYMENT_KEY};
 
-use redis::{Commands, Connection, RedisResult};
+use chrono::NaiveDateTime;
+use redis::{aio::ConnectionManager, AsyncCommands, ErrorKind, Pipeline, RedisError, RedisResult};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 /* Payment CRUD Operations
  * Payment represents a payment entry, used in groups.
- * Payment comprises of a description, immutable datetime, creditor, numeric total,
- * and a list of debts (stored under a different key).
+ * Payment comprises of a description, UTC timestamp, creditor, numeric total,
+ * a list of debts (stored under a different key), the items of an itemized receipt,
+ * an optional category, and the amounts paid by each payer if more than one paid.
  * Has add, exists, get, update, and delete operations.
+ * Add, update, and delete can also be queued into a pipeline for atomic ledger updates.
+ * Payments from before timestamps were kept have a formatted datetime instead, until migrated.
  */
 
 // Debt is an abstraction containing a debtor (String) and the owed amount (i64)
 pub type Debt = (String, i64);
 
+// An item of an itemized receipt, split equally among its own participants
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PaymentItem {
+    pub description: String,
+    pub amount: i64,
+    pub participants: Vec<String>,
+}
+
 // Payment contains all fields stored in Redis related to a single payment entry
-#[derive(Debug, PartialEq)]
+// Items are empty unless the payment was split by item
+// Category is None for payments which were never categorized
+// Payers are empty unless more than one paid, in which case the creditor is the first of them
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Payment {
     pub description: String,
-    pub datetime: String,
+    pub timestamp: i64,
     pub creditor: String,
     pub currency: String,
     pub total: i64,
     pub debts: Vec<Debt>,
+    #[serde(default)]
+    pub items: Vec<PaymentItem>,
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub payers: Vec<Debt>,
+}
+
+// Parses the formatted UTC datetime kept by payments from before timestamps were kept
+// Accepts both the format of Telegram message dates and ISO 8601, None if neither
+pub fn parse_legacy_datetime(datetime: &str) -> Option<i64> {
+    let datetime = datetime
+        .trim()
+        .trim_end_matches(" UTC")
+        .trim_end_matches('Z');
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(datetime, format).ok())
+        .map(|datetime| datetime.and_utc().timestamp())
+}
+
+// Renames a user in a list of amounts, merging the amounts of both the old and the new username
+fn rename_amounts(amounts: &[Debt], old_username: &str, new_username: &str) -> Vec<Debt> {
+    let is_renamed = |username: &str| {
+        username.to_lowercase() == old_username.to_lowercase()
+            || username.to_lowercase() == new_username.to_lowercase()
+    };
+    let mut renamed: Vec<Debt> = Vec::new();
+    for (username, amount) in amounts {
+        if !is_renamed(username) {
+            renamed.push((username.clone(), *amount));
+            continue;
+        }
+        match renamed
+            .iter_mut()
+            .find(|(existing, _)| existing == new_username)
+        {
+            Some(existing) => existing.1 += amount,
+            None => renamed.push((new_username.to_string(), *amount)),
+        }
+    }
+    renamed
+}
+
+impl Payment {
+    // Gets how much each payer paid, which is the whole total for a single creditor
+    pub fn paid_amounts(&self) -> Vec<Debt> {
+        if self.payers.is_empty() {
+            vec![(self.creditor.clone(), self.total)]
+        } else {
+            self.payers.clone()
+        }
+    }
+
+    // Gets what the payment counts towards its category, which is what everyone owes for it
+    pub fn category_total(&self) -> CategoryTotal {
+        CategoryTotal {
+            category: self.category.clone(),
+            currency: self.currency.clone(),
+            spending: self.debts.iter().map(|(_, amount)| amount).sum(),
+            latest: self.timestamp,
+        }
+    }
+
+    // Renames a user in the creditor, payers and debts, matching the old username in any casing
+    // Debts of both the old and the new username are merged into one, as are payers
+    // Returns None if the old username is not in the payment
+    pub fn rename_user(&self, old_username: &str, new_username: &str) -> Option<Payment> {
+        let is_old = |username: &str| username.to_lowercase() == old_username.to_lowercase();
+        let is_new = |username: &str| username.to_lowercase() == new_username.to_lowercase();
+        if !is_old(&self.creditor)
+            && !self.payers.iter().any(|(payer, _)| is_old(payer))
+            && !self.debts.iter().any(|(debtor, _)| is_old(debtor))
+        {
+            return None;
+        }
+
+        let mut payment = self.clone();
+        if is_old(&payment.creditor) || is_new(&payment.creditor) {
+            payment.creditor = new_username.to_string();
+        }
+        payment.debts = rename_amounts(&self.debts, old_username, new_username);
+
+        // A payment left with a single payer no longer keeps a list of them
+        payment.payers = rename_amounts(&self.payers, old_username, new_username);
+        if payment.payers.len() == 1 {
+            payment.payers = Vec::new();
+        }
+
+        for item in &mut payment.items {
+            let mut participants: Vec<String> = Vec::new();
+            for participant in &item.participants {
+                let participant = match is_old(participant) {
+                    true => new_username.to_string(),
+                    false => participant.clone(),
+                };
+                if !participants.contains(&participant) {
+                    participants.push(participant);
+                }
+            }
+            item.participants = participants;
+        }
+
+        Some(payment)
+    }
+}
+
+// Items are kept in the payment hash as a JSON list
+fn serialize_items(items: &[PaymentItem]) -> RedisResult<String> {
+    serde_json::to_string(items).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid payment items",
+            err.to_string(),
+        ))
+    })
+}
+
+// Payers are kept in the payment hash as a JSON list, the same as items
+fn serialize_payers(payers: &[Debt]) -> RedisResult<String> {
+    serde_json::to_string(payers).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid payment payers",
+            err.to_string(),
+        ))
+    })
+}
+
+fn deserialize_payers(payers: &str) -> RedisResult<Vec<Debt>> {
+    serde_json::from_str(payers).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid payment payers",
+            err.to_string(),
+        ))
+    })
+}
+
+fn deserialize_items(items: &str) -> RedisResult<Vec<PaymentItem>> {
+    serde_json::from_str(items).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid payment items",
+            err.to_string(),
+        ))
+    })
 }
 
 // Adds a new payment to Redis
-pub fn add_payment(con: &mut Connection, payment: &Payment) -> RedisResult<String> {
+// Mainly for testing purposes
+// In application, payments are only added through atomic ledger updates
+#[allow(dead_code)]
+pub async fn add_payment(con: &mut ConnectionManager, payment: &Payment) -> RedisResult<String> {
     let id = Uuid::new_v4().to_string();
     let main_key = format!("{PAYMENT_KEY}:{id}");
-    con.hset(&main_key, "description", &payment.description)?;
-    con.hset(&main_key, "datetime", &payment.datetime)?;
-    con.hset(&main_key, "creditor", &payment.creditor)?;
-    con.hset(&main_key, "currency", &payment.currency)?;
-    con.hset(&main_key, "total", &payment.total)?;
+    con.hset::<_, _, _, ()>(&main_key, "description", &payment.description)
+        .await?;
+    con.hset::<_, _, _, ()>(&main_key, "timestamp", payment.timestamp)
+        .await?;
+    con.hset::<_, _, _, ()>(&main_key, "creditor", &payment.creditor)
+        .await?;
+    con.hset::<_, _, _, ()>(&main_key, "currency", &payment.currency)
+        .await?;
+    con.hset::<_, _, _, ()>(&main_key, "total", &payment.total)
+        .await?;
+    if !payment.items.is_empty() {
+        con.hset::<_, _, _, ()>(&main_key, "items", serialize_items(&payment.items)?)
+            .await?;
+    }
+    if let Some(category) = &payment.category {
+        con.hset::<_, _, _, ()>(&main_key, "category", category)
+            .await?;
+    }
+    if !payment.payers.is_empty() {
+        con.hset::<_, _, _, ()>(&main_key, "payers", serialize_payers(&payment.payers)?)
+            .await?;
+    }
 
     let debt_key = format!("{PAYMENT_DEBT_KEY}:{id}");
     for debt in &payment.debts {
-        con.rpush(&debt_key, debt)?;
+        con.rpush::<_, _, ()>(&debt_key, debt).await?;
     }
 
     Ok(id)
 }
 
+// Queues a new payment with a given ID into a pipeline
+pub fn queue_add_payment(
+    pipe: &mut Pipeline,
+    payment_id: &str,
+    payment: &Payment,
+) -> RedisResult<()> {
+    let main_key = format!("{PAYMENT_KEY}:{payment_id}");
+    pipe.hset(&main_key, "description", &payment.description)
+        .ignore();
+    pipe.hset(&main_key, "timestamp", payment.timestamp)
+        .ignore();
+    pipe.hset(&main_key, "creditor", &payment.creditor).ignore();
+    pipe.hset(&main_key, "currency", &payment.currency).ignore();
+    pipe.hset(&main_key, "total", payment.total).ignore();
+    if !payment.items.is_empty() {
+        pipe.hset(&main_key, "items", serialize_items(&payment.items)?)
+            .ignore();
+    }
+    if let Some(category) = &payment.category {
+        pipe.hset(&main_key, "category", category).ignore();
+    }
+    if !payment.payers.is_empty() {
+        pipe.hset(&main_key, "payers", serialize_payers(&payment.payers)?)
+            .ignore();
+    }
+
+    let debt_key = format!("{PAYMENT_DEBT_KEY}:{payment_id}");
+    for debt in &payment.debts {
+        pipe.rpush(&debt_key, debt).ignore();
+    }
+    Ok(())
+}
+
+// Checks if a payment exists
+pub async fn get_payment_exists(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{PAYMENT_KEY}:{payment_id}")).await
+}
+
 // Gets a payment from Redis
-pub fn get_payment(con: &mut Connection, payment_id: &str) -> RedisResult<Payment> {
+pub async fn get_payment(con: &mut ConnectionManager, payment_id: &str) -> RedisResult<Payment> {
     let main_key = format!("{PAYMENT_KEY}:{payment_id}");
-    let description: String = con.hget(&main_key, "description")?;
-    let datetime: String = con.hget(&main_key, "datetime")?;
-    let creditor: String = con.hget(&main_key, "creditor")?;
-    let currency: String = con.hget(&main_key, "currency")?;
-    let total: i64 = con.hget(&main_key, "total")?;
+    let description: String = con.hget(&main_key, "description").await?;
+    let timestamp: i64 = con.hget(&main_key, "timestamp").await?;
+    let creditor: String = con.hget(&main_key, "creditor").await?;
+    let currency: String = con.hget(&main_key, "currency").await?;
+    let total: i64 = con.hget(&main_key, "total").await?;
+    let items: Option<String> = con.hget(&main_key, "items").await?;
+    let items = match items {
+        Some(items) => deserialize_items(&items)?,
+        None => Vec::new(),
+    };
+    let category: Option<String> = con.hget(&main_key, "category").await?;
+    let payers: Option<String> = con.hget(&main_key, "payers").await?;
+    let payers = match payers {
+        Some(payers) => deserialize_payers(&payers)?,
+        None => Vec::new(),
+    };
 
     let debt_key = format!("{PAYMENT_DEBT_KEY}:{payment_id}");
-    let debts: Vec<Debt> = con.lrange(&debt_key, 0, -1)?;
+    let debts: Vec<Debt> = con.lrange(&debt_key, 0, -1).await?;
 
     let payment = Payment {
         description,
-        datetime,
+        timestamp,
         creditor,
         currency,
         total,
         debts,
+        items,
+        category,
+        payers,
     };
 
     Ok(payment)
 }
 
 // Updates a payment in Redis
-pub fn update_payment(
-    con: &mut Connection,
+// Mainly for testing purposes
+// In application, payments are updated with queue_update_payment in a ledger update
+#[allow(dead_code)]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_payment(
+    con: &mut ConnectionManager,
     payment_id: &str,
     description: Option<&str>,
     creditor: Option<&str>,
     currency: Option<&str>,
     total: Option<&i64>,
     debts: Option<Vec<Debt>>,
+    items: Option<&[PaymentItem]>,
+    payers: Option<&[Debt]>,
 ) -> RedisResult<()> {
     let main_key = format!("{PAYMENT_KEY}:{payment_id}");
     if let Some(desc) = description {
-        con.hset(&main_key, "description", desc)?;
+        con.hset::<_, _, _, ()>(&main_key, "description", desc)
+            .await?;
     }
     if let Some(cred) = creditor {
-        con.hset(&main_key, "creditor", cred)?;
+        con.hset::<_, _, _, ()>(&main_key, "creditor", cred).await?;
     }
     if let Some(curr) = currency {
-        con.hset(&main_key, "currency", curr)?;
+        con.hset::<_, _, _, ()>(&main_key, "currency", curr).await?;
     }
     if let Some(tot) = total {
-        con.hset(&main_key, "total", tot)?;
+        con.hset::<_, _, _, ()>(&main_key, "total", tot).await?;
+    }
+    match items {
+        Some([]) => con.hdel::<_, _, ()>(&main_key, "items").await?,
+        Some(items) => {
+            con.hset::<_, _, _, ()>(&main_key, "items", serialize_items(items)?)
+                .await?
+        }
+        None => {}
+    }
+    match payers {
+        Some([]) => con.hdel::<_, _, ()>(&main_key, "payers").await?,
+        Some(payers) => {
+            con.hset::<_, _, _, ()>(&main_key, "payers", serialize_payers(payers)?)
+                .await?
+        }
+        None => {}
     }
     if let Some(debt) = debts {
         let debt_key = format!("{PAYMENT_DEBT_KEY}:{payment_id}");
-        con.del(&debt_key)?;
+        con.del::<_, ()>(&debt_key).await?;
         for d in debt {
-            con.rpush(&debt_key, d)?;
+            con.rpush::<_, _, ()>(&debt_key, d).await?;
         }
     }
 
     Ok(())
 }
 
+// Queues updating a payment into a pipeline
+#[allow(clippy::too_many_arguments)]
+pub fn queue_update_payment(
+    pipe: &mut Pipeline,
+    payment_id: &str,
+    description: Option<&str>,
+    timestamp: Option<i64>,
+    creditor: Option<&str>,
+    currency: Option<&str>,
+    total: Option<&i64>,
+    debts: Option<&[Debt]>,
+    items: Option<&[PaymentItem]>,
+    category: Option<Option<&str>>,
+    payers: Option<&[Debt]>,
+) -> RedisResult<()> {
+    let main_key = format!("{PAYMENT_KEY}:{payment_id}");
+    if let Some(desc) = description {
+        pipe.hset(&main_key, "description", desc).ignore();
+    }
+    if let Some(time) = timestamp {
+        pipe.hset(&main_key, "timestamp", time).ignore();
+    }
+    if let Some(cred) = creditor {
+        pipe.hset(&main_key, "creditor", cred).ignore();
+    }
+    if let Some(curr) = currency {
+        pipe.hset(&main_key, "currency", curr).ignore();
+    }
+    if let Some(tot) = total {
+        pipe.hset(&main_key, "total", tot).ignore();
+    }
+    if let Some(debt) = debts {
+        let debt_key = format!("{PAYMENT_DEBT_KEY}:{payment_id}");
+        pipe.del(&debt_key).ignore();
+        for d in debt {
+            pipe.rpush(&debt_key, d).ignore();
+        }
+    }
+    match items {
+        Some([]) => {
+            pipe.hdel(&main_key, "items").ignore();
+        }
+        Some(items) => {
+            pipe.hset(&main_key, "items", serialize_items(items)?)
+                .ignore();
+        }
+        None => {}
+    }
+    match category {
+        Some(Some(category)) => {
+            pipe.hset(&main_key, "category", category).ignore();
+        }
+        Some(None) => {
+            pipe.hdel(&main_key, "category").ignore();
+        }
+        None => {}
+    }
+    match payers {
+        Some([]) => {
+            pipe.hdel(&main_key, "payers").ignore();
+        }
+        Some(payers) => {
+            pipe.hset(&main_key, "payers", serialize_payers(payers)?)
+                .ignore();
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+// Gets all payments which still have a formatted datetime instead of a timestamp
+// Used when payments are migrated
+pub async fn get_legacy_payments(con: &mut ConnectionManager) -> RedisResult<Vec<String>> {
+    let prefix = format!("{PAYMENT_KEY}:");
+    let mut keys = Vec::new();
+    {
+        let mut iter = con.scan_match::<_, String>(format!("{prefix}*")).await?;
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+    }
+
+    let mut payment_ids = Vec::new();
+    for key in keys {
+        if con.hexists(&key, "datetime").await? {
+            if let Some(payment_id) = key.strip_prefix(&prefix) {
+                payment_ids.push(payment_id.to_string());
+            }
+        }
+    }
+    Ok(payment_ids)
+}
+
+// Gets the formatted datetime of a payment from before timestamps were kept
+pub async fn get_legacy_datetime(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> RedisResult<String> {
+    con.hget(format!("{PAYMENT_KEY}:{payment_id}"), "datetime")
+        .await
+}
+
+// Replaces the formatted datetime of a payment with its timestamp
+// Used when payments are migrated
+pub async fn set_payment_timestamp(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+    timestamp: i64,
+) -> RedisResult<()> {
+    let main_key = format!("{PAYMENT_KEY}:{payment_id}");
+    con.hset::<_, _, _, ()>(&main_key, "timestamp", timestamp)
+        .await?;
+    con.hdel(&main_key, "datetime").await
+}
+
 // Deletes a payment from Redis
-pub fn delete_payment(con: &mut Connection, payment_id: &str) -> RedisResult<()> {
+// Mainly for testing purposes
+// In application, payments are only deleted through atomic ledger updates
+#[allow(dead_code)]
+pub async fn delete_payment(con: &mut ConnectionManager, payment_id: &str) -> RedisResult<()> {
     let main_key = format!("{PAYMENT_KEY}:{payment_id}");
     let debt_key = format!("{PAYMENT_DEBT_KEY}:{payment_id}");
-    con.del(&main_key)?;
-    con.del(&debt_key)?;
+    con.del::<_, ()>(&main_key).await?;
+    con.del::<_, ()>(&debt_key).await?;
 
     Ok(())
 }
 
+// Queues deleting a payment into a pipeline
+pub fn queue_delete_payment(pipe: &mut Pipeline, payment_id: &str) {
+    pipe.del(format!("{PAYMENT_KEY}:{payment_id}")).ignore();
+    pipe.del(format!("{PAYMENT_DEBT_KEY}:{payment_id}"))
+        .ignore();
+}
+
 // Tests
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_add_get_payment() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_get_payment() {
+        let mut con = connect().await.unwrap();
 
         let description = "test_payment";
-        let datetime = "2020-01-01T00:00:00Z";
+        let timestamp = 1577836800;
         let creditor = "test_creditor";
         let currency = "USD";
         let total = 10000;
         let debts = vec![("test_debtor".to_string(), 10000)];
         let first_payment = Payment {
             description: description.to_string(),
-            datetime: datetime.to_string(),
+            timestamp,
             creditor: creditor.to_string(),
             currency: currency.to_string(),
             total,
             debts: debts.clone(),
+            items: vec![PaymentItem {
+                description: "test_item".to_string(),
+                amount: 10000,
+                participants: vec!["test_debtor".to_string()],
+            }],
+            category: None,
+            payers: Vec::new(),
         };
-        let payment_op = add_payment(&mut con, &first_payment);
+        let payment_op = add_payment(&mut con, &first_payment).await;
 
         assert!(payment_op.is_ok());
 
         let payment_id = payment_op.unwrap();
-        let payment = get_payment(&mut con, &payment_id);
+        let payment = get_payment(&mut con, &payment_id).await;
         assert_eq!(payment.unwrap(), first_payment);
 
-        delete_payment(&mut con, &payment_id).unwrap();
+        delete_payment(&mut con, &payment_id).await.unwrap();
     }
 
-    #[test]
-    fn test_update_payment() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_payment() {
+        let mut con = connect().await.unwrap();
 
         let description = "test_payment";
-        let datetime = "2020-01-01T00:00:00Z";
+        let timestamp = 1577836800;
         let creditor = "test_creditor";
         let currency = "JPY";
         let total = 10000;
         let debts = vec![("test_debtor".to_string(), 10000)];
         let first_payment = Payment {
             description: description.to_string(),
-            datetime: datetime.to_string(),
+            timestamp,
             creditor: creditor.to_string(),
             currency: currency.to_string(),
             total,
             debts: debts.clone(),
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
         };
-        let payment_id = add_payment(&mut con, &first_payment).unwrap();
+        let payment_id = add_payment(&mut con, &first_payment).await.unwrap();
 
         let new_description = "new_test_payment";
         let new_creditor = "new_test_creditor";
@@ -179,32 +575,109 @@ mod tests {
             Some(new_currency),
             Some(&new_total),
             Some(new_debts.clone()),
-        );
+            None,
+            None,
+        )
+        .await;
 
         assert!(update_op.is_ok());
 
-        let payment = get_payment(&mut con, &payment_id);
+        let payment = get_payment(&mut con, &payment_id).await;
         assert_eq!(
             payment.unwrap(),
             Payment {
                 description: new_description.to_string(),
-                datetime: datetime.to_string(),
+                timestamp,
                 creditor: new_creditor.to_string(),
                 currency: new_currency.to_string(),
                 total: new_total,
                 debts: new_debts.clone(),
+                items: Vec::new(),
+                category: None,
+                payers: Vec::new(),
             }
         );
 
-        delete_payment(&mut con, &payment_id).unwrap();
+        delete_payment(&mut con, &payment_id).await.unwrap();
     }
 
     #[test]
-    fn test_delete_payment() {
-        let mut con = connect().unwrap();
+    fn test_parse_legacy_datetime() {
+        assert_eq!(
+            parse_legacy_datetime("2024-01-01 00:00:00 UTC"),
+            Some(1704067200)
+        );
+        assert_eq!(
+            parse_legacy_datetime("2024-01-01T00:00:01Z"),
+            Some(1704067201)
+        );
+        assert_eq!(parse_legacy_datetime("not a datetime"), None);
+    }
+
+    #[test]
+    fn test_rename_user() {
+        let payment = Payment {
+            description: "test_payment".to_string(),
+            timestamp: 1577836800,
+            creditor: "Old_User".to_string(),
+            currency: "USD".to_string(),
+            total: 900,
+            debts: vec![
+                ("old_user".to_string(), 300),
+                ("other_user".to_string(), 300),
+                ("New_User".to_string(), 300),
+            ],
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        };
+
+        assert_eq!(payment.rename_user("missing_user", "New_User"), None);
+        assert_eq!(
+            payment.rename_user("Old_User", "New_User"),
+            Some(Payment {
+                creditor: "New_User".to_string(),
+                debts: vec![
+                    ("New_User".to_string(), 600),
+                    ("other_user".to_string(), 300),
+                ],
+                ..payment.clone()
+            })
+        );
+
+        // Payers are merged the same way, and dropped once only one is left
+        let shared = Payment {
+            payers: vec![
+                ("Old_User".to_string(), 500),
+                ("other_user".to_string(), 100),
+                ("new_user".to_string(), 300),
+            ],
+            ..payment.clone()
+        };
+        assert_eq!(
+            shared.rename_user("Old_User", "New_User").unwrap().payers,
+            vec![
+                ("New_User".to_string(), 800),
+                ("other_user".to_string(), 100),
+            ]
+        );
+        let pair = Payment {
+            payers: vec![("Old_User".to_string(), 500), ("New_User".to_string(), 400)],
+            ..payment.clone()
+        };
+        assert!(pair
+            .rename_user("Old_User", "New_User")
+            .unwrap()
+            .payers
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_payment() {
+        let mut con = connect().await.unwrap();
 
         let description = "test_payment";
-        let datetime = "2020-01-01T00:00:00Z";
+        let timestamp = 1577836800;
         let creditor = "test_creditor";
         let currency = "USD";
         let total = 10000;
@@ -213,14 +686,18 @@ mod tests {
             &mut con,
             &Payment {
                 description: description.to_string(),
-                datetime: datetime.to_string(),
+                timestamp,
                 creditor: creditor.to_string(),
                 currency: currency.to_string(),
                 total,
                 debts: debts.clone(),
+                items: Vec::new(),
+                category: None,
+                payers: Vec::new(),
             },
         )
+        .await
         .unwrap();
-        assert!(delete_payment(&mut con, &payment_id).is_ok());
+        assert!(delete_payment(&mut con, &payment_id).await.is_ok());
     }
 }
@@ -0,0 +1,212 @@
+use super::{payment::Payment, CHAT_RECURRING_KEY, RECURRING_DUE_KEY, RECURRING_KEY};
+
+use redis::{aio::ConnectionManager, AsyncCommands, ErrorKind, Pipeline, RedisError, RedisResult};
+use serde::{Deserialize, Serialize};
+
+/* Recurring Payment CRUD Operations
+ * A recurring payment is a payment that is added to its chat on a schedule, such as rent.
+ * It comprises of the payment to add, how often it is added, an optional end,
+ * and the UTC timestamp it is next due at, which is also the date of the payment then added.
+ * Each is kept whole as JSON, as it is only ever read and written whole.
+ * Each chat has a set of its recurring payments,
+ * and all recurring payments are kept in a sorted set by when they are next due.
+ * Has set, get, and delete operations, set and delete can also be queued into a pipeline.
+ */
+
+// How often a recurring payment is added
+// Custom schedules are cron expressions, in the time zone of the chat
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum RecurringFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Cron(String),
+}
+
+// RecurringPayment contains the payment to add, with the date it is first due as its timestamp
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RecurringPayment {
+    pub payment: Payment,
+    pub frequency: RecurringFrequency,
+    pub end: Option<i64>,
+    pub next: i64,
+    pub creator_id: String,
+    pub creator: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ChatRecurringPayment {
+    pub recurring_id: String,
+    pub chat_id: String,
+    pub recurring: RecurringPayment,
+}
+
+fn serialize_recurring(recurring: &ChatRecurringPayment) -> RedisResult<String> {
+    serde_json::to_string(recurring).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid recurring payment",
+            err.to_string(),
+        ))
+    })
+}
+
+fn deserialize_recurring(recurring: &str) -> RedisResult<ChatRecurringPayment> {
+    serde_json::from_str(recurring).map_err(|err| {
+        RedisError::from((
+            ErrorKind::TypeError,
+            "Invalid recurring payment",
+            err.to_string(),
+        ))
+    })
+}
+
+// Sets a recurring payment, adding it to its chat and to the due payments
+// Replaces any recurring payment with the same ID, together with when it is due
+pub async fn set_recurring(
+    con: &mut ConnectionManager,
+    recurring: &ChatRecurringPayment,
+) -> RedisResult<()> {
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    queue_set_recurring(&mut pipe, recurring)?;
+    pipe.query_async(con).await
+}
+
+// Queues setting a recurring payment into a pipeline
+pub fn queue_set_recurring(
+    pipe: &mut Pipeline,
+    recurring: &ChatRecurringPayment,
+) -> RedisResult<()> {
+    let recurring_id = &recurring.recurring_id;
+    pipe.set(
+        format!("{RECURRING_KEY}:{recurring_id}"),
+        serialize_recurring(recurring)?,
+    )
+    .ignore()
+    .sadd(
+        format!("{CHAT_RECURRING_KEY}:{}", recurring.chat_id),
+        recurring_id,
+    )
+    .ignore()
+    .zadd(RECURRING_DUE_KEY, recurring_id, recurring.recurring.next)
+    .ignore();
+    Ok(())
+}
+
+// Gets a recurring payment, if it exists
+pub async fn get_recurring(
+    con: &mut ConnectionManager,
+    recurring_id: &str,
+) -> RedisResult<Option<ChatRecurringPayment>> {
+    let recurring: Option<String> = con.get(format!("{RECURRING_KEY}:{recurring_id}")).await?;
+    recurring
+        .map(|recurring| deserialize_recurring(&recurring))
+        .transpose()
+}
+
+// Gets the IDs of all recurring payments of a chat
+pub async fn get_chat_recurring(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
+    con.smembers(format!("{CHAT_RECURRING_KEY}:{chat_id}"))
+        .await
+}
+
+// Gets the IDs of all recurring payments due by the given time, in any chat, earliest first
+pub async fn get_due_recurring(con: &mut ConnectionManager, now: i64) -> RedisResult<Vec<String>> {
+    con.zrangebyscore(RECURRING_DUE_KEY, "-inf", now).await
+}
+
+// Deletes a recurring payment, taking it out of its chat and the due payments
+pub async fn delete_recurring(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    recurring_id: &str,
+) -> RedisResult<()> {
+    redis::pipe()
+        .atomic()
+        .del(format!("{RECURRING_KEY}:{recurring_id}"))
+        .ignore()
+        .srem(format!("{CHAT_RECURRING_KEY}:{chat_id}"), recurring_id)
+        .ignore()
+        .zrem(RECURRING_DUE_KEY, recurring_id)
+        .ignore()
+        .query_async(con)
+        .await
+}
+
+// Queues deleting a recurring payment into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_recurring(pipe: &mut Pipeline, chat_id: &str, recurring_id: &str) {
+    pipe.del(format!("{RECURRING_KEY}:{recurring_id}")).ignore();
+    pipe.srem(format!("{CHAT_RECURRING_KEY}:{chat_id}"), recurring_id)
+        .ignore();
+    pipe.zrem(RECURRING_DUE_KEY, recurring_id).ignore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_set_get_delete_recurring() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "recurring_123456789";
+        let recurring = ChatRecurringPayment {
+            recurring_id: "recurring_test".to_string(),
+            chat_id: chat_id.to_string(),
+            recurring: RecurringPayment {
+                payment: Payment {
+                    description: "test_rent".to_string(),
+                    timestamp: 1577836800,
+                    creditor: "test_creditor".to_string(),
+                    currency: "USD".to_string(),
+                    total: 10000,
+                    debts: vec![("test_debtor".to_string(), 10000)],
+                    items: Vec::new(),
+                    category: None,
+                    payers: Vec::new(),
+                },
+                frequency: RecurringFrequency::Monthly,
+                end: None,
+                next: 1577836800,
+                creator_id: "987654321".to_string(),
+                creator: "test_creditor".to_string(),
+            },
+        };
+
+        assert!(set_recurring(&mut con, &recurring).await.is_ok());
+        assert_eq!(
+            get_recurring(&mut con, "recurring_test").await.unwrap(),
+            Some(recurring.clone())
+        );
+        assert_eq!(
+            get_chat_recurring(&mut con, chat_id).await.unwrap(),
+            vec!["recurring_test".to_string()]
+        );
+        assert!(get_due_recurring(&mut con, 1577836800)
+            .await
+            .unwrap()
+            .contains(&"recurring_test".to_string()));
+        assert!(!get_due_recurring(&mut con, 1577836799)
+            .await
+            .unwrap()
+            .contains(&"recurring_test".to_string()));
+
+        assert!(delete_recurring(&mut con, chat_id, "recurring_test")
+            .await
+            .is_ok());
+        assert_eq!(
+            get_recurring(&mut con, "recurring_test").await.unwrap(),
+            None
+        );
+        assert!(get_chat_recurring(&mut con, chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,6 +1,6 @@
 use super::REQUEST_KEY;
 
-use redis::{Commands, Connection, RedisResult};
+use redis::{aio::ConnectionManager, AsyncCommands, RedisResult};
 /* request.rs contains CRUD operations for `request`.
  * `request` is the main table used for tracking user requests.
  */
@@ -13,21 +13,25 @@ use redis::{Commands, Connection, RedisResult};
  */
 
 // Sets a request for a user
-pub fn set_request(con: &mut Connection, user_id: &str, timestamp: i64) -> RedisResult<()> {
-    con.set(format!("{REQUEST_KEY}:{user_id}"), timestamp)
+pub async fn set_request(
+    con: &mut ConnectionManager,
+    user_id: &str,
+    timestamp: i64,
+) -> RedisResult<()> {
+    con.set(format!("{REQUEST_KEY}:{user_id}"), timestamp).await
 }
 
 // Gets the request for a user
-pub fn get_request(con: &mut Connection, user_id: &str) -> RedisResult<i64> {
-    con.get(format!("{REQUEST_KEY}:{user_id}"))
+pub async fn get_request(con: &mut ConnectionManager, user_id: &str) -> RedisResult<i64> {
+    con.get(format!("{REQUEST_KEY}:{user_id}")).await
 }
 
 // Deletes the request for a user
 // Mainly for testing purposes
 // In application, no real need to delete keys
 #[allow(dead_code)]
-pub fn delete_request(con: &mut Connection, user_id: &str) -> RedisResult<()> {
-    con.del(format!("{REQUEST_KEY}:{user_id}"))
+pub async fn delete_request(con: &mut ConnectionManager, user_id: &str) -> RedisResult<()> {
+    con.del(format!("{REQUEST_KEY}:{user_id}")).await
 }
 
 #[cfg(test)]
@@ -35,16 +39,16 @@ mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_get_set_request() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_set_request() {
+        let mut con = connect().await.unwrap();
 
         let user_id = "12345678901";
         let timestamp = 1234567890;
 
-        assert!(set_request(&mut con, user_id, timestamp).is_ok());
-        assert_eq!(get_request(&mut con, user_id).unwrap(), timestamp);
+        assert!(set_request(&mut con, user_id, timestamp).await.is_ok());
+        assert_eq!(get_request(&mut con, user_id).await.unwrap(), timestamp);
 
-        assert!(delete_request(&mut con, user_id).is_ok());
+        assert!(delete_request(&mut con, user_id).await.is_ok());
     }
 }
@@ -1,14 +1,16 @@
 use super::EXPENSE_KEY;
-use redis::{Commands, Connection, RedisResult};
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
 
 /* Spending CRUD Operations
  * Spending represents the total expenses incurred by a user in a group.
  * Has get, set, exists, and delete operations.
  */
 
-// Adds or updates a spending to Redis
-pub fn set_spending(
-    con: &mut Connection,
+// Sets a spending to Redis directly
+// Mainly for testing purposes, spendings are otherwise only incremented
+#[allow(dead_code)]
+pub async fn set_spending(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
@@ -18,39 +20,83 @@ pub fn set_spending(
         format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"),
         spending,
     )
+    .await
+}
+
+// Queues adding a delta onto a spending into a pipeline, used for atomic ledger updates
+// The caller is responsible for checking that the spending does not turn negative
+pub fn queue_incr_spending(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    user_id: &str,
+    currency: &str,
+    delta: i64,
+) {
+    pipe.incr(
+        format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"),
+        delta,
+    )
+    .ignore();
 }
 
 // Checks if spending exists
-pub fn get_spending_exists(
-    con: &mut Connection,
+pub async fn get_spending_exists(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<bool> {
     con.exists(format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
 }
 
 // Gets a spending
-pub fn get_spending(
-    con: &mut Connection,
+pub async fn get_spending(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<u64> {
     con.get(format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
 }
 
 // Deletes a spending in Redis
 // Mainly for testing purposes
-// In application, no real need to delete keys
+// In application, spendings are only deleted through atomic pipelines
 #[allow(dead_code)]
-pub fn delete_spending(
-    con: &mut Connection,
+pub async fn delete_spending(
+    con: &mut ConnectionManager,
     chat_id: &str,
     user_id: &str,
     currency: &str,
 ) -> RedisResult<()> {
     con.del(format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .await
+}
+
+// Queues deleting a spending into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_spending(pipe: &mut Pipeline, chat_id: &str, user_id: &str, currency: &str) {
+    pipe.del(format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"))
+        .ignore();
+}
+
+// Queues moving a spending to a new chat into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the spending exists
+pub fn queue_move_spending(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    new_chat_id: &str,
+    user_id: &str,
+    currency: &str,
+) {
+    pipe.rename(
+        format!("{EXPENSE_KEY}:{chat_id}:{user_id}:{currency}"),
+        format!("{EXPENSE_KEY}:{new_chat_id}:{user_id}:{currency}"),
+    )
+    .ignore();
 }
 
 // Tests
@@ -59,41 +105,59 @@ mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_add_get_spending() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_get_spending() {
+        let mut con = connect().await.unwrap();
         let chat_id = "test_spending_chat";
         let user_id = "test_spending_user";
         let currency = "USD";
         let balance = 100;
 
-        assert!(set_spending(&mut con, chat_id, user_id, currency, balance).is_ok());
-        assert!(get_spending_exists(&mut con, chat_id, user_id, currency).unwrap());
+        assert!(set_spending(&mut con, chat_id, user_id, currency, balance)
+            .await
+            .is_ok());
+        assert!(get_spending_exists(&mut con, chat_id, user_id, currency)
+            .await
+            .unwrap());
         assert_eq!(
-            get_spending(&mut con, chat_id, user_id, currency).unwrap(),
+            get_spending(&mut con, chat_id, user_id, currency)
+                .await
+                .unwrap(),
             balance
         );
 
-        assert!(delete_spending(&mut con, chat_id, user_id, currency).is_ok());
+        assert!(delete_spending(&mut con, chat_id, user_id, currency)
+            .await
+            .is_ok());
     }
 
-    #[test]
-    fn test_update_spending() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_spending() {
+        let mut con = connect().await.unwrap();
         let chat_id = "test_spending_chat_2";
         let user_id = "test_spending_user_2";
         let currency = "USD";
         let balance = 100;
 
-        assert!(set_spending(&mut con, chat_id, user_id, currency, balance).is_ok());
+        assert!(set_spending(&mut con, chat_id, user_id, currency, balance)
+            .await
+            .is_ok());
 
         let updated_balance = 200;
-        assert!(set_spending(&mut con, chat_id, user_id, currency, updated_balance).is_ok());
+        assert!(
+            set_spending(&mut con, chat_id, user_id, currency, updated_balance)
+                .await
+                .is_ok()
+        );
         assert_eq!(
-            get_spending(&mut con, chat_id, user_id, currency).unwrap(),
+            get_spending(&mut con, chat_id, user_id, currency)
+                .await
+                .unwrap(),
             updated_balance
         );
 
-        assert!(delete_spending(&mut con, chat_id, user_id, currency).is_ok());
+        assert!(delete_spending(&mut con, chat_id, user_id, currency)
+            .await
+            .is_ok());
     }
 }
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use tokio::sync::Mutex;
+
+use crate::bot::store::LedgerStore;
+
+use super::category::CategoryTotal;
+use super::connect::connect;
+use super::manager::{
+    self, CrudError, LedgerUpdate, PaymentEvent, PaymentPage, PaymentQuery, TrashedPayment,
+    UserBalance, UserPayment,
+};
+use super::payment::Payment;
+use super::recurring::{ChatRecurringPayment, RecurringPayment};
+
+/* Redis Store
+ * RedisStore is the Redis implementation of the LedgerStore.
+ * All operations are delegated to the manager, which owns the Redis logic.
+ * Connections are opened once, and shared by every handler for the lifetime of the bot.
+ * Ledger updates, chat resets, migrations and renames use WATCH, which is tied to a connection,
+ * so they get a connection of their own, used by one of them at a time.
+ */
+
+pub struct RedisStore {
+    con: ConnectionManager,
+    ledger_con: Mutex<ConnectionManager>,
+}
+
+impl RedisStore {
+    // Connects to Redis, and migrates any payments stored by an older version
+    // Records which cannot be migrated are logged, and do not stop the bot from starting
+    pub async fn connect() -> Result<Self, CrudError> {
+        let mut con = connect().await?;
+        manager::migrate_payment_datetimes(&mut con)
+            .await?
+            .log("Redis Store");
+        let migrated = manager::migrate_category_totals(&mut con).await?;
+        if migrated > 0 {
+            log::info!("Redis Store - Migrated category totals of {migrated} chats");
+        }
+
+        Ok(RedisStore {
+            con,
+            ledger_con: Mutex::new(connect().await?),
+        })
+    }
+
+    // Each call gets its own handle, all multiplexed over the same connection
+    fn con(&self) -> ConnectionManager {
+        self.con.clone()
+    }
+}
+
+#[async_trait]
+impl LedgerStore for RedisStore {
+    async fn update_user(
+        &self,
+        username: &str,
+        chat_id: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), CrudError> {
+        manager::update_user(&mut self.con(), username, chat_id, user_id).await
+    }
+
+    async fn update_chat(&self, chat_id: &str, usernames: Vec<String>) -> Result<(), CrudError> {
+        manager::update_chat(&mut self.con(), chat_id, usernames).await
+    }
+
+    async fn get_user_chats(&self, username: &str) -> Result<Vec<String>, CrudError> {
+        manager::retrieve_user_chats(&mut self.con(), username).await
+    }
+
+    async fn get_chat_users(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        manager::retrieve_chat_users(&mut self.con(), chat_id).await
+    }
+
+    async fn forget_user(&self, username: &str) -> Result<(), CrudError> {
+        manager::forget_user(&mut self.con(), username).await
+    }
+
+    async fn reset_chat(&self, chat_id: &str) -> Result<(), CrudError> {
+        manager::reset_chat(&mut *self.ledger_con.lock().await, chat_id).await
+    }
+
+    async fn migrate_chat(&self, chat_id: &str, new_chat_id: &str) -> Result<(), CrudError> {
+        manager::migrate_chat(&mut *self.ledger_con.lock().await, chat_id, new_chat_id).await
+    }
+
+    async fn get_tracked_username(&self, user_id: &str) -> Result<Option<String>, CrudError> {
+        manager::get_tracked_username(&mut self.con(), user_id).await
+    }
+
+    async fn track_username(&self, user_id: &str, username: &str) -> Result<(), CrudError> {
+        manager::track_username(&mut self.con(), user_id, username).await
+    }
+
+    async fn rename_user(&self, old_username: &str, new_username: &str) -> Result<(), CrudError> {
+        manager::rename_user(
+            &mut *self.ledger_con.lock().await,
+            old_username,
+            new_username,
+        )
+        .await
+    }
+
+    async fn set_member_name(&self, member: &str, name: &str) -> Result<(), CrudError> {
+        manager::update_member_name(&mut self.con(), member, name).await
+    }
+
+    async fn get_member_names(
+        &self,
+        members: &[String],
+    ) -> Result<HashMap<String, String>, CrudError> {
+        manager::retrieve_member_names(&mut self.con(), members).await
+    }
+
+    async fn set_time_zone(&self, chat_id: &str, time_zone: &str) -> Result<(), CrudError> {
+        manager::set_time_zone(&mut self.con(), chat_id, time_zone).await
+    }
+
+    async fn get_time_zone(&self, chat_id: &str) -> Result<String, CrudError> {
+        manager::get_time_zone(&mut self.con(), chat_id).await
+    }
+
+    async fn set_default_currency(&self, chat_id: &str, currency: &str) -> Result<(), CrudError> {
+        manager::set_default_currency(&mut self.con(), chat_id, currency).await
+    }
+
+    async fn get_default_currency(&self, chat_id: &str) -> Result<String, CrudError> {
+        manager::get_default_currency(&mut self.con(), chat_id).await
+    }
+
+    async fn set_currency_conversion(
+        &self,
+        chat_id: &str,
+        conversion: bool,
+    ) -> Result<(), CrudError> {
+        manager::set_currency_conversion(&mut self.con(), chat_id, conversion).await
+    }
+
+    async fn get_currency_conversion(&self, chat_id: &str) -> Result<bool, CrudError> {
+        manager::get_currency_conversion(&mut self.con(), chat_id).await
+    }
+
+    async fn set_erase_messages(&self, chat_id: &str, erase: bool) -> Result<(), CrudError> {
+        manager::set_erase_messages(&mut self.con(), chat_id, erase).await
+    }
+
+    async fn get_erase_messages(&self, chat_id: &str) -> Result<bool, CrudError> {
+        manager::get_erase_messages(&mut self.con(), chat_id).await
+    }
+
+    async fn get_valid_chat_currencies(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        manager::get_valid_chat_currencies(&mut self.con(), chat_id).await
+    }
+
+    async fn get_chat_balances(&self, chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        manager::get_chat_balances(&mut self.con(), chat_id).await
+    }
+
+    async fn get_chat_balances_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        manager::get_chat_balances_currency(&mut self.con(), chat_id, currency).await
+    }
+
+    async fn get_chat_payments_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<UserPayment>, CrudError> {
+        manager::get_chat_payments_details(&mut self.con(), chat_id).await
+    }
+
+    async fn get_chat_payments_page(
+        &self,
+        chat_id: &str,
+        query: &PaymentQuery,
+    ) -> Result<PaymentPage, CrudError> {
+        manager::get_chat_payments_page(&mut self.con(), chat_id, query).await
+    }
+
+    async fn get_payment_entry(&self, payment_id: &str) -> Result<Payment, CrudError> {
+        manager::get_payment_entry(&mut self.con(), payment_id).await
+    }
+
+    async fn get_chat_trash_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<TrashedPayment>, CrudError> {
+        manager::get_chat_trash_details(&mut self.con(), chat_id).await
+    }
+
+    async fn get_payment_history(&self, payment_id: &str) -> Result<Vec<PaymentEvent>, CrudError> {
+        manager::get_payment_history(&mut self.con(), payment_id).await
+    }
+
+    async fn get_chat_activity(
+        &self,
+        chat_id: &str,
+        count: usize,
+    ) -> Result<Vec<PaymentEvent>, CrudError> {
+        manager::get_chat_activity(&mut self.con(), chat_id, count).await
+    }
+
+    async fn add_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring: &RecurringPayment,
+    ) -> Result<String, CrudError> {
+        manager::add_recurring_payment(&mut self.con(), chat_id, recurring).await
+    }
+
+    async fn get_chat_recurring_payments(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        manager::get_chat_recurring_payments(&mut self.con(), chat_id).await
+    }
+
+    async fn get_due_recurring_payments(
+        &self,
+        now: i64,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        manager::get_due_recurring_payments(&mut self.con(), now).await
+    }
+
+    async fn delete_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring_id: &str,
+    ) -> Result<(), CrudError> {
+        manager::delete_recurring_payment(&mut self.con(), chat_id, recurring_id).await
+    }
+
+    async fn retrieve_chat_spendings(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        manager::retrieve_chat_spendings(&mut self.con(), chat_id).await
+    }
+
+    async fn retrieve_chat_spendings_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        manager::retrieve_chat_spendings_currency(&mut self.con(), chat_id, currency).await
+    }
+
+    async fn retrieve_chat_categories(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<CategoryTotal>, CrudError> {
+        manager::retrieve_chat_categories(&mut self.con(), chat_id).await
+    }
+
+    async fn apply_ledger_update(
+        &self,
+        chat_id: &str,
+        update: LedgerUpdate,
+    ) -> Result<(), CrudError> {
+        manager::apply_ledger_update(&mut *self.ledger_con.lock().await, chat_id, update).await
+    }
+
+    async fn set_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        dialogue: &str,
+    ) -> Result<(), CrudError> {
+        manager::set_dialogue(&mut self.con(), chat_id, user_id, dialogue).await
+    }
+
+    async fn get_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<Option<String>, CrudError> {
+        manager::get_dialogue(&mut self.con(), chat_id, user_id).await
+    }
+
+    async fn delete_dialogue(&self, chat_id: &str, user_id: &str) -> Result<(), CrudError> {
+        manager::delete_dialogue(&mut self.con(), chat_id, user_id).await
+    }
+
+    async fn is_request_limit_exceeded(
+        &self,
+        user_id: &str,
+        time_now: i64,
+    ) -> Result<bool, CrudError> {
+        manager::is_request_limit_exceeded(&mut self.con(), user_id, time_now).await
+    }
+}
@@ -0,0 +1,152 @@
+use super::{CHAT_TRASH_KEY, TRASH_KEY};
+
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
+
+/* Trash CRUD Operations
+ * Trash keeps the payments deleted from a chat, so that they can still be restored.
+ * A trashed payment keeps its payment entry, and stays in the chat payments list,
+ * with the time of deletion and the deleter kept under a separate key.
+ * Each chat has a list of its trashed payments, latest deletion first.
+ * Has add, exists, get, and delete operations, add and delete can also be queued into a pipeline.
+ */
+
+// Moves a payment into the trash of a chat
+// Mainly for testing purposes
+// In application, payments are only trashed through ledger updates
+#[allow(dead_code)]
+pub async fn add_trash(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    payment_id: &str,
+    deleted_at: i64,
+    deleted_by: &str,
+) -> RedisResult<()> {
+    let main_key = format!("{TRASH_KEY}:{payment_id}");
+    con.hset::<_, _, _, ()>(&main_key, "deleted_at", deleted_at)
+        .await?;
+    con.hset::<_, _, _, ()>(&main_key, "deleted_by", deleted_by)
+        .await?;
+    con.lpush(format!("{CHAT_TRASH_KEY}:{chat_id}"), payment_id)
+        .await
+}
+
+// Queues moving a payment into the trash of a chat into a pipeline
+pub fn queue_add_trash(
+    pipe: &mut Pipeline,
+    chat_id: &str,
+    payment_id: &str,
+    deleted_at: i64,
+    deleted_by: &str,
+) {
+    let main_key = format!("{TRASH_KEY}:{payment_id}");
+    pipe.hset(&main_key, "deleted_at", deleted_at).ignore();
+    pipe.hset(&main_key, "deleted_by", deleted_by).ignore();
+    pipe.lpush(format!("{CHAT_TRASH_KEY}:{chat_id}"), payment_id)
+        .ignore();
+}
+
+// Checks if a payment is in the trash
+pub async fn get_trash_exists(con: &mut ConnectionManager, payment_id: &str) -> RedisResult<bool> {
+    con.exists(format!("{TRASH_KEY}:{payment_id}")).await
+}
+
+// Gets the time of deletion and the deleter of a trashed payment
+pub async fn get_trash(
+    con: &mut ConnectionManager,
+    payment_id: &str,
+) -> RedisResult<(i64, String)> {
+    let main_key = format!("{TRASH_KEY}:{payment_id}");
+    let deleted_at: i64 = con.hget(&main_key, "deleted_at").await?;
+    let deleted_by: String = con.hget(&main_key, "deleted_by").await?;
+    Ok((deleted_at, deleted_by))
+}
+
+// Gets all trashed payments of a chat, latest deletion first
+pub async fn get_chat_trash(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<Vec<String>> {
+    con.lrange(format!("{CHAT_TRASH_KEY}:{chat_id}"), 0, -1)
+        .await
+}
+
+// Takes a payment out of the trash of a chat
+// Mainly for testing purposes
+// In application, payments are only taken out of the trash through atomic pipelines
+#[allow(dead_code)]
+pub async fn delete_trash(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+    payment_id: &str,
+) -> RedisResult<()> {
+    con.del::<_, ()>(format!("{TRASH_KEY}:{payment_id}"))
+        .await?;
+    con.lrem(format!("{CHAT_TRASH_KEY}:{chat_id}"), 0, payment_id)
+        .await
+}
+
+// Queues deleting the list of trashed payments of a chat into a pipeline
+// Used when a chat is reset
+pub fn queue_delete_chat_trash(pipe: &mut Pipeline, chat_id: &str) {
+    pipe.del(format!("{CHAT_TRASH_KEY}:{chat_id}")).ignore();
+}
+
+// Checks if a chat has any trashed payments
+pub async fn get_chat_trash_exists(
+    con: &mut ConnectionManager,
+    chat_id: &str,
+) -> RedisResult<bool> {
+    con.exists(format!("{CHAT_TRASH_KEY}:{chat_id}")).await
+}
+
+// Queues moving the list of trashed payments of a chat to a new chat ID into a pipeline
+// Used when a chat is migrated
+// The caller is responsible for checking that the list exists
+pub fn queue_move_chat_trash(pipe: &mut Pipeline, chat_id: &str, new_chat_id: &str) {
+    pipe.rename(
+        format!("{CHAT_TRASH_KEY}:{chat_id}"),
+        format!("{CHAT_TRASH_KEY}:{new_chat_id}"),
+    )
+    .ignore();
+}
+
+// Queues taking a payment out of the trash of a chat into a pipeline
+pub fn queue_delete_trash(pipe: &mut Pipeline, chat_id: &str, payment_id: &str) {
+    pipe.del(format!("{TRASH_KEY}:{payment_id}")).ignore();
+    pipe.lrem(format!("{CHAT_TRASH_KEY}:{chat_id}"), 0, payment_id)
+        .ignore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::redis::connect::connect;
+
+    #[tokio::test]
+    async fn test_add_get_delete_trash() {
+        let mut con = connect().await.unwrap();
+
+        let chat_id = "trash_123456789";
+        let payment_id = "trash_payment_123456789";
+
+        assert!(!get_trash_exists(&mut con, payment_id).await.unwrap());
+        assert!(
+            add_trash(&mut con, chat_id, payment_id, 1700000000, "test_user")
+                .await
+                .is_ok()
+        );
+        assert!(get_trash_exists(&mut con, payment_id).await.unwrap());
+        assert_eq!(
+            get_trash(&mut con, payment_id).await.unwrap(),
+            (1700000000, "test_user".to_string())
+        );
+        assert_eq!(
+            get_chat_trash(&mut con, chat_id).await.unwrap(),
+            vec![payment_id.to_string()]
+        );
+
+        assert!(delete_trash(&mut con, chat_id, payment_id).await.is_ok());
+        assert!(!get_trash_exists(&mut con, payment_id).await.unwrap());
+        assert!(get_chat_trash(&mut con, chat_id).await.unwrap().is_empty());
+    }
+}
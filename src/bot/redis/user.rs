@@ -1,10 +1,13 @@
-use super::{USERNAME_KEY, USER_ID_KEY, USER_KEY};
+use std::collections::HashMap;
 
-use redis::{Commands, Connection, RedisResult};
+use super::{MEMBER_NAME_KEY, USERNAME_KEY, USER_ID_KEY, USER_KEY};
+
+use redis::{aio::ConnectionManager, AsyncCommands, Pipeline, RedisResult};
 
 /* user.rs contains CRUD operations for both `user` and `user_id`.
  * `user` is the main table used for normal operations.
  * `user_id` is used only to ensure the correctness of `user.username`
+ * `member_name` keeps the names of members without a username, for display only
  */
 
 /* User CRUD Operations
@@ -17,78 +20,118 @@ use redis::{Commands, Connection, RedisResult};
 
 // Adds a new user to Redis
 // Initialises user_id if provided
-pub fn add_user(
-    con: &mut Connection,
+pub async fn add_user(
+    con: &mut ConnectionManager,
     username: &str,
     chat_id: &str,
     _user_id: Option<&str>,
 ) -> RedisResult<()> {
-    con.rpush(format!("{USER_KEY}:{username}"), chat_id)
+    con.rpush(format!("{USER_KEY}:{username}"), chat_id).await
 }
 
 // Checks if user exists
-pub fn get_user_exists(con: &mut Connection, username: &str) -> RedisResult<bool> {
-    con.exists(format!("{USER_KEY}:{username}"))
+pub async fn get_user_exists(con: &mut ConnectionManager, username: &str) -> RedisResult<bool> {
+    con.exists(format!("{USER_KEY}:{username}")).await
 }
 
 // Gets user chats from a specified user
-pub fn get_user_chats(con: &mut Connection, username: &str) -> RedisResult<Vec<String>> {
-    con.lrange(format!("{USER_KEY}:{username}"), 0, -1)
+pub async fn get_user_chats(
+    con: &mut ConnectionManager,
+    username: &str,
+) -> RedisResult<Vec<String>> {
+    con.lrange(format!("{USER_KEY}:{username}"), 0, -1).await
 }
 
 // Update user chats with a new chat
 // Automatically checks if chat is already inside
-pub fn update_user_chats(con: &mut Connection, username: &str, chat_id: &str) -> RedisResult<()> {
-    con.rpush(format!("{USER_KEY}:{username}"), chat_id)
+pub async fn update_user_chats(
+    con: &mut ConnectionManager,
+    username: &str,
+    chat_id: &str,
+) -> RedisResult<()> {
+    con.rpush(format!("{USER_KEY}:{username}"), chat_id).await
 }
 
-// Deletes a user from Redis
+// Removes a chat from the chats of a user
 // Mainly for testing purposes
-// In application, no real need to delete keys
+// In application, chats are only removed through atomic pipelines
 #[allow(dead_code)]
-pub fn delete_user(con: &mut Connection, username: &str) -> RedisResult<()> {
-    con.del(format!("{USER_KEY}:{username}"))
+pub async fn delete_user_chat(
+    con: &mut ConnectionManager,
+    username: &str,
+    chat_id: &str,
+) -> RedisResult<()> {
+    con.lrem(format!("{USER_KEY}:{username}"), 0, chat_id).await
 }
 
-/* NOTE: Currently, User ID operations are not supported. The bot will not track User ID.
- *
- * User ID CRUD Operations
- * User ID represents a mapping of user_id to username.
+// Queues removing a chat from the chats of a user into a pipeline
+// Used when a chat is reset or migrated
+pub fn queue_delete_user_chat(pipe: &mut Pipeline, username: &str, chat_id: &str) {
+    pipe.lrem(format!("{USER_KEY}:{username}"), 0, chat_id)
+        .ignore();
+}
+
+// Queues adding a chat to the chats of a user into a pipeline
+// The caller is responsible for checking that the chat is not already added
+pub fn queue_update_user_chats(pipe: &mut Pipeline, username: &str, chat_id: &str) {
+    pipe.rpush(format!("{USER_KEY}:{username}"), chat_id)
+        .ignore();
+}
+
+// Deletes a user from Redis
+// Used when a user asks to be forgotten
+pub async fn delete_user(con: &mut ConnectionManager, username: &str) -> RedisResult<()> {
+    con.del(format!("{USER_KEY}:{username}")).await
+}
+
+// Queues deleting a user into a pipeline
+// Used when a user is renamed
+pub fn queue_delete_user(pipe: &mut Pipeline, username: &str) {
+    pipe.del(format!("{USER_KEY}:{username}")).ignore();
+}
+
+/* User ID CRUD Operations
+ * User ID represents a mapping of user_id to username, as last seen from the user themselves.
+ * It is the canonical identity of a user, used to detect changes in username.
  * Has add, exists, get, update, and delete operations.
  */
 
 // Initialises user with user_id
-#[allow(dead_code)]
-pub fn initialize_user(con: &mut Connection, user_id: &str, username: &str) -> RedisResult<()> {
-    con.set(format!("{USER_ID_KEY}:{user_id}"), username)
+pub async fn initialize_user(
+    con: &mut ConnectionManager,
+    user_id: &str,
+    username: &str,
+) -> RedisResult<()> {
+    con.set(format!("{USER_ID_KEY}:{user_id}"), username).await
 }
 
 // Checks if user is initialised
-#[allow(dead_code)]
-pub fn get_user_is_init(con: &mut Connection, user_id: &str) -> RedisResult<bool> {
-    con.exists(format!("{USER_ID_KEY}:{user_id}"))
+pub async fn get_user_is_init(con: &mut ConnectionManager, user_id: &str) -> RedisResult<bool> {
+    con.exists(format!("{USER_ID_KEY}:{user_id}")).await
 }
 
 // Gets username from a specified user_id
-#[allow(dead_code)]
-pub fn get_username(con: &mut Connection, user_id: &str) -> RedisResult<String> {
-    con.get(format!("{USER_ID_KEY}:{user_id}"))
+pub async fn get_username(con: &mut ConnectionManager, user_id: &str) -> RedisResult<String> {
+    con.get(format!("{USER_ID_KEY}:{user_id}")).await
 }
 
 // Updates username for a specified user_id
 // Only used when user_id is provided, activated when a change in username is detected
 // Otherwise, impossible to detect change in username without user_id
-#[allow(dead_code)]
-pub fn update_username(con: &mut Connection, user_id: &str, username: &str) -> RedisResult<()> {
-    con.set(format!("{USER_ID_KEY}:{user_id}"), username)
+pub async fn update_username(
+    con: &mut ConnectionManager,
+    user_id: &str,
+    username: &str,
+) -> RedisResult<()> {
+    con.set(format!("{USER_ID_KEY}:{user_id}"), username).await
 }
 
 // Deletes a user_id from Redis
 // Mainly for testing purposes
 // In application, no real need to delete keys
 #[allow(dead_code)]
-pub fn delete_user_id(con: &mut Connection, user_id: &str) -> RedisResult<()> {
-    con.del(format!("{USER_ID_KEY}:{user_id}"))
+pub async fn delete_user_id(con: &mut ConnectionManager, user_id: &str) -> RedisResult<()> {
+    con.del(format!("{USER_ID_KEY}:{user_id}")).await
 }
 
 /* Username CRUD Operations
@@ -98,25 +141,90 @@ pub fn delete_user_id(con: &mut Connection, user_id: &str) -> RedisResult<()> {
  */
 
 // Sets the preferred username of a user
-pub fn set_preferred_username(
-    con: &mut Connection,
+pub async fn set_preferred_username(
+    con: &mut ConnectionManager,
     username: &str,
     user_key: &str,
 ) -> RedisResult<()> {
     con.set(format!("{USERNAME_KEY}:{user_key}"), username)
+        .await
+}
+
+// Queues setting the preferred username of a user into a pipeline
+pub fn queue_set_preferred_username(pipe: &mut Pipeline, username: &str, user_key: &str) {
+    pipe.set(format!("{USERNAME_KEY}:{user_key}"), username)
+        .ignore();
 }
 
 // Gets the preferred username of a user
-pub fn get_preferred_username(con: &mut Connection, user_key: &str) -> RedisResult<String> {
-    con.get(format!("{USERNAME_KEY}:{user_key}"))
+pub async fn get_preferred_username(
+    con: &mut ConnectionManager,
+    user_key: &str,
+) -> RedisResult<String> {
+    con.get(format!("{USERNAME_KEY}:{user_key}")).await
 }
 
 // Deletes the preferred username of a user
-// Mainly for testing purposes
-// In application, no real need to delete keys
-#[allow(dead_code)]
-pub fn delete_preferred_username(con: &mut Connection, user_key: &str) -> RedisResult<()> {
-    con.del(format!("{USERNAME_KEY}:{user_key}"))
+// Used when a user asks to be forgotten
+pub async fn delete_preferred_username(
+    con: &mut ConnectionManager,
+    user_key: &str,
+) -> RedisResult<()> {
+    con.del(format!("{USERNAME_KEY}:{user_key}")).await
+}
+
+// Queues deleting the preferred username of a user into a pipeline
+// Used when a user is renamed
+pub fn queue_delete_preferred_username(pipe: &mut Pipeline, user_key: &str) {
+    pipe.del(format!("{USERNAME_KEY}:{user_key}")).ignore();
+}
+
+/* Member Name CRUD Operations
+ * Member name represents the name of a Telegram user without a username, kept apart from the
+ * member itself, which is only their user ID, so that a change in name changes nothing else.
+ * All names are kept in a single hash, by member.
+ * Has set, get, and delete operations.
+ */
+
+// Sets the name of a member, replacing any previous one
+pub async fn set_member_name(
+    con: &mut ConnectionManager,
+    member: &str,
+    name: &str,
+) -> RedisResult<()> {
+    con.hset(MEMBER_NAME_KEY, member, name).await
+}
+
+// Gets the names of the given members, by member. Members without a name are left out.
+pub async fn get_member_names(
+    con: &mut ConnectionManager,
+    members: &[String],
+) -> RedisResult<HashMap<String, String>> {
+    if members.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let names: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(MEMBER_NAME_KEY)
+        .arg(members)
+        .query_async(con)
+        .await?;
+    Ok(members
+        .iter()
+        .zip(names)
+        .filter_map(|(member, name)| Some((member.clone(), name?)))
+        .collect())
+}
+
+// Deletes the name of a member
+// Used when a user asks to be forgotten
+pub async fn delete_member_name(con: &mut ConnectionManager, member: &str) -> RedisResult<()> {
+    con.hdel(MEMBER_NAME_KEY, member).await
+}
+
+// Queues deleting the name of a member into a pipeline
+// Used when a user is renamed
+pub fn queue_delete_member_name(pipe: &mut Pipeline, member: &str) {
+    pipe.hdel(MEMBER_NAME_KEY, member).ignore();
 }
 
 // Tests
@@ -125,153 +233,164 @@ mod tests {
     use super::*;
     use crate::bot::redis::connect::connect;
 
-    #[test]
-    fn test_add_user_all() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_user_all() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_all";
         let user_id = "123456789";
         let chat_id = "9876543210";
-        assert!(add_user(&mut con, username, chat_id, Some(user_id),).is_ok());
+        assert!(add_user(&mut con, username, chat_id, Some(user_id),)
+            .await
+            .is_ok());
 
-        delete_user(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_add_user_no_id() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_add_user_no_id() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_no_id";
         let chat_id = "9876543211";
-        assert!(add_user(&mut con, username, chat_id, None).is_ok());
+        assert!(add_user(&mut con, username, chat_id, None).await.is_ok());
 
-        delete_user(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_get_user_exists_chat() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_user_exists_chat() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_exists";
         let chat_id = "9876543212";
-        add_user(&mut con, username, chat_id, None).unwrap();
-        assert!(get_user_exists(&mut con, username).unwrap());
-        assert!(get_user_chats(&mut con, username).unwrap() == vec![chat_id]);
+        add_user(&mut con, username, chat_id, None).await.unwrap();
+        assert!(get_user_exists(&mut con, username).await.unwrap());
+        assert!(get_user_chats(&mut con, username).await.unwrap() == vec![chat_id]);
 
-        delete_user(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_update_chats() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_chats() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_update_chats";
         let chat_id = "9876543214";
         let new_chat_id = "9876543215";
-        add_user(&mut con, username, chat_id, None).unwrap();
-        assert_eq!(get_user_chats(&mut con, username).unwrap(), vec![chat_id]);
+        add_user(&mut con, username, chat_id, None).await.unwrap();
+        assert_eq!(
+            get_user_chats(&mut con, username).await.unwrap(),
+            vec![chat_id]
+        );
 
-        update_user_chats(&mut con, username, new_chat_id).unwrap();
+        update_user_chats(&mut con, username, new_chat_id)
+            .await
+            .unwrap();
         assert_eq!(
-            get_user_chats(&mut con, username).unwrap(),
+            get_user_chats(&mut con, username).await.unwrap(),
             vec![chat_id, new_chat_id]
         );
 
-        delete_user(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_delete_user() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_delete";
         let chat_id = "9876543216";
-        add_user(&mut con, username, chat_id, None).unwrap();
-        assert!(get_user_exists(&mut con, username).unwrap());
-        delete_user(&mut con, username).unwrap();
-        assert!(!get_user_exists(&mut con, username).unwrap());
+        add_user(&mut con, username, chat_id, None).await.unwrap();
+        assert!(get_user_exists(&mut con, username).await.unwrap());
+        delete_user(&mut con, username).await.unwrap();
+        assert!(!get_user_exists(&mut con, username).await.unwrap());
     }
 
     /*
-    #[test]
-    fn test_initialize_get_user() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_initialize_get_user() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_initialize";
         let user_id = "1234567895";
-        assert!(initialize_user(&mut con, user_id, username).is_ok());
-        assert!(get_username(&mut con, user_id).unwrap() == username);
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
+        assert!(initialize_user(&mut con, user_id, username).await.is_ok());
+        assert!(get_username(&mut con, user_id).await.unwrap() == username);
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
 
-        delete_user_id(&mut con, user_id).unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
     }
 
-    #[test]
-    fn test_get_user_is_not_init() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_user_is_not_init() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_get_is_not_init";
         let chat_id = "9876543217";
-        add_user(&mut con, username, chat_id, None).unwrap();
-        assert!(!get_user_is_init(&mut con, username).unwrap());
+        add_user(&mut con, username, chat_id, None).await.unwrap();
+        assert!(!get_user_is_init(&mut con, username).await.unwrap());
 
-        delete_user(&mut con, username).unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_get_user_auto_init() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_get_user_auto_init() {
+        let mut con = connect().await.unwrap();
 
         let username = "test_user_auto_init";
         let user_id = "1234567894";
         let chat_id = "9876543218";
-        add_user(&mut con, username, chat_id, Some(user_id)).unwrap();
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
+        add_user(&mut con, username, chat_id, Some(user_id)).await.unwrap();
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
 
-        delete_user_id(&mut con, user_id).unwrap();
-        delete_user(&mut con, username).unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
+        delete_user(&mut con, username).await.unwrap();
     }
 
-    #[test]
-    fn test_update_username() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_update_username() {
+        let mut con = connect().await.unwrap();
 
         let user_id = "1234567896";
         let old_username = "test_user_update_username";
         let new_username = "test_user_update_username_new";
-        initialize_user(&mut con, user_id, old_username).unwrap();
-        assert_eq!(get_username(&mut con, user_id).unwrap(), old_username);
+        initialize_user(&mut con, user_id, old_username).await.unwrap();
+        assert_eq!(get_username(&mut con, user_id).await.unwrap(), old_username);
 
-        update_username(&mut con, user_id, new_username).unwrap();
-        assert_eq!(get_username(&mut con, user_id).unwrap(), new_username);
+        update_username(&mut con, user_id, new_username).await.unwrap();
+        assert_eq!(get_username(&mut con, user_id).await.unwrap(), new_username);
 
-        delete_user_id(&mut con, user_id).unwrap();
+        delete_user_id(&mut con, user_id).await.unwrap();
     }
 
-    #[test]
-    fn test_delete_user_id() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_delete_user_id() {
+        let mut con = connect().await.unwrap();
 
         let user_id = "1234567897";
         let username = "test_user_delete_user_id";
-        initialize_user(&mut con, user_id, username).unwrap();
-        assert!(get_user_is_init(&mut con, user_id).unwrap());
-        delete_user_id(&mut con, user_id).unwrap();
-        assert!(!get_user_is_init(&mut con, user_id).unwrap());
+        initialize_user(&mut con, user_id, username).await.unwrap();
+        assert!(get_user_is_init(&mut con, user_id).await.unwrap());
+        delete_user_id(&mut con, user_id).await.unwrap();
+        assert!(!get_user_is_init(&mut con, user_id).await.unwrap());
     }
     */
 
-    #[test]
-    fn test_set_get_delete_preferred_username() {
-        let mut con = connect().unwrap();
+    #[tokio::test]
+    async fn test_set_get_delete_preferred_username() {
+        let mut con = connect().await.unwrap();
         let username = "Test_User_Preferred_Username";
         let user_key = username.to_lowercase();
-        assert!(set_preferred_username(&mut con, username, &user_key).is_ok());
+        assert!(set_preferred_username(&mut con, username, &user_key)
+            .await
+            .is_ok());
         assert_eq!(
-            get_preferred_username(&mut con, &user_key).unwrap(),
+            get_preferred_username(&mut con, &user_key).await.unwrap(),
             username
         );
 
-        delete_preferred_username(&mut con, &user_key).unwrap();
+        delete_preferred_username(&mut con, &user_key)
+            .await
+            .unwrap();
     }
 }
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use teloxide::{prelude::*, RequestError};
+use tokio::time::MissedTickBehavior;
+
+use super::{
+    handlers::unfold_payment,
+    processor::{add_due_recurring_payment, get_due_recurring_payments, RecurringUpdate},
+    redis::UserPayment,
+    store::Store,
+    utils::{
+        format::{
+            balance_members, display_balance_header, display_balances, display_payment,
+            get_member_names, payment_members,
+        },
+        time::{format_timestamp, retrieve_time_zone},
+    },
+};
+
+/* Scheduler runs in the background for as long as the bot does.
+ * Every minute, it adds the recurring payments which are due to their group chats,
+ * and posts a notice in each chat with the payments added and the updated balances.
+ */
+
+// Seconds between runs of the scheduler
+const SCHEDULER_INTERVAL_SECS: u64 = 60;
+
+pub async fn run_scheduler(bot: Bot, store: Store) {
+    let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULER_INTERVAL_SECS));
+    // A slow run pushes back the next one, rather than running several at once
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        add_due_payments(&bot, &store).await;
+    }
+}
+
+// Adds every recurring payment which is due, and notifies its chat
+// A recurring payment that fails is logged, and does not stop the others
+async fn add_due_payments(bot: &Bot, store: &Store) {
+    let now = chrono::Utc::now().timestamp();
+    let due = match get_due_recurring_payments(store, now).await {
+        Ok(due) => due,
+        Err(err) => {
+            log::error!("Scheduler - Failed to get due recurring payments: {}", err);
+            return;
+        }
+    };
+
+    for recurring in due {
+        let recurring_id = recurring.recurring_id.clone();
+        let chat_id = recurring.chat_id.clone();
+        match add_due_recurring_payment(store, recurring, now).await {
+            Ok(update) => {
+                log::info!(
+                    "Scheduler - Added {} payments for recurring payment {} in chat {}",
+                    update.payments.len(),
+                    recurring_id,
+                    chat_id
+                );
+                if let Err(err) = notify_chat(bot, store, &update).await {
+                    log::error!(
+                        "Scheduler - Failed to notify chat {} of recurring payment {}: {}",
+                        chat_id,
+                        recurring_id,
+                        err
+                    );
+                }
+            }
+            Err(err) => {
+                log::error!(
+                    "Scheduler - Failed to add recurring payment {} in chat {}: {}",
+                    recurring_id,
+                    chat_id,
+                    err
+                );
+            }
+        }
+    }
+}
+
+// Posts the payments added for a recurring payment in its chat, with the updated balances
+async fn notify_chat(
+    bot: &Bot,
+    store: &Store,
+    update: &RecurringUpdate,
+) -> Result<(), RequestError> {
+    let (last, chat) = match (update.payments.last(), update.chat_id.parse::<i64>()) {
+        (Some(last), Ok(chat)) => (last, ChatId(chat)),
+        _ => return Ok(()),
+    };
+    let time_zone = retrieve_time_zone(store, &update.chat_id).await;
+    let mut members = balance_members(&update.debts);
+    for payment in &update.payments {
+        members.extend(payment_members(
+            &payment.creditor,
+            &payment.payers,
+            &payment.debts,
+            &payment.items,
+        ));
+    }
+    let names = get_member_names(store, members).await;
+
+    let mut formatted_payments = Vec::new();
+    for (index, payment) in update.payments.iter().enumerate() {
+        let payment = unfold_payment(UserPayment {
+            chat_id: update.chat_id.clone(),
+            payment_id: update.recurring_id.clone(),
+            payment: payment.clone(),
+        });
+        formatted_payments
+            .push(display_payment(store, &payment, index + 1, time_zone, &names).await);
+    }
+    let next = match update.next {
+        Some(next) => format!(
+            "It's next due on {}.",
+            format_timestamp(next, time_zone).trim()
+        ),
+        None => "That was the last one, so I've stopped repeating it.".to_string(),
+    };
+
+    bot.send_message(
+        chat,
+        format!(
+            "🔁 I added a recurring payment!\n\n{}\n{next}",
+            formatted_payments.join("")
+        ),
+    )
+    .await?;
+    bot.send_message(
+        chat,
+        format!(
+            "{}{}",
+            display_balance_header(store, &update.chat_id, &last.currency).await,
+            display_balances(&update.debts, &names)
+        ),
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,319 @@
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+
+use rusqlite::{params, Connection, Result};
+
+use crate::bot::redis::{
+    migrate_legacy_event, parse_legacy_datetime, CategoryTotal, DatetimeMigration,
+    MIGRATION_DATETIME_FALLBACK,
+};
+
+use super::schema::SCHEMA;
+
+// Opens a SQLite database at the given path, creating tables if they do not exist
+// Databases created by an older version are migrated before the schema is applied
+pub fn connect(path: &str) -> Result<Connection> {
+    let mut con = Connection::open(path)?;
+
+    // Foreign keys are off by default in SQLite, and must be enabled per connection
+    con.pragma_update(None, "foreign_keys", true)?;
+    migrate_datetimes(&mut con)?.log("SQLite Store");
+    let has_categories = has_table(&con, "chat_categories")?;
+    con.execute_batch(SCHEMA)?;
+    if !has_categories {
+        let migrated = migrate_categories(&mut con)?;
+        if migrated > 0 {
+            log::info!("SQLite Store - Migrated category totals of {migrated} chats");
+        }
+    }
+
+    Ok(con)
+}
+
+/* Migrates payments and their history from formatted datetimes to UTC timestamps.
+ * Same as the Redis migration, datetimes that cannot be parsed are given
+ * MIGRATION_DATETIME_FALLBACK, and events that cannot be read at all are skipped.
+ * Payments are migrated once, when the datetime column is dropped. Events are migrated
+ * on every connect while any of them still has a datetime, so skipped events are retried.
+ */
+fn migrate_datetimes(con: &mut Connection) -> Result<DatetimeMigration> {
+    let mut migration = DatetimeMigration::default();
+    let is_legacy: bool = con.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info('payments') WHERE name = 'datetime'",
+        [],
+        |row| row.get(0),
+    )?;
+    let has_events = has_table(con, "payment_events")?;
+
+    let tx = con.transaction()?;
+    if is_legacy {
+        tx.execute_batch(
+            "DROP INDEX IF EXISTS payments_time_index;
+             ALTER TABLE payments ADD COLUMN timestamp INTEGER NOT NULL DEFAULT 0;",
+        )?;
+
+        let payments = tx
+            .prepare("SELECT payment_id, datetime FROM payments")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>>>()?;
+        for (payment_id, datetime) in payments {
+            let timestamp = parse_legacy_datetime(&datetime).unwrap_or_else(|| {
+                migration
+                    .fallbacks
+                    .push(format!("{datetime} of payment {payment_id}"));
+                MIGRATION_DATETIME_FALLBACK
+            });
+            tx.execute(
+                "UPDATE payments SET timestamp = ?1 WHERE payment_id = ?2",
+                params![timestamp, payment_id],
+            )?;
+            migration.migrated += 1;
+        }
+        tx.execute_batch("ALTER TABLE payments DROP COLUMN datetime")?;
+    }
+
+    // Only events with a datetime key need migrating, which keeps later connects cheap
+    if has_events {
+        let events = tx
+            .prepare("SELECT rowid, event FROM payment_events WHERE event LIKE '%\"datetime\"%'")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(i64, String)>>>()?;
+        for (rowid, event) in events {
+            match migrate_legacy_event(&event) {
+                Ok(Some((event, fallbacks))) => {
+                    tx.execute(
+                        "UPDATE payment_events SET event = ?1 WHERE rowid = ?2",
+                        params![event, rowid],
+                    )?;
+                    migration.fallbacks.extend(
+                        fallbacks
+                            .into_iter()
+                            .map(|datetime| format!("{datetime} of event {rowid}")),
+                    );
+                }
+                Ok(None) => {}
+                Err(err) => migration.skipped.push(format!("event {rowid}: {err}")),
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(migration)
+}
+
+// Checks if a table exists, to tell which migrations a database needs
+fn has_table(con: &Connection, table: &str) -> Result<bool> {
+    con.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        params![table],
+        |row| row.get(0),
+    )
+}
+
+/* Migrates every chat to keep the spending on each category of its payments.
+ * Same as the Redis migration, totals are summed from the payments not in the trash.
+ * Runs once, when the table of category totals is created, as totals are kept from then on.
+ * Returns the number of chats migrated.
+ */
+fn migrate_categories(con: &mut Connection) -> Result<usize> {
+    let tx = con.transaction()?;
+    let payments = tx
+        .prepare(
+            "SELECT p.chat_id, c.category, p.currency, p.timestamp,
+                 (SELECT COALESCE(SUM(d.amount), 0) FROM payment_debts d
+                  WHERE d.payment_id = p.payment_id)
+             FROM payments p
+             LEFT JOIN payment_categories c ON c.payment_id = p.payment_id
+             WHERE p.payment_id NOT IN (SELECT payment_id FROM trashed_payments)
+             ORDER BY p.timestamp",
+        )?
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                CategoryTotal {
+                    category: row.get(1)?,
+                    currency: row.get(2)?,
+                    latest: row.get(3)?,
+                    spending: row.get(4)?,
+                },
+            ))
+        })?
+        .collect::<Result<Vec<(String, CategoryTotal)>>>()?;
+
+    // Categories are merged in Rust, as SQLite only lowercases ASCII
+    // Payments are read earliest first, so each category keeps the name it was first given
+    let mut totals: HashMap<(String, String, String), CategoryTotal> = HashMap::new();
+    for (chat_id, change) in payments {
+        match totals.entry((chat_id, change.key(), change.currency.clone())) {
+            Entry::Occupied(mut total) => total.get_mut().add(&change),
+            Entry::Vacant(total) => {
+                total.insert(change);
+            }
+        }
+    }
+
+    let mut chat_ids: HashSet<&str> = HashSet::new();
+    for ((chat_id, _, _), total) in totals.iter().filter(|(_, total)| total.spending != 0) {
+        tx.execute(
+            "INSERT INTO chat_categories (chat_id, category_key, currency, category, spending, latest)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                chat_id,
+                total.key(),
+                total.currency,
+                total.category,
+                total.spending,
+                total.latest
+            ],
+        )?;
+        chat_ids.insert(chat_id);
+    }
+
+    let migrated = chat_ids.len();
+    tx.commit()?;
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests that the schema applies cleanly, and can be applied twice
+    #[test]
+    fn test_connection() {
+        let con = connect(":memory:").unwrap();
+        assert!(con.execute_batch(super::SCHEMA).is_ok());
+
+        let foreign_keys: bool = con
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))
+            .unwrap();
+        assert!(foreign_keys);
+    }
+
+    // Tests that payments and events with formatted datetimes are migrated to timestamps
+    #[test]
+    fn test_migrate_datetimes() {
+        let mut con = Connection::open_in_memory().unwrap();
+        con.execute_batch(
+            "CREATE TABLE payments (
+                 payment_id TEXT PRIMARY KEY,
+                 chat_id TEXT NOT NULL,
+                 description TEXT NOT NULL,
+                 datetime TEXT NOT NULL,
+                 creditor TEXT NOT NULL,
+                 currency TEXT NOT NULL,
+                 total INTEGER NOT NULL
+             );
+             CREATE INDEX payments_time_index ON payments (chat_id, datetime, payment_id);
+             CREATE TABLE payment_events (
+                 chat_id TEXT NOT NULL,
+                 payment_id TEXT NOT NULL,
+                 event TEXT NOT NULL
+             );
+             INSERT INTO payments VALUES
+                 ('payment', 'chat', 'test_payment', '2024-01-01 00:00:00 UTC', 'Test_User', 'USD', 100);
+             INSERT INTO payment_events VALUES
+                 ('chat', 'payment', '{\"before\":null,\"after\":{\"datetime\":\"2024-01-01 00:00:00 UTC\"}}');",
+        )
+        .unwrap();
+
+        assert_eq!(migrate_datetimes(&mut con).unwrap().migrated, 1);
+        con.execute_batch(SCHEMA).unwrap();
+
+        let timestamp: i64 = con
+            .query_row("SELECT timestamp FROM payments", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(timestamp, 1704067200);
+        let event: String = con
+            .query_row("SELECT event FROM payment_events", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            event,
+            "{\"after\":{\"timestamp\":1704067200},\"before\":null}"
+        );
+
+        // Migrating again does nothing
+        assert_eq!(
+            migrate_datetimes(&mut con).unwrap(),
+            DatetimeMigration::default()
+        );
+    }
+
+    // Tests that category totals are summed from the payments not in the trash
+    #[test]
+    fn test_migrate_categories() {
+        let mut con = connect(":memory:").unwrap();
+        con.execute_batch(
+            "DROP TABLE chat_categories;
+             INSERT INTO chats VALUES ('chat');
+             INSERT INTO payments VALUES
+                 ('first', 'chat', 'test_payment', 1, 'Test_User', 'USD', 100),
+                 ('second', 'chat', 'test_payment', 2, 'Test_User', 'USD', 200),
+                 ('trashed', 'chat', 'test_payment', 3, 'Test_User', 'USD', 400);
+             INSERT INTO payment_debts VALUES
+                 ('first', 0, 'Test_User', 100),
+                 ('second', 0, 'Test_User', 150),
+                 ('second', 1, 'Other_User', 50),
+                 ('trashed', 0, 'Test_User', 400);
+             INSERT INTO payment_categories VALUES
+                 ('first', 'Food'), ('second', 'food'), ('trashed', 'Food');
+             INSERT INTO trashed_payments VALUES ('trashed', 'chat', 4, 'Test_User');",
+        )
+        .unwrap();
+        con.execute_batch(SCHEMA).unwrap();
+
+        assert_eq!(migrate_categories(&mut con).unwrap(), 1);
+        let total = con
+            .query_row(
+                "SELECT category, currency, spending, latest FROM chat_categories",
+                [],
+                |row| {
+                    Ok(CategoryTotal {
+                        category: row.get(0)?,
+                        currency: row.get(1)?,
+                        spending: row.get(2)?,
+                        latest: row.get(3)?,
+                    })
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            total,
+            CategoryTotal {
+                category: Some("Food".to_string()),
+                currency: "USD".to_string(),
+                spending: 300,
+                latest: 2,
+            }
+        );
+    }
+
+    // Tests that unparseable datetimes are given the fallback, and unreadable events are retried
+    #[test]
+    fn test_migrate_invalid_datetime() {
+        let mut con = Connection::open_in_memory().unwrap();
+        con.execute_batch(
+            "CREATE TABLE payments (payment_id TEXT PRIMARY KEY, datetime TEXT NOT NULL);
+             CREATE TABLE payment_events (event TEXT NOT NULL);
+             INSERT INTO payments VALUES ('payment', 'yesterday');
+             INSERT INTO payment_events VALUES
+                 ('{\"before\":null,\"after\":{\"datetime\":\"yesterday\"}}'),
+                 ('not an event with a \"datetime\"');",
+        )
+        .unwrap();
+
+        let migration = migrate_datetimes(&mut con).unwrap();
+        assert_eq!(migration.migrated, 1);
+        assert_eq!(migration.fallbacks.len(), 2);
+        assert_eq!(migration.skipped.len(), 1);
+        let timestamp: i64 = con
+            .query_row("SELECT timestamp FROM payments", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(timestamp, MIGRATION_DATETIME_FALLBACK);
+
+        // Only the skipped event is tried again
+        let migration = migrate_datetimes(&mut con).unwrap();
+        assert_eq!(migration.migrated, 0);
+        assert!(migration.fallbacks.is_empty());
+        assert_eq!(migration.skipped.len(), 1);
+    }
+}
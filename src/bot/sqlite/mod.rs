@@ -0,0 +1,9 @@
+pub use crate::bot::constants::sqlite::*;
+
+// Exported structs and types
+pub use self::store::SqliteStore;
+
+// Submodules
+mod connect;
+mod schema;
+mod store;
@@ -0,0 +1,158 @@
+/* SQLite Schema
+ * Tables mirror the keys used by the Redis backend:
+ * user, member_name, chat, chat_setting, chat_currency, payment, payment_debt, trash, event,
+ * recurring, balance, spending, chat_category, request. Items, categories and payers of payments are kept in the
+ * payment hash in Redis, and in their own tables here.
+ * Recurring payments are kept whole as JSON, same as in Redis.
+ * Every statement is idempotent, so the schema is applied on every connection.
+ */
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS users (
+    user_key TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    user_id TEXT
+);
+
+CREATE TABLE IF NOT EXISTS member_names (
+    member TEXT PRIMARY KEY,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS chats (
+    chat_id TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS chat_users (
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    user_key TEXT NOT NULL REFERENCES users (user_key) ON DELETE CASCADE,
+    PRIMARY KEY (chat_id, user_key)
+);
+
+CREATE TABLE IF NOT EXISTS chat_settings (
+    chat_id TEXT PRIMARY KEY REFERENCES chats (chat_id) ON DELETE CASCADE,
+    time_zone TEXT,
+    default_currency TEXT,
+    currency_conversion INTEGER,
+    erase_messages INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS chat_currencies (
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (chat_id, currency)
+);
+
+CREATE TABLE IF NOT EXISTS payments (
+    payment_id TEXT PRIMARY KEY,
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    description TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    creditor TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    total INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS payments_chat_index ON payments (chat_id);
+CREATE INDEX IF NOT EXISTS payments_time_index ON payments (chat_id, timestamp, payment_id);
+
+CREATE TABLE IF NOT EXISTS payment_debts (
+    payment_id TEXT NOT NULL REFERENCES payments (payment_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    debtor TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (payment_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS payment_items (
+    payment_id TEXT NOT NULL REFERENCES payments (payment_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    description TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    participants TEXT NOT NULL,
+    PRIMARY KEY (payment_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS payment_categories (
+    payment_id TEXT PRIMARY KEY REFERENCES payments (payment_id) ON DELETE CASCADE,
+    category TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS payment_payers (
+    payment_id TEXT NOT NULL REFERENCES payments (payment_id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    payer TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    PRIMARY KEY (payment_id, position)
+);
+
+CREATE TABLE IF NOT EXISTS trashed_payments (
+    payment_id TEXT PRIMARY KEY REFERENCES payments (payment_id) ON DELETE CASCADE,
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    deleted_at INTEGER NOT NULL,
+    deleted_by TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS trashed_payments_chat_index ON trashed_payments (chat_id);
+
+CREATE TABLE IF NOT EXISTS payment_events (
+    chat_id TEXT NOT NULL,
+    payment_id TEXT NOT NULL,
+    event TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS payment_events_chat_index ON payment_events (chat_id);
+CREATE INDEX IF NOT EXISTS payment_events_payment_index ON payment_events (payment_id);
+
+CREATE TABLE IF NOT EXISTS recurring_payments (
+    recurring_id TEXT PRIMARY KEY,
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    next INTEGER NOT NULL,
+    recurring TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS recurring_payments_chat_index ON recurring_payments (chat_id);
+CREATE INDEX IF NOT EXISTS recurring_payments_next_index ON recurring_payments (next);
+
+CREATE TABLE IF NOT EXISTS balances (
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    user_key TEXT NOT NULL REFERENCES users (user_key) ON DELETE CASCADE,
+    currency TEXT NOT NULL,
+    balance INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, user_key, currency)
+);
+
+CREATE INDEX IF NOT EXISTS balances_currency_index ON balances (chat_id, currency);
+
+CREATE TABLE IF NOT EXISTS spendings (
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    user_key TEXT NOT NULL REFERENCES users (user_key) ON DELETE CASCADE,
+    currency TEXT NOT NULL,
+    spending INTEGER NOT NULL CHECK (spending >= 0),
+    PRIMARY KEY (chat_id, user_key, currency)
+);
+
+CREATE INDEX IF NOT EXISTS spendings_currency_index ON spendings (chat_id, currency);
+
+CREATE TABLE IF NOT EXISTS chat_categories (
+    chat_id TEXT NOT NULL REFERENCES chats (chat_id) ON DELETE CASCADE,
+    category_key TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    category TEXT,
+    spending INTEGER NOT NULL CHECK (spending >= 0),
+    latest INTEGER NOT NULL,
+    PRIMARY KEY (chat_id, category_key, currency)
+);
+
+CREATE TABLE IF NOT EXISTS dialogues (
+    chat_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    state TEXT NOT NULL,
+    PRIMARY KEY (chat_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS requests (
+    user_id TEXT PRIMARY KEY,
+    timestamp INTEGER NOT NULL
+);
+";
@@ -0,0 +1,2895 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, ToSql};
+use uuid::Uuid;
+
+use crate::bot::{
+    redis::{
+        CategoryTotal, ChatRecurringPayment, CrudError, LedgerUpdate, Payment, PaymentAction,
+        PaymentChange, PaymentCursor, PaymentEvent, PaymentItem, PaymentPage, PaymentQuery,
+        RecurringChange, RecurringPayment, TrashedPayment, UserBalance, UserPayment,
+        CURRENCY_CODE_DEFAULT,
+    },
+    store::LedgerStore,
+};
+
+use super::connect::connect;
+
+/* SQLite Store
+ * SqliteStore keeps the ledger in a single SQLite database file.
+ * It is meant for small self-hosted deployments that do not run Redis.
+ * Behaviour mirrors the Redis manager, and multi-step writes run in a transaction.
+ * Queries block, so they are run on the blocking thread pool instead of the async workers.
+ */
+
+#[derive(Debug)]
+pub struct SqliteStore {
+    con: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    // Opens the database at the given path. ":memory:" opens a temporary database
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        Ok(SqliteStore {
+            con: Arc::new(Mutex::new(connect(path)?)),
+        })
+    }
+
+    // Runs a task on the connection in the blocking thread pool, one task at a time
+    async fn run<T, F>(&self, task: F) -> Result<T, CrudError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, CrudError> + Send + 'static,
+    {
+        let con = Arc::clone(&self.con);
+        tokio::task::spawn_blocking(move || {
+            // A poisoned lock only means another thread panicked mid-operation
+            let mut con = con.lock().unwrap_or_else(|err| err.into_inner());
+            task(&mut con)
+        })
+        .await
+        .map_err(|err| CrudError::TaskError(err.to_string()))?
+    }
+}
+
+// Adds a chat, a user and their membership if not exists
+fn ensure_chat_user(con: &Connection, chat_id: &str, username: &str) -> rusqlite::Result<()> {
+    let user_key = username.to_lowercase();
+    con.execute(
+        "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+        params![chat_id],
+    )?;
+    con.execute(
+        "INSERT OR IGNORE INTO users (user_key, username) VALUES (?1, ?2)",
+        params![user_key, username],
+    )?;
+    con.execute(
+        "INSERT OR IGNORE INTO chat_users (chat_id, user_key) VALUES (?1, ?2)",
+        params![chat_id, user_key],
+    )?;
+    Ok(())
+}
+
+// Sets a single chat setting column, adding the chat if not exists
+fn set_setting(
+    con: &Connection,
+    chat_id: &str,
+    column: &str,
+    value: &dyn ToSql,
+) -> rusqlite::Result<()> {
+    con.execute(
+        "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+        params![chat_id],
+    )?;
+    con.execute(
+        &format!(
+            "INSERT INTO chat_settings (chat_id, {column}) VALUES (?1, ?2)
+             ON CONFLICT (chat_id) DO UPDATE SET {column} = excluded.{column}"
+        ),
+        params![chat_id, value],
+    )?;
+    Ok(())
+}
+
+// Gets a single chat setting column, None if not set
+fn get_setting<T: rusqlite::types::FromSql>(
+    con: &Connection,
+    chat_id: &str,
+    column: &str,
+) -> rusqlite::Result<Option<T>> {
+    let value: Option<Option<T>> = con
+        .query_row(
+            &format!("SELECT {column} FROM chat_settings WHERE chat_id = ?1"),
+            params![chat_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.flatten())
+}
+
+// Gets all currencies of a chat, in the order they were added
+fn get_chat_currencies(con: &Connection, chat_id: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt =
+        con.prepare("SELECT currency FROM chat_currencies WHERE chat_id = ?1 ORDER BY rowid")?;
+    let currencies = stmt
+        .query_map(params![chat_id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(currencies)
+}
+
+// Gets all non-zero amounts of a ledger table for a currency, in the order users joined the chat
+fn get_ledger_currency(
+    con: &Connection,
+    table: &str,
+    column: &str,
+    chat_id: &str,
+    currency: &str,
+) -> rusqlite::Result<Vec<UserBalance>> {
+    let mut stmt = con.prepare(&format!(
+        "SELECT users.username, {table}.{column} FROM {table}
+         JOIN users ON users.user_key = {table}.user_key
+         JOIN chat_users ON chat_users.chat_id = {table}.chat_id
+             AND chat_users.user_key = {table}.user_key
+         WHERE {table}.chat_id = ?1 AND {table}.currency = ?2 AND {table}.{column} != 0
+         ORDER BY chat_users.rowid"
+    ))?;
+    let amounts = stmt
+        .query_map(params![chat_id, currency], |row| {
+            Ok(UserBalance {
+                username: row.get(0)?,
+                currency: currency.to_string(),
+                balance: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<UserBalance>>>()?;
+    Ok(amounts)
+}
+
+// Gets a payment with its debts, None if not exists
+fn get_payment(con: &Connection, payment_id: &str) -> rusqlite::Result<Option<Payment>> {
+    let payment = con
+        .query_row(
+            "SELECT description, timestamp, creditor, currency, total FROM payments
+             WHERE payment_id = ?1",
+            params![payment_id],
+            |row| {
+                Ok(Payment {
+                    description: row.get(0)?,
+                    timestamp: row.get(1)?,
+                    creditor: row.get(2)?,
+                    currency: row.get(3)?,
+                    total: row.get(4)?,
+                    debts: Vec::new(),
+                    items: Vec::new(),
+                    category: None,
+                    payers: Vec::new(),
+                })
+            },
+        )
+        .optional()?;
+
+    let mut payment = match payment {
+        Some(payment) => payment,
+        None => return Ok(None),
+    };
+
+    let mut stmt = con.prepare(
+        "SELECT debtor, amount FROM payment_debts WHERE payment_id = ?1 ORDER BY position",
+    )?;
+    payment.debts = stmt
+        .query_map(params![payment_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, i64)>>>()?;
+
+    let mut stmt = con.prepare(
+        "SELECT description, amount, participants FROM payment_items
+         WHERE payment_id = ?1 ORDER BY position",
+    )?;
+    payment.items = stmt
+        .query_map(params![payment_id], |row| {
+            let participants: String = row.get(2)?;
+            Ok(PaymentItem {
+                description: row.get(0)?,
+                amount: row.get(1)?,
+                participants: participants.split_whitespace().map(String::from).collect(),
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<PaymentItem>>>()?;
+
+    payment.category = con
+        .query_row(
+            "SELECT category FROM payment_categories WHERE payment_id = ?1",
+            params![payment_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    let mut stmt = con.prepare(
+        "SELECT payer, amount FROM payment_payers WHERE payment_id = ?1 ORDER BY position",
+    )?;
+    payment.payers = stmt
+        .query_map(params![payment_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, i64)>>>()?;
+
+    Ok(Some(payment))
+}
+
+// Replaces all debts of a payment
+fn set_payment_debts(
+    con: &Connection,
+    payment_id: &str,
+    debts: &[(String, i64)],
+) -> rusqlite::Result<()> {
+    con.execute(
+        "DELETE FROM payment_debts WHERE payment_id = ?1",
+        params![payment_id],
+    )?;
+    for (position, (debtor, amount)) in debts.iter().enumerate() {
+        con.execute(
+            "INSERT INTO payment_debts (payment_id, position, debtor, amount)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![payment_id, position as i64, debtor, amount],
+        )?;
+    }
+    Ok(())
+}
+
+// Replaces all items of a payment
+// Participants are kept separated by spaces, as members never contain whitespace
+fn set_payment_items(
+    con: &Connection,
+    payment_id: &str,
+    items: &[PaymentItem],
+) -> rusqlite::Result<()> {
+    con.execute(
+        "DELETE FROM payment_items WHERE payment_id = ?1",
+        params![payment_id],
+    )?;
+    for (position, item) in items.iter().enumerate() {
+        con.execute(
+            "INSERT INTO payment_items (payment_id, position, description, amount, participants)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                payment_id,
+                position as i64,
+                item.description,
+                item.amount,
+                item.participants.join(" ")
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Sets the category of a payment, None to clear it
+fn set_payment_category(
+    con: &Connection,
+    payment_id: &str,
+    category: Option<&str>,
+) -> rusqlite::Result<()> {
+    con.execute(
+        "DELETE FROM payment_categories WHERE payment_id = ?1",
+        params![payment_id],
+    )?;
+    if let Some(category) = category {
+        con.execute(
+            "INSERT INTO payment_categories (payment_id, category) VALUES (?1, ?2)",
+            params![payment_id, category],
+        )?;
+    }
+    Ok(())
+}
+
+// Replaces all payers of a payment, empty if the creditor paid the whole total
+fn set_payment_payers(
+    con: &Connection,
+    payment_id: &str,
+    payers: &[(String, i64)],
+) -> rusqlite::Result<()> {
+    con.execute(
+        "DELETE FROM payment_payers WHERE payment_id = ?1",
+        params![payment_id],
+    )?;
+    for (position, (payer, amount)) in payers.iter().enumerate() {
+        con.execute(
+            "INSERT INTO payment_payers (payment_id, position, payer, amount)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![payment_id, position as i64, payer, amount],
+        )?;
+    }
+    Ok(())
+}
+
+// Adds the given changes onto the current balances of a chat
+fn update_balances(
+    con: &Connection,
+    chat_id: &str,
+    changes: Vec<UserBalance>,
+) -> rusqlite::Result<()> {
+    for change in changes {
+        ensure_chat_user(con, chat_id, &change.username)?;
+        con.execute(
+            "INSERT OR IGNORE INTO chat_currencies (chat_id, currency) VALUES (?1, ?2)",
+            params![chat_id, change.currency],
+        )?;
+        con.execute(
+            "INSERT INTO balances (chat_id, user_key, currency, balance)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (chat_id, user_key, currency)
+             DO UPDATE SET balance = balance + excluded.balance",
+            params![
+                chat_id,
+                change.username.to_lowercase(),
+                change.currency,
+                change.balance
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+// Adds the given changes onto the current spendings of a chat.
+// Must run in a transaction, as earlier changes are written before a later one fails.
+fn update_spendings(
+    con: &Connection,
+    chat_id: &str,
+    spendings: Vec<UserBalance>,
+) -> Result<(), CrudError> {
+    for spending in spendings {
+        let user_key = spending.username.to_lowercase();
+        let current: Option<i64> = con
+            .query_row(
+                "SELECT spending FROM spendings
+                 WHERE chat_id = ?1 AND user_key = ?2 AND currency = ?3",
+                params![chat_id, user_key, spending.currency],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        let amount = current.unwrap_or(0) + spending.balance;
+        if amount < 0 {
+            return Err(CrudError::NegativeSpendingError());
+        }
+
+        ensure_chat_user(con, chat_id, &spending.username)?;
+        con.execute(
+            "INSERT INTO spendings (chat_id, user_key, currency, spending)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (chat_id, user_key, currency)
+             DO UPDATE SET spending = excluded.spending",
+            params![chat_id, user_key, spending.currency, amount],
+        )?;
+    }
+    Ok(())
+}
+
+// Adds the given changes onto the category totals of a chat, deleting those with nothing spent.
+// Must run in a transaction, as earlier changes are written before a later one fails.
+fn update_categories(
+    con: &Connection,
+    chat_id: &str,
+    changes: Vec<CategoryTotal>,
+) -> Result<(), CrudError> {
+    for change in changes {
+        let category_key = change.key();
+        let mut total = con
+            .query_row(
+                "SELECT category, spending, latest FROM chat_categories
+                 WHERE chat_id = ?1 AND category_key = ?2 AND currency = ?3",
+                params![chat_id, category_key, change.currency],
+                |row| {
+                    Ok(CategoryTotal {
+                        category: row.get(0)?,
+                        currency: change.currency.clone(),
+                        spending: row.get(1)?,
+                        latest: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?
+            .unwrap_or(CategoryTotal {
+                spending: 0,
+                ..change.clone()
+            });
+
+        total.add(&change);
+        if total.spending < 0 {
+            return Err(CrudError::NegativeSpendingError());
+        }
+
+        if total.spending == 0 {
+            con.execute(
+                "DELETE FROM chat_categories
+                 WHERE chat_id = ?1 AND category_key = ?2 AND currency = ?3",
+                params![chat_id, category_key, total.currency],
+            )?;
+        } else {
+            con.execute(
+                "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+                params![chat_id],
+            )?;
+            con.execute(
+                "INSERT INTO chat_categories (chat_id, category_key, currency, category, spending, latest)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (chat_id, category_key, currency)
+                 DO UPDATE SET spending = excluded.spending, latest = excluded.latest",
+                params![
+                    chat_id,
+                    category_key,
+                    total.currency,
+                    total.category,
+                    total.spending,
+                    total.latest
+                ],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Adds a new payment with its debts to a chat, returns the new payment id
+fn add_payment(con: &Connection, chat_id: &str, payment: &Payment) -> rusqlite::Result<String> {
+    let payment_id = Uuid::new_v4().to_string();
+    con.execute(
+        "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+        params![chat_id],
+    )?;
+    con.execute(
+        "INSERT INTO payments
+         (payment_id, chat_id, description, timestamp, creditor, currency, total)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            payment_id,
+            chat_id,
+            payment.description,
+            payment.timestamp,
+            payment.creditor,
+            payment.currency,
+            payment.total
+        ],
+    )?;
+    set_payment_debts(con, &payment_id, &payment.debts)?;
+    set_payment_items(con, &payment_id, &payment.items)?;
+    set_payment_category(con, &payment_id, payment.category.as_deref())?;
+    set_payment_payers(con, &payment_id, &payment.payers)?;
+    Ok(payment_id)
+}
+
+// Updates the given fields of a payment
+#[allow(clippy::too_many_arguments)]
+fn update_payment(
+    con: &Connection,
+    payment_id: &str,
+    description: Option<&str>,
+    timestamp: Option<i64>,
+    creditor: Option<&str>,
+    currency: Option<&str>,
+    total: Option<&i64>,
+    debts: Option<&[(String, i64)]>,
+    items: Option<&[PaymentItem]>,
+    category: Option<Option<&str>>,
+    payers: Option<&[(String, i64)]>,
+) -> Result<(), CrudError> {
+    let updated = con.execute(
+        "UPDATE payments SET
+             description = COALESCE(?2, description),
+             timestamp = COALESCE(?3, timestamp),
+             creditor = COALESCE(?4, creditor),
+             currency = COALESCE(?5, currency),
+             total = COALESCE(?6, total)
+         WHERE payment_id = ?1
+         AND payment_id NOT IN (SELECT payment_id FROM trashed_payments)",
+        params![
+            payment_id,
+            description,
+            timestamp,
+            creditor,
+            currency,
+            total
+        ],
+    )?;
+    if updated == 0 {
+        return Err(CrudError::NoSuchPaymentError());
+    }
+
+    if let Some(debts) = debts {
+        set_payment_debts(con, payment_id, debts)?;
+    }
+    if let Some(items) = items {
+        set_payment_items(con, payment_id, items)?;
+    }
+    if let Some(category) = category {
+        set_payment_category(con, payment_id, category)?;
+    }
+    if let Some(payers) = payers {
+        set_payment_payers(con, payment_id, payers)?;
+    }
+    Ok(())
+}
+
+// Deletes a payment, debts are removed together through the foreign key
+fn delete_payment(con: &Connection, chat_id: &str, payment_id: &str) -> Result<(), CrudError> {
+    let deleted = con.execute(
+        "DELETE FROM payments WHERE payment_id = ?1 AND chat_id = ?2",
+        params![payment_id, chat_id],
+    )?;
+    if deleted == 0 {
+        return Err(CrudError::NoSuchPaymentError());
+    }
+    Ok(())
+}
+
+// Moves a payment into the trash, keeping its entry
+fn trash_payment(
+    con: &Connection,
+    chat_id: &str,
+    payment_id: &str,
+    deleted_at: i64,
+    deleted_by: &str,
+) -> Result<(), CrudError> {
+    let trashed = con.execute(
+        "INSERT OR IGNORE INTO trashed_payments (payment_id, chat_id, deleted_at, deleted_by)
+         SELECT payment_id, chat_id, ?3, ?4 FROM payments WHERE payment_id = ?1 AND chat_id = ?2",
+        params![payment_id, chat_id, deleted_at, deleted_by],
+    )?;
+    if trashed == 0 {
+        return Err(CrudError::NoSuchPaymentError());
+    }
+    Ok(())
+}
+
+// Takes a payment out of the trash
+fn restore_payment(con: &Connection, chat_id: &str, payment_id: &str) -> Result<(), CrudError> {
+    let restored = con.execute(
+        "DELETE FROM trashed_payments WHERE payment_id = ?1 AND chat_id = ?2",
+        params![payment_id, chat_id],
+    )?;
+    if restored == 0 {
+        return Err(CrudError::NoSuchPaymentError());
+    }
+    Ok(())
+}
+
+// Deletes a trashed payment, the trash entry is removed together through the foreign key
+fn purge_payment(con: &Connection, chat_id: &str, payment_id: &str) -> Result<(), CrudError> {
+    let deleted = con.execute(
+        "DELETE FROM payments WHERE payment_id = ?1 AND chat_id = ?2
+         AND payment_id IN (SELECT payment_id FROM trashed_payments)",
+        params![payment_id, chat_id],
+    )?;
+    if deleted == 0 {
+        return Err(CrudError::NoSuchPaymentError());
+    }
+    Ok(())
+}
+
+// Records an event of a payment in a chat, events are never updated or removed
+fn add_event(con: &Connection, chat_id: &str, event: &PaymentEvent) -> Result<(), CrudError> {
+    con.execute(
+        "INSERT INTO payment_events (chat_id, payment_id, event) VALUES (?1, ?2, ?3)",
+        params![chat_id, event.payment_id, serde_json::to_string(event)?],
+    )?;
+    Ok(())
+}
+
+// Deserializes the events of a query, in the same order
+fn get_events(
+    con: &Connection,
+    query: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<PaymentEvent>, CrudError> {
+    let mut stmt = con.prepare(query)?;
+    let events = stmt
+        .query_map(params, |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut payment_events: Vec<PaymentEvent> = Vec::new();
+    for event in events {
+        payment_events.push(serde_json::from_str(&event)?);
+    }
+    Ok(payment_events)
+}
+
+// Deserializes the recurring payments of a query, in the same order
+fn get_recurring_payments(
+    con: &Connection,
+    query: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+    let mut stmt = con.prepare(query)?;
+    let recurring_payments = stmt
+        .query_map(params, |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut chat_recurring_payments: Vec<ChatRecurringPayment> = Vec::new();
+    for recurring in recurring_payments {
+        chat_recurring_payments.push(serde_json::from_str(&recurring)?);
+    }
+    Ok(chat_recurring_payments)
+}
+
+// Sets a recurring payment, replacing any with the same ID
+fn set_recurring_payment(
+    con: &Connection,
+    recurring: &ChatRecurringPayment,
+) -> Result<(), CrudError> {
+    con.execute(
+        "INSERT INTO recurring_payments (recurring_id, chat_id, next, recurring)
+         VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (recurring_id) DO UPDATE
+         SET chat_id = excluded.chat_id, next = excluded.next, recurring = excluded.recurring",
+        params![
+            recurring.recurring_id,
+            recurring.chat_id,
+            recurring.recurring.next,
+            serde_json::to_string(recurring)?
+        ],
+    )?;
+    Ok(())
+}
+
+// Schedules a recurring payment as part of a ledger update, only if it is still as it was read
+fn apply_recurring_change(
+    con: &Connection,
+    chat_id: &str,
+    change: RecurringChange,
+) -> Result<(), CrudError> {
+    let mut recurring = get_recurring_payments(
+        con,
+        "SELECT recurring FROM recurring_payments WHERE recurring_id = ?1 AND chat_id = ?2",
+        params![change.recurring_id, chat_id],
+    )?
+    .pop()
+    .ok_or(CrudError::NoSuchRecurringPaymentError())?;
+    if recurring.recurring != change.current {
+        return Err(CrudError::RecurringChangedError());
+    }
+
+    match change.next {
+        Some(next) => {
+            recurring.recurring.next = next;
+            set_recurring_payment(con, &recurring)
+        }
+        None => {
+            con.execute(
+                "DELETE FROM recurring_payments WHERE recurring_id = ?1",
+                params![change.recurring_id],
+            )?;
+            Ok(())
+        }
+    }
+}
+
+#[async_trait]
+impl LedgerStore for SqliteStore {
+    async fn update_user(
+        &self,
+        username: &str,
+        chat_id: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), CrudError> {
+        let username = username.to_string();
+        let chat_id = chat_id.to_string();
+        let user_id = user_id.map(String::from);
+        self.run(move |con| {
+            let tx = con.transaction()?;
+
+            // Preferred username and ID are only set when the user is first added
+            tx.execute(
+                "INSERT OR IGNORE INTO users (user_key, username, user_id) VALUES (?1, ?2, ?3)",
+                params![username.to_lowercase(), username, user_id],
+            )?;
+            ensure_chat_user(&tx, &chat_id, &username)?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn update_chat(&self, chat_id: &str, usernames: Vec<String>) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+
+            tx.execute(
+                "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+                params![chat_id],
+            )?;
+            tx.execute(
+                "INSERT OR IGNORE INTO chat_settings
+                 (chat_id, time_zone, default_currency, currency_conversion, erase_messages)
+                 VALUES (?1, 'UTC', ?2, FALSE, TRUE)",
+                params![chat_id, CURRENCY_CODE_DEFAULT],
+            )?;
+            for username in usernames {
+                ensure_chat_user(&tx, &chat_id, &username)?;
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_user_chats(&self, username: &str) -> Result<Vec<String>, CrudError> {
+        let user_key = username.to_lowercase();
+        self.run(move |con| {
+            let mut stmt =
+                con.prepare("SELECT chat_id FROM chat_users WHERE user_key = ?1 ORDER BY rowid")?;
+            let chats = stmt
+                .query_map(params![user_key], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(chats)
+        })
+        .await
+    }
+
+    async fn get_chat_users(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let mut stmt =
+                con.prepare("SELECT user_key FROM chat_users WHERE chat_id = ?1 ORDER BY rowid")?;
+            let users = stmt
+                .query_map(params![chat_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(users)
+        })
+        .await
+    }
+
+    async fn forget_user(&self, username: &str) -> Result<(), CrudError> {
+        let user_key = username.to_lowercase();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+
+            // The user is kept under the lowercase key, as balances and spendings still refer to it
+            tx.execute(
+                "DELETE FROM chat_users WHERE user_key = ?1",
+                params![user_key],
+            )?;
+            tx.execute(
+                "UPDATE users SET username = user_key, user_id = NULL WHERE user_key = ?1",
+                params![user_key],
+            )?;
+            tx.execute(
+                "DELETE FROM member_names WHERE member = ?1",
+                params![user_key],
+            )?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn reset_chat(&self, chat_id: &str) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+
+            // Everything else is deleted along with the chat, through foreign keys
+            tx.execute(
+                "DELETE FROM payment_events WHERE chat_id = ?1",
+                params![chat_id],
+            )?;
+            tx.execute("DELETE FROM chats WHERE chat_id = ?1", params![chat_id])?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn migrate_chat(&self, chat_id: &str, new_chat_id: &str) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let new_chat_id = new_chat_id.to_string();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+
+            let is_exists: bool = tx.query_row(
+                "SELECT EXISTS (SELECT 1 FROM chats WHERE chat_id = ?1)",
+                params![chat_id],
+                |row| row.get(0),
+            )?;
+            if !is_exists || chat_id == new_chat_id {
+                return Ok(());
+            }
+
+            // Records already under the new chat ID are replaced, as when resetting a chat
+            tx.execute(
+                "DELETE FROM payment_events WHERE chat_id = ?1",
+                params![new_chat_id],
+            )?;
+            tx.execute(
+                "DELETE FROM dialogues WHERE chat_id = ?1",
+                params![new_chat_id],
+            )?;
+            tx.execute("DELETE FROM chats WHERE chat_id = ?1", params![new_chat_id])?;
+            tx.execute(
+                "INSERT INTO chats (chat_id) VALUES (?1)",
+                params![new_chat_id],
+            )?;
+
+            // Every table referring to the chat is moved before the old chat is deleted
+            for table in [
+                "chat_users",
+                "chat_settings",
+                "chat_currencies",
+                "payments",
+                "trashed_payments",
+                "payment_events",
+                "balances",
+                "spendings",
+                "chat_categories",
+                "dialogues",
+            ] {
+                tx.execute(
+                    &format!("UPDATE {table} SET chat_id = ?2 WHERE chat_id = ?1"),
+                    params![chat_id, new_chat_id],
+                )?;
+            }
+
+            // Recurring payments keep their chat ID in their JSON as well
+            let recurring_payments = get_recurring_payments(
+                &tx,
+                "SELECT recurring FROM recurring_payments WHERE chat_id = ?1",
+                params![chat_id],
+            )?;
+            for mut recurring in recurring_payments {
+                recurring.chat_id = new_chat_id.clone();
+                set_recurring_payment(&tx, &recurring)?;
+            }
+            tx.execute("DELETE FROM chats WHERE chat_id = ?1", params![chat_id])?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_tracked_username(&self, user_id: &str) -> Result<Option<String>, CrudError> {
+        let user_id = user_id.to_string();
+        self.run(move |con| {
+            let username = con
+                .query_row(
+                    "SELECT username FROM users WHERE user_id = ?1",
+                    params![user_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(username)
+        })
+        .await
+    }
+
+    async fn track_username(&self, user_id: &str, username: &str) -> Result<(), CrudError> {
+        let user_id = user_id.to_string();
+        let username = username.to_string();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+            let user_key = username.to_lowercase();
+
+            // The tracked username is the preferred username of the user with the ID
+            tx.execute(
+                "UPDATE users SET user_id = NULL WHERE user_id = ?1 AND user_key != ?2",
+                params![user_id, user_key],
+            )?;
+            tx.execute(
+                "INSERT INTO users (user_key, username, user_id) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (user_key) DO UPDATE
+                 SET username = excluded.username, user_id = excluded.user_id",
+                params![user_key, username, user_id],
+            )?;
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn rename_user(&self, old_username: &str, new_username: &str) -> Result<(), CrudError> {
+        let old_username = old_username.to_string();
+        let new_username = new_username.to_string();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+            let old_key = old_username.to_lowercase();
+            let new_key = new_username.to_lowercase();
+
+            // Payments in every chat of the user, including those in the trash
+            let payment_ids = {
+                let mut stmt = tx.prepare(
+                    "SELECT payments.payment_id FROM payments
+                     JOIN chat_users ON chat_users.chat_id = payments.chat_id
+                     WHERE chat_users.user_key = ?1",
+                )?;
+                let payment_ids = stmt
+                    .query_map(params![old_key], |row| row.get(0))?
+                    .collect::<rusqlite::Result<Vec<String>>>()?;
+                payment_ids
+            };
+            for payment_id in payment_ids {
+                let renamed = get_payment(&tx, &payment_id)?
+                    .and_then(|payment| payment.rename_user(&old_username, &new_username));
+                if let Some(renamed) = renamed {
+                    tx.execute(
+                        "UPDATE payments SET creditor = ?2 WHERE payment_id = ?1",
+                        params![payment_id, renamed.creditor],
+                    )?;
+                    set_payment_debts(&tx, &payment_id, &renamed.debts)?;
+                    set_payment_items(&tx, &payment_id, &renamed.items)?;
+                    set_payment_payers(&tx, &payment_id, &renamed.payers)?;
+                }
+            }
+            let recurring_payments = get_recurring_payments(
+                &tx,
+                "SELECT recurring_payments.recurring FROM recurring_payments
+                 JOIN chat_users ON chat_users.chat_id = recurring_payments.chat_id
+                 WHERE chat_users.user_key = ?1",
+                params![old_key],
+            )?;
+            for mut recurring in recurring_payments {
+                let payment = &recurring.recurring.payment;
+                if let Some(renamed) = payment.rename_user(&old_username, &new_username) {
+                    recurring.recurring.payment = renamed;
+                    set_recurring_payment(&tx, &recurring)?;
+                }
+            }
+
+            tx.execute(
+                "INSERT INTO users (user_key, username) VALUES (?1, ?2)
+                 ON CONFLICT (user_key) DO UPDATE SET username = excluded.username",
+                params![new_key, new_username],
+            )?;
+
+            // Only casing has changed, so everything is already under the right key
+            if old_key != new_key {
+                for (table, column) in [("balances", "balance"), ("spendings", "spending")] {
+                    tx.execute(
+                        &format!(
+                            "INSERT INTO {table} (chat_id, user_key, currency, {column})
+                             SELECT chat_id, ?2, currency, {column} FROM {table} WHERE user_key = ?1
+                             ON CONFLICT (chat_id, user_key, currency)
+                             DO UPDATE SET {column} = {column} + excluded.{column}"
+                        ),
+                        params![old_key, new_key],
+                    )?;
+                }
+                tx.execute(
+                    "INSERT OR IGNORE INTO chat_users (chat_id, user_key)
+                     SELECT chat_id, ?2 FROM chat_users WHERE user_key = ?1",
+                    params![old_key, new_key],
+                )?;
+
+                // Everything left under the old username is deleted along with it, through foreign keys
+                tx.execute("DELETE FROM users WHERE user_key = ?1", params![old_key])?;
+                tx.execute(
+                    "DELETE FROM member_names WHERE member = ?1",
+                    params![old_key],
+                )?;
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_member_name(&self, member: &str, name: &str) -> Result<(), CrudError> {
+        let member = member.to_lowercase();
+        let name = name.to_string();
+        self.run(move |con| {
+            con.execute(
+                "INSERT INTO member_names (member, name) VALUES (?1, ?2)
+                 ON CONFLICT (member) DO UPDATE SET name = excluded.name",
+                params![member, name],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_member_names(
+        &self,
+        members: &[String],
+    ) -> Result<HashMap<String, String>, CrudError> {
+        let members: Vec<String> = members.iter().map(|member| member.to_lowercase()).collect();
+        self.run(move |con| {
+            let mut stmt = con.prepare("SELECT name FROM member_names WHERE member = ?1")?;
+            let mut names = HashMap::new();
+            for member in members {
+                let name: Option<String> = stmt
+                    .query_row(params![member], |row| row.get(0))
+                    .optional()?;
+                if let Some(name) = name {
+                    names.insert(member, name);
+                }
+            }
+            Ok(names)
+        })
+        .await
+    }
+
+    async fn set_time_zone(&self, chat_id: &str, time_zone: &str) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let time_zone = time_zone.to_string();
+        self.run(move |con| {
+            set_setting(con, &chat_id, "time_zone", &time_zone)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_time_zone(&self, chat_id: &str) -> Result<String, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let time_zone = get_setting(con, &chat_id, "time_zone")?;
+            Ok(time_zone.unwrap_or("UTC".to_string()))
+        })
+        .await
+    }
+
+    async fn set_default_currency(&self, chat_id: &str, currency: &str) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let currency = currency.to_string();
+        self.run(move |con| {
+            set_setting(con, &chat_id, "default_currency", &currency)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_default_currency(&self, chat_id: &str) -> Result<String, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let currency = get_setting(con, &chat_id, "default_currency")?;
+            Ok(currency.unwrap_or(CURRENCY_CODE_DEFAULT.to_string()))
+        })
+        .await
+    }
+
+    async fn set_currency_conversion(
+        &self,
+        chat_id: &str,
+        conversion: bool,
+    ) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            set_setting(con, &chat_id, "currency_conversion", &conversion)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_currency_conversion(&self, chat_id: &str) -> Result<bool, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let conversion = get_setting(con, &chat_id, "currency_conversion")?;
+            Ok(conversion.unwrap_or(false))
+        })
+        .await
+    }
+
+    async fn set_erase_messages(&self, chat_id: &str, erase: bool) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            set_setting(con, &chat_id, "erase_messages", &erase)?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_erase_messages(&self, chat_id: &str) -> Result<bool, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let erase = get_setting(con, &chat_id, "erase_messages")?;
+            Ok(erase.unwrap_or(true))
+        })
+        .await
+    }
+
+    async fn get_valid_chat_currencies(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let mut stmt = con.prepare(
+                "SELECT currency FROM chat_currencies
+                 WHERE chat_id = ?1 AND EXISTS (
+                     SELECT 1 FROM spendings
+                     WHERE spendings.chat_id = chat_currencies.chat_id
+                         AND spendings.currency = chat_currencies.currency
+                         AND spendings.spending > 0
+                 )
+                 ORDER BY rowid",
+            )?;
+            let currencies = stmt
+                .query_map(params![chat_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(currencies)
+        })
+        .await
+    }
+
+    async fn get_chat_balances(&self, chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let mut balances: Vec<Vec<UserBalance>> = Vec::new();
+            for currency in get_chat_currencies(con, &chat_id)? {
+                balances.push(get_ledger_currency(
+                    con, "balances", "balance", &chat_id, &currency,
+                )?);
+            }
+            Ok(balances)
+        })
+        .await
+    }
+
+    async fn get_chat_balances_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        let chat_id = chat_id.to_string();
+        let currency = currency.to_string();
+        self.run(move |con| {
+            let balances = get_ledger_currency(con, "balances", "balance", &chat_id, &currency)?;
+            Ok(balances)
+        })
+        .await
+    }
+
+    async fn get_chat_payments_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<UserPayment>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            // Latest payment first, same as the Redis list, leaving out trashed payments
+            let mut stmt = con.prepare(
+                "SELECT payment_id FROM payments WHERE chat_id = ?1
+                 AND payment_id NOT IN (SELECT payment_id FROM trashed_payments)
+                 ORDER BY rowid DESC",
+            )?;
+            let payment_ids = stmt
+                .query_map(params![chat_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+
+            if payment_ids.is_empty() {
+                return Err(CrudError::NoPaymentsError());
+            }
+
+            let mut payments: Vec<UserPayment> = Vec::new();
+            for payment_id in payment_ids {
+                let payment = match get_payment(con, &payment_id)? {
+                    Some(payment) => payment,
+                    None => return Err(CrudError::NoSuchPaymentError()),
+                };
+                payments.push(UserPayment {
+                    chat_id: chat_id.clone(),
+                    payment_id,
+                    payment,
+                });
+            }
+
+            Ok(payments)
+        })
+        .await
+    }
+
+    async fn get_chat_payments_page(
+        &self,
+        chat_id: &str,
+        query: &PaymentQuery,
+    ) -> Result<PaymentPage, CrudError> {
+        let chat_id = chat_id.to_string();
+        let query = query.clone();
+        self.run(move |con| {
+            let filter =
+                "chat_id = ?1 AND payment_id NOT IN (SELECT payment_id FROM trashed_payments)
+                 AND (?2 IS NULL OR timestamp >= ?2) AND (?3 IS NULL OR timestamp <= ?3)";
+
+            let total: i64 = con.query_row(
+                &format!("SELECT COUNT(*) FROM payments WHERE {filter}"),
+                params![chat_id, query.from, query.to],
+                |row| row.get(0),
+            )?;
+
+            // Latest payment first, same as the Redis index, with one more to tell if there is a next page
+            let cursor_timestamp = query.cursor.as_ref().map(|cursor| cursor.timestamp);
+            let cursor_id = query.cursor.as_ref().map(|cursor| &cursor.payment_id);
+            let mut stmt = con.prepare(&format!(
+                "SELECT payment_id FROM payments WHERE {filter}
+                 AND (?4 IS NULL OR timestamp < ?4 OR (timestamp = ?4 AND payment_id < ?5))
+                 ORDER BY timestamp DESC, payment_id DESC LIMIT ?6"
+            ))?;
+            let payment_ids = stmt
+                .query_map(
+                    params![
+                        chat_id,
+                        query.from,
+                        query.to,
+                        cursor_timestamp,
+                        cursor_id,
+                        query.page_size as i64 + 1
+                    ],
+                    |row| row.get(0),
+                )?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+
+            let is_more = payment_ids.len() > query.page_size;
+            let mut payments: Vec<UserPayment> = Vec::new();
+            for payment_id in payment_ids.into_iter().take(query.page_size) {
+                let payment = match get_payment(con, &payment_id)? {
+                    Some(payment) => payment,
+                    None => return Err(CrudError::NoSuchPaymentError()),
+                };
+                payments.push(UserPayment {
+                    chat_id: chat_id.clone(),
+                    payment_id,
+                    payment,
+                });
+            }
+
+            let next = match payments.last() {
+                Some(last) if is_more => Some(PaymentCursor::of(&last.payment_id, &last.payment)),
+                _ => None,
+            };
+
+            Ok(PaymentPage {
+                payments,
+                total: total as usize,
+                next,
+            })
+        })
+        .await
+    }
+
+    async fn get_payment_entry(&self, payment_id: &str) -> Result<Payment, CrudError> {
+        let payment_id = payment_id.to_string();
+        self.run(move |con| match get_payment(con, &payment_id)? {
+            Some(payment) => Ok(payment),
+            None => Err(CrudError::NoSuchPaymentError()),
+        })
+        .await
+    }
+
+    async fn get_chat_trash_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<TrashedPayment>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            // Latest deletion first, same as the Redis list
+            let mut stmt = con.prepare(
+                "SELECT payment_id, deleted_at, deleted_by FROM trashed_payments
+                 WHERE chat_id = ?1 ORDER BY rowid DESC",
+            )?;
+            let entries = stmt
+                .query_map(params![chat_id], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect::<rusqlite::Result<Vec<(String, i64, String)>>>()?;
+
+            let mut payments: Vec<TrashedPayment> = Vec::new();
+            for (payment_id, deleted_at, deleted_by) in entries {
+                let payment = match get_payment(con, &payment_id)? {
+                    Some(payment) => payment,
+                    None => return Err(CrudError::NoSuchPaymentError()),
+                };
+                payments.push(TrashedPayment {
+                    payment_id,
+                    payment,
+                    deleted_at,
+                    deleted_by,
+                });
+            }
+
+            Ok(payments)
+        })
+        .await
+    }
+
+    async fn get_payment_history(&self, payment_id: &str) -> Result<Vec<PaymentEvent>, CrudError> {
+        let payment_id = payment_id.to_string();
+        self.run(move |con| {
+            get_events(
+                con,
+                "SELECT event FROM payment_events WHERE payment_id = ?1 ORDER BY rowid",
+                params![payment_id],
+            )
+        })
+        .await
+    }
+
+    async fn get_chat_activity(
+        &self,
+        chat_id: &str,
+        count: usize,
+    ) -> Result<Vec<PaymentEvent>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            // Latest first, same as the Redis list
+            get_events(
+                con,
+                "SELECT event FROM payment_events WHERE chat_id = ?1 ORDER BY rowid DESC LIMIT ?2",
+                params![chat_id, count as i64],
+            )
+        })
+        .await
+    }
+
+    async fn add_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring: &RecurringPayment,
+    ) -> Result<String, CrudError> {
+        let chat_id = chat_id.to_string();
+        let recurring = recurring.clone();
+        self.run(move |con| {
+            let tx = con.transaction()?;
+            let recurring_id = Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT OR IGNORE INTO chats (chat_id) VALUES (?1)",
+                params![chat_id],
+            )?;
+            set_recurring_payment(
+                &tx,
+                &ChatRecurringPayment {
+                    recurring_id: recurring_id.clone(),
+                    chat_id,
+                    recurring,
+                },
+            )?;
+            tx.commit()?;
+            Ok(recurring_id)
+        })
+        .await
+    }
+
+    async fn get_chat_recurring_payments(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            get_recurring_payments(
+                con,
+                "SELECT recurring FROM recurring_payments WHERE chat_id = ?1 ORDER BY next",
+                params![chat_id],
+            )
+        })
+        .await
+    }
+
+    async fn get_due_recurring_payments(
+        &self,
+        now: i64,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        self.run(move |con| {
+            // Same as the Redis sorted set, ties are ordered by ID
+            get_recurring_payments(
+                con,
+                "SELECT recurring FROM recurring_payments WHERE next <= ?1
+                 ORDER BY next, recurring_id",
+                params![now],
+            )
+        })
+        .await
+    }
+
+    async fn delete_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring_id: &str,
+    ) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let recurring_id = recurring_id.to_string();
+        self.run(move |con| {
+            let deleted = con.execute(
+                "DELETE FROM recurring_payments WHERE recurring_id = ?1 AND chat_id = ?2",
+                params![recurring_id, chat_id],
+            )?;
+            if deleted == 0 {
+                return Err(CrudError::NoSuchRecurringPaymentError());
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn retrieve_chat_spendings(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let mut spendings: Vec<Vec<UserBalance>> = Vec::new();
+            for currency in get_chat_currencies(con, &chat_id)? {
+                spendings.push(get_ledger_currency(
+                    con,
+                    "spendings",
+                    "spending",
+                    &chat_id,
+                    &currency,
+                )?);
+            }
+            Ok(spendings)
+        })
+        .await
+    }
+
+    async fn retrieve_chat_spendings_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        let chat_id = chat_id.to_string();
+        let currency = currency.to_string();
+        self.run(move |con| {
+            let spendings = get_ledger_currency(con, "spendings", "spending", &chat_id, &currency)?;
+            Ok(spendings)
+        })
+        .await
+    }
+
+    async fn retrieve_chat_categories(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<CategoryTotal>, CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let totals = con
+                .prepare(
+                    "SELECT category, currency, spending, latest FROM chat_categories
+                     WHERE chat_id = ?1 AND spending != 0",
+                )?
+                .query_map(params![chat_id], |row| {
+                    Ok(CategoryTotal {
+                        category: row.get(0)?,
+                        currency: row.get(1)?,
+                        spending: row.get(2)?,
+                        latest: row.get(3)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<CategoryTotal>>>()?;
+            Ok(totals)
+        })
+        .await
+    }
+
+    async fn apply_ledger_update(
+        &self,
+        chat_id: &str,
+        update: LedgerUpdate,
+    ) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        self.run(move |con| {
+            let chat_id = chat_id.as_str();
+            let tx = con.transaction()?;
+
+            for change in update.payments {
+                let action = change.action();
+                let payment_id = match &change {
+                    PaymentChange::Add(payment) => add_payment(&tx, chat_id, payment)?,
+                    PaymentChange::Update { payment_id, .. }
+                    | PaymentChange::Delete(payment_id)
+                    | PaymentChange::Trash { payment_id, .. }
+                    | PaymentChange::Restore(payment_id)
+                    | PaymentChange::Purge(payment_id) => payment_id.clone(),
+                };
+                let before = get_payment(&tx, &payment_id)?;
+
+                match change {
+                    PaymentChange::Add(_) => {}
+                    PaymentChange::Update {
+                        payment_id,
+                        description,
+                        timestamp,
+                        creditor,
+                        currency,
+                        total,
+                        debts,
+                        items,
+                        category,
+                        payers,
+                    } => update_payment(
+                        &tx,
+                        &payment_id,
+                        description.as_deref(),
+                        timestamp,
+                        creditor.as_deref(),
+                        currency.as_deref(),
+                        total.as_ref(),
+                        debts.as_deref(),
+                        items.as_deref(),
+                        category.as_ref().map(|category| category.as_deref()),
+                        payers.as_deref(),
+                    )?,
+                    PaymentChange::Delete(payment_id) => delete_payment(&tx, chat_id, &payment_id)?,
+                    PaymentChange::Trash {
+                        payment_id,
+                        deleted_at,
+                        deleted_by,
+                    } => trash_payment(&tx, chat_id, &payment_id, deleted_at, &deleted_by)?,
+                    PaymentChange::Restore(payment_id) => {
+                        restore_payment(&tx, chat_id, &payment_id)?
+                    }
+                    PaymentChange::Purge(payment_id) => purge_payment(&tx, chat_id, &payment_id)?,
+                }
+
+                if let Some(actor) = &update.actor {
+                    let after = get_payment(&tx, &payment_id)?;
+                    let (before, after) = match action {
+                        PaymentAction::Add | PaymentAction::Restore => (None, after),
+                        PaymentAction::Edit => (before, after),
+                        PaymentAction::Delete | PaymentAction::Purge => (before, None),
+                    };
+                    add_event(
+                        &tx,
+                        chat_id,
+                        &actor.event(&payment_id, action, before, after),
+                    )?;
+                }
+            }
+
+            update_spendings(&tx, chat_id, update.spendings)?;
+            update_categories(&tx, chat_id, update.categories)?;
+            update_balances(&tx, chat_id, update.balances)?;
+
+            if let Some(currency) = update.default_currency {
+                set_setting(&tx, chat_id, "default_currency", &currency)?;
+            }
+            if let Some(conversion) = update.currency_conversion {
+                set_setting(&tx, chat_id, "currency_conversion", &conversion)?;
+            }
+            if let Some(change) = update.recurring {
+                apply_recurring_change(&tx, chat_id, change)?;
+            }
+
+            // Dropping the transaction without commit rolls back every change above
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn set_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        dialogue: &str,
+    ) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let user_id = user_id.to_string();
+        let dialogue = dialogue.to_string();
+        self.run(move |con| {
+            con.execute(
+                "INSERT INTO dialogues (chat_id, user_id, state) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (chat_id, user_id) DO UPDATE SET state = excluded.state",
+                params![chat_id, user_id, dialogue],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<Option<String>, CrudError> {
+        let chat_id = chat_id.to_string();
+        let user_id = user_id.to_string();
+        self.run(move |con| {
+            let dialogue = con
+                .query_row(
+                    "SELECT state FROM dialogues WHERE chat_id = ?1 AND user_id = ?2",
+                    params![chat_id, user_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(dialogue)
+        })
+        .await
+    }
+
+    async fn delete_dialogue(&self, chat_id: &str, user_id: &str) -> Result<(), CrudError> {
+        let chat_id = chat_id.to_string();
+        let user_id = user_id.to_string();
+        self.run(move |con| {
+            con.execute(
+                "DELETE FROM dialogues WHERE chat_id = ?1 AND user_id = ?2",
+                params![chat_id, user_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn is_request_limit_exceeded(
+        &self,
+        user_id: &str,
+        time_now: i64,
+    ) -> Result<bool, CrudError> {
+        let user_id = user_id.to_string();
+        self.run(move |con| {
+            let timestamp: Option<i64> = con
+                .query_row(
+                    "SELECT timestamp FROM requests WHERE user_id = ?1",
+                    params![user_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            let status = match timestamp {
+                Some(timestamp) => time_now <= timestamp,
+                None => false,
+            };
+
+            if !status {
+                con.execute(
+                    "INSERT INTO requests (user_id, timestamp) VALUES (?1, ?2)
+                     ON CONFLICT (user_id) DO UPDATE SET timestamp = excluded.timestamp",
+                    params![user_id, time_now],
+                )?;
+            }
+
+            Ok(status)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::redis::{LedgerActor, RecurringFrequency};
+
+    use super::*;
+
+    fn balance(username: &str, currency: &str, balance: i64) -> UserBalance {
+        UserBalance {
+            username: username.to_string(),
+            currency: currency.to_string(),
+            balance,
+        }
+    }
+
+    fn payment(creditor: &str, total: i64, debts: Vec<(String, i64)>) -> Payment {
+        Payment {
+            description: "test_payment".to_string(),
+            timestamp: 1704067200,
+            creditor: creditor.to_string(),
+            currency: "USD".to_string(),
+            total,
+            debts,
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        }
+    }
+
+    // Adds a payment on its own, through a ledger update same as in application
+    async fn add_payment_entry(store: &SqliteStore, chat_id: &str, payment: &Payment) {
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_chat_init_settings() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_settings";
+
+        store
+            .update_chat(chat_id, vec!["Test_User".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(store.get_time_zone(chat_id).await.unwrap(), "UTC");
+        assert_eq!(
+            store.get_default_currency(chat_id).await.unwrap(),
+            CURRENCY_CODE_DEFAULT
+        );
+        assert!(!store.get_currency_conversion(chat_id).await.unwrap());
+        assert!(store.get_erase_messages(chat_id).await.unwrap());
+
+        // Settings are kept when the chat is updated again
+        store
+            .set_time_zone(chat_id, "Asia/Singapore")
+            .await
+            .unwrap();
+        store.set_default_currency(chat_id, "SGD").await.unwrap();
+        store.set_currency_conversion(chat_id, true).await.unwrap();
+        store.set_erase_messages(chat_id, false).await.unwrap();
+        store
+            .update_chat(chat_id, vec!["Test_User_2".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_time_zone(chat_id).await.unwrap(),
+            "Asia/Singapore"
+        );
+        assert_eq!(store.get_default_currency(chat_id).await.unwrap(), "SGD");
+        assert!(store.get_currency_conversion(chat_id).await.unwrap());
+        assert!(!store.get_erase_messages(chat_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_get_chat_balances() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_balances";
+
+        store
+            .update_user("Test_User_1", chat_id, Some("sqlite_user_1"))
+            .await
+            .unwrap();
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let update = LedgerUpdate {
+            balances: vec![
+                balance("Test_User_1", "USD", 300),
+                balance("Test_User_2", "USD", -300),
+                balance("Test_User_2", "EUR", 0),
+            ],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let update = LedgerUpdate {
+            balances: vec![balance("test_user_1", "USD", -100)],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        // Zero balances are skipped, and preferred usernames are used
+        assert_eq!(
+            store.get_chat_balances(chat_id).await.unwrap(),
+            vec![
+                vec![
+                    balance("Test_User_1", "USD", 200),
+                    balance("Test_User_2", "USD", -300),
+                ],
+                vec![],
+            ]
+        );
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap(),
+            vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -300),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_spendings_negative() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_spendings";
+
+        let update = LedgerUpdate {
+            spendings: vec![balance("Test_User", "USD", 100)],
+            balances: vec![balance("Test_User", "USD", 0)],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        assert_eq!(
+            store.get_valid_chat_currencies(chat_id).await.unwrap(),
+            vec!["USD"]
+        );
+
+        // Nothing is written when a spending turns negative
+        let update = LedgerUpdate {
+            spendings: vec![
+                balance("Test_User", "USD", 50),
+                balance("Test_User_2", "USD", -1),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.retrieve_chat_spendings(chat_id).await.unwrap(),
+            vec![vec![balance("Test_User", "USD", 100)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_get_update_delete_payment_entry() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_payments";
+
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+
+        let first = payment("Test_User_1", 100, vec![("Test_User_2".to_string(), 100)]);
+        let second = payment("Test_User_2", 200, vec![("Test_User_1".to_string(), 200)]);
+        add_payment_entry(&store, chat_id, &first).await;
+        add_payment_entry(&store, chat_id, &second).await;
+
+        // Latest payment first
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[0].payment, second);
+        assert_eq!(payments[1].payment, first);
+
+        let payment_id = payments[0].payment_id.clone();
+        let debts = vec![
+            ("Test_User_1".to_string(), 100),
+            ("Test_User_3".to_string(), 150),
+        ];
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: None,
+                timestamp: None,
+                creditor: None,
+                currency: Some("EUR".to_string()),
+                total: Some(250),
+                debts: Some(debts.clone()),
+                items: None,
+                category: None,
+                payers: None,
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let updated = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(updated.description, "test_payment");
+        assert_eq!(updated.currency, "EUR");
+        assert_eq!(updated.total, 250);
+        assert_eq!(updated.debts, debts);
+
+        let delete = LedgerUpdate {
+            payments: vec![PaymentChange::Delete(payment_id.clone())],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, delete.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        assert_eq!(
+            store.apply_ledger_update(chat_id, delete).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Debts are removed together with the payment
+        let orphan_debts: i64 = store
+            .con
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM payment_debts WHERE payment_id = ?1",
+                params![payment_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(orphan_debts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_payment_items() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_items";
+
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+        store
+            .update_user("Test_User_2", chat_id, None)
+            .await
+            .unwrap();
+        let mut itemized = payment(
+            "Test_User_1",
+            300,
+            vec![
+                ("Test_User_1".to_string(), 150),
+                ("Test_User_2".to_string(), 150),
+            ],
+        );
+        itemized.items = vec![
+            PaymentItem {
+                description: "pizza".to_string(),
+                amount: 200,
+                participants: vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            },
+            PaymentItem {
+                description: "wine".to_string(),
+                amount: 100,
+                participants: vec!["Test_User_2".to_string()],
+            },
+        ];
+        add_payment_entry(&store, chat_id, &itemized).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await.unwrap(),
+            itemized
+        );
+
+        // Renaming a user renames them in items too
+        store
+            .rename_user("Test_User_2", "New_User_2")
+            .await
+            .unwrap();
+        let renamed = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(
+            renamed.items[1].participants,
+            vec!["New_User_2".to_string()]
+        );
+
+        // Items are cleared by a split that is not itemized
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: None,
+                timestamp: None,
+                creditor: None,
+                currency: None,
+                total: None,
+                debts: Some(vec![("New_User_2".to_string(), 300)]),
+                items: Some(Vec::new()),
+                category: None,
+                payers: None,
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        assert!(store
+            .get_payment_entry(&payment_id)
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payment_category() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_category";
+
+        store
+            .update_chat(chat_id, vec!["Test_User_1".to_string()])
+            .await
+            .unwrap();
+        let mut categorized = payment("Test_User_1", 300, vec![("Test_User_1".to_string(), 300)]);
+        categorized.category = Some("Food".to_string());
+        add_payment_entry(&store, chat_id, &categorized).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await.unwrap(),
+            categorized
+        );
+
+        // Category can be changed, then cleared
+        for category in [Some("Transport"), None] {
+            let update = LedgerUpdate {
+                payments: vec![PaymentChange::Update {
+                    payment_id: payment_id.clone(),
+                    description: None,
+                    timestamp: None,
+                    creditor: None,
+                    currency: None,
+                    total: None,
+                    debts: None,
+                    items: None,
+                    category: Some(category.map(|category| category.to_string())),
+                    payers: None,
+                }],
+                ..Default::default()
+            };
+            store.apply_ledger_update(chat_id, update).await.unwrap();
+            assert_eq!(
+                store
+                    .get_payment_entry(&payment_id)
+                    .await
+                    .unwrap()
+                    .category
+                    .as_deref(),
+                category
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payment_payers() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_payers";
+
+        store
+            .update_chat(chat_id, vec!["Test_User_1".to_string()])
+            .await
+            .unwrap();
+        let mut shared = payment("Test_User_1", 300, vec![("Test_User_1".to_string(), 300)]);
+        shared.payers = vec![
+            ("Test_User_1".to_string(), 200),
+            ("Test_User_2".to_string(), 100),
+        ];
+        add_payment_entry(&store, chat_id, &shared).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(store.get_payment_entry(&payment_id).await.unwrap(), shared);
+
+        // Payers can be changed, then cleared
+        for payers in [
+            vec![
+                ("Test_User_1".to_string(), 100),
+                ("Test_User_2".to_string(), 200),
+            ],
+            Vec::new(),
+        ] {
+            let update = LedgerUpdate {
+                payments: vec![PaymentChange::Update {
+                    payment_id: payment_id.clone(),
+                    description: None,
+                    timestamp: None,
+                    creditor: None,
+                    currency: None,
+                    total: None,
+                    debts: None,
+                    items: None,
+                    category: None,
+                    payers: Some(payers.clone()),
+                }],
+                ..Default::default()
+            };
+            store.apply_ledger_update(chat_id, update).await.unwrap();
+            assert_eq!(
+                store.get_payment_entry(&payment_id).await.unwrap().payers,
+                payers
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recurring_payments() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_recurring";
+        let new_chat_id = "sqlite_chat_recurring_new";
+
+        for username in ["Test_User_1", "Test_User_2"] {
+            store.update_user(username, chat_id, None).await.unwrap();
+        }
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let mut recurring = RecurringPayment {
+            payment: payment("Test_User_1", 100, vec![("Test_User_2".to_string(), 100)]),
+            frequency: RecurringFrequency::Monthly,
+            end: None,
+            next: 1704067200,
+            creator_id: "123456789".to_string(),
+            creator: "Test_User_1".to_string(),
+        };
+        let first_id = store
+            .add_recurring_payment(chat_id, &recurring)
+            .await
+            .unwrap();
+        recurring.next = 1706745600;
+        let second_id = store
+            .add_recurring_payment(chat_id, &recurring)
+            .await
+            .unwrap();
+
+        // Earliest due first
+        let recurring_payments = store.get_chat_recurring_payments(chat_id).await.unwrap();
+        assert_eq!(recurring_payments.len(), 2);
+        assert_eq!(recurring_payments[0].recurring_id, first_id);
+        assert_eq!(recurring_payments[1].recurring_id, second_id);
+        assert_eq!(recurring_payments[1].recurring, recurring);
+
+        // Only those due by the given time, across all chats
+        let due = store.get_due_recurring_payments(1704067200).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].recurring_id, first_id);
+        assert_eq!(due[0].chat_id, chat_id);
+
+        // Scheduled within a ledger update, only if it has not changed since it was read
+        let schedule = |recurring_id: &str, current: &RecurringPayment| LedgerUpdate {
+            recurring: Some(RecurringChange {
+                recurring_id: recurring_id.to_string(),
+                current: current.clone(),
+                next: Some(1709251200),
+            }),
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, schedule(&second_id, &recurring))
+            .await
+            .unwrap();
+        assert!(store
+            .get_due_recurring_payments(1706745600)
+            .await
+            .unwrap()
+            .iter()
+            .all(|due| due.recurring_id != second_id));
+        assert_eq!(
+            store
+                .apply_ledger_update(chat_id, schedule(&second_id, &recurring))
+                .await,
+            Err(CrudError::RecurringChangedError())
+        );
+        recurring.next = 1709251200;
+        assert_eq!(
+            store
+                .apply_ledger_update(new_chat_id, schedule(&second_id, &recurring))
+                .await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+        assert_eq!(
+            store
+                .apply_ledger_update(chat_id, schedule("missing", &recurring))
+                .await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+
+        // Recurring payments are renamed and moved with the rest of the chat
+        store
+            .rename_user("Test_User_2", "New_User_2")
+            .await
+            .unwrap();
+        store.migrate_chat(chat_id, new_chat_id).await.unwrap();
+        assert!(store
+            .get_chat_recurring_payments(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        let recurring_payments = store
+            .get_chat_recurring_payments(new_chat_id)
+            .await
+            .unwrap();
+        assert_eq!(recurring_payments.len(), 2);
+        assert_eq!(recurring_payments[0].chat_id, new_chat_id);
+        assert_eq!(
+            recurring_payments[0].recurring.payment.debts,
+            vec![("New_User_2".to_string(), 100)]
+        );
+
+        // Deleting only works within the chat of the recurring payment
+        assert_eq!(
+            store.delete_recurring_payment(chat_id, &first_id).await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+        store
+            .delete_recurring_payment(new_chat_id, &first_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get_chat_recurring_payments(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        store.reset_chat(new_chat_id).await.unwrap();
+        assert!(store
+            .get_due_recurring_payments(i64::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_chat_payments_page() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_payments_page";
+
+        let timestamps = [1704067200, 1704153600, 1704153600, 1704240000, 1704326400];
+        for timestamp in timestamps {
+            let mut payment = payment("Test_User_1", 100, vec![("Test_User_2".to_string(), 100)]);
+            payment.timestamp = timestamp;
+            add_payment_entry(&store, chat_id, &payment).await;
+        }
+
+        // Trashed payments are left out
+        let trashed_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        let trash = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: trashed_id,
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, trash).await.unwrap();
+
+        // Latest payment first, payments at the same time by payment ID descending
+        let mut expected = store.get_chat_payments_details(chat_id).await.unwrap();
+        expected.sort_by(|first, second| {
+            (second.payment.timestamp, &second.payment_id)
+                .cmp(&(first.payment.timestamp, &first.payment_id))
+        });
+
+        let mut query = PaymentQuery {
+            page_size: 3,
+            ..Default::default()
+        };
+        let mut payments = Vec::new();
+        loop {
+            let page = store.get_chat_payments_page(chat_id, &query).await.unwrap();
+            assert_eq!(page.total, 4);
+            payments.extend(page.payments);
+            match page.next {
+                Some(next) => query.cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(payments, expected);
+
+        // Only payments within the time range, inclusive
+        let query = PaymentQuery {
+            page_size: 3,
+            from: Some(1704153600),
+            to: Some(1704153600),
+            ..Default::default()
+        };
+        let page = store.get_chat_payments_page(chat_id, &query).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.payments, expected[1..3].to_vec());
+        assert_eq!(page.next, None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_ledger_update() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_ledger_update";
+
+        for username in ["Test_User_1", "Test_User_2"] {
+            store.update_user(username, chat_id, None).await.unwrap();
+        }
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            spendings: vec![balance("Test_User_2", "USD", 200)],
+            categories: vec![first.category_total()],
+            balances: vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -200),
+            ],
+            default_currency: Some("USD".to_string()),
+            currency_conversion: Some(true),
+            recurring: None,
+            actor: None,
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment, first);
+        assert_eq!(
+            store.retrieve_chat_categories(chat_id).await.unwrap(),
+            vec![first.category_total()]
+        );
+        assert_eq!(store.get_default_currency(chat_id).await.unwrap(), "USD");
+        assert!(store.get_currency_conversion(chat_id).await.unwrap());
+
+        // A failing change leaves everything untouched, including earlier changes
+        let update = LedgerUpdate {
+            payments: vec![
+                PaymentChange::Delete(payments[0].payment_id.clone()),
+                PaymentChange::Add(payment("Test_User_2", 100, vec![])),
+            ],
+            spendings: vec![balance("Test_User_2", "USD", -300)],
+            balances: vec![balance("Test_User_1", "USD", -200)],
+            default_currency: Some("EUR".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await.unwrap(),
+            payments
+        );
+        assert_eq!(
+            store.retrieve_chat_spendings(chat_id).await.unwrap(),
+            vec![vec![balance("Test_User_2", "USD", 200)]]
+        );
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap(),
+            vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -200),
+            ]
+        );
+        assert_eq!(store.get_default_currency(chat_id).await.unwrap(), "USD");
+
+        // Category totals cannot turn negative either
+        let update = LedgerUpdate {
+            categories: vec![CategoryTotal {
+                spending: -300,
+                ..first.category_total()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.retrieve_chat_categories(chat_id).await.unwrap(),
+            vec![first.category_total()]
+        );
+
+        // Missing payments fail the whole update as well
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Delete("missing_payment".to_string())],
+            balances: vec![balance("Test_User_1", "USD", -200)],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap()[0],
+            balance("Test_User_1", "USD", 200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trash_restore_purge_payment() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_trash";
+
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        let payment_id = payments[0].payment_id.clone();
+
+        // Trashed payments are hidden, but kept in the trash
+        let trash = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: payment_id.clone(),
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, trash.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+        assert_eq!(
+            store.get_chat_trash_details(chat_id).await.unwrap(),
+            vec![TrashedPayment {
+                payment_id: payment_id.clone(),
+                payment: first,
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }]
+        );
+        assert_eq!(
+            store.apply_ledger_update(chat_id, trash.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Restored payments are shown again
+        let restore = LedgerUpdate {
+            payments: vec![PaymentChange::Restore(payment_id.clone())],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, restore.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await.unwrap(),
+            payments
+        );
+        assert!(store
+            .get_chat_trash_details(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.apply_ledger_update(chat_id, restore).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Only trashed payments can be purged
+        let purge = LedgerUpdate {
+            payments: vec![PaymentChange::Purge(payment_id.clone())],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, purge.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        store.apply_ledger_update(chat_id, trash).await.unwrap();
+        store.apply_ledger_update(chat_id, purge).await.unwrap();
+        assert!(store
+            .get_chat_trash_details(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_history() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_history";
+        let actor = |timestamp: i64| LedgerActor {
+            user_id: "123456789".to_string(),
+            username: "Test_User_1".to_string(),
+            timestamp,
+        };
+
+        // Updates without an actor are not recorded
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let untracked_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert!(store
+            .get_payment_history(&untracked_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            actor: Some(actor(1700000000)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: Some("edited_payment".to_string()),
+                timestamp: Some(1703980800),
+                creditor: None,
+                currency: None,
+                total: Some(300),
+                debts: Some(vec![("Test_User_2".to_string(), 300)]),
+                items: None,
+                category: None,
+                payers: None,
+            }],
+            actor: Some(actor(1700000001)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let edited = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(edited.timestamp, 1703980800);
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: payment_id.clone(),
+                deleted_at: 1700000002,
+                deleted_by: "@Test_User_1".to_string(),
+            }],
+            actor: Some(actor(1700000002)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        // History is kept even after the payment is gone
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Purge(payment_id.clone())],
+            actor: Some(actor(1700000003)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        let history = vec![
+            actor(1700000000).event(&payment_id, PaymentAction::Add, None, Some(first.clone())),
+            actor(1700000001).event(
+                &payment_id,
+                PaymentAction::Edit,
+                Some(first.clone()),
+                Some(edited.clone()),
+            ),
+            actor(1700000002).event(
+                &payment_id,
+                PaymentAction::Delete,
+                Some(edited.clone()),
+                None,
+            ),
+            actor(1700000003).event(&payment_id, PaymentAction::Purge, Some(edited), None),
+        ];
+        assert_eq!(
+            store.get_payment_history(&payment_id).await.unwrap(),
+            history
+        );
+
+        // Chat activity is latest first
+        let activity: Vec<PaymentEvent> = history.into_iter().rev().take(3).collect();
+        assert_eq!(store.get_chat_activity(chat_id, 3).await.unwrap(), activity);
+        assert_eq!(store.get_chat_activity(chat_id, 10).await.unwrap().len(), 4);
+        assert!(store
+            .get_chat_activity("sqlite_history_empty", 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reset_chat_forget_user() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_reset";
+        let other_chat_id = "sqlite_reset_other";
+        let actor = LedgerActor {
+            user_id: "123456789".to_string(),
+            username: "Test_User_1".to_string(),
+            timestamp: 1700000000,
+        };
+
+        for chat_id in [chat_id, other_chat_id] {
+            store
+                .update_chat(
+                    chat_id,
+                    vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+                )
+                .await
+                .unwrap();
+            let update = LedgerUpdate {
+                payments: vec![PaymentChange::Add(payment(
+                    "Test_User_1",
+                    200,
+                    vec![("Test_User_2".to_string(), 200)],
+                ))],
+                spendings: vec![balance("Test_User_2", "USD", 200)],
+                balances: vec![
+                    balance("Test_User_1", "USD", 200),
+                    balance("Test_User_2", "USD", -200),
+                ],
+                actor: Some(actor.clone()),
+                ..Default::default()
+            };
+            store.apply_ledger_update(chat_id, update).await.unwrap();
+        }
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        store
+            .set_time_zone(chat_id, "Asia/Singapore")
+            .await
+            .unwrap();
+
+        store.reset_chat(chat_id).await.unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+        assert!(store
+            .get_payment_history(&payment_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(store.get_chat_balances(chat_id).await.unwrap().is_empty());
+        assert!(store
+            .retrieve_chat_spendings(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.get_time_zone(chat_id).await.unwrap(), "UTC");
+        assert_eq!(
+            store.get_user_chats("test_user_1").await.unwrap(),
+            vec![other_chat_id.to_string()]
+        );
+        assert_eq!(
+            store
+                .get_chat_activity(other_chat_id, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Spendings are kept under the lowercase username once forgotten
+        store.forget_user("Test_User_2").await.unwrap();
+        assert!(store
+            .get_user_chats("Test_User_2")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.retrieve_chat_spendings(other_chat_id).await.unwrap(),
+            vec![vec![]]
+        );
+        store
+            .update_chat(other_chat_id, vec!["Test_User_2".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.retrieve_chat_spendings(other_chat_id).await.unwrap(),
+            vec![vec![balance("test_user_2", "USD", 200)]]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migrate_chat() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_migrate";
+        let new_chat_id = "sqlite_migrate_new";
+
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment(
+                "Test_User_1",
+                200,
+                vec![("Test_User_2".to_string(), 200)],
+            ))],
+            spendings: vec![balance("Test_User_2", "USD", 200)],
+            balances: vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -200),
+            ],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        store
+            .set_time_zone(chat_id, "Asia/Singapore")
+            .await
+            .unwrap();
+        store.set_dialogue(chat_id, "1", "dialogue").await.unwrap();
+
+        // Records already under the new chat ID are replaced
+        store
+            .update_chat(new_chat_id, vec!["Test_User_3".to_string()])
+            .await
+            .unwrap();
+        store.set_dialogue(new_chat_id, "1", "other").await.unwrap();
+        store.set_dialogue(new_chat_id, "2", "other").await.unwrap();
+
+        store.migrate_chat(chat_id, new_chat_id).await.unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+        assert_eq!(
+            store
+                .get_chat_payments_details(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            store.retrieve_chat_spendings(new_chat_id).await.unwrap(),
+            vec![vec![balance("Test_User_2", "USD", 200)]]
+        );
+        assert_eq!(
+            store.get_time_zone(new_chat_id).await.unwrap(),
+            "Asia/Singapore"
+        );
+        assert_eq!(
+            store.get_dialogue(new_chat_id, "1").await,
+            Ok(Some("dialogue".to_string()))
+        );
+        assert_eq!(store.get_dialogue(chat_id, "1").await, Ok(None));
+        assert_eq!(store.get_dialogue(new_chat_id, "2").await, Ok(None));
+        assert_eq!(
+            store.get_user_chats("Test_User_1").await.unwrap(),
+            vec![new_chat_id.to_string()]
+        );
+        assert!(store
+            .get_user_chats("Test_User_3")
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Migrating again does nothing, as the old chat is gone
+        store.migrate_chat(chat_id, new_chat_id).await.unwrap();
+        assert_eq!(
+            store
+                .get_chat_payments_details(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_track_rename_user() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_rename";
+
+        assert_eq!(store.get_tracked_username("123456789").await.unwrap(), None);
+        store
+            .track_username("123456789", "Test_User_2")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_tracked_username("123456789").await.unwrap(),
+            Some("Test_User_2".to_string())
+        );
+
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment(
+                "Test_User_2",
+                200,
+                vec![("Test_User_1".to_string(), 200)],
+            ))],
+            spendings: vec![balance("Test_User_1", "USD", 200)],
+            balances: vec![
+                balance("Test_User_1", "USD", -200),
+                balance("Test_User_2", "USD", 200),
+            ],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        // Renaming moves payments, balances and chats to the new username
+        store
+            .rename_user("Test_User_2", "New_User_2")
+            .await
+            .unwrap();
+        store
+            .track_username("123456789", "New_User_2")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_tracked_username("123456789").await.unwrap(),
+            Some("New_User_2".to_string())
+        );
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments[0].payment.creditor, "New_User_2");
+        assert!(store
+            .get_user_chats("Test_User_2")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_user_chats("New_User_2").await.unwrap(),
+            vec![chat_id.to_string()]
+        );
+        let balances: Vec<UserBalance> = store
+            .get_chat_balances(chat_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert!(balances.contains(&balance("New_User_2", "USD", 200)));
+        assert!(!balances
+            .iter()
+            .any(|balance| balance.username == "Test_User_2"));
+    }
+
+    #[tokio::test]
+    async fn test_set_get_member_names() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let member = "#123456789";
+
+        let members = vec![member.to_string(), "#987654321".to_string()];
+
+        assert!(store.get_member_names(&members).await.unwrap().is_empty());
+        store.set_member_name(member, "Test User").await.unwrap();
+        store.set_member_name(member, "Renamed User").await.unwrap();
+        store
+            .set_member_name("#555555555", "Other User")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_member_names(&members).await.unwrap(),
+            HashMap::from([(member.to_string(), "Renamed User".to_string())])
+        );
+        assert!(store.get_member_names(&[]).await.unwrap().is_empty());
+
+        // Forgetting a member also forgets their name
+        store.forget_user(member).await.unwrap();
+        assert!(store.get_member_names(&members).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_is_request_limit_exceeded() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let user_id = "sqlite_request_user";
+
+        assert!(!store.is_request_limit_exceeded(user_id, 100).await.unwrap());
+        assert!(store.is_request_limit_exceeded(user_id, 100).await.unwrap());
+        assert!(!store.is_request_limit_exceeded(user_id, 101).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_set_get_delete_dialogue() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        let chat_id = "sqlite_chat_dialogue";
+
+        assert_eq!(store.get_dialogue(chat_id, "1").await, Ok(None));
+        store.set_dialogue(chat_id, "1", "first").await.unwrap();
+        store.set_dialogue(chat_id, "1", "second").await.unwrap();
+        store.set_dialogue(chat_id, "2", "other").await.unwrap();
+        assert_eq!(
+            store.get_dialogue(chat_id, "1").await,
+            Ok(Some("second".to_string()))
+        );
+        assert_eq!(
+            store.get_dialogue(chat_id, "2").await,
+            Ok(Some("other".to_string()))
+        );
+
+        store.delete_dialogue(chat_id, "1").await.unwrap();
+        assert_eq!(store.get_dialogue(chat_id, "1").await, Ok(None));
+        assert_eq!(
+            store.get_dialogue(chat_id, "2").await,
+            Ok(Some("other".to_string()))
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
+
+use crate::bot::redis::CrudError;
+
+use super::Store;
+
+/* Dialogue Storage
+ * DialogueStorage keeps the dialogue state of every user in every chat in the configured store,
+ * so that flows in progress survive a restart of the bot.
+ * States are serialized to JSON together with a version tag.
+ * A stored state with a different version, or one that no longer parses,
+ * is discarded, and the user simply starts over from the default state.
+ */
+
+#[derive(thiserror::Error, Debug)]
+pub enum DialogueStorageError {
+    #[error("Store error: {0}")]
+    StoreError(CrudError),
+    #[error("Serialization error: {0}")]
+    SerializationError(serde_json::Error),
+}
+
+impl From<CrudError> for DialogueStorageError {
+    fn from(crud_error: CrudError) -> DialogueStorageError {
+        DialogueStorageError::StoreError(crud_error)
+    }
+}
+
+impl From<serde_json::Error> for DialogueStorageError {
+    fn from(serde_error: serde_json::Error) -> DialogueStorageError {
+        DialogueStorageError::SerializationError(serde_error)
+    }
+}
+
+// A dialogue state as written to the store
+#[derive(Serialize)]
+struct StoredDialogue<'a, D> {
+    version: u32,
+    state: &'a D,
+}
+
+// Only the version is read first, so that stale states are never parsed as the current one
+#[derive(Deserialize)]
+struct StoredDialogueVersion {
+    version: u32,
+}
+
+#[derive(Deserialize)]
+struct StoredDialogueState<D> {
+    state: D,
+}
+
+// Identifies a dialogue, each user has a dialogue of their own in every chat
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DialogueKey {
+    pub chat_id: ChatId,
+    pub user_id: UserId,
+}
+
+pub struct DialogueStorage {
+    store: Store,
+    version: u32,
+}
+
+impl DialogueStorage {
+    pub fn new(store: Store, version: u32) -> Arc<Self> {
+        Arc::new(DialogueStorage { store, version })
+    }
+
+    fn serialize<D: Serialize>(&self, state: &D) -> Result<String, DialogueStorageError> {
+        let stored = StoredDialogue {
+            version: self.version,
+            state,
+        };
+        Ok(serde_json::to_string(&stored)?)
+    }
+
+    // Returns None if the stored state is stale or unreadable
+    fn deserialize<D: DeserializeOwned>(&self, key: DialogueKey, stored: &str) -> Option<D> {
+        let DialogueKey { chat_id, user_id } = key;
+        match serde_json::from_str::<StoredDialogueVersion>(stored) {
+            Ok(StoredDialogueVersion { version }) if version == self.version => {}
+            Ok(StoredDialogueVersion { version }) => {
+                log::info!(
+                    "Discarding dialogue of user {user_id} in chat {chat_id} from version {version}"
+                );
+                return None;
+            }
+            Err(err) => {
+                log::warn!(
+                    "Discarding unreadable dialogue of user {user_id} in chat {chat_id}: {err}"
+                );
+                return None;
+            }
+        }
+
+        match serde_json::from_str::<StoredDialogueState<D>>(stored) {
+            Ok(StoredDialogueState { state }) => Some(state),
+            Err(err) => {
+                log::warn!(
+                    "Discarding unreadable dialogue of user {user_id} in chat {chat_id}: {err}"
+                );
+                None
+            }
+        }
+    }
+
+    pub async fn get_dialogue<D: DeserializeOwned>(
+        &self,
+        key: DialogueKey,
+    ) -> Result<Option<D>, DialogueStorageError> {
+        let (chat_id, user_id) = (key.chat_id.to_string(), key.user_id.to_string());
+        let stored = match self.store.get_dialogue(&chat_id, &user_id).await? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+
+        let state = self.deserialize(key, &stored);
+        if state.is_none() {
+            self.store.delete_dialogue(&chat_id, &user_id).await?;
+        }
+        Ok(state)
+    }
+
+    pub async fn update_dialogue<D: Serialize>(
+        &self,
+        key: DialogueKey,
+        state: &D,
+    ) -> Result<(), DialogueStorageError> {
+        let stored = self.serialize(state)?;
+        self.store
+            .set_dialogue(&key.chat_id.to_string(), &key.user_id.to_string(), &stored)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_dialogue(&self, key: DialogueKey) -> Result<(), DialogueStorageError> {
+        self.store
+            .delete_dialogue(&key.chat_id.to_string(), &key.user_id.to_string())
+            .await?;
+        Ok(())
+    }
+}
+
+/* Dialogue is a handle on the dialogue of a single user in a single chat.
+ * Handlers receive one for the user who sent the update,
+ * so that updates from other members never advance or block it.
+ */
+pub struct Dialogue<D> {
+    storage: Arc<DialogueStorage>,
+    key: DialogueKey,
+    _state: PhantomData<fn() -> D>,
+}
+
+// Derived Clone would require D: Clone
+impl<D> Clone for Dialogue<D> {
+    fn clone(&self) -> Self {
+        Dialogue {
+            storage: self.storage.clone(),
+            key: self.key,
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<D> Dialogue<D>
+where
+    D: Serialize + DeserializeOwned + Default,
+{
+    pub fn new(storage: Arc<DialogueStorage>, key: DialogueKey) -> Self {
+        Dialogue {
+            storage,
+            key,
+            _state: PhantomData,
+        }
+    }
+
+    pub async fn get(&self) -> Result<Option<D>, DialogueStorageError> {
+        self.storage.get_dialogue(self.key).await
+    }
+
+    pub async fn get_or_default(&self) -> Result<D, DialogueStorageError> {
+        Ok(self.get().await?.unwrap_or_default())
+    }
+
+    pub async fn update(&self, state: D) -> Result<(), DialogueStorageError> {
+        self.storage.update_dialogue(self.key, &state).await
+    }
+
+    pub async fn exit(&self) -> Result<(), DialogueStorageError> {
+        self.storage.remove_dialogue(self.key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::bot::store::MemoryStore;
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+    enum TestState {
+        #[default]
+        Start,
+        Step {
+            messages: Vec<i32>,
+            total: Option<i64>,
+        },
+    }
+
+    fn key(chat_id: i64, user_id: u64) -> DialogueKey {
+        DialogueKey {
+            chat_id: ChatId(chat_id),
+            user_id: UserId(user_id),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_get_exit_dialogue() {
+        let storage = DialogueStorage::new(Arc::new(MemoryStore::new()), 1);
+        let dialogue: Dialogue<TestState> = Dialogue::new(storage, key(-100123, 1));
+
+        assert_eq!(dialogue.get().await.unwrap(), None);
+        assert_eq!(dialogue.get_or_default().await.unwrap(), TestState::Start);
+
+        let state = TestState::Step {
+            messages: vec![1, 2],
+            total: Some(1000),
+        };
+        dialogue.update(state).await.unwrap();
+        assert_eq!(
+            dialogue.get().await.unwrap(),
+            Some(TestState::Step {
+                messages: vec![1, 2],
+                total: Some(1000),
+            })
+        );
+
+        dialogue.exit().await.unwrap();
+        assert_eq!(dialogue.get().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dialogues_per_user() {
+        let storage = DialogueStorage::new(Arc::new(MemoryStore::new()), 1);
+        let first: Dialogue<TestState> = Dialogue::new(storage.clone(), key(-100123, 1));
+        let second: Dialogue<TestState> = Dialogue::new(storage.clone(), key(-100123, 2));
+        let elsewhere: Dialogue<TestState> = Dialogue::new(storage, key(-100789, 1));
+
+        first
+            .update(TestState::Step {
+                messages: vec![1],
+                total: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(second.get().await.unwrap(), None);
+        assert_eq!(elsewhere.get().await.unwrap(), None);
+
+        second
+            .update(TestState::Step {
+                messages: vec![2],
+                total: Some(500),
+            })
+            .await
+            .unwrap();
+        first.exit().await.unwrap();
+        assert_eq!(first.get().await.unwrap(), None);
+        assert_eq!(
+            second.get().await.unwrap(),
+            Some(TestState::Step {
+                messages: vec![2],
+                total: Some(500),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discard_stale_dialogue() {
+        let store: Store = Arc::new(MemoryStore::new());
+        let key = key(-100456, 1);
+
+        // Written by an older version of the bot
+        let old_storage = DialogueStorage::new(store.clone(), 1);
+        old_storage
+            .update_dialogue(key, &TestState::Start)
+            .await
+            .unwrap();
+
+        let storage = DialogueStorage::new(store.clone(), 2);
+        let dialogue: Option<TestState> = storage.get_dialogue(key).await.unwrap();
+        assert_eq!(dialogue, None);
+        assert_eq!(store.get_dialogue("-100456", "1").await, Ok(None));
+
+        // Unreadable states are discarded as well
+        store
+            .set_dialogue("-100456", "1", "{\"version\":2,\"state\":\"Gone\"}")
+            .await
+            .unwrap();
+        let dialogue: Option<TestState> = storage.get_dialogue(key).await.unwrap();
+        assert_eq!(dialogue, None);
+        assert_eq!(store.get_dialogue("-100456", "1").await, Ok(None));
+    }
+}
@@ -0,0 +1,2053 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::bot::redis::{
+    CategoryTotal, ChatRecurringPayment, CrudError, LedgerUpdate, Payment, PaymentAction,
+    PaymentChange, PaymentCursor, PaymentEvent, PaymentItem, PaymentPage, PaymentQuery,
+    RecurringPayment, TrashedPayment, UserBalance, UserPayment, CURRENCY_CODE_DEFAULT,
+};
+
+use super::LedgerStore;
+
+/* Memory Store
+ * MemoryStore keeps the whole ledger in process memory.
+ * It mirrors the behaviour of the Redis manager operation by operation,
+ * and is meant for tests and local runs without a database.
+ * Nothing is persisted, all data is lost when the store is dropped.
+ */
+
+// Key of a balance or spending: (chat_id, user_key, currency)
+type LedgerKey = (String, String, String);
+
+#[derive(Debug, Default, Clone)]
+struct ChatSettings {
+    time_zone: Option<String>,
+    default_currency: Option<String>,
+    currency_conversion: Option<bool>,
+    erase_messages: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct MemoryData {
+    user_chats: HashMap<String, Vec<String>>,
+    usernames: HashMap<String, String>,
+    user_ids: HashMap<String, String>,
+    member_names: HashMap<String, String>,
+    chat_users: HashMap<String, Vec<String>>,
+    chat_payments: HashMap<String, Vec<String>>,
+    chat_currencies: HashMap<String, Vec<String>>,
+    chat_settings: HashMap<String, ChatSettings>,
+    payments: HashMap<String, Payment>,
+    chat_trash: HashMap<String, Vec<String>>,
+    trash: HashMap<String, (i64, String)>,
+    payment_events: HashMap<String, Vec<PaymentEvent>>,
+    chat_events: HashMap<String, Vec<PaymentEvent>>,
+    recurring: HashMap<String, ChatRecurringPayment>,
+    balances: HashMap<LedgerKey, i64>,
+    spendings: HashMap<LedgerKey, u64>,
+    // Keyed by (chat_id, category key, currency) instead of a user
+    categories: HashMap<LedgerKey, CategoryTotal>,
+    dialogues: HashMap<(String, String), String>,
+    requests: HashMap<String, i64>,
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemoryData> {
+        // A poisoned lock only means another thread panicked mid-operation
+        self.data.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl MemoryData {
+    fn settings(&mut self, chat_id: &str) -> &mut ChatSettings {
+        self.chat_settings.entry(chat_id.to_string()).or_default()
+    }
+
+    fn preferred_username(&self, user_key: &str) -> String {
+        self.usernames
+            .get(user_key)
+            .cloned()
+            .unwrap_or(user_key.to_string())
+    }
+
+    fn chat_users(&self, chat_id: &str) -> Vec<String> {
+        self.chat_users.get(chat_id).cloned().unwrap_or_default()
+    }
+
+    fn chat_currencies(&self, chat_id: &str) -> Vec<String> {
+        self.chat_currencies
+            .get(chat_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn balances_currency(&self, chat_id: &str, currency: &str) -> Vec<UserBalance> {
+        let mut balances: Vec<UserBalance> = Vec::new();
+        for user in self.chat_users(chat_id) {
+            let key = (chat_id.to_string(), user.clone(), currency.to_string());
+            if let Some(balance) = self.balances.get(&key) {
+                if *balance != 0 {
+                    balances.push(UserBalance {
+                        username: self.preferred_username(&user),
+                        currency: currency.to_string(),
+                        balance: *balance,
+                    });
+                }
+            }
+        }
+        balances
+    }
+
+    fn spendings_currency(&self, chat_id: &str, currency: &str) -> Vec<UserBalance> {
+        let mut spendings: Vec<UserBalance> = Vec::new();
+        for user in self.chat_users(chat_id) {
+            let key = (chat_id.to_string(), user.clone(), currency.to_string());
+            if let Some(spending) = self.spendings.get(&key) {
+                if *spending != 0 {
+                    spendings.push(UserBalance {
+                        username: self.preferred_username(&user),
+                        currency: currency.to_string(),
+                        balance: *spending as i64,
+                    });
+                }
+            }
+        }
+        spendings
+    }
+
+    fn update_balances(&mut self, chat_id: &str, changes: Vec<UserBalance>) {
+        for change in changes {
+            let currencies = self.chat_currencies.entry(chat_id.to_string()).or_default();
+            if !currencies.contains(&change.currency) {
+                currencies.push(change.currency.clone());
+            }
+
+            let key = (
+                chat_id.to_string(),
+                change.username.to_lowercase(),
+                change.currency,
+            );
+            *self.balances.entry(key).or_insert(0) += change.balance;
+        }
+    }
+
+    fn update_spendings(
+        &mut self,
+        chat_id: &str,
+        spendings: Vec<UserBalance>,
+    ) -> Result<(), CrudError> {
+        for spending in spendings {
+            let key = (
+                chat_id.to_string(),
+                spending.username.to_lowercase(),
+                spending.currency,
+            );
+            let amount = self.spendings.get(&key).copied().unwrap_or(0) as i64 + spending.balance;
+            if amount < 0 {
+                return Err(CrudError::NegativeSpendingError());
+            }
+            self.spendings.insert(key, amount as u64);
+        }
+        Ok(())
+    }
+
+    fn update_categories(
+        &mut self,
+        chat_id: &str,
+        changes: Vec<CategoryTotal>,
+    ) -> Result<(), CrudError> {
+        for change in changes {
+            let key = (chat_id.to_string(), change.key(), change.currency.clone());
+            let mut total = self.categories.remove(&key).unwrap_or(CategoryTotal {
+                spending: 0,
+                ..change.clone()
+            });
+            total.add(&change);
+            if total.spending < 0 {
+                return Err(CrudError::NegativeSpendingError());
+            }
+            if total.spending != 0 {
+                self.categories.insert(key, total);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_payment(&mut self, chat_id: &str, payment: &Payment) -> String {
+        let payment_id = Uuid::new_v4().to_string();
+        self.payments.insert(payment_id.clone(), payment.clone());
+
+        // Latest payment first, same as LPUSH
+        self.chat_payments
+            .entry(chat_id.to_string())
+            .or_default()
+            .insert(0, payment_id.clone());
+        payment_id
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_payment(
+        &mut self,
+        payment_id: &str,
+        description: Option<&str>,
+        timestamp: Option<i64>,
+        creditor: Option<&str>,
+        currency: Option<&str>,
+        total: Option<&i64>,
+        debts: Option<Vec<(String, i64)>>,
+        items: Option<Vec<PaymentItem>>,
+        category: Option<Option<String>>,
+        payers: Option<Vec<(String, i64)>>,
+    ) -> Result<(), CrudError> {
+        if self.trash.contains_key(payment_id) {
+            return Err(CrudError::NoSuchPaymentError());
+        }
+        let payment = match self.payments.get_mut(payment_id) {
+            Some(payment) => payment,
+            None => return Err(CrudError::NoSuchPaymentError()),
+        };
+
+        if let Some(description) = description {
+            payment.description = description.to_string();
+        }
+        if let Some(timestamp) = timestamp {
+            payment.timestamp = timestamp;
+        }
+        if let Some(creditor) = creditor {
+            payment.creditor = creditor.to_string();
+        }
+        if let Some(currency) = currency {
+            payment.currency = currency.to_string();
+        }
+        if let Some(total) = total {
+            payment.total = *total;
+        }
+        if let Some(debts) = debts {
+            payment.debts = debts;
+        }
+        if let Some(items) = items {
+            payment.items = items;
+        }
+        if let Some(category) = category {
+            payment.category = category;
+        }
+        if let Some(payers) = payers {
+            payment.payers = payers;
+        }
+        Ok(())
+    }
+
+    fn delete_payment(&mut self, chat_id: &str, payment_id: &str) -> Result<(), CrudError> {
+        if self.payments.remove(payment_id).is_none() {
+            return Err(CrudError::NoSuchPaymentError());
+        }
+
+        if let Some(payment_ids) = self.chat_payments.get_mut(chat_id) {
+            payment_ids.retain(|id| id != payment_id);
+        }
+        self.untrash_payment(chat_id, payment_id);
+        Ok(())
+    }
+
+    fn trash_payment(
+        &mut self,
+        chat_id: &str,
+        payment_id: &str,
+        deleted_at: i64,
+        deleted_by: &str,
+    ) -> Result<(), CrudError> {
+        if !self.payments.contains_key(payment_id) || self.trash.contains_key(payment_id) {
+            return Err(CrudError::NoSuchPaymentError());
+        }
+
+        self.trash
+            .insert(payment_id.to_string(), (deleted_at, deleted_by.to_string()));
+        // Latest deletion first, same as LPUSH
+        self.chat_trash
+            .entry(chat_id.to_string())
+            .or_default()
+            .insert(0, payment_id.to_string());
+        Ok(())
+    }
+
+    // Takes a payment out of the trash, returns false if it was not trashed
+    fn untrash_payment(&mut self, chat_id: &str, payment_id: &str) -> bool {
+        if let Some(payment_ids) = self.chat_trash.get_mut(chat_id) {
+            payment_ids.retain(|id| id != payment_id);
+        }
+        self.trash.remove(payment_id).is_some()
+    }
+
+    // Events are kept in order of recording, and never removed
+    fn add_event(&mut self, chat_id: &str, event: PaymentEvent) {
+        self.payment_events
+            .entry(event.payment_id.clone())
+            .or_default()
+            .push(event.clone());
+        self.chat_events
+            .entry(chat_id.to_string())
+            .or_default()
+            .push(event);
+    }
+
+    fn apply_ledger_update(
+        &mut self,
+        chat_id: &str,
+        update: LedgerUpdate,
+    ) -> Result<(), CrudError> {
+        for change in update.payments {
+            let action = change.action();
+            let payment_id = match &change {
+                PaymentChange::Add(payment) => self.add_payment(chat_id, payment),
+                PaymentChange::Update { payment_id, .. }
+                | PaymentChange::Delete(payment_id)
+                | PaymentChange::Trash { payment_id, .. }
+                | PaymentChange::Restore(payment_id)
+                | PaymentChange::Purge(payment_id) => payment_id.clone(),
+            };
+            let before = self.payments.get(&payment_id).cloned();
+
+            match change {
+                PaymentChange::Add(_) => {}
+                PaymentChange::Update {
+                    payment_id,
+                    description,
+                    timestamp,
+                    creditor,
+                    currency,
+                    total,
+                    debts,
+                    items,
+                    category,
+                    payers,
+                } => self.update_payment(
+                    &payment_id,
+                    description.as_deref(),
+                    timestamp,
+                    creditor.as_deref(),
+                    currency.as_deref(),
+                    total.as_ref(),
+                    debts,
+                    items,
+                    category,
+                    payers,
+                )?,
+                PaymentChange::Delete(payment_id) => self.delete_payment(chat_id, &payment_id)?,
+                PaymentChange::Trash {
+                    payment_id,
+                    deleted_at,
+                    deleted_by,
+                } => self.trash_payment(chat_id, &payment_id, deleted_at, &deleted_by)?,
+                PaymentChange::Restore(payment_id) => {
+                    if !self.untrash_payment(chat_id, &payment_id) {
+                        return Err(CrudError::NoSuchPaymentError());
+                    }
+                }
+                PaymentChange::Purge(payment_id) => {
+                    if !self.trash.contains_key(&payment_id) {
+                        return Err(CrudError::NoSuchPaymentError());
+                    }
+                    self.delete_payment(chat_id, &payment_id)?;
+                }
+            }
+
+            if let Some(actor) = &update.actor {
+                let after = self.payments.get(&payment_id).cloned();
+                let (before, after) = match action {
+                    PaymentAction::Add | PaymentAction::Restore => (None, after),
+                    PaymentAction::Edit => (before, after),
+                    PaymentAction::Delete | PaymentAction::Purge => (before, None),
+                };
+                self.add_event(chat_id, actor.event(&payment_id, action, before, after));
+            }
+        }
+
+        self.update_spendings(chat_id, update.spendings)?;
+        self.update_categories(chat_id, update.categories)?;
+        self.update_balances(chat_id, update.balances);
+
+        if let Some(currency) = update.default_currency {
+            self.settings(chat_id).default_currency = Some(currency);
+        }
+        if let Some(conversion) = update.currency_conversion {
+            self.settings(chat_id).currency_conversion = Some(conversion);
+        }
+
+        // Recurring payment, only if it is still as it was read
+        if let Some(change) = update.recurring {
+            match self.recurring.get_mut(&change.recurring_id) {
+                Some(recurring) if recurring.chat_id == chat_id => {
+                    if recurring.recurring != change.current {
+                        return Err(CrudError::RecurringChangedError());
+                    }
+                    match change.next {
+                        Some(next) => recurring.recurring.next = next,
+                        None => {
+                            self.recurring.remove(&change.recurring_id);
+                        }
+                    }
+                }
+                _ => return Err(CrudError::NoSuchRecurringPaymentError()),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LedgerStore for MemoryStore {
+    async fn update_user(
+        &self,
+        username: &str,
+        chat_id: &str,
+        _user_id: Option<&str>,
+    ) -> Result<(), CrudError> {
+        let mut data = self.lock();
+        let user_key = username.to_lowercase();
+
+        if !data.user_chats.contains_key(&user_key) {
+            data.usernames
+                .insert(user_key.clone(), username.to_string());
+        }
+
+        let chats = data.user_chats.entry(user_key).or_default();
+        if !chats.contains(&chat_id.to_string()) {
+            chats.push(chat_id.to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn update_chat(&self, chat_id: &str, usernames: Vec<String>) -> Result<(), CrudError> {
+        let mut data = self.lock();
+
+        // Same as Redis, a chat only exists once it has at least one user
+        if data.chat_users(chat_id).is_empty() {
+            *data.settings(chat_id) = ChatSettings {
+                time_zone: Some("UTC".to_string()),
+                default_currency: Some(CURRENCY_CODE_DEFAULT.to_string()),
+                currency_conversion: Some(false),
+                erase_messages: Some(true),
+            };
+        }
+
+        let users = data.chat_users.entry(chat_id.to_string()).or_default();
+        for username in usernames {
+            let user_key = username.to_lowercase();
+            if !users.contains(&user_key) {
+                users.push(user_key);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_user_chats(&self, username: &str) -> Result<Vec<String>, CrudError> {
+        let data = self.lock();
+        Ok(data
+            .user_chats
+            .get(&username.to_lowercase())
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_chat_users(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        Ok(self
+            .lock()
+            .chat_users
+            .get(chat_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn forget_user(&self, username: &str) -> Result<(), CrudError> {
+        let mut data = self.lock();
+        let user_key = username.to_lowercase();
+
+        for chat_id in data.user_chats.remove(&user_key).unwrap_or_default() {
+            if let Some(users) = data.chat_users.get_mut(&chat_id) {
+                users.retain(|user| *user != user_key);
+            }
+        }
+        data.usernames.remove(&user_key);
+        data.member_names.remove(&user_key);
+
+        Ok(())
+    }
+
+    async fn reset_chat(&self, chat_id: &str) -> Result<(), CrudError> {
+        let mut data = self.lock();
+
+        for payment_id in data.chat_payments.remove(chat_id).unwrap_or_default() {
+            data.payments.remove(&payment_id);
+            data.trash.remove(&payment_id);
+        }
+        data.chat_trash.remove(chat_id);
+
+        // Includes the history of payments which were already purged
+        for event in data.chat_events.remove(chat_id).unwrap_or_default() {
+            data.payment_events.remove(&event.payment_id);
+        }
+
+        data.recurring
+            .retain(|_, recurring| recurring.chat_id != chat_id);
+        data.balances.retain(|(chat, _, _), _| chat != chat_id);
+        data.spendings.retain(|(chat, _, _), _| chat != chat_id);
+        data.categories.retain(|(chat, _, _), _| chat != chat_id);
+        data.chat_currencies.remove(chat_id);
+        data.chat_settings.remove(chat_id);
+
+        for user in data.chat_users.remove(chat_id).unwrap_or_default() {
+            if let Some(chats) = data.user_chats.get_mut(&user) {
+                chats.retain(|chat| chat != chat_id);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn migrate_chat(&self, chat_id: &str, new_chat_id: &str) -> Result<(), CrudError> {
+        let is_exists = {
+            let data = self.lock();
+            data.chat_users.contains_key(chat_id)
+                || data.chat_payments.contains_key(chat_id)
+                || data.chat_settings.contains_key(chat_id)
+        };
+        if !is_exists || chat_id == new_chat_id {
+            return Ok(());
+        }
+        self.reset_chat(new_chat_id).await?;
+
+        let mut data = self.lock();
+        let new_chat = new_chat_id.to_string();
+        if let Some(users) = data.chat_users.remove(chat_id) {
+            for user in &users {
+                if let Some(chats) = data.user_chats.get_mut(user) {
+                    chats.retain(|chat| chat != chat_id);
+                    chats.push(new_chat.clone());
+                }
+            }
+            data.chat_users.insert(new_chat.clone(), users);
+        }
+        if let Some(payments) = data.chat_payments.remove(chat_id) {
+            data.chat_payments.insert(new_chat.clone(), payments);
+        }
+        if let Some(currencies) = data.chat_currencies.remove(chat_id) {
+            data.chat_currencies.insert(new_chat.clone(), currencies);
+        }
+        if let Some(settings) = data.chat_settings.remove(chat_id) {
+            data.chat_settings.insert(new_chat.clone(), settings);
+        }
+        if let Some(trash) = data.chat_trash.remove(chat_id) {
+            data.chat_trash.insert(new_chat.clone(), trash);
+        }
+        if let Some(events) = data.chat_events.remove(chat_id) {
+            data.chat_events.insert(new_chat.clone(), events);
+        }
+        for recurring in data.recurring.values_mut() {
+            if recurring.chat_id == chat_id {
+                recurring.chat_id = new_chat.clone();
+            }
+        }
+
+        data.balances = std::mem::take(&mut data.balances)
+            .into_iter()
+            .map(|((chat, user, currency), balance)| {
+                let chat = if chat == chat_id {
+                    new_chat.clone()
+                } else {
+                    chat
+                };
+                ((chat, user, currency), balance)
+            })
+            .collect();
+        data.spendings = std::mem::take(&mut data.spendings)
+            .into_iter()
+            .map(|((chat, user, currency), spending)| {
+                let chat = if chat == chat_id {
+                    new_chat.clone()
+                } else {
+                    chat
+                };
+                ((chat, user, currency), spending)
+            })
+            .collect();
+        data.categories = std::mem::take(&mut data.categories)
+            .into_iter()
+            .map(|((chat, category, currency), total)| {
+                let chat = if chat == chat_id {
+                    new_chat.clone()
+                } else {
+                    chat
+                };
+                ((chat, category, currency), total)
+            })
+            .collect();
+        // Dialogues are not cleared by a reset, so those under the new chat ID are replaced here
+        data.dialogues.retain(|(chat, _), _| chat != new_chat_id);
+        data.dialogues = std::mem::take(&mut data.dialogues)
+            .into_iter()
+            .map(|((chat, user_id), dialogue)| {
+                let chat = if chat == chat_id {
+                    new_chat.clone()
+                } else {
+                    chat
+                };
+                ((chat, user_id), dialogue)
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    async fn get_tracked_username(&self, user_id: &str) -> Result<Option<String>, CrudError> {
+        Ok(self.lock().user_ids.get(user_id).cloned())
+    }
+
+    async fn track_username(&self, user_id: &str, username: &str) -> Result<(), CrudError> {
+        self.lock()
+            .user_ids
+            .insert(user_id.to_string(), username.to_string());
+        Ok(())
+    }
+
+    async fn rename_user(&self, old_username: &str, new_username: &str) -> Result<(), CrudError> {
+        let mut data = self.lock();
+        let old_key = old_username.to_lowercase();
+        let new_key = new_username.to_lowercase();
+        let chats = data.user_chats.get(&old_key).cloned().unwrap_or_default();
+
+        for chat_id in &chats {
+            for payment_id in data.chat_payments.get(chat_id).cloned().unwrap_or_default() {
+                if let Some(payment) = data.payments.get_mut(&payment_id) {
+                    if let Some(renamed) = payment.rename_user(old_username, new_username) {
+                        *payment = renamed;
+                    }
+                }
+            }
+            for recurring in data.recurring.values_mut() {
+                if recurring.chat_id != *chat_id {
+                    continue;
+                }
+                let payment = &mut recurring.recurring.payment;
+                if let Some(renamed) = payment.rename_user(old_username, new_username) {
+                    *payment = renamed;
+                }
+            }
+
+            // Only casing has changed, so everything is already under the right key
+            if old_key == new_key {
+                continue;
+            }
+
+            for currency in data.chat_currencies(chat_id) {
+                let old = (chat_id.clone(), old_key.clone(), currency.clone());
+                let new = (chat_id.clone(), new_key.clone(), currency);
+                if let Some(balance) = data.balances.remove(&old) {
+                    *data.balances.entry(new.clone()).or_insert(0) += balance;
+                }
+                if let Some(spending) = data.spendings.remove(&old) {
+                    *data.spendings.entry(new).or_insert(0) += spending;
+                }
+            }
+
+            // Same as Redis, the new username is added to the end of the chat
+            let users = data.chat_users.entry(chat_id.clone()).or_default();
+            users.retain(|user| *user != old_key);
+            if !users.contains(&new_key) {
+                users.push(new_key.clone());
+            }
+        }
+
+        if old_key != new_key {
+            data.user_chats.remove(&old_key);
+            data.usernames.remove(&old_key);
+            data.member_names.remove(&old_key);
+            let new_chats = data.user_chats.entry(new_key.clone()).or_default();
+            for chat_id in chats {
+                if !new_chats.contains(&chat_id) {
+                    new_chats.push(chat_id);
+                }
+            }
+        }
+        data.usernames.insert(new_key, new_username.to_string());
+
+        Ok(())
+    }
+
+    async fn set_member_name(&self, member: &str, name: &str) -> Result<(), CrudError> {
+        self.lock()
+            .member_names
+            .insert(member.to_lowercase(), name.to_string());
+        Ok(())
+    }
+
+    async fn get_member_names(
+        &self,
+        members: &[String],
+    ) -> Result<HashMap<String, String>, CrudError> {
+        let data = self.lock();
+        Ok(members
+            .iter()
+            .filter_map(|member| {
+                let member = member.to_lowercase();
+                let name = data.member_names.get(&member)?.clone();
+                Some((member, name))
+            })
+            .collect())
+    }
+
+    async fn set_time_zone(&self, chat_id: &str, time_zone: &str) -> Result<(), CrudError> {
+        self.lock().settings(chat_id).time_zone = Some(time_zone.to_string());
+        Ok(())
+    }
+
+    async fn get_time_zone(&self, chat_id: &str) -> Result<String, CrudError> {
+        let time_zone = self.lock().settings(chat_id).time_zone.clone();
+        Ok(time_zone.unwrap_or("UTC".to_string()))
+    }
+
+    async fn set_default_currency(&self, chat_id: &str, currency: &str) -> Result<(), CrudError> {
+        self.lock().settings(chat_id).default_currency = Some(currency.to_string());
+        Ok(())
+    }
+
+    async fn get_default_currency(&self, chat_id: &str) -> Result<String, CrudError> {
+        let currency = self.lock().settings(chat_id).default_currency.clone();
+        Ok(currency.unwrap_or(CURRENCY_CODE_DEFAULT.to_string()))
+    }
+
+    async fn set_currency_conversion(
+        &self,
+        chat_id: &str,
+        conversion: bool,
+    ) -> Result<(), CrudError> {
+        self.lock().settings(chat_id).currency_conversion = Some(conversion);
+        Ok(())
+    }
+
+    async fn get_currency_conversion(&self, chat_id: &str) -> Result<bool, CrudError> {
+        let conversion = self.lock().settings(chat_id).currency_conversion;
+        Ok(conversion.unwrap_or(false))
+    }
+
+    async fn set_erase_messages(&self, chat_id: &str, erase: bool) -> Result<(), CrudError> {
+        self.lock().settings(chat_id).erase_messages = Some(erase);
+        Ok(())
+    }
+
+    async fn get_erase_messages(&self, chat_id: &str) -> Result<bool, CrudError> {
+        let erase = self.lock().settings(chat_id).erase_messages;
+        Ok(erase.unwrap_or(true))
+    }
+
+    async fn get_valid_chat_currencies(&self, chat_id: &str) -> Result<Vec<String>, CrudError> {
+        let data = self.lock();
+        let users = data.chat_users(chat_id);
+
+        let valid_currencies = data
+            .chat_currencies(chat_id)
+            .into_iter()
+            .filter(|currency| {
+                users.iter().any(|user| {
+                    let key = (chat_id.to_string(), user.clone(), currency.clone());
+                    data.spendings.get(&key).copied().unwrap_or(0) > 0
+                })
+            })
+            .collect();
+
+        Ok(valid_currencies)
+    }
+
+    async fn get_chat_balances(&self, chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        let data = self.lock();
+        let balances = data
+            .chat_currencies(chat_id)
+            .iter()
+            .map(|currency| data.balances_currency(chat_id, currency))
+            .collect();
+        Ok(balances)
+    }
+
+    async fn get_chat_balances_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        Ok(self.lock().balances_currency(chat_id, currency))
+    }
+
+    async fn get_chat_payments_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<UserPayment>, CrudError> {
+        let data = self.lock();
+        // Trashed payments are kept in the chat, but not shown
+        let payment_ids: Vec<String> = data
+            .chat_payments
+            .get(chat_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|payment_id| !data.trash.contains_key(payment_id))
+            .collect();
+        if payment_ids.is_empty() {
+            return Err(CrudError::NoPaymentsError());
+        }
+
+        let mut payments: Vec<UserPayment> = Vec::new();
+        for payment_id in payment_ids {
+            let payment = match data.payments.get(&payment_id) {
+                Some(payment) => payment.clone(),
+                None => return Err(CrudError::NoSuchPaymentError()),
+            };
+            payments.push(UserPayment {
+                chat_id: chat_id.to_string(),
+                payment_id,
+                payment,
+            });
+        }
+
+        Ok(payments)
+    }
+
+    async fn get_chat_payments_page(
+        &self,
+        chat_id: &str,
+        query: &PaymentQuery,
+    ) -> Result<PaymentPage, CrudError> {
+        let data = self.lock();
+        let mut payments: Vec<UserPayment> = Vec::new();
+        for payment_id in data.chat_payments.get(chat_id).into_iter().flatten() {
+            if data.trash.contains_key(payment_id) {
+                continue;
+            }
+            let payment = match data.payments.get(payment_id) {
+                Some(payment) => payment.clone(),
+                None => return Err(CrudError::NoSuchPaymentError()),
+            };
+            let timestamp = payment.timestamp;
+            if query.from.is_some_and(|from| timestamp < from)
+                || query.to.is_some_and(|to| timestamp > to)
+            {
+                continue;
+            }
+            payments.push(UserPayment {
+                chat_id: chat_id.to_string(),
+                payment_id: payment_id.clone(),
+                payment,
+            });
+        }
+
+        // Latest payment first, same as the Redis index
+        payments.sort_by(|first, second| {
+            (second.payment.timestamp, &second.payment_id)
+                .cmp(&(first.payment.timestamp, &first.payment_id))
+        });
+        let total = payments.len();
+        if let Some(cursor) = &query.cursor {
+            payments
+                .retain(|payment| cursor.is_before(payment.payment.timestamp, &payment.payment_id));
+        }
+
+        let is_more = payments.len() > query.page_size;
+        payments.truncate(query.page_size);
+        let next = match payments.last() {
+            Some(last) if is_more => Some(PaymentCursor::of(&last.payment_id, &last.payment)),
+            _ => None,
+        };
+
+        Ok(PaymentPage {
+            payments,
+            total,
+            next,
+        })
+    }
+
+    async fn get_payment_entry(&self, payment_id: &str) -> Result<Payment, CrudError> {
+        match self.lock().payments.get(payment_id) {
+            Some(payment) => Ok(payment.clone()),
+            None => Err(CrudError::NoSuchPaymentError()),
+        }
+    }
+
+    async fn get_chat_trash_details(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<TrashedPayment>, CrudError> {
+        let data = self.lock();
+        let payment_ids = data.chat_trash.get(chat_id).cloned().unwrap_or_default();
+
+        let mut payments: Vec<TrashedPayment> = Vec::new();
+        for payment_id in payment_ids {
+            let (payment, (deleted_at, deleted_by)) =
+                match (data.payments.get(&payment_id), data.trash.get(&payment_id)) {
+                    (Some(payment), Some(trash)) => (payment.clone(), trash.clone()),
+                    _ => return Err(CrudError::NoSuchPaymentError()),
+                };
+            payments.push(TrashedPayment {
+                payment_id,
+                payment,
+                deleted_at,
+                deleted_by,
+            });
+        }
+
+        Ok(payments)
+    }
+
+    async fn get_payment_history(&self, payment_id: &str) -> Result<Vec<PaymentEvent>, CrudError> {
+        let data = self.lock();
+        Ok(data
+            .payment_events
+            .get(payment_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn get_chat_activity(
+        &self,
+        chat_id: &str,
+        count: usize,
+    ) -> Result<Vec<PaymentEvent>, CrudError> {
+        let data = self.lock();
+        let events = data.chat_events.get(chat_id).cloned().unwrap_or_default();
+
+        // Latest first, same as LPUSH
+        Ok(events.into_iter().rev().take(count).collect())
+    }
+
+    async fn add_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring: &RecurringPayment,
+    ) -> Result<String, CrudError> {
+        let recurring_id = Uuid::new_v4().to_string();
+        self.lock().recurring.insert(
+            recurring_id.clone(),
+            ChatRecurringPayment {
+                recurring_id: recurring_id.clone(),
+                chat_id: chat_id.to_string(),
+                recurring: recurring.clone(),
+            },
+        );
+        Ok(recurring_id)
+    }
+
+    async fn get_chat_recurring_payments(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        let mut recurring_payments: Vec<ChatRecurringPayment> = self
+            .lock()
+            .recurring
+            .values()
+            .filter(|recurring| recurring.chat_id == chat_id)
+            .cloned()
+            .collect();
+        recurring_payments.sort_by_key(|recurring| recurring.recurring.next);
+        Ok(recurring_payments)
+    }
+
+    async fn get_due_recurring_payments(
+        &self,
+        now: i64,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError> {
+        let mut recurring_payments: Vec<ChatRecurringPayment> = self
+            .lock()
+            .recurring
+            .values()
+            .filter(|recurring| recurring.recurring.next <= now)
+            .cloned()
+            .collect();
+        // Same as the Redis sorted set, ties are ordered by ID
+        recurring_payments.sort_by(|first, second| {
+            (first.recurring.next, &first.recurring_id)
+                .cmp(&(second.recurring.next, &second.recurring_id))
+        });
+        Ok(recurring_payments)
+    }
+
+    async fn delete_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring_id: &str,
+    ) -> Result<(), CrudError> {
+        let mut data = self.lock();
+        match data.recurring.get(recurring_id) {
+            Some(recurring) if recurring.chat_id == chat_id => {
+                data.recurring.remove(recurring_id);
+                Ok(())
+            }
+            _ => Err(CrudError::NoSuchRecurringPaymentError()),
+        }
+    }
+
+    async fn retrieve_chat_spendings(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<Vec<UserBalance>>, CrudError> {
+        let data = self.lock();
+        let spendings = data
+            .chat_currencies(chat_id)
+            .iter()
+            .map(|currency| data.spendings_currency(chat_id, currency))
+            .collect();
+        Ok(spendings)
+    }
+
+    async fn retrieve_chat_spendings_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError> {
+        Ok(self.lock().spendings_currency(chat_id, currency))
+    }
+
+    async fn retrieve_chat_categories(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<CategoryTotal>, CrudError> {
+        Ok(self
+            .lock()
+            .categories
+            .iter()
+            .filter(|((chat, _, _), _)| chat == chat_id)
+            .map(|(_, total)| total.clone())
+            .collect())
+    }
+
+    async fn apply_ledger_update(
+        &self,
+        chat_id: &str,
+        update: LedgerUpdate,
+    ) -> Result<(), CrudError> {
+        // Changes are staged on a copy, and only swapped in if all of them succeed
+        let mut data = self.lock();
+        let mut staged = data.clone();
+        staged.apply_ledger_update(chat_id, update)?;
+        *data = staged;
+        Ok(())
+    }
+
+    async fn set_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        dialogue: &str,
+    ) -> Result<(), CrudError> {
+        let key = (chat_id.to_string(), user_id.to_string());
+        self.lock().dialogues.insert(key, dialogue.to_string());
+        Ok(())
+    }
+
+    async fn get_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+    ) -> Result<Option<String>, CrudError> {
+        let key = (chat_id.to_string(), user_id.to_string());
+        Ok(self.lock().dialogues.get(&key).cloned())
+    }
+
+    async fn delete_dialogue(&self, chat_id: &str, user_id: &str) -> Result<(), CrudError> {
+        let key = (chat_id.to_string(), user_id.to_string());
+        self.lock().dialogues.remove(&key);
+        Ok(())
+    }
+
+    async fn is_request_limit_exceeded(
+        &self,
+        user_id: &str,
+        time_now: i64,
+    ) -> Result<bool, CrudError> {
+        let mut data = self.lock();
+        let status = match data.requests.get(user_id) {
+            Some(timestamp) => time_now <= *timestamp,
+            None => false,
+        };
+
+        if !status {
+            data.requests.insert(user_id.to_string(), time_now);
+        }
+
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::redis::{LedgerActor, RecurringChange, RecurringFrequency};
+
+    use super::*;
+
+    fn balance(username: &str, currency: &str, balance: i64) -> UserBalance {
+        UserBalance {
+            username: username.to_string(),
+            currency: currency.to_string(),
+            balance,
+        }
+    }
+
+    fn payment(creditor: &str, total: i64, debts: Vec<(String, i64)>) -> Payment {
+        Payment {
+            description: "test_payment".to_string(),
+            timestamp: 1704067200,
+            creditor: creditor.to_string(),
+            currency: "USD".to_string(),
+            total,
+            debts,
+            items: Vec::new(),
+            category: None,
+            payers: Vec::new(),
+        }
+    }
+
+    // Adds a payment on its own, through a ledger update same as in application
+    async fn add_payment_entry(store: &MemoryStore, chat_id: &str, payment: &Payment) {
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(payment.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_chat_init_settings() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_settings";
+
+        store
+            .update_chat(chat_id, vec!["Test_User".to_string()])
+            .await
+            .unwrap();
+        store
+            .set_time_zone(chat_id, "Asia/Singapore")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_time_zone(chat_id).await.unwrap(),
+            "Asia/Singapore"
+        );
+
+        // Settings are not reset once the chat exists
+        store
+            .update_chat(chat_id, vec!["Test_User_2".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_time_zone(chat_id).await.unwrap(),
+            "Asia/Singapore"
+        );
+        assert_eq!(
+            store.get_default_currency(chat_id).await.unwrap(),
+            CURRENCY_CODE_DEFAULT
+        );
+        assert!(!store.get_currency_conversion(chat_id).await.unwrap());
+        assert!(store.get_erase_messages(chat_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_update_get_chat_balances() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_balances";
+
+        store.update_user("Test_User", chat_id, None).await.unwrap();
+        store
+            .update_user("Other_User", chat_id, None)
+            .await
+            .unwrap();
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User".to_string(), "Other_User".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let update = LedgerUpdate {
+            balances: vec![
+                balance("test_user", "USD", 300),
+                balance("other_user", "USD", -300),
+            ],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let update = LedgerUpdate {
+            balances: vec![balance("TEST_USER", "USD", -100)],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        // Preferred usernames are returned, zero balances are skipped
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap(),
+            vec![
+                balance("Test_User", "USD", 200),
+                balance("Other_User", "USD", -300)
+            ]
+        );
+        assert!(store
+            .get_chat_balances_currency(chat_id, "EUR")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(store.get_chat_balances(chat_id).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_spendings_negative() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_spendings";
+        store
+            .update_chat(
+                chat_id,
+                vec!["test_user".to_string(), "other_user".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let spendings = |spendings: Vec<UserBalance>| LedgerUpdate {
+            spendings,
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, spendings(vec![balance("test_user", "USD", 100)]))
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .apply_ledger_update(chat_id, spendings(vec![balance("test_user", "USD", -200)]))
+                .await,
+            Err(CrudError::NegativeSpendingError())
+        );
+
+        // No change is applied if any of them is rejected
+        assert_eq!(
+            store
+                .apply_ledger_update(
+                    chat_id,
+                    spendings(vec![
+                        balance("other_user", "USD", 50),
+                        balance("test_user", "USD", -200),
+                    ])
+                )
+                .await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.retrieve_chat_spendings_currency(chat_id, "USD").await,
+            Ok(vec![balance("test_user", "USD", 100)])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_get_update_delete_payment_entry() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_payments";
+
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+
+        let first = payment("test_user", 100, vec![("test_user".to_string(), 100)]);
+        let second = payment("other_user", 200, vec![("test_user".to_string(), 200)]);
+        add_payment_entry(&store, chat_id, &first).await;
+        add_payment_entry(&store, chat_id, &second).await;
+
+        // Latest payment comes first
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments.len(), 2);
+        assert_eq!(payments[0].payment, second);
+        assert_eq!(payments[1].payment, first);
+
+        let payment_id = payments[0].payment_id.clone();
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: Some("edited".to_string()),
+                timestamp: None,
+                creditor: None,
+                currency: None,
+                total: Some(50),
+                debts: None,
+                items: None,
+                category: None,
+                payers: None,
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let edited = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(edited.description, "edited");
+        assert_eq!(edited.total, 50);
+        assert_eq!(edited.creditor, "other_user");
+
+        let delete = LedgerUpdate {
+            payments: vec![PaymentChange::Delete(payment_id.clone())],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, delete.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        assert_eq!(
+            store.apply_ledger_update(chat_id, delete).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        assert_eq!(
+            store
+                .get_chat_payments_details(chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_items() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_items";
+
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+        store
+            .update_user("Test_User_2", chat_id, None)
+            .await
+            .unwrap();
+        let mut itemized = payment(
+            "Test_User_1",
+            300,
+            vec![
+                ("Test_User_1".to_string(), 150),
+                ("Test_User_2".to_string(), 150),
+            ],
+        );
+        itemized.items = vec![
+            PaymentItem {
+                description: "pizza".to_string(),
+                amount: 200,
+                participants: vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            },
+            PaymentItem {
+                description: "wine".to_string(),
+                amount: 100,
+                participants: vec!["Test_User_2".to_string()],
+            },
+        ];
+        add_payment_entry(&store, chat_id, &itemized).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await.unwrap(),
+            itemized
+        );
+
+        // Renaming a user renames them in items too
+        store
+            .rename_user("Test_User_2", "New_User_2")
+            .await
+            .unwrap();
+        let renamed = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(
+            renamed.items[1].participants,
+            vec!["New_User_2".to_string()]
+        );
+
+        // Items are cleared by a split that is not itemized
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: None,
+                timestamp: None,
+                creditor: None,
+                currency: None,
+                total: None,
+                debts: Some(vec![("New_User_2".to_string(), 300)]),
+                items: Some(Vec::new()),
+                category: None,
+                payers: None,
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        assert!(store
+            .get_payment_entry(&payment_id)
+            .await
+            .unwrap()
+            .items
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_payment_category() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_category";
+
+        store
+            .update_chat(chat_id, vec!["Test_User_1".to_string()])
+            .await
+            .unwrap();
+        let mut categorized = payment("Test_User_1", 300, vec![("Test_User_1".to_string(), 300)]);
+        categorized.category = Some("Food".to_string());
+        add_payment_entry(&store, chat_id, &categorized).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await.unwrap(),
+            categorized
+        );
+
+        // Category can be changed, then cleared
+        for category in [Some("Transport"), None] {
+            let update = LedgerUpdate {
+                payments: vec![PaymentChange::Update {
+                    payment_id: payment_id.clone(),
+                    description: None,
+                    timestamp: None,
+                    creditor: None,
+                    currency: None,
+                    total: None,
+                    debts: None,
+                    items: None,
+                    category: Some(category.map(|category| category.to_string())),
+                    payers: None,
+                }],
+                ..Default::default()
+            };
+            store.apply_ledger_update(chat_id, update).await.unwrap();
+            assert_eq!(
+                store
+                    .get_payment_entry(&payment_id)
+                    .await
+                    .unwrap()
+                    .category
+                    .as_deref(),
+                category
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_payment_payers() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_payers";
+
+        store
+            .update_chat(chat_id, vec!["Test_User_1".to_string()])
+            .await
+            .unwrap();
+        let mut shared = payment("Test_User_1", 300, vec![("Test_User_1".to_string(), 300)]);
+        shared.payers = vec![
+            ("Test_User_1".to_string(), 200),
+            ("Test_User_2".to_string(), 100),
+        ];
+        add_payment_entry(&store, chat_id, &shared).await;
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert_eq!(store.get_payment_entry(&payment_id).await.unwrap(), shared);
+
+        // Payers can be changed, then cleared
+        for payers in [
+            vec![
+                ("Test_User_1".to_string(), 100),
+                ("Test_User_2".to_string(), 200),
+            ],
+            Vec::new(),
+        ] {
+            let update = LedgerUpdate {
+                payments: vec![PaymentChange::Update {
+                    payment_id: payment_id.clone(),
+                    description: None,
+                    timestamp: None,
+                    creditor: None,
+                    currency: None,
+                    total: None,
+                    debts: None,
+                    items: None,
+                    category: None,
+                    payers: Some(payers.clone()),
+                }],
+                ..Default::default()
+            };
+            store.apply_ledger_update(chat_id, update).await.unwrap();
+            assert_eq!(
+                store.get_payment_entry(&payment_id).await.unwrap().payers,
+                payers
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recurring_payments() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_chat_recurring";
+        let new_chat_id = "memory_chat_recurring_new";
+
+        for username in ["Test_User_1", "Test_User_2"] {
+            store.update_user(username, chat_id, None).await.unwrap();
+        }
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let mut recurring = RecurringPayment {
+            payment: payment("Test_User_1", 100, vec![("Test_User_2".to_string(), 100)]),
+            frequency: RecurringFrequency::Monthly,
+            end: None,
+            next: 1704067200,
+            creator_id: "123456789".to_string(),
+            creator: "Test_User_1".to_string(),
+        };
+        let first_id = store
+            .add_recurring_payment(chat_id, &recurring)
+            .await
+            .unwrap();
+        recurring.next = 1706745600;
+        let second_id = store
+            .add_recurring_payment(chat_id, &recurring)
+            .await
+            .unwrap();
+
+        // Earliest due first
+        let recurring_payments = store.get_chat_recurring_payments(chat_id).await.unwrap();
+        assert_eq!(recurring_payments.len(), 2);
+        assert_eq!(recurring_payments[0].recurring_id, first_id);
+        assert_eq!(recurring_payments[1].recurring_id, second_id);
+        assert_eq!(recurring_payments[1].recurring, recurring);
+
+        // Only those due by the given time, across all chats
+        let due = store.get_due_recurring_payments(1704067200).await.unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].recurring_id, first_id);
+        assert_eq!(due[0].chat_id, chat_id);
+
+        // Scheduled within a ledger update, only if it has not changed since it was read
+        let schedule = |recurring_id: &str, current: &RecurringPayment| LedgerUpdate {
+            recurring: Some(RecurringChange {
+                recurring_id: recurring_id.to_string(),
+                current: current.clone(),
+                next: Some(1709251200),
+            }),
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, schedule(&second_id, &recurring))
+            .await
+            .unwrap();
+        assert!(store
+            .get_due_recurring_payments(1706745600)
+            .await
+            .unwrap()
+            .iter()
+            .all(|due| due.recurring_id != second_id));
+        assert_eq!(
+            store
+                .apply_ledger_update(chat_id, schedule(&second_id, &recurring))
+                .await,
+            Err(CrudError::RecurringChangedError())
+        );
+        recurring.next = 1709251200;
+        assert_eq!(
+            store
+                .apply_ledger_update(new_chat_id, schedule(&second_id, &recurring))
+                .await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+        assert_eq!(
+            store
+                .apply_ledger_update(chat_id, schedule("missing", &recurring))
+                .await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+
+        // Recurring payments are renamed and moved with the rest of the chat
+        store
+            .rename_user("Test_User_2", "New_User_2")
+            .await
+            .unwrap();
+        store.migrate_chat(chat_id, new_chat_id).await.unwrap();
+        assert!(store
+            .get_chat_recurring_payments(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        let recurring_payments = store
+            .get_chat_recurring_payments(new_chat_id)
+            .await
+            .unwrap();
+        assert_eq!(recurring_payments.len(), 2);
+        assert_eq!(recurring_payments[0].chat_id, new_chat_id);
+        assert_eq!(
+            recurring_payments[0].recurring.payment.debts,
+            vec![("New_User_2".to_string(), 100)]
+        );
+
+        // Deleting only works within the chat of the recurring payment
+        assert_eq!(
+            store.delete_recurring_payment(chat_id, &first_id).await,
+            Err(CrudError::NoSuchRecurringPaymentError())
+        );
+        store
+            .delete_recurring_payment(new_chat_id, &first_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            store
+                .get_chat_recurring_payments(new_chat_id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        store.reset_chat(new_chat_id).await.unwrap();
+        assert!(store
+            .get_due_recurring_payments(i64::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_chat_payments_page() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_payments_page";
+
+        let timestamps = [1704067200, 1704153600, 1704153600, 1704240000, 1704326400];
+        for timestamp in timestamps {
+            let mut payment = payment("Test_User_1", 100, vec![("Test_User_2".to_string(), 100)]);
+            payment.timestamp = timestamp;
+            add_payment_entry(&store, chat_id, &payment).await;
+        }
+
+        // Trashed payments are left out
+        let trashed_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        let trash = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: trashed_id,
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, trash).await.unwrap();
+
+        // Latest payment first, payments at the same time by payment ID descending
+        let mut expected = store.get_chat_payments_details(chat_id).await.unwrap();
+        expected.sort_by(|first, second| {
+            (second.payment.timestamp, &second.payment_id)
+                .cmp(&(first.payment.timestamp, &first.payment_id))
+        });
+
+        let mut query = PaymentQuery {
+            page_size: 3,
+            ..Default::default()
+        };
+        let mut payments = Vec::new();
+        loop {
+            let page = store.get_chat_payments_page(chat_id, &query).await.unwrap();
+            assert_eq!(page.total, 4);
+            payments.extend(page.payments);
+            match page.next {
+                Some(next) => query.cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(payments, expected);
+
+        // Only payments within the time range, inclusive
+        let query = PaymentQuery {
+            page_size: 3,
+            from: Some(1704153600),
+            to: Some(1704153600),
+            ..Default::default()
+        };
+        let page = store.get_chat_payments_page(chat_id, &query).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.payments, expected[1..3].to_vec());
+        assert_eq!(page.next, None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_ledger_update() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_ledger_update";
+
+        for username in ["Test_User_1", "Test_User_2"] {
+            store.update_user(username, chat_id, None).await.unwrap();
+        }
+        store
+            .update_chat(
+                chat_id,
+                vec!["Test_User_1".to_string(), "Test_User_2".to_string()],
+            )
+            .await
+            .unwrap();
+
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            spendings: vec![balance("Test_User_2", "USD", 200)],
+            categories: vec![first.category_total()],
+            balances: vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -200),
+            ],
+            default_currency: Some("USD".to_string()),
+            currency_conversion: Some(true),
+            recurring: None,
+            actor: None,
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert_eq!(payments[0].payment, first);
+        assert_eq!(
+            store.retrieve_chat_categories(chat_id).await.unwrap(),
+            vec![first.category_total()]
+        );
+        assert_eq!(store.get_default_currency(chat_id).await.unwrap(), "USD");
+        assert!(store.get_currency_conversion(chat_id).await.unwrap());
+
+        // A failing change leaves everything untouched, including earlier changes
+        let update = LedgerUpdate {
+            payments: vec![
+                PaymentChange::Delete(payments[0].payment_id.clone()),
+                PaymentChange::Add(payment("Test_User_2", 100, vec![])),
+            ],
+            spendings: vec![balance("Test_User_2", "USD", -300)],
+            balances: vec![balance("Test_User_1", "USD", -200)],
+            default_currency: Some("EUR".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await.unwrap(),
+            payments
+        );
+        assert_eq!(
+            store.retrieve_chat_spendings(chat_id).await.unwrap(),
+            vec![vec![balance("Test_User_2", "USD", 200)]]
+        );
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap(),
+            vec![
+                balance("Test_User_1", "USD", 200),
+                balance("Test_User_2", "USD", -200),
+            ]
+        );
+        assert_eq!(store.get_default_currency(chat_id).await.unwrap(), "USD");
+
+        // Category totals cannot turn negative either
+        let update = LedgerUpdate {
+            categories: vec![CategoryTotal {
+                spending: -300,
+                ..first.category_total()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NegativeSpendingError())
+        );
+        assert_eq!(
+            store.retrieve_chat_categories(chat_id).await.unwrap(),
+            vec![first.category_total()]
+        );
+
+        // Missing payments fail the whole update as well
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Delete("missing_payment".to_string())],
+            balances: vec![balance("Test_User_1", "USD", -200)],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, update).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        assert_eq!(
+            store
+                .get_chat_balances_currency(chat_id, "USD")
+                .await
+                .unwrap()[0],
+            balance("Test_User_1", "USD", 200)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trash_restore_purge_payment() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_trash";
+
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let payments = store.get_chat_payments_details(chat_id).await.unwrap();
+        let payment_id = payments[0].payment_id.clone();
+
+        // Trashed payments are hidden, but kept in the trash
+        let trash = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: payment_id.clone(),
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, trash.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await,
+            Err(CrudError::NoPaymentsError())
+        );
+        assert_eq!(
+            store.get_chat_trash_details(chat_id).await.unwrap(),
+            vec![TrashedPayment {
+                payment_id: payment_id.clone(),
+                payment: first,
+                deleted_at: 1700000000,
+                deleted_by: "Test_User_1".to_string(),
+            }]
+        );
+        assert_eq!(
+            store.apply_ledger_update(chat_id, trash.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Restored payments are shown again
+        let restore = LedgerUpdate {
+            payments: vec![PaymentChange::Restore(payment_id.clone())],
+            ..Default::default()
+        };
+        store
+            .apply_ledger_update(chat_id, restore.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_chat_payments_details(chat_id).await.unwrap(),
+            payments
+        );
+        assert!(store
+            .get_chat_trash_details(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.apply_ledger_update(chat_id, restore).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+
+        // Only trashed payments can be purged
+        let purge = LedgerUpdate {
+            payments: vec![PaymentChange::Purge(payment_id.clone())],
+            ..Default::default()
+        };
+        assert_eq!(
+            store.apply_ledger_update(chat_id, purge.clone()).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+        store.apply_ledger_update(chat_id, trash).await.unwrap();
+        store.apply_ledger_update(chat_id, purge).await.unwrap();
+        assert!(store
+            .get_chat_trash_details(chat_id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.get_payment_entry(&payment_id).await,
+            Err(CrudError::NoSuchPaymentError())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_history() {
+        let store = MemoryStore::new();
+        let chat_id = "memory_history";
+        let actor = |timestamp: i64| LedgerActor {
+            user_id: "123456789".to_string(),
+            username: "Test_User_1".to_string(),
+            timestamp,
+        };
+
+        // Updates without an actor are not recorded
+        let first = payment("Test_User_1", 200, vec![("Test_User_2".to_string(), 200)]);
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let untracked_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+        assert!(store
+            .get_payment_history(&untracked_id)
+            .await
+            .unwrap()
+            .is_empty());
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Add(first.clone())],
+            actor: Some(actor(1700000000)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let payment_id = store.get_chat_payments_details(chat_id).await.unwrap()[0]
+            .payment_id
+            .clone();
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Update {
+                payment_id: payment_id.clone(),
+                description: Some("edited_payment".to_string()),
+                timestamp: Some(1703980800),
+                creditor: None,
+                currency: None,
+                total: Some(300),
+                debts: Some(vec![("Test_User_2".to_string(), 300)]),
+                items: None,
+                category: None,
+                payers: None,
+            }],
+            actor: Some(actor(1700000001)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+        let edited = store.get_payment_entry(&payment_id).await.unwrap();
+        assert_eq!(edited.timestamp, 1703980800);
+
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Trash {
+                payment_id: payment_id.clone(),
+                deleted_at: 1700000002,
+                deleted_by: "@Test_User_1".to_string(),
+            }],
+            actor: Some(actor(1700000002)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        // History is kept even after the payment is gone
+        let update = LedgerUpdate {
+            payments: vec![PaymentChange::Purge(payment_id.clone())],
+            actor: Some(actor(1700000003)),
+            ..Default::default()
+        };
+        store.apply_ledger_update(chat_id, update).await.unwrap();
+
+        let history = vec![
+            actor(1700000000).event(&payment_id, PaymentAction::Add, None, Some(first.clone())),
+            actor(1700000001).event(
+                &payment_id,
+                PaymentAction::Edit,
+                Some(first.clone()),
+                Some(edited.clone()),
+            ),
+            actor(1700000002).event(
+                &payment_id,
+                PaymentAction::Delete,
+                Some(edited.clone()),
+                None,
+            ),
+            actor(1700000003).event(&payment_id, PaymentAction::Purge, Some(edited), None),
+        ];
+        assert_eq!(
+            store.get_payment_history(&payment_id).await.unwrap(),
+            history
+        );
+
+        // Chat activity is latest first
+        let activity: Vec<PaymentEvent> = history.into_iter().rev().take(3).collect();
+        assert_eq!(store.get_chat_activity(chat_id, 3).await.unwrap(), activity);
+        assert_eq!(store.get_chat_activity(chat_id, 10).await.unwrap().len(), 4);
+        assert!(store
+            .get_chat_activity("memory_history_empty", 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_set_get_member_names() {
+        let store = MemoryStore::new();
+        let member = "#123456789";
+
+        let members = vec![member.to_string(), "#987654321".to_string()];
+
+        assert!(store.get_member_names(&members).await.unwrap().is_empty());
+        store.set_member_name(member, "Test User").await.unwrap();
+        store.set_member_name(member, "Renamed User").await.unwrap();
+        store
+            .set_member_name("#555555555", "Other User")
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_member_names(&members).await.unwrap(),
+            HashMap::from([(member.to_string(), "Renamed User".to_string())])
+        );
+        assert!(store.get_member_names(&[]).await.unwrap().is_empty());
+
+        // Forgetting a member also forgets their name
+        store.forget_user(member).await.unwrap();
+        assert!(store.get_member_names(&members).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_is_request_limit_exceeded() {
+        let store = MemoryStore::new();
+        let user_id = "memory_user";
+
+        assert!(!store.is_request_limit_exceeded(user_id, 100).await.unwrap());
+        assert!(store.is_request_limit_exceeded(user_id, 100).await.unwrap());
+        assert!(!store.is_request_limit_exceeded(user_id, 101).await.unwrap());
+    }
+}
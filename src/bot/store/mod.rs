@@ -0,0 +1,264 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+
+use super::{
+    redis::{
+        CategoryTotal, ChatRecurringPayment, CrudError, LedgerUpdate, Payment, PaymentEvent,
+        PaymentPage, PaymentQuery, RecurringPayment, RedisStore, TrashedPayment, UserBalance,
+        UserPayment,
+    },
+    sqlite::{SqliteStore, SQLITE_PATH_DEFAULT},
+};
+
+/* Store defines the storage backend used by the bot.
+ * The processor never talks to a database directly, only through a LedgerStore.
+ * Each backend implements the same set of operations as the Redis manager,
+ * so that the processor behaves identically regardless of where data is kept.
+ * The backend is chosen once at startup and injected into every handler.
+ */
+
+// Shared handle to the storage backend, injected into the dispatcher
+pub type Store = Arc<dyn LedgerStore>;
+
+#[async_trait]
+pub trait LedgerStore: Send + Sync {
+    /* Users and chats */
+
+    // Adds a user if not exists, and ensures that the chat is in the user's chats
+    async fn update_user(
+        &self,
+        username: &str,
+        chat_id: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), CrudError>;
+
+    // Adds a chat if not exists, and ensures that all usernames are in the chat
+    async fn update_chat(&self, chat_id: &str, usernames: Vec<String>) -> Result<(), CrudError>;
+
+    // Gets all chats a user has been added to
+    async fn get_user_chats(&self, username: &str) -> Result<Vec<String>, CrudError>;
+
+    // Gets all users of a chat by their lowercase username, whose balances and spendings are read
+    async fn get_chat_users(&self, chat_id: &str) -> Result<Vec<String>, CrudError>;
+
+    // Removes a user from all their chats, and deletes their preferred username
+    // Balances and spendings are kept, so callers must check that they are settled
+    async fn forget_user(&self, username: &str) -> Result<(), CrudError>;
+
+    // Deletes all payments, history, recurring payments, balances, spendings, currencies
+    // and settings of a chat
+    async fn reset_chat(&self, chat_id: &str) -> Result<(), CrudError>;
+
+    // Moves all records and settings of a chat to a new chat ID, replacing any under the new one
+    // Does nothing if the old chat ID has no records, so migrating twice is harmless
+    async fn migrate_chat(&self, chat_id: &str, new_chat_id: &str) -> Result<(), CrudError>;
+
+    // Gets the username last seen for a user ID, None if the user ID is not tracked yet
+    async fn get_tracked_username(&self, user_id: &str) -> Result<Option<String>, CrudError>;
+
+    // Tracks the username of a user ID, replacing any previous one
+    async fn track_username(&self, user_id: &str, username: &str) -> Result<(), CrudError>;
+
+    // Moves everything under the old username of a user to their new username, in all their chats
+    // Payments are rewritten, and balances and spendings are added onto those of the new username
+    async fn rename_user(&self, old_username: &str, new_username: &str) -> Result<(), CrudError>;
+
+    // Sets the name of a member without a username, replacing any previous one
+    async fn set_member_name(&self, member: &str, name: &str) -> Result<(), CrudError>;
+
+    // Gets the names of the given members without a username, by member
+    // Members without a name kept are left out
+    async fn get_member_names(
+        &self,
+        members: &[String],
+    ) -> Result<HashMap<String, String>, CrudError>;
+
+    /* Chat settings */
+
+    async fn set_time_zone(&self, chat_id: &str, time_zone: &str) -> Result<(), CrudError>;
+
+    async fn get_time_zone(&self, chat_id: &str) -> Result<String, CrudError>;
+
+    async fn set_default_currency(&self, chat_id: &str, currency: &str) -> Result<(), CrudError>;
+
+    async fn get_default_currency(&self, chat_id: &str) -> Result<String, CrudError>;
+
+    async fn set_currency_conversion(
+        &self,
+        chat_id: &str,
+        conversion: bool,
+    ) -> Result<(), CrudError>;
+
+    async fn get_currency_conversion(&self, chat_id: &str) -> Result<bool, CrudError>;
+
+    async fn set_erase_messages(&self, chat_id: &str, erase: bool) -> Result<(), CrudError>;
+
+    async fn get_erase_messages(&self, chat_id: &str) -> Result<bool, CrudError>;
+
+    // Gets all currencies with some spending in the chat
+    async fn get_valid_chat_currencies(&self, chat_id: &str) -> Result<Vec<String>, CrudError>;
+
+    /* Balances */
+
+    // Gets all non-zero balances of a chat, grouped by currency
+    async fn get_chat_balances(&self, chat_id: &str) -> Result<Vec<Vec<UserBalance>>, CrudError>;
+
+    // Gets all non-zero balances of a chat for a single currency
+    async fn get_chat_balances_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError>;
+
+    /* Payments */
+
+    // Gets all payments of a chat, latest first, leaving out trashed ones
+    async fn get_chat_payments_details(&self, chat_id: &str)
+        -> Result<Vec<UserPayment>, CrudError>;
+
+    // Gets a page of payments of a chat, latest first, leaving out trashed payments
+    async fn get_chat_payments_page(
+        &self,
+        chat_id: &str,
+        query: &PaymentQuery,
+    ) -> Result<PaymentPage, CrudError>;
+
+    async fn get_payment_entry(&self, payment_id: &str) -> Result<Payment, CrudError>;
+
+    // Gets all deleted payments still in the trash of a chat, latest deletion first
+    async fn get_chat_trash_details(&self, chat_id: &str)
+        -> Result<Vec<TrashedPayment>, CrudError>;
+
+    /* Payment history */
+
+    // Gets all events of a payment, earliest first, including those of deleted payments
+    async fn get_payment_history(&self, payment_id: &str) -> Result<Vec<PaymentEvent>, CrudError>;
+
+    // Gets the latest payment events of a chat, latest first, up to the given count
+    async fn get_chat_activity(
+        &self,
+        chat_id: &str,
+        count: usize,
+    ) -> Result<Vec<PaymentEvent>, CrudError>;
+
+    /* Recurring payments */
+
+    // Adds a recurring payment to a chat, returning its ID
+    async fn add_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring: &RecurringPayment,
+    ) -> Result<String, CrudError>;
+
+    // Gets all recurring payments of a chat, earliest due first
+    async fn get_chat_recurring_payments(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError>;
+
+    // Gets all recurring payments due by the given time, across all chats, earliest due first
+    async fn get_due_recurring_payments(
+        &self,
+        now: i64,
+    ) -> Result<Vec<ChatRecurringPayment>, CrudError>;
+
+    // Deletes a recurring payment of a chat, keeping the payments it has already added
+    async fn delete_recurring_payment(
+        &self,
+        chat_id: &str,
+        recurring_id: &str,
+    ) -> Result<(), CrudError>;
+
+    /* Spendings */
+
+    // Gets all non-zero spendings of a chat, grouped by currency
+    async fn retrieve_chat_spendings(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<Vec<UserBalance>>, CrudError>;
+
+    // Gets all non-zero spendings of a chat for a single currency
+    async fn retrieve_chat_spendings_currency(
+        &self,
+        chat_id: &str,
+        currency: &str,
+    ) -> Result<Vec<UserBalance>, CrudError>;
+
+    // Gets the non-zero spending of a chat on each category of payments, in each currency
+    async fn retrieve_chat_categories(
+        &self,
+        chat_id: &str,
+    ) -> Result<Vec<CategoryTotal>, CrudError>;
+
+    /* Ledger */
+
+    // Applies all payment, spending, balance and setting changes atomically.
+    // If any change fails, none of them are applied.
+    // Payment changes are recorded in the payment history if the update has an actor.
+    async fn apply_ledger_update(
+        &self,
+        chat_id: &str,
+        update: LedgerUpdate,
+    ) -> Result<(), CrudError>;
+
+    /* Dialogues */
+
+    // Saves the serialized dialogue state of a user in a chat, replacing any previous one
+    async fn set_dialogue(
+        &self,
+        chat_id: &str,
+        user_id: &str,
+        dialogue: &str,
+    ) -> Result<(), CrudError>;
+
+    // Gets the serialized dialogue state of a user in a chat, None if there is none
+    async fn get_dialogue(&self, chat_id: &str, user_id: &str)
+        -> Result<Option<String>, CrudError>;
+
+    // Deletes the dialogue state of a user in a chat, does nothing if there is none
+    async fn delete_dialogue(&self, chat_id: &str, user_id: &str) -> Result<(), CrudError>;
+
+    /* Requests */
+
+    // Checks the rate limit of a user, recording the request if not exceeded
+    async fn is_request_limit_exceeded(
+        &self,
+        user_id: &str,
+        time_now: i64,
+    ) -> Result<bool, CrudError>;
+}
+
+/* Creates the storage backend selected by the STORE_BACKEND environment variable.
+ * Defaults to Redis, configured by REDIS_URL, with connections opened once here.
+ * "sqlite" uses a database file at SQLITE_PATH, no Redis required.
+ * "memory" keeps everything in process, useful for local runs.
+ */
+pub async fn init_store() -> Store {
+    dotenv::dotenv().ok();
+    let backend = std::env::var("STORE_BACKEND").unwrap_or_default();
+    match backend.to_lowercase().as_str() {
+        "sqlite" => {
+            let path = std::env::var("SQLITE_PATH").unwrap_or(SQLITE_PATH_DEFAULT.to_string());
+            log::info!("Using SQLite store at {path}");
+            Arc::new(SqliteStore::open(&path).expect("Failed to open SQLite database"))
+        }
+        "memory" => {
+            log::warn!("Using in-memory store, all data will be lost on exit");
+            Arc::new(MemoryStore::new())
+        }
+        _ => Arc::new(
+            RedisStore::connect()
+                .await
+                .expect("Failed to connect to Redis"),
+        ),
+    }
+}
+
+// Exported structs and types
+pub use self::dialogue::{Dialogue, DialogueKey, DialogueStorage, DialogueStorageError};
+pub use self::memory::MemoryStore;
+
+// Submodules
+mod dialogue;
+mod memory;
@@ -1,10 +1,11 @@
 use std::collections::HashSet;
 
 use crate::bot::{
-    constants::misc::MAX_VALUE,
+    constants::misc::{CHARGE_KEYWORDS, MAX_CHARGE_PERCENTAGE, MAX_VALUE},
     currency::{get_default_currency, Currency},
     handlers::AddDebtsFormat,
     processor::is_username_equal,
+    redis::PaymentItem,
 };
 
 use super::{
@@ -12,6 +13,12 @@ use super::{
     BotError,
 };
 
+// Debts of a split, together with the items they add up to, if split by item.
+pub type ItemizedDebts = (Vec<(String, i64)>, Vec<PaymentItem>);
+
+// Creditor of a payment, the amounts paid if more than one paid, and the total paid if given.
+pub type PaidAmounts = (String, Vec<(String, i64)>, Option<i64>);
+
 // Parse an amount. Reads a string, returns i64 based on currency.
 pub fn parse_amount(text: &str, decimal_places: i32) -> Result<i64, BotError> {
     let factor = 10.0_f64.powi(decimal_places);
@@ -70,24 +77,96 @@ pub fn parse_float(text: &str) -> Result<f64, BotError> {
 pub fn parse_currency_amount(text: &str) -> Result<(i64, Currency), BotError> {
     let items = text.split_whitespace().collect::<Vec<&str>>();
     if items.len() > 2 {
-        return Err(BotError::UserError(
+        Err(BotError::UserError(
             "Sorry, unknown format... Please use the following format!".to_string(),
-        ));
+        ))
     } else if items.len() == 1 {
         let currency = get_default_currency();
         let amount = parse_amount(items[0], currency.1)?;
         Ok((amount, currency))
     } else {
-        let currency = get_currency(&items[1])?;
+        let currency = get_currency(items[1])?;
         let amount = parse_amount(items[0], currency.1)?;
         Ok((amount, currency))
     }
 }
 
+// Checks if a word is a charge on top of a receipt, such as tax or tip.
+// Members with the same name can still be given with a leading '@'.
+fn is_charge(word: &str) -> bool {
+    let word = word.trim_end_matches(':').to_lowercase();
+    CHARGE_KEYWORDS.contains(&word.as_str())
+}
+
+// Adds an amount to a sum, as long as the sum stays small enough to handle.
+fn add_amount(sum: i64, amount: i64) -> Result<i64, BotError> {
+    match sum.checked_add(amount) {
+        Some(sum) if sum <= MAX_VALUE => Ok(sum),
+        _ => Err(BotError::UserError(
+            "Uh-oh! 🥺 This number is too large for me to handle!".to_string(),
+        )),
+    }
+}
+
+// Parse charges, each an amount or a percentage of the subtotal, such as "5" or "8%".
+// Returns the total amount charged.
+fn parse_charges(charges: &[&str], subtotal: i64, decimal_places: i32) -> Result<i64, BotError> {
+    let mut sum: i64 = 0;
+    for charge in charges {
+        let amount = match charge.strip_suffix('%') {
+            Some(percentage) => {
+                let percentage = parse_float(percentage)?;
+                if percentage > MAX_CHARGE_PERCENTAGE {
+                    return Err(BotError::UserError(format!(
+                        "Uh-oh! ❌ Please give me a percentage of at most {MAX_CHARGE_PERCENTAGE}%!"
+                    )));
+                }
+                // At most the subtotal, so it cannot overflow
+                (subtotal as f64 * percentage / 100.0).round() as i64
+            }
+            None => parse_amount(charge, decimal_places)?,
+        };
+        sum = add_amount(sum, amount)?;
+    }
+
+    Ok(sum)
+}
+
+// Spreads charges over a list of debts, in proportion to each debt, same as split by ratio.
+fn distribute_charges(debts: &mut [(String, i64)], charges: i64) -> Result<(), BotError> {
+    if charges == 0 {
+        return Ok(());
+    }
+
+    let subtotal = debts
+        .iter()
+        .try_fold(0, |sum, debt| add_amount(sum, debt.1))?;
+    if subtotal == 0 {
+        return Err(BotError::UserError(
+            "Uh-oh! ❌ Please give me at least one amount to add the tax or tip to!".to_string(),
+        ));
+    }
+
+    let mut exact_sum: i64 = 0;
+    for debt in debts.iter_mut() {
+        let amount = ((debt.1 as f64 / subtotal as f64) * charges as f64).round() as i64;
+        debt.1 = add_amount(debt.1, amount)?;
+        exact_sum = add_amount(exact_sum, amount)?;
+    }
+
+    // Distribute the difference in amount to as many users as required through smallest denomination
+    let diff = charges - exact_sum;
+    for i in 0..(diff).abs() {
+        debts[i as usize].1 += if diff > 0 { 1 } else { -1 };
+    }
+
+    Ok(())
+}
+
 // Parse and process a string to retrieve a list of debts, for split by equal amount.
 pub fn process_debts_equal(text: &str, total: Option<i64>) -> Result<Vec<(String, i64)>, BotError> {
     let mut users = text.split_whitespace().collect::<Vec<&str>>();
-    if users.len() == 0 {
+    if users.is_empty() {
         return Err(BotError::UserError(
             "Uh-oh! ❌ Please give me at least one username!".to_string(),
         ));
@@ -143,18 +222,24 @@ pub fn process_debts_exact(
         if let Some(total) = total {
             if let Some(currency) = currency {
                 let mut debts: Vec<(String, i64)> = Vec::new();
+                let mut charges: Vec<&str> = Vec::new();
                 let mut sum: i64 = 0;
                 let items: Vec<&str> = text.split_whitespace().collect();
-                if items.len() % 2 != 0 {
+                if !items.len().is_multiple_of(2) {
                     return Err(BotError::UserError(
                         "Sorry, unknown format... Please use the following format!".to_string(),
                     ));
                 }
 
                 for i in (0..items.len()).step_by(2) {
+                    if is_charge(items[i]) {
+                        charges.push(items[i + 1]);
+                        continue;
+                    }
+
                     let username = parse_username(items[i])?;
                     let amount = parse_amount(items[i + 1], currency.1)?;
-                    sum += amount;
+                    sum = add_amount(sum, amount)?;
 
                     let mut found = false;
                     for debt in &mut debts {
@@ -170,6 +255,10 @@ pub fn process_debts_exact(
                     }
                 }
 
+                let charges = parse_charges(&charges, sum, currency.1)?;
+                distribute_charges(&mut debts, charges)?;
+                sum = add_amount(sum, charges)?;
+
                 if sum > total {
                     Err(BotError::UserError(
                         "Uh-oh! ❌ The amounts you gave me are more than the total paid!"
@@ -177,7 +266,7 @@ pub fn process_debts_exact(
                     ))
                 } else if sum < total {
                     for debt in &mut debts {
-                        if debt.0 == creditor.to_string() {
+                        if debt.0 == *creditor {
                             debt.1 += total - sum;
                             return Ok(debts);
                         }
@@ -205,19 +294,141 @@ pub fn process_debts_exact(
     }
 }
 
+// Parse and process a string to retrieve a list of items, for split by item.
+// Each line is an item, such as "pizza 24 @user1 @user2", split equally among its participants.
+// Returns the items, together with the debts they add up to.
+pub fn process_debts_itemized(
+    text: &str,
+    creditor: &Option<String>,
+    currency: Option<Currency>,
+    total: Option<i64>,
+) -> Result<ItemizedDebts, BotError> {
+    let creditor = match creditor {
+        Some(creditor) => creditor,
+        None => {
+            return Err(BotError::UserError(
+                "Uh-oh! ❌ The payer isn't provided.".to_string(),
+            ));
+        }
+    };
+    let total = match total {
+        Some(val) => val,
+        None => {
+            return Err(BotError::UserError(
+                "Uh-oh! ❌ The total amount isn't provided.".to_string(),
+            ));
+        }
+    };
+    let currency = match currency {
+        Some(currency) => currency,
+        None => {
+            return Err(BotError::UserError(
+                "Uh-oh! ❌ The currency isn't provided.".to_string(),
+            ));
+        }
+    };
+
+    let mut debts: Vec<(String, i64)> = Vec::new();
+    let mut items: Vec<PaymentItem> = Vec::new();
+    let mut charges: Vec<&str> = Vec::new();
+    let mut sum: i64 = 0;
+    for line in text.split(['\n', ';']) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+
+        // A charge, such as "tax 8%", is shared by everyone rather than being an item
+        if words.len() == 2 && is_charge(words[0]) {
+            charges.push(words[1]);
+            continue;
+        }
+
+        // The amount is the last number of the line, followed by the participants
+        let pos = match words
+            .iter()
+            .rposition(|word| parse_amount(word, currency.1).is_ok())
+        {
+            Some(pos) if pos + 1 < words.len() => pos,
+            _ => {
+                return Err(BotError::UserError(format!(
+                    "Sorry, I don't understand the item \"{}\"... Please use the following format!",
+                    line.trim()
+                )));
+            }
+        };
+        let amount = parse_amount(words[pos], currency.1)?;
+        let item_debts = process_debts_equal(&words[pos + 1..].join(" "), Some(amount))?;
+        sum = add_amount(sum, amount)?;
+
+        for (username, amount) in &item_debts {
+            match debts
+                .iter_mut()
+                .find(|debt| is_username_equal(&debt.0, username))
+            {
+                Some(debt) => debt.1 += amount,
+                None => debts.push((username.clone(), *amount)),
+            }
+        }
+
+        let description = if pos == 0 {
+            format!("Item {}", items.len() + 1)
+        } else {
+            words[..pos].join(" ")
+        };
+        items.push(PaymentItem {
+            description,
+            amount,
+            participants: item_debts.into_iter().map(|debt| debt.0).collect(),
+        });
+    }
+
+    if items.is_empty() {
+        return Err(BotError::UserError(
+            "Uh-oh! ❌ Please give me at least one item!".to_string(),
+        ));
+    }
+
+    let charges = parse_charges(&charges, sum, currency.1)?;
+    distribute_charges(&mut debts, charges)?;
+    sum = add_amount(sum, charges)?;
+
+    if sum > total {
+        return Err(BotError::UserError(
+            "Uh-oh! ❌ The items you gave me are more than the total paid!".to_string(),
+        ));
+    } else if sum < total {
+        // Anything not itemized, such as a tip, is left to the payer
+        match debts
+            .iter_mut()
+            .find(|debt| is_username_equal(&debt.0, creditor))
+        {
+            Some(debt) => debt.1 += total - sum,
+            None => debts.push((creditor.to_string(), total - sum)),
+        }
+    }
+
+    Ok((debts, items))
+}
+
 // Parse and process a string to retrieve a list of debts, returns Vec<Debt>.
+// Items are only given for split by item, and are empty otherwise.
 pub fn process_debts(
     debts_format: AddDebtsFormat,
     text: &str,
     creditor: &Option<String>,
     currency: Option<Currency>,
     total: Option<i64>,
-) -> Result<Vec<(String, i64)>, BotError> {
-    match debts_format {
-        AddDebtsFormat::Equal => process_debts_equal(text, total),
-        AddDebtsFormat::Exact => process_debts_exact(text, creditor, currency, total),
-        AddDebtsFormat::Ratio => process_debts_ratio(text, total),
-    }
+) -> Result<ItemizedDebts, BotError> {
+    let debts = match debts_format {
+        AddDebtsFormat::Equal => process_debts_equal(text, total)?,
+        AddDebtsFormat::Exact => process_debts_exact(text, creditor, currency, total)?,
+        AddDebtsFormat::Ratio => process_debts_ratio(text, total)?,
+        AddDebtsFormat::Itemized => {
+            return process_debts_itemized(text, creditor, currency, total);
+        }
+    };
+    Ok((debts, Vec::new()))
 }
 
 // Parse and process a string to retrieve a list of debts, for split by ratio.
@@ -227,7 +438,7 @@ pub fn process_debts_ratio(text: &str, total: Option<i64>) -> Result<Vec<(String
     let mut debts: Vec<(String, i64)> = Vec::new();
     let mut sum: f64 = 0.0;
 
-    if items.len() % 2 != 0 {
+    if !items.len().is_multiple_of(2) {
         return Err(BotError::UserError(
             "Sorry, unknown format... Please use the following format!".to_string(),
         ));
@@ -281,6 +492,62 @@ pub fn process_debts_ratio(text: &str, total: Option<i64>) -> Result<Vec<(String
     Ok(debts)
 }
 
+// Checks that the amounts paid by each payer add up to the total, if more than one paid.
+pub fn check_payers_total(payers: &[(String, i64)], total: i64) -> Result<(), BotError> {
+    if !payers.is_empty() && payers.iter().map(|(_, amount)| amount).sum::<i64>() != total {
+        Err(BotError::UserError(
+            "Uh-oh! ❌ The amounts paid by each payer don't add up to the total!".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// Parse and process a string to retrieve the payers of a payment.
+// A single username paid the whole total. Else each username is followed by the amount they paid,
+// which add up to the total. The first of them is the creditor.
+pub fn process_payers(text: &str, currency: Currency) -> Result<PaidAmounts, BotError> {
+    let items: Vec<&str> = text.split_whitespace().collect();
+    if items.len() == 1 {
+        return Ok((parse_username(items[0])?, Vec::new(), None));
+    }
+    if items.is_empty() || !items.len().is_multiple_of(2) {
+        return Err(BotError::UserError(
+            "Sorry, unknown format... Please use the following format!".to_string(),
+        ));
+    }
+
+    let mut payers: Vec<(String, i64)> = Vec::new();
+    let mut paid: i64 = 0;
+    for i in (0..items.len()).step_by(2) {
+        let username = parse_username(items[i])?;
+        let amount = parse_amount(items[i + 1], currency.1)?;
+        // No single payer can exceed the amount paid, so checking it is enough
+        paid = match paid.checked_add(amount) {
+            Some(paid) if paid <= MAX_VALUE => paid,
+            _ => {
+                return Err(BotError::UserError(
+                    "Uh-oh! 🥺 This number is too large for me to handle!".to_string(),
+                ))
+            }
+        };
+        match payers
+            .iter_mut()
+            .find(|(payer, _)| is_username_equal(payer, &username))
+        {
+            Some(payer) => payer.1 += amount,
+            None => payers.push((username, amount)),
+        }
+    }
+
+    // A single payer paid the whole total, so no list of payers is kept
+    let creditor = payers[0].0.clone();
+    if payers.len() == 1 {
+        payers = Vec::new();
+    }
+    Ok((creditor, payers, Some(paid)))
+}
+
 pub fn parse_debts_payback(
     text: &str,
     currency: Currency,
@@ -288,7 +555,7 @@ pub fn parse_debts_payback(
 ) -> Result<Vec<(String, i64)>, BotError> {
     let mut debts: Vec<(String, i64)> = Vec::new();
     let items: Vec<&str> = text.split_whitespace().collect();
-    if items.len() % 2 != 0 {
+    if !items.len().is_multiple_of(2) {
         return Err(BotError::UserError(
             "Sorry, unknown format... Please use the following format!".to_string(),
         ));
@@ -317,3 +584,185 @@ pub fn parse_debts_payback(
 
     Ok(debts)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd() -> Currency {
+        ("USD".to_string(), 2)
+    }
+
+    // Tests that each item is split among its own participants, and the rest is left to the payer
+    #[test]
+    fn test_process_debts_itemized() {
+        let text = "pizza 24 @alice_a @bobby_b\nwine 30 @alice_a @carol_c @dave_d\ntip 5 @carol_c";
+        let (debts, items) =
+            process_debts_itemized(text, &Some("bobby_b".to_string()), Some(usd()), Some(6500))
+                .unwrap();
+
+        assert_eq!(
+            debts,
+            vec![
+                ("alice_a".to_string(), 2200),
+                ("bobby_b".to_string(), 1800),
+                ("carol_c".to_string(), 1500),
+                ("dave_d".to_string(), 1000),
+            ]
+        );
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].description, "pizza");
+        assert_eq!(items[0].amount, 2400);
+        assert_eq!(items[0].participants, vec!["alice_a", "bobby_b"]);
+        assert_eq!(items[2].description, "tip");
+        assert_eq!(items[2].participants, vec!["carol_c"]);
+    }
+
+    // Tests that items are numbered without a description, and that lines may be separated by semicolons
+    #[test]
+    fn test_process_debts_itemized_unnamed() {
+        let (debts, items) = process_debts_itemized(
+            "10 @alice_a; 10.01 @alice_a @bobby_b",
+            &Some("alice_a".to_string()),
+            Some(usd()),
+            Some(2001),
+        )
+        .unwrap();
+
+        assert_eq!(
+            debts,
+            vec![("alice_a".to_string(), 1500), ("bobby_b".to_string(), 501)]
+        );
+        assert_eq!(items[0].description, "Item 1");
+        assert_eq!(items[1].description, "Item 2");
+    }
+
+    // Tests that tax and tip are spread over each member in proportion to what they owe
+    #[test]
+    fn test_process_debts_charges() {
+        let creditor = Some("alice_a".to_string());
+        let debts = process_debts_exact(
+            "@bobby_b 10 @carol_c 20 tax 10% tip 3",
+            &creditor,
+            Some(usd()),
+            Some(3600),
+        )
+        .unwrap();
+        assert_eq!(
+            debts,
+            vec![("bobby_b".to_string(), 1200), ("carol_c".to_string(), 2400)]
+        );
+
+        // The smallest denomination left over is given to as many members as required
+        let debts = process_debts_exact(
+            "@bobby_b 1 @carol_c 1 @dave_d 1 service 0.01",
+            &creditor,
+            Some(usd()),
+            Some(301),
+        )
+        .unwrap();
+        assert_eq!(
+            debts,
+            vec![
+                ("bobby_b".to_string(), 101),
+                ("carol_c".to_string(), 100),
+                ("dave_d".to_string(), 100),
+            ]
+        );
+
+        let (debts, items) = process_debts_itemized(
+            "pizza 20 @bobby_b @carol_c\nwine 10 @carol_c\ntax 8%\ntip: 1.5",
+            &creditor,
+            Some(usd()),
+            Some(3390),
+        )
+        .unwrap();
+        assert_eq!(
+            debts,
+            vec![("bobby_b".to_string(), 1130), ("carol_c".to_string(), 2260)]
+        );
+        assert_eq!(items.len(), 2);
+
+        // Charges need an amount to be added to
+        assert!(process_debts_exact("tax 5", &creditor, Some(usd()), Some(500)).is_err());
+        assert!(
+            process_debts_exact("@bobby_b 10 tax 5", &creditor, Some(usd()), Some(1000)).is_err()
+        );
+
+        // Charges too large to handle are rejected rather than overflowing
+        assert!(process_debts_exact(
+            "@bobby_b 10 tax 1e17% tip 1e17%",
+            &creditor,
+            Some(usd()),
+            Some(1000)
+        )
+        .is_err());
+        assert!(
+            process_debts_exact("@bobby_b 10 tax 101%", &creditor, Some(usd()), Some(3000))
+                .is_err()
+        );
+        assert!(process_debts_itemized(
+            "pizza 10 @bobby_b\ntax 1e17%\ntip 1e17%",
+            &creditor,
+            Some(usd()),
+            Some(1000)
+        )
+        .is_err());
+        assert!(process_debts_exact(
+            "@bobby_b 10000000000000000 tip 10000000000000000 tax 10000000000000000",
+            &creditor,
+            Some(usd()),
+            Some(MAX_VALUE)
+        )
+        .is_err());
+    }
+
+    // Tests that several payers are kept with what they paid, and a single payer is not
+    #[test]
+    fn test_process_payers() {
+        assert_eq!(
+            process_payers("@alice_a", usd()).unwrap(),
+            ("alice_a".to_string(), Vec::new(), None)
+        );
+        assert_eq!(
+            process_payers("@alice_a 30 @bobby_b 15.5 @alice_a 4.5", usd()).unwrap(),
+            (
+                "alice_a".to_string(),
+                vec![("alice_a".to_string(), 3450), ("bobby_b".to_string(), 1550)],
+                Some(5000)
+            )
+        );
+        assert_eq!(
+            process_payers("@alice_a 50", usd()).unwrap(),
+            ("alice_a".to_string(), Vec::new(), Some(5000))
+        );
+        assert_eq!(
+            process_payers("@alice_a 30 @Alice_A 20", usd()).unwrap(),
+            ("alice_a".to_string(), Vec::new(), Some(5000))
+        );
+        assert!(process_payers("@alice_a @bobby_b 15", usd()).is_err());
+        assert!(process_payers("@alice_a 30 @bobby_b", usd()).is_err());
+        assert!(process_payers("", usd()).is_err());
+        assert!(process_payers(
+            &"@alice_a 10000000000000000 @bobby_b 10000000000000000 ".repeat(5),
+            usd()
+        )
+        .is_err());
+        assert!(check_payers_total(&[("alice_a".to_string(), 3000)], 5000).is_err());
+        assert!(check_payers_total(&[], 5000).is_ok());
+    }
+
+    // Tests that invalid items are rejected
+    #[test]
+    fn test_process_debts_itemized_invalid() {
+        let creditor = Some("alice_a".to_string());
+        assert!(
+            process_debts_itemized("pizza @alice_a", &creditor, Some(usd()), Some(100)).is_err()
+        );
+        assert!(process_debts_itemized("pizza 24", &creditor, Some(usd()), Some(10000)).is_err());
+        assert!(process_debts_itemized("\n;", &creditor, Some(usd()), Some(100)).is_err());
+        assert!(
+            process_debts_itemized("pizza 24 @alice_a", &creditor, Some(usd()), Some(100)).is_err()
+        );
+    }
+}
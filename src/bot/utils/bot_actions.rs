@@ -1,28 +1,32 @@
 /* Common utilites for handlers. */
 
 use teloxide::{
-    payloads::SendMessage,
+    payloads::{SendDocument, SendMessage},
     prelude::*,
-    requests::JsonRequest,
-    types::{Message, MessageId},
-    Bot,
+    requests::{JsonRequest, MultipartRequest},
+    types::{InputFile, Message, MessageId, User},
+    Bot, RequestError,
 };
 
 use crate::bot::{
-    constants::currency::CURRENCY_DEFAULT,
-    processor::{assert_rate_limit, get_chat_setting, retrieve_valid_currencies, ChatSetting},
+    constants::{currency::CURRENCY_DEFAULT, misc::PAYMENT_CATEGORIES},
+    processor::{
+        assert_rate_limit, get_chat_setting, retrieve_chat_categories, retrieve_valid_currencies,
+        ChatSetting,
+    },
+    store::Store,
 };
 
 use super::{BotError, StatementOption};
 
 // Checks and asserts the rate limit of 1 request per user per second.
 // Returns true if okay, false if exceeded
-pub fn assert_handle_request_limit(msg: Message) -> bool {
+pub async fn assert_handle_request_limit(store: &Store, msg: Message) -> bool {
     if let Some(user) = msg.from() {
         let user_id = user.id.to_string();
         let timestamp = msg.date.timestamp();
-        let request_status = assert_rate_limit(&user_id, timestamp);
-        if let Err(_) = request_status {
+        let request_status = assert_rate_limit(store, &user_id, timestamp).await;
+        if request_status.is_err() {
             log::error!(
                 "Rate limit exceeded for user: {} in chat: {}, with message timestamp: {}",
                 user_id,
@@ -48,6 +52,32 @@ pub fn send_bot_message(bot: &Bot, msg: &Message, text: String) -> JsonRequest<S
     }
 }
 
+// Wrapper function to send bot document to specific thread, if available
+pub fn send_bot_document(
+    bot: &Bot,
+    msg: &Message,
+    document: InputFile,
+) -> MultipartRequest<SendDocument> {
+    let thread_id = msg.thread_id;
+    match thread_id {
+        Some(thread_id) => bot
+            .send_document(msg.chat.id, document)
+            .message_thread_id(thread_id),
+        None => bot.send_document(msg.chat.id, document),
+    }
+}
+
+// Checks if a user is an admin of the chat, as required to change everyone's records
+// Anyone is an admin of their own private chat
+pub async fn is_chat_admin(bot: &Bot, msg: &Message, user: &User) -> Result<bool, RequestError> {
+    if msg.chat.is_private() {
+        return Ok(true);
+    }
+
+    let member = bot.get_chat_member(msg.chat.id, user.id).await?;
+    Ok(member.is_privileged())
+}
+
 // Removes all old messages, given a chat and a list of message IDs
 pub async fn delete_bot_messages(
     bot: &Bot,
@@ -61,23 +91,20 @@ pub async fn delete_bot_messages(
 }
 
 // Checks if Erase Messages setting is enabled
-pub fn is_erase_messages(chat_id: &str) -> bool {
-    let erase = get_chat_setting(chat_id, ChatSetting::EraseMessages(None));
-    if let Ok(ChatSetting::EraseMessages(Some(true))) = erase {
-        true
-    } else {
-        false
-    }
+pub async fn is_erase_messages(store: &Store, chat_id: &str) -> bool {
+    let erase = get_chat_setting(store, chat_id, ChatSetting::EraseMessages(None)).await;
+    matches!(erase, Ok(ChatSetting::EraseMessages(Some(true))))
 }
 
 // Processes and retrieves appropriate valid currencies for balances and spendings.
-pub fn process_valid_currencies(
+pub async fn process_valid_currencies(
+    store: &Store,
     chat_id: &str,
     sender_id: &str,
     option: StatementOption,
     default_currency: String,
 ) -> Vec<String> {
-    let mut valid_currencies = match retrieve_valid_currencies(&chat_id) {
+    let mut valid_currencies = match retrieve_valid_currencies(store, chat_id).await {
         Ok(currencies) => currencies,
         Err(_) => {
             log::error!(
@@ -101,7 +128,7 @@ pub fn process_valid_currencies(
             if curr != &default_currency {
                 valid_currencies.push(default_currency.clone());
             }
-        } else if valid_currencies.len() > 0 {
+        } else if !valid_currencies.is_empty() {
             // Adds back default currency on convert, only if there are also other
             // currencies. Else, the converted is already equal to the default.
             valid_currencies.push(default_currency.clone());
@@ -113,7 +140,7 @@ pub fn process_valid_currencies(
     // Add conversion button only if not currently on convert, and have default currency
     if option != StatementOption::ConvertCurrency
         && default_currency != CURRENCY_DEFAULT.0
-        && valid_currencies.len() > 0
+        && !valid_currencies.is_empty()
     {
         valid_currencies.push(conversion_button);
         // Add no currency button if no default currency, and not currently NIL
@@ -127,3 +154,22 @@ pub fn process_valid_currencies(
 
     valid_currencies
 }
+
+// Retrieves the categories a payment of a chat can be given.
+// Falls back to the default categories, if the custom ones of the chat cannot be retrieved.
+pub async fn process_chat_categories(store: &Store, chat_id: &str) -> Vec<String> {
+    match retrieve_chat_categories(store, chat_id).await {
+        Ok(categories) => categories,
+        Err(err) => {
+            log::error!(
+                "Categories - Failed to retrieve categories for group {}: {}",
+                chat_id,
+                err.to_string()
+            );
+            PAYMENT_CATEGORIES
+                .iter()
+                .map(|category| category.to_string())
+                .collect()
+        }
+    }
+}
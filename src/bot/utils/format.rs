@@ -1,14 +1,22 @@
+use std::collections::{BTreeSet, HashMap};
+
 use chrono_tz::Tz;
 use regex::Regex;
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+use teloxide::types::{
+    InlineKeyboardButton, InlineKeyboardMarkup, Message, MessageEntityKind, User,
+};
 
 use crate::bot::{
-    constants::currency::CURRENCY_DEFAULT,
+    constants::{
+        currency::CURRENCY_DEFAULT,
+        misc::{GUEST_PREFIX, MAX_CATEGORY_LENGTH, MAX_GUEST_NAME_LENGTH, TELEGRAM_USER_PREFIX},
+    },
     currency::{get_currency_from_code, get_default_currency, Currency},
     handlers::Payment,
     processor::{get_chat_setting, ChatSetting},
-    redis::Debt,
-    utils::time::reformat_datetime,
+    redis::{Debt, PaymentItem},
+    store::Store,
+    utils::time::format_timestamp,
 };
 
 use super::BotError;
@@ -25,22 +33,74 @@ pub fn get_currency(code: &str) -> Result<Currency, BotError> {
 }
 
 // Retrieves the default currency of a chat. Does not return an error, assumes default.
-pub fn get_chat_default_currency(chat_id: &str) -> Currency {
+pub async fn get_chat_default_currency(store: &Store, chat_id: &str) -> Currency {
     let setting = ChatSetting::DefaultCurrency(None);
-    let currency = get_chat_setting(&chat_id, setting);
-    match currency {
-        Ok(ChatSetting::DefaultCurrency(Some(currency))) => {
-            let currency = get_currency(&currency);
-            if let Ok(currency) = currency {
-                return currency;
-            }
+    let currency = get_chat_setting(store, chat_id, setting).await;
+    if let Ok(ChatSetting::DefaultCurrency(Some(currency))) = currency {
+        let currency = get_currency(&currency);
+        if let Ok(currency) = currency {
+            return currency;
         }
-        // Skips error, assumes default
-        _ => {}
     }
+    // Skips error, assumes default
     get_default_currency()
 }
 
+// Names of members without a username, by member. Members only keep their user ID,
+// so their name is looked up when displaying them.
+pub type MemberNames = HashMap<String, String>;
+
+// Retrieves the names of the members to be displayed. Does not return an error, assumes none.
+// Only members without a username have a name kept, so only they are looked up.
+pub async fn get_member_names<'a>(
+    store: &Store,
+    members: impl IntoIterator<Item = &'a str>,
+) -> MemberNames {
+    let members: BTreeSet<String> = members
+        .into_iter()
+        .filter(|member| member.starts_with(TELEGRAM_USER_PREFIX))
+        .map(|member| member.to_lowercase())
+        .collect();
+    if members.is_empty() {
+        return MemberNames::new();
+    }
+
+    // Skips error, members are then shown by their user ID
+    let members: Vec<String> = members.into_iter().collect();
+    store.get_member_names(&members).await.unwrap_or_default()
+}
+
+// Gets the members displayed with a payment: the payers, the debtors, and those sharing an item.
+pub fn payment_members<'a>(
+    creditor: &'a str,
+    payers: &'a [(String, i64)],
+    debts: &'a [(String, i64)],
+    items: &'a [PaymentItem],
+) -> Vec<&'a str> {
+    let mut members = vec![creditor];
+    members.extend(debt_members(payers));
+    members.extend(debt_members(debts));
+    members.extend(
+        items
+            .iter()
+            .flat_map(|item| item.participants.iter().map(|member| member.as_str())),
+    );
+    members
+}
+
+// Gets the members displayed with a list of debts, or of amounts paid.
+pub fn debt_members(debts: &[(String, i64)]) -> Vec<&str> {
+    debts.iter().map(|(member, _)| member.as_str()).collect()
+}
+
+// Gets the members displayed with balances, both debtors and creditors.
+pub fn balance_members(debts: &[Debt]) -> Vec<&str> {
+    debts
+        .iter()
+        .flat_map(|debt| [debt.debtor.as_str(), debt.creditor.as_str()])
+        .collect()
+}
+
 // Converts an amount from base value to actual representation in currency.
 pub fn display_amount(amount: i64, decimal_places: i32) -> String {
     if decimal_places == 0 {
@@ -62,15 +122,15 @@ pub fn display_amount(amount: i64, decimal_places: i32) -> String {
 // Displays an amount together with its currency
 pub fn display_currency_amount(amount: i64, currency: Currency) -> String {
     if currency.0 == CURRENCY_DEFAULT.0 {
-        format!("{}", display_amount(amount, currency.1))
+        display_amount(amount, currency.1).to_string()
     } else {
         format!("{} {}", display_amount(amount, currency.1), currency.0)
     }
 }
 
 // Gets the currency to be used when provided with the chosen currency, and the chat ID.
-pub fn use_currency(currency: Currency, chat_id: &str) -> Currency {
-    let default_currency = get_chat_default_currency(chat_id);
+pub async fn use_currency(store: &Store, currency: Currency, chat_id: &str) -> Currency {
+    let default_currency = get_chat_default_currency(store, chat_id).await;
     if currency.0 == CURRENCY_DEFAULT.0 {
         default_currency
     } else {
@@ -79,15 +139,17 @@ pub fn use_currency(currency: Currency, chat_id: &str) -> Currency {
 }
 
 // Displays the header for the balances, depending on the statement option applied.
-pub fn display_balance_header(chat_id: &str, currency: &str) -> String {
-    let conversion = match get_chat_setting(chat_id, ChatSetting::CurrencyConversion(None)) {
-        Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
-        _ => false,
-    };
-    let default_currency = match get_chat_setting(chat_id, ChatSetting::DefaultCurrency(None)) {
-        Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
-        _ => CURRENCY_DEFAULT.0.to_string(),
-    };
+pub async fn display_balance_header(store: &Store, chat_id: &str, currency: &str) -> String {
+    let conversion =
+        match get_chat_setting(store, chat_id, ChatSetting::CurrencyConversion(None)).await {
+            Ok(ChatSetting::CurrencyConversion(Some(value))) => value,
+            _ => false,
+        };
+    let default_currency =
+        match get_chat_setting(store, chat_id, ChatSetting::DefaultCurrency(None)).await {
+            Ok(ChatSetting::DefaultCurrency(Some(currency))) => currency,
+            _ => CURRENCY_DEFAULT.0.to_string(),
+        };
 
     if conversion {
         format!(
@@ -98,7 +160,7 @@ pub fn display_balance_header(chat_id: &str, currency: &str) -> String {
         if default_currency != CURRENCY_DEFAULT.0 {
             format!("Updated balances in {}!\n\n", default_currency)
         } else {
-            format!("Updated balances!\n\n")
+            "Updated balances!\n\n".to_string()
         }
     } else {
         format!("Updated balances in {}!\n\n", currency)
@@ -106,7 +168,7 @@ pub fn display_balance_header(chat_id: &str, currency: &str) -> String {
 }
 
 // Displays balances in a more readable format. Now only shows in one currency.
-pub fn display_balances(debts: &Vec<Debt>) -> String {
+pub fn display_balances(debts: &Vec<Debt>, names: &MemberNames) -> String {
     let mut message = String::new();
     for debt in debts {
         let currency = get_currency(&debt.currency);
@@ -114,8 +176,8 @@ pub fn display_balances(debts: &Vec<Debt>) -> String {
             Ok(currency) => {
                 message.push_str(&format!(
                     "{} owes {}: {}\n",
-                    display_username(&debt.debtor),
-                    display_username(&debt.creditor),
+                    display_username(&debt.debtor, names),
+                    display_username(&debt.creditor, names),
                     display_amount(debt.amount, currency.1),
                 ));
             }
@@ -134,30 +196,87 @@ pub fn display_balances(debts: &Vec<Debt>) -> String {
 }
 
 // Displays debts in a more readable format.
-pub fn display_debts(debts: &Vec<(String, i64)>, decimal_places: i32) -> String {
+pub fn display_debts(
+    debts: &Vec<(String, i64)>,
+    decimal_places: i32,
+    names: &MemberNames,
+) -> String {
     let mut message = String::new();
     for debt in debts {
         message.push_str(&format!(
             "    {}: {}\n",
-            display_username(&debt.0),
+            display_username(&debt.0, names),
             display_amount(debt.1, decimal_places),
         ));
     }
     message
 }
 
+// Displays who paid for a payment, with how much each paid if more than one paid.
+pub fn display_payers(
+    creditor: &str,
+    payers: &Vec<(String, i64)>,
+    decimal_places: i32,
+    names: &MemberNames,
+) -> String {
+    if payers.is_empty() {
+        format!("Payer: {}\n", display_username(creditor, names))
+    } else {
+        format!("Payers:\n{}", display_debts(payers, decimal_places, names))
+    }
+}
+
+// Displays the items of a payment, each with its amount and the members sharing it.
+pub fn display_items(items: &[PaymentItem], decimal_places: i32, names: &MemberNames) -> String {
+    let mut message = String::new();
+    for item in items {
+        let participants = item
+            .participants
+            .iter()
+            .map(|participant| display_username(participant, names))
+            .collect::<Vec<String>>();
+        message.push_str(&format!(
+            "    {}: {} ({})\n",
+            item.description,
+            display_amount(item.amount, decimal_places),
+            participants.join(", "),
+        ));
+    }
+    message
+}
+
 // Displays a single payment entry in a user-friendly format.
-pub fn display_payment(payment: &Payment, serial_num: usize, time_zone: Tz) -> String {
-    let actual_currency = use_currency(payment.currency.clone(), &payment.chat_id);
+pub async fn display_payment(
+    store: &Store,
+    payment: &Payment,
+    serial_num: usize,
+    time_zone: Tz,
+    names: &MemberNames,
+) -> String {
+    let actual_currency = use_currency(store, payment.currency.clone(), &payment.chat_id).await;
+    let category = match &payment.category {
+        Some(category) => format!("Category: {}\n", category),
+        None => "".to_string(),
+    };
+    let items = if payment.items.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "Items:\n{}",
+            display_items(&payment.items, actual_currency.1, names)
+        )
+    };
 
     format!(
-        "__________________________\n{}. {}\nDate: {}\nPayer: {}\nTotal: {}\nSplit:\n{}",
+        "__________________________\n{}. {}\n{}Date: {}\n{}Total: {}\n{}Split:\n{}",
         serial_num,
         payment.description,
-        reformat_datetime(&payment.datetime, time_zone),
-        display_username(&payment.creditor),
+        category,
+        format_timestamp(payment.timestamp, time_zone),
+        display_payers(&payment.creditor, &payment.payers, actual_currency.1, names),
         display_currency_amount(payment.total, actual_currency.clone()),
-        display_debts(&payment.debts, actual_currency.1)
+        items,
+        display_debts(&payment.debts, actual_currency.1, names)
     )
 }
 
@@ -186,23 +305,112 @@ pub fn make_keyboard(options: Vec<&str>, columns: Option<usize>) -> InlineKeyboa
 
 // Make debt selection keyboard
 pub fn make_keyboard_debt_selection() -> InlineKeyboardMarkup {
-    let buttons = vec!["Equal", "Exact", "Proportion"];
+    let buttons = vec!["Equal", "Exact", "Proportion", "Itemized"];
     make_keyboard(buttons, Some(1))
 }
 
-// Displays a username with the '@' symbol.
-pub fn display_username(username: &str) -> String {
-    format!("@{}", username)
+// Make category selection keyboard, with an option to leave the payment uncategorized
+pub fn make_keyboard_categories(categories: &[String]) -> InlineKeyboardMarkup {
+    let mut buttons: Vec<&str> = categories
+        .iter()
+        .map(|category| category.as_str())
+        .collect();
+    buttons.push("None");
+    make_keyboard(buttons, Some(3))
 }
 
-// Ensures that a username has a leading '@'.
-pub fn parse_username(username: &str) -> Result<String, BotError> {
-    let text: &str;
-    if username.starts_with('@') {
-        text = username.trim_start_matches('@');
+// Displays a member. Usernames are shown with the '@' symbol,
+// while members without a username are shown by their name, or their user ID if unknown.
+pub fn display_username(username: &str, names: &MemberNames) -> String {
+    if let Some(name) = username.strip_prefix(GUEST_PREFIX) {
+        display_member_name(name)
+    } else if let Some(user_id) = username.strip_prefix(TELEGRAM_USER_PREFIX) {
+        match names.get(&username.to_lowercase()) {
+            Some(name) => name.to_string(),
+            None => format!("User {}", user_id),
+        }
     } else {
-        text = username;
+        format!("@{}", username)
     }
+}
+
+// Names of guests are stored without whitespace, which is replaced by underscores.
+fn display_member_name(name: &str) -> String {
+    name.replace('_', " ")
+}
+
+// Displays a Telegram user, by username if they have one, or by name otherwise.
+pub fn display_user(user: &User) -> String {
+    match &user.username {
+        Some(username) => format!("@{}", username),
+        None => user.full_name(),
+    }
+}
+
+// Gets the member representing a Telegram user.
+// Users without a username are identified by their user ID only, as their name can change.
+// Their name is kept separately, for display.
+pub fn member_from_user(user: &User) -> String {
+    match &user.username {
+        Some(username) => username.to_string(),
+        None => format!("{TELEGRAM_USER_PREFIX}{}", user.id),
+    }
+}
+
+// Gets the text of a message, with text mentions replaced by the members they mention.
+// Text mentions are how Telegram users without a username are mentioned.
+pub fn parse_text_mentions(msg: &Message) -> Option<String> {
+    let text = msg.text()?;
+    let mut parsed = String::new();
+    let mut end = 0;
+    for entity in msg.parse_entities().unwrap_or_default() {
+        if let MessageEntityKind::TextMention { user } = entity.kind() {
+            parsed.push_str(&text[end..entity.start()]);
+            parsed.push_str(&member_from_user(user));
+            end = entity.end();
+        }
+    }
+    parsed.push_str(&text[end..]);
+    Some(parsed)
+}
+
+// Gets all Telegram users mentioned in a message by text mentions.
+pub fn get_text_mentioned_users(msg: &Message) -> Vec<User> {
+    msg.parse_entities()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entity| match entity.kind() {
+            MessageEntityKind::TextMention { user } => Some(user.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Ensures that a member is valid, removing any leading '@' from a username.
+// Guests are written with a leading '+', such as +Name.
+pub fn parse_username(username: &str) -> Result<String, BotError> {
+    if let Some(name) = username.strip_prefix(GUEST_PREFIX) {
+        if !name.is_empty() && name.chars().count() <= MAX_GUEST_NAME_LENGTH {
+            let re = Regex::new(r"^\p{L}[\w'-]*$");
+            if let Ok(re) = re {
+                if re.is_match(name) {
+                    return Ok(username.to_string());
+                }
+            }
+        }
+
+        return Err(BotError::UserError(
+            "Uh-oh! ❌ Please give me a valid guest name, such as +Name!".to_string(),
+        ));
+    }
+
+    if let Some(user_id) = username.strip_prefix(TELEGRAM_USER_PREFIX) {
+        if !user_id.is_empty() && user_id.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(username.to_string());
+        }
+    }
+
+    let text = username.trim_start_matches('@');
 
     if text.split_whitespace().count() == 1 && text.len() >= 5 {
         let re = Regex::new(r"^[a-zA-Z0-9_]+$");
@@ -217,3 +425,94 @@ pub fn parse_username(username: &str) -> Result<String, BotError> {
         "Uh-oh! ❌ Please give me a valid username!".to_string(),
     ))
 }
+
+// Ensures that a category is valid, collapsing any whitespace within it.
+// A category matching one of the given categories, ignoring case, is written the same way.
+pub fn parse_category(text: &str, categories: &[String]) -> Result<String, BotError> {
+    let category = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if category.is_empty() || category.chars().count() > MAX_CATEGORY_LENGTH {
+        return Err(BotError::UserError(format!(
+            "Uh-oh! ❌ Please give me a category of at most {MAX_CATEGORY_LENGTH} characters!"
+        )));
+    }
+
+    let known = categories
+        .iter()
+        .find(|known| known.to_lowercase() == category.to_lowercase());
+    match known {
+        Some(known) => Ok(known.clone()),
+        None => Ok(category),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use teloxide::types::UserId;
+
+    fn user(id: u64, first_name: &str, username: Option<&str>) -> User {
+        User {
+            id: UserId(id),
+            is_bot: false,
+            first_name: first_name.to_string(),
+            last_name: None,
+            username: username.map(|username| username.to_string()),
+            language_code: None,
+            is_premium: false,
+            added_to_attachment_menu: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_display_member() {
+        assert_eq!(parse_username("@Test_User").unwrap(), "Test_User");
+        assert!(parse_username("@abc").is_err());
+
+        // Guests keep their prefix, and are shown by their name
+        assert_eq!(parse_username("+Mary_Ann").unwrap(), "+Mary_Ann");
+        assert!(parse_username("+").is_err());
+        assert!(parse_username("+100").is_err());
+        assert_eq!(
+            display_username("+Mary_Ann", &MemberNames::new()),
+            "Mary Ann"
+        );
+
+        // Telegram users without a username are identified by their user ID only
+        let names = MemberNames::new();
+        let member = member_from_user(&user(123456789, "Test User", None));
+        assert_eq!(member, "#123456789");
+        assert_eq!(parse_username(&member).unwrap(), member);
+        assert!(parse_username("#abc").is_err());
+        assert_eq!(display_username(&member, &names), "User 123456789");
+        assert_eq!(
+            display_user(&user(123456789, "Test User", None)),
+            "Test User"
+        );
+
+        // Their name is looked up separately, so a change in name keeps the same member
+        let names = MemberNames::from([(member.clone(), "Test User".to_string())]);
+        assert_eq!(display_username(&member, &names), "Test User");
+        let member = member_from_user(&user(123456789, "Renamed User", None));
+        assert_eq!(member, "#123456789");
+
+        let member = member_from_user(&user(123456789, "Test User", Some("Test_User")));
+        assert_eq!(member, "Test_User");
+        assert_eq!(display_username(&member, &names), "@Test_User");
+    }
+
+    #[test]
+    fn test_parse_category() {
+        let categories = vec!["Food".to_string(), "Day Trips".to_string()];
+        assert_eq!(parse_category(" food ", &categories).unwrap(), "Food");
+        assert_eq!(
+            parse_category("day   trips", &categories).unwrap(),
+            "Day Trips"
+        );
+        assert_eq!(
+            parse_category("Groceries", &categories).unwrap(),
+            "Groceries"
+        );
+        assert!(parse_category("   ", &categories).is_err());
+        assert!(parse_category("A very long category", &categories).is_err());
+    }
+}
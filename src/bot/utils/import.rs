@@ -0,0 +1,660 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::bot::{
+    constants::misc::MAX_VALUE, currency::Currency, handlers::AddDebtsFormat,
+    processor::is_username_equal, redis::Payment,
+};
+
+use super::{
+    amounts::{parse_amount, process_debts},
+    format::{get_currency, parse_username},
+    BotError,
+};
+
+/* Types */
+
+// A row of a Splitwise export, with the net share of each member: paid minus owed
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SplitwiseRow {
+    pub row: usize,
+    pub timestamp: i64,
+    pub description: String,
+    pub currency: String,
+    pub cost: i64,
+    pub shares: Vec<i64>,
+}
+
+// A row that cannot be imported, with the reason why
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SkippedRow {
+    pub row: usize,
+    pub description: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct SplitwiseImport {
+    pub members: Vec<String>,
+    pub rows: Vec<SplitwiseRow>,
+    pub skipped: Vec<SkippedRow>,
+}
+
+// A record of any CSV, together with its row number
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CsvRecord {
+    pub row: usize,
+    pub fields: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CsvTable {
+    pub header: Vec<String>,
+    pub records: Vec<CsvRecord>,
+}
+
+// Details of a payment that can be read from a column of any CSV
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsvField {
+    Date,
+    Description,
+    Payer,
+    Amount,
+    Currency,
+    Participants,
+    SplitType,
+}
+
+// Order in which columns are matched to the details of a payment
+pub const CSV_FIELDS: [CsvField; 7] = [
+    CsvField::Date,
+    CsvField::Description,
+    CsvField::Payer,
+    CsvField::Amount,
+    CsvField::Currency,
+    CsvField::Participants,
+    CsvField::SplitType,
+];
+
+impl CsvField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CsvField::Date => "date",
+            CsvField::Description => "description",
+            CsvField::Payer => "payer",
+            CsvField::Amount => "amount",
+            CsvField::Currency => "currency",
+            CsvField::Participants => "participants",
+            CsvField::SplitType => "split type",
+        }
+    }
+
+    // Optional details have a fallback when they are not in the CSV
+    pub fn is_optional(&self) -> bool {
+        matches!(
+            self,
+            CsvField::Date | CsvField::Currency | CsvField::SplitType
+        )
+    }
+}
+
+// Columns that come before the member columns in a Splitwise export
+const SPLITWISE_COLUMNS: [&str; 5] = ["Date", "Description", "Category", "Cost", "Currency"];
+
+// Summary row at the end of a Splitwise export
+const SPLITWISE_TOTAL_DESCRIPTION: &str = "Total balance";
+
+/* Utilities */
+
+// Parses CSV text into records of fields. Handles quoted fields, escaped quotes and line breaks.
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut chars = text.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if is_quoted {
+            match char {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => is_quoted = false,
+                _ => field.push(char),
+            }
+            continue;
+        }
+
+        match char {
+            '"' => is_quoted = true,
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => (),
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            _ => field.push(char),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    records
+}
+
+// Checks if a CSV record has no content, such as a blank line
+pub fn is_blank_record(record: &[String]) -> bool {
+    record.iter().all(|field| field.trim().is_empty())
+}
+
+// Parses a signed amount, where a blank field is zero
+fn parse_signed_amount(text: &str, decimal_places: i32) -> Result<i64, BotError> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0);
+    }
+
+    let factor = 10.0_f64.powi(decimal_places);
+    match text.parse::<f64>() {
+        Ok(val) if (val * factor).abs() <= MAX_VALUE as f64 => Ok((val * factor).round() as i64),
+        _ => Err(BotError::UserError(format!("{text} is not a valid amount"))),
+    }
+}
+
+// Date formats accepted in a CSV, with or without a time
+const CSV_DATETIME_FORMATS: [&str; 3] =
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"];
+const CSV_DATE_FORMATS: [&str; 4] = ["%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%d.%m.%Y"];
+
+// Parses a date in the time zone of the chat into a UTC timestamp, as with message dates
+fn parse_date(text: &str, time_zone: Tz) -> Result<i64, BotError> {
+    let text = text.trim();
+    let datetime = CSV_DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            CSV_DATE_FORMATS
+                .iter()
+                .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        });
+
+    match datetime.and_then(|datetime| time_zone.from_local_datetime(&datetime).earliest()) {
+        Some(datetime) => Ok(datetime.timestamp()),
+        None => Err(BotError::UserError(format!("{text} is not a valid date"))),
+    }
+}
+
+fn parse_splitwise_row(
+    row: usize,
+    record: &[String],
+    members: usize,
+    time_zone: Tz,
+) -> Result<SplitwiseRow, BotError> {
+    let field = |index: usize| record.get(index).map(|field| field.trim()).unwrap_or("");
+
+    let timestamp = parse_date(field(0), time_zone)?;
+    let currency = get_currency(field(4))
+        .map_err(|_| BotError::UserError(format!("{} is not a known currency", field(4))))?;
+    let cost = parse_amount(field(3), currency.1)
+        .map_err(|_| BotError::UserError(format!("{} is not a valid cost", field(3))))?;
+    let shares = (0..members)
+        .map(|index| parse_signed_amount(field(SPLITWISE_COLUMNS.len() + index), currency.1))
+        .collect::<Result<Vec<i64>, BotError>>()?;
+
+    Ok(SplitwiseRow {
+        row,
+        timestamp,
+        description: field(1).to_string(),
+        currency: currency.0,
+        cost,
+        shares,
+    })
+}
+
+/* Parses any CSV into its header and records.
+ * Blank lines are left out, and records keep the row number they are on.
+ */
+pub fn parse_csv_table(text: &str) -> Result<CsvTable, BotError> {
+    let mut records = parse_csv(text)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, record)| !is_blank_record(record))
+        .map(|(index, fields)| CsvRecord {
+            row: index + 1,
+            fields,
+        });
+
+    let header = match records.next() {
+        Some(header) => header
+            .fields
+            .iter()
+            .map(|field| field.trim().to_string())
+            .collect(),
+        None => {
+            return Err(BotError::UserError(
+                "🥺 Sorry, this file is empty!".to_string(),
+            ))
+        }
+    };
+    let records: Vec<CsvRecord> = records.collect();
+    if records.is_empty() {
+        return Err(BotError::UserError(
+            "🥺 Sorry, this file has no rows besides the header!".to_string(),
+        ));
+    }
+
+    Ok(CsvTable { header, records })
+}
+
+// Checks if the header of a CSV is the one of a Splitwise export
+pub fn is_splitwise_csv(table: &CsvTable) -> bool {
+    table.header.len() > SPLITWISE_COLUMNS.len()
+        && SPLITWISE_COLUMNS
+            .iter()
+            .zip(table.header.iter())
+            .all(|(column, field)| column.eq_ignore_ascii_case(field))
+}
+
+/* Parses a CSV exported from Splitwise.
+ * The header has the fixed columns, followed by one column per member.
+ * Dates are read in the time zone of the chat.
+ * Rows that cannot be read are skipped, with the reason kept to be reported.
+ */
+pub fn parse_splitwise_csv(table: &CsvTable, time_zone: Tz) -> Result<SplitwiseImport, BotError> {
+    if !is_splitwise_csv(table) {
+        return Err(BotError::UserError(
+            "🥺 Sorry, this doesn't look like a CSV exported from Splitwise!".to_string(),
+        ));
+    }
+    let members: Vec<String> = table.header[SPLITWISE_COLUMNS.len()..].to_vec();
+
+    let mut rows: Vec<SplitwiseRow> = Vec::new();
+    let mut skipped: Vec<SkippedRow> = Vec::new();
+    for record in table.records.iter() {
+        let description = record.fields.get(1).map(|field| field.trim()).unwrap_or("");
+        if description == SPLITWISE_TOTAL_DESCRIPTION {
+            continue;
+        }
+
+        match parse_splitwise_row(record.row, &record.fields, members.len(), time_zone) {
+            Ok(row) => rows.push(row),
+            Err(err) => skipped.push(SkippedRow {
+                row: record.row,
+                description: description.to_string(),
+                reason: err.to_string(),
+            }),
+        }
+    }
+
+    Ok(SplitwiseImport {
+        members,
+        rows,
+        skipped,
+    })
+}
+
+/* Converts a Splitwise row into a payment, given the username of each member.
+ * The member who lent money is the payer, and owes the rest of the cost themselves.
+ * Everyone who borrowed owes what they borrowed.
+ */
+pub fn convert_splitwise_row(
+    row: &SplitwiseRow,
+    members: &[String],
+    usernames: &[Option<String>],
+) -> Result<Payment, SkippedRow> {
+    let skip = |reason: String| SkippedRow {
+        row: row.row,
+        description: row.description.clone(),
+        reason,
+    };
+
+    // Every member involved in the row has to be mapped.
+    // Members mapped to the same username are combined.
+    let mut shares: Vec<(String, i64)> = Vec::new();
+    for (index, share) in row.shares.iter().enumerate() {
+        if *share == 0 {
+            continue;
+        }
+        let username = match usernames.get(index) {
+            Some(Some(username)) => username,
+            _ => {
+                return Err(skip(format!(
+                    "{} is not matched to anyone here",
+                    members.get(index).cloned().unwrap_or_default()
+                )))
+            }
+        };
+        match shares
+            .iter_mut()
+            .find(|(user, _)| is_username_equal(user, username))
+        {
+            Some((_, total_share)) => *total_share += share,
+            None => shares.push((username.clone(), *share)),
+        }
+    }
+
+    let lenders: Vec<&(String, i64)> = shares.iter().filter(|(_, share)| *share > 0).collect();
+    let (creditor, lent) = match lenders[..] {
+        [(creditor, lent)] => (creditor.clone(), *lent),
+        [] => return Err(skip("No one paid for this".to_string())),
+        _ => return Err(skip("More than one member paid for this".to_string())),
+    };
+    if lent > row.cost {
+        return Err(skip("The payer lent more than the cost".to_string()));
+    }
+
+    let mut debts: Vec<(String, i64)> = shares
+        .iter()
+        .filter(|(_, share)| *share < 0)
+        .map(|(user, share)| (user.clone(), -share))
+        .collect();
+    if row.cost > lent {
+        debts.insert(0, (creditor.clone(), row.cost - lent));
+    }
+
+    let total = debts.iter().map(|(_, debt)| debt).sum::<i64>();
+    if total != row.cost {
+        return Err(skip("The shares don't add up to the cost".to_string()));
+    }
+
+    Ok(Payment {
+        description: row.description.clone(),
+        timestamp: row.timestamp,
+        creditor,
+        currency: row.currency.clone(),
+        total,
+        debts,
+        items: Vec::new(),
+        category: None,
+        payers: Vec::new(),
+    })
+}
+
+// Parses the split type of a row, where a blank field is an equal split
+fn parse_split_type(text: &str) -> Result<AddDebtsFormat, BotError> {
+    match text.trim().to_lowercase().as_str() {
+        "" | "equal" | "equally" => Ok(AddDebtsFormat::Equal),
+        "exact" | "exactly" | "amount" | "amounts" => Ok(AddDebtsFormat::Exact),
+        "ratio" | "share" | "shares" | "proportion" => Ok(AddDebtsFormat::Ratio),
+        _ => Err(BotError::UserError(format!(
+            "{} is not a known split type",
+            text.trim()
+        ))),
+    }
+}
+
+/* Converts a record of any CSV into a payment, given the column of each detail.
+ * Columns are given in the order of CSV_FIELDS, with optional details possibly left out.
+ * Without a date, the payment is dated at the given timestamp,
+ * and without a currency, the payment is in the given currency.
+ * Participants are split in the same way as when adding a payment.
+ */
+pub fn convert_csv_record(
+    record: &CsvRecord,
+    columns: &[Option<usize>],
+    timestamp: i64,
+    currency: &Currency,
+    time_zone: Tz,
+) -> Result<Payment, SkippedRow> {
+    let field = |csv_field: CsvField| {
+        CSV_FIELDS
+            .iter()
+            .position(|field| *field == csv_field)
+            .and_then(|position| columns.get(position).copied().flatten())
+            .and_then(|column| record.fields.get(column))
+            .map(|field| field.trim())
+    };
+    let description = field(CsvField::Description).unwrap_or("").to_string();
+    let skip = |err: BotError| SkippedRow {
+        row: record.row,
+        description: description.clone(),
+        reason: err.to_string(),
+    };
+
+    let timestamp = match field(CsvField::Date) {
+        Some(date) if !date.is_empty() => parse_date(date, time_zone).map_err(skip)?,
+        _ => timestamp,
+    };
+    let currency = match field(CsvField::Currency) {
+        Some(code) if !code.is_empty() => get_currency(code).map_err(|_| {
+            skip(BotError::UserError(format!(
+                "{code} is not a known currency"
+            )))
+        })?,
+        _ => currency.clone(),
+    };
+    let creditor = parse_username(field(CsvField::Payer).unwrap_or("")).map_err(skip)?;
+    let total = parse_amount(field(CsvField::Amount).unwrap_or(""), currency.1).map_err(skip)?;
+    let debts_format = parse_split_type(field(CsvField::SplitType).unwrap_or("")).map_err(skip)?;
+
+    // Participants may be separated by commas or semicolons as well
+    let participants = field(CsvField::Participants)
+        .unwrap_or("")
+        .replace([',', ';'], " ");
+    let (debts, items) = process_debts(
+        debts_format,
+        &participants,
+        &Some(creditor.clone()),
+        Some(currency.clone()),
+        Some(total),
+    )
+    .map_err(skip)?;
+
+    Ok(Payment {
+        description,
+        timestamp,
+        creditor,
+        currency: currency.0,
+        total,
+        debts,
+        items,
+        category: None,
+        payers: Vec::new(),
+    })
+}
+
+// Displays a skipped row, with the reason it was skipped
+pub fn display_skipped_row(skipped: &SkippedRow) -> String {
+    format!(
+        "Row {} ({}): {}",
+        skipped.row, skipped.description, skipped.reason
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPLITWISE_CSV: &str =
+        "\u{feff}Date,Description,Category,Cost,Currency,Alice Tan,Bob,Carol\r\n\
+\r\n\
+2024-01-01,\"Dinner, with drinks\",Dining out,30.00,USD,20.00,-10.00,-10.00\r\n\
+2024-01-02,Bob paid Alice,Payment,10.00,USD,-10.00,10.00,0.00\r\n\
+2024-01-03,Taxi,Taxi,12.00,XYZ,6.00,-6.00,0.00\r\n\
+2024-01-04,Hotel,Hotel,90.00,USD,30.00,30.00,-60.00\r\n\
+\r\n\
+2024-01-05,Total balance, , ,USD,10.00,20.00,-70.00\r\n";
+
+    fn usernames(names: &[Option<&str>]) -> Vec<Option<String>> {
+        names
+            .iter()
+            .map(|name| name.map(|name| name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        assert_eq!(
+            parse_csv("a,\"b, \"\"c\"\"\",\r\n\"d\ne\",f"),
+            vec![
+                vec!["a".to_string(), "b, \"c\"".to_string(), "".to_string()],
+                vec!["d\ne".to_string(), "f".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_splitwise_csv() {
+        let import =
+            parse_splitwise_csv(&parse_csv_table(SPLITWISE_CSV).unwrap(), Tz::UTC).unwrap();
+        assert_eq!(import.members, vec!["Alice Tan", "Bob", "Carol"]);
+        assert_eq!(import.rows.len(), 3);
+        assert_eq!(import.rows[0].row, 3);
+        assert_eq!(import.rows[0].description, "Dinner, with drinks");
+        assert_eq!(import.rows[0].timestamp, 1704067200);
+        assert_eq!(import.rows[0].cost, 3000);
+        assert_eq!(import.rows[0].shares, vec![2000, -1000, -1000]);
+
+        // Unknown currencies are skipped, and the total balance row is left out
+        assert_eq!(import.skipped.len(), 1);
+        assert_eq!(import.skipped[0].row, 5);
+        assert_eq!(import.skipped[0].description, "Taxi");
+
+        let table = parse_csv_table("Date,Description,Amount\n2024-01-01,Dinner,10").unwrap();
+        assert!(!is_splitwise_csv(&table));
+        assert!(parse_splitwise_csv(&table, Tz::UTC).is_err());
+        assert!(parse_csv_table("").is_err());
+        assert!(parse_csv_table("Date,Description,Amount\n").is_err());
+    }
+
+    #[test]
+    fn test_convert_splitwise_row() {
+        let import =
+            parse_splitwise_csv(&parse_csv_table(SPLITWISE_CSV).unwrap(), Tz::UTC).unwrap();
+        let mapped = usernames(&[Some("alice_tan"), Some("bob_bob"), Some("carol_c")]);
+
+        let payment = convert_splitwise_row(&import.rows[0], &import.members, &mapped).unwrap();
+        assert_eq!(payment.creditor, "alice_tan");
+        assert_eq!(payment.currency, "USD");
+        assert_eq!(payment.total, 3000);
+        assert_eq!(
+            payment.debts,
+            vec![
+                ("alice_tan".to_string(), 1000),
+                ("bob_bob".to_string(), 1000),
+                ("carol_c".to_string(), 1000),
+            ]
+        );
+
+        // Paying back is a payment without a share for the payer
+        let payment = convert_splitwise_row(&import.rows[1], &import.members, &mapped).unwrap();
+        assert_eq!(payment.creditor, "bob_bob");
+        assert_eq!(payment.debts, vec![("alice_tan".to_string(), 1000)]);
+
+        // Rows paid by more than one member cannot be imported
+        let skipped = convert_splitwise_row(&import.rows[2], &import.members, &mapped).unwrap_err();
+        assert_eq!(skipped.row, 6);
+
+        // Members who are left out cannot be part of an imported row
+        let unmapped = usernames(&[Some("alice_tan"), Some("bob_bob"), None]);
+        assert!(convert_splitwise_row(&import.rows[1], &import.members, &unmapped).is_ok());
+        let skipped =
+            convert_splitwise_row(&import.rows[0], &import.members, &unmapped).unwrap_err();
+        assert_eq!(skipped.reason, "Carol is not matched to anyone here");
+
+        // Members matched to the same user are combined
+        let combined = usernames(&[Some("alice_tan"), Some("alice_tan"), Some("carol_c")]);
+        let payment = convert_splitwise_row(&import.rows[2], &import.members, &combined).unwrap();
+        assert_eq!(payment.creditor, "alice_tan");
+        assert_eq!(
+            payment.debts,
+            vec![
+                ("alice_tan".to_string(), 3000),
+                ("carol_c".to_string(), 6000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_csv_record() {
+        let table = parse_csv_table(
+            "When,What,Who,How much,Currency,With,Split\n\
+2024-01-01 20:30,Dinner,@alice_tan,30,,\"@alice_tan, @bob_bob, @carol_c\",\n\
+02/01/2024,Hotel,bob_bob,100,usd,@alice_tan 70 @bob_bob 30,Exact\n\
+,Taxi,carol_c,12,JPY,@alice_tan 1 @carol_c 2,shares\n\
+2024-01-04,Museum,carol_c,ten,,@alice_tan,\n\
+2024-01-05,Snacks,carol_c,5,,@alice_tan,halves\n",
+        )
+        .unwrap();
+        let columns = vec![
+            Some(0),
+            Some(1),
+            Some(2),
+            Some(3),
+            Some(4),
+            Some(5),
+            Some(6),
+        ];
+        let currency = ("SGD".to_string(), 2);
+        let time_zone: Tz = "Asia/Singapore".parse().unwrap();
+        let convert = |index: usize| {
+            convert_csv_record(
+                &table.records[index],
+                &columns,
+                1706745600,
+                &currency,
+                time_zone,
+            )
+        };
+
+        // Dates are read in the time zone of the chat, and currency falls back to the given one
+        let payment = convert(0).unwrap();
+        assert_eq!(payment.timestamp, 1704112200);
+        assert_eq!(payment.creditor, "alice_tan");
+        assert_eq!(payment.currency, "SGD");
+        assert_eq!(payment.total, 3000);
+        assert_eq!(
+            payment.debts,
+            vec![
+                ("alice_tan".to_string(), 1000),
+                ("bob_bob".to_string(), 1000),
+                ("carol_c".to_string(), 1000),
+            ]
+        );
+
+        let payment = convert(1).unwrap();
+        assert_eq!(payment.timestamp, 1704124800);
+        assert_eq!(payment.currency, "USD");
+        assert_eq!(
+            payment.debts,
+            vec![
+                ("alice_tan".to_string(), 7000),
+                ("bob_bob".to_string(), 3000)
+            ]
+        );
+
+        // Without a date, the payment is dated at the given timestamp
+        let payment = convert(2).unwrap();
+        assert_eq!(payment.timestamp, 1706745600);
+        assert_eq!(payment.total, 12);
+        assert_eq!(
+            payment.debts,
+            vec![("alice_tan".to_string(), 4), ("carol_c".to_string(), 8)]
+        );
+
+        let skipped = convert(3).unwrap_err();
+        assert_eq!(skipped.row, 5);
+        assert_eq!(skipped.description, "Museum");
+        assert!(convert(4).is_err());
+
+        // Optional details can be left out
+        let columns = vec![None, Some(1), Some(2), Some(3), None, Some(5), None];
+        let payment = convert_csv_record(
+            &table.records[0],
+            &columns,
+            1706745600,
+            &currency,
+            time_zone,
+        )
+        .unwrap();
+        assert_eq!(payment.timestamp, 1706745600);
+        assert_eq!(payment.debts.len(), 3);
+    }
+}
@@ -1,12 +1,14 @@
-use teloxide::{
-    dispatching::dialogue::{Dialogue, InMemStorage, InMemStorageError},
-    RequestError,
-};
+use serde::{Deserialize, Serialize};
+use teloxide::RequestError;
 
-use crate::bot::{processor::ProcessError, State};
+use crate::bot::{
+    processor::ProcessError,
+    store::{Dialogue, DialogueStorageError},
+    State,
+};
 
 /* Types */
-pub type UserDialogue = Dialogue<State, InMemStorage<State>>;
+pub type UserDialogue = Dialogue<State>;
 pub type HandlerResult = Result<(), BotError>;
 
 #[derive(PartialEq, Debug, Clone)]
@@ -15,13 +17,15 @@ pub enum StatementOption {
     ConvertCurrency,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SelectPaymentType {
     EditPayment,
     DeletePayment,
+    ViewHistory,
 }
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum BotError {
     #[error("{0}")]
     UserError(String),
@@ -37,8 +41,8 @@ impl From<RequestError> for BotError {
     }
 }
 
-impl From<InMemStorageError> for BotError {
-    fn from(storage_error: InMemStorageError) -> BotError {
+impl From<DialogueStorageError> for BotError {
+    fn from(storage_error: DialogueStorageError) -> BotError {
         BotError::UserError(storage_error.to_string())
     }
 }
@@ -52,4 +56,6 @@ impl From<ProcessError> for BotError {
 pub mod amounts;
 pub mod bot_actions;
 pub mod format;
+pub mod import;
+pub mod schedule;
 pub mod time;
@@ -0,0 +1,480 @@
+use chrono::{Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::bot::redis::RecurringFrequency;
+
+use super::{
+    time::{format_date, format_timestamp, DATE_FORMATS},
+    BotError,
+};
+
+/* Schedules of recurring payments.
+ * A schedule is how often a payment is added, from its start until its optional end.
+ * Daily, weekly and monthly schedules repeat at the time of day of the start,
+ * while custom schedules are cron expressions.
+ * All of them are evaluated in the time zone of the chat.
+ */
+
+// Schedule of a recurring payment being added
+// Without a start, the schedule starts at the date of the payment
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub frequency: RecurringFrequency,
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+// Days ahead searched for the next time matching a cron expression
+const CRON_SEARCH_DAYS: u64 = 5 * 366;
+
+// Cron expression with the values matched by each field
+#[derive(Debug, PartialEq)]
+struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    is_any_day: bool,
+    is_any_weekday: bool,
+}
+
+impl Cron {
+    // Same as cron, a date matches either the day of month or the day of week,
+    // unless one of them is unrestricted
+    fn is_date_match(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(&date.month()) {
+            return false;
+        }
+
+        let is_day = self.days.contains(&date.day());
+        let is_weekday = self
+            .weekdays
+            .contains(&date.weekday().num_days_from_sunday());
+        if self.is_any_day || self.is_any_weekday {
+            is_day && is_weekday
+        } else {
+            is_day || is_weekday
+        }
+    }
+
+    // Finds the first time matching the expression after the given timestamp
+    fn next_after(&self, after: i64, time_zone: Tz) -> Option<i64> {
+        let first_date = time_zone.timestamp_opt(after, 0).single()?.date_naive();
+        for offset in 0..CRON_SEARCH_DAYS {
+            let date = first_date.checked_add_days(Days::new(offset))?;
+            if !self.is_date_match(date) {
+                continue;
+            }
+
+            for hour in &self.hours {
+                for minute in &self.minutes {
+                    let time = NaiveTime::from_hms_opt(*hour, *minute, 0)?;
+                    // Times skipped by daylight saving are skipped, same as cron
+                    let timestamp = time_zone
+                        .from_local_datetime(&date.and_time(time))
+                        .earliest()
+                        .map(|datetime| datetime.timestamp());
+                    if let Some(timestamp) = timestamp.filter(|timestamp| *timestamp > after) {
+                        return Some(timestamp);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// Parses a field of a cron expression into the values it matches, in order
+// Accepts *, single values, ranges and steps, separated by commas
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Option<Vec<u32>> {
+    let mut values: Vec<u32> = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (first, last) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            // A single value with a step runs until the end of the field, same as cron
+            None if step > 1 => (range.parse().ok()?, max),
+            None => {
+                let value = range.parse().ok()?;
+                (value, value)
+            }
+        };
+        if first < min || last > max || first > last {
+            return None;
+        }
+        values.extend((first..=last).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Some(values)
+}
+
+// Parses a cron expression of minute, hour, day of month, month and day of week
+fn parse_cron(text: &str) -> Option<Cron> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    if fields.len() != 5 {
+        return None;
+    }
+
+    // Sunday is both 0 and 7
+    let mut weekdays: Vec<u32> = parse_cron_field(fields[4], 0, 7)?
+        .into_iter()
+        .map(|weekday| weekday % 7)
+        .collect();
+    weekdays.sort_unstable();
+    weekdays.dedup();
+
+    Some(Cron {
+        minutes: parse_cron_field(fields[0], 0, 59)?,
+        hours: parse_cron_field(fields[1], 0, 23)?,
+        days: parse_cron_field(fields[2], 1, 31)?,
+        months: parse_cron_field(fields[3], 1, 12)?,
+        weekdays,
+        is_any_day: fields[2].starts_with('*'),
+        is_any_weekday: fields[4].starts_with('*'),
+    })
+}
+
+// Converts a local date and time into a UTC timestamp
+// Times skipped by daylight saving are moved an hour later
+fn local_timestamp(datetime: NaiveDateTime, time_zone: Tz) -> Option<i64> {
+    time_zone
+        .from_local_datetime(&datetime)
+        .earliest()
+        .or_else(|| {
+            time_zone
+                .from_local_datetime(&(datetime + Duration::hours(1)))
+                .earliest()
+        })
+        .map(|datetime| datetime.timestamp())
+}
+
+// Gets the nth occurrence after the start of a daily, weekly or monthly schedule
+// Monthly occurrences past the end of a shorter month fall on its last day
+fn nth_occurrence(
+    frequency: &RecurringFrequency,
+    start: NaiveDateTime,
+    count: u32,
+    time_zone: Tz,
+) -> Option<i64> {
+    let date = match frequency {
+        RecurringFrequency::Daily => start.date().checked_add_days(Days::new(count.into())),
+        RecurringFrequency::Weekly => start
+            .date()
+            .checked_add_days(Days::new(7 * u64::from(count))),
+        RecurringFrequency::Monthly => start.date().checked_add_months(Months::new(count)),
+        RecurringFrequency::Cron(_) => None,
+    }?;
+    local_timestamp(date.and_time(start.time()), time_zone)
+}
+
+/* Gets the first occurrence of a schedule after the given time, and not before its start.
+ * Returns None if the schedule never occurs again.
+ */
+pub fn next_occurrence(
+    frequency: &RecurringFrequency,
+    start: i64,
+    after: i64,
+    time_zone: Tz,
+) -> Option<i64> {
+    let after = after.max(start - 1);
+    if let RecurringFrequency::Cron(expression) = frequency {
+        return parse_cron(expression)?.next_after(after, time_zone);
+    }
+
+    let start_local = time_zone.timestamp_opt(start, 0).single()?.naive_local();
+    let after_date = time_zone.timestamp_opt(after, 0).single()?.date_naive();
+
+    // Skips close to the given time, then steps over any occurrence not after it
+    let days = (after_date - start_local.date()).num_days();
+    let months = (after_date.year() - start_local.year()) * 12 + after_date.month() as i32
+        - start_local.month() as i32;
+    let skipped = match frequency {
+        RecurringFrequency::Weekly => days / 7,
+        RecurringFrequency::Monthly => months.into(),
+        _ => days,
+    };
+    let mut count = u32::try_from(skipped.saturating_sub(1)).unwrap_or(0);
+    loop {
+        let occurrence = nth_occurrence(frequency, start_local, count, time_zone)?;
+        if occurrence > after {
+            return Some(occurrence);
+        }
+        count = count.checked_add(1)?;
+    }
+}
+
+// Gets the first occurrence of a schedule, at or after its start
+pub fn first_occurrence(frequency: &RecurringFrequency, start: i64, time_zone: Tz) -> Option<i64> {
+    next_occurrence(frequency, start, start - 1, time_zone)
+}
+
+// Parses a date of a schedule, which may be in the future
+fn parse_schedule_date(text: &str, today: NaiveDate) -> Result<NaiveDate, BotError> {
+    let date = match text {
+        "today" => Some(today),
+        "tomorrow" => today.checked_add_days(Days::new(1)),
+        _ => DATE_FORMATS
+            .iter()
+            .find_map(|format| NaiveDate::parse_from_str(text, format).ok()),
+    };
+    date.ok_or(BotError::UserError(format!(
+        "🥺 Sorry, I don't recognize {text} as a date!"
+    )))
+}
+
+// Parses how often a payment repeats
+fn parse_frequency(text: &str) -> Result<RecurringFrequency, BotError> {
+    match text {
+        "daily" | "every day" => Ok(RecurringFrequency::Daily),
+        "weekly" | "every week" => Ok(RecurringFrequency::Weekly),
+        "monthly" | "every month" => Ok(RecurringFrequency::Monthly),
+        _ => match parse_cron(text) {
+            Some(_) => Ok(RecurringFrequency::Cron(
+                text.split_whitespace().collect::<Vec<&str>>().join(" "),
+            )),
+            None => Err(BotError::UserError(format!(
+                "🥺 Sorry, I don't recognize {text} as a schedule!"
+            ))),
+        },
+    }
+}
+
+/* Parses a schedule given by a user, in the time zone of the chat.
+ * Accepts daily, weekly, monthly or a cron expression, optionally followed by
+ * "from" and a start date, and "until" and an end date. Both dates may be in the future.
+ * The start keeps the time of day of the payment, and the end includes the whole of its day.
+ */
+pub fn parse_schedule(
+    text: &str,
+    time_zone: Tz,
+    current: i64,
+    now: i64,
+) -> Result<Schedule, BotError> {
+    let error = || BotError::UserError(format!("🥺 Sorry, I don't recognize {text} as a date!"));
+    let text = text.trim().to_lowercase();
+    let (text, end_text) = match text.split_once(" until ") {
+        Some((text, end_text)) => (text.trim(), Some(end_text.trim())),
+        None => (text.as_str(), None),
+    };
+    let (frequency_text, start_text) = match text.split_once(" from ") {
+        Some((frequency_text, start_text)) => (frequency_text.trim(), Some(start_text.trim())),
+        None => (text, None),
+    };
+
+    let frequency = parse_frequency(frequency_text)?;
+    let today = time_zone
+        .timestamp_opt(now, 0)
+        .single()
+        .ok_or_else(error)?
+        .date_naive();
+    let start = match start_text {
+        Some(start_text) => {
+            let time = time_zone
+                .timestamp_opt(current, 0)
+                .single()
+                .ok_or_else(error)?
+                .time();
+            let date = parse_schedule_date(start_text, today)?;
+            Some(local_timestamp(date.and_time(time), time_zone).ok_or_else(error)?)
+        }
+        None => None,
+    };
+    let end = match end_text {
+        Some(end_text) => {
+            let date = parse_schedule_date(end_text, today)?;
+            let end_of_day = date.and_hms_opt(23, 59, 59).ok_or_else(error)?;
+            Some(local_timestamp(end_of_day, time_zone).ok_or_else(error)?)
+        }
+        None => None,
+    };
+
+    let first = first_occurrence(&frequency, start.unwrap_or(current), time_zone);
+    match first {
+        Some(first) if end.is_none_or(|end| first <= end) => Ok(Schedule {
+            frequency,
+            start,
+            end,
+        }),
+        _ => Err(BotError::UserError(
+            "🥺 Sorry, this payment would never repeat! Check that the schedule ends after it starts."
+                .to_string(),
+        )),
+    }
+}
+
+// Displays how often a payment repeats, from when, and until when
+pub fn display_schedule(
+    frequency: &RecurringFrequency,
+    start: i64,
+    end: Option<i64>,
+    time_zone: Tz,
+) -> String {
+    let frequency = match frequency {
+        RecurringFrequency::Daily => "Daily".to_string(),
+        RecurringFrequency::Weekly => "Weekly".to_string(),
+        RecurringFrequency::Monthly => "Monthly".to_string(),
+        RecurringFrequency::Cron(expression) => format!("Custom ({expression})"),
+    };
+    let end = match end {
+        Some(end) => format!(" until {}", format_date(end, time_zone).trim()),
+        None => "".to_string(),
+    };
+    format!(
+        "{frequency} from {}{end}",
+        format_timestamp(start, time_zone).trim()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-01-31 09:00:00 in Singapore
+    const START: i64 = 1769821200;
+    const DAY: i64 = 86400;
+
+    fn singapore() -> Tz {
+        "Asia/Singapore".parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_cron_field() {
+        assert_eq!(parse_cron_field("*", 0, 3), Some(vec![0, 1, 2, 3]));
+        assert_eq!(parse_cron_field("*/15", 0, 59), Some(vec![0, 15, 30, 45]));
+        assert_eq!(parse_cron_field("1-5/2,10", 0, 59), Some(vec![1, 3, 5, 10]));
+        assert_eq!(parse_cron_field("50/5", 0, 59), Some(vec![50, 55]));
+        assert_eq!(parse_cron_field("5,5,1", 0, 59), Some(vec![1, 5]));
+
+        assert_eq!(parse_cron_field("60", 0, 59), None);
+        assert_eq!(parse_cron_field("5-1", 0, 59), None);
+        assert_eq!(parse_cron_field("*/0", 0, 59), None);
+        assert_eq!(parse_cron_field("mon", 0, 7), None);
+    }
+
+    #[test]
+    fn test_next_occurrence_periodic() {
+        let time_zone = singapore();
+        let next = |frequency, after| next_occurrence(&frequency, START, after, time_zone);
+
+        // The start is the first occurrence
+        assert_eq!(
+            first_occurrence(&RecurringFrequency::Daily, START, time_zone),
+            Some(START)
+        );
+        assert_eq!(next(RecurringFrequency::Daily, 0), Some(START));
+        assert_eq!(next(RecurringFrequency::Daily, START), Some(START + DAY));
+        assert_eq!(
+            next(RecurringFrequency::Weekly, START + 3 * DAY),
+            Some(START + 7 * DAY)
+        );
+        assert_eq!(
+            next(RecurringFrequency::Weekly, START + 7 * DAY),
+            Some(START + 14 * DAY)
+        );
+
+        // Monthly falls on the last day of shorter months, and returns after
+        let february = START + 28 * DAY;
+        let march = START + 59 * DAY;
+        assert_eq!(next(RecurringFrequency::Monthly, START), Some(february));
+        assert_eq!(next(RecurringFrequency::Monthly, february), Some(march));
+        assert_eq!(
+            next(RecurringFrequency::Monthly, START + 400 * DAY),
+            Some(START + 424 * DAY)
+        );
+    }
+
+    #[test]
+    fn test_next_occurrence_cron() {
+        let time_zone = singapore();
+        let next = |expression: &str, after| {
+            next_occurrence(
+                &RecurringFrequency::Cron(expression.to_string()),
+                START,
+                after,
+                time_zone,
+            )
+        };
+
+        // 09:00 on the 1st of every month
+        assert_eq!(next("0 9 1 * *", START), Some(START + DAY));
+        assert_eq!(next("0 9 1 * *", START + DAY), Some(START + 29 * DAY));
+
+        // 08:30 on weekdays, and Sunday as both 0 and 7
+        assert_eq!(next("30 8 * * 1-5", START), Some(START + 2 * DAY - 1800));
+        assert_eq!(next("0 9 * * 7", START), Some(START + DAY));
+        assert_eq!(next("0 9 * * 0", START), Some(START + DAY));
+
+        // Day of month or day of week, when both are given
+        assert_eq!(next("0 9 15 * 1", START), Some(START + 2 * DAY));
+
+        // Not before the start
+        assert_eq!(next("0 9 * * *", 0), Some(START));
+
+        assert_eq!(next("0 9 31 2 *", START), None);
+        assert_eq!(next("0 9 * *", START), None);
+    }
+
+    #[test]
+    fn test_parse_schedule() {
+        let time_zone = singapore();
+        let parse = |text: &str| parse_schedule(text, time_zone, START, START);
+
+        assert_eq!(
+            parse("Monthly").unwrap(),
+            Schedule {
+                frequency: RecurringFrequency::Monthly,
+                start: None,
+                end: None,
+            }
+        );
+        assert_eq!(
+            parse("weekly from 2026-02-02 until 2026-06-30").unwrap(),
+            Schedule {
+                frequency: RecurringFrequency::Weekly,
+                start: Some(START + 2 * DAY),
+                end: Some(START + 150 * DAY + 15 * 3600 - 1),
+            }
+        );
+        assert_eq!(
+            parse("daily until tomorrow").unwrap().end,
+            Some(START + DAY + 15 * 3600 - 1)
+        );
+        assert_eq!(
+            parse("0  9 1 * *").unwrap().frequency,
+            RecurringFrequency::Cron("0 9 1 * *".to_string())
+        );
+
+        // Schedules that never repeat are rejected
+        assert!(parse("daily until 2026-01-01").is_err());
+        assert!(parse("0 9 1 * * until 2026-01-31").is_err());
+
+        assert!(parse("fortnightly").is_err());
+        assert!(parse("daily from someday").is_err());
+    }
+
+    #[test]
+    fn test_display_schedule() {
+        let time_zone = singapore();
+        assert_eq!(
+            display_schedule(&RecurringFrequency::Monthly, START, None, time_zone),
+            "Monthly from 31 Jan 2026 09:00"
+        );
+        assert_eq!(
+            display_schedule(
+                &RecurringFrequency::Cron("0 9 1 * *".to_string()),
+                START,
+                Some(START + 150 * DAY),
+                time_zone
+            ),
+            "Custom (0 9 1 * *) from 31 Jan 2026 09:00 until 30 Jun 2026"
+        );
+    }
+}
@@ -1,9 +1,12 @@
 use std::collections::HashMap;
 
-use chrono::{DateTime, Local, NaiveDateTime};
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, TimeZone};
 use chrono_tz::Tz;
 
-use crate::bot::processor::{get_chat_setting, ChatSetting};
+use crate::bot::{
+    processor::{get_chat_setting, ChatSetting},
+    store::Store,
+};
 
 use super::BotError;
 
@@ -636,9 +639,9 @@ pub fn parse_time_zone(text: &str) -> Result<Tz, BotError> {
 
 // Retrieves the time zone string from database, converts it to TimeZone object
 // Assumes that time zone is valid, thus does not return any error
-pub fn retrieve_time_zone(chat_id: &str) -> Tz {
+pub async fn retrieve_time_zone(store: &Store, chat_id: &str) -> Tz {
     let setting = ChatSetting::TimeZone(None);
-    let time_zone = get_chat_setting(&chat_id, setting);
+    let time_zone = get_chat_setting(store, chat_id, setting).await;
     if let Ok(ChatSetting::TimeZone(Some(time_zone))) = time_zone {
         let time_zone = parse_time_zone(&time_zone);
         if let Ok(time_zone) = time_zone {
@@ -649,25 +652,181 @@ pub fn retrieve_time_zone(chat_id: &str) -> Tz {
     "UTC".parse::<Tz>().expect("UTC is a valid time zone")
 }
 
-// Parses a string representing a datetime, and returns the Datetime object
-fn parse_datetime(text: &str, time_zone: Tz) -> DateTime<Tz> {
-    // Checks if text contains "UTC" at the end
-    let mut new_text = text.to_string();
-    if text.ends_with(" UTC") {
-        new_text = new_text.replace(" UTC", "");
+// Formats a Datetime object into an easy to read string
+fn format_datetime(datetime: &DateTime<Tz>) -> String {
+    datetime.format("%e %b %Y %R").to_string()
+}
+
+// Formats a Datetime object into a sortable ISO 8601 string, for exports
+fn format_datetime_iso(datetime: &DateTime<Tz>) -> String {
+    datetime.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+// Formats a UNIX timestamp into a sortable ISO 8601 string
+// Timestamps out of range are shown as is, rather than as some other time
+pub fn format_timestamp_iso(timestamp: i64, time_zone: Tz) -> String {
+    match time_zone.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => format_datetime_iso(&datetime),
+        None => timestamp.to_string(),
     }
-    let datetime = NaiveDateTime::parse_from_str(&new_text, "%Y-%m-%d %H:%M:%S");
-    match datetime {
-        Ok(val) => val.and_utc().with_timezone(&time_zone),
-        Err(_) => Local::now().with_timezone(&time_zone),
+}
+
+// Formats a UNIX timestamp into an easy to read string
+pub fn format_timestamp(timestamp: i64, time_zone: Tz) -> String {
+    match time_zone.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => format_datetime(&datetime),
+        None => timestamp.to_string(),
     }
 }
 
-// Formats a Datetime object into an easy to read string
-fn format_datetime(datetime: &DateTime<Tz>) -> String {
-    datetime.format("%e %b %Y %R").to_string()
+// Formats a UNIX timestamp into an easy to read date, without the time
+pub fn format_date(timestamp: i64, time_zone: Tz) -> String {
+    match time_zone.timestamp_opt(timestamp, 0).single() {
+        Some(datetime) => datetime.format("%e %b %Y").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+// Date formats accepted when dating a payment, with and without a year
+pub const DATE_FORMATS: [&str; 5] = ["%Y-%m-%d", "%d/%m/%Y", "%d.%m.%Y", "%d %b %Y", "%d %B %Y"];
+const DATE_FORMATS_NO_YEAR: [&str; 4] = ["%d/%m", "%d.%m", "%d %b", "%d %B"];
+const TIME_FORMATS: [&str; 2] = ["%H:%M", "%H:%M:%S"];
+
+// Parses a date without a time, relative to today in the time zone
+fn parse_date(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match text {
+        "today" => return Some(today),
+        "yesterday" => return today.checked_sub_days(Days::new(1)),
+        _ => {}
+    }
+
+    if let Some(date) = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(text, format).ok())
+    {
+        return Some(date);
+    }
+
+    // Without a year, takes the latest valid such date that is not after today
+    // A leap day is only valid every four years, and not always then, so looks back eight years
+    DATE_FORMATS_NO_YEAR.iter().find_map(|format| {
+        (0..=8).map(|years| today.year() - years).find_map(|year| {
+            NaiveDate::parse_from_str(&format!("{text} {year}"), &format!("{format} %Y"))
+                .ok()
+                .filter(|date| *date <= today)
+        })
+    })
+}
+
+/* Parses a date given by a user into a UTC timestamp, in the time zone of the chat.
+ * Accepts "today", "yesterday", or a date such as 2026-10-12 or 12 Oct, optionally followed by a time.
+ * Without a time, the payment keeps the time of day it currently has, but is never dated after now.
+ * Dates in the future are rejected.
+ */
+pub fn parse_payment_date(
+    text: &str,
+    time_zone: Tz,
+    current: i64,
+    now: i64,
+) -> Result<i64, BotError> {
+    let error = || BotError::UserError(format!("🥺 Sorry, I don't recognize {text} as a date!"));
+    let text = text.trim().to_lowercase();
+
+    // The time, if any, is the last part of the text
+    let (date_text, time) = match text.rsplit_once(' ') {
+        Some((date_text, time_text)) => match TIME_FORMATS
+            .iter()
+            .find_map(|format| NaiveTime::parse_from_str(time_text, format).ok())
+        {
+            Some(time) => (date_text.trim(), Some(time)),
+            None => (text.as_str(), None),
+        },
+        None => (text.as_str(), None),
+    };
+
+    let now_local = time_zone.timestamp_opt(now, 0).single().ok_or_else(error)?;
+    let current_time = time_zone
+        .timestamp_opt(current, 0)
+        .single()
+        .map(|datetime| datetime.time())
+        .unwrap_or(now_local.time());
+
+    let date = parse_date(date_text, now_local.date_naive()).ok_or_else(error)?;
+    let timestamp = time_zone
+        .from_local_datetime(&date.and_time(time.unwrap_or(current_time)))
+        .earliest()
+        .ok_or_else(error)?
+        .timestamp();
+
+    match time {
+        Some(_) if timestamp > now => Err(BotError::UserError(
+            "🥺 Sorry, payments can't be dated in the future!".to_string(),
+        )),
+        None if date > now_local.date_naive() => Err(BotError::UserError(
+            "🥺 Sorry, payments can't be dated in the future!".to_string(),
+        )),
+        Some(_) => Ok(timestamp),
+        None => Ok(timestamp.min(now)),
+    }
 }
-// Combines both datetime functions to essentially reformat a string into an easier format
-pub fn reformat_datetime(text: &str, time_zone: Tz) -> String {
-    format_datetime(&parse_datetime(text, time_zone))
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2026-10-18 10:00:00 in Singapore
+    const NOW: i64 = 1792288800;
+
+    #[test]
+    fn test_parse_payment_date() {
+        let time_zone: Tz = "Asia/Singapore".parse().unwrap();
+        let parse = |text: &str| parse_payment_date(text, time_zone, NOW, NOW);
+
+        // Relative dates keep the time of day
+        assert_eq!(parse("today").unwrap(), NOW);
+        assert_eq!(parse("Yesterday").unwrap(), NOW - 86400);
+
+        // Dates with a year, with and without a time
+        assert_eq!(parse("2026-10-12").unwrap(), NOW - 6 * 86400);
+        assert_eq!(
+            parse("2026-10-12 18:30").unwrap(),
+            NOW - 6 * 86400 + 8 * 3600 + 1800
+        );
+        assert_eq!(parse("12/10/2026").unwrap(), NOW - 6 * 86400);
+
+        // Dates without a year are the latest such date not after today
+        assert_eq!(
+            parse("12 Oct 18:30").unwrap(),
+            NOW - 6 * 86400 + 8 * 3600 + 1800
+        );
+        assert_eq!(parse("18 October").unwrap(), NOW);
+        assert_eq!(parse("19 Oct").unwrap(), NOW - 364 * 86400);
+
+        // A leap day without a year is the latest one, not one in the current year
+        assert_eq!(parse("29 Feb").unwrap(), NOW - 962 * 86400);
+        assert!(parse("30 Feb").is_err());
+
+        // Payments cannot be dated in the future
+        assert!(parse("today 18:30").is_err());
+        assert!(parse("2026-10-19").is_err());
+
+        assert!(parse("someday").is_err());
+        assert!(parse("2026-02-30").is_err());
+    }
+
+    #[test]
+    fn test_parse_payment_date_keeps_time() {
+        let time_zone: Tz = "Asia/Singapore".parse().unwrap();
+
+        // An earlier payment keeps its time of day, unless that is after now
+        let current = NOW - 7 * 86400 + 3600;
+        assert_eq!(
+            parse_payment_date("yesterday", time_zone, current, NOW).unwrap(),
+            NOW - 86400 + 3600
+        );
+        assert_eq!(
+            parse_payment_date("today", time_zone, current, NOW).unwrap(),
+            NOW
+        );
+    }
 }
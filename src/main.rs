@@ -1,4 +1,4 @@
-use chaching_clan::bot::run_dispatcher;
+use finamaton::bot::{init_store, run_dispatcher};
 
 #[tokio::main]
 pub async fn main() {
@@ -6,6 +6,7 @@ pub async fn main() {
     dotenv::dotenv().ok();
 
     let bot = teloxide::Bot::from_env();
+    let store = init_store().await;
 
-    run_dispatcher(bot).await;
+    run_dispatcher(bot, store).await;
 }